@@ -1,6 +1,11 @@
 use crate::names::*;
+use crate::parser::lexer::Span;
 use crate::parser::token::Token;
 
+/// Name of the param of async methods which takes the continuation
+/// (cannot be written in Shiika programs)
+pub const ASYNC_CONT_PARAM: &str = " k";
+
 #[derive(Debug, PartialEq)]
 pub struct Program {
     pub toplevel_items: Vec<TopLevelItem>,
@@ -16,9 +21,13 @@ pub enum TopLevelItem {
 pub enum Definition {
     ClassDefinition {
         name: ClassFirstname,
+        /// Where the name is written
+        name_span: Span,
         typarams: Vec<String>,
         super_name: ClassFullname,
         defs: Vec<Definition>,
+        /// The doc comment (see `Parser::doc_comment`)
+        doc: Option<String>,
     },
     InstanceMethodDefinition {
         sig: AstMethodSignature,
@@ -28,23 +37,51 @@ pub enum Definition {
         sig: AstMethodSignature,
         body_exprs: Vec<AstExpression>,
     },
+    /// `abstract def foo(...) -> ...` (has no body)
+    AbstractMethodDefinition { sig: AstMethodSignature },
+    /// `extern def foo(...) -> ...` (a C function; defined as a class method)
+    ExternMethodDefinition { sig: AstMethodSignature },
     ConstDefinition {
         name: ConstFirstname,
+        name_span: Span,
         expr: AstExpression,
     },
+    /// `@delegate(to: @foo)` (defines the methods which call the ones of
+    /// `@foo`; see hir/delegation.rs)
+    Delegation {
+        /// Name of the ivar (without `@`)
+        ivar_name: String,
+        /// Where the ivar is written
+        ivar_span: Span,
+    },
+    /// `@derive(Eq, Hash, ToS)` (defines `==`, `hash` and `to_s` from the
+    /// ivars; see hir/derive.rs)
+    Derive {
+        /// Names of the traits
+        traits: Vec<String>,
+        /// Where `@derive(...)` is written
+        span: Span,
+    },
 }
 
 #[derive(Debug, PartialEq)]
 pub struct AstMethodSignature {
     pub name: MethodFirstname,
+    pub name_span: Span,
+    /// Type parameters of the method (eg. `U` of `def map<U>`)
+    pub typarams: Vec<String>,
     pub params: Vec<Param>,
     pub ret_typ: Typ,
+    /// The doc comment (see `Parser::doc_comment`)
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Param {
     pub name: String,
     pub typ: Typ,
+    /// Whether declared like `x: ~String`
+    pub implicit_conv: bool,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -53,10 +90,21 @@ pub struct Typ {
     pub typ_args: Vec<Typ>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub struct AstExpression {
     pub body: AstExpressionBody,
     pub primary: bool,
+    /// Where the expression is in the source (given to the errors found
+    /// by HirMaker.) `None` for the expressions made by the compiler
+    pub span: Option<Span>,
+}
+
+/// The spans are not compared so that the expressions made by the
+/// functions below are equal to the parsed ones
+impl PartialEq for AstExpression {
+    fn eq(&self, other: &AstExpression) -> bool {
+        self.body == other.body && self.primary == other.primary
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -81,7 +129,31 @@ pub enum AstExpressionBody {
         cond_expr: Box<AstExpression>,
         body_exprs: Vec<AstExpression>,
     },
-    Break,
+    For {
+        var_name: String,
+        iterable: Box<AstExpression>,
+        body_exprs: Vec<AstExpression>,
+    },
+    /// `loop do ... end`
+    Loop {
+        body_exprs: Vec<AstExpression>,
+    },
+    /// `case expr when pattern ... else ... end`
+    Case {
+        expr: Box<AstExpression>,
+        clauses: Vec<CaseClause>,
+        else_exprs: Option<Vec<AstExpression>>,
+    },
+    Break {
+        /// `break` with a value (only allowed in `while true`)
+        expr: Option<Box<AstExpression>>,
+    },
+    Next,
+    /// `expr?` (returns the error from the method if `expr` is an error
+    /// `Result`)
+    Try {
+        expr: Box<AstExpression>,
+    },
     LVarAssign {
         name: String,
         rhs: Box<AstExpression>,
@@ -98,6 +170,11 @@ pub enum AstExpressionBody {
         names: Vec<String>,
         rhs: Box<AstExpression>,
     },
+    /// `a, b = tuple`
+    MultipleAssign {
+        names: Vec<String>,
+        rhs: Box<AstExpression>,
+    },
     MethodCall {
         receiver_expr: Option<Box<AstExpression>>, // Box is needed to aboid E0072
         method_name: MethodFirstname,
@@ -108,12 +185,38 @@ pub enum AstExpressionBody {
         params: Vec<Param>,
         exprs: Vec<AstExpression>,
     },
+    /// `await foo(...)` (replaced with `AwaitCall` by the parser if it is
+    /// a statement of an async method or the toplevel)
+    Await {
+        expr: Box<AstExpression>,
+    },
+    /// `foo(...)` called with the statements after `await` as the last arg
+    /// (see parser/async_lowering.rs)
+    AwaitCall {
+        call: Box<AstExpression>,
+        /// Name of the param of the continuation (the value of `await`)
+        var_name: Option<String>,
+        rest: Vec<AstExpression>,
+    },
     // Local variable reference or method call with implicit receiver(self)
     BareName(String),
     IVarRef(String),
     ConstRef(Vec<String>),
+    /// `A<Int>` (a generic class with the type args; eg. `A<Int>.new`)
+    SpecializedConstRef {
+        names: Vec<String>,
+        typ_args: Vec<Typ>,
+    },
     PseudoVariable(Token),
     ArrayLiteral(Vec<AstExpression>),
+    /// `(a, b)`
+    TupleLiteral(Vec<AstExpression>),
+    RangeLiteral {
+        first: Box<AstExpression>,
+        last: Box<AstExpression>,
+        /// `true` for `a...b`
+        exclusive: bool,
+    },
     FloatLiteral {
         value: f64,
     },
@@ -123,6 +226,59 @@ pub enum AstExpressionBody {
     StringLiteral {
         content: String,
     },
+    /// `'a'` (an instance of `Char`)
+    CharLiteral {
+        value: char,
+    },
+}
+
+/// `when pattern, ... if guard` and its body
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaseClause {
+    /// Matches if any of them matches
+    pub patterns: Vec<Pattern>,
+    /// `when x if x > 0`
+    pub guard: Option<AstExpression>,
+    pub body_exprs: Vec<AstExpression>,
+}
+
+/// A pattern of `when`
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// `_` (matches anything)
+    Wildcard,
+    /// `x` (matches anything and assigns it to `x`)
+    Binding(String),
+    /// `1`, `"foo"`, `A::B`, `(x)` (matches if `==` it)
+    Value(AstExpression),
+    /// `[x, 0, *rest]` (matches an array of the length with the items
+    /// matching each of the patterns. `*rest` is the remaining items and
+    /// `*_` ignores them)
+    Array {
+        items: Vec<Pattern>,
+        rest: Option<String>,
+    },
+}
+
+impl Pattern {
+    /// Return the names of the variables assigned by this pattern
+    pub fn binding_names(&self) -> Vec<&str> {
+        match self {
+            Pattern::Wildcard | Pattern::Value(_) => vec![],
+            Pattern::Binding(name) => vec![name],
+            Pattern::Array { items, rest } => {
+                let mut names = items
+                    .iter()
+                    .flat_map(|item| item.binding_names())
+                    .collect::<Vec<_>>();
+                match rest {
+                    Some(name) if name != "_" => names.push(name),
+                    _ => (),
+                }
+                names
+            }
+        }
+    }
 }
 
 impl Definition {
@@ -135,6 +291,12 @@ impl Definition {
 }
 
 impl AstExpression {
+    /// Set the location of the expression
+    pub fn with_span(mut self, span: Span) -> AstExpression {
+        self.span = Some(span);
+        self
+    }
+
     pub fn may_have_paren_wo_args(&self) -> bool {
         match self.body {
             AstExpressionBody::MethodCall {
@@ -154,7 +316,9 @@ impl AstExpression {
         match self.body {
             AstExpressionBody::IVarRef(_) => true,
             AstExpressionBody::ConstRef(_) => true,
-            // TODO: a[b]
+            AstExpressionBody::MethodCall {
+                ref method_name, ..
+            } => method_name.0 == "[]",
             _ => false,
         }
     }
@@ -199,8 +363,48 @@ pub fn while_expr(cond_expr: AstExpression, body_exprs: Vec<AstExpression>) -> A
     })
 }
 
-pub fn break_expr() -> AstExpression {
-    non_primary_expression(AstExpressionBody::Break {})
+pub fn for_expr(
+    var_name: String,
+    iterable: AstExpression,
+    body_exprs: Vec<AstExpression>,
+) -> AstExpression {
+    non_primary_expression(AstExpressionBody::For {
+        var_name,
+        iterable: Box::new(iterable),
+        body_exprs,
+    })
+}
+
+pub fn loop_expr(body_exprs: Vec<AstExpression>) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Loop { body_exprs })
+}
+
+pub fn case_expr(
+    expr: AstExpression,
+    clauses: Vec<CaseClause>,
+    else_exprs: Option<Vec<AstExpression>>,
+) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Case {
+        expr: Box::new(expr),
+        clauses,
+        else_exprs,
+    })
+}
+
+pub fn break_expr(expr: Option<AstExpression>) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Break {
+        expr: expr.map(Box::new),
+    })
+}
+
+pub fn next_expr() -> AstExpression {
+    non_primary_expression(AstExpressionBody::Next {})
+}
+
+pub fn try_expr(expr: AstExpression) -> AstExpression {
+    primary_expression(AstExpressionBody::Try {
+        expr: Box::new(expr),
+    })
 }
 
 /// Create an expression for an assigment
@@ -220,6 +424,21 @@ pub fn assignment(lhs: AstExpression, rhs: AstExpression) -> AstExpression {
             names,
             rhs: Box::new(rhs),
         },
+        // `a[b] = c` (`a.[]=(b, c)`)
+        AstExpressionBody::MethodCall {
+            receiver_expr,
+            method_name,
+            mut arg_exprs,
+            ..
+        } if method_name.0 == "[]" => {
+            arg_exprs.push(rhs);
+            AstExpressionBody::MethodCall {
+                receiver_expr,
+                method_name: method_name.append("="),
+                arg_exprs,
+                may_have_paren_wo_args: false,
+            }
+        }
         AstExpressionBody::MethodCall {
             receiver_expr,
             method_name,
@@ -243,6 +462,13 @@ pub fn lvar_decl(name: String, rhs: AstExpression) -> AstExpression {
     })
 }
 
+pub fn multiple_assignment(names: Vec<String>, rhs: AstExpression) -> AstExpression {
+    non_primary_expression(AstExpressionBody::MultipleAssign {
+        names,
+        rhs: Box::new(rhs),
+    })
+}
+
 pub fn ivar_decl(name: String, rhs: AstExpression) -> AstExpression {
     non_primary_expression(AstExpressionBody::IVarAssign {
         name,
@@ -281,6 +507,10 @@ pub fn const_ref(names: Vec<String>) -> AstExpression {
     primary_expression(AstExpressionBody::ConstRef(names))
 }
 
+pub fn specialized_const_ref(names: Vec<String>, typ_args: Vec<Typ>) -> AstExpression {
+    primary_expression(AstExpressionBody::SpecializedConstRef { names, typ_args })
+}
+
 pub fn unary_expr(expr: AstExpression, op: &str) -> AstExpression {
     primary_expression(AstExpressionBody::MethodCall {
         receiver_expr: Some(Box::new(expr)),
@@ -303,6 +533,24 @@ pub fn lambda_expr(params: Vec<Param>, exprs: Vec<AstExpression>) -> AstExpressi
     primary_expression(AstExpressionBody::LambdaExpr { params, exprs })
 }
 
+pub fn await_expr(expr: AstExpression) -> AstExpression {
+    non_primary_expression(AstExpressionBody::Await {
+        expr: Box::new(expr),
+    })
+}
+
+pub fn await_call(
+    call: AstExpression,
+    var_name: Option<String>,
+    rest: Vec<AstExpression>,
+) -> AstExpression {
+    non_primary_expression(AstExpressionBody::AwaitCall {
+        call: Box::new(call),
+        var_name,
+        rest,
+    })
+}
+
 pub fn pseudo_variable(token: Token) -> AstExpression {
     primary_expression(AstExpressionBody::PseudoVariable(token))
 }
@@ -311,6 +559,27 @@ pub fn array_literal(exprs: Vec<AstExpression>) -> AstExpression {
     primary_expression(AstExpressionBody::ArrayLiteral(exprs))
 }
 
+/// Tuples have 2 to this number of elements (`Tuple2` to `Tuple4` are
+/// defined in builtin/tuple.sk)
+pub const MAX_TUPLE_LEN: usize = 4;
+
+/// Return the name of the class of tuples with `len` elements
+pub fn tuple_class_name(len: usize) -> String {
+    format!("Tuple{}", len)
+}
+
+pub fn tuple_literal(exprs: Vec<AstExpression>) -> AstExpression {
+    primary_expression(AstExpressionBody::TupleLiteral(exprs))
+}
+
+pub fn range_literal(first: AstExpression, last: AstExpression, exclusive: bool) -> AstExpression {
+    non_primary_expression(AstExpressionBody::RangeLiteral {
+        first: Box::new(first),
+        last: Box::new(last),
+        exclusive,
+    })
+}
+
 pub fn float_literal(value: f64) -> AstExpression {
     primary_expression(AstExpressionBody::FloatLiteral { value })
 }
@@ -323,10 +592,15 @@ pub fn string_literal(content: String) -> AstExpression {
     primary_expression(AstExpressionBody::StringLiteral { content })
 }
 
+pub fn char_literal(value: char) -> AstExpression {
+    primary_expression(AstExpressionBody::CharLiteral { value })
+}
+
 pub fn primary_expression(body: AstExpressionBody) -> AstExpression {
     AstExpression {
         primary: true,
         body,
+        span: None,
     }
 }
 
@@ -334,6 +608,7 @@ pub fn non_primary_expression(body: AstExpressionBody) -> AstExpression {
     AstExpression {
         primary: false,
         body,
+        span: None,
     }
 }
 
@@ -356,6 +631,7 @@ pub fn set_method_call_args(expr: AstExpression, args: Vec<AstExpression>) -> As
 
             AstExpression {
                 primary: false,
+                span: None,
                 body: AstExpressionBody::MethodCall {
                     receiver_expr,
                     method_name,
@@ -366,6 +642,7 @@ pub fn set_method_call_args(expr: AstExpression, args: Vec<AstExpression>) -> As
         }
         AstExpressionBody::BareName(s) => AstExpression {
             primary: false,
+            span: None,
             body: AstExpressionBody::MethodCall {
                 receiver_expr: None,
                 method_name: method_firstname(&s),
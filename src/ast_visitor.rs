@@ -0,0 +1,197 @@
+/// Traversal of the AST
+///
+/// An implementor of `AstVisitor` overrides the `visit_*` methods for the
+/// nodes it is interested in, and calls the corresponding `walk_*` function
+/// to visit their children.
+use crate::ast::*;
+
+pub trait AstVisitor: Sized {
+    fn visit_def(&mut self, def: &Definition) {
+        walk_def(self, def);
+    }
+
+    fn visit_exprs(&mut self, exprs: &[AstExpression]) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &AstExpression) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern);
+    }
+
+    /// Called for the types written in the program (of the params, the
+    /// return values and the type arguments)
+    fn visit_typ(&mut self, typ: &Typ) {
+        walk_typ(self, typ);
+    }
+}
+
+/// Visit the toplevel items of `prog`
+pub fn walk_program<V: AstVisitor>(v: &mut V, prog: &Program) {
+    for item in &prog.toplevel_items {
+        match item {
+            TopLevelItem::Def(def) => v.visit_def(def),
+            TopLevelItem::Expr(expr) => v.visit_expr(expr),
+        }
+    }
+}
+
+pub fn walk_def<V: AstVisitor>(v: &mut V, def: &Definition) {
+    match def {
+        Definition::ClassDefinition { defs, .. } => {
+            for def in defs {
+                v.visit_def(def);
+            }
+        }
+        Definition::InstanceMethodDefinition { sig, body_exprs }
+        | Definition::ClassMethodDefinition { sig, body_exprs } => {
+            walk_sig(v, sig);
+            v.visit_exprs(body_exprs);
+        }
+        Definition::AbstractMethodDefinition { sig }
+        | Definition::ExternMethodDefinition { sig } => walk_sig(v, sig),
+        Definition::ConstDefinition { expr, .. } => v.visit_expr(expr),
+        Definition::Delegation { .. } | Definition::Derive { .. } => (),
+    }
+}
+
+fn walk_sig<V: AstVisitor>(v: &mut V, sig: &AstMethodSignature) {
+    walk_params(v, &sig.params);
+    v.visit_typ(&sig.ret_typ);
+}
+
+fn walk_params<V: AstVisitor>(v: &mut V, params: &[Param]) {
+    for param in params {
+        v.visit_typ(&param.typ);
+    }
+}
+
+pub fn walk_typ<V: AstVisitor>(v: &mut V, typ: &Typ) {
+    for arg in &typ.typ_args {
+        v.visit_typ(arg);
+    }
+}
+
+pub fn walk_pattern<V: AstVisitor>(v: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Value(expr) => v.visit_expr(expr),
+        Pattern::Array { items, .. } => {
+            for item in items {
+                v.visit_pattern(item);
+            }
+        }
+        Pattern::Wildcard | Pattern::Binding(_) => (),
+    }
+}
+
+pub fn walk_expr<V: AstVisitor>(v: &mut V, expr: &AstExpression) {
+    match &expr.body {
+        AstExpressionBody::LogicalNot { expr } => v.visit_expr(expr),
+        AstExpressionBody::LogicalAnd { left, right }
+        | AstExpressionBody::LogicalOr { left, right } => {
+            v.visit_expr(left);
+            v.visit_expr(right);
+        }
+        AstExpressionBody::If {
+            cond_expr,
+            then_exprs,
+            else_exprs,
+        } => {
+            v.visit_expr(cond_expr);
+            v.visit_exprs(then_exprs);
+            if let Some(exprs) = else_exprs {
+                v.visit_exprs(exprs);
+            }
+        }
+        AstExpressionBody::While {
+            cond_expr,
+            body_exprs,
+        } => {
+            v.visit_expr(cond_expr);
+            v.visit_exprs(body_exprs);
+        }
+        AstExpressionBody::Loop { body_exprs } => v.visit_exprs(body_exprs),
+        AstExpressionBody::For {
+            iterable,
+            body_exprs,
+            ..
+        } => {
+            v.visit_expr(iterable);
+            v.visit_exprs(body_exprs);
+        }
+        AstExpressionBody::Case {
+            expr,
+            clauses,
+            else_exprs,
+        } => {
+            v.visit_expr(expr);
+            for clause in clauses {
+                for pattern in &clause.patterns {
+                    v.visit_pattern(pattern);
+                }
+                if let Some(guard) = &clause.guard {
+                    v.visit_expr(guard);
+                }
+                v.visit_exprs(&clause.body_exprs);
+            }
+            if let Some(exprs) = else_exprs {
+                v.visit_exprs(exprs);
+            }
+        }
+        AstExpressionBody::Break { expr } => {
+            if let Some(expr) = expr {
+                v.visit_expr(expr);
+            }
+        }
+        AstExpressionBody::Next => (),
+        AstExpressionBody::Try { expr } => v.visit_expr(expr),
+        AstExpressionBody::LVarAssign { rhs, .. } => v.visit_expr(rhs),
+        AstExpressionBody::IVarAssign { rhs, .. } => v.visit_expr(rhs),
+        AstExpressionBody::ConstAssign { rhs, .. } => v.visit_expr(rhs),
+        AstExpressionBody::MultipleAssign { rhs, .. } => v.visit_expr(rhs),
+        AstExpressionBody::MethodCall {
+            receiver_expr,
+            arg_exprs,
+            ..
+        } => {
+            if let Some(expr) = receiver_expr {
+                v.visit_expr(expr);
+            }
+            v.visit_exprs(arg_exprs);
+        }
+        AstExpressionBody::LambdaExpr { params, exprs } => {
+            walk_params(v, params);
+            v.visit_exprs(exprs);
+        }
+        AstExpressionBody::Await { expr } => v.visit_expr(expr),
+        AstExpressionBody::AwaitCall { call, rest, .. } => {
+            v.visit_expr(call);
+            v.visit_exprs(rest);
+        }
+        AstExpressionBody::BareName(_) => (),
+        AstExpressionBody::IVarRef(_) => (),
+        AstExpressionBody::ConstRef(_) => (),
+        AstExpressionBody::SpecializedConstRef { typ_args, .. } => {
+            for typ in typ_args {
+                v.visit_typ(typ);
+            }
+        }
+        AstExpressionBody::PseudoVariable(_) => (),
+        AstExpressionBody::ArrayLiteral(exprs) | AstExpressionBody::TupleLiteral(exprs) => {
+            v.visit_exprs(exprs)
+        }
+        AstExpressionBody::RangeLiteral { first, last, .. } => {
+            v.visit_expr(first);
+            v.visit_expr(last);
+        }
+        AstExpressionBody::FloatLiteral { .. } => (),
+        AstExpressionBody::DecimalLiteral { .. } => (),
+        AstExpressionBody::StringLiteral { .. } => (),
+        AstExpressionBody::CharLiteral { .. } => (),
+    }
+}
@@ -0,0 +1,63 @@
+/// Cache of the compiled programs
+///
+/// When a program is compiled, `<file>.ll.stamp` is written next to the
+/// .ll. It has the hash of the inputs of the compilation (the program, the
+/// builtin library, the version of the compiler and the options), and if
+/// it is the same next time, the .ll is up to date and parsing, type
+/// checking and code generation are skipped.
+///
+/// A program is a single file, so the unit of the cache is the whole
+/// program rather than each class in it. Programs with warnings are not
+/// cached so that the warnings are reported every time. This is disabled
+/// with `SHIIKA_NO_CACHE` as well as the cache of the builtin library.
+use crate::builtin_cache;
+use crate::error::*;
+use crate::hir::config::BuildConfig;
+use std::fs;
+use std::path::PathBuf;
+
+/// Return the hash of the inputs of the compilation
+pub fn key(src: &str, builtin: &str, verify: bool, config: &BuildConfig) -> String {
+    let cache_dir = builtin_cache::cache_dir();
+    format!(
+        "{} {:016x} {:016x} {:016x} {} {:016x}",
+        env!("CARGO_PKG_VERSION"),
+        builtin_cache::hash_of(src),
+        builtin_cache::hash_of(builtin),
+        builtin_cache::hash_of(&cache_dir),
+        verify,
+        builtin_cache::hash_of(&format!("{:?}", config))
+    )
+}
+
+/// Returns true if the .ll is compiled from the inputs of `key` (and the
+/// bitcode of the builtin library linked with it still exists)
+pub fn is_fresh(ll_path: &str, key: &str) -> bool {
+    let stamp_ok =
+        fs::read_to_string(stamp_path(ll_path)).map_or(false, |stamp| stamp.trim_end() == key);
+    if !stamp_ok {
+        return false;
+    }
+    match builtin_cache::linked_bitcode(ll_path) {
+        Ok(Some(bitcode_path)) => bitcode_path.is_file(),
+        Ok(None) => true,
+        Err(_) => false,
+    }
+}
+
+/// Record that the .ll is compiled from the inputs of `key`
+pub fn save(ll_path: &str, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let path = stamp_path(ll_path);
+    fs::write(&path, format!("{}\n", key))
+        .map_err(|e| runner_error(format!("failed to write {}", path.display()), e))?;
+    Ok(())
+}
+
+/// Remove the record of the .ll (called before the .ll is written)
+pub fn invalidate(ll_path: &str) {
+    let _ = fs::remove_file(stamp_path(ll_path));
+}
+
+fn stamp_path(ll_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.stamp", ll_path))
+}
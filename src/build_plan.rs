@@ -0,0 +1,205 @@
+/// The steps to make an executable from a program
+///
+/// `shiika compile --emit plan foo.sk` prints them as JSON without running
+/// them, so that other build systems (Make, Bazel, etc.) can run the
+/// compiler and the tools by themselves:
+///
+/// ```text
+///   {"sources": [...],         the program and the builtin library
+///    "products": [...],        the executable
+///    "steps": [{"name": "compile", "inputs": [...], "outputs": [...],
+///               "command": ["/path/to/shiika", "compile", "foo.sk"]},
+///              {"name": "llc", ...}, {"name": "link", ...}]}
+/// ```
+///
+/// The steps are in the order to run them, and the inputs and the outputs
+/// of each step are the edges of the dependency graph. `shiika run` links
+/// the program with the same commands.
+use crate::builtin_cache;
+use crate::error::{json_str, Error};
+use crate::platform::{self, Platform, Toolchain};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The steps to make an executable
+#[derive(Debug, PartialEq)]
+pub struct BuildPlan {
+    /// The program and the .sk files of the builtin library
+    pub sources: Vec<PathBuf>,
+    pub steps: Vec<Step>,
+    /// The executable
+    pub product: PathBuf,
+}
+
+/// A command run to make the executable
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    /// `compile`, `llc`, `llc-builtin` or `link`
+    pub name: &'static str,
+    /// Files read by the command
+    pub inputs: Vec<PathBuf>,
+    /// Files written by the command
+    pub outputs: Vec<PathBuf>,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+}
+
+impl BuildPlan {
+    /// Make the plan for `sk_path`. `builtin_bitcode` is the bitcode of
+    /// the builtin library if it is linked from the cache (see
+    /// builtin_cache.rs)
+    pub fn new(
+        sk_path: &str,
+        builtin_sources: Vec<PathBuf>,
+        builtin_bitcode: Option<PathBuf>,
+        platform: Platform,
+        toolchain: Toolchain,
+    ) -> Result<BuildPlan, Error> {
+        let ll_path = PathBuf::from(format!("{}.ll", sk_path));
+        let obj_path = PathBuf::from(format!("{}{}", sk_path, toolchain.llc_output_ext()));
+        let product = platform.exe_path(sk_path);
+        let mut sources = vec![PathBuf::from(sk_path)];
+        sources.extend(builtin_sources);
+
+        let mut compile_outputs = vec![ll_path.clone()];
+        compile_outputs.extend(builtin_bitcode.clone());
+        let compiler = env::current_exe().unwrap_or_else(|_| PathBuf::from("shiika"));
+        let mut steps = vec![
+            Step {
+                name: "compile",
+                inputs: sources.clone(),
+                outputs: compile_outputs,
+                program: compiler,
+                args: vec!["compile".to_string(), sk_path.to_string()],
+            },
+            llc_step(toolchain, &ll_path, &obj_path)?,
+        ];
+        let mut link_inputs = vec![obj_path];
+        if let Some(bitcode_path) = builtin_bitcode {
+            let builtin_obj = builtin_cache::object_path_of(&bitcode_path, toolchain);
+            steps.push(llc_step_(
+                "llc-builtin",
+                toolchain.llc_object_args(),
+                &bitcode_path,
+                &builtin_obj,
+            )?);
+            link_inputs.push(builtin_obj);
+        }
+        steps.push(link_step(platform, toolchain, link_inputs, &product)?);
+        Ok(BuildPlan {
+            sources,
+            steps,
+            product,
+        })
+    }
+
+    /// Return the step named `name`
+    pub fn step(&self, name: &str) -> Option<&Step> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+
+    pub fn to_json(&self) -> String {
+        let steps = self
+            .steps
+            .iter()
+            .map(|step| {
+                let command = std::iter::once(step.program.to_string_lossy().to_string())
+                    .chain(step.args.iter().cloned())
+                    .map(|s| json_str(&s))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\"name\":{},\"inputs\":{},\"outputs\":{},\"command\":[{}]}}",
+                    json_str(step.name),
+                    json_paths(&step.inputs),
+                    json_paths(&step.outputs),
+                    command.join(",")
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"sources\":{},\"products\":{},\"steps\":[{}]}}\n",
+            json_paths(&self.sources),
+            json_paths(&[self.product.clone()]),
+            steps.join(",")
+        )
+    }
+}
+
+impl Step {
+    /// Return the command to run this step
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        cmd
+    }
+}
+
+/// The step which compiles .ll into an assembly (or an object file)
+pub fn llc_step(toolchain: Toolchain, ll_path: &Path, obj_path: &Path) -> Result<Step, Error> {
+    llc_step_("llc", toolchain.llc_args(), ll_path, obj_path)
+}
+
+fn llc_step_(
+    name: &'static str,
+    args: Vec<&str>,
+    input: &Path,
+    output: &Path,
+) -> Result<Step, Error> {
+    let mut args = args.into_iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    args.push(input.to_string_lossy().to_string());
+    args.push("-o".to_string());
+    args.push(output.to_string_lossy().to_string());
+    Ok(Step {
+        name,
+        inputs: vec![input.to_path_buf()],
+        outputs: vec![output.to_path_buf()],
+        program: platform::llvm_tool("LLC", "llc")?,
+        args,
+    })
+}
+
+/// The step which links the executable. The arguments in `CFLAGS`,
+/// `LDFLAGS` and `LDLIBS` are given to the linker
+pub fn link_step(
+    platform: Platform,
+    toolchain: Toolchain,
+    inputs: Vec<PathBuf>,
+    out_path: &Path,
+) -> Result<Step, Error> {
+    let mut args = vec![];
+    if toolchain != Toolchain::LldLink {
+        args.extend(args_from_env("CFLAGS"));
+    }
+    args.extend(args_from_env("LDFLAGS"));
+    args.extend(args_from_env("LDLIBS"));
+    let input_strs = inputs
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    let input_strs = input_strs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    args.extend(toolchain.link_args(platform, &input_strs, out_path));
+    Ok(Step {
+        name: "link",
+        inputs,
+        outputs: vec![out_path.to_path_buf()],
+        program: toolchain.linker()?,
+        args,
+    })
+}
+
+fn args_from_env(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_else(|_| "".to_string())
+        .split_ascii_whitespace()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn json_paths(paths: &[PathBuf]) -> String {
+    let items = paths
+        .iter()
+        .map(|path| json_str(&path.to_string_lossy()))
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
@@ -0,0 +1,209 @@
+/// Cache of the compiled builtin library
+///
+/// The methods of the builtin library are compiled into LLVM bitcode when
+/// a program is compiled for the first time (see code_gen/part.rs), and
+/// saved in the cache directory with the list of them. The file names
+/// contain the version of the compiler and the hash of the builtin
+/// library, so they are made again when either is changed. When a program
+/// is linked, the bitcode is compiled into an object file for the
+/// toolchain (which is cached too.)
+///
+/// The cache directory is `SHIIKA_CACHE_DIR` (default: `shiika` in the
+/// temporary directory.) Set `SHIIKA_NO_CACHE` to compile the whole
+/// program every time.
+use crate::code_gen::{self, BuiltinIndex};
+use crate::error::*;
+use crate::hir::config::BuildConfig;
+use crate::platform::{self, Toolchain};
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The first line of .ll starts with this (followed by the path of the
+/// bitcode) when it needs the compiled builtin library
+const HEADER_PREFIX: &str = "; shiika-builtin: ";
+
+/// Counter to make the names of the temporary files unique
+static TMP_CT: AtomicUsize = AtomicUsize::new(0);
+
+/// The compiled builtin library
+#[derive(Debug)]
+pub struct BuiltinCache {
+    /// Path of the bitcode
+    pub bitcode_path: PathBuf,
+    /// What the bitcode contains
+    pub index: BuiltinIndex,
+}
+
+impl BuiltinCache {
+    /// Return the first line of .ll which links this
+    pub fn header(&self) -> String {
+        format!("{}{}", HEADER_PREFIX, self.bitcode_path.to_string_lossy())
+    }
+}
+
+/// Returns false if `SHIIKA_NO_CACHE` is set
+pub fn enabled() -> bool {
+    env::var_os("SHIIKA_NO_CACHE").is_none()
+}
+
+/// Return the directory to save the caches
+pub fn cache_dir() -> PathBuf {
+    match env::var_os("SHIIKA_CACHE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::temp_dir().join("shiika"),
+    }
+}
+
+/// Return the path of the bitcode of the builtin library
+pub fn bitcode_path(builtin: &str) -> PathBuf {
+    cache_dir().join(format!(
+        "builtin-{}-{:016x}.bc",
+        env!("CARGO_PKG_VERSION"),
+        hash_of(builtin)
+    ))
+}
+
+/// Return what the bitcode of the builtin library contains without
+/// compiling it (the index is made from the source if not cached)
+pub fn index(builtin: &str) -> Result<BuiltinIndex, Box<dyn std::error::Error>> {
+    match cached_index(&bitcode_path(builtin)) {
+        Some(index) => Ok(index),
+        None => {
+            let ast = crate::runner::parse_program("", builtin, &BuildConfig::default())?;
+            let hir = crate::runner::build_hir(ast, builtin, &BuildConfig::default())?;
+            Ok(BuiltinIndex::new(&hir))
+        }
+    }
+}
+
+/// Return the compiled builtin library. It is compiled if not cached
+pub fn load(builtin: &str) -> Result<BuiltinCache, Box<dyn std::error::Error>> {
+    let bitcode_path = bitcode_path(builtin);
+    let index_path = bitcode_path.with_extension("index");
+    if let Some(index) = cached_index(&bitcode_path) {
+        return Ok(BuiltinCache {
+            bitcode_path,
+            index,
+        });
+    }
+
+    let dir = cache_dir();
+    fs::create_dir_all(&dir)
+        .map_err(|e| runner_error(format!("failed to create {}", dir.display()), e))?;
+    let ast = crate::runner::parse_program("", builtin, &BuildConfig::default())?;
+    let hir = crate::runner::build_hir(ast, builtin, &BuildConfig::default())?;
+    let index = BuiltinIndex::new(&hir);
+    // Write to temporary files first so that other compilers do not read
+    // broken ones. The index is renamed first because the bitcode is
+    // looked up first
+    let tmp_bitcode = tmp_path(&bitcode_path);
+    code_gen::gen_builtin_bitcode(&hir, &tmp_bitcode)?;
+    let tmp_index = tmp_path(&index_path);
+    fs::write(&tmp_index, index.to_text())
+        .map_err(|e| runner_error(format!("failed to write {}", tmp_index.display()), e))?;
+    rename(&tmp_index, &index_path)?;
+    rename(&tmp_bitcode, &bitcode_path)?;
+    Ok(BuiltinCache {
+        bitcode_path,
+        index,
+    })
+}
+
+/// Return the index saved with the bitcode (`None` if either is missing)
+fn cached_index(bitcode_path: &Path) -> Option<BuiltinIndex> {
+    if !bitcode_path.is_file() {
+        return None;
+    }
+    fs::read_to_string(bitcode_path.with_extension("index"))
+        .ok()
+        .and_then(|text| BuiltinIndex::from_text(&text))
+}
+
+/// Return the path of the bitcode to link with the .ll (`None` if the .ll
+/// contains the whole program)
+pub fn linked_bitcode(ll_path: &str) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let file =
+        fs::File::open(ll_path).map_err(|e| runner_error(format!("{} not found", ll_path), e))?;
+    let mut line = String::new();
+    BufReader::new(file)
+        .read_line(&mut line)
+        .map_err(|e| runner_error(format!("failed to read {}", ll_path), e))?;
+    if line.starts_with(HEADER_PREFIX) {
+        Ok(Some(PathBuf::from(line[HEADER_PREFIX.len()..].trim_end())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Return the object file of the bitcode for the toolchain. It is made
+/// with llc if not cached
+pub fn object_path(
+    bitcode_path: &Path,
+    toolchain: Toolchain,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !bitcode_path.is_file() {
+        return Err(Box::new(plain_runner_error(format!(
+            "{} not found (compile the program again)",
+            bitcode_path.display()
+        ))));
+    }
+    let obj_path = object_path_of(bitcode_path, toolchain);
+    if obj_path.is_file() {
+        return Ok(obj_path);
+    }
+
+    let tmp_obj = tmp_path(&obj_path);
+    let mut cmd = Command::new(platform::llvm_tool("LLC", "llc")?);
+    cmd.args(toolchain.llc_object_args());
+    cmd.arg(bitcode_path);
+    cmd.arg("-o");
+    cmd.arg(&tmp_obj);
+    let status = cmd
+        .status()
+        .map_err(|e| runner_error("failed to run llc", e))?;
+    if !status.success() {
+        return Err(Box::new(plain_runner_error("llc failed")));
+    }
+    rename(&tmp_obj, &obj_path)?;
+    Ok(obj_path)
+}
+
+/// Return the path of the object file made from the bitcode for the
+/// toolchain (without making it)
+pub fn object_path_of(bitcode_path: &Path, toolchain: Toolchain) -> PathBuf {
+    bitcode_path.with_extension(format!(
+        "{:016x}{}",
+        hash_of(&toolchain.llc_object_args().join(" ")),
+        toolchain.object_ext()
+    ))
+}
+
+/// Return the hash of the value (used in the names of the cache files)
+pub(crate) fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Return a unique path to write `path` temporarily
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut s = path.as_os_str().to_os_string();
+    s.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TMP_CT.fetch_add(1, Ordering::SeqCst)
+    ));
+    PathBuf::from(s)
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::rename(from, to)
+        .map_err(|e| runner_error(format!("failed to write {}", to.display()), e))?;
+    Ok(())
+}
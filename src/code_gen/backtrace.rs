@@ -0,0 +1,174 @@
+/// The backtrace printed by `panic` (a shadow stack)
+///
+/// When a Shiika method is called, it stores its name (eg. `A#foo`) to
+/// `shiika_backtrace[depth % MAX_FRAMES]` and increments
+/// `shiika_backtrace_depth`. The depth is restored when the method
+/// returns. `shiika_print_backtrace` prints the names from the innermost
+/// one:
+///
+/// ```text
+///   [panic at A#foo: msg]
+///     from Object#panic_at
+///     from A#foo
+///     from Meta:A#run
+/// ```
+///
+/// Only the last `MAX_FRAMES` frames are kept. The methods of a release
+/// build (`--release`) are not recorded (but the builtin library is
+/// compiled only once and always records its methods.) Lambdas, the
+/// toplevel and the methods implemented in Rust are not recorded, and the
+/// frames of a fiber are mixed with the ones of its caller. The HIR has no
+/// line numbers, so only the names of the methods are printed.
+use crate::code_gen::code_gen_context::*;
+use crate::code_gen::*;
+use inkwell::values::{BasicValueEnum, IntValue, PointerValue};
+use inkwell::IntPredicate;
+
+const FRAMES: &str = "shiika_backtrace";
+const DEPTH: &str = "shiika_backtrace_depth";
+const PRINT_BACKTRACE: &str = "shiika_print_backtrace";
+
+/// Number of the frames kept (must be a power of 2)
+const MAX_FRAMES: u64 = 64;
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_backtrace_declares(&self) {
+        let nulls = vec![self.i8ptr_type.const_null(); MAX_FRAMES as usize];
+        let frames = self.i8ptr_type.const_array(&nulls);
+        let global = self.module.add_global(frames.get_type(), None, FRAMES);
+        self.set_shared_initializer(global, &frames);
+        let global = self.module.add_global(self.i64_type, None, DEPTH);
+        self.set_shared_initializer(global, &self.i64_type.const_int(0, false));
+        let fn_type = self.void_type.fn_type(&[], false);
+        self.module.add_function(PRINT_BACKTRACE, fn_type, None);
+    }
+
+    /// Record that the method `name` is called. Returns the depth to
+    /// restore when it returns (see `build_backtrace_pop`)
+    pub(super) fn build_backtrace_push(&self, name: &str) -> IntValue<'ictx> {
+        let depth_ptr = self.module.get_global(DEPTH).unwrap().as_pointer_value();
+        let depth = self.builder.build_load(depth_ptr, "depth").into_int_value();
+        let slot = self.backtrace_slot(depth);
+        let name_ptr = self
+            .builder
+            .build_global_string_ptr(name, "method_name")
+            .as_pointer_value();
+        self.builder.build_store(slot, name_ptr);
+        let next = self
+            .builder
+            .build_int_add(depth, self.i64_type.const_int(1, false), "next");
+        self.builder.build_store(depth_ptr, next);
+        depth
+    }
+
+    /// Restore the depth of the backtrace before returning from a method
+    pub(super) fn build_backtrace_pop(&self, ctx: &CodeGenContext<'hir, 'run>) {
+        if let Some(depth) = ctx.backtrace_depth {
+            let depth_ptr = self.module.get_global(DEPTH).unwrap().as_pointer_value();
+            self.builder.build_store(depth_ptr, depth);
+        }
+    }
+
+    /// Call `shiika_print_backtrace`
+    pub fn build_print_backtrace(&self) {
+        let func = self.get_llvm_func(PRINT_BACKTRACE);
+        self.builder.build_call(func, &[], "");
+    }
+
+    /// Define `shiika_print_backtrace`
+    pub(super) fn gen_print_backtrace(&self) {
+        let function = self.get_llvm_func(PRINT_BACKTRACE);
+        let begin_block = self.context.append_basic_block(function, "Begin");
+        let loop_block = self.context.append_basic_block(function, "Loop");
+        let print_block = self.context.append_basic_block(function, "Print");
+        let omitted_block = self.context.append_basic_block(function, "Omitted");
+        let print_omitted_block = self.context.append_basic_block(function, "PrintOmitted");
+        let end_block = self.context.append_basic_block(function, "End");
+        let max = self.i64_type.const_int(MAX_FRAMES, false);
+        let zero = self.i64_type.const_int(0, false);
+
+        // Begin:
+        self.builder.position_at_end(begin_block);
+        let depth_ptr = self.module.get_global(DEPTH).unwrap().as_pointer_value();
+        let depth = self.builder.build_load(depth_ptr, "depth").into_int_value();
+        // The frames below `lowest` are overwritten
+        let overflow = self
+            .builder
+            .build_int_compare(IntPredicate::UGT, depth, max, "overflow");
+        let lowest = self
+            .builder
+            .build_select(
+                overflow,
+                self.builder.build_int_sub(depth, max, ""),
+                zero,
+                "lowest",
+            )
+            .into_int_value();
+        let i_ptr = self.builder.build_alloca(self.i64_type, "i");
+        self.builder.build_store(i_ptr, depth);
+        self.builder.build_unconditional_branch(loop_block);
+
+        // Loop:
+        self.builder.position_at_end(loop_block);
+        let i = self.builder.build_load(i_ptr, "i").into_int_value();
+        let has_more = self
+            .builder
+            .build_int_compare(IntPredicate::UGT, i, lowest, "has_more");
+        self.builder
+            .build_conditional_branch(has_more, print_block, omitted_block);
+
+        // Print:
+        self.builder.position_at_end(print_block);
+        let i = self
+            .builder
+            .build_int_sub(i, self.i64_type.const_int(1, false), "i");
+        self.builder.build_store(i_ptr, i);
+        let slot = self.backtrace_slot(i);
+        let name = self.builder.build_load(slot, "name");
+        self.build_printf("  from %s\n", &[name]);
+        self.builder.build_unconditional_branch(loop_block);
+
+        // Omitted:
+        self.builder.position_at_end(omitted_block);
+        let omitted = self
+            .builder
+            .build_int_compare(IntPredicate::UGT, lowest, zero, "omitted");
+        self.builder
+            .build_conditional_branch(omitted, print_omitted_block, end_block);
+
+        // PrintOmitted:
+        self.builder.position_at_end(print_omitted_block);
+        self.build_printf("  ... (%lld more)\n", &[lowest.into()]);
+        self.builder.build_unconditional_branch(end_block);
+
+        // End:
+        self.builder.position_at_end(end_block);
+        self.builder.build_return(None);
+    }
+
+    /// The element of `shiika_backtrace` for `depth`
+    fn backtrace_slot(&self, depth: IntValue<'ictx>) -> PointerValue<'ictx> {
+        let frames = self.module.get_global(FRAMES).unwrap().as_pointer_value();
+        let idx =
+            self.builder
+                .build_and(depth, self.i64_type.const_int(MAX_FRAMES - 1, false), "idx");
+        unsafe {
+            self.builder.build_in_bounds_gep(
+                frames,
+                &[self.i64_type.const_int(0, false), idx],
+                "slot",
+            )
+        }
+    }
+
+    fn build_printf(&self, fmt: &str, args: &[BasicValueEnum<'ictx>]) {
+        let fmt = self
+            .builder
+            .build_global_string_ptr(fmt, "fmt")
+            .as_pointer_value();
+        let mut printf_args = vec![fmt.into()];
+        printf_args.extend_from_slice(args);
+        let func = self.get_llvm_func("printf");
+        self.builder.build_call(func, &printf_args, "");
+    }
+}
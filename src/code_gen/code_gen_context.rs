@@ -2,7 +2,6 @@ use crate::hir::*;
 use crate::ty::*;
 use std::collections::HashMap;
 use std::collections::VecDeque;
-use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct CodeGenContext<'hir: 'run, 'run> {
@@ -16,9 +15,13 @@ pub struct CodeGenContext<'hir: 'run, 'run> {
     pub function_params: Option<&'hir [MethodParam]>,
     /// Ptr of local variables
     pub lvars: HashMap<String, inkwell::values::PointerValue<'run>>,
-    pub current_loop_end: Option<Rc<inkwell::basic_block::BasicBlock<'run>>>,
+    /// Loops we are in. The last one is the innermost
+    pub loops: Vec<CodeGenLoop<'run>>,
     /// Lambdas to be compiled
     pub lambdas: VecDeque<CodeGenLambda<'hir>>,
+    /// The depth of the backtrace to restore when returning from the
+    /// method (see code_gen/backtrace.rs)
+    pub backtrace_depth: Option<inkwell::values::IntValue<'run>>,
 }
 
 #[derive(Debug)]
@@ -28,6 +31,17 @@ pub enum FunctionOrigin {
     Other,
 }
 
+/// A loop (`while`)
+#[derive(Debug)]
+pub struct CodeGenLoop<'run> {
+    /// Where `next` jumps to
+    pub begin_block: inkwell::basic_block::BasicBlock<'run>,
+    /// Where `break` jumps to
+    pub end_block: inkwell::basic_block::BasicBlock<'run>,
+    /// Where `break` stores the value of the loop (if it has one)
+    pub result_ptr: Option<inkwell::values::PointerValue<'run>>,
+}
+
 #[derive(Debug)]
 pub struct CodeGenLambda<'hir> {
     pub func_name: String,
@@ -46,8 +60,9 @@ impl<'hir, 'run> CodeGenContext<'hir, 'run> {
             function_origin,
             function_params,
             lvars: HashMap::new(),
-            current_loop_end: None,
+            loops: vec![],
             lambdas: VecDeque::new(),
+            backtrace_depth: None,
         }
     }
 }
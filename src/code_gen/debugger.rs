@@ -0,0 +1,636 @@
+/// The debugger for `breakpoint`
+///
+/// `breakpoint` calls `shiika_debugger(place, n, vars)`, where `vars` is an
+/// array of `n` records of `var_type`:
+///
+/// - `name`: the name of the variable (eg. `x`, `@x`)
+/// - `type`: the name of its type (shown for the objects other than
+///   `Int`, `Float`, `Bool` and `String`)
+/// - `kind`: how the value is shown (`KIND_*`)
+/// - `value`: the object (null if not set yet)
+///
+/// The debugger reads commands from stdin until `c` is given (or stdin is
+/// closed). `n` continues too but sets `@shiika_debugger_stepping`; the
+/// statements after a `breakpoint` are preceded by step points, which enter
+/// the debugger only when it is set.
+///
+/// The debugger runs only if the environment variable `SHIIKA_DEBUGGER` is
+/// set (`shiika run` sets it), so `breakpoint` does nothing in the
+/// executables run by other ways.
+use crate::code_gen::code_gen_context::*;
+use crate::code_gen::*;
+use crate::error::Error;
+use crate::hir::HirExpression;
+use crate::ty;
+use crate::ty::TermTy;
+use inkwell::values::{BasicValueEnum, IntValue, PointerValue};
+use inkwell::AddressSpace;
+
+const DEBUGGER: &str = "shiika_debugger";
+const PRINT_VAR: &str = "shiika_debugger_print_var";
+
+/// Global variable which is true after the `n` command
+const STEPPING: &str = "shiika_debugger_stepping";
+
+/// Name of the environment variable which enables the debugger
+pub const DEBUGGER_ENV: &str = "SHIIKA_DEBUGGER";
+
+/// Bytes of `var_type` (assuming 64bit env)
+const VAR_SIZE: u64 = 32;
+
+/// Bytes of the buffer of a command line (longer lines are truncated)
+const LINE_SIZE: u32 = 256;
+
+const KIND_OBJECT: u64 = 0;
+const KIND_INT: u64 = 1;
+const KIND_FLOAT: u64 = 2;
+const KIND_BOOL: u64 = 3;
+const KIND_STRING: u64 = 4;
+
+const HELP: &str = "c, continue    continue the program
+n, next        run until the next statement
+l, locals      show the variables
+p NAME         show the variable
+q, quit        exit the program
+";
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_debugger_declares(&self) {
+        let var_ptr_type = self.debugger_var_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.void_type.fn_type(
+            &[
+                self.i8ptr_type.into(),
+                self.i32_type.into(),
+                var_ptr_type.into(),
+            ],
+            false,
+        );
+        self.module.add_function(DEBUGGER, fn_type, None);
+        let fn_type = self.void_type.fn_type(&[var_ptr_type.into()], false);
+        self.module.add_function(PRINT_VAR, fn_type, None);
+
+        let fn_type = self.i32_type.fn_type(&[], false);
+        self.module.add_function("getchar", fn_type, None);
+        let fn_type = self.i8ptr_type.fn_type(&[self.i8ptr_type.into()], false);
+        self.module.add_function("getenv", fn_type, None);
+        let fn_type = self
+            .i32_type
+            .fn_type(&[self.i8ptr_type.into(), self.i8ptr_type.into()], false);
+        self.module.add_function("strcmp", fn_type, None);
+        let fn_type = self.i32_type.fn_type(
+            &[
+                self.i8ptr_type.into(),
+                self.i8ptr_type.into(),
+                self.i64_type.into(),
+            ],
+            false,
+        );
+        self.module.add_function("strncmp", fn_type, None);
+
+        let global = self.module.add_global(self.i1_type, None, STEPPING);
+        self.set_shared_initializer(global, &self.i1_type.const_int(0, false));
+    }
+
+    /// `{ i8* name, i8* type, i32 kind, i8* value }`
+    fn debugger_var_type(&self) -> inkwell::types::StructType<'ictx> {
+        self.context.struct_type(
+            &[
+                self.i8ptr_type.into(),
+                self.i8ptr_type.into(),
+                self.i32_type.into(),
+                self.i8ptr_type.into(),
+            ],
+            false,
+        )
+    }
+
+    /// Generate `breakpoint` (or a step point if `step` is true)
+    pub(super) fn gen_breakpoint(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        place: &str,
+        vars: &'hir [(String, HirExpression)],
+        step: bool,
+    ) -> Result<(), Error> {
+        let mut end_block = None;
+        if step {
+            // Step points do nothing unless the `n` command is given
+            let stepping = self.module.get_global(STEPPING).unwrap().as_pointer_value();
+            let flag = self
+                .builder
+                .build_load(stepping, "stepping")
+                .into_int_value();
+            let stop_block = self.context.append_basic_block(ctx.function, "StepPoint");
+            let after_block = self
+                .context
+                .append_basic_block(ctx.function, "AfterStepPoint");
+            self.builder
+                .build_conditional_branch(flag, stop_block, after_block);
+            self.builder.position_at_end(stop_block);
+            end_block = Some(after_block);
+        }
+
+        let var_ptr_type = self.debugger_var_type().ptr_type(AddressSpace::Generic);
+        let size = self.i64_type.const_int(VAR_SIZE * vars.len() as u64, false);
+        let ary = self
+            .builder
+            .build_call(self.get_llvm_func("GC_malloc"), &[size.into()], "vars")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        let ary = self
+            .builder
+            .build_bitcast(ary, var_ptr_type, "vars")
+            .into_pointer_value();
+        for (i, (name, expr)) in vars.iter().enumerate() {
+            let value = self.gen_expr(ctx, expr)?;
+            let idx = self.i64_type.const_int(i as u64, false);
+            let var = unsafe { self.builder.build_in_bounds_gep(ary, &[idx], "var") };
+            let fields: [BasicValueEnum; 4] = [
+                self.debugger_str(name),
+                self.debugger_str(&expr.ty.fullname.0),
+                self.i32_type
+                    .const_int(debugger_var_kind(&expr.ty), false)
+                    .into(),
+                self.builder.build_bitcast(value, self.i8ptr_type, "value"),
+            ];
+            for (j, field) in fields.iter().enumerate() {
+                let ptr = self.builder.build_struct_gep(var, j as u32, "").unwrap();
+                self.builder.build_store(ptr, *field);
+            }
+        }
+        let n = self.i32_type.const_int(vars.len() as u64, false);
+        self.builder.build_call(
+            self.get_llvm_func(DEBUGGER),
+            &[self.debugger_str(place), n.into(), ary.into()],
+            "",
+        );
+
+        if let Some(block) = end_block {
+            self.builder.build_unconditional_branch(block);
+            self.builder.position_at_end(block);
+        }
+        Ok(())
+    }
+
+    /// Define `shiika_debugger` and `shiika_debugger_print_var`
+    pub(super) fn gen_debugger(&self) {
+        self.gen_debugger_print_var();
+
+        let function = self.get_llvm_func(DEBUGGER);
+        let place = function.get_params()[0];
+        let n = function.get_params()[1].into_int_value();
+        let vars = function.get_params()[2].into_pointer_value();
+        let block = |name: &str| self.context.append_basic_block(function, name);
+        let entry_block = block("Entry");
+        let start_block = block("Start");
+        let prompt_block = block("Prompt");
+        let read_block = block("ReadChar");
+        let check_block = block("CheckChar");
+        let store_block = block("StoreChar");
+        let eof_block = block("Eof");
+        let dispatch_block = block("Dispatch");
+        let next_block = block("Next");
+        let locals_block = block("Locals");
+        let locals_body_block = block("LocalsBody");
+        let print_block = block("Print");
+        let print_check_block = block("PrintCheck");
+        let print_found_block = block("PrintFound");
+        let print_next_block = block("PrintNext");
+        let not_found_block = block("NotFound");
+        let help_block = block("Help");
+        let quit_block = block("Quit");
+        let unknown_block = block("Unknown");
+        let return_block = block("Return");
+
+        // Entry:
+        self.builder.position_at_end(entry_block);
+        let buf_type = self.i8_type.array_type(LINE_SIZE);
+        let buf = self.builder.build_alloca(buf_type, "buf");
+        let buf = self
+            .builder
+            .build_bitcast(buf, self.i8ptr_type, "line")
+            .into_pointer_value();
+        let len_ptr = self.builder.build_alloca(self.i32_type, "len");
+        let i_ptr = self.builder.build_alloca(self.i32_type, "i");
+        let env = self
+            .builder
+            .build_call(
+                self.get_llvm_func("getenv"),
+                &[self.debugger_str(DEBUGGER_ENV)],
+                "env",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        let disabled = self.builder.build_is_null(env, "disabled");
+        self.builder
+            .build_conditional_branch(disabled, return_block, start_block);
+
+        // Start:
+        self.builder.position_at_end(start_block);
+        let stepping = self.module.get_global(STEPPING).unwrap().as_pointer_value();
+        self.builder
+            .build_store(stepping, self.i1_type.const_int(0, false));
+        self.build_debugger_printf("[breakpoint] %s\n", &[place]);
+        self.builder.build_unconditional_branch(prompt_block);
+
+        // Prompt:
+        self.builder.position_at_end(prompt_block);
+        self.build_debugger_printf("(debug) ", &[]);
+        self.builder.build_call(
+            self.get_llvm_func("fflush"),
+            &[self.i8ptr_type.const_null().into()],
+            "",
+        );
+        self.builder
+            .build_store(len_ptr, self.i32_type.const_int(0, false));
+        self.builder.build_unconditional_branch(read_block);
+
+        // ReadChar:
+        self.builder.position_at_end(read_block);
+        let c = self
+            .builder
+            .build_call(self.get_llvm_func("getchar"), &[], "c")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let minus_one = self.i32_type.const_int(-1i64 as u64, true);
+        let is_eof =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::EQ, c, minus_one, "is_eof");
+        self.builder
+            .build_conditional_branch(is_eof, eof_block, check_block);
+
+        // CheckChar:
+        self.builder.position_at_end(check_block);
+        let newline = self.i32_type.const_int('\n' as u64, false);
+        let is_newline =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::EQ, c, newline, "is_newline");
+        let len = self.builder.build_load(len_ptr, "len").into_int_value();
+        let max = self.i32_type.const_int(LINE_SIZE as u64 - 1, false);
+        let is_full =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::SGE, len, max, "is_full");
+        let skip_char = self.builder.build_or(is_newline, is_full, "skip");
+        let newline_or_skip = self.context.append_basic_block(function, "SkipChar");
+        self.builder
+            .build_conditional_branch(skip_char, newline_or_skip, store_block);
+        // SkipChar:
+        self.builder.position_at_end(newline_or_skip);
+        self.builder
+            .build_conditional_branch(is_newline, dispatch_block, read_block);
+
+        // StoreChar:
+        self.builder.position_at_end(store_block);
+        let ptr = unsafe { self.builder.build_in_bounds_gep(buf, &[len], "ptr") };
+        let ch = self.builder.build_int_truncate(c, self.i8_type, "ch");
+        self.builder.build_store(ptr, ch);
+        let one = self.i32_type.const_int(1, false);
+        let len2 = self.builder.build_int_add(len, one, "len");
+        self.builder.build_store(len_ptr, len2);
+        self.builder.build_unconditional_branch(read_block);
+
+        // Eof: (continue the program after the last command)
+        self.builder.position_at_end(eof_block);
+        let len = self.builder.build_load(len_ptr, "len").into_int_value();
+        let is_empty = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            len,
+            self.i32_type.const_int(0, false),
+            "is_empty",
+        );
+        self.builder
+            .build_conditional_branch(is_empty, return_block, dispatch_block);
+
+        // Dispatch:
+        self.builder.position_at_end(dispatch_block);
+        let len = self.builder.build_load(len_ptr, "len").into_int_value();
+        let ptr = unsafe { self.builder.build_in_bounds_gep(buf, &[len], "ptr") };
+        self.builder
+            .build_store(ptr, self.i8_type.const_int(0, false));
+        self.build_command_branch(function, buf, &["c", "continue"], return_block);
+        self.build_command_branch(function, buf, &["n", "next"], next_block);
+        self.build_command_branch(function, buf, &["l", "locals"], locals_block);
+        self.build_command_branch(function, buf, &["h", "help"], help_block);
+        self.build_command_branch(function, buf, &["q", "quit"], quit_block);
+        self.build_command_branch(function, buf, &[""], prompt_block);
+        let is_print = self.build_str_compare(buf, "p ", Some(2));
+        self.builder
+            .build_conditional_branch(is_print, print_block, unknown_block);
+
+        // Next:
+        self.builder.position_at_end(next_block);
+        self.builder
+            .build_store(stepping, self.i1_type.const_int(1, false));
+        self.builder.build_unconditional_branch(return_block);
+
+        // Locals:
+        self.builder.position_at_end(locals_block);
+        self.build_debugger_loop(i_ptr, n, locals_body_block, prompt_block);
+        // LocalsBody:
+        self.builder.position_at_end(locals_body_block);
+        let (i, var) = self.debugger_nth_var(i_ptr, vars);
+        self.builder
+            .build_call(self.get_llvm_func(PRINT_VAR), &[var.into()], "");
+        self.debugger_loop_next(i_ptr, i, n, locals_body_block, prompt_block);
+
+        // Print:
+        self.builder.position_at_end(print_block);
+        let two = self.i64_type.const_int(2, false);
+        let name = unsafe { self.builder.build_in_bounds_gep(buf, &[two], "name") };
+        self.build_debugger_loop(i_ptr, n, print_check_block, not_found_block);
+        // PrintCheck:
+        self.builder.position_at_end(print_check_block);
+        let (i, var) = self.debugger_nth_var(i_ptr, vars);
+        let var_name_ptr = self.builder.build_struct_gep(var, 0, "").unwrap();
+        let var_name = self
+            .builder
+            .build_load(var_name_ptr, "var_name")
+            .into_pointer_value();
+        let cmp = self
+            .builder
+            .build_call(
+                self.get_llvm_func("strcmp"),
+                &[var_name.into(), name.into()],
+                "cmp",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let found = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            cmp,
+            self.i32_type.const_int(0, false),
+            "found",
+        );
+        self.builder
+            .build_conditional_branch(found, print_found_block, print_next_block);
+        // PrintFound:
+        self.builder.position_at_end(print_found_block);
+        self.builder
+            .build_call(self.get_llvm_func(PRINT_VAR), &[var.into()], "");
+        self.builder.build_unconditional_branch(prompt_block);
+        // PrintNext:
+        self.builder.position_at_end(print_next_block);
+        self.debugger_loop_next(i_ptr, i, n, print_check_block, not_found_block);
+        // NotFound:
+        self.builder.position_at_end(not_found_block);
+        self.build_debugger_printf("no such variable: %s\n", &[name.into()]);
+        self.builder.build_unconditional_branch(prompt_block);
+
+        // Help:
+        self.builder.position_at_end(help_block);
+        self.build_debugger_printf("%s", &[self.debugger_str(HELP)]);
+        self.builder.build_unconditional_branch(prompt_block);
+
+        // Quit:
+        self.builder.position_at_end(quit_block);
+        self.builder
+            .build_call(self.get_llvm_func("shiika_teardown"), &[], "");
+        self.builder.build_call(
+            self.get_llvm_func("exit"),
+            &[self.i32_type.const_int(1, false).into()],
+            "",
+        );
+        self.builder.build_unreachable();
+
+        // Unknown:
+        self.builder.position_at_end(unknown_block);
+        self.build_debugger_printf("unknown command: %s (h for help)\n", &[buf.into()]);
+        self.builder.build_unconditional_branch(prompt_block);
+
+        // Return:
+        self.builder.position_at_end(return_block);
+        self.builder.build_return(None);
+    }
+
+    /// Define `shiika_debugger_print_var`, which prints `name = value`
+    fn gen_debugger_print_var(&self) {
+        let function = self.get_llvm_func(PRINT_VAR);
+        let var = function.get_params()[0].into_pointer_value();
+        let block = |name: &str| self.context.append_basic_block(function, name);
+        let entry_block = block("Entry");
+        let not_set_block = block("NotSet");
+        let switch_block = block("Switch");
+        let int_block = block("Int");
+        let float_block = block("Float");
+        let bool_block = block("Bool");
+        let string_block = block("String");
+        let object_block = block("Object");
+
+        // Entry:
+        self.builder.position_at_end(entry_block);
+        let field = |idx: u32, name: &str| {
+            let ptr = self.builder.build_struct_gep(var, idx, "").unwrap();
+            self.builder.build_load(ptr, name)
+        };
+        let name = field(0, "name");
+        let type_name = field(1, "type");
+        let kind = field(2, "kind").into_int_value();
+        let value = field(3, "value");
+        let is_null = self
+            .builder
+            .build_is_null(value.into_pointer_value(), "is_null");
+        self.builder
+            .build_conditional_branch(is_null, not_set_block, switch_block);
+
+        // NotSet:
+        self.builder.position_at_end(not_set_block);
+        self.build_debugger_printf("%s = (not set)\n", &[name]);
+        self.builder.build_return(None);
+
+        // Switch:
+        self.builder.position_at_end(switch_block);
+        let case = |k: u64| self.i32_type.const_int(k, false);
+        self.builder.build_switch(
+            kind,
+            object_block,
+            &[
+                (case(KIND_INT), int_block),
+                (case(KIND_FLOAT), float_block),
+                (case(KIND_BOOL), bool_block),
+                (case(KIND_STRING), string_block),
+            ],
+        );
+
+        // Int:
+        self.builder.position_at_end(int_block);
+        let obj = self
+            .builder
+            .build_bitcast(value, self.llvm_type(&ty::raw("Int")), "int");
+        let i = self.unbox_int(obj);
+        self.build_debugger_printf("%s = %d\n", &[name, i.into()]);
+        self.builder.build_return(None);
+
+        // Float:
+        self.builder.position_at_end(float_block);
+        let obj = self
+            .builder
+            .build_bitcast(value, self.llvm_type(&ty::raw("Float")), "float");
+        let f = self.unbox_float(obj);
+        self.build_debugger_printf("%s = %f\n", &[name, f.into()]);
+        self.builder.build_return(None);
+
+        // Bool:
+        self.builder.position_at_end(bool_block);
+        let obj = self
+            .builder
+            .build_bitcast(value, self.llvm_type(&ty::raw("Bool")), "bool");
+        let b = self.unbox_bool(obj);
+        let s = self.builder.build_select(
+            b,
+            self.debugger_str("true"),
+            self.debugger_str("false"),
+            "s",
+        );
+        self.build_debugger_printf("%s = %s\n", &[name, s]);
+        self.builder.build_return(None);
+
+        // String:
+        self.builder.position_at_end(string_block);
+        let obj = self
+            .builder
+            .build_bitcast(value, self.llvm_type(&ty::raw("String")), "str");
+        let ptr = self.build_ivar_load(obj, 0, "ptr");
+        let bytesize = self.build_ivar_load(obj, 1, "bytesize");
+        let len = self.unbox_int(bytesize);
+        self.build_debugger_printf("%s = \"%.*s\"\n", &[name, len.into(), ptr]);
+        self.builder.build_return(None);
+
+        // Object:
+        self.builder.position_at_end(object_block);
+        self.build_debugger_printf("%s = #<%s>\n", &[name, type_name]);
+        self.builder.build_return(None);
+    }
+
+    /// Jump to `then_block` if `cmd` is one of `names`. Otherwise continue
+    /// from a new block
+    fn build_command_branch(
+        &self,
+        function: inkwell::values::FunctionValue<'ictx>,
+        cmd: PointerValue<'ictx>,
+        names: &[&str],
+        then_block: inkwell::basic_block::BasicBlock<'ictx>,
+    ) {
+        let mut matched = self.i1_type.const_int(0, false);
+        for name in names {
+            let eq = self.build_str_compare(cmd, name, None);
+            matched = self.builder.build_or(matched, eq, "matched");
+        }
+        let else_block = self.context.append_basic_block(function, "Dispatch");
+        self.builder
+            .build_conditional_branch(matched, then_block, else_block);
+        self.builder.position_at_end(else_block);
+    }
+
+    /// Return true (i1) if `s` is `other` (or starts with it, if `n_bytes`
+    /// is given)
+    fn build_str_compare(
+        &self,
+        s: PointerValue<'ictx>,
+        other: &str,
+        n_bytes: Option<u64>,
+    ) -> IntValue<'ictx> {
+        let other = self.debugger_str(other);
+        let result = match n_bytes {
+            Some(n) => self.builder.build_call(
+                self.get_llvm_func("strncmp"),
+                &[s.into(), other, self.i64_type.const_int(n, false).into()],
+                "cmp",
+            ),
+            None => {
+                self.builder
+                    .build_call(self.get_llvm_func("strcmp"), &[s.into(), other], "cmp")
+            }
+        };
+        let cmp = result.try_as_basic_value().left().unwrap().into_int_value();
+        self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            cmp,
+            self.i32_type.const_int(0, false),
+            "eq",
+        )
+    }
+
+    /// Start a loop over the variables (`i` from 0 to `n - 1`)
+    fn build_debugger_loop(
+        &self,
+        i_ptr: PointerValue<'ictx>,
+        n: IntValue<'ictx>,
+        body_block: inkwell::basic_block::BasicBlock<'ictx>,
+        end_block: inkwell::basic_block::BasicBlock<'ictx>,
+    ) {
+        let zero = self.i32_type.const_int(0, false);
+        self.builder.build_store(i_ptr, zero);
+        let has_var =
+            self.builder
+                .build_int_compare(inkwell::IntPredicate::SLT, zero, n, "has_var");
+        self.builder
+            .build_conditional_branch(has_var, body_block, end_block);
+    }
+
+    /// Go to the next iteration of a loop started by `build_debugger_loop`
+    fn debugger_loop_next(
+        &self,
+        i_ptr: PointerValue<'ictx>,
+        i: IntValue<'ictx>,
+        n: IntValue<'ictx>,
+        body_block: inkwell::basic_block::BasicBlock<'ictx>,
+        end_block: inkwell::basic_block::BasicBlock<'ictx>,
+    ) {
+        let i2 = self
+            .builder
+            .build_int_add(i, self.i32_type.const_int(1, false), "i");
+        self.builder.build_store(i_ptr, i2);
+        let has_var = self
+            .builder
+            .build_int_compare(inkwell::IntPredicate::SLT, i2, n, "has_var");
+        self.builder
+            .build_conditional_branch(has_var, body_block, end_block);
+    }
+
+    /// Return `i` and the pointer to `vars[i]`
+    fn debugger_nth_var(
+        &self,
+        i_ptr: PointerValue<'ictx>,
+        vars: PointerValue<'ictx>,
+    ) -> (IntValue<'ictx>, PointerValue<'ictx>) {
+        let i = self.builder.build_load(i_ptr, "i").into_int_value();
+        let var = unsafe { self.builder.build_in_bounds_gep(vars, &[i], "var") };
+        (i, var)
+    }
+
+    fn build_debugger_printf(&self, fmt: &str, args: &[BasicValueEnum<'ictx>]) {
+        let mut printf_args = vec![self.debugger_str(fmt)];
+        printf_args.extend_from_slice(args);
+        self.builder
+            .build_call(self.get_llvm_func("printf"), &printf_args, "");
+    }
+
+    /// Return a pointer to a C string constant
+    fn debugger_str(&self, s: &str) -> BasicValueEnum<'ictx> {
+        self.builder
+            .build_global_string_ptr(s, "str")
+            .as_pointer_value()
+            .into()
+    }
+}
+
+/// How the value of type `ty` is shown by the debugger
+fn debugger_var_kind(ty: &TermTy) -> u64 {
+    if !ty.is_raw() {
+        return KIND_OBJECT;
+    }
+    match ty.fullname.0.as_str() {
+        "Int" => KIND_INT,
+        "Float" => KIND_FLOAT,
+        "Bool" => KIND_BOOL,
+        "String" => KIND_STRING,
+        _ => KIND_OBJECT,
+    }
+}
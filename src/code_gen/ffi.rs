@@ -0,0 +1,168 @@
+/// Calls of the C functions declared with `extern def`
+///
+/// The values are converted between Shiika and C like this:
+///
+/// | Shiika                  | C                                     |
+/// | ----------------------- | ------------------------------------- |
+/// | `Int`                   | `int`                                 |
+/// | `Float`                 | `double`                              |
+/// | `Bool`                  | `int` (0 is false)                    |
+/// | `String`                | `const char *` (copied when returned) |
+/// | `Shiika::Internal::Ptr` | `void *`                              |
+/// | `Void` (return type)    | `void`                                |
+///
+/// (The types are checked in hir/extern_methods.rs)
+use crate::code_gen::*;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::BasicValueEnum;
+use inkwell::AddressSpace;
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    /// Generate the body of the method `function` which calls the C
+    /// function `c_name` with the args and returns the result
+    pub fn build_extern_call(
+        &self,
+        c_name: &str,
+        sig: &MethodSignature,
+        function: &inkwell::values::FunctionValue,
+    ) {
+        let params = function.get_params();
+        let args = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| self.sk_to_c(&param.ty, params[i + 1]))
+            .collect::<Vec<_>>();
+        let param_types = sig
+            .params
+            .iter()
+            .map(|param| self.c_type(&param.ty))
+            .collect::<Vec<_>>();
+        let fn_type = if sig.ret_ty.is_void_type() {
+            self.void_type.fn_type(&param_types, false)
+        } else {
+            self.c_type(&sig.ret_ty).fn_type(&param_types, false)
+        };
+        let func = self.c_func_ptr(c_name, fn_type);
+        let result = self
+            .builder
+            .build_call(func, &args, "result")
+            .try_as_basic_value()
+            .left();
+        match result {
+            Some(value) => {
+                let sk_value = self.c_to_sk(&sig.ret_ty, value, function);
+                self.builder.build_return(Some(&sk_value))
+            }
+            None => self.builder.build_return(None),
+        };
+    }
+
+    /// Return the C function as a pointer of `fn_type`. The function is
+    /// declared unless it is already (possibly with another type, eg.
+    /// `write` is also used by the runtime)
+    fn c_func_ptr(
+        &self,
+        c_name: &str,
+        fn_type: inkwell::types::FunctionType<'ictx>,
+    ) -> inkwell::values::PointerValue<'ictx> {
+        let func = match self.module.get_function(c_name) {
+            Some(f) => f,
+            None => self.module.add_function(c_name, fn_type, None),
+        };
+        self.builder
+            .build_bitcast(
+                func.as_global_value().as_pointer_value(),
+                fn_type.ptr_type(AddressSpace::Generic),
+                c_name,
+            )
+            .into_pointer_value()
+    }
+
+    /// Return the C type for the Shiika type
+    fn c_type(&self, ty: &TermTy) -> BasicTypeEnum<'ictx> {
+        match ty.fullname.0.as_str() {
+            "Int" | "Bool" => self.i32_type.into(),
+            "Float" => self.f64_type.into(),
+            _ => self.i8ptr_type.into(),
+        }
+    }
+
+    /// Convert the Shiika value into the C value
+    fn sk_to_c<'a>(&'a self, ty: &TermTy, value: BasicValueEnum<'a>) -> BasicValueEnum<'a> {
+        match ty.fullname.0.as_str() {
+            "Int" => self.unbox_int(value).into(),
+            "Float" => self.unbox_float(value).into(),
+            "Bool" => {
+                let b = self.unbox_bool(value);
+                self.builder
+                    .build_int_z_extend(b, self.i32_type, "c_bool")
+                    .into()
+            }
+            // The content of a String is terminated by a null
+            "String" => self.build_ivar_load(value, 0, "@ptr"),
+            _ => value,
+        }
+    }
+
+    /// Convert the C value into the Shiika value
+    fn c_to_sk<'a>(
+        &'a self,
+        ty: &TermTy,
+        value: BasicValueEnum<'a>,
+        function: &inkwell::values::FunctionValue,
+    ) -> BasicValueEnum<'a> {
+        match ty.fullname.0.as_str() {
+            "Int" => self.box_int(&value.into_int_value()),
+            "Float" => self.box_float(&value.into_float_value()),
+            "Bool" => {
+                let b = self.builder.build_int_compare(
+                    inkwell::IntPredicate::NE,
+                    value.into_int_value(),
+                    self.i32_type.const_int(0, false),
+                    "bool",
+                );
+                self.box_bool(b)
+            }
+            "String" => self.build_string_from_c(value.into_pointer_value(), function),
+            _ => value,
+        }
+    }
+
+    /// Create a String by copying the null-terminated string `ptr`
+    /// (an empty string if `ptr` is null)
+    fn build_string_from_c(
+        &self,
+        ptr: inkwell::values::PointerValue<'ictx>,
+        function: &inkwell::values::FunctionValue,
+    ) -> BasicValueEnum<'ictx> {
+        let nonnull_block = self.context.append_basic_block(*function, "NonNull");
+        let copy_block = self.context.append_basic_block(*function, "Copy");
+        let entry_block = self.builder.get_insert_block().unwrap();
+        let is_null = self.builder.build_is_null(ptr, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, copy_block, nonnull_block);
+
+        // NonNull:
+        self.builder.position_at_end(nonnull_block);
+        let strlen_type = self.i64_type.fn_type(&[self.i8ptr_type.into()], false);
+        let strlen = self.c_func_ptr("strlen", strlen_type);
+        let len = self
+            .builder
+            .build_call(strlen, &[ptr.into()], "len")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        self.builder.build_unconditional_branch(copy_block);
+
+        // Copy:
+        self.builder.position_at_end(copy_block);
+        let phi = self.builder.build_phi(self.i64_type, "bytesize");
+        phi.add_incoming(&[
+            (&self.i64_type.const_int(0, false), entry_block),
+            (&len, nonnull_block),
+        ]);
+        let bytesize = phi.as_basic_value().into_int_value();
+        self.build_string_copy(ptr, bytesize)
+    }
+}
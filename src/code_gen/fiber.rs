@@ -0,0 +1,333 @@
+/// Fibers (`Shiika::Internal::Fiber`)
+///
+/// A fiber runs a lambda on its own stack. The stacks are switched with
+/// `swapcontext` of ucontext, and each fiber is a record of `fiber_type`:
+///
+/// - `context`: the context of the fiber (where `resume` goes to)
+/// - `caller`: the context of the caller of `resume` (where `yield_value`
+///   and the end of the lambda go back to)
+/// - `value`: the value given to `yield_value` or returned by the lambda
+/// - `parent`: the fiber which was running when this fiber is resumed
+/// - `fn_obj`, `stack`: the lambda and the stack
+/// - `finished`: 1 if the lambda has returned
+///
+/// `@shiika_fiber_current` is the running fiber (null on the main stack.)
+/// A new fiber starts from `shiika_fiber_start`, which calls the lambda.
+///
+/// The stacks are allocated with GC_malloc so that the GC finds the
+/// objects referred from the suspended fibers. The GC does not know the
+/// stack of the running fiber, so it is disabled (`GC_disable`) from
+/// `resume` until the fiber yields or returns; the garbage made meanwhile
+/// is collected after that.
+///
+/// Fibers are not available on Windows (the operations do nothing.)
+use crate::code_gen::*;
+use crate::platform::Platform;
+use crate::ty;
+use inkwell::values::{IntValue, PointerValue};
+
+/// Global variable which holds the running fiber
+const CURRENT: &str = "shiika_fiber_current";
+
+/// Bytes of the stack of a fiber
+const STACK_SIZE: u64 = 256 * 1024;
+
+/// Bytes reserved for a `ucontext_t` (larger than it on every platform)
+const CONTEXT_SIZE: u32 = 8192;
+
+/// Bytes of `fiber_type`
+const FIBER_SIZE: u64 = 2 * CONTEXT_SIZE as u64 + 5 * 8;
+
+// Fields of `fiber_type`
+const CONTEXT: u32 = 0;
+const CALLER: u32 = 1;
+const VALUE: u32 = 2;
+const PARENT: u32 = 3;
+const FN_OBJ: u32 = 4;
+const STACK: u32 = 5;
+const FINISHED: u32 = 6;
+
+/// Offsets of the fields of `ucontext_t` set before `makecontext`
+struct UcontextLayout {
+    uc_link: u64,
+    ss_sp: u64,
+    ss_size: u64,
+}
+
+// The offsets are the ones of the libc of the target the compiler is built
+// for (the programs are compiled for the host.) Check them before adding a
+// target.
+#[cfg(not(any(
+    all(
+        any(target_os = "linux", target_os = "macos"),
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    target_os = "windows"
+)))]
+compile_error!("the layout of ucontext_t is not known for this target (see code_gen/fiber.rs)");
+
+/// `int uc_onstack; sigset_t uc_sigmask; stack_t uc_stack;
+/// ucontext_t *uc_link; ...` (`stack_t` is `ss_sp, ss_size, ss_flags`)
+#[cfg(target_os = "macos")]
+fn ucontext_layout() -> UcontextLayout {
+    UcontextLayout {
+        uc_link: 32,
+        ss_sp: 8,
+        ss_size: 16,
+    }
+}
+
+/// `unsigned long uc_flags; ucontext_t *uc_link; stack_t uc_stack; ...`
+/// (`stack_t` is `ss_sp, ss_flags, ss_size`; the same in glibc and musl)
+#[cfg(target_os = "linux")]
+fn ucontext_layout() -> UcontextLayout {
+    UcontextLayout {
+        uc_link: 8,
+        ss_sp: 16,
+        ss_size: 32,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn ucontext_layout() -> UcontextLayout {
+    unreachable!("fibers are not available on Windows")
+}
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_fiber_declares(&self) {
+        let global = self.module.add_global(self.i8ptr_type, None, CURRENT);
+        self.set_shared_initializer(global, &self.i8ptr_type.const_null());
+        if !self.fiber_available() {
+            return;
+        }
+
+        let i8ptr = self.i8ptr_type.into();
+        let fn_type = self.i32_type.fn_type(&[i8ptr], false);
+        self.module.add_function("getcontext", fn_type, None);
+        let fn_type = self
+            .void_type
+            .fn_type(&[i8ptr, i8ptr, self.i32_type.into()], true);
+        self.module.add_function("makecontext", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i8ptr, i8ptr], false);
+        self.module.add_function("swapcontext", fn_type, None);
+        let fn_type = self.void_type.fn_type(&[], false);
+        self.module.add_function("GC_disable", fn_type, None);
+        self.module.add_function("GC_enable", fn_type, None);
+        self.module
+            .add_function("shiika_fiber_start", fn_type, None);
+    }
+
+    /// Define `shiika_fiber_start`, which calls the lambda of the running
+    /// fiber. When it returns, `uc_link` (the caller) is resumed
+    pub(super) fn gen_fiber_start(&self) {
+        if !self.fiber_available() {
+            return;
+        }
+        let function = self.get_llvm_func("shiika_fiber_start");
+        let basic_block = self.context.append_basic_block(function, "");
+        self.builder.position_at_end(basic_block);
+
+        let fiber = self.build_current_fiber();
+        let fn_obj = self
+            .builder
+            .build_load(self.fiber_field(fiber, FN_OBJ), "fn_i8");
+        let fn_type = self.llvm_type(&ty::raw("Fn0"));
+        let fn_obj = self.builder.build_bitcast(fn_obj, fn_type, "fn");
+        let result = self.build_lambda_call(fn_obj, vec![]);
+        let result = self.builder.build_bitcast(result, self.i8ptr_type, "");
+        self.builder
+            .build_store(self.fiber_field(fiber, VALUE), result);
+        self.builder.build_store(
+            self.fiber_field(fiber, FINISHED),
+            self.i32_type.const_int(1, false),
+        );
+        self.builder.build_return(None);
+    }
+
+    pub fn fiber_available(&self) -> bool {
+        Platform::host() != Platform::Windows
+    }
+
+    /// Create a fiber which runs `fn_obj` (an instance of `Fn0`)
+    pub fn build_fiber_new(&self, fn_obj: BasicValueEnum<'ictx>) -> BasicValueEnum<'ictx> {
+        if !self.fiber_available() {
+            return self.i8ptr_type.const_null().into();
+        }
+        let fiber = self.build_gc_malloc(FIBER_SIZE, "fiber");
+        let fn_i8 = self.builder.build_bitcast(fn_obj, self.i8ptr_type, "");
+        self.builder
+            .build_store(self.fiber_field(fiber, FN_OBJ), fn_i8);
+        let stack = self.build_gc_malloc(STACK_SIZE, "stack");
+        self.builder
+            .build_store(self.fiber_field(fiber, STACK), stack);
+
+        let context = self.fiber_context(fiber, CONTEXT);
+        let func = self.get_llvm_func("getcontext");
+        self.builder.build_call(func, &[context.into()], "");
+        let layout = ucontext_layout();
+        self.build_store_at(context, layout.ss_sp, stack.into());
+        let size = self.i64_type.const_int(STACK_SIZE, false);
+        self.build_store_at(context, layout.ss_size, size.into());
+        let caller = self.fiber_context(fiber, CALLER);
+        self.build_store_at(context, layout.uc_link, caller.into());
+        let start = self
+            .get_llvm_func("shiika_fiber_start")
+            .as_global_value()
+            .as_pointer_value();
+        let start = self.builder.build_bitcast(start, self.i8ptr_type, "start");
+        let func = self.get_llvm_func("makecontext");
+        self.builder.build_call(
+            func,
+            &[
+                context.into(),
+                start,
+                self.i32_type.const_int(0, false).into(),
+            ],
+            "",
+        );
+        fiber.into()
+    }
+
+    /// Run `fiber` until it yields or finishes. Returns the value
+    pub fn build_fiber_resume(&self, fiber: PointerValue<'ictx>) -> BasicValueEnum<'ictx> {
+        let obj_type = self.llvm_type(&ty::raw("Object"));
+        if !self.fiber_available() {
+            return obj_type.into_pointer_type().const_null().into();
+        }
+        let current = self.module.get_global(CURRENT).unwrap().as_pointer_value();
+        let parent = self.builder.build_load(current, "parent");
+        self.builder
+            .build_store(self.fiber_field(fiber, PARENT), parent);
+        self.builder.build_store(current, fiber);
+
+        self.builder
+            .build_call(self.get_llvm_func("GC_disable"), &[], "");
+        let func = self.get_llvm_func("swapcontext");
+        self.builder.build_call(
+            func,
+            &[
+                self.fiber_context(fiber, CALLER).into(),
+                self.fiber_context(fiber, CONTEXT).into(),
+            ],
+            "",
+        );
+        // Back from the fiber
+        self.builder
+            .build_call(self.get_llvm_func("GC_enable"), &[], "");
+        let parent = self
+            .builder
+            .build_load(self.fiber_field(fiber, PARENT), "parent");
+        self.builder.build_store(current, parent);
+
+        let value = self
+            .builder
+            .build_load(self.fiber_field(fiber, VALUE), "value");
+        self.builder.build_bitcast(value, obj_type, "value")
+    }
+
+    /// Pass `value` to the caller of `resume` of the running fiber and
+    /// suspend it
+    pub fn build_fiber_yield(&self, value: BasicValueEnum<'ictx>) {
+        if !self.fiber_available() {
+            return;
+        }
+        let fiber = self.build_current_fiber();
+        let value = self.builder.build_bitcast(value, self.i8ptr_type, "value");
+        self.builder
+            .build_store(self.fiber_field(fiber, VALUE), value);
+        let func = self.get_llvm_func("swapcontext");
+        self.builder.build_call(
+            func,
+            &[
+                self.fiber_context(fiber, CONTEXT).into(),
+                self.fiber_context(fiber, CALLER).into(),
+            ],
+            "",
+        );
+    }
+
+    /// Whether the lambda of `fiber` has returned (i1)
+    pub fn build_fiber_finished(&self, fiber: PointerValue<'ictx>) -> IntValue<'ictx> {
+        if !self.fiber_available() {
+            return self.i1_type.const_int(1, false);
+        }
+        let finished = self
+            .builder
+            .build_load(self.fiber_field(fiber, FINISHED), "finished")
+            .into_int_value();
+        self.builder.build_int_compare(
+            inkwell::IntPredicate::NE,
+            finished,
+            self.i32_type.const_int(0, false),
+            "finished",
+        )
+    }
+
+    /// Whether a fiber is running (i1)
+    pub fn build_fiber_running(&self) -> IntValue<'ictx> {
+        let fiber = self.build_current_fiber();
+        self.builder.build_is_not_null(fiber, "running")
+    }
+
+    fn build_current_fiber(&self) -> PointerValue<'ictx> {
+        let current = self.module.get_global(CURRENT).unwrap().as_pointer_value();
+        self.builder
+            .build_load(current, "fiber")
+            .into_pointer_value()
+    }
+
+    fn build_gc_malloc(&self, n_bytes: u64, name: &str) -> PointerValue<'ictx> {
+        let size = self.i64_type.const_int(n_bytes, false);
+        self.builder
+            .build_call(self.get_llvm_func("GC_malloc"), &[size.into()], name)
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    /// Store `value` at `offset` bytes from `ptr` (an i8*)
+    fn build_store_at(&self, ptr: PointerValue<'ictx>, offset: u64, value: BasicValueEnum<'ictx>) {
+        let idx = self.i64_type.const_int(offset, false);
+        let field = unsafe { self.builder.build_in_bounds_gep(ptr, &[idx], "field") };
+        let field = self.builder.build_pointer_cast(
+            field,
+            value.get_type().ptr_type(AddressSpace::Generic),
+            "field",
+        );
+        self.builder.build_store(field, value);
+    }
+
+    /// Return the pointer to the field of `fiber` (an i8*)
+    fn fiber_field(&self, fiber: PointerValue<'ictx>, idx: u32) -> PointerValue<'ictx> {
+        let fiber = self.builder.build_pointer_cast(
+            fiber,
+            self.fiber_type().ptr_type(AddressSpace::Generic),
+            "fiber",
+        );
+        self.builder.build_struct_gep(fiber, idx, "").unwrap()
+    }
+
+    /// Return the `context` or the `caller` of `fiber` as an i8*
+    fn fiber_context(&self, fiber: PointerValue<'ictx>, idx: u32) -> PointerValue<'ictx> {
+        let field = self.fiber_field(fiber, idx);
+        self.builder
+            .build_pointer_cast(field, self.i8ptr_type, "context")
+    }
+
+    fn fiber_type(&self) -> inkwell::types::StructType<'ictx> {
+        let context_type = self.i8_type.array_type(CONTEXT_SIZE);
+        self.context.struct_type(
+            &[
+                context_type.into(),
+                context_type.into(),
+                self.i8ptr_type.into(),
+                self.i8ptr_type.into(),
+                self.i8ptr_type.into(),
+                self.i8ptr_type.into(),
+                self.i32_type.into(),
+            ],
+            false,
+        )
+    }
+}
@@ -8,7 +8,6 @@ use crate::names::*;
 use crate::ty;
 use crate::ty::*;
 use inkwell::values::*;
-use std::rc::Rc;
 
 impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     pub fn gen_exprs(
@@ -42,8 +41,10 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             HirWhileExpression {
                 cond_expr,
                 body_exprs,
-            } => self.gen_while_expr(ctx, &cond_expr, &body_exprs),
-            HirBreakExpression => self.gen_break_expr(ctx),
+            } => self.gen_while_expr(ctx, &expr.ty, &cond_expr, &body_exprs),
+            HirBreakExpression { expr } => self.gen_break_expr(ctx, expr),
+            HirNextExpression => self.gen_next_expr(ctx),
+            HirReturnExpression { arg } => self.gen_return_expr(ctx, arg),
             HirLVarAssign { name, rhs } => self.gen_lvar_assign(ctx, name, rhs),
             HirIVarAssign { name, idx, rhs, .. } => self.gen_ivar_assign(ctx, name, idx, rhs),
             HirConstAssign { fullname, rhs } => self.gen_const_assign(ctx, fullname, rhs),
@@ -63,18 +64,24 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 captures_ary,
             } => self.gen_lambda_expr(ctx, name, params, exprs, captures_ary),
             HirSelfExpression => self.gen_self_expression(ctx),
+            HirParenthesizedExpr { exprs } => self.gen_exprs(ctx, exprs),
             HirArrayLiteral { exprs } => self.gen_array_literal(ctx, exprs),
             HirFloatLiteral { value } => Ok(self.gen_float_literal(*value)),
             HirDecimalLiteral { value } => Ok(self.gen_decimal_literal(*value)),
             HirStringLiteral { idx } => Ok(self.gen_string_literal(idx)),
             HirBooleanLiteral { value } => Ok(self.gen_boolean_literal(*value)),
 
-            HirLambdaCaptureRef { idx } => self.gen_lambda_capture_ref(ctx, idx, &expr.ty),
+            HirBox { expr: target } => self.gen_box(ctx, target, &expr.ty),
+            HirUnbox { expr: target } => self.gen_unbox(ctx, target),
             HirBitCast { expr: target } => self.gen_bitcast(ctx, target, &expr.ty),
             HirClassLiteral {
                 fullname,
                 str_literal_idx,
             } => Ok(self.gen_class_literal(fullname, str_literal_idx)),
+            HirBreakpoint { place, vars, step } => {
+                self.gen_breakpoint(ctx, place, vars, *step)?;
+                Ok(self.gen_void())
+            }
         }
     }
 
@@ -83,11 +90,9 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         ctx: &mut CodeGenContext<'hir, 'run>,
         expr: &'hir HirExpression,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
-        let b = self.gen_expr(ctx, expr)?;
-        let i = self.unbox_bool(b);
+        let i = self.gen_expr(ctx, expr)?.into_int_value();
         let one = self.i1_type.const_int(1, false);
-        let b2 = self.builder.build_int_sub(one, i, "");
-        Ok(self.box_bool(b2))
+        Ok(self.builder.build_int_sub(one, i, "").as_basic_value_enum())
     }
 
     fn gen_logical_and(
@@ -114,9 +119,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         // AndEnd:
         self.builder.position_at_end(merge_block);
 
-        let phi_node = self
-            .builder
-            .build_phi(self.llvm_type(&ty::raw("Bool")), "AndResult");
+        let phi_node = self.builder.build_phi(self.i1_type, "AndResult");
         phi_node.add_incoming(&[
             (&left_value, begin_block_end),
             (&right_value, more_block_end),
@@ -147,9 +150,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         // OrEnd:
         self.builder.position_at_end(merge_block);
 
-        let phi_node = self
-            .builder
-            .build_phi(self.llvm_type(&ty::raw("Bool")), "OrResult");
+        let phi_node = self.builder.build_phi(self.i1_type, "OrResult");
         phi_node.add_incoming(&[
             (&left_value, begin_block_end),
             (&right_value, else_block_end),
@@ -178,21 +179,25 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 self.gen_conditional_branch(cond_value, then_block, else_block);
                 // IfThen:
                 self.builder.position_at_end(then_block);
-                let then_value: &dyn inkwell::values::BasicValue =
-                    &self.gen_exprs(ctx, then_exprs)?;
-                self.builder.build_unconditional_branch(merge_block);
-                let then_block_end = self.builder.get_insert_block().unwrap();
+                let then_value = self.gen_exprs(ctx, then_exprs)?;
+                let then_end = self.gen_if_branch_end(&then_exprs.ty, merge_block);
                 // IfElse:
                 self.builder.position_at_end(else_block);
                 let else_value = self.gen_exprs(ctx, else_exprs)?;
-                self.builder.build_unconditional_branch(merge_block);
-                let else_block_end = self.builder.get_insert_block().unwrap();
+                let else_end = self.gen_if_branch_end(&else_exprs.ty, merge_block);
                 // IfEnd:
                 self.builder.position_at_end(merge_block);
 
+                if ty.is_void_type() || ty.is_never_type() {
+                    return Ok(self.gen_void());
+                }
                 let phi_node = self.builder.build_phi(self.llvm_type(ty), "ifResult");
-                phi_node
-                    .add_incoming(&[(then_value, then_block_end), (&else_value, else_block_end)]);
+                if let Some(block) = then_end {
+                    phi_node.add_incoming(&[(&then_value, block)]);
+                }
+                if let Some(block) = else_end {
+                    phi_node.add_incoming(&[(&else_value, block)]);
+                }
                 Ok(phi_node.as_basic_value())
             }
             None => {
@@ -206,17 +211,45 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 self.builder.build_unconditional_branch(merge_block);
                 // IfEnd:
                 self.builder.position_at_end(merge_block);
-                Ok(self.i1_type.const_int(0, false).as_basic_value_enum()) // dummy value
+                Ok(self.gen_void())
             }
         }
     }
 
+    /// Jump from the end of a branch of `if` to `merge_block`. Returns the
+    /// block which jumps, or None if the branch never ends (eg. `break`)
+    fn gen_if_branch_end(
+        &self,
+        branch_ty: &TermTy,
+        merge_block: inkwell::basic_block::BasicBlock,
+    ) -> Option<inkwell::basic_block::BasicBlock> {
+        if branch_ty.is_never_type() {
+            self.builder.build_unreachable();
+            None
+        } else {
+            self.builder.build_unconditional_branch(merge_block);
+            self.builder.get_insert_block()
+        }
+    }
+
+    /// The value of expressions which have no value (`Void`)
+    fn gen_void(&self) -> inkwell::values::BasicValueEnum {
+        self.gen_const_ref(&const_fullname("::Void"))
+    }
+
     fn gen_while_expr(
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
+        ty: &TermTy,
         cond_expr: &'hir HirExpression,
         body_exprs: &'hir HirExpressions,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        // The value of the loop is passed from `break` via this
+        let result_ptr = if ty.is_void_type() || ty.is_never_type() {
+            None
+        } else {
+            Some(self.builder.build_alloca(self.llvm_type(ty), "whileResult"))
+        };
         let begin_block = self.context.append_basic_block(ctx.function, "WhileBegin");
         self.builder.build_unconditional_branch(begin_block);
         // WhileBegin:
@@ -227,29 +260,78 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         self.gen_conditional_branch(cond_value, body_block, end_block);
         // WhileBody:
         self.builder.position_at_end(body_block);
-        let rc1 = Rc::new(end_block);
-        let rc2 = Rc::clone(&rc1);
-        ctx.current_loop_end = Some(rc1);
-        self.gen_exprs(ctx, body_exprs)?;
-        ctx.current_loop_end = None;
+        ctx.loops.push(CodeGenLoop {
+            begin_block,
+            end_block,
+            result_ptr,
+        });
+        let result = self.gen_exprs(ctx, body_exprs);
+        ctx.loops.pop();
+        result?;
         self.builder.build_unconditional_branch(begin_block);
 
         // WhileEnd:
-        self.builder.position_at_end(*rc2);
-        Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Void
+        self.builder.position_at_end(end_block);
+        match result_ptr {
+            Some(ptr) => Ok(self.builder.build_load(ptr, "whileResult")),
+            None => Ok(self.gen_void()),
+        }
     }
 
     fn gen_break_expr(
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
+        expr: &'hir Option<Box<HirExpression>>,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
-        match &ctx.current_loop_end {
-            Some(b) => {
-                self.builder.build_unconditional_branch(*Rc::clone(b));
-                Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Void
-            }
-            None => Err(error::program_error("break outside of a loop")),
+        if let Some(e) = expr {
+            let value = self.gen_expr(ctx, e)?;
+            let ptr = self.current_loop(ctx)?.result_ptr.unwrap();
+            self.builder.build_store(ptr, value);
         }
+        let end_block = self.current_loop(ctx)?.end_block;
+        self.builder.build_unconditional_branch(end_block);
+        self.start_unreachable_block(ctx, "AfterBreak");
+        Ok(self.gen_void())
+    }
+
+    fn gen_next_expr(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let begin_block = self.current_loop(ctx)?.begin_block;
+        self.builder.build_unconditional_branch(begin_block);
+        self.start_unreachable_block(ctx, "AfterNext");
+        Ok(self.gen_void())
+    }
+
+    fn gen_return_expr(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        arg: &'hir HirExpression,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, arg)?;
+        self.build_backtrace_pop(ctx);
+        self.builder.build_return(Some(&value));
+        self.start_unreachable_block(ctx, "AfterReturn");
+        Ok(self.gen_void())
+    }
+
+    /// Return the innermost loop
+    fn current_loop<'a>(
+        &self,
+        ctx: &'a CodeGenContext<'hir, 'run>,
+    ) -> Result<&'a CodeGenLoop<'run>, Error> {
+        ctx.loops
+            .last()
+            .ok_or_else(|| error::program_error("break/next outside of a loop"))
+    }
+
+    /// Create a new block for the instructions after a jump (they are
+    /// never executed, but a block must not have instructions after its
+    /// terminator)
+    fn start_unreachable_block(&self, ctx: &CodeGenContext<'hir, 'run>, name: &str) {
+        let block = self.context.append_basic_block(ctx.function, name);
+        self.builder.position_at_end(block);
     }
 
     fn gen_lvar_assign(
@@ -265,7 +347,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
                 self.builder.build_store(*ptr, value);
             }
             None => {
-                let ptr = self.builder.build_alloca(self.llvm_type(&rhs.ty), name);
+                let ptr = self.build_entry_alloca(ctx, self.llvm_type(&rhs.ty), name);
                 self.builder.build_store(ptr, value);
                 ctx.lvars.insert(name.to_string(), ptr);
             }
@@ -273,6 +355,24 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         Ok(value)
     }
 
+    /// Allocate a local variable at the beginning of the function so that
+    /// it can be used in any block after assigned (eg. the variables of
+    /// the patterns of `case` are assigned in the condition of `when`)
+    fn build_entry_alloca(
+        &self,
+        ctx: &CodeGenContext<'hir, 'run>,
+        ty: inkwell::types::BasicTypeEnum<'run>,
+        name: &str,
+    ) -> inkwell::values::PointerValue<'run> {
+        let builder = self.context.create_builder();
+        let entry = ctx.function.get_first_basic_block().unwrap();
+        match entry.get_first_instruction() {
+            Some(inst) => builder.position_before(&inst),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name)
+    }
+
     fn gen_ivar_assign(
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
@@ -282,7 +382,7 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
         let theself = self.gen_self_expression(ctx)?;
         let value = self.gen_expr(ctx, rhs)?;
-        self.build_ivar_store(&theself, *idx, value, name);
+        self.build_checked_ivar_store(&theself, *idx, value, name);
         Ok(value)
     }
 
@@ -315,6 +415,10 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             .iter()
             .map(|arg_expr| self.gen_expr(ctx, arg_expr))
             .collect::<Result<Vec<_>, _>>()?;
+        // Call the lambda directly instead of via `FnN#call`
+        if method_fullname.first_name.0 == "call" && receiver_expr.ty.fn_arity().is_some() {
+            return Ok(self.build_lambda_call(receiver_value, arg_values));
+        }
         self.gen_llvm_func_call(&method_fullname.full_name, receiver_value, arg_values)
     }
 
@@ -406,7 +510,11 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         arg_types.push(&captures_type);
         let ret_ty = &exprs.ty;
         let func_type = self.llvm_func_type(None, &arg_types, &ret_ty);
-        self.module.add_function(&func_name, func_type, None);
+        let function = self.module.add_function(&func_name, func_type, None);
+        if let Part::Builtin = self.part {
+            // Avoid conflicting with the lambdas of the user's module
+            function.set_linkage(inkwell::module::Linkage::Internal);
+        }
 
         // Fn1.new(fnptr, captures)
         let cls_name = format!("Fn{}", params.len() - 1); // -1 for the last `captures` ary
@@ -457,11 +565,13 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     }
 
     fn gen_float_literal(&self, value: f64) -> inkwell::values::BasicValueEnum {
-        self.box_float(&self.f64_type.const_float(value))
+        self.f64_type.const_float(value).as_basic_value_enum()
     }
 
     fn gen_decimal_literal(&self, value: i32) -> inkwell::values::BasicValueEnum {
-        self.box_int(&self.i32_type.const_int(value as u64, false))
+        self.i32_type
+            .const_int(value as u64, false)
+            .as_basic_value_enum()
     }
 
     fn gen_string_literal(&self, idx: &usize) -> inkwell::values::BasicValueEnum {
@@ -490,39 +600,47 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
 
     fn gen_boolean_literal(&self, value: bool) -> inkwell::values::BasicValueEnum {
         let n = if value { 1 } else { 0 };
-        let i = self.i1_type.const_int(n, false);
-        self.box_bool(i)
+        self.i1_type.const_int(n, false).as_basic_value_enum()
     }
 
+    /// `cond`: native bool (i1)
     fn gen_conditional_branch(
         &self,
         cond: inkwell::values::BasicValueEnum,
         then_block: inkwell::basic_block::BasicBlock,
         else_block: inkwell::basic_block::BasicBlock,
     ) {
-        let i = self.unbox_bool(cond);
-        let one = self.i1_type.const_int(1, false);
-        let istrue = self
-            .builder
-            .build_int_compare(inkwell::IntPredicate::EQ, i, one, "istrue");
         self.builder
-            .build_conditional_branch(istrue, then_block, else_block);
+            .build_conditional_branch(cond.into_int_value(), then_block, else_block);
     }
 
-    fn gen_lambda_capture_ref(
+    fn gen_box(
         &self,
         ctx: &mut CodeGenContext<'hir, 'run>,
-        idx_in_captures: &usize,
+        expr: &'hir HirExpression,
         ty: &TermTy,
     ) -> Result<inkwell::values::BasicValueEnum, Error> {
-        let idx_of_captures = ctx.function_params.unwrap().len() - 1;
-        let captures = self.gen_arg_ref(ctx, &idx_of_captures)?;
-        let obj = self.gen_llvm_func_call(
-            "Array#nth",
-            captures,
-            vec![self.gen_decimal_literal(*idx_in_captures as i32)],
-        )?;
-        Ok(self.builder.build_bitcast(obj, self.llvm_type(ty), ""))
+        let value = self.gen_expr(ctx, expr)?;
+        match ty.fullname.0.as_str() {
+            "Bool" => Ok(self.box_bool(value.into_int_value())),
+            "Int" => Ok(self.box_int(&value.into_int_value())),
+            "Float" => Ok(self.box_float(&value.into_float_value())),
+            _ => panic!("[BUG] cannot box into {:?}", ty),
+        }
+    }
+
+    fn gen_unbox(
+        &self,
+        ctx: &mut CodeGenContext<'hir, 'run>,
+        expr: &'hir HirExpression,
+    ) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let obj = self.gen_expr(ctx, expr)?;
+        match expr.ty.fullname.0.as_str() {
+            "Bool" => Ok(self.unbox_bool(obj).as_basic_value_enum()),
+            "Int" => Ok(self.unbox_int(obj).as_basic_value_enum()),
+            "Float" => Ok(self.unbox_float(obj).as_basic_value_enum()),
+            _ => panic!("[BUG] cannot unbox {:?}", expr.ty),
+        }
     }
 
     fn gen_bitcast(
@@ -12,11 +12,17 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
     pub(super) fn gen_lambda_funcs(&self, hir: &'hir Hir) -> Result<(), Error> {
         for (_, methods) in &hir.sk_methods {
             for method in methods {
+                if !self.generates_method(method) {
+                    continue;
+                }
                 if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
                     self.gen_lambda_funcs_in_exprs(&exprs)?;
                 }
             }
         }
+        if !self.is_main_module() {
+            return Ok(());
+        }
 
         for expr in &hir.const_inits {
             self.gen_lambda_funcs_in_expr(&expr)?;
@@ -62,7 +68,13 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
                 self.gen_lambda_funcs_in_expr(cond_expr)?;
                 self.gen_lambda_funcs_in_exprs(body_exprs)?;
             }
-            HirBreakExpression => (),
+            HirBreakExpression { expr } => {
+                if let Some(expr) = expr {
+                    self.gen_lambda_funcs_in_expr(expr)?;
+                }
+            }
+            HirNextExpression => (),
+            HirReturnExpression { arg } => self.gen_lambda_funcs_in_expr(arg)?,
             HirLVarAssign { rhs, .. } => self.gen_lambda_funcs_in_expr(rhs)?,
             HirIVarAssign { rhs, .. } => self.gen_lambda_funcs_in_expr(rhs)?,
             HirConstAssign { rhs, .. } => self.gen_lambda_funcs_in_expr(rhs)?,
@@ -90,15 +102,18 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
                 self.gen_lambda_funcs_in_exprs(exprs)?;
             }
             HirSelfExpression => (),
+            HirParenthesizedExpr { exprs } => self.gen_lambda_funcs_in_exprs(exprs)?,
             HirArrayLiteral { exprs } => self.gen_lambda_funcs_in_exprs(exprs)?,
             HirFloatLiteral { .. } => (),
             HirDecimalLiteral { .. } => (),
             HirStringLiteral { .. } => (),
             HirBooleanLiteral { .. } => (),
 
-            HirLambdaCaptureRef { .. } => (),
+            HirBox { expr } => self.gen_lambda_funcs_in_expr(expr)?,
+            HirUnbox { expr } => self.gen_lambda_funcs_in_expr(expr)?,
             HirBitCast { expr } => self.gen_lambda_funcs_in_expr(expr)?,
             HirClassLiteral { .. } => (),
+            HirBreakpoint { .. } => (),
         }
         Ok(())
     }
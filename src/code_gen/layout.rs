@@ -0,0 +1,126 @@
+/// Memory layout of the objects (`shiika compile --emit layout`)
+///
+/// The offsets and the sizes are the ones of the LLVM struct types made by
+/// `gen_class_structs`, computed with the data layout of the host. Each
+/// object is preceded by a header which holds the class id and the frozen
+/// flag (not included in the size.)
+use crate::code_gen::utils::OBJ_HEADER_SIZE;
+use crate::code_gen::*;
+use std::fmt;
+
+/// Layout of the objects of a class
+#[derive(Debug, PartialEq)]
+pub struct ClassLayout {
+    pub name: ClassFullname,
+    /// Size of the object (excluding the header) in bytes
+    pub size: u64,
+    /// The fields in the order of the offsets
+    pub fields: Vec<FieldLayout>,
+}
+
+/// Layout of a field of an object
+#[derive(Debug, PartialEq)]
+pub struct FieldLayout {
+    /// Name of the ivar (`None` for the value of Int, Float and Bool)
+    pub name: Option<String>,
+    /// Type of the ivar
+    pub ty: Option<TermTy>,
+    pub offset: u64,
+    pub size: u64,
+    /// Bytes of the padding after this field
+    pub padding: u64,
+}
+
+impl ClassLayout {
+    /// Bytes of the padding in the object
+    pub fn padding(&self) -> u64 {
+        self.fields.iter().map(|field| field.padding).sum()
+    }
+
+    /// Bytes allocated for an object (including the header)
+    pub fn allocated_size(&self) -> u64 {
+        self.size + OBJ_HEADER_SIZE
+    }
+}
+
+impl fmt::Display for ClassLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (size: {}, padding: {}, header: {})",
+            self.name.0,
+            self.size,
+            self.padding(),
+            OBJ_HEADER_SIZE
+        )?;
+        for field in &self.fields {
+            let desc = match (&field.name, &field.ty) {
+                (Some(name), Some(ty)) => format!("@{}: {}", name, ty),
+                _ => "(value)".to_string(),
+            };
+            writeln!(f, "  {:>4} {:>4}  {}", field.offset, field.size, desc)?;
+            if field.padding > 0 {
+                writeln!(
+                    f,
+                    "  {:>4} {:>4}  (padding)",
+                    field.offset + field.size,
+                    field.padding
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the layouts of the classes (sorted by the name)
+pub fn class_layouts(hir: &Hir) -> Result<Vec<ClassLayout>, Box<dyn std::error::Error>> {
+    let context = inkwell::context::Context::create();
+    let module = context.create_module("layout");
+    let builder = context.create_builder();
+    let mut code_gen = CodeGen::new(hir, &context, &module, &builder);
+    code_gen.gen_class_structs(&hir.sk_classes);
+    // The execution engine sets the data layout of the host to the module
+    let engine = module.create_jit_execution_engine(inkwell::OptimizationLevel::None)?;
+    let target_data = engine.get_target_data();
+
+    let mut names = hir.sk_classes.keys().collect::<Vec<_>>();
+    names.sort_by(|a, b| a.0.cmp(&b.0));
+    let layouts = names
+        .into_iter()
+        .map(|name| {
+            let struct_type = code_gen.llvm_struct_types.get(name).unwrap();
+            let mut ivars = hir.sk_classes[name].ivars.values().collect::<Vec<_>>();
+            ivars.sort_by_key(|ivar| ivar.idx);
+            let size = target_data.get_abi_size(struct_type);
+            let field_types = struct_type.get_field_types();
+            let offsets = (0..field_types.len())
+                .map(|i| {
+                    target_data
+                        .offset_of_element(struct_type, i as u32)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            let fields = field_types
+                .iter()
+                .enumerate()
+                .map(|(i, field_type)| {
+                    let field_size = target_data.get_abi_size(field_type);
+                    let next = offsets.get(i + 1).copied().unwrap_or(size);
+                    FieldLayout {
+                        name: ivars.get(i).map(|ivar| ivar.name.clone()),
+                        ty: ivars.get(i).map(|ivar| ivar.ty.clone()),
+                        offset: offsets[i],
+                        size: field_size,
+                        padding: next - offsets[i] - field_size,
+                    }
+                })
+                .collect();
+            ClassLayout {
+                name: name.clone(),
+                size,
+                fields,
+            }
+        })
+        .collect();
+    Ok(layouts)
+}
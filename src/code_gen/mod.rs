@@ -1,17 +1,37 @@
+mod backtrace;
 mod boxing;
 mod code_gen_context;
+mod debugger;
+mod ffi;
+mod fiber;
 mod gen_exprs;
 mod lambda;
+mod layout;
+mod parallel;
+mod part;
+mod process;
+mod runtime;
+mod selector;
+mod socket;
+mod source_map;
 mod utils;
+mod verify;
 use crate::code_gen::code_gen_context::*;
+pub use crate::code_gen::debugger::DEBUGGER_ENV;
+pub use crate::code_gen::layout::{class_layouts, ClassLayout, FieldLayout};
+use crate::code_gen::parallel::Chunk;
+pub use crate::code_gen::part::{BuiltinIndex, Part};
+pub use crate::code_gen::source_map::annotate_llvm_ir;
 use crate::error::Error;
 use crate::hir::*;
 use crate::names::*;
+use crate::ty;
 use crate::ty::*;
 use either::*;
 use inkwell::types::*;
 use inkwell::values::*;
 use inkwell::AddressSpace;
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 /// CodeGen
@@ -33,20 +53,152 @@ pub struct CodeGen<'hir: 'ictx, 'run, 'ictx: 'run> {
     pub f64_type: inkwell::types::FloatType<'ictx>,
     pub void_type: inkwell::types::VoidType<'ictx>,
     pub llvm_struct_types: HashMap<ClassFullname, inkwell::types::StructType<'ictx>>,
+    /// Unique number of each class (stored in the header of the objects)
+    pub class_ids: HashMap<ClassFullname, u64>,
     str_literals: &'hir Vec<String>,
     /// Toplevel `self`
     the_main: Option<inkwell::values::BasicValueEnum<'ictx>>,
+    /// HIR which each llvm function is generated from (for bug reports)
+    hir_origins: RefCell<HashMap<String, &'hir dyn std::fmt::Debug>>,
+    /// Which part of the program to generate (see code_gen/part.rs)
+    part: Part<'run>,
+    /// Which methods to generate in this module, when they are divided
+    /// into threads (see code_gen/parallel.rs)
+    chunk: Option<Chunk<'run>>,
+    /// Whether the methods record themselves to the backtrace (see
+    /// code_gen/backtrace.rs)
+    backtrace: bool,
 }
 
 /// Compile hir and dump it to `outpath`
-pub fn run(hir: &Hir, outpath: &str) -> Result<(), Box<dyn std::error::Error>> {
+///
+/// If `verify` is true, the generated IR is checked with the LLVM verifier
+/// (see code_gen/verify.rs). If `n_jobs` is more than 1, the methods are
+/// generated in that number of threads (see code_gen/parallel.rs)
+pub fn run(
+    hir: &Hir,
+    outpath: &str,
+    verify: bool,
+    n_jobs: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let context = inkwell::context::Context::create();
+    let module = context.create_module("main");
+    gen_module(hir, &context, &module, verify, Part::Whole, n_jobs)?;
+    module.print_to_file(outpath)?;
+    Ok(())
+}
+
+/// Same as `run` but generates only the given part of the program.
+/// `header` (a comment) is prepended to the LLVM IR
+pub fn run_part(
+    hir: &Hir,
+    outpath: &str,
+    verify: bool,
+    part: Part,
+    header: &str,
+    n_jobs: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
     let context = inkwell::context::Context::create();
     let module = context.create_module("main");
+    gen_module(hir, &context, &module, verify, part, n_jobs)?;
+    let ir = format!("{}\n{}", header, module.print_to_string().to_string());
+    std::fs::write(outpath, ir)?;
+    Ok(())
+}
+
+/// Compile the builtin library (`hir` must not contain the user's program)
+/// and write it to `outpath` as LLVM bitcode
+pub fn gen_builtin_bitcode(
+    hir: &Hir,
+    outpath: &std::path::Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let context = inkwell::context::Context::create();
+    let module = context.create_module("builtin");
+    gen_module(hir, &context, &module, false, Part::Builtin, 1)?;
+    if module.write_bitcode_to_path(outpath) {
+        Ok(())
+    } else {
+        Err(Box::new(crate::error::plain_runner_error(format!(
+            "failed to write {}",
+            outpath.display()
+        ))))
+    }
+}
+
+/// Same as `run` but returns the LLVM IR as a string
+pub fn gen_llvm_ir(
+    hir: &Hir,
+    verify: bool,
+    n_jobs: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let context = inkwell::context::Context::create();
+    let module = context.create_module("main");
+    gen_module(hir, &context, &module, verify, Part::Whole, n_jobs)?;
+    Ok(module.print_to_string().to_string())
+}
+
+fn gen_module<'ictx>(
+    hir: &'ictx Hir,
+    context: &'ictx inkwell::context::Context,
+    module: &inkwell::module::Module<'ictx>,
+    verify: bool,
+    part: Part,
+    n_jobs: usize,
+) -> Result<(), Error> {
+    if n_jobs > 1 {
+        return parallel::gen_module_parallel(hir, context, module, verify, part, n_jobs);
+    }
+    gen_chunk(hir, context, module, verify, part, None)
+}
+
+/// Generate the methods in `chunk` (or all of them if `None`) and the
+/// other part of the program if this is the main module
+fn gen_chunk<'ictx>(
+    hir: &'ictx Hir,
+    context: &'ictx inkwell::context::Context,
+    module: &inkwell::module::Module<'ictx>,
+    verify: bool,
+    part: Part,
+    chunk: Option<Chunk>,
+) -> Result<(), Error> {
     let builder = context.create_builder();
     let mut code_gen = CodeGen::new(&hir, &context, &module, &builder);
-    code_gen.gen_program(&hir)?;
-    code_gen.module.print_to_file(outpath)?;
-    Ok(())
+    code_gen.part = part;
+    code_gen.chunk = chunk;
+    if verify {
+        code_gen.gen_program_verified(&hir)
+    } else {
+        code_gen.gen_program(&hir)
+    }
+}
+
+/// Convert the LLVM IR (made by `gen_llvm_ir`) into bitcode
+pub fn llvm_ir_to_bitcode(ir: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let context = inkwell::context::Context::create();
+    let module = parse_llvm_ir(&context, ir)?;
+    Ok(module.write_bitcode_to_memory().as_slice().to_vec())
+}
+
+/// Execute the LLVM IR (made by `gen_llvm_ir`) with the JIT compiler and
+/// return the value of `main`. `args` are passed as `argv` (excluding the
+/// program name)
+pub fn run_llvm_ir_jit(ir: &str, args: &[&str]) -> Result<i32, Box<dyn std::error::Error>> {
+    let context = inkwell::context::Context::create();
+    let module = parse_llvm_ir(&context, ir)?;
+    let engine = module.create_jit_execution_engine(inkwell::OptimizationLevel::None)?;
+    let main = module
+        .get_function("main")
+        .ok_or_else(|| crate::error::plain_runner_error("`main' not found in the module"))?;
+    Ok(unsafe { engine.run_function_as_main(main, args) })
+}
+
+fn parse_llvm_ir<'ictx>(
+    context: &'ictx inkwell::context::Context,
+    ir: &str,
+) -> Result<inkwell::module::Module<'ictx>, Box<dyn std::error::Error>> {
+    let buf =
+        inkwell::memory_buffer::MemoryBuffer::create_from_memory_range_copy(ir.as_bytes(), "main");
+    Ok(context.create_module_from_ir(buf)?)
 }
 
 impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
@@ -68,8 +220,13 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             f64_type: context.f64_type(),
             void_type: context.void_type(),
             llvm_struct_types: HashMap::new(),
+            class_ids: HashMap::new(),
             str_literals: &hir.str_literals,
             the_main: None,
+            hir_origins: RefCell::new(HashMap::new()),
+            part: Part::Whole,
+            chunk: None,
+            backtrace: !hir.release,
         }
     }
 
@@ -80,9 +237,18 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         self.gen_constant_ptrs(&hir.constants);
         self.gen_method_funcs(&hir.sk_methods);
         self.gen_methods(&hir.sk_methods)?;
+        if !self.is_main_module() {
+            // The rest are in the main module
+            return self.gen_lambda_funcs(&hir);
+        }
         self.gen_const_inits(&hir.const_inits)?;
         self.gen_user_main(&hir.main_exprs)?;
         self.gen_lambda_funcs(&hir)?;
+        self.gen_runtime_init();
+        self.gen_runtime_teardown();
+        self.gen_fiber_start();
+        self.gen_debugger();
+        self.gen_print_backtrace();
         self.gen_main()?;
         Ok(())
     }
@@ -92,6 +258,15 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         self.module.add_function("putchar", fn_type, None);
         let fn_type = self.i32_type.fn_type(&[self.i8ptr_type.into()], true);
         self.module.add_function("printf", fn_type, None);
+        let fn_type = self.i32_type.fn_type(
+            &[
+                self.i8ptr_type.into(),
+                self.i64_type.into(),
+                self.i8ptr_type.into(),
+            ],
+            true,
+        );
+        self.module.add_function("snprintf", fn_type, None);
         let fn_type = self.i32_type.fn_type(&[self.i8ptr_type.into()], false);
         self.module.add_function("puts", fn_type, None);
 
@@ -127,6 +302,14 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         let fn_type = self.f64_type.fn_type(&[self.f64_type.into()], false);
         self.module.add_function("floor", fn_type, None);
 
+        self.gen_runtime_declares();
+        self.gen_selector_declares();
+        self.gen_socket_declares();
+        self.gen_process_declares();
+        self.gen_fiber_declares();
+        self.gen_debugger_declares();
+        self.gen_backtrace_declares();
+
         let str_type = self.i8_type.array_type(3);
         let global = self.module.add_global(str_type, None, "putd_tmpl");
         global.set_linkage(inkwell::module::Linkage::Internal);
@@ -146,12 +329,23 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             self.i8_type.const_int(0, false),
         ]));
         global.set_constant(true);
+
+        let str_type = self.i8_type.array_type(3);
+        let global = self.module.add_global(str_type, None, "print_tmpl");
+        global.set_linkage(inkwell::module::Linkage::Internal);
+        global.set_initializer(&self.i8_type.const_array(&[
+            self.i8_type.const_int(37, false),  // %
+            self.i8_type.const_int(115, false), // s
+            self.i8_type.const_int(0, false),
+        ]));
+        global.set_constant(true);
     }
 
     fn gen_user_main(&mut self, main_exprs: &'hir HirExpressions) -> Result<(), Error> {
-        // define void @user_main()
-        let user_main_type = self.void_type.fn_type(&[], false);
+        // define i32 @user_main()
+        let user_main_type = self.i32_type.fn_type(&[], false);
         let function = self.module.add_function("user_main", user_main_type, None);
+        self.set_hir_origin("user_main", main_exprs);
         let create_main_block = self.context.append_basic_block(function, "CreateMain");
         let user_main_block = self.context.append_basic_block(function, "UserMain");
 
@@ -163,32 +357,44 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         // UserMain:
         self.builder.position_at_end(user_main_block);
         let mut ctx = CodeGenContext::new(function, FunctionOrigin::Other, None);
-        self.gen_exprs(&mut ctx, &main_exprs)?;
-        self.builder.build_return(None);
+        let value = self.gen_exprs(&mut ctx, &main_exprs)?;
+        // The exit status (see `HirMaker::convert_toplevel_items`)
+        let status = if main_exprs.ty == ty::raw("Int") {
+            self.unbox_int(value)
+        } else {
+            self.i32_type.const_int(0, false)
+        };
+        self.builder.build_return(Some(&status));
 
         Ok(())
     }
 
+    /// Generate `main` (see code_gen/runtime.rs for the details)
     fn gen_main(&mut self) -> Result<(), Error> {
-        // define i32 @main() {
-        let main_type = self.i32_type.fn_type(&[], false);
+        // define i32 @main(i32 %argc, i8** %argv) {
+        let argv_type = self.i8ptr_type.ptr_type(AddressSpace::Generic);
+        let main_type = self
+            .i32_type
+            .fn_type(&[self.i32_type.into(), argv_type.into()], false);
         let function = self.module.add_function("main", main_type, None);
         let basic_block = self.context.append_basic_block(function, "");
         self.builder.position_at_end(basic_block);
 
-        // Call GC_init
-        let func = self.get_llvm_func("GC_init");
-        self.builder.build_call(func, &[], "");
-
-        // Call init_constants, user_main
-        let func = self.get_llvm_func("init_constants");
-        self.builder.build_call(func, &[], "");
+        let func = self.get_llvm_func("shiika_init");
+        let args = function.get_params();
+        self.builder.build_call(func, &args, "");
         let func = self.get_llvm_func("user_main");
+        let status = self
+            .builder
+            .build_call(func, &[], "status")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        let func = self.get_llvm_func("shiika_teardown");
         self.builder.build_call(func, &[], "");
 
-        // ret i32 0
-        self.builder
-            .build_return(Some(&self.i32_type.const_int(0, false)));
+        // ret i32 %status
+        self.builder.build_return(Some(&status));
         Ok(())
     }
 
@@ -200,7 +406,18 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
                 .insert(name.clone(), self.context.opaque_struct_type(&name.0));
         }
 
-        // 2. Set ivars
+        // 2. Assign class ids (sorted to make the output stable. The classes
+        // of the builtin module come first to have the same ids as in it)
+        let mut names = classes.keys().collect::<Vec<_>>();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Part::User(index) = self.part {
+            names.sort_by_key(|name| !index.has_class(name));
+        }
+        for (i, name) in names.into_iter().enumerate() {
+            self.class_ids.insert(name.clone(), i as u64);
+        }
+
+        // 3. Set ivars
         for (name, sk_class) in classes {
             let struct_type = self.llvm_struct_types.get(&name).unwrap();
             if name.0 == "Int" {
@@ -251,11 +468,13 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         for (fullname, ty) in constants {
             let name = &fullname.0;
             let global = self.module.add_global(self.llvm_type(&ty), None, name);
-            global.set_linkage(inkwell::module::Linkage::Internal);
+            if let (Part::Whole, None) = (self.part, self.chunk) {
+                global.set_linkage(inkwell::module::Linkage::Internal);
+            }
             let null = self.i32_type.ptr_type(AddressSpace::Generic).const_null();
             match self.llvm_zero_value(ty) {
-                Some(zero) => global.set_initializer(&zero),
-                None => global.set_initializer(&null),
+                Some(zero) => self.set_shared_initializer(global, &zero),
+                None => self.set_shared_initializer(global, &null),
             }
         }
     }
@@ -266,7 +485,9 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             match &expr.node {
                 HirExpressionBase::HirConstAssign { fullname, .. } => {
                     let fn_type = self.void_type.fn_type(&[], false);
-                    let function = self.module.add_function(&format!("init_{}", fullname.0), fn_type, None);
+                    let func_name = format!("init_{}", fullname.0);
+                    let function = self.module.add_function(&func_name, fn_type, None);
+                    self.set_hir_origin(&func_name, expr);
                     let mut ctx = CodeGenContext::new(function, FunctionOrigin::Other, None);
                     let basic_block = self.context.append_basic_block(function, "");
                     self.builder.position_at_end(basic_block);
@@ -283,7 +504,17 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         let basic_block = self.context.append_basic_block(function, "");
         self.builder.position_at_end(basic_block);
 
+        // Generate ::Void (first, because initializers may refer it)
+        let ptr = self
+            .module
+            .get_global(&"::Void")
+            .unwrap()
+            .as_pointer_value();
+        let value = self.allocate_sk_obj(&class_fullname("Void"), "void_obj");
+        self.builder.build_store(ptr, value);
+
         // call void @"init_::XX"()
+        // (`const_inits` is sorted by the dependency. See hir::const_init_order)
         for expr in const_inits {
             match &expr.node {
                 HirExpressionBase::HirConstAssign { fullname, .. } => {
@@ -294,15 +525,6 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
             }
         }
 
-        // Generate ::Void
-        let ptr = self
-            .module
-            .get_global(&"::Void")
-            .unwrap()
-            .as_pointer_value();
-        let value = self.allocate_sk_obj(&class_fullname("Void"), "void_obj");
-        self.builder.build_store(ptr, value);
-
         self.builder.build_return(None);
         Ok(())
     }
@@ -360,10 +582,36 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         methods.values().try_for_each(|sk_methods| {
             sk_methods
                 .iter()
+                .filter(|method| self.generates_method(method))
                 .try_for_each(|method| self.gen_method(&method))
         })
     }
 
+    /// Returns true if the body of the method is generated in this module
+    /// (otherwise it is linked from another module)
+    fn generates_method(&self, method: &SkMethod) -> bool {
+        let name = &method.signature.fullname;
+        self.part.has_method(name) && self.chunk.map_or(true, |chunk| chunk.has_method(name))
+    }
+
+    /// Returns true if this module has the things other than the methods
+    /// (`main`, the initializers of the constants, etc.)
+    fn is_main_module(&self) -> bool {
+        match self.part {
+            Part::Builtin => false,
+            _ => self.chunk.map_or(true, |chunk| chunk.is_main()),
+        }
+    }
+
+    /// Set the initial value of a global variable which other modules
+    /// refer. It is left as a declaration if this is not the main module
+    fn set_shared_initializer(&self, global: GlobalValue<'ictx>, value: &dyn BasicValue<'ictx>) {
+        if !self.is_main_module() {
+            return;
+        }
+        global.set_initializer(value);
+    }
+
     fn gen_method(&self, method: &'hir SkMethod) -> Result<(), Error> {
         let func_name = &method.signature.fullname.full_name;
         self.gen_llvm_func_body(
@@ -405,10 +653,18 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
                 SkMethodBody::RustMethodBody { gen } => gen(self, &function)?,
                 SkMethodBody::RustClosureMethodBody { boxed_gen } => boxed_gen(self, &function)?,
                 SkMethodBody::ShiikaMethodBody { exprs } => {
-                    self.gen_shiika_method_body(function, None, ret_ty.is_void_type(), &exprs)?
+                    self.set_hir_origin(func_name, exprs);
+                    self.gen_shiika_method_body(
+                        func_name,
+                        function,
+                        None,
+                        ret_ty.is_void_type(),
+                        &exprs,
+                    )?
                 }
             },
             Right(exprs) => {
+                self.set_hir_origin(func_name, exprs);
                 self.gen_shiika_lambda_body(function, Some(params), ret_ty.is_void_type(), &exprs)?;
             }
         }
@@ -418,15 +674,25 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
     /// Generate body of llvm function of Shiika method
     fn gen_shiika_method_body(
         &self,
+        func_name: &str,
         function: inkwell::values::FunctionValue<'run>,
         function_params: Option<&'hir [MethodParam]>,
         void_method: bool,
         exprs: &'hir HirExpressions,
     ) -> Result<(), Error> {
         let mut ctx = CodeGenContext::new(function, FunctionOrigin::Method, function_params);
+        if self.backtrace {
+            ctx.backtrace_depth = Some(self.build_backtrace_push(func_name));
+        }
         let last_value = self.gen_exprs(&mut ctx, exprs)?;
+        if !exprs.ty.is_never_type() {
+            self.build_backtrace_pop(&ctx);
+        }
         if void_method {
             self.builder.build_return(None);
+        } else if exprs.ty.is_never_type() {
+            // The end is not reachable (eg. `loop` without `break`)
+            self.builder.build_unreachable();
         } else {
             self.builder.build_return(Some(&last_value));
         }
@@ -445,6 +711,9 @@ impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
         let last_value = self.gen_exprs(&mut ctx, exprs)?;
         if void_method {
             self.builder.build_return(None);
+        } else if exprs.ty.is_never_type() {
+            // The end is not reachable (eg. `loop` without `break`)
+            self.builder.build_unreachable();
         } else {
             let llvm_type = self.llvm_type(&exprs.ty);
             let v = self.builder.build_bitcast(last_value, llvm_type, "");
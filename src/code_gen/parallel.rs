@@ -0,0 +1,119 @@
+/// Parallel code generation
+///
+/// The bodies of the methods do not depend on each other, so they can be
+/// generated in different threads. Each thread has its own LLVM context
+/// (which cannot be shared between threads) and makes a module of the
+/// methods assigned to it (a `Chunk`.) The modules are passed as bitcode
+/// and linked into the main module, which has the rest of the program
+/// (`main`, the initializers of the constants, etc.)
+use crate::code_gen::*;
+use crate::error;
+use crate::error::Error;
+use crate::hir::Hir;
+use crate::names::MethodFullname;
+use std::collections::HashMap;
+use std::panic;
+
+/// The methods generated in a module
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Chunk<'a> {
+    /// Number of the module (0 is the main module)
+    pub id: usize,
+    /// Number of the module which generates each method
+    pub owners: &'a HashMap<String, usize>,
+}
+
+impl<'a> Chunk<'a> {
+    /// Returns true if the method is generated in this module
+    pub fn has_method(&self, name: &MethodFullname) -> bool {
+        match self.owners.get(&name.full_name) {
+            Some(id) => *id == self.id,
+            None => self.is_main(),
+        }
+    }
+
+    /// Returns true if this is the main module
+    pub fn is_main(&self) -> bool {
+        self.id == 0
+    }
+}
+
+/// Generate the program into `module` with `n_jobs` threads (including
+/// the current one)
+pub(super) fn gen_module_parallel<'ictx>(
+    hir: &'ictx Hir,
+    context: &'ictx inkwell::context::Context,
+    module: &inkwell::module::Module<'ictx>,
+    verify: bool,
+    part: Part,
+    n_jobs: usize,
+) -> Result<(), Error> {
+    let owners = assign_methods(hir, part, n_jobs);
+    let bitcodes = std::thread::scope(|s| {
+        let workers = (1..n_jobs)
+            .map(|id| {
+                let chunk = Chunk {
+                    id,
+                    owners: &owners,
+                };
+                s.spawn(move || gen_chunk_bitcode(hir, verify, part, chunk))
+            })
+            .collect::<Vec<_>>();
+        let chunk = Chunk {
+            id: 0,
+            owners: &owners,
+        };
+        let result = gen_chunk(hir, context, module, verify, part, Some(chunk));
+        let bitcodes = workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|payload| panic::resume_unwind(payload))
+            })
+            .collect::<Vec<_>>();
+        result.map(|_| bitcodes)
+    })?;
+
+    for bitcode in bitcodes {
+        let bitcode = bitcode.map_err(error::bug)?;
+        let buf =
+            inkwell::memory_buffer::MemoryBuffer::create_from_memory_range_copy(&bitcode, "chunk");
+        let chunk_module = context
+            .create_module_from_ir(buf)
+            .map_err(|e| error::bug(format!("failed to load a chunk: {}", e.to_string())))?;
+        module
+            .link_in_module(chunk_module)
+            .map_err(|e| error::bug(format!("failed to link a chunk: {}", e.to_string())))?;
+    }
+    Ok(())
+}
+
+/// Generate the methods of the chunk in a new LLVM context and return the
+/// module as bitcode (or the message of the error, because `Error` cannot
+/// be sent between threads)
+fn gen_chunk_bitcode(hir: &Hir, verify: bool, part: Part, chunk: Chunk) -> Result<Vec<u8>, String> {
+    let context = inkwell::context::Context::create();
+    let module = context.create_module(&format!("chunk{}", chunk.id));
+    gen_chunk(hir, &context, &module, verify, part, Some(chunk)).map_err(|e| e.msg)?;
+    Ok(module.write_bitcode_to_memory().as_slice().to_vec())
+}
+
+/// Decide which module generates each method. The methods are sorted by
+/// the name (to make the output stable) and dealt to the modules in turn
+fn assign_methods(hir: &Hir, part: Part, n_jobs: usize) -> HashMap<String, usize> {
+    let mut names = hir
+        .sk_methods
+        .values()
+        .flatten()
+        .map(|method| &method.signature.fullname)
+        .filter(|name| part.has_method(name))
+        .map(|name| &name.full_name)
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name.clone(), i % n_jobs))
+        .collect()
+}
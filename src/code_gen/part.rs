@@ -0,0 +1,137 @@
+/// Separate compilation of the builtin library
+///
+/// The methods of the builtin library (and the lambdas in them) can be
+/// compiled into a module of their own, which is cached and linked with the
+/// modules of the user's programs (see `crate::builtin_cache`.) The
+/// constants and the global variables of the runtime are defined in the
+/// user's module; the builtin module only refers them. So are the methods
+/// which depend on the classes of the user's program
+/// (`hash_dispatch::DISPATCHED_METHODS`.)
+use crate::hir::hash_dispatch::DISPATCHED_METHODS;
+use crate::hir::*;
+use crate::names::*;
+use std::collections::HashSet;
+
+/// Which part of the program to generate
+#[derive(Debug, Clone, Copy)]
+pub enum Part<'a> {
+    /// The whole program
+    Whole,
+    /// Only the methods of the builtin library (given a HIR of the builtin
+    /// library alone)
+    Builtin,
+    /// The program except the methods in the index, which are only
+    /// declared
+    User(&'a BuiltinIndex),
+}
+
+impl<'a> Part<'a> {
+    /// Returns true if the body of the method is generated in this part
+    /// (otherwise it is linked from another module)
+    pub fn has_method(&self, name: &MethodFullname) -> bool {
+        match self {
+            Part::Whole => true,
+            Part::Builtin => !DISPATCHED_METHODS.contains(&name.full_name.as_str()),
+            Part::User(index) => !index.has_method(name),
+        }
+    }
+}
+
+/// What the compiled builtin library contains
+#[derive(Debug, PartialEq)]
+pub struct BuiltinIndex {
+    /// The classes and their number of ivars
+    pub classes: Vec<(ClassFullname, usize)>,
+    /// Full names of the methods
+    pub methods: HashSet<String>,
+}
+
+impl BuiltinIndex {
+    /// Create the index of the HIR of the builtin library
+    pub fn new(hir: &Hir) -> BuiltinIndex {
+        let mut classes = hir
+            .sk_classes
+            .iter()
+            .map(|(name, class)| (name.clone(), class.ivars.len()))
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+        let methods = hir
+            .sk_methods
+            .values()
+            .flatten()
+            .map(|method| &method.signature.fullname)
+            .filter(|name| Part::Builtin.has_method(name))
+            .map(|name| name.full_name.clone())
+            .collect();
+        BuiltinIndex { classes, methods }
+    }
+
+    /// Returns true if the builtin part of `hir` is the same as the
+    /// compiled one, ie. the user's program does not add methods (or
+    /// ivars) to the builtin classes. `user_defined` tells if the method
+    /// is defined (or redefined) in the user's program
+    pub fn covers(&self, hir: &Hir, user_defined: impl Fn(&str) -> bool) -> bool {
+        let classes_ok = self.classes.iter().all(|(name, n_ivars)| {
+            hir.sk_classes
+                .get(name)
+                .map_or(false, |class| class.ivars.len() == *n_ivars)
+        });
+        let methods_ok = self.classes.iter().all(|(name, _)| {
+            hir.sk_methods.get(name).map_or(true, |methods| {
+                methods.iter().all(|method| {
+                    let name = &method.signature.fullname.full_name;
+                    let compiled =
+                        self.methods.contains(name) || DISPATCHED_METHODS.contains(&name.as_str());
+                    compiled && !user_defined(name)
+                })
+            })
+        });
+        classes_ok && methods_ok
+    }
+
+    /// Returns true if the body of the method is in the builtin module
+    pub fn has_method(&self, name: &MethodFullname) -> bool {
+        self.methods.contains(&name.full_name)
+    }
+
+    /// Returns true if the class is in the builtin module
+    pub fn has_class(&self, name: &ClassFullname) -> bool {
+        self.classes.iter().any(|(cls, _)| cls == name)
+    }
+
+    /// Serialize the index (one line for each class or method)
+    pub fn to_text(&self) -> String {
+        let mut lines = self
+            .classes
+            .iter()
+            .map(|(name, n_ivars)| format!("class {} {}", name.0, n_ivars))
+            .collect::<Vec<_>>();
+        let mut methods = self
+            .methods
+            .iter()
+            .map(|name| format!("method {}", name))
+            .collect::<Vec<_>>();
+        methods.sort();
+        lines.append(&mut methods);
+        lines.join("\n") + "\n"
+    }
+
+    /// Deserialize the index made by `to_text`
+    pub fn from_text(text: &str) -> Option<BuiltinIndex> {
+        let mut classes = vec![];
+        let mut methods = HashSet::new();
+        for line in text.lines() {
+            let words = line.split(' ').collect::<Vec<_>>();
+            match words.as_slice() {
+                ["class", name, n_ivars] => {
+                    classes.push((class_fullname(*name), n_ivars.parse().ok()?))
+                }
+                ["method", name] => {
+                    methods.insert(name.to_string());
+                }
+                _ => return None,
+            }
+        }
+        Some(BuiltinIndex { classes, methods })
+    }
+}
@@ -0,0 +1,134 @@
+/// Child processes (`Shiika::Internal::Process`)
+///
+/// Commands are run by the shell with popen(3) and system(3) (`_popen` etc.
+/// on Windows), which fork and exec the shell for us:
+///
+/// - `build_popen`: start a command and return the `FILE *` to read its
+///   stdout from (null on failure)
+/// - `build_pclose`: wait for the command started by `build_popen`
+/// - `build_system`: run a command, waiting for it
+///
+/// Stdio is flushed before starting a command so that the outputs of the
+/// program and the command are not mixed up. `build_pclose` and
+/// `build_system` return the exit status of the command (128 + the signal
+/// number if it is killed by a signal, like the shells) or -1 on failure.
+use crate::code_gen::*;
+use crate::platform::Platform;
+use inkwell::values::{BasicValueEnum, IntValue, PointerValue};
+use inkwell::IntPredicate;
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_process_declares(&self) {
+        let i8ptr = self.i8ptr_type.into();
+        let fn_type = self.i8ptr_type.fn_type(&[i8ptr, i8ptr], false);
+        self.module
+            .add_function(self.process_func("popen"), fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i8ptr], false);
+        self.module
+            .add_function(self.process_func("pclose"), fn_type, None);
+        self.module
+            .add_function(self.process_func("fileno"), fn_type, None);
+        self.module.add_function("system", fn_type, None);
+    }
+
+    /// Start the command `cmd` (a C string) to read its stdout
+    pub fn build_popen(&self, cmd: BasicValueEnum<'ictx>) -> PointerValue<'ictx> {
+        self.build_flush_stdio();
+        let mode = self
+            .builder
+            .build_global_string_ptr("r", "popen_mode")
+            .as_pointer_value();
+        let func = self.get_llvm_func(self.process_func("popen"));
+        self.builder
+            .build_call(func, &[cmd, mode.into()], "file")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    /// Returns the fd of `file` (a `FILE *`; -1 if it is null)
+    pub fn build_fileno(&self, file: PointerValue<'ictx>) -> IntValue<'ictx> {
+        let start_block = self.builder.get_insert_block().unwrap();
+        let function = start_block.get_parent().unwrap();
+        let fileno_block = self.context.append_basic_block(function, "Fileno");
+        let end_block = self.context.append_basic_block(function, "FilenoEnd");
+        let is_null = self.builder.build_is_null(file, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, end_block, fileno_block);
+        // Fileno:
+        self.builder.position_at_end(fileno_block);
+        let fd = self.call_i32(self.process_func("fileno"), &[file.into()]);
+        self.builder.build_unconditional_branch(end_block);
+        // FilenoEnd:
+        self.builder.position_at_end(end_block);
+        let phi = self.builder.build_phi(self.i32_type, "fd");
+        phi.add_incoming(&[(&self.failure(), start_block), (&fd, fileno_block)]);
+        phi.as_basic_value().into_int_value()
+    }
+
+    pub fn build_pclose(&self, file: PointerValue<'ictx>) -> IntValue<'ictx> {
+        let status = self.call_i32(self.process_func("pclose"), &[file.into()]);
+        self.exit_status(status)
+    }
+
+    /// Run the command `cmd` (a C string)
+    pub fn build_system(&self, cmd: BasicValueEnum<'ictx>) -> IntValue<'ictx> {
+        self.build_flush_stdio();
+        let status = self.call_i32("system", &[cmd]);
+        self.exit_status(status)
+    }
+
+    fn build_flush_stdio(&self) {
+        self.builder.build_call(
+            self.get_llvm_func("fflush"),
+            &[self.i8ptr_type.const_null().into()],
+            "",
+        );
+    }
+
+    /// Convert the status returned by pclose or system to the exit status
+    fn exit_status(&self, status: IntValue<'ictx>) -> IntValue<'ictx> {
+        if Platform::host() == Platform::Windows {
+            return status;
+        }
+        // WIFEXITED(status) ? WEXITSTATUS(status) : 128 + WTERMSIG(status)
+        let signal = self.bits(status, 0x7f);
+        let exited = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            signal,
+            self.i32_type.const_int(0, false),
+            "exited",
+        );
+        let code =
+            self.builder
+                .build_right_shift(status, self.i32_type.const_int(8, false), false, "");
+        let code = self.bits(code, 0xff);
+        let killed =
+            self.builder
+                .build_int_add(signal, self.i32_type.const_int(128, false), "killed");
+        let result = self
+            .builder
+            .build_select(exited, code, killed, "exit_status")
+            .into_int_value();
+        let failed =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, status, self.failure(), "failed");
+        self.builder
+            .build_select(failed, status, result, "result")
+            .into_int_value()
+    }
+
+    /// The name of the C function (prefixed with `_` on Windows)
+    fn process_func(&self, name: &'static str) -> &'static str {
+        if Platform::host() != Platform::Windows {
+            return name;
+        }
+        match name {
+            "popen" => "_popen",
+            "pclose" => "_pclose",
+            "fileno" => "_fileno",
+            _ => name,
+        }
+    }
+}
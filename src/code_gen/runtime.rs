@@ -0,0 +1,316 @@
+/// Initialization and teardown of the runtime
+///
+/// `main` of the generated program does the following:
+///
+/// 1. `shiika_init(argc, argv)`: initializes the GC, saves `argc` and
+///    `argv` (in `@shiika_argc`, `@shiika_argv`) and initializes the constants
+/// 2. `user_main()`: runs the program (including the `.inherited` hooks)
+///    and returns the exit status (see `CodeGen::gen_user_main`)
+/// 3. `shiika_teardown()`: calls the handlers registered by `at_exit` (the
+///    last registered one first) and flushes stdio
+///
+/// `exit(status)` also calls `shiika_teardown` before exiting, so outputs
+/// are not lost.
+///
+/// `shiika_init` also saves the current time (in `@shiika_time_origin`) so
+/// that `Shiika::Internal::Time.now_ms` can return the milliseconds since
+/// the program started in an `Int`.
+use crate::code_gen::*;
+use crate::ty;
+use inkwell::AddressSpace;
+
+/// Global variable which holds the list of the `at_exit` handlers
+/// (each node is `[next, fn_obj]`)
+const AT_EXIT_HANDLERS: &str = "shiika_at_exit_handlers";
+
+/// Global variable which holds the seconds of the time when the program
+/// started
+const TIME_ORIGIN: &str = "shiika_time_origin";
+
+/// `TIME_UTC` of `timespec_get`
+const TIME_UTC: u64 = 1;
+
+/// `gmtime` and `mktime` with 64-bit `time_t` (they are inline functions
+/// on Windows)
+const GMTIME: &str = if cfg!(windows) { "_gmtime64" } else { "gmtime" };
+const MKTIME: &str = if cfg!(windows) { "_mktime64" } else { "mktime" };
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_runtime_declares(&self) {
+        let fn_type = self.i32_type.fn_type(&[self.i8ptr_type.into()], false);
+        self.module.add_function("fflush", fn_type, None);
+        let fn_type = self.void_type.fn_type(&[self.i32_type.into()], false);
+        self.module.add_function("exit", fn_type, None);
+
+        let argv_type = self.i8ptr_type.ptr_type(AddressSpace::Generic);
+        let fn_type = self
+            .void_type
+            .fn_type(&[self.i32_type.into(), argv_type.into()], false);
+        self.module.add_function("shiika_init", fn_type, None);
+        let fn_type = self.void_type.fn_type(&[], false);
+        self.module.add_function("shiika_teardown", fn_type, None);
+
+        // C11 (available on Windows too)
+        let fn_type = self
+            .i32_type
+            .fn_type(&[self.i8ptr_type.into(), self.i32_type.into()], false);
+        self.module.add_function("timespec_get", fn_type, None);
+        let fn_type = self.i8ptr_type.fn_type(&[self.i8ptr_type.into()], false);
+        self.module.add_function(GMTIME, fn_type, None);
+        let fn_type = self.i64_type.fn_type(&[self.i8ptr_type.into()], false);
+        self.module.add_function(MKTIME, fn_type, None);
+        if cfg!(windows) {
+            let fn_type = self.void_type.fn_type(&[self.i32_type.into()], false);
+            self.module.add_function("Sleep", fn_type, None);
+        } else {
+            let fn_type = self.i32_type.fn_type(&[self.i32_type.into()], false);
+            self.module.add_function("usleep", fn_type, None);
+        }
+
+        let global = self.module.add_global(self.i32_type, None, "shiika_argc");
+        self.set_shared_initializer(global, &self.i32_type.const_int(0, false));
+        let global = self.module.add_global(argv_type, None, "shiika_argv");
+        self.set_shared_initializer(global, &argv_type.const_null());
+        let global = self
+            .module
+            .add_global(self.i8ptr_type, None, AT_EXIT_HANDLERS);
+        self.set_shared_initializer(global, &self.i8ptr_type.const_null());
+        let global = self.module.add_global(self.i64_type, None, TIME_ORIGIN);
+        self.set_shared_initializer(global, &self.i64_type.const_int(0, false));
+    }
+
+    /// Define `shiika_init`
+    pub(super) fn gen_runtime_init(&self) {
+        let function = self.get_llvm_func("shiika_init");
+        let basic_block = self.context.append_basic_block(function, "");
+        self.builder.position_at_end(basic_block);
+
+        let func = self.get_llvm_func("GC_init");
+        self.builder.build_call(func, &[], "");
+
+        let argc = self.module.get_global("shiika_argc").unwrap();
+        self.builder
+            .build_store(argc.as_pointer_value(), function.get_params()[0]);
+        let argv = self.module.get_global("shiika_argv").unwrap();
+        self.builder
+            .build_store(argv.as_pointer_value(), function.get_params()[1]);
+        let (sec, _) = self.build_timespec_get();
+        let origin = self.module.get_global(TIME_ORIGIN).unwrap();
+        self.builder.build_store(origin.as_pointer_value(), sec);
+
+        let func = self.get_llvm_func("init_constants");
+        self.builder.build_call(func, &[], "");
+        self.builder.build_return(None);
+    }
+
+    /// Define `shiika_teardown`
+    pub(super) fn gen_runtime_teardown(&self) {
+        let function = self.get_llvm_func("shiika_teardown");
+        let begin_block = self.context.append_basic_block(function, "Begin");
+        let body_block = self.context.append_basic_block(function, "CallHandler");
+        let end_block = self.context.append_basic_block(function, "End");
+        let handlers = self
+            .module
+            .get_global(AT_EXIT_HANDLERS)
+            .unwrap()
+            .as_pointer_value();
+
+        // Begin:
+        self.builder.position_at_end(begin_block);
+        let node = self
+            .builder
+            .build_load(handlers, "node")
+            .into_pointer_value();
+        let is_null = self.builder.build_is_null(node, "is_null");
+        self.builder
+            .build_conditional_branch(is_null, end_block, body_block);
+
+        // CallHandler:
+        // Remove the handler from the list before calling it so that
+        // calling `exit` in a handler does not call it again
+        self.builder.position_at_end(body_block);
+        let (next_ptr, fn_ptr) = self.at_exit_node_fields(node);
+        let next = self.builder.build_load(next_ptr, "next");
+        self.builder.build_store(handlers, next);
+        let fn_obj = self.builder.build_load(fn_ptr, "fn_i8");
+        let fn_type = self.llvm_type(&ty::raw("Fn0"));
+        let fn_obj = self.builder.build_bitcast(fn_obj, fn_type, "fn");
+        self.build_lambda_call(fn_obj, vec![]);
+        self.builder.build_unconditional_branch(begin_block);
+
+        // End:
+        self.builder.position_at_end(end_block);
+        let func = self.get_llvm_func("fflush");
+        self.builder
+            .build_call(func, &[self.i8ptr_type.const_null().into()], "");
+        self.builder.build_return(None);
+    }
+
+    /// Add `fn_obj` (an instance of `Fn0`) to the list of the `at_exit`
+    /// handlers
+    pub fn build_at_exit_push(&self, fn_obj: inkwell::values::BasicValueEnum) {
+        let handlers = self
+            .module
+            .get_global(AT_EXIT_HANDLERS)
+            .unwrap()
+            .as_pointer_value();
+        let func = self.get_llvm_func("GC_malloc");
+        let size = self.i64_type.const_int(16, false);
+        let node = self
+            .builder
+            .build_call(func, &[size.as_basic_value_enum()], "node")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        let (next_ptr, fn_ptr) = self.at_exit_node_fields(node);
+        let next = self.builder.build_load(handlers, "next");
+        self.builder.build_store(next_ptr, next);
+        let fn_i8 = self.builder.build_bitcast(fn_obj, self.i8ptr_type, "");
+        self.builder.build_store(fn_ptr, fn_i8);
+        self.builder.build_store(handlers, node);
+    }
+
+    /// Return the pointers to the fields of a node of the `at_exit` handlers
+    fn at_exit_node_fields(
+        &self,
+        node: inkwell::values::PointerValue<'ictx>,
+    ) -> (
+        inkwell::values::PointerValue<'ictx>,
+        inkwell::values::PointerValue<'ictx>,
+    ) {
+        let fields = self.builder.build_pointer_cast(
+            node,
+            self.i8ptr_type.ptr_type(AddressSpace::Generic),
+            "fields",
+        );
+        let one = self.i64_type.const_int(1, false);
+        let fn_ptr = unsafe { self.builder.build_in_bounds_gep(fields, &[one], "fn_ptr") };
+        (fields, fn_ptr)
+    }
+
+    /// Return the milliseconds since the program started (as i32)
+    pub fn build_now_ms(&self) -> inkwell::values::IntValue<'ictx> {
+        let (sec, nsec) = self.build_timespec_get();
+        let origin = self.module.get_global(TIME_ORIGIN).unwrap();
+        let origin = self
+            .builder
+            .build_load(origin.as_pointer_value(), "origin")
+            .into_int_value();
+        let sec = self.builder.build_int_sub(sec, origin, "sec");
+        let ms = self
+            .builder
+            .build_int_mul(sec, self.i64_type.const_int(1000, false), "sec_ms");
+        let nsec = self.builder.build_int_s_extend(nsec, self.i64_type, "nsec");
+        let nsec_ms = self.builder.build_int_signed_div(
+            nsec,
+            self.i64_type.const_int(1_000_000, false),
+            "nsec_ms",
+        );
+        let ms = self.builder.build_int_add(ms, nsec_ms, "ms");
+        self.builder.build_int_truncate(ms, self.i32_type, "ms")
+    }
+
+    /// Return a number made from the current time (as i32; for the seed
+    /// of `Random`)
+    pub fn build_clock_seed(&self) -> inkwell::values::IntValue<'ictx> {
+        let (sec, nsec) = self.build_timespec_get();
+        let sec = self.builder.build_int_truncate(sec, self.i32_type, "sec");
+        let seed = self.builder.build_xor(sec, nsec, "seed");
+        // Make it non-negative
+        self.builder
+            .build_and(seed, self.i32_type.const_int(0x7fff_ffff, false), "seed")
+    }
+
+    /// Return the seconds since the Unix epoch (as i32, so it overflows in
+    /// 2038)
+    pub fn build_unix_time(&self) -> inkwell::values::IntValue<'ictx> {
+        let (sec, _) = self.build_timespec_get();
+        self.builder
+            .build_int_truncate(sec, self.i32_type, "unix_time")
+    }
+
+    /// Return the UTC offset of the local time zone in seconds (as i32).
+    /// It is the difference between the current time and the result of
+    /// `mktime` (which takes a local time) given the UTC time from `gmtime`
+    pub fn build_utc_offset(&self) -> inkwell::values::IntValue<'ictx> {
+        let (sec, _) = self.build_timespec_get();
+        let time = self.builder.build_alloca(self.i64_type, "time");
+        self.builder.build_store(time, sec);
+        let time_i8 = self.builder.build_bitcast(time, self.i8ptr_type, "time_i8");
+        let tm = self
+            .builder
+            .build_call(self.get_llvm_func(GMTIME), &[time_i8], "tm")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        // Set `tm_isdst` (the 9th int) to -1 so that `mktime` decides
+        // whether the daylight saving time is in effect
+        let fields = self
+            .builder
+            .build_bitcast(tm, self.i32_type.ptr_type(AddressSpace::Generic), "fields")
+            .into_pointer_value();
+        let isdst = unsafe {
+            self.builder
+                .build_in_bounds_gep(fields, &[self.i64_type.const_int(8, false)], "isdst")
+        };
+        self.builder
+            .build_store(isdst, self.i32_type.const_int(-1i64 as u64, true));
+        let local = self
+            .builder
+            .build_call(self.get_llvm_func(MKTIME), &[tm], "local")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let offset = self.builder.build_int_sub(sec, local, "offset");
+        self.builder
+            .build_int_truncate(offset, self.i32_type, "offset")
+    }
+
+    /// Sleep for `ms` (i32) milliseconds (without busy waiting)
+    pub fn build_sleep_ms(&self, ms: inkwell::values::IntValue<'ictx>) {
+        if cfg!(windows) {
+            let func = self.get_llvm_func("Sleep");
+            self.builder.build_call(func, &[ms.into()], "");
+        } else {
+            let usec = self
+                .builder
+                .build_int_mul(ms, self.i32_type.const_int(1000, false), "usec");
+            let func = self.get_llvm_func("usleep");
+            self.builder.build_call(func, &[usec.into()], "");
+        }
+    }
+
+    /// Call `timespec_get` and return `tv_sec` (i64) and `tv_nsec` (i32)
+    fn build_timespec_get(
+        &self,
+    ) -> (
+        inkwell::values::IntValue<'ictx>,
+        inkwell::values::IntValue<'ictx>,
+    ) {
+        // `tv_nsec` is a `long`, which is 32bit on Windows. It is read as
+        // i32 since it is less than 10^9 (and the targets are little endian)
+        let timespec_type = self.context.struct_type(
+            &[
+                self.i64_type.into(),
+                self.i32_type.into(),
+                self.i32_type.into(),
+            ],
+            false,
+        );
+        let ts = self.builder.build_alloca(timespec_type, "ts");
+        let ts_i8 = self.builder.build_bitcast(ts, self.i8ptr_type, "ts_i8");
+        let func = self.get_llvm_func("timespec_get");
+        self.builder.build_call(
+            func,
+            &[ts_i8, self.i32_type.const_int(TIME_UTC, false).into()],
+            "",
+        );
+        let sec_ptr = self.builder.build_struct_gep(ts, 0, "sec_ptr").unwrap();
+        let nsec_ptr = self.builder.build_struct_gep(ts, 1, "nsec_ptr").unwrap();
+        let sec = self.builder.build_load(sec_ptr, "sec").into_int_value();
+        let nsec = self.builder.build_load(nsec_ptr, "nsec").into_int_value();
+        (sec, nsec)
+    }
+}
@@ -0,0 +1,463 @@
+/// File descriptors and the selector (`Shiika::Internal::IO`)
+///
+/// The selector is epoll on Linux and kqueue on macOS. Both are wrapped
+/// in the same operations so that `EventLoop` (builtin/io.sk) does
+/// not depend on the platform:
+///
+/// - `build_selector_new`: create a selector (returns its fd)
+/// - `build_selector_ctl`: add, modify or remove the fd to watch
+/// - `build_selector_wait`: wait for the events and write them to a buffer
+///   (an array of `epoll_event` or `kevent`)
+/// - `build_selector_event`: read the fd and the events of an item of the
+///   buffer
+///
+/// Events are given and returned as a bitmask of `READABLE` and `WRITABLE`.
+/// The selector is not available on Windows (the operations just fail).
+/// All the operations return a negative number on failure.
+use crate::code_gen::*;
+use crate::platform::Platform;
+use inkwell::values::{IntValue, PointerValue};
+use inkwell::IntPredicate;
+
+const READABLE: u64 = 1;
+const WRITABLE: u64 = 2;
+
+/// Operation of `build_selector_ctl` to remove the fd (the operations are
+/// the same as `EPOLL_CTL_ADD` (1), `EPOLL_CTL_DEL` (2) and `EPOLL_CTL_MOD` (3))
+const CTL_DEL: u64 = 2;
+
+// epoll
+const EPOLLIN: u64 = 0x1;
+const EPOLLOUT: u64 = 0x4;
+const EPOLLERR: u64 = 0x8;
+const EPOLLHUP: u64 = 0x10;
+
+// kqueue
+const EVFILT_READ: u64 = -1i64 as u64;
+const EVFILT_WRITE: u64 = -2i64 as u64;
+const EV_ADD: u64 = 0x1;
+const EV_DELETE: u64 = 0x2;
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_selector_declares(&self) {
+        let i8ptr = self.i8ptr_type.into();
+        let i32 = self.i32_type.into();
+        if Platform::host() == Platform::Windows {
+            // int _pipe(int *fds, unsigned int size, int mode)
+            let fn_type = self.i32_type.fn_type(&[i8ptr, i32, i32], false);
+            self.module.add_function("_pipe", fn_type, None);
+            let fn_type = self.i32_type.fn_type(&[i32, i8ptr, i32], false);
+            self.module.add_function("_read", fn_type, None);
+            self.module.add_function("_write", fn_type, None);
+            let fn_type = self.i32_type.fn_type(&[i32], false);
+            self.module.add_function("_close", fn_type, None);
+            return;
+        }
+
+        let i64 = self.i64_type.into();
+        let fn_type = self.i32_type.fn_type(&[i8ptr], false);
+        self.module.add_function("pipe", fn_type, None);
+        let fn_type = self.i64_type.fn_type(&[i32, i8ptr, i64], false);
+        self.module.add_function("read", fn_type, None);
+        self.module.add_function("write", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i32], false);
+        self.module.add_function("close", fn_type, None);
+        if Platform::host() == Platform::MacOS {
+            let fn_type = self.i32_type.fn_type(&[], false);
+            self.module.add_function("kqueue", fn_type, None);
+            let fn_type = self
+                .i32_type
+                .fn_type(&[i32, i8ptr, i32, i8ptr, i32, i8ptr], false);
+            self.module.add_function("kevent", fn_type, None);
+        } else {
+            let fn_type = self.i32_type.fn_type(&[i32], false);
+            self.module.add_function("epoll_create1", fn_type, None);
+            let fn_type = self.i32_type.fn_type(&[i32, i32, i32, i8ptr], false);
+            self.module.add_function("epoll_ctl", fn_type, None);
+            let fn_type = self.i32_type.fn_type(&[i32, i8ptr, i32, i32], false);
+            self.module.add_function("epoll_wait", fn_type, None);
+        }
+    }
+
+    /// Create a pipe and write the fds of the both ends to `fds` (`int[2]`)
+    pub fn build_pipe(&self, fds: PointerValue<'ictx>) -> IntValue<'ictx> {
+        let fds = self.builder.build_bitcast(fds, self.i8ptr_type, "fds");
+        if Platform::host() == Platform::Windows {
+            // O_BINARY
+            let mode = self.i32_type.const_int(0x8000, false);
+            let size = self.i32_type.const_int(4096, false);
+            self.call_i32("_pipe", &[fds, size.into(), mode.into()])
+        } else {
+            self.call_i32("pipe", &[fds])
+        }
+    }
+
+    /// Call read(2) or write(2). Returns the number of the bytes (i32)
+    pub fn build_fd_io(
+        &self,
+        name: &str,
+        fd: IntValue<'ictx>,
+        buf: PointerValue<'ictx>,
+        n_bytes: IntValue<'ictx>,
+    ) -> IntValue<'ictx> {
+        if Platform::host() == Platform::Windows {
+            return self.call_i32(
+                &format!("_{}", name),
+                &[fd.into(), buf.into(), n_bytes.into()],
+            );
+        }
+        let n_bytes = self
+            .builder
+            .build_int_s_extend(n_bytes, self.i64_type, "n_bytes");
+        let func = self.get_llvm_func(name);
+        let result = self
+            .builder
+            .build_call(func, &[fd.into(), buf.into(), n_bytes.into()], "result")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        self.builder
+            .build_int_truncate(result, self.i32_type, "result")
+    }
+
+    pub fn build_fd_close(&self, fd: IntValue<'ictx>) -> IntValue<'ictx> {
+        let name = if Platform::host() == Platform::Windows {
+            "_close"
+        } else {
+            "close"
+        };
+        self.call_i32(name, &[fd.into()])
+    }
+
+    pub fn build_selector_new(&self) -> IntValue<'ictx> {
+        match Platform::host() {
+            Platform::Linux => {
+                self.call_i32("epoll_create1", &[self.i32_type.const_int(0, false).into()])
+            }
+            Platform::MacOS => self.call_i32("kqueue", &[]),
+            Platform::Windows => self.failure(),
+        }
+    }
+
+    /// Add (`op` is 1), remove (2) or modify (3) `fd` to be watched for
+    /// `events`
+    pub fn build_selector_ctl(
+        &self,
+        sel: IntValue<'ictx>,
+        op: IntValue<'ictx>,
+        fd: IntValue<'ictx>,
+        events: IntValue<'ictx>,
+    ) -> IntValue<'ictx> {
+        match Platform::host() {
+            Platform::Linux => {
+                // READABLE => EPOLLIN, WRITABLE => EPOLLOUT
+                let readable = self.bits(events, READABLE);
+                let writable = self.builder.build_left_shift(
+                    self.bits(events, WRITABLE),
+                    self.i32_type.const_int(1, false),
+                    "writable",
+                );
+                let mask = self.builder.build_or(readable, writable, "mask");
+                let ev = self.builder.build_alloca(self.epoll_event_type(), "ev");
+                let events_ptr = self.builder.build_struct_gep(ev, 0, "events").unwrap();
+                self.builder.build_store(events_ptr, mask);
+                let data_ptr = self.builder.build_struct_gep(ev, 1, "data").unwrap();
+                let data = self.builder.build_int_z_extend(fd, self.i64_type, "data");
+                self.builder.build_store(data_ptr, data);
+                let ev = self.builder.build_bitcast(ev, self.i8ptr_type, "ev_i8");
+                self.call_i32("epoll_ctl", &[sel.into(), op.into(), fd.into(), ev])
+            }
+            Platform::MacOS => {
+                // Set the filter for each event (EV_ADD if the bit is set,
+                // EV_DELETE if not). Errors of EV_DELETE are ignored since
+                // the filter may not have been added
+                let is_del = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    op,
+                    self.i32_type.const_int(CTL_DEL, false),
+                    "is_del",
+                );
+                let is_del = self.builder.build_int_z_extend(is_del, self.i32_type, "");
+                let keep =
+                    self.builder
+                        .build_xor(is_del, self.i32_type.const_int(1, false), "keep");
+                let read_bit = self
+                    .builder
+                    .build_and(self.bits(events, READABLE), keep, "");
+                let write_bit = self.builder.build_right_shift(
+                    self.bits(events, WRITABLE),
+                    self.i32_type.const_int(1, false),
+                    false,
+                    "",
+                );
+                let write_bit = self.builder.build_and(write_bit, keep, "");
+                let r1 = self.build_kevent_change(sel, fd, EVFILT_READ, read_bit);
+                let r2 = self.build_kevent_change(sel, fd, EVFILT_WRITE, write_bit);
+                let r1 = self.builder.build_int_mul(r1, read_bit, "");
+                let r2 = self.builder.build_int_mul(r2, write_bit, "");
+                self.builder.build_int_add(r1, r2, "result")
+            }
+            Platform::Windows => self.failure(),
+        }
+    }
+
+    /// Call `kevent` to add (`add` is 1) or delete (`add` is 0) the filter
+    fn build_kevent_change(
+        &self,
+        kq: IntValue<'ictx>,
+        fd: IntValue<'ictx>,
+        filter: u64,
+        add: IntValue<'ictx>,
+    ) -> IntValue<'ictx> {
+        // EV_ADD (1) or EV_DELETE (2)
+        let flags = self.builder.build_int_sub(
+            self.i32_type.const_int(EV_DELETE, false),
+            self.builder
+                .build_int_mul(add, self.i32_type.const_int(EV_DELETE - EV_ADD, false), ""),
+            "flags",
+        );
+        let i16_type = self.context.i16_type();
+        let flags = self.builder.build_int_truncate(flags, i16_type, "flags16");
+        let kev = self.builder.build_alloca(self.kevent_type(), "kev");
+        let ident = self.builder.build_int_z_extend(fd, self.i64_type, "ident");
+        let fields: [BasicValueEnum<'ictx>; 6] = [
+            ident.into(),
+            i16_type.const_int(filter, true).into(),
+            flags.into(),
+            self.i32_type.const_int(0, false).into(),
+            self.i64_type.const_int(0, false).into(),
+            self.i8ptr_type.const_null().into(),
+        ];
+        for (i, value) in fields.iter().enumerate() {
+            let ptr = self.builder.build_struct_gep(kev, i as u32, "").unwrap();
+            self.builder.build_store(ptr, *value);
+        }
+        let kev = self.builder.build_bitcast(kev, self.i8ptr_type, "kev_i8");
+        let null = self.i8ptr_type.const_null();
+        let zero = self.i32_type.const_int(0, false);
+        let one = self.i32_type.const_int(1, false);
+        self.call_i32(
+            "kevent",
+            &[
+                kq.into(),
+                kev,
+                one.into(),
+                null.into(),
+                zero.into(),
+                null.into(),
+            ],
+        )
+    }
+
+    /// Wait (for `timeout_ms`, or forever if negative) for the events and
+    /// write at most `max` of them to `buf`. Returns the number of the
+    /// events
+    ///
+    /// Must be called at the end of a function since this may generate
+    /// basic blocks
+    pub fn build_selector_wait(
+        &self,
+        function: inkwell::values::FunctionValue<'ictx>,
+        sel: IntValue<'ictx>,
+        buf: PointerValue<'ictx>,
+        max: IntValue<'ictx>,
+        timeout_ms: IntValue<'ictx>,
+    ) -> IntValue<'ictx> {
+        let buf = self.builder.build_bitcast(buf, self.i8ptr_type, "buf");
+        match Platform::host() {
+            Platform::Linux => self.call_i32(
+                "epoll_wait",
+                &[sel.into(), buf, max.into(), timeout_ms.into()],
+            ),
+            Platform::MacOS => {
+                // Pass NULL as the timeout to wait forever
+                let ts = self.builder.build_alloca(self.timespec_type(), "ts");
+                let zero = self.i32_type.const_int(0, false);
+                let is_forever = self.builder.build_int_compare(
+                    IntPredicate::SLT,
+                    timeout_ms,
+                    zero,
+                    "is_forever",
+                );
+                let thousand = self.i32_type.const_int(1000, false);
+                let sec = self
+                    .builder
+                    .build_int_signed_div(timeout_ms, thousand, "sec");
+                let ms = self
+                    .builder
+                    .build_int_signed_rem(timeout_ms, thousand, "ms");
+                let nsec = self.builder.build_int_mul(
+                    ms,
+                    self.i32_type.const_int(1_000_000, false),
+                    "nsec",
+                );
+                let sec_ptr = self.builder.build_struct_gep(ts, 0, "sec_ptr").unwrap();
+                let sec = self.builder.build_int_s_extend(sec, self.i64_type, "sec");
+                self.builder.build_store(sec_ptr, sec);
+                let nsec_ptr = self.builder.build_struct_gep(ts, 1, "nsec_ptr").unwrap();
+                let nsec = self.builder.build_int_s_extend(nsec, self.i64_type, "nsec");
+                self.builder.build_store(nsec_ptr, nsec);
+                let ts = self.builder.build_bitcast(ts, self.i8ptr_type, "ts_i8");
+
+                let block = self.builder.get_insert_block().unwrap();
+                let forever_block = self.context.append_basic_block(function, "Forever");
+                let end_block = self.context.append_basic_block(function, "WaitEvents");
+                self.builder
+                    .build_conditional_branch(is_forever, forever_block, end_block);
+                self.builder.position_at_end(forever_block);
+                self.builder.build_unconditional_branch(end_block);
+                self.builder.position_at_end(end_block);
+                let phi = self.builder.build_phi(self.i8ptr_type, "timeout");
+                phi.add_incoming(&[(&ts, block), (&self.i8ptr_type.const_null(), forever_block)]);
+                let null = self.i8ptr_type.const_null();
+                self.call_i32(
+                    "kevent",
+                    &[
+                        sel.into(),
+                        null.into(),
+                        zero.into(),
+                        buf,
+                        max.into(),
+                        phi.as_basic_value(),
+                    ],
+                )
+            }
+            Platform::Windows => self.failure(),
+        }
+    }
+
+    /// Return the fd and the events (`READABLE`, `WRITABLE`) of the `idx`th
+    /// event in `buf`
+    pub fn build_selector_event(
+        &self,
+        buf: PointerValue<'ictx>,
+        idx: IntValue<'ictx>,
+    ) -> (IntValue<'ictx>, IntValue<'ictx>) {
+        match Platform::host() {
+            Platform::Linux => {
+                let ty = self.epoll_event_type();
+                let ev = self.event_ptr(buf, ty, idx);
+                let events_ptr = self.builder.build_struct_gep(ev, 0, "events").unwrap();
+                let events = self
+                    .builder
+                    .build_load(events_ptr, "events")
+                    .into_int_value();
+                let data_ptr = self.builder.build_struct_gep(ev, 1, "data").unwrap();
+                let data = self.builder.build_load(data_ptr, "data").into_int_value();
+                let fd = self.builder.build_int_truncate(data, self.i32_type, "fd");
+                // Errors and hangups are returned as READABLE so that the
+                // next read(2) returns them
+                let readable = self.bits(events, EPOLLIN | EPOLLERR | EPOLLHUP);
+                let readable = self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    readable,
+                    self.i32_type.const_int(0, false),
+                    "readable",
+                );
+                let readable = self.builder.build_int_z_extend(readable, self.i32_type, "");
+                let writable = self.builder.build_right_shift(
+                    self.bits(events, EPOLLOUT),
+                    self.i32_type.const_int(1, false),
+                    false,
+                    "writable",
+                );
+                (fd, self.builder.build_or(readable, writable, "result"))
+            }
+            Platform::MacOS => {
+                let ty = self.kevent_type();
+                let kev = self.event_ptr(buf, ty, idx);
+                let ident_ptr = self.builder.build_struct_gep(kev, 0, "ident").unwrap();
+                let ident = self.builder.build_load(ident_ptr, "ident").into_int_value();
+                let fd = self.builder.build_int_truncate(ident, self.i32_type, "fd");
+                // EVFILT_READ (-1) => READABLE, EVFILT_WRITE (-2) => WRITABLE
+                let filter_ptr = self.builder.build_struct_gep(kev, 1, "filter").unwrap();
+                let filter = self
+                    .builder
+                    .build_load(filter_ptr, "filter")
+                    .into_int_value();
+                let filter = self
+                    .builder
+                    .build_int_s_extend(filter, self.i32_type, "filter");
+                (fd, self.builder.build_int_neg(filter, "events"))
+            }
+            Platform::Windows => (self.failure(), self.failure()),
+        }
+    }
+
+    /// Number of the bytes of an item of the buffer of `build_selector_wait`
+    pub fn selector_event_size(&self) -> IntValue<'ictx> {
+        let size = match Platform::host() {
+            Platform::Linux => self.epoll_event_type().size_of(),
+            Platform::MacOS => self.kevent_type().size_of(),
+            Platform::Windows => None,
+        };
+        match size {
+            Some(size) => self.builder.build_int_truncate(size, self.i32_type, "size"),
+            None => self.i32_type.const_int(1, false),
+        }
+    }
+
+    /// `struct epoll_event` (which is packed on x86_64)
+    fn epoll_event_type(&self) -> inkwell::types::StructType<'ictx> {
+        self.context.struct_type(
+            &[self.i32_type.into(), self.i64_type.into()],
+            cfg!(target_arch = "x86_64"),
+        )
+    }
+
+    /// `struct kevent`
+    fn kevent_type(&self) -> inkwell::types::StructType<'ictx> {
+        let i16_type = self.context.i16_type();
+        self.context.struct_type(
+            &[
+                self.i64_type.into(),
+                i16_type.into(),
+                i16_type.into(),
+                self.i32_type.into(),
+                self.i64_type.into(),
+                self.i8ptr_type.into(),
+            ],
+            false,
+        )
+    }
+
+    /// `struct timespec` of macOS
+    fn timespec_type(&self) -> inkwell::types::StructType<'ictx> {
+        self.context
+            .struct_type(&[self.i64_type.into(), self.i64_type.into()], false)
+    }
+
+    fn event_ptr(
+        &self,
+        buf: PointerValue<'ictx>,
+        ty: inkwell::types::StructType<'ictx>,
+        idx: IntValue<'ictx>,
+    ) -> PointerValue<'ictx> {
+        let events =
+            self.builder
+                .build_pointer_cast(buf, ty.ptr_type(AddressSpace::Generic), "events");
+        unsafe { self.builder.build_in_bounds_gep(events, &[idx], "event") }
+    }
+
+    /// `value & mask`
+    pub(super) fn bits(&self, value: IntValue<'ictx>, mask: u64) -> IntValue<'ictx> {
+        self.builder
+            .build_and(value, self.i32_type.const_int(mask, false), "")
+    }
+
+    /// The result of the operations not available on the platform
+    pub(super) fn failure(&self) -> IntValue<'ictx> {
+        self.i32_type.const_int(-1i64 as u64, true)
+    }
+
+    pub(super) fn call_i32(&self, name: &str, args: &[BasicValueEnum<'ictx>]) -> IntValue<'ictx> {
+        let func = self.get_llvm_func(name);
+        self.builder
+            .build_call(func, args, "result")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value()
+    }
+}
@@ -0,0 +1,299 @@
+/// TCP sockets (`Shiika::Internal::Socket`)
+///
+/// The BSD socket functions are declared here and called with the
+/// `struct addrinfo` returned by getaddrinfo(3), so that builtin/socket.sk
+/// only deals with fds (which are read, written and closed like the other
+/// fds):
+///
+/// - `build_tcp_open`: create a socket connected to the address (client)
+///   or listening on it (server)
+/// - `build_tcp_accept`: accept a connection to a listening socket
+/// - `build_tcp_local_port`: get the port which a socket is bound to
+///
+/// Only IPv4 is supported. Sockets are not available on Windows (the
+/// operations just fail). All the operations return a negative number on
+/// failure.
+use crate::code_gen::*;
+use crate::platform::Platform;
+use inkwell::values::{BasicValueEnum, IntValue, PointerValue};
+use inkwell::IntPredicate;
+
+const AF_INET: u64 = 2;
+const SOCK_STREAM: u64 = 1;
+const AI_PASSIVE: u64 = 1;
+/// Maximum number of the pending connections of a listening socket
+const BACKLOG: u64 = 128;
+
+/// Size of `struct addrinfo` (large enough on both platforms)
+const ADDRINFO_SIZE: u32 = 64;
+// Offsets of the fields of `struct addrinfo`
+const AI_FLAGS: u64 = 0;
+const AI_FAMILY: u64 = 4;
+const AI_SOCKTYPE: u64 = 8;
+const AI_PROTOCOL: u64 = 12;
+const AI_ADDRLEN: u64 = 16;
+
+/// Size of `struct sockaddr_in`
+const SOCKADDR_IN_SIZE: u64 = 16;
+/// Offset of `sin_port` (in the network byte order) of `struct sockaddr_in`
+const SIN_PORT: u64 = 2;
+
+impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    pub(super) fn gen_socket_declares(&self) {
+        if Platform::host() == Platform::Windows {
+            return;
+        }
+        let i8ptr = self.i8ptr_type.into();
+        let i32 = self.i32_type.into();
+        let fn_type = self.i32_type.fn_type(&[i8ptr, i8ptr, i8ptr, i8ptr], false);
+        self.module.add_function("getaddrinfo", fn_type, None);
+        let fn_type = self.void_type.fn_type(&[i8ptr], false);
+        self.module.add_function("freeaddrinfo", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i32, i32, i32], false);
+        self.module.add_function("socket", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i32, i8ptr, i32], false);
+        self.module.add_function("connect", fn_type, None);
+        self.module.add_function("bind", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i32, i32], false);
+        self.module.add_function("listen", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i32, i8ptr, i8ptr], false);
+        self.module.add_function("accept", fn_type, None);
+        self.module.add_function("getsockname", fn_type, None);
+        let fn_type = self.i32_type.fn_type(&[i32, i32, i32, i8ptr, i32], false);
+        self.module.add_function("setsockopt", fn_type, None);
+    }
+
+    /// Resolve `host` and `port` (C strings) and create a socket connected
+    /// to the address, or listening on it if `server` is true. Returns the
+    /// fd
+    pub fn build_tcp_open(
+        &self,
+        host: BasicValueEnum<'ictx>,
+        port: BasicValueEnum<'ictx>,
+        server: bool,
+    ) -> IntValue<'ictx> {
+        let ai_addr = match Platform::host() {
+            Platform::Linux => 24,
+            Platform::MacOS => 32,
+            Platform::Windows => return self.failure(),
+        };
+        let function = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let start_block = self.builder.get_insert_block().unwrap();
+        let open_block = self.context.append_basic_block(function, "TcpOpen");
+        let end_block = self.context.append_basic_block(function, "TcpOpenEnd");
+
+        // hints
+        let hints_type = self.i8_type.array_type(ADDRINFO_SIZE);
+        let hints = self.builder.build_alloca(hints_type, "hints");
+        self.builder.build_store(hints, hints_type.const_zero());
+        let hints = self
+            .builder
+            .build_bitcast(hints, self.i8ptr_type, "hints_i8");
+        let flags = if server { AI_PASSIVE } else { 0 };
+        self.store_i32_at(hints, AI_FLAGS, self.i32_type.const_int(flags, false));
+        self.store_i32_at(hints, AI_FAMILY, self.i32_type.const_int(AF_INET, false));
+        self.store_i32_at(
+            hints,
+            AI_SOCKTYPE,
+            self.i32_type.const_int(SOCK_STREAM, false),
+        );
+        let res = self.builder.build_alloca(self.i8ptr_type, "res");
+        let r = self.call_i32(
+            "getaddrinfo",
+            &[
+                host,
+                port,
+                hints,
+                self.builder.build_bitcast(res, self.i8ptr_type, "res_i8"),
+            ],
+        );
+        let resolved = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            r,
+            self.i32_type.const_int(0, false),
+            "resolved",
+        );
+        self.builder
+            .build_conditional_branch(resolved, open_block, end_block);
+
+        // TcpOpen:
+        self.builder.position_at_end(open_block);
+        let info = self.builder.build_load(res, "info");
+        let fd = self.call_i32(
+            "socket",
+            &[
+                self.load_i32_at(info, AI_FAMILY).into(),
+                self.load_i32_at(info, AI_SOCKTYPE).into(),
+                self.load_i32_at(info, AI_PROTOCOL).into(),
+            ],
+        );
+        let addr_ptr = self.byte_offset(info, ai_addr);
+        let addr_ptr = self.builder.build_bitcast(
+            addr_ptr,
+            self.i8ptr_type.ptr_type(AddressSpace::Generic),
+            "addr_ptr",
+        );
+        let addr = self
+            .builder
+            .build_load(addr_ptr.into_pointer_value(), "addr");
+        let addrlen = self.load_i32_at(info, AI_ADDRLEN);
+        let result = if server {
+            self.build_tcp_bind(fd, addr, addrlen)
+        } else {
+            self.call_i32("connect", &[fd.into(), addr, addrlen.into()])
+        };
+        self.builder
+            .build_call(self.get_llvm_func("freeaddrinfo"), &[info], "");
+        // Close the socket if it is created but not connected (or bound)
+        let created = self.builder.build_int_compare(
+            IntPredicate::SGE,
+            fd,
+            self.i32_type.const_int(0, false),
+            "created",
+        );
+        let succeeded = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            result,
+            self.i32_type.const_int(0, false),
+            "succeeded",
+        );
+        let ok = self.builder.build_and(created, succeeded, "ok");
+        let to_close = self
+            .builder
+            .build_select(ok, self.failure(), fd, "to_close")
+            .into_int_value();
+        self.build_fd_close(to_close);
+        let opened = self
+            .builder
+            .build_select(ok, fd, self.failure(), "opened")
+            .into_int_value();
+        self.builder.build_unconditional_branch(end_block);
+
+        // TcpOpenEnd:
+        self.builder.position_at_end(end_block);
+        let phi = self.builder.build_phi(self.i32_type, "fd");
+        phi.add_incoming(&[(&self.failure(), start_block), (&opened, open_block)]);
+        phi.as_basic_value().into_int_value()
+    }
+
+    /// Accept a connection. Returns the fd of the connected socket
+    pub fn build_tcp_accept(&self, fd: IntValue<'ictx>) -> IntValue<'ictx> {
+        if Platform::host() == Platform::Windows {
+            return self.failure();
+        }
+        let null = self.i8ptr_type.const_null();
+        self.call_i32("accept", &[fd.into(), null.into(), null.into()])
+    }
+
+    /// Returns the port which the socket is bound to (eg. the one chosen by
+    /// the OS for port 0)
+    pub fn build_tcp_local_port(&self, fd: IntValue<'ictx>) -> IntValue<'ictx> {
+        if Platform::host() == Platform::Windows {
+            return self.failure();
+        }
+        let addr_type = self.i8_type.array_type(SOCKADDR_IN_SIZE as u32);
+        let addr = self.builder.build_alloca(addr_type, "addr");
+        let addr = self.builder.build_bitcast(addr, self.i8ptr_type, "addr_i8");
+        let len = self.builder.build_alloca(self.i32_type, "len");
+        self.builder
+            .build_store(len, self.i32_type.const_int(SOCKADDR_IN_SIZE, false));
+        let len = self.builder.build_bitcast(len, self.i8ptr_type, "len_i8");
+        let r = self.call_i32("getsockname", &[fd.into(), addr, len]);
+        let high = self.load_u8_at(addr, SIN_PORT);
+        let low = self.load_u8_at(addr, SIN_PORT + 1);
+        let high = self
+            .builder
+            .build_left_shift(high, self.i32_type.const_int(8, false), "high");
+        let port = self.builder.build_or(high, low, "port");
+        let ok = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            r,
+            self.i32_type.const_int(0, false),
+            "ok",
+        );
+        self.builder
+            .build_select(ok, port, self.failure(), "local_port")
+            .into_int_value()
+    }
+
+    /// Set SO_REUSEADDR (so that the port can be reused soon after the
+    /// server exits), bind and listen
+    fn build_tcp_bind(
+        &self,
+        fd: IntValue<'ictx>,
+        addr: BasicValueEnum<'ictx>,
+        addrlen: IntValue<'ictx>,
+    ) -> IntValue<'ictx> {
+        let (sol_socket, so_reuseaddr) = if Platform::host() == Platform::MacOS {
+            (0xffff, 0x4)
+        } else {
+            (1, 2)
+        };
+        let one = self.builder.build_alloca(self.i32_type, "one");
+        self.builder
+            .build_store(one, self.i32_type.const_int(1, false));
+        let one = self.builder.build_bitcast(one, self.i8ptr_type, "one_i8");
+        self.call_i32(
+            "setsockopt",
+            &[
+                fd.into(),
+                self.i32_type.const_int(sol_socket, false).into(),
+                self.i32_type.const_int(so_reuseaddr, false).into(),
+                one,
+                self.i32_type.const_int(4, false).into(),
+            ],
+        );
+        let bound = self.call_i32("bind", &[fd.into(), addr, addrlen.into()]);
+        let listening = self.call_i32(
+            "listen",
+            &[fd.into(), self.i32_type.const_int(BACKLOG, false).into()],
+        );
+        // 0 if both succeeded
+        self.builder.build_or(bound, listening, "result")
+    }
+
+    /// Returns `ptr + offset` (`ptr` is an `i8*`)
+    fn byte_offset(&self, ptr: BasicValueEnum<'ictx>, offset: u64) -> PointerValue<'ictx> {
+        unsafe {
+            self.builder.build_in_bounds_gep(
+                ptr.into_pointer_value(),
+                &[self.i64_type.const_int(offset, false)],
+                "",
+            )
+        }
+    }
+
+    fn load_i32_at(&self, ptr: BasicValueEnum<'ictx>, offset: u64) -> IntValue<'ictx> {
+        let item = self.builder.build_bitcast(
+            self.byte_offset(ptr, offset),
+            self.i32_type.ptr_type(AddressSpace::Generic),
+            "",
+        );
+        self.builder
+            .build_load(item.into_pointer_value(), "")
+            .into_int_value()
+    }
+
+    /// Load the byte at `offset` as an i32
+    fn load_u8_at(&self, ptr: BasicValueEnum<'ictx>, offset: u64) -> IntValue<'ictx> {
+        let byte = self
+            .builder
+            .build_load(self.byte_offset(ptr, offset), "")
+            .into_int_value();
+        self.builder.build_int_z_extend(byte, self.i32_type, "")
+    }
+
+    fn store_i32_at(&self, ptr: BasicValueEnum<'ictx>, offset: u64, value: IntValue<'ictx>) {
+        let item = self.builder.build_bitcast(
+            self.byte_offset(ptr, offset),
+            self.i32_type.ptr_type(AddressSpace::Generic),
+            "",
+        );
+        self.builder.build_store(item.into_pointer_value(), value);
+    }
+}
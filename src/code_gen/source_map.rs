@@ -0,0 +1,59 @@
+/// Comments which tell where the LLVM functions come from
+/// (`shiika compile --emit llvm-ir`)
+///
+/// Each function generated from a method of the program is preceded by
+/// the line of its definition:
+///
+/// ```text
+/// ; a.sk:12: def foo(x: Int) -> Int
+/// define %Int* @"A#foo"(%A* %self, %Int* %x) {
+/// ```
+///
+/// The HIR keeps the spans of the definitions only (see
+/// hir/def_spans.rs), not the ones of the expressions, so the lines in a
+/// method are not shown. The functions of the builtin library, the
+/// lambdas and `user_main` (the toplevel) are not annotated.
+use crate::parser::lexer::{Cursor, Span};
+use std::collections::HashMap;
+
+/// Insert the comments into `ir`. `def_spans` is `Hir::def_spans`, `src`
+/// is the source of the program (in `path`) and `base` is the end of the
+/// builtin library which precedes it in the spans
+pub fn annotate_llvm_ir(
+    ir: &str,
+    def_spans: &HashMap<String, Span>,
+    path: &str,
+    src: &str,
+    base: &Cursor,
+) -> String {
+    let src_lines = src.lines().collect::<Vec<_>>();
+    let mut annotated = String::with_capacity(ir.len());
+    for line in ir.lines() {
+        if line.starts_with("define ") {
+            let span = defined_function(line)
+                .and_then(|name| def_spans.get(name))
+                .and_then(|span| span.relative_to(base));
+            if let Some(span) = span {
+                let n = span.begin.line();
+                let snippet = src_lines.get(n).map_or("", |s| s.trim());
+                annotated.push_str(&format!("; {}:{}: {}\n", path, n + 1, snippet));
+            }
+        }
+        annotated.push_str(line);
+        annotated.push('\n');
+    }
+    annotated
+}
+
+/// The name of the function defined by the line (eg. `A#foo` for
+/// `define %Int* @"A#foo"(...`)
+fn defined_function(line: &str) -> Option<&str> {
+    let rest = &line[line.find('@')? + 1..];
+    if rest.starts_with('"') {
+        let rest = &rest[1..];
+        Some(&rest[..rest.find('"')?])
+    } else {
+        let end = rest.find('(')?;
+        Some(&rest[..end])
+    }
+}
@@ -1,10 +1,200 @@
 /// Provides utility functions used by code_gen/*.rs
 /// (some are also used by corelib/*.rs)
 use crate::code_gen::*;
+use crate::ty;
 use inkwell::types::*;
 use inkwell::AddressSpace;
 
+/// Size of the header of Shiika objects (in bytes)
+///
+/// Shiika objects have no vtable, so the header is the only way to know the
+/// class of an object at runtime; the dispatchers of the abstract methods
+/// (and of `hash`/`==`) switch on it. It is placed before the object so that
+/// the offsets of the ivars (and the pointers passed to the runtime functions)
+/// are unchanged, and it is 8 bytes so that the object stays aligned for
+/// pointers and `Float`s (GC_malloc returns memory aligned to 8 bytes at
+/// least.) See `tests/layout_test.rs`.
+pub(super) const OBJ_HEADER_SIZE: u64 = 8;
+
+/// The bit of the header which is set when the object is frozen (see
+/// `Object#freeze`)
+const OBJ_FROZEN_FLAG: u64 = 1 << 63;
+
 impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
+    /// Create a String by copying `bytesize` (i64) bytes from `ptr`
+    pub fn build_string_copy(
+        &self,
+        ptr: inkwell::values::PointerValue<'ictx>,
+        bytesize: inkwell::values::IntValue<'ictx>,
+    ) -> inkwell::values::BasicValueEnum<'ictx> {
+        // GC_malloc fills the memory with zeros, so the last byte is null
+        let size = self
+            .builder
+            .build_int_add(bytesize, self.i64_type.const_int(1, false), "size");
+        let mem = self
+            .builder
+            .build_call(self.get_llvm_func("GC_malloc"), &[size.into()], "mem")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        self.builder.build_call(
+            self.get_llvm_func("llvm.memcpy.p0i8.p0i8.i64"),
+            &[
+                mem,
+                ptr.into(),
+                bytesize.into(),
+                self.i32_type.const_int(0, false).into(),
+                self.i1_type.const_int(0, false).into(),
+            ],
+            "",
+        );
+
+        let sk_str = self.allocate_sk_obj(&class_fullname("String"), "str");
+        self.build_ivar_store(&sk_str, 0, mem, "@ptr");
+        let bytesize = self
+            .builder
+            .build_int_truncate(bytesize, self.i32_type, "bytesize");
+        let sk_int = self.box_int(&bytesize);
+        self.build_ivar_store(&sk_str, 1, sk_int, "@bytesize");
+        sk_str
+    }
+
+    /// Create a String by formatting `value` (an i32 or a double) with
+    /// `spec`, a Shiika String of one printf conversion like `%05d` (which
+    /// must be checked by the caller.) The length is measured by calling
+    /// `snprintf` without a buffer first
+    pub fn build_snprintf<'a>(
+        &'a self,
+        spec: inkwell::values::BasicValueEnum<'a>,
+        value: inkwell::values::BasicValueEnum<'a>,
+    ) -> inkwell::values::BasicValueEnum<'a> {
+        let snprintf = self.get_llvm_func("snprintf");
+        let spec_ptr = self.build_ivar_load(spec, 0, "@ptr");
+        let null = self.i8ptr_type.const_null();
+        let zero = self.i64_type.const_int(0, false);
+        let len = self
+            .builder
+            .build_call(
+                snprintf,
+                &[null.into(), zero.into(), spec_ptr, value],
+                "len",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let len = self.builder.build_int_s_extend(len, self.i64_type, "len");
+        let size = self
+            .builder
+            .build_int_add(len, self.i64_type.const_int(1, false), "size");
+        let mem = self
+            .builder
+            .build_call(self.get_llvm_func("GC_malloc"), &[size.into()], "mem")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        self.builder
+            .build_call(snprintf, &[mem, size.into(), spec_ptr, value], "");
+
+        let sk_str = self.allocate_sk_obj(&class_fullname("String"), "str");
+        self.build_ivar_store(&sk_str, 0, mem, "@ptr");
+        let bytesize = self
+            .builder
+            .build_int_truncate(len, self.i32_type, "bytesize");
+        let sk_int = self.box_int(&bytesize);
+        self.build_ivar_store(&sk_str, 1, sk_int, "@bytesize");
+        sk_str
+    }
+
+    /// Create a String of the decimal digits of `n` (i32). The digits are
+    /// written backwards into a buffer on the stack and the String is
+    /// made by copying it once
+    pub fn build_int_to_s(
+        &self,
+        n: inkwell::values::IntValue<'ictx>,
+        function: &inkwell::values::FunctionValue,
+    ) -> inkwell::values::BasicValueEnum<'ictx> {
+        // `-2147483648` has 11 bytes
+        let buf_len = 11;
+        let buf_type = self.i8_type.array_type(buf_len);
+        let buf = self.builder.build_alloca(buf_type, "buf");
+        let buf = self
+            .builder
+            .build_bitcast(buf, self.i8ptr_type, "buf_i8")
+            .into_pointer_value();
+        // Use i64 so that the absolute value of `i32::MIN` does not overflow
+        let wide = self.builder.build_int_s_extend(n, self.i64_type, "wide");
+        let is_neg = self.builder.build_int_compare(
+            inkwell::IntPredicate::SLT,
+            wide,
+            self.i64_type.const_int(0, false),
+            "is_neg",
+        );
+        let negated = self.builder.build_int_neg(wide, "negated");
+        let abs = self
+            .builder
+            .build_select(is_neg, negated, wide, "abs")
+            .into_int_value();
+        let ten = self.i64_type.const_int(10, false);
+        let entry_block = self.builder.get_insert_block().unwrap();
+        let loop_block = self.context.append_basic_block(*function, "Digits");
+        let end_block = self.context.append_basic_block(*function, "DigitsEnd");
+        self.builder.build_unconditional_branch(loop_block);
+
+        // Digits: (put the last digit and divide by 10 until it is 0)
+        self.builder.position_at_end(loop_block);
+        let value = self.builder.build_phi(self.i64_type, "value");
+        let pos = self.builder.build_phi(self.i64_type, "pos");
+        let value_v = value.as_basic_value().into_int_value();
+        let pos_v = pos.as_basic_value().into_int_value();
+        let new_pos =
+            self.builder
+                .build_int_sub(pos_v, self.i64_type.const_int(1, false), "new_pos");
+        let digit = self.builder.build_int_unsigned_rem(value_v, ten, "digit");
+        let digit = self
+            .builder
+            .build_int_truncate(digit, self.i8_type, "digit");
+        let chr =
+            self.builder
+                .build_int_add(digit, self.i8_type.const_int(b'0' as u64, false), "chr");
+        let chr_ptr = unsafe { self.builder.build_gep(buf, &[new_pos], "chr_ptr") };
+        self.builder.build_store(chr_ptr, chr);
+        let rest = self.builder.build_int_unsigned_div(value_v, ten, "rest");
+        value.add_incoming(&[(&abs, entry_block), (&rest, loop_block)]);
+        pos.add_incoming(&[
+            (&self.i64_type.const_int(buf_len as u64, false), entry_block),
+            (&new_pos, loop_block),
+        ]);
+        let done = self.builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            rest,
+            self.i64_type.const_int(0, false),
+            "done",
+        );
+        self.builder
+            .build_conditional_branch(done, end_block, loop_block);
+
+        // DigitsEnd: (put `-` if negative)
+        self.builder.position_at_end(end_block);
+        let minus_pos =
+            self.builder
+                .build_int_sub(new_pos, self.i64_type.const_int(1, false), "minus_pos");
+        let minus_ptr = unsafe { self.builder.build_gep(buf, &[minus_pos], "minus_ptr") };
+        self.builder
+            .build_store(minus_ptr, self.i8_type.const_int(b'-' as u64, false));
+        let start = self
+            .builder
+            .build_select(is_neg, minus_pos, new_pos, "start")
+            .into_int_value();
+        let start_ptr = unsafe { self.builder.build_gep(buf, &[start], "start_ptr") };
+        let bytesize = self.builder.build_int_sub(
+            self.i64_type.const_int(buf_len as u64, false),
+            start,
+            "bytesize",
+        );
+        self.build_string_copy(start_ptr, bytesize)
+    }
+
     pub fn build_ivar_load<'a>(
         &'a self,
         object: inkwell::values::BasicValueEnum<'a>,
@@ -40,7 +230,54 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
         self.builder.build_store(ptr, value);
     }
 
+    /// Store `value` to the ivar after checking that `object` is not frozen
+    /// (exits the program if it is)
+    pub fn build_checked_ivar_store<'a>(
+        &'a self,
+        object: &'a inkwell::values::BasicValueEnum<'a>,
+        idx: usize,
+        value: inkwell::values::BasicValueEnum<'a>,
+        name: &str,
+    ) {
+        let function = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+        let frozen_block = self.context.append_basic_block(function, "Frozen");
+        let store_block = self.context.append_basic_block(function, "Store");
+        let frozen = self.build_frozen_flag_load(*object);
+        self.builder
+            .build_conditional_branch(frozen, frozen_block, store_block);
+
+        // Frozen:
+        self.builder.position_at_end(frozen_block);
+        let msg = format!("[cannot modify @{} of a frozen object]", name);
+        let msg_ptr = self.builder.build_global_string_ptr(&msg, "str");
+        self.builder.build_call(
+            self.get_llvm_func("puts"),
+            &[msg_ptr.as_pointer_value().into()],
+            "",
+        );
+        self.builder
+            .build_call(self.get_llvm_func("shiika_teardown"), &[], "");
+        self.builder.build_call(
+            self.get_llvm_func("exit"),
+            &[self.i32_type.const_int(1, false).into()],
+            "",
+        );
+        self.builder.build_unreachable();
+
+        // Store:
+        self.builder.position_at_end(store_block);
+        self.build_ivar_store(object, idx, value, name);
+    }
+
     /// Generate call of GC_malloc and returns a ptr to Shiika object
+    ///
+    /// Each object is preceded by a header (i64) which holds the class id
+    /// (and `OBJ_FROZEN_FLAG`.)
+    /// The returned pointer points to the object (i.e. just after the header)
     pub fn allocate_sk_obj(
         &self,
         class_fullname: &ClassFullname,
@@ -48,9 +285,11 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     ) -> inkwell::values::BasicValueEnum<'ictx> {
         let object_type = self.llvm_struct_types.get(&class_fullname).unwrap();
         let obj_ptr_type = object_type.ptr_type(AddressSpace::Generic);
-        let size = object_type
+        let obj_size = object_type
             .size_of()
             .expect("[BUG] object_type has no size");
+        let header_size = self.i64_type.const_int(OBJ_HEADER_SIZE, false);
+        let size = self.builder.build_int_add(obj_size, header_size, "size");
 
         // %mem = call i8* @GC_malloc(i64 %size)",
         let func = self.get_llvm_func("GC_malloc");
@@ -59,15 +298,179 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
             .build_call(func, &[size.as_basic_value_enum()], "mem")
             .try_as_basic_value()
             .left()
-            .unwrap();
+            .unwrap()
+            .into_pointer_value();
+
+        // Write the class id to the header
+        let header = self.builder.build_pointer_cast(
+            raw_addr,
+            self.i64_type.ptr_type(AddressSpace::Generic),
+            "header",
+        );
+        self.builder
+            .build_store(header, self.class_id(class_fullname));
+
+        // %foo = bitcast i8* %mem_body to %#{t}*",
+        let body_addr = unsafe {
+            self.builder
+                .build_in_bounds_gep(raw_addr, &[header_size], "mem_body")
+        };
+        self.builder
+            .build_bitcast(body_addr, obj_ptr_type, reg_name)
+    }
+
+    /// Call the llvm function of a lambda via the pointer stored in `fn_obj`
+    /// (an instance of `FnN`)
+    pub fn build_lambda_call<'a>(
+        &'a self,
+        fn_obj: inkwell::values::BasicValueEnum<'a>,
+        mut args: Vec<inkwell::values::BasicValueEnum<'a>>,
+    ) -> inkwell::values::BasicValueEnum<'a> {
+        let fnptr = self.build_ivar_load(fn_obj, 0, "func");
+        let capary = self.build_ivar_load(fn_obj, 1, "captures");
+
+        // Create the type of lambda_xx()
+        let obj_type = self.llvm_type(&ty::raw("Object"));
+        let ary_type = self.llvm_type(&ty::raw("Array"));
+        let mut arg_types = args
+            .iter()
+            .map(|_| obj_type.into())
+            .collect::<Vec<BasicTypeEnum>>();
+        arg_types.push(ary_type.into());
+        let fntype = obj_type.fn_type(&arg_types, false);
+        let fnptype = fntype.ptr_type(AddressSpace::Generic);
+
+        // Cast `fnptr` to that type
+        let func = self
+            .builder
+            .build_bitcast(fnptr, fnptype, "")
+            .into_pointer_value();
+
+        args.push(capary);
+        self.builder
+            .build_call(func, &args, "result")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    /// Return the class id of the class as llvm constant
+    pub fn class_id(&self, class_fullname: &ClassFullname) -> inkwell::values::IntValue<'ictx> {
+        let id = self
+            .class_ids
+            .get(class_fullname)
+            .unwrap_or_else(|| panic!("[BUG] class id of `{}' not found", class_fullname));
+        self.i64_type.const_int(*id, false)
+    }
+
+    /// Load the class id from the header of a Shiika object
+    pub fn build_class_id_load(
+        &self,
+        object: inkwell::values::BasicValueEnum<'ictx>,
+    ) -> inkwell::values::IntValue<'ictx> {
+        let i64ptr_type = self.i64_type.ptr_type(AddressSpace::Generic);
+        let ptr = self
+            .builder
+            .build_pointer_cast(object.into_pointer_value(), i64ptr_type, "obj");
+        let minus_one = self.i64_type.const_int(-1i64 as u64, true);
+        let header = unsafe {
+            self.builder
+                .build_in_bounds_gep(ptr, &[minus_one], "header")
+        };
+        let value = self.builder.build_load(header, "header").into_int_value();
+        let mask = self.i64_type.const_int(!OBJ_FROZEN_FLAG, false);
+        self.builder.build_and(value, mask, "class_id")
+    }
+
+    /// Returns the hash value of the identity of the object (as an i32;
+    /// made from the address, see `Object#hash`)
+    pub fn build_identity_hash(
+        &self,
+        object: inkwell::values::BasicValueEnum<'ictx>,
+    ) -> inkwell::values::IntValue<'ictx> {
+        let addr =
+            self.builder
+                .build_ptr_to_int(object.into_pointer_value(), self.i64_type, "addr");
+        // The lowest bits are always zero because of the alignment
+        let shifted =
+            self.builder
+                .build_right_shift(addr, self.i64_type.const_int(4, false), false, "");
+        self.builder
+            .build_int_truncate(shifted, self.i32_type, "identity_hash")
+    }
+
+    /// Returns true (as an i1) if the two objects are the same one
+    pub fn build_identical(
+        &self,
+        a: inkwell::values::BasicValueEnum<'ictx>,
+        b: inkwell::values::BasicValueEnum<'ictx>,
+    ) -> inkwell::values::IntValue<'ictx> {
+        let a = self
+            .builder
+            .build_bitcast(a, self.i8ptr_type, "")
+            .into_pointer_value();
+        let b = self
+            .builder
+            .build_bitcast(b, self.i8ptr_type, "")
+            .into_pointer_value();
+        let a = self.builder.build_ptr_to_int(a, self.i64_type, "");
+        let b = self.builder.build_ptr_to_int(b, self.i64_type, "");
+        self.builder
+            .build_int_compare(inkwell::IntPredicate::EQ, a, b, "identical")
+    }
 
-        // %foo = bitcast i8* %mem to %#{t}*",
-        self.builder.build_bitcast(raw_addr, obj_ptr_type, reg_name)
+    /// Load whether the object is frozen (as an i1)
+    pub fn build_frozen_flag_load<'a>(
+        &'a self,
+        object: inkwell::values::BasicValueEnum<'a>,
+    ) -> inkwell::values::IntValue<'a> {
+        let header = self.build_header_ptr(object);
+        let value = self.builder.build_load(header, "header").into_int_value();
+        let flag = self.i64_type.const_int(OBJ_FROZEN_FLAG, false);
+        let bit = self.builder.build_and(value, flag, "frozen_bit");
+        self.builder.build_int_compare(
+            inkwell::IntPredicate::NE,
+            bit,
+            self.i64_type.const_int(0, false),
+            "frozen",
+        )
+    }
+
+    /// Set the frozen flag of the object
+    pub fn build_freeze<'a>(&'a self, object: inkwell::values::BasicValueEnum<'a>) {
+        let header = self.build_header_ptr(object);
+        let value = self.builder.build_load(header, "header").into_int_value();
+        let flag = self.i64_type.const_int(OBJ_FROZEN_FLAG, false);
+        let new_value = self.builder.build_or(value, flag, "frozen_header");
+        self.builder.build_store(header, new_value);
+    }
+
+    /// Return the pointer to the header of a Shiika object
+    fn build_header_ptr<'a>(
+        &'a self,
+        object: inkwell::values::BasicValueEnum<'a>,
+    ) -> inkwell::values::PointerValue<'a> {
+        let i64ptr_type = self.i64_type.ptr_type(AddressSpace::Generic);
+        let ptr = self
+            .builder
+            .build_pointer_cast(object.into_pointer_value(), i64ptr_type, "obj");
+        let minus_one = self.i64_type.const_int(-1i64 as u64, true);
+        unsafe {
+            self.builder
+                .build_in_bounds_gep(ptr, &[minus_one], "header_ptr")
+        }
     }
 
     pub fn llvm_type(&self, ty: &TermTy) -> inkwell::types::BasicTypeEnum<'ictx> {
         if ty.body == TyBody::TyRaw && ty.fullname.0 == "Shiika::Internal::Ptr" {
             self.i8ptr_type.as_basic_type_enum()
+        } else if ty.is_native() {
+            match ty.boxed_ty().unwrap().fullname.0.as_str() {
+                "Bool" => self.i1_type.as_basic_type_enum(),
+                "Int" => self.i32_type.as_basic_type_enum(),
+                "Float" => self.f64_type.as_basic_type_enum(),
+                _ => panic!("[BUG] unknown native type: {:?}", ty),
+            }
         } else {
             self.sk_obj_llvm_type(ty)
         }
@@ -82,13 +485,14 @@ impl<'hir, 'run, 'ictx> CodeGen<'hir, 'run, 'ictx> {
     /// Helper func for self.llvm_type()
     fn sk_obj_llvm_type(&self, ty: &TermTy) -> inkwell::types::BasicTypeEnum<'ictx> {
         let s = match &ty.body {
-            TyBody::TySpe { base_name, .. } => &base_name,
-            TyBody::TyParamRef { .. } => "Object", // its upper bound
-            _ => &ty.fullname.0,
+            TyBody::TySpe { base_name, .. } => base_name.clone(),
+            TyBody::TySpeMeta { base_name, .. } => format!("Meta:{}", base_name),
+            TyBody::TyParamRef { .. } => "Object".to_string(), // its upper bound
+            _ => ty.fullname.0.clone(),
         };
         let struct_type = self
             .llvm_struct_types
-            .get(&class_fullname(s))
+            .get(&class_fullname(&s))
             .unwrap_or_else(|| panic!("[BUG] struct_type not found: {:?}", ty.fullname));
         struct_type
             .ptr_type(AddressSpace::Generic)
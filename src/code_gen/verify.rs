@@ -0,0 +1,69 @@
+/// Verification of the generated LLVM IR (`shiika compile --verify`)
+///
+/// Internal errors of the compiler (invalid IR, or panics with "[BUG]")
+/// are reported with the IR of the function and the HIR it was generated
+/// from, so that they can be attached to a bug report.
+use crate::code_gen::*;
+use crate::error;
+use std::panic;
+
+impl<'hir: 'ictx, 'run, 'ictx: 'run> CodeGen<'hir, 'run, 'ictx> {
+    /// Same as `gen_program` but checks the result with the LLVM verifier
+    pub fn gen_program_verified(&mut self, hir: &'hir Hir) -> Result<(), Error> {
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| self.gen_program(hir)));
+        match result {
+            Ok(r) => r?,
+            Err(payload) => {
+                let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+                    s.to_string()
+                } else if let Some(s) = payload.downcast_ref::<String>() {
+                    s.clone()
+                } else {
+                    "(unknown)".to_string()
+                };
+                let func = self
+                    .builder
+                    .get_insert_block()
+                    .and_then(|block| block.get_parent());
+                return Err(self.bug_report(&format!("panicked: {}", msg), func));
+            }
+        }
+
+        let mut func = self.module.get_first_function();
+        while let Some(f) = func {
+            if !f.verify(false) {
+                return Err(self.bug_report("invalid LLVM IR", Some(f)));
+            }
+            func = f.get_next_function();
+        }
+        self.module
+            .verify()
+            .map_err(|e| error::bug(format!("invalid LLVM module: {}", e.to_string())))
+    }
+
+    /// Remember that the llvm function `func_name` is generated from `hir`
+    pub(super) fn set_hir_origin(&self, func_name: &str, hir: &'hir dyn std::fmt::Debug) {
+        self.hir_origins
+            .borrow_mut()
+            .insert(func_name.to_string(), hir);
+    }
+
+    fn bug_report(&self, what: &str, func: Option<inkwell::values::FunctionValue>) -> Error {
+        let func = match func {
+            Some(f) => f,
+            None => return error::bug(format!("[BUG] {}", what)),
+        };
+        let name = func.get_name().to_string_lossy().to_string();
+        let hir = match self.hir_origins.borrow().get(&name) {
+            Some(hir) => format!("{:#?}", hir),
+            None => "(not generated from HIR)".to_string(),
+        };
+        error::bug(format!(
+            "[BUG] {} in `{}'\n\n--- LLVM IR ---\n{}\n--- HIR ---\n{}",
+            what,
+            name,
+            func.print_to_string().to_string(),
+            hir
+        ))
+    }
+}
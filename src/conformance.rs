@@ -0,0 +1,158 @@
+/// Conformance suite runner (`shiika conformance`)
+///
+/// The suite is a directory of features (`conformance/<feature>/`), each of
+/// which contains programs (`<name>.sk`) and their expected outputs
+/// (`<name>.out`). A case passes if the stdout of the program is the same
+/// as the expected output.
+///
+/// By default the programs are compiled and run by this compiler. An
+/// alternative implementation can be tested with `--runner CMD`; `CMD` is
+/// called with the path of the .sk and its stdout is compared instead.
+use crate::error;
+use crate::runner;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, PartialEq)]
+pub struct Case {
+    pub feature: String,
+    pub name: String,
+    pub sk_path: PathBuf,
+    pub expected: String,
+}
+
+impl Case {
+    /// eg. `strings/concat`
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.feature, self.name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// The program printed something else
+    Fail {
+        actual: String,
+    },
+    /// The program could not be compiled or run
+    Error(String),
+}
+
+/// Return the cases in `dir`, sorted by the feature and the name. If
+/// `features` is not empty, only the cases of them are returned
+pub fn collect_cases(
+    dir: &Path,
+    features: &[&str],
+) -> Result<Vec<Case>, Box<dyn std::error::Error>> {
+    let mut cases = vec![];
+    for feature_dir in sorted_entries(dir)? {
+        if !feature_dir.is_dir() {
+            continue;
+        }
+        let feature = file_name(&feature_dir);
+        if !features.is_empty() && !features.contains(&feature.as_str()) {
+            continue;
+        }
+        for sk_path in sorted_entries(&feature_dir)? {
+            if sk_path.extension().and_then(|ext| ext.to_str()) != Some("sk") {
+                continue;
+            }
+            let out_path = sk_path.with_extension("out");
+            let expected = fs::read_to_string(&out_path).map_err(|e| {
+                error::runner_error(format!("failed to read {}", out_path.display()), e)
+            })?;
+            cases.push(Case {
+                feature: feature.clone(),
+                name: file_name(&sk_path.with_extension("")),
+                sk_path,
+                expected,
+            });
+        }
+    }
+    Ok(cases)
+}
+
+/// Run the case with this compiler, or with `runner_cmd` if given
+pub fn run_case(case: &Case, runner_cmd: Option<&str>) -> Outcome {
+    let result = match runner_cmd {
+        Some(cmd) => run_external(cmd, &case.sk_path),
+        None => run_reference(&case.sk_path),
+    };
+    match result {
+        Ok(actual) if actual == case.expected => Outcome::Pass,
+        Ok(actual) => Outcome::Fail { actual },
+        Err(e) => Outcome::Error(e.to_string()),
+    }
+}
+
+/// Compile and run the program with this compiler and return its stdout
+fn run_reference(sk_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    runner::compile(sk_path, false)?;
+    let (stdout, _) = runner::run_and_capture(sk_path)?;
+    runner::cleanup(sk_path)?;
+    Ok(stdout)
+}
+
+/// Run `cmd` (a command and its arguments, separated by spaces) with the
+/// path of the program and return its stdout
+fn run_external(cmd: &str, sk_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut words = cmd.split_ascii_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| error::plain_runner_error("empty runner command"))?;
+    let output = Command::new(program)
+        .args(words)
+        .arg(sk_path)
+        .output()
+        .map_err(|e| error::runner_error(format!("failed to run {}", program), e))?;
+    if !output.status.success() {
+        return Err(Box::new(error::plain_runner_error(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Run all the cases and print the results. Returns the number of the
+/// cases which did not pass
+pub fn run_suite(cases: &[Case], runner_cmd: Option<&str>) -> usize {
+    let mut n_failed = 0;
+    for case in cases {
+        match run_case(case, runner_cmd) {
+            Outcome::Pass => println!("PASS {}", case.id()),
+            Outcome::Fail { actual } => {
+                n_failed += 1;
+                println!("FAIL {}", case.id());
+                println!("  expected: {:?}", case.expected);
+                println!("  actual:   {:?}", actual);
+            }
+            Outcome::Error(msg) => {
+                n_failed += 1;
+                println!("ERROR {}: {}", case.id(), msg);
+            }
+        }
+    }
+    println!("\n{} passed, {} failed", cases.len() - n_failed, n_failed);
+    n_failed
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| error::runner_error(format!("failed to read {}", dir.display()), e))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
@@ -204,6 +204,17 @@ pub fn create_methods() -> Vec<SkMethod> {
             code_gen.builder.build_return(Some(&sk_int));
             Ok(())
         }),
+        create_method(
+            "Float",
+            "_format(spec: String) -> String",
+            |code_gen, function| {
+                let float = code_gen.unbox_float(function.get_params()[0]);
+                let spec = function.get_params()[1];
+                let sk_str = code_gen.build_snprintf(spec, float.into());
+                code_gen.builder.build_return(Some(&sk_str));
+                Ok(())
+            },
+        ),
         create_method("Float", "-@ -> Float", |code_gen, function| {
             let this = function.get_params()[0];
             let float = code_gen.unbox_float(this);
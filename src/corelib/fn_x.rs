@@ -1,89 +1,174 @@
+/// `Fn0`, `Fn1`, ... (the classes of lambdas)
+///
+/// Instead of predefining the classes up to some arity, the classes are
+/// created for each arity used in the program (see `required_arity`.)
+use crate::ast::*;
+use crate::ast_visitor::*;
 use crate::corelib::*;
-use inkwell::types::*;
-use inkwell::AddressSpace;
 
-macro_rules! create_fn_call {
-    ($i:expr) => {{
-        let args_str = (1..=$i)
-            .map(|i| format!("arg{}: S{}", i, i))
-            .collect::<Vec<_>>()
-            .join(", ");
+/// Return the largest arity of the lambdas and the `FnN` types
+/// (including the superclasses) found in `prog`
+pub fn required_arity(prog: &Program) -> usize {
+    let mut collector = ArityCollector::default();
+    walk_program(&mut collector, prog);
+    collector.max
+}
 
-        let mut typarams = (1..=$i).map(|i| format!("S{}", i)).collect::<Vec<_>>();
-        typarams.push("T".to_string());
+/// Create `Fn0` to `Fn{max_arity}`
+pub fn fn_items(max_arity: usize) -> Vec<ClassItem> {
+    (0..=max_arity).map(fn_item).collect()
+}
 
-        create_method_generic(
-            &format!("Fn{}", $i),
-            &format!("call({}) -> T", args_str),
-            |code_gen, function| {
-                let fn_obj = function.get_params()[0];
-                let fnptr = code_gen.build_ivar_load(fn_obj, 0, "func");
-                let capary = code_gen.build_ivar_load(fn_obj, 1, "captures");
+fn fn_item(arity: usize) -> ClassItem {
+    let class_name = format!("Fn{}", arity);
+    let mut typarams = (1..=arity).map(|i| format!("S{}", i)).collect::<Vec<_>>();
+    typarams.push("T".to_string());
 
-                let mut args = vec![];
-                for k in 1..=$i {
-                    args.push(function.get_params()[k]);
-                }
-                args.push(capary);
+    (
+        class_name.clone(),
+        vec![
+            create_fn_call(&class_name, arity, &typarams),
+            create_fn_arity(&class_name, arity),
+        ],
+        vec![create_fn_new(&class_name)],
+        fn_ivars(),
+        typarams,
+    )
+}
 
-                // Create the type of lambda_xx()
-                let obj_type = code_gen.llvm_type(&ty::raw("Object"));
-                let ary_type = code_gen.llvm_type(&ty::raw("Array"));
-                let mut arg_types = vec![];
-                for _ in 1..=$i {
-                    arg_types.push(obj_type.into());
-                }
-                arg_types.push(ary_type.into());
-                let fntype = obj_type.fn_type(&arg_types, false);
-                let fnptype = fntype.ptr_type(AddressSpace::Generic);
+fn fn_ivars() -> HashMap<String, SkIVar> {
+    let mut ivars = HashMap::new();
+    ivars.insert(
+        "@func".to_string(),
+        SkIVar {
+            name: "@func".to_string(),
+            idx: 0,
+            ty: ty::raw("Shiika::Internal::Ptr"),
+            readonly: true,
+        },
+    );
+    ivars.insert(
+        "@captures".to_string(),
+        SkIVar {
+            name: "@captures".to_string(),
+            idx: 1,
+            ty: ty::ary(ty::raw("Shiika::Internal::Ptr")),
+            readonly: true,
+        },
+    );
+    ivars
+}
+
+/// `FnN.new(func, captures)` (called from the code of lambda expressions)
+fn create_fn_new(class_name: &str) -> SkMethod {
+    let cls = class_fullname(class_name);
+    create_closure_method(
+        &cls.meta_name().0,
+        &format!(
+            "new(func: Shiika::Internal::Ptr, captures: Array<Shiika::Internal::Ptr>) -> {}",
+            class_name
+        ),
+        Box::new(move |code_gen, function| {
+            let obj = code_gen.allocate_sk_obj(&cls, "fn");
+            code_gen.build_ivar_store(&obj, 0, function.get_params()[1], "@func");
+            code_gen.build_ivar_store(&obj, 1, function.get_params()[2], "@captures");
+            code_gen.builder.build_return(Some(&obj));
+            Ok(())
+        }),
+        &[],
+    )
+}
 
-                // Cast `fnptr` to that type
-                let func = code_gen
-                    .builder
-                    .build_bitcast(fnptr, fnptype, "")
-                    .into_pointer_value();
+/// `FnN#call(arg1, ..., argN)`
+///
+/// Usually the call is inlined by the code generator; this is used when
+/// the method is called via a generic type etc.
+fn create_fn_call(class_name: &str, arity: usize, typarams: &[String]) -> SkMethod {
+    let args_str = (1..=arity)
+        .map(|i| format!("arg{}: S{}", i, i))
+        .collect::<Vec<_>>()
+        .join(", ");
 
-                // Generate function call
-                let result = code_gen
-                    .builder
-                    .build_call(func, &args, "result")
-                    .try_as_basic_value()
-                    .left()
-                    .unwrap();
-                code_gen.builder.build_return(Some(&result));
-                Ok(())
-            },
-            &typarams,
-        )
-    }};
+    create_closure_method(
+        class_name,
+        &format!("call({}) -> T", args_str),
+        Box::new(move |code_gen, function| {
+            let fn_obj = function.get_params()[0];
+            let args = (1..=arity)
+                .map(|k| function.get_params()[k])
+                .collect::<Vec<_>>();
+            let result = code_gen.build_lambda_call(fn_obj, args);
+            code_gen.builder.build_return(Some(&result));
+            Ok(())
+        }),
+        typarams,
+    )
 }
 
-macro_rules! fn_item {
-    ($i:expr) => {{
-        let mut typarams = (1..=$i).map(|i| format!("S{}", i)).collect::<Vec<_>>();
-        typarams.push("T".to_string());
+/// `FnN#arity`
+fn create_fn_arity(class_name: &str, arity: usize) -> SkMethod {
+    create_closure_method(
+        class_name,
+        "arity -> Int",
+        Box::new(move |code_gen, _function| {
+            let n = code_gen.i32_type.const_int(arity as u64, false);
+            let sk_int = code_gen.box_int(&n);
+            code_gen.builder.build_return(Some(&sk_int));
+            Ok(())
+        }),
+        &[],
+    )
+}
+
+#[derive(Debug, Default)]
+struct ArityCollector {
+    max: usize,
+}
+
+impl ArityCollector {
+    /// Check if `name` is like `Fn12`
+    fn found_name(&mut self, name: &str) {
+        if name.starts_with("Fn") {
+            if let Ok(n) = name[2..].parse::<usize>() {
+                self.found(n);
+            }
+        }
+    }
 
-        (
-            format!("Fn{}", $i),
-            vec![create_fn_call!($i)],
-            vec![],
-            HashMap::new(),
-            typarams,
-        )
-    }};
+    fn found(&mut self, arity: usize) {
+        if arity > self.max {
+            self.max = arity;
+        }
+    }
 }
 
-pub fn fn_items() -> Vec<ClassItem> {
-    vec![
-        fn_item!(0),
-        fn_item!(1),
-        fn_item!(2),
-        fn_item!(3),
-        fn_item!(4),
-        fn_item!(5),
-        fn_item!(6),
-        fn_item!(7),
-        fn_item!(8),
-        fn_item!(9),
-    ]
+impl AstVisitor for ArityCollector {
+    fn visit_def(&mut self, def: &Definition) {
+        if let Definition::ClassDefinition { super_name, .. } = def {
+            // `class A : Fn2` (the name may be qualified but `FnN` is
+            // always toplevel)
+            self.found_name(&super_name.0);
+        }
+        walk_def(self, def);
+    }
+
+    fn visit_expr(&mut self, expr: &AstExpression) {
+        match &expr.body {
+            AstExpressionBody::LambdaExpr { params, .. } => self.found(params.len()),
+            // The continuation is a lambda which takes at most one arg
+            AstExpressionBody::AwaitCall { .. } => self.found(1),
+            AstExpressionBody::ConstRef(names) => {
+                if let Some(name) = names.last() {
+                    self.found_name(name);
+                }
+            }
+            _ => (),
+        }
+        walk_expr(self, expr);
+    }
+
+    fn visit_typ(&mut self, typ: &Typ) {
+        self.found_name(&typ.name);
+        walk_typ(self, typ);
+    }
 }
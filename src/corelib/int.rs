@@ -229,6 +229,24 @@ pub fn create_methods() -> Vec<SkMethod> {
             code_gen.builder.build_return(Some(&sk_result));
             Ok(())
         }),
+        create_method("Int", "to_s() -> String", |code_gen, function| {
+            let this = function.get_params()[0];
+            let int = code_gen.unbox_int(this);
+            let sk_str = code_gen.build_int_to_s(int, function);
+            code_gen.builder.build_return(Some(&sk_str));
+            Ok(())
+        }),
+        create_method(
+            "Int",
+            "_format(spec: String) -> String",
+            |code_gen, function| {
+                let int = code_gen.unbox_int(function.get_params()[0]);
+                let spec = function.get_params()[1];
+                let sk_str = code_gen.build_snprintf(spec, int.into());
+                code_gen.builder.build_return(Some(&sk_str));
+                Ok(())
+            },
+        ),
         create_method("Int", "-@ -> Int", |code_gen, function| {
             let sk_int = function.get_params()[0];
             let this = code_gen.unbox_int(sk_int);
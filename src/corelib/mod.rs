@@ -5,10 +5,16 @@ mod int;
 mod math;
 mod never;
 mod object;
+mod shiika_internal_fiber;
+mod shiika_internal_io;
 mod shiika_internal_memory;
+mod shiika_internal_process;
 mod shiika_internal_ptr;
+mod shiika_internal_socket;
+mod shiika_internal_time;
 mod string;
 mod void;
+use crate::ast;
 use crate::hir::*;
 use crate::names::*;
 use crate::parser;
@@ -29,8 +35,14 @@ impl Corelib {
         }
     }
 
-    pub fn create() -> Corelib {
-        let items = rust_body_items();
+    /// Create the corelib classes (including the `Fn` classes needed by `prog`)
+    pub fn create(prog: &ast::Program) -> Corelib {
+        Corelib::with_fn_arity(fn_x::required_arity(prog))
+    }
+
+    /// Create the corelib classes with `Fn0` to `Fn{fn_arity}`
+    pub fn with_fn_arity(fn_arity: usize) -> Corelib {
+        let items = rust_body_items(fn_arity);
         let (sk_classes, sk_methods) = make_classes(items);
         Corelib {
             sk_classes,
@@ -47,7 +59,7 @@ type ClassItem = (
     Vec<String>,
 );
 
-fn rust_body_items() -> Vec<ClassItem> {
+fn rust_body_items(fn_arity: usize) -> Vec<ClassItem> {
     let mut ret = vec![
         // Classes
         (
@@ -130,8 +142,43 @@ fn rust_body_items() -> Vec<ClassItem> {
             HashMap::new(),
             vec![],
         ),
+        (
+            "Shiika::Internal::Time".to_string(),
+            vec![],
+            shiika_internal_time::create_class_methods(),
+            HashMap::new(),
+            vec![],
+        ),
+        (
+            "Shiika::Internal::IO".to_string(),
+            vec![],
+            shiika_internal_io::create_class_methods(),
+            HashMap::new(),
+            vec![],
+        ),
+        (
+            "Shiika::Internal::Process".to_string(),
+            vec![],
+            shiika_internal_process::create_class_methods(),
+            HashMap::new(),
+            vec![],
+        ),
+        (
+            "Shiika::Internal::Socket".to_string(),
+            vec![],
+            shiika_internal_socket::create_class_methods(),
+            HashMap::new(),
+            vec![],
+        ),
+        (
+            "Shiika::Internal::Fiber".to_string(),
+            vec![],
+            shiika_internal_fiber::create_class_methods(),
+            HashMap::new(),
+            vec![],
+        ),
     ];
-    ret.append(&mut fn_x::fn_items());
+    ret.append(&mut fn_x::fn_items(fn_arity));
     ret
 }
 
@@ -165,6 +212,7 @@ fn make_classes(
                     .map(|x| (x.signature.first_name().clone(), x.signature.clone()))
                     .collect(),
                 const_is_obj: (name == "Void"),
+                abstract_methods: vec![],
             },
         );
 
@@ -191,6 +239,7 @@ fn make_classes(
                     .map(|x| (x.signature.first_name().clone(), x.signature.clone()))
                     .collect(),
                 const_is_obj: false,
+                abstract_methods: vec![],
             },
         );
 
@@ -224,3 +273,25 @@ fn create_method_generic(
         body: SkMethodBody::RustMethodBody { gen },
     }
 }
+
+/// Same as `create_method_generic` but the body can capture variables
+fn create_closure_method(
+    class_name: &str,
+    sig_str: &str,
+    boxed_gen: Box<ClosureMethodBody>,
+    typaram_names: &[String],
+) -> SkMethod {
+    let mut parser = parser::Parser::new_with_state(sig_str, parser::lexer::LexerState::MethodName);
+    let (ast_sig, _) = parser.parse_method_signature().unwrap();
+    parser.expect_eof().unwrap();
+    let sig = crate::hir::signature::create_signature(
+        &class_fullname(class_name),
+        &ast_sig,
+        typaram_names,
+    );
+
+    SkMethod {
+        signature: sig,
+        body: SkMethodBody::RustClosureMethodBody { boxed_gen },
+    }
+}
@@ -64,6 +64,32 @@ pub fn create_methods() -> Vec<SkMethod> {
             code_gen.builder.build_return(None);
             Ok(())
         }),
+        create_method(
+            "Object",
+            "print(s: String) -> Void",
+            |code_gen, function| {
+                let s = function.get_params()[1].into_pointer_value();
+                let pptr = code_gen.builder.build_struct_gep(s, 0, "").unwrap();
+                let ptr = code_gen.builder.build_load(pptr, "");
+                let printf = code_gen.module.get_function("printf").unwrap();
+                let tmpl = code_gen
+                    .module
+                    .get_global("print_tmpl")
+                    .unwrap()
+                    .as_pointer_value();
+                let tmpl_ptr = unsafe {
+                    tmpl.const_in_bounds_gep(&[
+                        code_gen.i32_type.const_int(0, false),
+                        code_gen.i32_type.const_int(0, false),
+                    ])
+                };
+                code_gen
+                    .builder
+                    .build_call(printf, &[tmpl_ptr.into(), ptr], "");
+                code_gen.builder.build_return(None);
+                Ok(())
+            },
+        ),
         create_method("Object", "puts(s: String) -> Void", |code_gen, function| {
             let s = function.get_params()[1].into_pointer_value();
             let pptr = code_gen.builder.build_struct_gep(s, 0, "").unwrap();
@@ -73,5 +99,80 @@ pub fn create_methods() -> Vec<SkMethod> {
             code_gen.builder.build_return(None);
             Ok(())
         }),
+        create_method(
+            "Object",
+            "at_exit(f: Fn0<Void>) -> Void",
+            |code_gen, function| {
+                code_gen.build_at_exit_push(function.get_params()[1]);
+                code_gen.builder.build_return(None);
+                Ok(())
+            },
+        ),
+        create_method(
+            "Object",
+            "exit(status: Int) -> Void",
+            |code_gen, function| {
+                let status = code_gen.unbox_int(function.get_params()[1]);
+                let func = code_gen.module.get_function("shiika_teardown").unwrap();
+                code_gen.builder.build_call(func, &[], "");
+                let func = code_gen.module.get_function("exit").unwrap();
+                code_gen.builder.build_call(func, &[status.into()], "");
+                code_gen.builder.build_unreachable();
+                Ok(())
+            },
+        ),
+        // Prints `msg` and the backtrace and exits with status 1 (see `panic`)
+        create_method(
+            "Object",
+            "_abort(msg: String) -> Never",
+            |code_gen, function| {
+                let s = function.get_params()[1].into_pointer_value();
+                let pptr = code_gen.builder.build_struct_gep(s, 0, "").unwrap();
+                let ptr = code_gen.builder.build_load(pptr, "");
+                let func = code_gen.module.get_function("puts").unwrap();
+                code_gen.builder.build_call(func, &[ptr], "");
+                code_gen.build_print_backtrace();
+                let func = code_gen.module.get_function("shiika_teardown").unwrap();
+                code_gen.builder.build_call(func, &[], "");
+                let func = code_gen.module.get_function("exit").unwrap();
+                code_gen.builder.build_call(
+                    func,
+                    &[code_gen.i32_type.const_int(1, false).into()],
+                    "",
+                );
+                code_gen.builder.build_unreachable();
+                Ok(())
+            },
+        ),
+        create_method("Object", "freeze() -> Void", |code_gen, function| {
+            code_gen.build_freeze(function.get_params()[0]);
+            code_gen.builder.build_return(None);
+            Ok(())
+        }),
+        // These are replaced with the ones which call the `hash` and `==` of
+        // the class of the receiver (see hir/hash_dispatch.rs)
+        create_method("Object", "hash() -> Int", |code_gen, function| {
+            let hash = code_gen.build_identity_hash(function.get_params()[0]);
+            let sk_int = code_gen.box_int(&hash);
+            code_gen.builder.build_return(Some(&sk_int));
+            Ok(())
+        }),
+        create_method(
+            "Object",
+            "==(other: Object) -> Bool",
+            |code_gen, function| {
+                let params = function.get_params();
+                let eq = code_gen.build_identical(params[0], params[1]);
+                let sk_bool = code_gen.box_bool(eq);
+                code_gen.builder.build_return(Some(&sk_bool));
+                Ok(())
+            },
+        ),
+        create_method("Object", "frozen() -> Bool", |code_gen, function| {
+            let frozen = code_gen.build_frozen_flag_load(function.get_params()[0]);
+            let sk_bool = code_gen.box_bool(frozen);
+            code_gen.builder.build_return(Some(&sk_bool));
+            Ok(())
+        }),
     ]
 }
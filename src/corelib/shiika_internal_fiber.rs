@@ -0,0 +1,66 @@
+use crate::corelib::create_method;
+use crate::hir::*;
+
+pub fn create_class_methods() -> Vec<SkMethod> {
+    vec![
+        create_method(
+            "Meta:Shiika::Internal::Fiber",
+            "available() -> Bool",
+            |code_gen, _function| {
+                let b = code_gen
+                    .i1_type
+                    .const_int(code_gen.fiber_available() as u64, false);
+                code_gen.builder.build_return(Some(&code_gen.box_bool(b)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Fiber",
+            "create(f: Object) -> Shiika::Internal::Ptr",
+            |code_gen, function| {
+                let fn_obj = function.get_params()[1];
+                let fiber = code_gen.build_fiber_new(fn_obj);
+                code_gen.builder.build_return(Some(&fiber));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Fiber",
+            "resume(fiber: Shiika::Internal::Ptr) -> Object",
+            |code_gen, function| {
+                let fiber = function.get_params()[1].into_pointer_value();
+                let value = code_gen.build_fiber_resume(fiber);
+                code_gen.builder.build_return(Some(&value));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Fiber",
+            "yield_value(value: Object) -> Void",
+            |code_gen, function| {
+                code_gen.build_fiber_yield(function.get_params()[1]);
+                code_gen.builder.build_return(None);
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Fiber",
+            "finished(fiber: Shiika::Internal::Ptr) -> Bool",
+            |code_gen, function| {
+                let fiber = function.get_params()[1].into_pointer_value();
+                let b = code_gen.build_fiber_finished(fiber);
+                code_gen.builder.build_return(Some(&code_gen.box_bool(b)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Fiber",
+            "running() -> Bool",
+            |code_gen, _function| {
+                let b = code_gen.build_fiber_running();
+                code_gen.builder.build_return(Some(&code_gen.box_bool(b)));
+                Ok(())
+            },
+        ),
+    ]
+}
@@ -0,0 +1,154 @@
+use crate::corelib::create_method;
+use crate::hir::*;
+
+pub fn create_class_methods() -> Vec<SkMethod> {
+    vec![
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "pipe(fds: Shiika::Internal::Ptr) -> Int",
+            |code_gen, function| {
+                let fds = function.get_params()[1].into_pointer_value();
+                let result = code_gen.build_pipe(fds);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "load_i32(ptr: Shiika::Internal::Ptr, idx: Int) -> Int",
+            |code_gen, function| {
+                let ptr = function.get_params()[1].into_pointer_value();
+                let idx = code_gen.unbox_int(function.get_params()[2]);
+                let i32ptr = code_gen.builder.build_pointer_cast(
+                    ptr,
+                    code_gen.i32_type.ptr_type(inkwell::AddressSpace::Generic),
+                    "i32ptr",
+                );
+                let item = unsafe { code_gen.builder.build_in_bounds_gep(i32ptr, &[idx], "") };
+                let value = code_gen.builder.build_load(item, "value").into_int_value();
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&value)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "read(fd: Int, buf: Shiika::Internal::Ptr, n_bytes: Int) -> Int",
+            |code_gen, function| {
+                let fd = code_gen.unbox_int(function.get_params()[1]);
+                let buf = function.get_params()[2].into_pointer_value();
+                let n_bytes = code_gen.unbox_int(function.get_params()[3]);
+                let result = code_gen.build_fd_io("read", fd, buf, n_bytes);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "write(fd: Int, buf: Shiika::Internal::Ptr, n_bytes: Int) -> Int",
+            |code_gen, function| {
+                let fd = code_gen.unbox_int(function.get_params()[1]);
+                let buf = function.get_params()[2].into_pointer_value();
+                let n_bytes = code_gen.unbox_int(function.get_params()[3]);
+                let result = code_gen.build_fd_io("write", fd, buf, n_bytes);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "close(fd: Int) -> Int",
+            |code_gen, function| {
+                let fd = code_gen.unbox_int(function.get_params()[1]);
+                let result = code_gen.build_fd_close(fd);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "selector_new() -> Int",
+            |code_gen, _function| {
+                let result = code_gen.build_selector_new();
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "selector_ctl(sel: Int, op: Int, fd: Int, events: Int) -> Int",
+            |code_gen, function| {
+                let sel = code_gen.unbox_int(function.get_params()[1]);
+                let op = code_gen.unbox_int(function.get_params()[2]);
+                let fd = code_gen.unbox_int(function.get_params()[3]);
+                let events = code_gen.unbox_int(function.get_params()[4]);
+                let result = code_gen.build_selector_ctl(sel, op, fd, events);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "selector_wait(sel: Int, buf: Shiika::Internal::Ptr, max: Int, timeout_ms: Int) -> Int",
+            |code_gen, function| {
+                let sel = code_gen.unbox_int(function.get_params()[1]);
+                let buf = function.get_params()[2].into_pointer_value();
+                let max = code_gen.unbox_int(function.get_params()[3]);
+                let timeout_ms = code_gen.unbox_int(function.get_params()[4]);
+                let result = code_gen.build_selector_wait(*function, sel, buf, max, timeout_ms);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "event_size() -> Int",
+            |code_gen, _function| {
+                let size = code_gen.selector_event_size();
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&size)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "event_fd(buf: Shiika::Internal::Ptr, idx: Int) -> Int",
+            |code_gen, function| {
+                let buf = function.get_params()[1].into_pointer_value();
+                let idx = code_gen.unbox_int(function.get_params()[2]);
+                let (fd, _) = code_gen.build_selector_event(buf, idx);
+                code_gen.builder.build_return(Some(&code_gen.box_int(&fd)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::IO",
+            "event_events(buf: Shiika::Internal::Ptr, idx: Int) -> Int",
+            |code_gen, function| {
+                let buf = function.get_params()[1].into_pointer_value();
+                let idx = code_gen.unbox_int(function.get_params()[2]);
+                let (_, events) = code_gen.build_selector_event(buf, idx);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&events)));
+                Ok(())
+            },
+        ),
+    ]
+}
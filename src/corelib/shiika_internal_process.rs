@@ -0,0 +1,53 @@
+use crate::corelib::create_method;
+use crate::hir::*;
+
+pub fn create_class_methods() -> Vec<SkMethod> {
+    vec![
+        create_method(
+            "Meta:Shiika::Internal::Process",
+            "popen(cmd: String) -> Shiika::Internal::Ptr",
+            |code_gen, function| {
+                let cmd = code_gen.build_ivar_load(function.get_params()[1], 0, "@ptr");
+                let file = code_gen.build_popen(cmd);
+                code_gen.builder.build_return(Some(&file));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Process",
+            "fileno(file: Shiika::Internal::Ptr) -> Int",
+            |code_gen, function| {
+                let file = function.get_params()[1].into_pointer_value();
+                let result = code_gen.build_fileno(file);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Process",
+            "pclose(file: Shiika::Internal::Ptr) -> Int",
+            |code_gen, function| {
+                let file = function.get_params()[1].into_pointer_value();
+                let result = code_gen.build_pclose(file);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Process",
+            "system(cmd: String) -> Int",
+            |code_gen, function| {
+                let cmd = code_gen.build_ivar_load(function.get_params()[1], 0, "@ptr");
+                let result = code_gen.build_system(cmd);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+    ]
+}
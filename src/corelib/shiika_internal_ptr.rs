@@ -55,5 +55,33 @@ pub fn create_methods() -> Vec<SkMethod> {
                 Ok(())
             },
         ),
+        create_method(
+            "Shiika::Internal::Ptr",
+            "load_byte -> Int",
+            |code_gen, function| {
+                let i8ptr = function.get_params()[0].into_pointer_value();
+                let byte = code_gen.builder.build_load(i8ptr, "byte").into_int_value();
+                let int = code_gen
+                    .builder
+                    .build_int_z_extend(byte, code_gen.i32_type, "int");
+                let sk_int = code_gen.box_int(&int);
+                code_gen.builder.build_return(Some(&sk_int));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Shiika::Internal::Ptr",
+            "store_byte(byte: Int)",
+            |code_gen, function| {
+                let i8ptr = function.get_params()[0].into_pointer_value();
+                let int = code_gen.unbox_int(function.get_params()[1]);
+                let byte = code_gen
+                    .builder
+                    .build_int_truncate(int, code_gen.i8_type, "byte");
+                code_gen.builder.build_store(i8ptr, byte);
+                code_gen.builder.build_return(None);
+                Ok(())
+            },
+        ),
     ]
 }
@@ -0,0 +1,57 @@
+use crate::corelib::create_method;
+use crate::hir::*;
+
+pub fn create_class_methods() -> Vec<SkMethod> {
+    vec![
+        create_method(
+            "Meta:Shiika::Internal::Socket",
+            "tcp_connect(host: String, port: String) -> Int",
+            |code_gen, function| {
+                let host = code_gen.build_ivar_load(function.get_params()[1], 0, "@ptr");
+                let port = code_gen.build_ivar_load(function.get_params()[2], 0, "@ptr");
+                let result = code_gen.build_tcp_open(host, port, false);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Socket",
+            "tcp_listen(host: String, port: String) -> Int",
+            |code_gen, function| {
+                let host = code_gen.build_ivar_load(function.get_params()[1], 0, "@ptr");
+                let port = code_gen.build_ivar_load(function.get_params()[2], 0, "@ptr");
+                let result = code_gen.build_tcp_open(host, port, true);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Socket",
+            "accept(fd: Int) -> Int",
+            |code_gen, function| {
+                let fd = code_gen.unbox_int(function.get_params()[1]);
+                let result = code_gen.build_tcp_accept(fd);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Socket",
+            "local_port(fd: Int) -> Int",
+            |code_gen, function| {
+                let fd = code_gen.unbox_int(function.get_params()[1]);
+                let result = code_gen.build_tcp_local_port(fd);
+                code_gen
+                    .builder
+                    .build_return(Some(&code_gen.box_int(&result)));
+                Ok(())
+            },
+        ),
+    ]
+}
@@ -0,0 +1,58 @@
+use crate::corelib::create_method;
+use crate::hir::*;
+
+pub fn create_class_methods() -> Vec<SkMethod> {
+    vec![
+        create_method(
+            "Meta:Shiika::Internal::Time",
+            "now_ms() -> Int",
+            |code_gen, _function| {
+                let ms = code_gen.build_now_ms();
+                let sk_int = code_gen.box_int(&ms);
+                code_gen.builder.build_return(Some(&sk_int));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Time",
+            "clock_seed() -> Int",
+            |code_gen, _function| {
+                let seed = code_gen.build_clock_seed();
+                let sk_int = code_gen.box_int(&seed);
+                code_gen.builder.build_return(Some(&sk_int));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Time",
+            "unix_time() -> Int",
+            |code_gen, _function| {
+                let sec = code_gen.build_unix_time();
+                let sk_int = code_gen.box_int(&sec);
+                code_gen.builder.build_return(Some(&sk_int));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Time",
+            "utc_offset() -> Int",
+            |code_gen, _function| {
+                let offset = code_gen.build_utc_offset();
+                let sk_int = code_gen.box_int(&offset);
+                code_gen.builder.build_return(Some(&sk_int));
+                Ok(())
+            },
+        ),
+        create_method(
+            "Meta:Shiika::Internal::Time",
+            "sleep_ms(ms: Int) -> Void",
+            |code_gen, function| {
+                let sk_int = function.get_params()[1];
+                let ms = code_gen.unbox_int(sk_int);
+                code_gen.build_sleep_ms(ms);
+                code_gen.builder.build_return(None);
+                Ok(())
+            },
+        ),
+    ]
+}
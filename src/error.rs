@@ -1,3 +1,5 @@
+use crate::parser::lexer::Span;
+use crate::ty::TermTy;
 use backtrace::Backtrace;
 
 #[derive(Debug)]
@@ -6,6 +8,9 @@ pub struct Error {
     pub backtrace: Backtrace,
     pub details: ErrorDetails,
     pub source: Option<Box<dyn std::error::Error>>,
+    /// Where the error is in the source (`None` if the error is not about
+    /// a part of the program, eg. a conformance error of a class)
+    pub span: Option<Span>,
 }
 #[derive(Debug)]
 pub enum ErrorDetails {
@@ -16,7 +21,16 @@ pub enum ErrorDetails {
     // Parsing is succeeded but syntactically wrong
     SyntaxError,
     // Errors related to types
-    TypeError,
+    TypeError {
+        /// Full names of the types, if the error is a type mismatch
+        expected: Option<String>,
+        actual: Option<String>,
+    },
+    // Wrong number of arguments
+    ArityError {
+        expected: usize,
+        actual: usize,
+    },
     // Invalid name
     NameError,
     // Syntactically correct but not a valid program (eg. "no such method")
@@ -27,6 +41,119 @@ pub enum ErrorDetails {
     Bug,
 }
 
+impl Error {
+    /// Error code (see `crate::error_codes`)
+    pub fn code(&self) -> &'static str {
+        self.details.code()
+    }
+
+    /// Set the location of the error
+    pub fn with_span(mut self, span: Span) -> Error {
+        self.span = Some(span);
+        self
+    }
+
+    /// Return the error as a JSON object (for editors and other tools)
+    ///
+    /// eg. `{"code":"E0302","kind":"ArityError","message":"...",
+    /// "span":{"begin":{"line":0,"col":4},"end":{"line":0,"col":7}},
+    /// "expected":"1","actual":"2"}` (line and col are 0-origin)
+    pub fn to_json(&self) -> String {
+        let span = match &self.span {
+            Some(span) => format!(
+                "{{\"begin\":{{\"line\":{},\"col\":{}}},\"end\":{{\"line\":{},\"col\":{}}}}}",
+                span.begin.line(),
+                span.begin.col(),
+                span.end.line(),
+                span.end.col()
+            ),
+            None => "null".to_string(),
+        };
+        let (expected, actual) = match &self.details {
+            ErrorDetails::TypeError { expected, actual } => (expected.clone(), actual.clone()),
+            ErrorDetails::ArityError { expected, actual } => {
+                (Some(expected.to_string()), Some(actual.to_string()))
+            }
+            _ => (None, None),
+        };
+        let opt_json = |s: Option<String>| match s {
+            Some(s) => json_str(&s),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"code\":{},\"kind\":{},\"message\":{},\"span\":{},\"expected\":{},\"actual\":{}}}",
+            json_str(self.code()),
+            json_str(self.details.kind()),
+            json_str(&self.msg.replace("**", "")),
+            span,
+            opt_json(expected),
+            opt_json(actual)
+        )
+    }
+}
+
+/// Quote `s` as a JSON string
+pub(crate) fn json_str(s: &str) -> String {
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\t' => ret.push_str("\\t"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+impl ErrorDetails {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorDetails::ParseError { .. } => "E0101",
+            ErrorDetails::SyntaxError => "E0201",
+            ErrorDetails::TypeError { .. } => "E0301",
+            ErrorDetails::ArityError { .. } => "E0302",
+            ErrorDetails::NameError => "E0401",
+            ErrorDetails::ProgramError => "E0501",
+            ErrorDetails::RunnerError => "E0601",
+            ErrorDetails::Bug => "E0901",
+        }
+    }
+
+    /// Name of the variant (used in the JSON output)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ErrorDetails::ParseError { .. } => "ParseError",
+            ErrorDetails::SyntaxError => "SyntaxError",
+            ErrorDetails::TypeError { .. } => "TypeError",
+            ErrorDetails::ArityError { .. } => "ArityError",
+            ErrorDetails::NameError => "NameError",
+            ErrorDetails::ProgramError => "ProgramError",
+            ErrorDetails::RunnerError => "RunnerError",
+            ErrorDetails::Bug => "Bug",
+        }
+    }
+}
+
+/// Replace the `**emphasized**` parts of an error message with
+/// ANSI escape sequences (bold red)
+pub fn colorize_message(msg: &str) -> String {
+    let mut ret = String::new();
+    for (i, part) in msg.split("**").enumerate() {
+        if i % 2 == 1 {
+            ret.push_str("\x1b[1;31m");
+            ret.push_str(part);
+            ret.push_str("\x1b[0m");
+        } else {
+            ret.push_str(part);
+        }
+    }
+    ret
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.msg)
@@ -40,6 +167,7 @@ pub fn syntax_error(msg: &str) -> Error {
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::SyntaxError,
         source: None,
+        span: None,
     }
 }
 
@@ -47,8 +175,37 @@ pub fn type_error(msg: &str) -> Error {
     Error {
         msg: msg.to_string(),
         backtrace: backtrace::Backtrace::new(),
-        details: ErrorDetails::TypeError,
+        details: ErrorDetails::TypeError {
+            expected: None,
+            actual: None,
+        },
         source: None,
+        span: None,
+    }
+}
+
+/// A type error where `actual` is given but `expected` is required
+pub fn type_mismatch(msg: &str, expected: &TermTy, actual: &TermTy) -> Error {
+    Error {
+        msg: msg.to_string(),
+        backtrace: backtrace::Backtrace::new(),
+        details: ErrorDetails::TypeError {
+            expected: Some(expected.fullname.0.clone()),
+            actual: Some(actual.fullname.0.clone()),
+        },
+        source: None,
+        span: None,
+    }
+}
+
+/// Wrong number of arguments
+pub fn arity_error(msg: &str, expected: usize, actual: usize) -> Error {
+    Error {
+        msg: msg.to_string(),
+        backtrace: backtrace::Backtrace::new(),
+        details: ErrorDetails::ArityError { expected, actual },
+        source: None,
+        span: None,
     }
 }
 
@@ -58,6 +215,7 @@ pub fn name_error(msg: &str) -> Error {
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::NameError,
         source: None,
+        span: None,
     }
 }
 
@@ -67,6 +225,7 @@ pub fn program_error(msg: &str) -> Error {
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::ProgramError,
         source: None,
+        span: None,
     }
 }
 
@@ -76,6 +235,18 @@ pub fn runner_error(msg: impl Into<String>, source: impl std::error::Error + 'st
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::RunnerError,
         source: Some(Box::new(source)),
+        span: None,
+    }
+}
+
+/// An internal error of the compiler
+pub fn bug(msg: impl Into<String>) -> Error {
+    Error {
+        msg: msg.into(),
+        backtrace: backtrace::Backtrace::new(),
+        details: ErrorDetails::Bug,
+        source: None,
+        span: None,
     }
 }
 
@@ -85,5 +256,6 @@ pub fn plain_runner_error(msg: impl Into<String>) -> Error {
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::RunnerError,
         source: None,
+        span: None,
     }
 }
@@ -0,0 +1,167 @@
+/// Registry of error codes and their explanations.
+/// Used by `shiika explain`.
+pub struct ErrorCode {
+    /// eg. "E0301"
+    pub code: &'static str,
+    /// One-line summary
+    pub title: &'static str,
+    /// Extended explanation with examples
+    pub explanation: &'static str,
+}
+
+pub static ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0101",
+        title: "parse error",
+        explanation: "\
+The source code could not be parsed.
+
+This usually means there is a typo or a missing token (eg. `end`, `)`).
+
+Example:
+
+    class A
+      def foo -> Int
+        1
+    end          # `end' of the class is missing
+
+Fix: add the missing `end'.
+
+    class A
+      def foo -> Int
+        1
+      end
+    end
+",
+    },
+    ErrorCode {
+        code: "E0201",
+        title: "syntax error",
+        explanation: "\
+The program was parsed but a construct is placed where it is not allowed.
+
+Example:
+
+    def foo    # methods must be defined in a class
+    end
+
+Fix: move the definition into a class.
+
+    class A
+      def foo
+      end
+    end
+",
+    },
+    ErrorCode {
+        code: "E0301",
+        title: "type mismatch",
+        explanation: "\
+The type of an expression does not match the expected type.
+
+Example:
+
+    class A
+      def foo(x: Int) -> Int
+        x
+      end
+    end
+    A.new.foo(1.0)   # passing a Float where an Int is expected
+
+Fix: pass a value of the expected type (or convert the value).
+
+    A.new.foo(1)
+    A.new.foo(1.0.to_i)
+",
+    },
+    ErrorCode {
+        code: "E0302",
+        title: "wrong number of arguments",
+        explanation: "\
+A method (or a lambda) is called with too many or too few arguments.
+
+Example:
+
+    class A
+      def foo(x: Int) -> Int
+        x
+      end
+    end
+    A.new.foo(1, 2)   # A#foo takes 1 argument
+
+Fix: pass as many arguments as the method takes.
+
+    A.new.foo(1)
+",
+    },
+    ErrorCode {
+        code: "E0401",
+        title: "unknown name",
+        explanation: "\
+A name (eg. a class or a constant) is not defined.
+
+Example:
+
+    class A : B   # B is not defined
+    end
+
+Fix: check the spelling, or define the class before using it.
+
+    class B; end
+    class A : B
+    end
+",
+    },
+    ErrorCode {
+        code: "E0501",
+        title: "invalid program",
+        explanation: "\
+The program is syntactically correct but is not valid.
+For example, calling an undefined method or reassigning to a readonly variable.
+
+Example:
+
+    a = 1
+    a = 2     # `a' is readonly
+
+Fix: declare the variable with `var'.
+
+    var a = 1
+    a = 2
+",
+    },
+    ErrorCode {
+        code: "E0601",
+        title: "runner error",
+        explanation: "\
+An error occurred while compiling or running the generated program
+(eg. the source file is not found, or `llc`/`clang` failed).
+
+Fix: check the file path and that the required tools (llc, clang, bdw-gc)
+are installed.
+",
+    },
+    ErrorCode {
+        code: "E0901",
+        title: "internal compiler error",
+        explanation: "\
+This is a bug of the Shiika compiler, not an error of your program.
+
+Please report it with the program which caused the error. Compiling with
+`--verify` adds the LLVM IR and the HIR of the broken function to the
+message, which helps to find the cause.
+",
+    },
+];
+
+/// Find the entry for `code`.
+/// Accepts lowercase and omission of the leading `E` (eg. `e0301`, `0301`)
+pub fn find(code: &str) -> Option<&'static ErrorCode> {
+    let code = code.to_uppercase();
+    let normalized = if code.starts_with('E') {
+        code
+    } else {
+        format!("E{}", code)
+    };
+    ERROR_CODES.iter().find(|e| e.code == normalized)
+}
@@ -0,0 +1,301 @@
+/// Formatter of Shiika programs (`shiika fmt`)
+///
+/// Prints the tokens of a program with canonical indentation (2 spaces
+/// for each `class`, `def`, `if`, etc. and unclosed brackets) and spacing.
+/// Comments and (up to one) blank lines are kept.
+use crate::error;
+use crate::error::Error;
+use crate::parser::lexer::{Lexer, Span};
+use crate::parser::token::Token;
+
+const INDENT: &str = "  ";
+
+/// Return the formatted source of `src`. An error is returned if `src`
+/// has a syntax error
+pub fn format(src: &str) -> Result<String, Error> {
+    // A comment at the end of the file must be terminated by a newline
+    let src = format!("{}\n", src);
+    crate::parser::Parser::parse(&src)?;
+
+    let tokens = Lexer::tokenize(&src);
+    let mut printer = Printer {
+        src: &src,
+        out: String::new(),
+        line: String::new(),
+        line_no: 0,
+        openers: vec![],
+        line_openers: vec![],
+        dedent: 0,
+        line_head: false,
+        newlines: 0,
+        method_name_op: false,
+        splat: false,
+    };
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        let prev = prev_word(&tokens, i);
+        let space_before = i > 0 && tokens[i - 1].0 == Token::Space;
+        printer.print(token, span, prev, space_before);
+    }
+    printer.end_line();
+    let formatted = printer.out;
+
+    // Make sure that the program is not changed
+    if words(&src) != words(&formatted) {
+        return Err(error::bug("[BUG] the formatter changed the program"));
+    }
+    Ok(formatted)
+}
+
+/// Format the file in place. If `check` is true, the file is not
+/// modified. Returns true if the file is (or would be) changed
+pub fn format_file(path: &str, check: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|e| error::runner_error(format!("failed to read {}", path), e))?;
+    let formatted = format(&src)?;
+    if formatted == src {
+        return Ok(false);
+    }
+    if !check {
+        std::fs::write(path, formatted)
+            .map_err(|e| error::runner_error(format!("failed to write {}", path), e))?;
+    }
+    Ok(true)
+}
+
+/// Return the tokens except spaces and separators (comments)
+fn words(src: &str) -> Vec<Token> {
+    Lexer::tokens(src)
+        .map(|(token, _)| token)
+        .filter(|token| *token != Token::Space && *token != Token::Separator)
+        .collect()
+}
+
+/// Return the last token before `i` which is not a space
+fn prev_word(tokens: &[(Token, Span)], i: usize) -> Option<&Token> {
+    tokens[..i]
+        .iter()
+        .rev()
+        .map(|(token, _)| token)
+        .find(|token| **token != Token::Space)
+}
+
+struct Printer<'a> {
+    src: &'a str,
+    out: String,
+    /// The line being printed (without the indentation)
+    line: String,
+    /// Number of the lines printed
+    line_no: usize,
+    /// Line numbers where the unclosed `class`, `def`, `(`, etc. are
+    openers: Vec<usize>,
+    /// `openers` at the beginning of the current line
+    line_openers: Vec<usize>,
+    /// Number of `end`, `)`, etc. at the beginning of the current line
+    dedent: usize,
+    /// Whether no tokens other than them are printed in the current line
+    line_head: bool,
+    /// Number of newlines after the last line
+    newlines: usize,
+    /// Whether the last token is an operator used as a method name
+    /// (eg. `+` of `def +(other)`)
+    method_name_op: bool,
+    /// Whether the last token is `*` of `*rest` (in an array pattern)
+    splat: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn print(&mut self, token: &Token, span: &Span, prev: Option<&Token>, space_before: bool) {
+        let text = span.src(self.src);
+        match token {
+            Token::Space => return,
+            Token::Separator if text.starts_with('#') => {
+                // Comment (keep the spaces before it if not at the
+                // beginning of the line)
+                if self.line.is_empty() {
+                    self.begin_line();
+                } else {
+                    let before = &self.src[..span.begin.pos()];
+                    let spaces = before.len() - before.trim_end_matches(' ').len();
+                    self.line += &" ".repeat(spaces.max(1));
+                }
+                self.line += text.trim_end();
+                self.end_line();
+                // The comment includes the newline (except `#| ... |#`,
+                // which is kept as is even if it has newlines)
+                self.newlines = if text.starts_with("#|") { 0 } else { 1 };
+                return;
+            }
+            Token::Separator => {
+                let newlines = text.matches('\n').count();
+                if newlines == 0 {
+                    self.line += "; ";
+                } else {
+                    self.end_line();
+                    self.newlines += newlines;
+                }
+                return;
+            }
+            _ => (),
+        }
+
+        if self.line.is_empty() {
+            self.begin_line();
+        } else if self.needs_space(token, prev, space_before) {
+            self.line.push(' ');
+        }
+        self.line += text;
+        self.method_name_op = is_binary_op(token) && follows_method_name(prev);
+        self.splat = is_splat(token, prev);
+
+        if is_opener(token, prev, self.line_head && self.dedent == 0) {
+            self.openers.push(self.line_no);
+        } else if is_closer(token) && token != &Token::KwElse && token != &Token::KwWhen {
+            self.openers.pop();
+        }
+        if self.line_head && is_closer(token) {
+            self.dedent += 1;
+        } else {
+            self.line_head = false;
+        }
+    }
+
+    fn begin_line(&mut self) {
+        self.line_openers = self.openers.clone();
+        self.dedent = 0;
+        self.line_head = true;
+    }
+
+    /// Whether to put a space between `prev` and `token`
+    fn needs_space(&self, token: &Token, prev: Option<&Token>, space_before: bool) -> bool {
+        let prev = match prev {
+            Some(t) => t,
+            None => return false,
+        };
+        match token {
+            Token::Comma | Token::RParen | Token::RSqBracket | Token::Dot => false,
+            t if is_splat(t, Some(prev)) => prev == &Token::Comma,
+            t if is_binary_op(t) && !follows_method_name(Some(prev)) => true,
+            _ => match prev {
+                Token::LParen | Token::LSqBracket | Token::Dot | Token::ColonColon => false,
+                Token::Comma => true,
+                t if is_binary_op(t) && !self.method_name_op && !self.splat => true,
+                _ => space_before,
+            },
+        }
+    }
+
+    /// Print the current line and start a new one
+    fn end_line(&mut self) {
+        if self.line.is_empty() {
+            return;
+        }
+        if self.newlines >= 2 && !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.newlines = 0;
+        // Indent once for each line which has unclosed openers, except the
+        // ones closed at the beginning of this line
+        let n_open = self.line_openers.len();
+        let dedent = self.dedent.min(n_open);
+        let mut lines = self.line_openers[..n_open - dedent].to_vec();
+        lines.dedup();
+        self.out += &INDENT.repeat(lines.len());
+        self.out += self.line.trim_end().trim_end_matches(';');
+        self.out.push('\n');
+        self.line.clear();
+        self.line_no += 1;
+    }
+}
+
+/// Whether the token is a binary operator which is surrounded by spaces
+/// (`<` and `>` are not included because they are also used for type
+/// arguments)
+fn is_binary_op(token: &Token) -> bool {
+    match token {
+        Token::BinaryPlus
+        | Token::BinaryMinus
+        | Token::Mul
+        | Token::Div
+        | Token::Mod
+        | Token::EqEq
+        | Token::EqTilde
+        | Token::NotEq
+        | Token::LessEq
+        | Token::GreaterEq
+        | Token::Spaceship
+        | Token::Equal
+        | Token::AndAnd
+        | Token::OrOr
+        | Token::RightArrow => true,
+        _ => false,
+    }
+}
+
+/// Whether an operator after `prev` is (a part of) a method name
+/// (eg. `def +(other)`, `a.+(b)`, `def []=(i: Int, v: T)`)
+fn follows_method_name(prev: Option<&Token>) -> bool {
+    match prev {
+        Some(Token::KwDef) | Some(Token::Dot) | Some(Token::GetMethod) => true,
+        _ => false,
+    }
+}
+
+/// Whether the token is `*` of `*rest` (eg. `when [a, *rest]`)
+fn is_splat(token: &Token, prev: Option<&Token>) -> bool {
+    match prev {
+        Some(Token::Comma) | Some(Token::LSqBracket) => token == &Token::Mul,
+        _ => false,
+    }
+}
+
+/// Whether the token begins a block which ends with `end` or a bracket
+fn is_opener(token: &Token, prev: Option<&Token>, at_line_head: bool) -> bool {
+    match token {
+        Token::KwClass | Token::KwCase | Token::KwLoop => true,
+        Token::KwDef => prev != Some(&Token::KwAbstract) && prev != Some(&Token::KwExtern),
+        // Not an opener if it is a modifier (eg. `foo if bar`)
+        Token::KwIf | Token::KwUnless | Token::KwWhile | Token::KwFor => {
+            at_line_head || !prev.map_or(false, ends_value)
+        }
+        Token::LParen | Token::LSqBracket | Token::LBrace => true,
+        _ => false,
+    }
+}
+
+/// Whether an expression may end with the token
+pub(crate) fn ends_value(token: &Token) -> bool {
+    match token {
+        Token::UpperWord(_)
+        | Token::LowerWord(_)
+        | Token::IVar(_)
+        | Token::Number(_)
+        | Token::Str(_)
+        | Token::Char(_)
+        | Token::Regexp(_)
+        | Token::Command(_)
+        | Token::RParen
+        | Token::RSqBracket
+        | Token::RBrace
+        | Token::KwEnd
+        | Token::KwBreak
+        | Token::KwNext
+        | Token::KwSelf
+        | Token::KwTrue
+        | Token::KwFalse => true,
+        _ => false,
+    }
+}
+
+/// Whether the token ends a block (`else` ends the `then` clause and
+/// `when` ends the previous one)
+fn is_closer(token: &Token) -> bool {
+    match token {
+        Token::KwEnd
+        | Token::KwElse
+        | Token::KwWhen
+        | Token::RParen
+        | Token::RSqBracket
+        | Token::RBrace => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,85 @@
+use crate::code_gen::CodeGen;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+
+impl HirMaker {
+    /// Define a method for each abstract method, which calls the
+    /// implementation according to the class of the receiver
+    pub(super) fn define_abstract_method_dispatchers(&mut self) {
+        let mut dispatchers = vec![];
+        for class in self.class_dict.sk_classes.values() {
+            for name in &class.abstract_methods {
+                let sig = class.method_sigs[name].clone();
+                let impls = self
+                    .class_dict
+                    .abstract_method_implementations(&class.fullname, name);
+                dispatchers.push((class.fullname.clone(), create_dispatcher(sig, impls)));
+            }
+        }
+        for (clsname, method) in dispatchers {
+            self.method_dict.add_method(&clsname, method);
+        }
+    }
+}
+
+/// `impls`: list of (receiver class, class which implements the method)
+fn create_dispatcher(sig: MethodSignature, impls: Vec<(ClassFullname, ClassFullname)>) -> SkMethod {
+    let method_name = sig.first_name().0.clone();
+    let dispatcher_body = move |code_gen: &CodeGen, function: &inkwell::values::FunctionValue| {
+        let this = function.get_params()[0];
+        let class_id = code_gen.build_class_id_load(this);
+        let no_impl_block = code_gen.context.append_basic_block(*function, "NoImpl");
+        let cases = impls
+            .iter()
+            .map(|(cls, _)| {
+                let block = code_gen
+                    .context
+                    .append_basic_block(*function, &format!("Call_{}", cls.0));
+                (code_gen.class_id(cls), block)
+            })
+            .collect::<Vec<_>>();
+        code_gen
+            .builder
+            .build_switch(class_id, no_impl_block, &cases);
+
+        for ((_, owner), (_, block)) in impls.iter().zip(cases.iter()) {
+            code_gen.builder.position_at_end(*block);
+            let func_name = method_fullname(owner, &method_name);
+            let func = code_gen
+                .module
+                .get_function(&func_name.full_name)
+                .unwrap_or_else(|| panic!("[BUG] function `{}' not found", &func_name));
+            let receiver_type = code_gen
+                .llvm_struct_types
+                .get(owner)
+                .expect("[BUG] struct type not found")
+                .ptr_type(inkwell::AddressSpace::Generic);
+            let mut args = function.get_params();
+            args[0] = code_gen
+                .builder
+                .build_bitcast(this, receiver_type, "receiver");
+            let result = code_gen
+                .builder
+                .build_call(func, &args, "result")
+                .try_as_basic_value()
+                .left();
+            match result {
+                Some(value) => code_gen.builder.build_return(Some(&value)),
+                None => code_gen.builder.build_return(None),
+            };
+        }
+
+        // Should not happen because non-abstract classes are
+        // checked to implement all the abstract methods
+        code_gen.builder.position_at_end(no_impl_block);
+        code_gen.builder.build_unreachable();
+        Ok(())
+    };
+
+    SkMethod {
+        signature: sig,
+        body: SkMethodBody::RustClosureMethodBody {
+            boxed_gen: Box::new(dispatcher_body),
+        },
+    }
+}
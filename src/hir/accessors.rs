@@ -1,6 +1,7 @@
 use crate::code_gen::CodeGen;
 use crate::hir::hir_maker::HirMaker;
 use crate::hir::*;
+use std::collections::HashSet;
 
 impl HirMaker {
     /// Define getters and setters (unless there is a method of the same name)
@@ -12,16 +13,14 @@ impl HirMaker {
     ) {
         let method_names = defs
             .iter()
-            .filter_map(|def| {
-                if let ast::Definition::InstanceMethodDefinition { sig, .. } = def {
-                    Some(&sig.name.0)
-                } else {
-                    None
-                }
+            .filter_map(|def| match def {
+                ast::Definition::InstanceMethodDefinition { sig, .. }
+                | ast::Definition::AbstractMethodDefinition { sig } => Some(sig.name.0.as_str()),
+                _ => None,
             })
-            .collect::<Vec<_>>();
+            .collect::<HashSet<_>>();
         for (name, ivar) in ivars {
-            if !method_names.iter().any(|x| ***x == name) {
+            if !method_names.contains(name.as_str()) {
                 let getter = create_getter(&clsname, &ivar);
                 let sig = getter.signature.clone();
                 self.method_dict.add_method(&clsname, getter);
@@ -29,7 +28,7 @@ impl HirMaker {
             }
 
             let setter_name = format!("{}=", name);
-            if !method_names.iter().any(|x| ***x == setter_name) {
+            if !method_names.contains(setter_name.as_str()) {
                 let setter = create_setter(&clsname, &ivar);
                 let sig = setter.signature.clone();
                 self.method_dict.add_method(&clsname, setter);
@@ -44,6 +43,7 @@ fn create_getter(clsname: &ClassFullname, ivar: &SkIVar) -> SkMethod {
         fullname: method_fullname(clsname, &ivar.name),
         ret_ty: ivar.ty.clone(),
         params: vec![],
+        typarams: vec![],
     };
     let name = ivar.name.clone(); // Clone to embed into the closure
     let idx = ivar.idx;
@@ -70,13 +70,16 @@ fn create_setter(clsname: &ClassFullname, ivar: &SkIVar) -> SkMethod {
         params: vec![MethodParam {
             name: ivar.name.clone(),
             ty: ivar.ty.clone(),
+            implicit_conv: false,
         }],
+        typarams: vec![],
     };
     let idx = ivar.idx;
+    let ivar_name = ivar.name.clone();
     let getter_body = move |code_gen: &CodeGen, function: &inkwell::values::FunctionValue| {
         let this = function.get_params()[0];
         let val = function.get_params()[1];
-        code_gen.build_ivar_store(&this, idx, val, &name);
+        code_gen.build_checked_ivar_store(&this, idx, val, &ivar_name);
         code_gen.builder.build_return(Some(&val));
         Ok(())
     };
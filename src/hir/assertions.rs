@@ -0,0 +1,71 @@
+/// `panic(msg)` and `assert(cond, msg)`
+///
+/// A call without a receiver is converted into `panic_at(place, msg)` or
+/// `assert_at(place, cond, msg)` (see builtin/object.sk), where `place` is
+/// the method which calls it (eg. `A#foo`) or `main`, so that the message
+/// tells where the program stopped. (`shiika test` passes `path:line`
+/// instead; see test_runner.rs.)
+///
+/// In a release build (`--release`), `assert(cond, msg)` and
+/// `assert_at(place, cond, msg)` are removed and their arguments are not
+/// evaluated. `assert(cond)` of the tests is not removed.
+///
+/// TODO: Print a backtrace of the Shiika frames (needs debug info)
+use crate::ast::AstExpression;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+
+impl HirMaker {
+    /// Convert a call of `panic` or `assert` without a receiver. Returns
+    /// `None` for other calls (and for the methods redefined in the class
+    /// of `self`)
+    pub(super) fn convert_assertion(
+        &mut self,
+        method_name: &MethodFirstname,
+        arg_exprs: &[AstExpression],
+    ) -> Result<Option<HirExpression>, Error> {
+        let arity = match method_name.0.as_str() {
+            "panic" => 1,
+            "assert" => 2,
+            "assert_at" => 3,
+            _ => return Ok(None),
+        };
+        let receiver_hir = self.convert_self_expr()?;
+        if arg_exprs.len() != arity || !self.is_object_method(&receiver_hir.ty, method_name, arity)
+        {
+            return Ok(None);
+        }
+        if method_name.0 != "panic" && self.config.release {
+            return Ok(Some(Hir::const_ref(
+                ty::raw("Void"),
+                const_fullname("::Void"),
+            )));
+        }
+        if method_name.0 == "assert_at" {
+            return Ok(None);
+        }
+        let place = self.current_place();
+        let mut arg_hirs = vec![Hir::string_literal(self.register_string_literal(&place))];
+        for expr in arg_exprs {
+            arg_hirs.push(self.convert_expr(expr)?);
+        }
+        let name = method_firstname(&format!("{}_at", method_name.0));
+        self.make_method_call(receiver_hir, &name, arg_hirs)
+            .map(Some)
+    }
+
+    /// Whether the method called with `arity` args is the one of `Object`
+    fn is_object_method(
+        &self,
+        self_ty: &TermTy,
+        method_name: &MethodFirstname,
+        arity: usize,
+    ) -> bool {
+        let name = self.class_dict.overload_name(self_ty, method_name, arity);
+        match self.class_dict.lookup_method(self_ty, &name) {
+            Ok((_, owner)) => owner.0 == "Object",
+            Err(_) => false,
+        }
+    }
+}
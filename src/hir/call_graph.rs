@@ -0,0 +1,169 @@
+/// Call graph of methods and dependency graph of classes
+/// (`shiika compile --emit callgraph`)
+use crate::error::json_str;
+use crate::hir::const_init_order::{collect_refs_in_expr, collect_refs_in_exprs, Refs};
+use crate::hir::*;
+use std::collections::BTreeSet;
+
+/// Name of the caller for the toplevel expressions
+pub const MAIN: &str = "main";
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum ClassDepKind {
+    /// The class inherits the other
+    Superclass,
+    /// A method of the class calls a method of the other
+    Call,
+    /// A method of the class refers the other as a value (eg. `B.new`)
+    Ref,
+}
+
+impl ClassDepKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClassDepKind::Superclass => "superclass",
+            ClassDepKind::Call => "call",
+            ClassDepKind::Ref => "ref",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    /// (caller, callee). Callers are full names of methods (eg. `A#foo`,
+    /// `Meta:A#new`), `MAIN` or full names of constants (for their
+    /// initializers)
+    pub calls: BTreeSet<(String, String)>,
+    /// (class, depended class, kind)
+    pub class_deps: BTreeSet<(String, String, ClassDepKind)>,
+}
+
+impl CallGraph {
+    pub fn new(hir: &Hir) -> CallGraph {
+        let mut graph = CallGraph::default();
+        for methods in hir.sk_methods.values() {
+            for method in methods {
+                if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
+                    let mut refs = Refs::default();
+                    collect_refs_in_exprs(exprs, &mut refs);
+                    graph.add(&method.signature.fullname.full_name, refs);
+                }
+            }
+        }
+        for expr in &hir.const_inits {
+            if let HirExpressionBase::HirConstAssign { fullname, rhs } = &expr.node {
+                let mut refs = Refs::default();
+                collect_refs_in_expr(rhs, &mut refs);
+                graph.add(&fullname.0, refs);
+            }
+        }
+        let mut refs = Refs::default();
+        collect_refs_in_exprs(&hir.main_exprs, &mut refs);
+        graph.add(MAIN, refs);
+
+        for class in hir.sk_classes.values() {
+            if let Some(sup) = &class.superclass_fullname {
+                if !class.fullname.is_meta() {
+                    graph.class_deps.insert((
+                        class.fullname.0.clone(),
+                        sup.0.clone(),
+                        ClassDepKind::Superclass,
+                    ));
+                }
+            }
+        }
+        graph
+    }
+
+    fn add(&mut self, caller: &str, refs: Refs) {
+        for callee in refs.methods {
+            if let (Some(from), Some(to)) = (class_of(caller), class_of(&callee)) {
+                if from != to {
+                    let dep = (from.to_string(), to.to_string(), ClassDepKind::Call);
+                    self.class_deps.insert(dep);
+                }
+            }
+            self.calls.insert((caller.to_string(), callee));
+        }
+        if let Some(from) = class_of(caller) {
+            for class in refs.classes {
+                let to = class.0.trim_start_matches("Meta:");
+                if from != to {
+                    let dep = (from.to_string(), to.to_string(), ClassDepKind::Ref);
+                    self.class_deps.insert(dep);
+                }
+            }
+        }
+    }
+
+    /// Remove the edges whose caller (or depending class) does not
+    /// satisfy `f` (eg. to omit the builtin library)
+    pub fn retain<F: Fn(&str) -> bool>(&mut self, f: F) {
+        self.calls = std::mem::take(&mut self.calls)
+            .into_iter()
+            .filter(|(caller, _)| f(caller))
+            .collect();
+        self.class_deps = std::mem::take(&mut self.class_deps)
+            .into_iter()
+            .filter(|(class, _, _)| f(class))
+            .collect();
+    }
+
+    /// Return the graph in the DOT language of Graphviz. Class
+    /// dependencies are drawn with dashed lines
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph callgraph {\n");
+        for (caller, callee) in &self.calls {
+            s += &format!("  {} -> {};\n", json_str(caller), json_str(callee));
+        }
+        for (class, dep, kind) in &self.class_deps {
+            s += &format!(
+                "  {} -> {} [style=dashed, label={}];\n",
+                json_str(class),
+                json_str(dep),
+                kind.name()
+            );
+        }
+        s += "}\n";
+        s
+    }
+
+    /// Return the graph as JSON
+    /// (`{"calls":[{"from":..,"to":..}],"class_deps":[{"from":..,"to":..,"kind":..}]}`)
+    pub fn to_json(&self) -> String {
+        let calls = self
+            .calls
+            .iter()
+            .map(|(caller, callee)| {
+                format!(
+                    "{{\"from\":{},\"to\":{}}}",
+                    json_str(caller),
+                    json_str(callee)
+                )
+            })
+            .collect::<Vec<_>>();
+        let class_deps = self
+            .class_deps
+            .iter()
+            .map(|(class, dep, kind)| {
+                format!(
+                    "{{\"from\":{},\"to\":{},\"kind\":{}}}",
+                    json_str(class),
+                    json_str(dep),
+                    json_str(kind.name())
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"calls\":[{}],\"class_deps\":[{}]}}\n",
+            calls.join(","),
+            class_deps.join(",")
+        )
+    }
+}
+
+/// Return the class of the method `name` (`A` for `A#foo` and `Meta:A#foo`)
+pub fn class_of(name: &str) -> Option<&str> {
+    let i = name.find('#')?;
+    Some(name[..i].trim_start_matches("Meta:"))
+}
@@ -0,0 +1,187 @@
+use crate::error;
+use crate::error::*;
+use crate::hir::class_dict::class_dict::ClassDict;
+use crate::hir::*;
+use crate::names::*;
+use std::collections::HashMap;
+
+impl ClassDict {
+    /// Check that each non-abstract class implements all the abstract
+    /// methods declared in its ancestors
+    pub fn check_abstract_methods(&self) -> Result<(), Error> {
+        let mut names = self.sk_classes.keys().collect::<Vec<_>>();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        for name in names {
+            let class = self.get_class(name, "ClassDict::check_abstract_methods");
+            if name.is_meta() || class.is_abstract() {
+                continue;
+            }
+            self.check_class_conformance(class)?;
+        }
+        Ok(())
+    }
+
+    fn check_class_conformance(&self, class: &SkClass) -> Result<(), Error> {
+        // Concrete methods found so far (from `class` to the ancestors)
+        let mut implemented: HashMap<&MethodFirstname, &MethodSignature> = HashMap::new();
+        let mut missing = vec![];
+        for cls in self.ancestor_classes(&class.fullname) {
+            for name in &cls.abstract_methods {
+                let abstract_sig = &cls.method_sigs[name];
+                match implemented.get(name) {
+                    Some(sig) => check_signature_conformance(sig, abstract_sig)?,
+                    None => missing.push(abstract_sig.fullname.to_string()),
+                }
+            }
+            for (name, sig) in &cls.method_sigs {
+                if !cls.abstract_methods.contains(name) {
+                    implemented.entry(name).or_insert(sig);
+                }
+            }
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(error::program_error(&format!(
+                "class `{}' must implement the abstract method(s): {}",
+                class.fullname,
+                missing.join(", ")
+            )))
+        }
+    }
+
+    /// Return the non-abstract descendants of `fullname` and the class
+    /// which implements the abstract method `method_name` for each of them
+    pub fn abstract_method_implementations(
+        &self,
+        fullname: &ClassFullname,
+        method_name: &MethodFirstname,
+    ) -> Vec<(ClassFullname, ClassFullname)> {
+        let mut impls = self
+            .sk_classes
+            .values()
+            .filter(|cls| !cls.is_abstract() && cls.fullname != *fullname)
+            .filter(|cls| {
+                self.ancestor_classes(&cls.fullname)
+                    .iter()
+                    .any(|c| c.fullname == *fullname)
+            })
+            .filter_map(|cls| {
+                self.ancestor_classes(&cls.fullname)
+                    .into_iter()
+                    .find(|c| {
+                        c.method_sigs.contains_key(method_name)
+                            && !c.abstract_methods.contains(method_name)
+                    })
+                    .map(|c| (cls.fullname.clone(), c.fullname.clone()))
+            })
+            .collect::<Vec<_>>();
+        impls.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+        impls
+    }
+
+    /// Check that a class which overrides one of the overloads of a method
+    /// (see parser/overloading.rs) overrides all of them. Otherwise a call
+    /// would go to the class for some numbers of args and to the superclass
+    /// for the others
+    pub fn check_overloads(&self) -> Result<(), Error> {
+        let mut names = self.sk_classes.keys().collect::<Vec<_>>();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        for name in names {
+            let ancestors = self.ancestor_classes(name);
+            let inherited = ancestors[1..]
+                .iter()
+                .map(|cls| method_arities(cls))
+                .collect::<Vec<_>>();
+            let own = method_arities(ancestors[0]);
+            let mut method_names = own.keys().collect::<Vec<_>>();
+            method_names.sort();
+            for method_name in method_names {
+                let arities = &own[method_name].0;
+                let required = match inherited.iter().find_map(|m| m.get(method_name)) {
+                    Some((required, true)) => required,
+                    _ => continue,
+                };
+                let mut missing = required
+                    .iter()
+                    .filter(|n| !arities.contains(n))
+                    .collect::<Vec<_>>();
+                if missing.is_empty() || !arities.iter().any(|n| required.contains(n)) {
+                    continue;
+                }
+                missing.sort();
+                return Err(error::program_error(&format!(
+                    "class `{}' must override all the overloads of `{}' (missing the one(s) with {} param(s))",
+                    name,
+                    method_name,
+                    missing
+                        .iter()
+                        .map(|n| n.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the numbers of the params of the methods of `class` by the name
+/// before overloading, and whether the name is overloaded (eg. `log` =>
+/// `([1, 2], true)` for `log/1` and `log/2`)
+fn method_arities(class: &SkClass) -> HashMap<&str, (Vec<usize>, bool)> {
+    let mut arities: HashMap<&str, (Vec<usize>, bool)> = HashMap::new();
+    for (name, sig) in &class.method_sigs {
+        let (base, overloaded) = match overloaded_base(&name.0) {
+            Some(base) => (base, true),
+            None => (name.0.as_str(), false),
+        };
+        let entry = arities.entry(base).or_default();
+        entry.0.push(sig.params.len());
+        entry.1 |= overloaded;
+    }
+    arities
+}
+
+/// Return `log` for `log/2` (and None for the names not overloaded, like `/`)
+fn overloaded_base(name: &str) -> Option<&str> {
+    let idx = name.rfind('/')?;
+    let arity = &name[idx + 1..];
+    if idx > 0 && !arity.is_empty() && arity.bytes().all(|b| b.is_ascii_digit()) {
+        Some(&name[..idx])
+    } else {
+        None
+    }
+}
+
+/// Check that the implementation has the same signature as the abstract method
+fn check_signature_conformance(
+    sig: &MethodSignature,
+    abstract_sig: &MethodSignature,
+) -> Result<(), Error> {
+    let param_tys = sig.params.iter().map(|p| &p.ty).collect::<Vec<_>>();
+    let abstract_param_tys = abstract_sig
+        .params
+        .iter()
+        .map(|p| &p.ty)
+        .collect::<Vec<_>>();
+    if param_tys != abstract_param_tys || sig.ret_ty != abstract_sig.ret_ty {
+        return Err(error::type_error(&format!(
+            "method `{}' does not match the abstract method `{}' (expected ({}) -> {}, got ({}) -> {})",
+            sig.fullname,
+            abstract_sig.fullname,
+            format_tys(&abstract_param_tys),
+            abstract_sig.ret_ty,
+            format_tys(&param_tys),
+            sig.ret_ty
+        )));
+    }
+    Ok(())
+}
+
+fn format_tys(tys: &[&TermTy]) -> String {
+    tys.iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -2,11 +2,12 @@ use crate::ast;
 use crate::error;
 use crate::error::*;
 use crate::hir::class_dict::class_dict::ClassDict;
+use crate::hir::derive;
 use crate::hir::signature;
 use crate::hir::*;
 use crate::names::*;
 use crate::ty::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 impl ClassDict {
     /// Define ivars of a class
@@ -45,25 +46,41 @@ impl ClassDict {
         corelib.into_iter().for_each(|(_, c)| {
             self.add_class(SkClass {
                 fullname: c.fullname,
-                typarams: vec![],
+                typarams: c.typarams,
                 superclass_fullname: c.superclass_fullname,
                 instance_ty: c.instance_ty,
                 ivars: c.ivars,
                 method_sigs: c.method_sigs,
                 const_is_obj: c.const_is_obj,
+                abstract_methods: c.abstract_methods,
             })
         });
     }
 
     pub fn index_program(&mut self, toplevel_defs: &[&ast::Definition]) -> Result<(), Error> {
+        // Collect class names beforehand to resolve names in signatures
+        let mut class_names = self.sk_classes.keys().cloned().collect::<HashSet<_>>();
+        for def in toplevel_defs {
+            collect_class_names(def, &class_fullname(""), &mut class_names);
+        }
+
+        let mut indexed = HashSet::new();
         toplevel_defs.iter().try_for_each(|def| match def {
             ast::Definition::ClassDefinition {
                 name,
                 typarams,
                 super_name,
                 defs,
+                ..
             } => {
-                self.index_class(&name.add_namespace(""), &typarams, &super_name, &defs)?;
+                self.index_class(
+                    &name.add_namespace(""),
+                    &typarams,
+                    &super_name,
+                    &defs,
+                    &class_names,
+                    &mut indexed,
+                )?;
                 Ok(())
             }
             ast::Definition::ConstDefinition { .. } => Ok(()),
@@ -80,38 +97,81 @@ impl ClassDict {
         typarams: &[String],
         super_name: &ClassFullname,
         defs: &[ast::Definition],
+        class_names: &HashSet<ClassFullname>,
+        indexed: &mut HashSet<ClassFullname>,
     ) -> Result<(), Error> {
+        // The first definition of a corelib class is not a reopening
+        let reopened = !indexed.insert(fullname.clone());
+        let class_exists = |name: &ClassFullname| class_names.contains(name);
+        // Superclass name is resolved in the outer namespace
+        let super_name = resolve_class_name(&super_name.0, &fullname.namespace(), &class_exists)
+            .unwrap_or_else(|| super_name.clone());
         let instance_ty = ty::raw(&fullname.0);
         let class_ty = instance_ty.meta_ty();
 
         let metaclass_fullname = class_ty.fullname.clone();
         let mut instance_methods = HashMap::new();
         let mut class_methods = HashMap::new();
+        let mut abstract_methods = vec![];
         let new_sig = signature::signature_of_new(
             &metaclass_fullname,
-            self.initializer_params(&super_name.instance_ty(), &defs),
+            self.initializer_params(
+                &super_name.instance_ty(),
+                &fullname,
+                typarams,
+                &defs,
+                &class_exists,
+            ),
             &instance_ty,
         );
 
         for def in defs {
             match def {
                 ast::Definition::InstanceMethodDefinition { sig, .. } => {
-                    let hir_sig = signature::create_signature(&fullname, sig, typarams);
+                    let sig = signature::resolve_signature(sig, &fullname, typarams, &class_exists);
+                    let hir_sig = signature::create_signature(&fullname, &sig, typarams);
                     instance_methods.insert(sig.name.clone(), hir_sig);
                 }
+                ast::Definition::AbstractMethodDefinition { sig } => {
+                    let sig = signature::resolve_signature(sig, &fullname, typarams, &class_exists);
+                    let hir_sig = signature::create_signature(&fullname, &sig, typarams);
+                    instance_methods.insert(sig.name.clone(), hir_sig);
+                    abstract_methods.push(sig.name.clone());
+                }
+                // The type parameters of the class can be used in the
+                // signature (eg. `Result<Int, String>.ok(1)`)
                 ast::Definition::ClassMethodDefinition { sig, .. } => {
-                    let hir_sig = signature::create_signature(&metaclass_fullname, sig, &[]);
+                    let sig = signature::resolve_signature(sig, &fullname, typarams, &class_exists);
+                    let hir_sig = signature::create_signature(&metaclass_fullname, &sig, typarams);
+                    class_methods.insert(sig.name.clone(), hir_sig);
+                }
+                ast::Definition::ExternMethodDefinition { sig } => {
+                    let sig = signature::resolve_signature(sig, &fullname, &[], &class_exists);
+                    let hir_sig = signature::create_signature(&metaclass_fullname, &sig, &[]);
                     class_methods.insert(sig.name.clone(), hir_sig);
                 }
-                ast::Definition::ConstDefinition { .. } => (),
+                // The methods are defined in hir/delegation.rs (the type of
+                // the ivar is not known yet)
+                ast::Definition::ConstDefinition { .. } | ast::Definition::Delegation { .. } => (),
+                // The bodies are made in hir/derive.rs
+                ast::Definition::Derive { traits, .. } => {
+                    for name in derive::derived_method_names(traits, defs)? {
+                        let sig = derive::derived_signature(&fullname, typarams, name);
+                        let sig =
+                            signature::resolve_signature(&sig, &fullname, typarams, &class_exists);
+                        let hir_sig = signature::create_signature(&fullname, &sig, typarams);
+                        instance_methods.insert(sig.name.clone(), hir_sig);
+                    }
+                }
                 ast::Definition::ClassDefinition {
                     name,
                     typarams,
                     super_name,
                     defs,
+                    ..
                 } => {
                     let full = name.add_namespace(&fullname.0);
-                    self.index_class(&full, &typarams, &super_name, &defs)?;
+                    self.index_class(&full, &typarams, &super_name, &defs, class_names, indexed)?;
                 }
             }
         }
@@ -119,11 +179,19 @@ impl ClassDict {
         match self.sk_classes.get_mut(&fullname) {
             Some(class) => {
                 // Merge methods to existing class (Class is reopened)
+                if reopened {
+                    check_reopening(class, &super_name, typarams, defs)?;
+                    check_redefinitions(&class.method_sigs, &instance_methods)?;
+                }
                 class.method_sigs.extend(instance_methods);
+                class.abstract_methods.extend(abstract_methods);
                 let metaclass = self
                     .sk_classes
                     .get_mut(&metaclass_fullname)
                     .expect("[BUG] Only class is indexed");
+                if reopened {
+                    check_redefinitions(&metaclass.method_sigs, &class_methods)?;
+                }
                 metaclass.method_sigs.extend(class_methods);
                 // Add `.new` to the metaclass
                 if !metaclass.method_sigs.contains_key(&method_firstname("new")) {
@@ -143,12 +211,18 @@ impl ClassDict {
                 }
                 self.add_class(SkClass {
                     fullname: fullname.clone(),
-                    typarams: vec![],
+                    typarams: typarams
+                        .iter()
+                        .map(|name| TyParam {
+                            name: name.to_string(),
+                        })
+                        .collect(),
                     superclass_fullname: Some(super_name.clone()),
                     instance_ty,
                     ivars: HashMap::new(),
                     method_sigs: instance_methods,
                     const_is_obj: false,
+                    abstract_methods,
                 });
                 self.add_class(SkClass {
                     fullname: metaclass_fullname,
@@ -158,9 +232,91 @@ impl ClassDict {
                     ivars: HashMap::new(),
                     method_sigs: class_methods,
                     const_is_obj: false,
+                    abstract_methods: vec![],
                 });
             }
         }
         Ok(())
     }
 }
+
+/// Check the definition which reopens `class` does not change the class
+/// itself
+fn check_reopening(
+    class: &SkClass,
+    super_name: &ClassFullname,
+    typarams: &[String],
+    defs: &[ast::Definition],
+) -> Result<(), Error> {
+    // The superclass may be omitted
+    if super_name.0 != "Object" && class.superclass_fullname.as_ref() != Some(super_name) {
+        return Err(error::program_error(&format!(
+            "cannot change the superclass of {} to {} by reopening it",
+            class.fullname.0, super_name.0
+        )));
+    }
+    if typarams.len() != class.typarams.len() {
+        return Err(error::program_error(&format!(
+            "{} has {} type parameter(s) but reopened with {}",
+            class.fullname.0,
+            class.typarams.len(),
+            typarams.len()
+        )));
+    }
+    // The ivars and `.new` are made from `#initialize`
+    if defs.iter().any(|def| def.is_initializer()) {
+        return Err(error::program_error(&format!(
+            "cannot define #initialize by reopening {}",
+            class.fullname.0
+        )));
+    }
+    Ok(())
+}
+
+/// Check the methods defined by reopening a class have the same types as
+/// the ones they replace
+fn check_redefinitions(
+    method_sigs: &HashMap<MethodFirstname, MethodSignature>,
+    new_sigs: &HashMap<MethodFirstname, MethodSignature>,
+) -> Result<(), Error> {
+    for (name, new_sig) in new_sigs {
+        if let Some(sig) = method_sigs.get(name) {
+            let param_tys =
+                |sig: &MethodSignature| sig.params.iter().map(|p| p.ty.clone()).collect::<Vec<_>>();
+            if param_tys(sig) != param_tys(new_sig) || sig.ret_ty != new_sig.ret_ty {
+                return Err(error::program_error(&format!(
+                    "cannot change the signature of {} by reopening the class (was: {}, now: {})",
+                    sig.fullname,
+                    signature_to_s(sig),
+                    signature_to_s(new_sig)
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// eg. `(Int, String) -> Bool`
+fn signature_to_s(sig: &MethodSignature) -> String {
+    let params = sig
+        .params
+        .iter()
+        .map(|p| p.ty.fullname.0.clone())
+        .collect::<Vec<_>>();
+    format!("({}) -> {}", params.join(", "), sig.ret_ty.fullname.0)
+}
+
+/// Collect the fullnames of the classes defined in `def`
+fn collect_class_names(
+    def: &ast::Definition,
+    namespace: &ClassFullname,
+    class_names: &mut HashSet<ClassFullname>,
+) {
+    if let ast::Definition::ClassDefinition { name, defs, .. } = def {
+        let fullname = name.add_namespace(&namespace.0);
+        for d in defs {
+            collect_class_names(d, &fullname, class_names);
+        }
+        class_names.insert(fullname);
+    }
+}
@@ -1,5 +1,6 @@
 mod class_dict;
 pub use crate::hir::class_dict::class_dict::ClassDict;
+mod conformance;
 mod indexing;
 mod query;
 use crate::ast;
@@ -30,13 +31,21 @@ pub fn create(
 
 impl ClassDict {
     /// Return parameters of `initialize`
-    fn initializer_params(&self, class: &TermTy, defs: &[ast::Definition]) -> Vec<MethodParam> {
+    fn initializer_params(
+        &self,
+        class: &TermTy,
+        namespace: &ClassFullname,
+        typarams: &[String],
+        defs: &[ast::Definition],
+        class_exists: &dyn Fn(&ClassFullname) -> bool,
+    ) -> Vec<MethodParam> {
         if let Some(ast::Definition::InstanceMethodDefinition { sig, .. }) =
             defs.iter().find(|d| d.is_initializer())
         {
             // Has explicit initializer definition
-            // TODO: Support typarams in initializer params
-            hir::signature::convert_params(&sig.params, &[])
+            let params =
+                hir::signature::resolve_params(&sig.params, namespace, typarams, class_exists);
+            hir::signature::convert_params(&params, typarams)
         } else {
             // Inherit #initialize from superclass
             let (sig, _found_cls) = self
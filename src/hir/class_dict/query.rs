@@ -35,11 +35,45 @@ impl ClassDict {
                 .instance_ty;
             let (base_sig, found_cls) = self.lookup_method_(base_cls, base_cls, method_name)?;
             Ok((base_sig.specialize(&type_args), found_cls))
+        } else if let TyBody::TySpeMeta {
+            base_name,
+            type_args,
+        } = &class.body
+        {
+            // eg. `Meta:Pair<Int, Bool>`
+            let meta_cls = ty::meta(base_name);
+            let (base_sig, found_cls) = self.lookup_method_(&meta_cls, &meta_cls, method_name)?;
+            let mut sig = base_sig.specialize(&type_args);
+            if method_name.0 == "new" {
+                sig.ret_ty = ty::spe(base_name, type_args.clone());
+            }
+            Ok((sig, found_cls))
+        } else if let TyBody::TyParamRef { .. } = &class.body {
+            // A value of a type parameter is an `Object` (its upper bound)
+            let object = ty::raw("Object");
+            self.lookup_method_(&object, &object, method_name)
         } else {
             self.lookup_method_(class, class, method_name)
         }
     }
 
+    /// Return the name of the overload of `method_name` which takes `arity`
+    /// args (eg. `log/2`), or `method_name` if there is no such overload
+    /// (see parser/overloading.rs)
+    pub fn overload_name(
+        &self,
+        class: &TermTy,
+        method_name: &MethodFirstname,
+        arity: usize,
+    ) -> MethodFirstname {
+        let overloaded = method_name.overloaded(arity);
+        if self.lookup_method(class, &overloaded).is_ok() {
+            overloaded
+        } else {
+            method_name.clone()
+        }
+    }
+
     fn lookup_method_(
         &self,
         receiver_class: &TermTy,
@@ -129,6 +163,53 @@ impl ClassDict {
         v
     }
 
+    /// Return true if `ty` is `other` or a subclass of `other`
+    pub fn conforms(&self, ty: &TermTy, other: &TermTy) -> bool {
+        if ty.conforms_to(other) {
+            return true;
+        }
+        if let TyBody::TySpe { base_name, .. } = &ty.body {
+            // eg. `Array<Int>` conforms to `Object` (but not to `Array`)
+            let base_ty = ty::raw(base_name);
+            return other.is_raw() && *other != base_ty && self.conforms(&base_ty, other);
+        }
+        if let TyBody::TySpe {
+            base_name,
+            type_args,
+        } = &other.body
+        {
+            // eg. `Enumerator` (the type of `self` and `Enumerator.new` in
+            // the class) conforms to `Enumerator<T>`, and to `Enumerator<U>`
+            // in a generic method `Enumerator<T>#map<U>`
+            if !ty.is_raw() || ty.fullname.0 != *base_name {
+                return false;
+            }
+            let n_typarams = match self.find_class(&ty.fullname) {
+                Some(cls) => cls.typarams.len(),
+                None => return false,
+            };
+            return type_args.iter().enumerate().all(|(i, t)| match &t.body {
+                TyBody::TyParamRef { idx, .. } => *idx == i || *idx >= n_typarams,
+                _ => false,
+            });
+        }
+        ty.is_raw() && other.is_raw() && self.ancestor_types(ty).contains(other)
+    }
+
+    /// Return ancestor classes of `fullname`, including itself.
+    pub fn ancestor_classes(&self, fullname: &ClassFullname) -> Vec<&SkClass> {
+        let mut v = vec![];
+        let mut cls = self.find_class(fullname);
+        while let Some(c) = cls {
+            v.push(c);
+            cls = c
+                .superclass_fullname
+                .as_ref()
+                .and_then(|name| self.find_class(name));
+        }
+        v
+    }
+
     pub fn find_ivar(&self, classname: &ClassFullname, ivar_name: &str) -> Option<&SkIVar> {
         let class = self.sk_classes.get(&classname).unwrap_or_else(|| {
             panic!(
@@ -0,0 +1,79 @@
+/// Class-level hooks
+///
+/// Only `.inherited` is supported. `.included` is deferred until modules
+/// (and `include`) are added to the language.
+use crate::ast;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+
+impl HirMaker {
+    /// Return calls of the `.inherited` hooks
+    ///
+    /// For each class, `A.inherited(C)` is called where `C` is the class and
+    /// `A` is the nearest ancestor of `C` which defines `.inherited`.
+    /// The calls are in the order of the class definitions and evaluated
+    /// before the main program (after the constants are initialized.)
+    pub(super) fn inherited_hook_calls(
+        &self,
+        items: &[ast::TopLevelItem],
+    ) -> Result<Vec<HirExpression>, Error> {
+        let mut classes = vec![];
+        for item in items {
+            if let ast::TopLevelItem::Def(def) = item {
+                collect_class_names(def, "", &mut classes);
+            }
+        }
+
+        let mut calls = vec![];
+        for fullname in &classes {
+            if let Some(owner) = self.find_inherited_hook(fullname) {
+                let subclass = Hir::bit_cast(ty::raw("Class"), class_const_ref(fullname));
+                calls.push(self.make_method_call(
+                    class_const_ref(&owner),
+                    &method_firstname("inherited"),
+                    vec![subclass],
+                )?);
+            }
+        }
+        Ok(calls)
+    }
+
+    /// Return the nearest ancestor (excluding itself) which defines
+    /// `.inherited`
+    fn find_inherited_hook(&self, fullname: &ClassFullname) -> Option<ClassFullname> {
+        self.class_dict
+            .ancestor_classes(fullname)
+            .into_iter()
+            .skip(1)
+            .find(|cls| {
+                self.class_dict
+                    .find_method(&cls.fullname.meta_name(), &method_firstname("inherited"))
+                    .is_some()
+            })
+            .map(|cls| cls.fullname.clone())
+    }
+}
+
+/// Collect the names of the classes (including nested ones) in the order
+/// of definition
+fn collect_class_names(def: &ast::Definition, namespace: &str, names: &mut Vec<ClassFullname>) {
+    if let ast::Definition::ClassDefinition { name, defs, .. } = def {
+        let fullname = name.add_namespace(namespace);
+        // A class may be reopened
+        if !names.contains(&fullname) {
+            names.push(fullname.clone());
+        }
+        for d in defs {
+            collect_class_names(d, &fullname.0, names);
+        }
+    }
+}
+
+/// Return the constant which holds the class
+fn class_const_ref(fullname: &ClassFullname) -> HirExpression {
+    Hir::const_ref(
+        fullname.class_ty(),
+        const_fullname(&format!("::{}", fullname.0)),
+    )
+}
@@ -0,0 +1,220 @@
+/// Compile-time configuration of a program
+///
+/// These constants are defined in every program and their values are known
+/// at compile time:
+///
+/// - `PLATFORM`: `"linux"`, `"macos"` or `"windows"` (the platform the
+///   program is compiled on)
+/// - `DEBUG`: `false` in a release build (`--release`), otherwise `true`
+/// - `KEY` given with `--define KEY=VALUE`: an `Int` if `VALUE` is a decimal
+///   number, a `Bool` if it is `true` or `false`, otherwise a `String`
+///
+/// A reference to one of them is replaced with its value. The condition of
+/// `if` (and `unless`, `cond ? a : b`) which consists of them, literals,
+/// `==`, `!=`, `!`, `&&` and `||` is evaluated at compile time and only the
+/// taken branch is converted, so the other one may refer to the classes and
+/// methods which do not exist on the platform (it still has to be parsed):
+///
+/// ```text
+/// if PLATFORM == "windows"
+///   Shiika::Internal::Win32.sleep(ms)
+/// else
+///   Shiika::Internal::Posix.usleep(ms * 1000)
+/// end
+/// ```
+///
+/// The builtin library is compiled once with the default configuration,
+/// so it does not see the `--define`s of the program.
+use crate::ast::*;
+use crate::error;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+use crate::parser::token::Token;
+use crate::platform::Platform;
+
+/// The configuration given to the compiler
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildConfig {
+    /// `--release` (see `Hir::release`)
+    pub release: bool,
+    /// The value of `PLATFORM`
+    pub platform: Platform,
+    /// `--define KEY=VALUE` (in the order given)
+    pub defines: Vec<(String, String)>,
+    /// `--max-parse-nesting`: the parser fails if the program is nested
+    /// deeper than this (see `Parser::max_nesting`)
+    pub max_parse_nesting: usize,
+    /// `--max-type-nesting`: the type checker fails if an expression is
+    /// nested deeper than this
+    pub max_type_nesting: usize,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            release: false,
+            platform: Platform::host(),
+            defines: vec![],
+            max_parse_nesting: crate::parser::DEFAULT_MAX_NESTING,
+            max_type_nesting: crate::hir::hir_maker::DEFAULT_MAX_NESTING,
+        }
+    }
+}
+
+/// The value of a compile-time constant
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    Bool(bool),
+    Int(i32),
+    Str(String),
+}
+
+/// The names of the constants defined by the compiler
+const BUILTIN_NAMES: &[&str] = &["PLATFORM", "DEBUG"];
+
+impl BuildConfig {
+    /// Parse `KEY=VALUE` of `--define`
+    pub fn parse_define(s: &str) -> Result<(String, String), Error> {
+        let mut parts = s.splitn(2, '=');
+        let key = parts.next().unwrap();
+        let value = parts.next().ok_or_else(|| {
+            error::plain_runner_error(format!("--define needs KEY=VALUE (got `{}')", s))
+        })?;
+        let is_const_name = key.starts_with(|c: char| c.is_ascii_uppercase())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_const_name {
+            return Err(error::plain_runner_error(format!(
+                "--define: `{}' is not a constant name",
+                key
+            )));
+        }
+        if BUILTIN_NAMES.contains(&key) {
+            return Err(error::plain_runner_error(format!(
+                "--define: `{}' is defined by the compiler",
+                key
+            )));
+        }
+        Ok((key.to_string(), value.to_string()))
+    }
+
+    /// The value of the compile-time constant `name`, if any
+    pub fn value(&self, name: &str) -> Option<ConfigValue> {
+        match name {
+            "PLATFORM" => Some(ConfigValue::Str(self.platform.name().to_string())),
+            "DEBUG" => Some(ConfigValue::Bool(!self.release)),
+            _ => {
+                self.defines
+                    .iter()
+                    .rev()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| match value.as_str() {
+                        "true" => ConfigValue::Bool(true),
+                        "false" => ConfigValue::Bool(false),
+                        _ => match value.parse() {
+                            Ok(n) => ConfigValue::Int(n),
+                            Err(_) => ConfigValue::Str(value.clone()),
+                        },
+                    })
+            }
+        }
+    }
+}
+
+impl HirMaker {
+    /// The value of `names` if it refers to a compile-time constant (and
+    /// not to a constant of the program)
+    pub(super) fn config_value(&self, names: &[String]) -> Option<ConfigValue> {
+        if names.len() != 1 || self.resolve_const_name(names).is_some() {
+            return None;
+        }
+        self.config.value(&names[0])
+    }
+
+    /// Make the literal of a compile-time constant
+    pub(super) fn convert_config_value(&mut self, value: ConfigValue) -> HirExpression {
+        match value {
+            ConfigValue::Bool(b) => Hir::boolean_literal(b),
+            ConfigValue::Int(n) => Hir::decimal_literal(n),
+            ConfigValue::Str(s) => Hir::string_literal(self.register_string_literal(&s)),
+        }
+    }
+
+    /// A compile-time constant cannot be redefined at toplevel
+    pub(super) fn check_config_const_def(
+        &self,
+        namespace: &ClassFullname,
+        name: &ConstFirstname,
+    ) -> Result<(), Error> {
+        if namespace.0.is_empty() && self.config.value(&name.0).is_some() {
+            return Err(error::name_error(&format!(
+                "constant `{}' is defined by the compiler (see `--define')",
+                name.0
+            )));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the condition of `if` at compile time. Returns `None`
+    /// unless it consists of literals and compile-time constants (and
+    /// refers at least one of the latter)
+    pub(super) fn fold_condition(&self, cond_expr: &AstExpression) -> Option<bool> {
+        let mut uses_config = false;
+        match self.eval_config_expr(cond_expr, &mut uses_config) {
+            Some(ConfigValue::Bool(b)) if uses_config => Some(b),
+            _ => None,
+        }
+    }
+
+    fn eval_config_expr(
+        &self,
+        expr: &AstExpression,
+        uses_config: &mut bool,
+    ) -> Option<ConfigValue> {
+        match &expr.body {
+            AstExpressionBody::ConstRef(names) => {
+                let value = self.config_value(names)?;
+                *uses_config = true;
+                Some(value)
+            }
+            AstExpressionBody::PseudoVariable(Token::KwTrue) => Some(ConfigValue::Bool(true)),
+            AstExpressionBody::PseudoVariable(Token::KwFalse) => Some(ConfigValue::Bool(false)),
+            AstExpressionBody::DecimalLiteral { value } => Some(ConfigValue::Int(*value)),
+            AstExpressionBody::StringLiteral { content } => Some(ConfigValue::Str(content.clone())),
+            AstExpressionBody::LogicalNot { expr } => Some(ConfigValue::Bool(
+                !self.eval_config_bool(expr, uses_config)?,
+            )),
+            AstExpressionBody::LogicalAnd { left, right } => {
+                let l = self.eval_config_bool(left, uses_config)?;
+                let r = self.eval_config_bool(right, uses_config)?;
+                Some(ConfigValue::Bool(l && r))
+            }
+            AstExpressionBody::LogicalOr { left, right } => {
+                let l = self.eval_config_bool(left, uses_config)?;
+                let r = self.eval_config_bool(right, uses_config)?;
+                Some(ConfigValue::Bool(l || r))
+            }
+            AstExpressionBody::MethodCall {
+                receiver_expr: Some(receiver),
+                method_name,
+                arg_exprs,
+                ..
+            } if arg_exprs.len() == 1 && (method_name.0 == "==" || method_name.0 == "!=") => {
+                let l = self.eval_config_expr(receiver, uses_config)?;
+                let r = self.eval_config_expr(&arg_exprs[0], uses_config)?;
+                if std::mem::discriminant(&l) != std::mem::discriminant(&r) {
+                    return None;
+                }
+                Some(ConfigValue::Bool((l == r) == (method_name.0 == "==")))
+            }
+            _ => None,
+        }
+    }
+
+    fn eval_config_bool(&self, expr: &AstExpression, uses_config: &mut bool) -> Option<bool> {
+        match self.eval_config_expr(expr, uses_config) {
+            Some(ConfigValue::Bool(b)) => Some(b),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,231 @@
+/// Decides the order to evaluate the initializers of constants.
+///
+/// A constant is initialized after the constants it refers, including
+/// the ones referred (directly or indirectly) from the methods called
+/// in the initializer.
+use crate::error;
+use crate::error::Error;
+use crate::hir::HirExpressionBase::*;
+use crate::hir::*;
+use std::collections::HashSet;
+
+/// Constants, methods and classes referred from an expression
+/// (also used by `call_graph`)
+#[derive(Debug, Default)]
+pub(super) struct Refs {
+    pub consts: Vec<ConstFullname>,
+    /// Full names of methods
+    pub methods: Vec<String>,
+    /// Classes referred with `HirClassLiteral`
+    pub classes: Vec<ClassFullname>,
+    /// Local variables assigned (or lambda parameters) and their types
+    pub lvars: Vec<(String, TermTy)>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum State {
+    NotYet,
+    Visiting,
+    Done,
+}
+
+/// Sort `const_inits` so that each constant is initialized after the
+/// constants it depends on. Returns an error if there is a cycle
+pub fn sort_const_inits(
+    const_inits: Vec<HirExpression>,
+    sk_methods: &HashMap<ClassFullname, Vec<SkMethod>>,
+) -> Result<Vec<HirExpression>, Error> {
+    let mut method_refs = HashMap::new();
+    for methods in sk_methods.values() {
+        for method in methods {
+            if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
+                let mut refs = Refs::default();
+                collect_refs_in_exprs(exprs, &mut refs);
+                method_refs.insert(method.signature.fullname.full_name.clone(), refs);
+            }
+        }
+    }
+
+    let names = const_inits
+        .iter()
+        .map(|expr| match &expr.node {
+            HirConstAssign { fullname, .. } => fullname.clone(),
+            _ => panic!("[BUG] const_inits: Not a HirConstAssign"),
+        })
+        .collect::<Vec<_>>();
+    let deps = const_inits
+        .iter()
+        .map(|expr| const_deps(expr, &method_refs))
+        .collect::<Vec<_>>();
+
+    let mut sorter = Sorter {
+        names: &names,
+        deps: &deps,
+        states: vec![State::NotYet; names.len()],
+        path: vec![],
+        order: vec![],
+    };
+    for i in 0..names.len() {
+        sorter.visit(i)?;
+    }
+    let order = sorter.order;
+
+    let mut slots = const_inits.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(order
+        .into_iter()
+        .map(|i| slots[i].take().unwrap())
+        .collect())
+}
+
+struct Sorter<'a> {
+    names: &'a [ConstFullname],
+    deps: &'a [HashSet<ConstFullname>],
+    states: Vec<State>,
+    /// Constants being visited (used for the error message)
+    path: Vec<usize>,
+    /// Result (list of indices of `names`)
+    order: Vec<usize>,
+}
+
+impl<'a> Sorter<'a> {
+    fn visit(&mut self, i: usize) -> Result<(), Error> {
+        match self.states[i] {
+            State::Done => return Ok(()),
+            State::Visiting => {
+                let start = self.path.iter().position(|j| *j == i).unwrap();
+                let cycle = self.path[start..]
+                    .iter()
+                    .chain(std::iter::once(&i))
+                    .map(|j| self.names[*j].0.clone())
+                    .collect::<Vec<_>>();
+                return Err(error::program_error(&format!(
+                    "cyclic initialization of constants: {}",
+                    cycle.join(" -> ")
+                )));
+            }
+            State::NotYet => (),
+        }
+        self.states[i] = State::Visiting;
+        self.path.push(i);
+        // Visit in the order of definition so that the result is stable
+        for (j, name) in self.names.iter().enumerate() {
+            if self.deps[i].contains(name) {
+                self.visit(j)?;
+            }
+        }
+        self.path.pop();
+        self.states[i] = State::Done;
+        self.order.push(i);
+        Ok(())
+    }
+}
+
+/// Return the constants needed to evaluate `const_init`
+fn const_deps(
+    const_init: &HirExpression,
+    method_refs: &HashMap<String, Refs>,
+) -> HashSet<ConstFullname> {
+    let mut refs = Refs::default();
+    collect_refs_in_expr(const_init, &mut refs);
+
+    let mut consts = refs.consts.into_iter().collect::<HashSet<_>>();
+    let mut visited = HashSet::new();
+    let mut queue = refs.methods;
+    while let Some(name) = queue.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        if let Some(r) = method_refs.get(&name) {
+            consts.extend(r.consts.iter().cloned());
+            queue.extend(r.methods.iter().cloned());
+        }
+    }
+    consts
+}
+
+pub(super) fn collect_refs_in_exprs(exprs: &HirExpressions, refs: &mut Refs) {
+    for expr in &exprs.exprs {
+        collect_refs_in_expr(expr, refs);
+    }
+}
+
+pub(super) fn collect_refs_in_expr(expr: &HirExpression, refs: &mut Refs) {
+    match &expr.node {
+        HirLogicalNot { expr } => collect_refs_in_expr(expr, refs),
+        HirLogicalAnd { left, right } | HirLogicalOr { left, right } => {
+            collect_refs_in_expr(left, refs);
+            collect_refs_in_expr(right, refs);
+        }
+        HirIfExpression {
+            cond_expr,
+            then_exprs,
+            else_exprs,
+        } => {
+            collect_refs_in_expr(cond_expr, refs);
+            collect_refs_in_exprs(then_exprs, refs);
+            if let Some(exprs) = else_exprs.as_ref() {
+                collect_refs_in_exprs(exprs, refs);
+            }
+        }
+        HirWhileExpression {
+            cond_expr,
+            body_exprs,
+        } => {
+            collect_refs_in_expr(cond_expr, refs);
+            collect_refs_in_exprs(body_exprs, refs);
+        }
+        HirBreakExpression { expr } => {
+            if let Some(expr) = expr {
+                collect_refs_in_expr(expr, refs);
+            }
+        }
+        HirNextExpression => (),
+        HirReturnExpression { arg } => collect_refs_in_expr(arg, refs),
+        HirLVarAssign { name, rhs } => {
+            refs.lvars.push((name.clone(), rhs.ty.clone()));
+            collect_refs_in_expr(rhs, refs)
+        }
+        HirIVarAssign { rhs, .. } => collect_refs_in_expr(rhs, refs),
+        HirConstAssign { rhs, .. } => collect_refs_in_expr(rhs, refs),
+        HirMethodCall {
+            receiver_expr,
+            method_fullname,
+            arg_exprs,
+        } => {
+            refs.methods.push(method_fullname.full_name.clone());
+            collect_refs_in_expr(receiver_expr, refs);
+            for expr in arg_exprs {
+                collect_refs_in_expr(expr, refs);
+            }
+        }
+        HirArgRef { .. } => (),
+        HirLVarRef { .. } => (),
+        HirIVarRef { .. } => (),
+        HirConstRef { fullname } => refs.consts.push(fullname.clone()),
+        HirLambdaExpr {
+            params,
+            exprs,
+            captures_ary,
+            ..
+        } => {
+            for param in params {
+                refs.lvars.push((param.name.clone(), param.ty.clone()));
+            }
+            // The lambda may be called during the initialization
+            collect_refs_in_exprs(exprs, refs);
+            collect_refs_in_expr(captures_ary, refs);
+        }
+        HirSelfExpression => (),
+        HirParenthesizedExpr { exprs } => collect_refs_in_exprs(exprs, refs),
+        HirArrayLiteral { exprs } => collect_refs_in_exprs(exprs, refs),
+        HirFloatLiteral { .. } => (),
+        HirDecimalLiteral { .. } => (),
+        HirStringLiteral { .. } => (),
+        HirBooleanLiteral { .. } => (),
+        HirBox { expr } => collect_refs_in_expr(expr, refs),
+        HirUnbox { expr } => collect_refs_in_expr(expr, refs),
+        HirBitCast { expr } => collect_refs_in_expr(expr, refs),
+        HirClassLiteral { fullname, .. } => refs.classes.push(fullname.clone()),
+        HirBreakpoint { .. } => (),
+    }
+}
@@ -1,8 +1,12 @@
 use crate::ast::*;
 use crate::error;
 use crate::error::Error;
+use crate::hir::call_graph;
+use crate::hir::class_dict::ClassDict;
+use crate::hir::format;
 use crate::hir::hir_maker::HirMaker;
 use crate::hir::hir_maker_context::*;
+use crate::hir::shared_lvars;
 use crate::hir::*;
 use crate::parser::token::Token;
 use crate::type_checking;
@@ -12,15 +16,51 @@ impl HirMaker {
         &mut self,
         exprs: &[AstExpression],
     ) -> Result<HirExpressions, Error> {
-        let hir_exprs = exprs
-            .iter()
-            .map(|expr| self.convert_expr(expr))
-            .collect::<Result<Vec<_>, _>>()?;
+        let mut hir_exprs = vec![];
+        // Whether a `breakpoint` is found (the statements after it can be
+        // stepped over with the debugger)
+        let mut stepping = false;
+        for expr in exprs {
+            if stepping {
+                hir_exprs.push(self.convert_breakpoint(true));
+            }
+            let hir_expr = self.convert_expr(expr)?;
+            if let HirExpressionBase::HirBreakpoint { .. } = &hir_expr.node {
+                stepping = true;
+            }
+            hir_exprs.push(hir_expr);
+        }
 
         Ok(HirExpressions::new(hir_exprs))
     }
 
+    /// Convert the body of `if`, `while`, etc. The variables first assigned
+    /// in it are local to it
+    pub(super) fn convert_block(
+        &mut self,
+        exprs: &[AstExpression],
+    ) -> Result<HirExpressions, Error> {
+        self.push_scope();
+        let hirs = self.convert_exprs(exprs)?;
+        self.pop_scope();
+        Ok(hirs)
+    }
+
     pub(super) fn convert_expr(&mut self, expr: &AstExpression) -> Result<HirExpression, Error> {
+        if self.nesting >= self.config.max_type_nesting {
+            return Err(error::program_error(&format!(
+                "program too deeply nested (max: {})",
+                self.config.max_type_nesting
+            )));
+        }
+        self.nesting += 1;
+        let result = self.convert_expr_(expr);
+        self.nesting -= 1;
+        // The innermost expression which has a span tells where the error is
+        result.map_err(|err| at(expr, err))
+    }
+
+    fn convert_expr_(&mut self, expr: &AstExpression) -> Result<HirExpression, Error> {
         match &expr.body {
             AstExpressionBody::LogicalNot { expr } => self.convert_logical_not(expr),
             AstExpressionBody::LogicalAnd { left, right } => self.convert_logical_and(left, right),
@@ -36,7 +76,25 @@ impl HirMaker {
                 body_exprs,
             } => self.convert_while_expr(cond_expr, body_exprs),
 
-            AstExpressionBody::Break => self.convert_break_expr(),
+            AstExpressionBody::For {
+                var_name,
+                iterable,
+                body_exprs,
+            } => self.convert_for_expr(var_name, iterable, body_exprs),
+
+            AstExpressionBody::Loop { body_exprs } => self.convert_loop_expr(body_exprs),
+
+            AstExpressionBody::Case {
+                expr,
+                clauses,
+                else_exprs,
+            } => self.convert_case_expr(expr, clauses, else_exprs),
+
+            AstExpressionBody::Break { expr } => self.convert_break_expr(expr),
+
+            AstExpressionBody::Next => self.convert_next_expr(),
+
+            AstExpressionBody::Try { expr } => self.convert_try_expr(expr),
 
             AstExpressionBody::LVarAssign { name, rhs, is_var } => {
                 self.convert_lvar_assign(name, &*rhs, is_var)
@@ -50,6 +108,10 @@ impl HirMaker {
                 self.convert_const_assign(names, &*rhs)
             }
 
+            AstExpressionBody::MultipleAssign { names, rhs } => {
+                self.convert_multiple_assign(names, &*rhs)
+            }
+
             AstExpressionBody::MethodCall {
                 receiver_expr,
                 method_name,
@@ -61,28 +123,53 @@ impl HirMaker {
                 self.convert_lambda_expr(params, exprs)
             }
 
+            AstExpressionBody::Await { .. } => Err(error::program_error(
+                "`await' can only be used as a statement of async methods or the toplevel",
+            )),
+
+            AstExpressionBody::AwaitCall {
+                call,
+                var_name,
+                rest,
+            } => self.convert_await_call(call, var_name, rest),
+
             AstExpressionBody::BareName(name) => self.convert_bare_name(name),
 
             AstExpressionBody::IVarRef(names) => self.convert_ivar_ref(names),
 
             AstExpressionBody::ConstRef(names) => self.convert_const_ref(names),
 
+            AstExpressionBody::SpecializedConstRef { names, typ_args } => {
+                self.convert_specialized_const_ref(names, typ_args)
+            }
+
             AstExpressionBody::PseudoVariable(token) => self.convert_pseudo_variable(token),
 
             AstExpressionBody::ArrayLiteral(exprs) => self.convert_array_literal(exprs),
 
+            AstExpressionBody::TupleLiteral(exprs) => self.convert_tuple_literal(exprs),
+
+            AstExpressionBody::RangeLiteral {
+                first,
+                last,
+                exclusive,
+            } => self.convert_range_literal(first, last, *exclusive),
+
             AstExpressionBody::FloatLiteral { value } => Ok(Hir::float_literal(*value)),
 
             AstExpressionBody::DecimalLiteral { value } => Ok(Hir::decimal_literal(*value)),
 
             AstExpressionBody::StringLiteral { content } => self.convert_string_literal(content),
+
+            AstExpressionBody::CharLiteral { value } => self.convert_char_literal(*value),
             //x => panic!("TODO: {:?}", x)
         }
     }
 
     fn convert_logical_not(&mut self, expr: &AstExpression) -> Result<HirExpression, Error> {
         let expr_hir = self.convert_expr(expr)?;
-        type_checking::check_logical_operator_ty(&expr_hir.ty, "argument of logical not")?;
+        type_checking::check_logical_operator_ty(&expr_hir.ty, "argument of logical not")
+            .map_err(|e| at(expr, e))?;
         Ok(Hir::logical_not(expr_hir))
     }
 
@@ -93,8 +180,10 @@ impl HirMaker {
     ) -> Result<HirExpression, Error> {
         let left_hir = self.convert_expr(left)?;
         let right_hir = self.convert_expr(right)?;
-        type_checking::check_logical_operator_ty(&left_hir.ty, "lhs of logical and")?;
-        type_checking::check_logical_operator_ty(&right_hir.ty, "rhs of logical and")?;
+        type_checking::check_logical_operator_ty(&left_hir.ty, "lhs of logical and")
+            .map_err(|e| at(left, e))?;
+        type_checking::check_logical_operator_ty(&right_hir.ty, "rhs of logical and")
+            .map_err(|e| at(right, e))?;
         Ok(Hir::logical_and(left_hir, right_hir))
     }
 
@@ -105,8 +194,10 @@ impl HirMaker {
     ) -> Result<HirExpression, Error> {
         let left_hir = self.convert_expr(left)?;
         let right_hir = self.convert_expr(right)?;
-        type_checking::check_logical_operator_ty(&left_hir.ty, "lhs of logical or")?;
-        type_checking::check_logical_operator_ty(&right_hir.ty, "rhs of logical or")?;
+        type_checking::check_logical_operator_ty(&left_hir.ty, "lhs of logical or")
+            .map_err(|e| at(left, e))?;
+        type_checking::check_logical_operator_ty(&right_hir.ty, "rhs of logical or")
+            .map_err(|e| at(right, e))?;
         Ok(Hir::logical_or(left_hir, right_hir))
     }
 
@@ -116,21 +207,50 @@ impl HirMaker {
         then_exprs: &[AstExpression],
         else_exprs: &Option<Vec<AstExpression>>,
     ) -> Result<HirExpression, Error> {
+        if let Some(value) = self.fold_condition(cond_expr) {
+            return self.convert_folded_if(value, then_exprs, else_exprs);
+        }
         let cond_hir = self.convert_expr(cond_expr)?;
-        type_checking::check_condition_ty(&cond_hir.ty, "if")?;
+        type_checking::check_condition_ty(&cond_hir.ty, "if").map_err(|e| at(cond_expr, e))?;
+        self.warn_constant_condition(cond_expr, false);
 
-        let then_hirs = self.convert_exprs(then_exprs)?;
+        let then_hirs = self.convert_block(then_exprs)?;
         let else_hirs = match else_exprs {
-            Some(exprs) => Some(self.convert_exprs(exprs)?),
+            Some(exprs) => Some(self.convert_block(exprs)?),
             None => None,
         };
-        // TODO: then and else must have conpatible type
-        Ok(Hir::if_expression(
-            then_hirs.ty.clone(),
-            cond_hir,
-            then_hirs,
-            else_hirs,
-        ))
+        // `if` without `else` has no value
+        let ty = match &else_hirs {
+            Some(else_hirs) => if_ty(&then_hirs.ty, &else_hirs.ty),
+            None => ty::raw("Void"),
+        };
+        Ok(Hir::if_expression(ty, cond_hir, then_hirs, else_hirs))
+    }
+
+    /// `if` whose condition is known at compile time (see config.rs). The
+    /// branch not taken is not converted
+    fn convert_folded_if(
+        &mut self,
+        value: bool,
+        then_exprs: &[AstExpression],
+        else_exprs: &Option<Vec<AstExpression>>,
+    ) -> Result<HirExpression, Error> {
+        let taken = if value {
+            Some(then_exprs)
+        } else {
+            else_exprs.as_deref()
+        };
+        let mut hirs = match taken {
+            Some(exprs) => self.convert_block(exprs)?,
+            None => HirExpressions::new(vec![]),
+        };
+        // `if` without `else` has no value
+        if else_exprs.is_none() && !hirs.ty.is_never_type() {
+            hirs.exprs
+                .push(Hir::const_ref(ty::raw("Void"), const_fullname("::Void")));
+            hirs.ty = ty::raw("Void");
+        }
+        Ok(Hir::parenthesized_expression(hirs))
     }
 
     fn convert_while_expr(
@@ -139,14 +259,302 @@ impl HirMaker {
         body_exprs: &[AstExpression],
     ) -> Result<HirExpression, Error> {
         let cond_hir = self.convert_expr(cond_expr)?;
-        type_checking::check_condition_ty(&cond_hir.ty, "while")?;
+        type_checking::check_condition_ty(&cond_hir.ty, "while").map_err(|e| at(cond_expr, e))?;
+        self.warn_constant_condition(cond_expr, true);
+
+        self.ctx_mut().loops.push(CtxLoop::default());
+        let body_hirs = self.convert_block(body_exprs)?;
+        let lp = self.ctx_mut().loops.pop().unwrap();
 
+        if !lp.break_tys.is_empty() {
+            // Otherwise the loop may end without a value
+            let is_infinite = match &cond_expr.body {
+                AstExpressionBody::PseudoVariable(Token::KwTrue) => true,
+                _ => false,
+            };
+            if !is_infinite {
+                return Err(error::type_error(
+                    "`break' with a value is only allowed in `while true'",
+                ));
+            }
+        }
+        let ty = loop_value_ty(&lp)?;
+        Ok(Hir::while_expression(ty, cond_hir, body_hirs))
+    }
+
+    /// Convert `loop do ... end` into `while true`. Unlike `while true`,
+    /// it is `Never` if there is no `break` for it (so the code after it
+    /// is rejected as unreachable)
+    fn convert_loop_expr(&mut self, body_exprs: &[AstExpression]) -> Result<HirExpression, Error> {
+        self.ctx_mut().loops.push(CtxLoop::default());
+        let body_hirs = self.convert_block(body_exprs)?;
+        let lp = self.ctx_mut().loops.pop().unwrap();
+
+        let ty = if lp.break_tys.is_empty() && !lp.has_plain_break {
+            ty::raw("Never")
+        } else {
+            loop_value_ty(&lp)?
+        };
+        Ok(Hir::while_expression(
+            ty,
+            Hir::boolean_literal(true),
+            body_hirs,
+        ))
+    }
+
+    /// Convert `for x in collection` into a while loop
+    ///
+    /// `for x in ary` becomes
+    ///     tmp_ary = ary; tmp_i = 0; tmp_limit = tmp_ary.length
+    ///     while tmp_i < tmp_limit
+    ///       x = tmp_ary[tmp_i]; tmp_i = tmp_i + 1
+    ///       ...
+    ///     end
+    ///
+    /// and `for x in a..b` becomes
+    ///     tmp_i = a; tmp_limit = b + 1
+    ///     while tmp_i < tmp_limit
+    ///       x = tmp_i; tmp_i = tmp_i + 1
+    ///       ...
+    ///     end
+    ///
+    /// (The counter is incremented first so that `next` works.)
+    fn convert_for_expr(
+        &mut self,
+        var_name: &str,
+        iterable: &AstExpression,
+        body_exprs: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        let int_ty = ty::raw("Int");
+        let counter = self.gensym();
+        let limit = self.gensym();
+        let counter_ref = || Hir::lvar_ref(ty::raw("Int"), counter.clone());
+        let mut exprs = vec![];
+        // The array being iterated, if any
+        let mut ary = None;
+        match &iterable.body {
+            // Avoid creating a Range
+            AstExpressionBody::RangeLiteral {
+                first,
+                last,
+                exclusive,
+            } => {
+                let first_hir = self.convert_expr(first)?;
+                let last_hir = self.convert_expr(last)?;
+                check_range_ty(&first_hir.ty, &last_hir.ty)?;
+                let limit_hir = if *exclusive {
+                    last_hir
+                } else {
+                    self.make_method_call(
+                        last_hir,
+                        &method_firstname("+"),
+                        vec![Hir::decimal_literal(1)],
+                    )?
+                };
+                exprs.push(Hir::assign_lvar(&counter, first_hir));
+                exprs.push(Hir::assign_lvar(&limit, limit_hir));
+            }
+            _ => {
+                let hir = self.convert_expr(iterable)?;
+                let iterable_ty = hir.ty.clone();
+                let tmp = self.gensym();
+                exprs.push(Hir::assign_lvar(&tmp, hir));
+                let tmp_ref = || Hir::lvar_ref(iterable_ty.clone(), tmp.clone());
+                let is_array = match &iterable_ty.body {
+                    TyBody::TySpe { base_name, .. } => base_name == "Array",
+                    _ => false,
+                };
+                let (first_hir, limit_hir) = if iterable_ty == ty::raw("Range") {
+                    (
+                        self.make_method_call(tmp_ref(), &method_firstname("first"), vec![])?,
+                        self.make_method_call(tmp_ref(), &method_firstname("_limit"), vec![])?,
+                    )
+                } else if is_array {
+                    ary = Some(tmp_ref());
+                    (
+                        Hir::decimal_literal(0),
+                        self.make_method_call(tmp_ref(), &method_firstname("length"), vec![])?,
+                    )
+                } else {
+                    return Err(error::type_error(&format!(
+                        "cannot iterate over {} with `for' (Array or Range expected)",
+                        iterable_ty
+                    )));
+                };
+                exprs.push(Hir::assign_lvar(&counter, first_hir));
+                exprs.push(Hir::assign_lvar(&limit, limit_hir));
+            }
+        }
+
+        let item_hir = match ary {
+            Some(ary_ref) => {
+                self.make_method_call(ary_ref, &method_firstname("[]"), vec![counter_ref()])?
+            }
+            None => counter_ref(),
+        };
+        // `x` is local to the loop
+        self.push_scope();
+        let assign_hir = self.assign_loop_var(var_name, item_hir)?;
+        let cond_hir = self.make_method_call(
+            counter_ref(),
+            &method_firstname("<"),
+            vec![Hir::lvar_ref(int_ty, limit)],
+        )?;
+        let incr_hir = self.make_method_call(
+            counter_ref(),
+            &method_firstname("+"),
+            vec![Hir::decimal_literal(1)],
+        )?;
+
+        self.ctx_mut().loops.push(CtxLoop::default());
         let body_hirs = self.convert_exprs(body_exprs)?;
-        Ok(Hir::while_expression(cond_hir, body_hirs))
+        let lp = self.ctx_mut().loops.pop().unwrap();
+        self.pop_scope();
+        if !lp.break_tys.is_empty() {
+            return Err(error::type_error(
+                "`break' with a value is only allowed in `while true'",
+            ));
+        }
+
+        let mut loop_body = vec![assign_hir, Hir::assign_lvar(&counter, incr_hir)];
+        loop_body.extend(body_hirs.exprs);
+        exprs.push(Hir::while_expression(
+            ty::raw("Void"),
+            cond_hir,
+            Hir::expressions(loop_body),
+        ));
+        Ok(Hir::parenthesized_expression(Hir::expressions(exprs)))
     }
 
-    fn convert_break_expr(&mut self) -> Result<HirExpression, Error> {
-        Ok(Hir::break_expression())
+    /// Return the assignment to the variable of `for` or a variable of a
+    /// pattern of `when` (declare it if needed)
+    pub(super) fn assign_loop_var(
+        &mut self,
+        name: &str,
+        item: HirExpression,
+    ) -> Result<HirExpression, Error> {
+        if self.ctx().find_lvar(name).is_none() {
+            self.warn_shadowing(name);
+        }
+        let ctx = self.ctx_mut();
+        match ctx.lvars.get(name) {
+            Some(lvar) => {
+                if lvar.readonly {
+                    return Err(error::program_error(&format!(
+                        "cannot reassign to {} (Hint: declare it with `var')",
+                        name
+                    )));
+                }
+                type_checking::check_reassign_var(&lvar.ty, &item.ty, name)?;
+                if lvar.shared {
+                    let cell = Hir::lvar_ref(shared_lvars::cell_ty(), name.to_string());
+                    return Ok(shared_lvars::set_cell_value(cell, item));
+                }
+            }
+            None => {
+                let shared = ctx.shared_lvars.contains(name);
+                check_ended_lvars(ctx, name, &item.ty, shared)?;
+                ctx.declare_lvar(CtxLVar {
+                    name: name.to_string(),
+                    ty: item.ty.clone(),
+                    readonly: false,
+                    shared,
+                    used: false,
+                });
+                if shared {
+                    // Each iteration has its own cell
+                    return Ok(declare_shared_lvar(name, item));
+                }
+            }
+        }
+        Ok(Hir::assign_lvar(name, item))
+    }
+
+    fn convert_break_expr(
+        &mut self,
+        expr: &Option<Box<AstExpression>>,
+    ) -> Result<HirExpression, Error> {
+        if self.ctx().loops.is_empty() {
+            return Err(error::program_error("`break' outside of a loop"));
+        }
+        let hir = match expr {
+            Some(e) => {
+                let h = self.convert_expr(e)?;
+                type_checking::check_not_void(&h.ty, "the value of `break'")?;
+                Some(h)
+            }
+            None => None,
+        };
+        let lp = self.ctx_mut().loops.last_mut().unwrap();
+        match &hir {
+            Some(h) => lp.break_tys.push(h.ty.clone()),
+            None => lp.has_plain_break = true,
+        }
+        Ok(Hir::break_expression(hir))
+    }
+
+    fn convert_next_expr(&mut self) -> Result<HirExpression, Error> {
+        if self.ctx().loops.is_empty() {
+            return Err(error::program_error("`next' outside of a loop"));
+        }
+        Ok(Hir::next_expression())
+    }
+
+    /// `expr?` is converted into
+    /// `(tmp = expr; if tmp.is_ok then tmp.value else return tmp end)`
+    /// (only allowed in a method which returns a `Result` with the same
+    /// error type)
+    fn convert_try_expr(&mut self, expr: &AstExpression) -> Result<HirExpression, Error> {
+        let expr_hir = self.convert_expr(expr)?;
+        let err_ty = match &expr_hir.ty.body {
+            TyBody::TySpe {
+                base_name,
+                type_args,
+            } if base_name == "Result" => type_args[1].clone(),
+            _ => {
+                return Err(error::type_error(&format!(
+                    "`?' needs a Result (got {})",
+                    expr_hir.ty
+                )))
+            }
+        };
+        let ret_ty = match (&self.ctx().kind, &self.ctx().method_sig) {
+            (CtxKind::Method, Some(sig)) => sig.ret_ty.clone(),
+            _ => {
+                return Err(error::program_error(
+                    "`?' can be used only in a method (not in a lambda)",
+                ))
+            }
+        };
+        match &ret_ty.body {
+            TyBody::TySpe {
+                base_name,
+                type_args,
+            } if base_name == "Result" && type_args[1] == err_ty => (),
+            _ => {
+                return Err(error::type_error(&format!(
+                    "`?' on {} needs the method to return Result<_, {}> (returns {})",
+                    expr_hir.ty, err_ty, ret_ty
+                )))
+            }
+        }
+
+        let result_ty = expr_hir.ty.clone();
+        let tmp = self.gensym();
+        let tmp_ref = || Hir::lvar_ref(result_ty.clone(), tmp.clone());
+        let assign_hir = Hir::assign_lvar(&tmp, expr_hir);
+        let cond_hir = self.make_method_call(tmp_ref(), &method_firstname("is_ok"), vec![])?;
+        let value_hir = self.make_method_call(tmp_ref(), &method_firstname("value"), vec![])?;
+        let return_hir = Hir::return_expression(Hir::bit_cast(ret_ty, tmp_ref()));
+        let if_hir = Hir::if_expression(
+            value_hir.ty.clone(),
+            cond_hir,
+            Hir::expressions(vec![value_hir]),
+            Some(Hir::expressions(vec![return_hir])),
+        );
+        Ok(Hir::parenthesized_expression(Hir::expressions(vec![
+            assign_hir, if_hir,
+        ])))
     }
 
     fn convert_lvar_assign(
@@ -156,6 +564,28 @@ impl HirMaker {
         is_var: &bool,
     ) -> Result<HirExpression, Error> {
         let expr = self.convert_expr(rhs)?;
+        type_checking::check_not_void(&expr.ty, &format!("the value assigned to `{}'", name))?;
+        let ctx = self.ctx();
+        if !*is_var && ctx.find_lvar(name).is_none() && ctx.find_fn_arg(name).is_none() {
+            if let Some(var) = self.lookup_outer_var(name) {
+                // Reassigning a variable of the outer scope
+                if var.readonly {
+                    return Err(error::program_error(&format!(
+                        "cannot reassign to {} (Hint: declare it with `var')",
+                        name
+                    )));
+                }
+                if !var.shared {
+                    return Err(error::bug(format!("[BUG] lvar `{}' is not shared", name)));
+                }
+                type_checking::check_reassign_var(&var.ty, &expr.ty, name)?;
+                return Ok(shared_lvars::set_cell_value(var.expr, expr));
+            }
+        }
+
+        if self.ctx().find_lvar(name).is_none() {
+            self.warn_shadowing(name);
+        }
         let ctx = self.ctx_mut();
         match ctx.lvars.get(name) {
             Some(lvar) => {
@@ -173,17 +603,25 @@ impl HirMaker {
                 } else {
                     type_checking::check_reassign_var(&lvar.ty, &expr.ty, name)?;
                 }
+                if lvar.shared {
+                    let cell = Hir::lvar_ref(shared_lvars::cell_ty(), name.to_string());
+                    return Ok(shared_lvars::set_cell_value(cell, expr));
+                }
             }
             None => {
                 // Newly introduced lvar
-                ctx.lvars.insert(
-                    name.to_string(),
-                    CtxLVar {
-                        name: name.to_string(),
-                        ty: expr.ty.clone(),
-                        readonly: !is_var,
-                    },
-                );
+                let shared = *is_var && ctx.shared_lvars.contains(name);
+                check_ended_lvars(ctx, name, &expr.ty, shared)?;
+                ctx.declare_lvar(CtxLVar {
+                    name: name.to_string(),
+                    ty: expr.ty.clone(),
+                    readonly: !is_var,
+                    shared,
+                    used: false,
+                });
+                if shared {
+                    return Ok(declare_shared_lvar(name, expr));
+                }
             }
         }
 
@@ -197,13 +635,14 @@ impl HirMaker {
         is_var: &bool,
     ) -> Result<HirExpression, Error> {
         let expr = self.convert_expr(rhs)?;
+        type_checking::check_not_void(&expr.ty, &format!("the value assigned to `{}'", name))?;
         let ctx = self.method_ctx().ok_or_else(|| {
             error::program_error(&format!("cannot assign ivar `{}' out of a method", name))
         })?;
 
         if ctx.is_initializer {
-            let idx = self.declare_ivar(name, &expr.ty, !is_var)?;
-            return Ok(Hir::assign_ivar(name, idx, expr, *is_var));
+            let (idx, ivar_ty) = self.declare_ivar(name, &expr.ty, !is_var)?;
+            return Ok(Hir::assign_ivar(name, idx, cast_to(ivar_ty, expr), *is_var));
         }
 
         if let Some(ivar) = self.class_dict.find_ivar(&ctx.self_ty.fullname, name) {
@@ -213,14 +652,13 @@ impl HirMaker {
                     name
                 )));
             }
-            if !ivar.ty.equals_to(&expr.ty) {
-                // TODO: Subtype (@obj = 1, etc.)
-                return Err(error::type_error(&format!(
-                    "instance variable `{}' has type {:?} but tried to assign a {:?}",
-                    name, ivar.ty, expr.ty
-                )));
-            }
-            Ok(Hir::assign_ivar(name, ivar.idx, expr, false))
+            type_checking::check_ivar_assign(&self.class_dict, &ivar.ty, &expr.ty, name)?;
+            Ok(Hir::assign_ivar(
+                name,
+                ivar.idx,
+                cast_to(ivar.ty.clone(), expr),
+                false,
+            ))
         } else {
             Err(error::program_error(&format!(
                 "instance variable `{}' not found",
@@ -229,8 +667,13 @@ impl HirMaker {
         }
     }
 
-    /// Declare a new ivar
-    fn declare_ivar(&mut self, name: &str, ty: &TermTy, readonly: bool) -> Result<usize, Error> {
+    /// Declare a new ivar. Returns its index and type
+    fn declare_ivar(
+        &mut self,
+        name: &str,
+        ty: &TermTy,
+        readonly: bool,
+    ) -> Result<(usize, TermTy), Error> {
         let ctx = self.method_ctx_mut().unwrap();
         if let Some(super_ivar) = ctx.super_ivars.get(name) {
             if super_ivar.ty != *ty {
@@ -246,9 +689,20 @@ impl HirMaker {
                 )));
             }
             // This is not a declaration (assigning to an ivar defined in superclass)
-            return Ok(super_ivar.idx);
+            return Ok((super_ivar.idx, ty.clone()));
+        }
+        if let Some(ivar) = ctx.iivars.get(name) {
+            // Assigned again in the initializer
+            if ivar.readonly != readonly {
+                return Err(error::program_error(&format!(
+                    "instance variable `{}' is declared with and without `var'",
+                    name
+                )));
+            }
+            let (idx, ivar_ty) = (ivar.idx, ivar.ty.clone());
+            type_checking::check_ivar_assign(&self.class_dict, &ivar_ty, ty, name)?;
+            return Ok((idx, ivar_ty));
         }
-        // TODO: check duplicates
         let idx = ctx.super_ivars.len() + ctx.iivars.len();
         ctx.iivars.insert(
             name.to_string(),
@@ -259,7 +713,7 @@ impl HirMaker {
                 readonly,
             },
         );
-        Ok(idx)
+        Ok((idx, ty.clone()))
     }
 
     fn convert_const_assign(
@@ -268,8 +722,51 @@ impl HirMaker {
         rhs: &AstExpression,
     ) -> Result<HirExpression, Error> {
         let name = const_firstname(&names.join("::")); // TODO: pass entire `names` rather than ConstFirstname?
-        let fullname = self.register_const(&name, &rhs)?;
-        Ok(Hir::assign_const(fullname, self.convert_expr(rhs)?))
+        let namespace = self.ctx().namespace.clone();
+        let fullname = self.register_const(&namespace, &name, &rhs)?;
+        // The initializer is evaluated in `init_constants` (before the main
+        // program) so here we just refer the value
+        let ty = self.constants[&fullname].clone();
+        Ok(Hir::const_ref(ty, fullname))
+    }
+
+    /// `a, b = tuple` is expanded into `tmp = tuple; a = tmp.0; b = tmp.1`
+    /// and `a, b = ary` into `tmp = ary; tmp._check_length(2); a = tmp[0];
+    /// b = tmp[1]` (the number of the elements is checked at runtime)
+    fn convert_multiple_assign(
+        &mut self,
+        names: &[String],
+        rhs: &AstExpression,
+    ) -> Result<HirExpression, Error> {
+        let tmp = self.gensym();
+        let tmp_assign = self.convert_lvar_assign(&tmp, rhs, &false)?;
+        let tmp_ref = || ast::bare_name(&tmp);
+        let tuple_name = ast::tuple_class_name(names.len());
+        let is_array = base_class_name(&tmp_assign.ty) == "Array";
+        if !is_array && base_class_name(&tmp_assign.ty) != tuple_name {
+            return Err(error::type_error(&format!(
+                "cannot assign {} to {} variables ({} or Array expected)",
+                tmp_assign.ty,
+                names.len(),
+                tuple_name
+            )));
+        }
+        let mut exprs = vec![tmp_assign];
+        if is_array {
+            let n = ast::decimal_literal(names.len() as i32);
+            let check = ast::method_call(Some(tmp_ref()), "_check_length", vec![n], true, false);
+            exprs.push(self.convert_expr(&check)?);
+        }
+        for (i, name) in names.iter().enumerate() {
+            let item = if is_array {
+                let idx = ast::decimal_literal(i as i32);
+                ast::method_call(Some(tmp_ref()), "[]", vec![idx], true, false)
+            } else {
+                ast::method_call(Some(tmp_ref()), &format!("_{}", i), vec![], true, false)
+            };
+            exprs.push(self.convert_lvar_assign(name, &item, &false)?);
+        }
+        Ok(Hir::parenthesized_expression(Hir::expressions(exprs)))
     }
 
     fn convert_method_call(
@@ -278,34 +775,140 @@ impl HirMaker {
         method_name: &MethodFirstname,
         arg_exprs: &[AstExpression],
     ) -> Result<HirExpression, Error> {
+        if receiver_expr.is_none() {
+            if let Some(hir) = self.convert_assertion(method_name, arg_exprs)? {
+                return Ok(hir);
+            }
+        }
         let receiver_hir = match receiver_expr {
             Some(expr) => self.convert_expr(&expr)?,
             // Implicit self
             _ => self.convert_self_expr()?,
         };
+        if receiver_expr.is_none()
+            && method_name.0 == "breakpoint"
+            && arg_exprs.is_empty()
+            && self
+                .class_dict
+                .lookup_method(&receiver_hir.ty, method_name)
+                .is_err()
+        {
+            return Ok(self.convert_breakpoint(false));
+        }
+        // An async method takes the callback as the last param, so the
+        // overload with one more param is the one `await` would call
+        let name_with_callback =
+            self.class_dict
+                .overload_name(&receiver_hir.ty, method_name, arg_exprs.len() + 1);
+        if let Ok((sig, _)) = self
+            .class_dict
+            .lookup_method(&receiver_hir.ty, &name_with_callback)
+        {
+            if is_async(&sig) && arg_exprs.len() + 1 == sig.params.len() {
+                return Err(error::program_error(&format!(
+                    "async method `{}' must be called with `await'",
+                    sig.fullname
+                )));
+            }
+        }
         // TODO: arg types must match with method signature
-        let arg_hirs = arg_exprs
+        let mut arg_hirs = arg_exprs
             .iter()
             .map(|arg_expr| self.convert_expr(arg_expr))
             .collect::<Result<Vec<_>, _>>()?;
 
+        // `format(fmt, ...)` (or `sprintf`) and `fmt % arg` (see hir/format.rs)
+        let is_format = receiver_expr.is_none()
+            && (method_name.0 == "format" || method_name.0 == "sprintf")
+            && !arg_hirs.is_empty();
+        let is_percent =
+            method_name.0 == "%" && receiver_hir.ty == ty::raw("String") && arg_hirs.len() == 1;
+        if (is_format || is_percent)
+            && self
+                .class_dict
+                .lookup_method(&receiver_hir.ty, method_name)
+                .is_err()
+        {
+            if is_format {
+                let fmt_hir = arg_hirs.remove(0);
+                let literal = format::string_literal_content(&arg_exprs[0]);
+                return self.convert_format(&method_name.0, literal, fmt_hir, arg_hirs);
+            }
+            let literal = receiver_expr
+                .as_ref()
+                .and_then(|expr| format::string_literal_content(expr));
+            let (assign_hir, args) = self.format_operand(arg_hirs.remove(0))?;
+            let hir = self.convert_format("%", literal, receiver_hir, args)?;
+            return Ok(match assign_hir {
+                Some(assign_hir) => {
+                    Hir::parenthesized_expression(Hir::expressions(vec![assign_hir, hir]))
+                }
+                None => hir,
+            });
+        }
+
+        // `ary[first..last]` is `ary.slice_range(first..last)` (because
+        // methods cannot be overloaded by the types of the args)
+        if method_name.0 == "[]"
+            && arg_hirs.len() == 1
+            && arg_hirs[0].ty == ty::raw("Range")
+            && base_class_name(&receiver_hir.ty) == "Array"
+        {
+            let slice_range = method_firstname("slice_range");
+            return self.make_method_call(receiver_hir, &slice_range, arg_hirs);
+        }
         self.make_method_call(receiver_hir, &method_name, arg_hirs)
     }
 
-    fn make_method_call(
+    pub(super) fn make_method_call(
         &self,
         receiver_hir: HirExpression,
         method_name: &MethodFirstname,
         arg_hirs: Vec<HirExpression>,
     ) -> Result<HirExpression, Error> {
+        type_checking::check_not_void(
+            &receiver_hir.ty,
+            &format!("the receiver of `{}'", method_name),
+        )?;
+        for (i, arg) in arg_hirs.iter().enumerate() {
+            type_checking::check_not_void(
+                &arg.ty,
+                &format!("the arg #{} of `{}'", i + 1, method_name),
+            )?;
+        }
+        let arity = arg_hirs.len();
+        let method_name = &self
+            .class_dict
+            .overload_name(&receiver_hir.ty, method_name, arity);
         let specialized = receiver_hir.ty.is_specialized();
         let class_fullname = &receiver_hir.ty.fullname;
         let (sig, found_class_name) = self
             .class_dict
             .lookup_method(&receiver_hir.ty, method_name)?;
+        if method_name.0 == "new" {
+            self.check_not_abstract(&receiver_hir.ty)?;
+        }
 
+        let arg_hirs = self.apply_implicit_conversions(&sig, arg_hirs)?;
         let param_tys = arg_hirs.iter().map(|expr| &expr.ty).collect::<Vec<_>>();
-        type_checking::check_method_args(&sig, &param_tys, &receiver_hir, &arg_hirs)?;
+        let generic = !sig.typarams.is_empty();
+        let sig = type_checking::instantiate_method(sig, &param_tys)?;
+        if method_name.0 == "call" && receiver_hir.ty.fn_arity().is_some() {
+            type_checking::check_fn_call_args(
+                &self.class_dict,
+                &receiver_hir.ty,
+                &sig,
+                &param_tys,
+            )?;
+        } else {
+            type_checking::check_method_args(
+                &self.class_dict,
+                &sig,
+                &param_tys,
+                &receiver_hir,
+                &arg_hirs,
+            )?;
+        }
 
         let receiver = if &found_class_name != class_fullname {
             // Upcast needed
@@ -315,32 +918,137 @@ impl HirMaker {
         };
 
         let args;
-        if specialized {
+        if specialized || generic {
+            // Cast the args into the types of the params before
+            // specialization (eg. `Object` for `T`)
+            let base_sig = self
+                .class_dict
+                .find_method(&found_class_name, method_name)
+                .expect("[BUG] method not found in the class");
             args = arg_hirs
                 .into_iter()
-                .map(|expr| Hir::bit_cast(ty::raw("Object"), expr))
+                .zip(base_sig.params.iter())
+                .map(|(expr, param)| Hir::bit_cast(param.ty.upper_bound(), expr))
                 .collect::<Vec<_>>();
         } else {
-            args = arg_hirs;
+            // Upcast args if needed
+            args = arg_hirs
+                .into_iter()
+                .zip(sig.params.iter())
+                .map(|(expr, param)| {
+                    if expr.ty != param.ty
+                        && (expr.ty.is_raw() || expr.ty.is_specialized())
+                        && param.ty.is_raw()
+                    {
+                        Hir::bit_cast(param.ty.clone(), expr)
+                    } else {
+                        expr
+                    }
+                })
+                .collect::<Vec<_>>();
         }
 
         let mut ret = Hir::method_call(sig.ret_ty.clone(), receiver, sig.fullname.clone(), args);
-        if specialized {
+        if specialized || generic {
             ret = Hir::bit_cast(sig.ret_ty, ret)
         }
         Ok(ret)
     }
 
+    /// Insert calls of `to_str`, etc. for the args passed to `~Type` params
+    fn apply_implicit_conversions(
+        &self,
+        sig: &MethodSignature,
+        arg_hirs: Vec<HirExpression>,
+    ) -> Result<Vec<HirExpression>, Error> {
+        let mut converted = vec![];
+        for (i, arg) in arg_hirs.into_iter().enumerate() {
+            match sig.params.get(i) {
+                Some(param)
+                    if param.implicit_conv && !self.class_dict.conforms(&arg.ty, &param.ty) =>
+                {
+                    converted.push(self.make_implicit_conversion(sig, param, arg)?);
+                }
+                _ => converted.push(arg),
+            }
+        }
+        Ok(converted)
+    }
+
+    /// Return a call of the conversion method (eg. `to_str`) on `arg`
+    fn make_implicit_conversion(
+        &self,
+        sig: &MethodSignature,
+        param: &MethodParam,
+        arg: HirExpression,
+    ) -> Result<HirExpression, Error> {
+        let conv_name = match implicit_conversion_method(&param.ty) {
+            Some(s) => method_firstname(s),
+            None => {
+                return Err(error::type_error(&format!(
+                    "{}: param `{}' cannot be `~{}' (no implicit conversion defined)",
+                    sig.fullname, param.name, param.ty
+                )))
+            }
+        };
+        if self.class_dict.lookup_method(&arg.ty, &conv_name).is_err() {
+            return Err(error::type_error(&format!(
+                "{}: `{}' should be {} or have `{}' but got {}",
+                sig.fullname, param.name, param.ty, conv_name, arg.ty
+            )));
+        }
+        self.make_method_call(arg, &conv_name, vec![])
+    }
+
+    /// Raise an error if `cls_ty` is a metaclass of abstract class
+    fn check_not_abstract(&self, cls_ty: &TermTy) -> Result<(), Error> {
+        if let TyBody::TyMeta { base_fullname } = &cls_ty.body {
+            if let Some(cls) = self.class_dict.find_class(&class_fullname(base_fullname)) {
+                if cls.is_abstract() {
+                    return Err(error::program_error(&format!(
+                        "cannot instantiate abstract class `{}'",
+                        base_fullname
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn convert_lambda_expr(
         &mut self,
         params: &[ast::Param],
         exprs: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        let class_dict = &self.class_dict;
+        let params = signature::resolve_params(params, &self.ctx().namespace, &[], &|name| {
+            class_dict.class_exists(&name.0)
+        });
+        let hir_params = signature::convert_params(&params, &[]);
+        self.convert_lambda(hir_params, exprs, false)
+    }
+
+    /// Create a lambda which takes `hir_params`. Returns Void if `void`
+    /// is true
+    fn convert_lambda(
+        &mut self,
+        hir_params: Vec<MethodParam>,
+        exprs: &[AstExpression],
+        void: bool,
     ) -> Result<HirExpression, Error> {
         self.lambda_ct += 1;
         let lambda_id = self.lambda_ct;
-        let hir_params = signature::convert_params(params, &[]);
         self.push_ctx(HirMakerContext::lambda_ctx(self.ctx(), hir_params.clone()));
-        let hir_exprs = self.convert_exprs(exprs)?;
+        for param in &hir_params {
+            self.warn_shadowing(&param.name);
+        }
+        self.ctx_mut().shared_lvars = shared_lvars::collect_shared_lvars(exprs);
+        let mut hir_exprs = self.convert_exprs(exprs)?;
+        if void && !hir_exprs.ty.is_void_type() {
+            let mut exprs = hir_exprs.exprs;
+            exprs.push(Hir::const_ref(ty::raw("Void"), const_fullname("::Void")));
+            hir_exprs = HirExpressions::new(exprs);
+        }
         // This pops ctx
         let capture_exprs = self.resolve_lambda_captures();
         let captures_ary = self.convert_array_literal_(capture_exprs)?;
@@ -352,11 +1060,95 @@ impl HirMaker {
         ))
     }
 
+    /// Call the method with the continuation of `await` (a lambda which
+    /// runs `rest`) as the last arg (see parser/async_lowering.rs)
+    fn convert_await_call(
+        &mut self,
+        call: &AstExpression,
+        var_name: &Option<String>,
+        rest: &[AstExpression],
+    ) -> Result<HirExpression, Error> {
+        let (receiver_expr, method_name, arg_exprs) = match &call.body {
+            AstExpressionBody::MethodCall {
+                receiver_expr,
+                method_name,
+                arg_exprs,
+                ..
+            } => (
+                receiver_expr.as_deref(),
+                method_name.clone(),
+                &arg_exprs[..],
+            ),
+            AstExpressionBody::BareName(name) => (None, method_firstname(name), &[][..]),
+            _ => {
+                return Err(error::program_error(
+                    "`await' takes a method call (eg. `await foo(bar)')",
+                ))
+            }
+        };
+        let receiver_hir = match receiver_expr {
+            Some(expr) => self.convert_expr(expr)?,
+            _ => self.convert_self_expr()?,
+        };
+        let mut arg_hirs = arg_exprs
+            .iter()
+            .map(|arg_expr| self.convert_expr(arg_expr))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The last param must be `Fn0<Void>` or `Fn1<T, Void>`
+        let method_name =
+            self.class_dict
+                .overload_name(&receiver_hir.ty, &method_name, arg_hirs.len() + 1);
+        let (sig, _) = self
+            .class_dict
+            .lookup_method(&receiver_hir.ty, &method_name)?;
+        let cont_ty = match sig.params.last() {
+            Some(param) if arg_hirs.len() + 1 == sig.params.len() => param.ty.clone(),
+            _ => {
+                return Err(error::type_error(&format!(
+                    "cannot await `{}' because it does not take the callback as the last arg",
+                    sig.fullname
+                )))
+            }
+        };
+        let type_args = match &cont_ty.body {
+            ty::TyBody::TySpe { type_args, .. } => type_args.clone(),
+            _ => vec![],
+        };
+        let value_ty = match (cont_ty.fn_arity(), type_args.last()) {
+            (Some(0), Some(t)) if t.is_void_type() => None,
+            (Some(1), Some(t)) if t.is_void_type() => Some(type_args[0].clone()),
+            _ => {
+                return Err(error::type_error(&format!(
+                "cannot await `{}' because the callback is not Fn0<Void> or Fn1<T, Void> (got {})",
+                sig.fullname, cont_ty
+            )))
+            }
+        };
+        let params = match (value_ty, var_name) {
+            (Some(ty), name) => vec![MethodParam {
+                name: name.clone().unwrap_or_else(|| " _".to_string()),
+                ty,
+                implicit_conv: false,
+            }],
+            (None, Some(name)) => {
+                return Err(error::type_error(&format!(
+                    "`{}' gives no value to `{}'",
+                    sig.fullname, name
+                )))
+            }
+            (None, None) => vec![],
+        };
+        arg_hirs.push(self.convert_lambda(params, rest, true)?);
+        self.make_method_call(receiver_hir, &method_name, arg_hirs)
+    }
+
     /// Resolve LambdaCapture into HirExpression
     /// Also, concat lambda_captures to outer_captures
     fn resolve_lambda_captures(&mut self) -> Vec<HirExpression> {
+        let place = self.warning_place();
         let lambda_ctx = self.pop_ctx();
-        let arity = lambda_ctx.method_sig.as_ref().unwrap().params.len();
+        self.warn_unused_lvars(&lambda_ctx, place);
         let ctx = self.ctx_mut();
         lambda_ctx
             .captures
@@ -373,7 +1165,9 @@ impl HirMaker {
                     let ty = cap.ty.clone();
                     ctx.captures.push(cap);
                     let cidx = ctx.captures.len() - 1;
-                    Hir::lambda_capture_ref(ty, arity, cidx)
+                    // Loaded in the outer lambda
+                    let outer_arity = ctx.method_sig.as_ref().unwrap().params.len();
+                    Hir::lambda_capture_ref(ty, outer_arity, cidx)
                 }
             })
             .collect()
@@ -383,56 +1177,152 @@ impl HirMaker {
     fn convert_bare_name(&mut self, name: &str) -> Result<HirExpression, Error> {
         if let Some(expr) = self.lookup_var(name) {
             Ok(expr)
+        } else if name == "breakpoint" {
+            Ok(self.convert_breakpoint(false))
         } else {
             Err(error::program_error(&format!(
-                "variable `{}' was not found",
-                name
+                "variable `{}' was not found{}",
+                name,
+                self.toplevel_lvar_hint(name)
             )))
         }
     }
 
+    /// Explain why a local variable of the toplevel is not visible here
+    /// (see `HirMaker::convert_toplevel_items`)
+    fn toplevel_lvar_hint(&self, name: &str) -> &'static str {
+        if !self.const_resolving.is_empty() {
+            " (Hint: the initializer of a constant is evaluated before the main \
+             program and cannot refer to local variables)"
+        } else if self.ctx().kind != CtxKind::Toplevel
+            && self.ctx_stack.first().map_or(false, |ctx| {
+                ctx.kind == CtxKind::Toplevel && ctx.lvars.contains_key(name)
+            })
+        {
+            " (Hint: the local variables of the toplevel are not visible in \
+             methods; use a constant instead)"
+        } else {
+            ""
+        }
+    }
+
+    /// `breakpoint`, or a step point after it if `step` is true (see
+    /// code_gen/debugger.rs.) The debugger can show the parameters, the
+    /// local variables declared so far and the ivars (in a method)
+    fn convert_breakpoint(&self, step: bool) -> HirExpression {
+        let ctx = self.ctx();
+        let mut vars = vec![];
+        if let Some(sig) = &ctx.method_sig {
+            for (idx, param) in sig.params.iter().enumerate() {
+                vars.push((param.name.clone(), Hir::hir_arg_ref(param.ty.clone(), idx)));
+            }
+        }
+        let mut lvars = ctx.lvars.values().collect::<Vec<_>>();
+        lvars.sort_by_key(|lvar| &lvar.name);
+        for lvar in lvars {
+            let expr = if lvar.shared {
+                let cell = Hir::lvar_ref(shared_lvars::cell_ty(), lvar.name.clone());
+                shared_lvars::cell_value(cell, lvar.ty.clone())
+            } else {
+                Hir::lvar_ref(lvar.ty.clone(), lvar.name.clone())
+            };
+            vars.push((lvar.name.clone(), expr));
+        }
+        // Ivars are not set yet in an initializer
+        if ctx.kind == CtxKind::Method && !ctx.is_initializer {
+            if let Some(class) = self.class_dict.find_class(&ctx.self_ty.fullname) {
+                let mut ivars = class.ivars.values().collect::<Vec<_>>();
+                ivars.sort_by_key(|ivar| ivar.idx);
+                for ivar in ivars {
+                    let expr = Hir::ivar_ref(ivar.ty.clone(), ivar.name.clone(), ivar.idx);
+                    vars.push((format!("@{}", ivar.name), expr));
+                }
+            }
+        }
+        Hir::breakpoint(self.current_place(), vars, step)
+    }
+
+    /// The method being converted (eg. `A#foo`), or `main` for the
+    /// toplevel
+    pub(super) fn current_place(&self) -> String {
+        match self.method_ctx().and_then(|c| c.method_sig.as_ref()) {
+            Some(sig) => sig.fullname.full_name.clone(),
+            None => call_graph::MAIN.to_string(),
+        }
+    }
+
     /// Lookup variable of the given name.
     /// If it is a free variable, ctx.captures will be modified
     fn lookup_var(&mut self, name: &str) -> Option<HirExpression> {
-        let ctx = self.ctx();
-        if let Some(lvar) = ctx.find_lvar(name) {
+        let ctx = self.ctx_mut();
+        if let Some(lvar) = ctx.lvars.get_mut(name) {
+            lvar.used = true;
+            if lvar.shared {
+                let cell = Hir::lvar_ref(shared_lvars::cell_ty(), name.to_string());
+                return Some(shared_lvars::cell_value(cell, lvar.ty.clone()));
+            }
             return Some(Hir::lvar_ref(lvar.ty.clone(), name.to_string()));
         }
         if let Some((idx, param)) = ctx.find_fn_arg(name) {
             return Some(Hir::hir_arg_ref(param.ty.clone(), idx));
         }
-        if let Some(outer_ctx) = self.outer_lvar_scope_of(&ctx) {
-            // The `ctx` has outer scope == `ctx` is a lambda
-            let arity = ctx.method_sig.as_ref().unwrap().params.len();
-            let cidx = ctx.captures.len();
-            if let Some((cap, expr)) = self.lookup_var_in_outer_scope(arity, cidx, outer_ctx, name)
-            {
-                self.ctx_mut().captures.push(cap);
-                return Some(expr);
+        self.lookup_outer_var(name).map(|var| {
+            if var.shared {
+                shared_lvars::cell_value(var.expr, var.ty)
+            } else {
+                var.expr
+            }
+        })
+    }
+
+    /// Lookup variable of the given name in the outer scopes (and capture it)
+    fn lookup_outer_var(&mut self, name: &str) -> Option<OuterVar> {
+        let ctx = self.ctx();
+        // The `ctx` has outer scope == `ctx` is a lambda
+        let outer_ctx = self.outer_lvar_scope_of(&ctx)?;
+        let arity = ctx.method_sig.as_ref().unwrap().params.len();
+        let cidx = ctx.captures.len();
+        let (cap, var) = self.lookup_var_in_outer_scope(arity, cidx, outer_ctx, name)?;
+        if let LambdaCaptureDetail::CapLVar { name } = &cap.detail {
+            if let Some(lvar) = self.ctx_stack[cap.ctx_depth].lvars.get_mut(name) {
+                lvar.used = true;
             }
         }
-        None
+        self.ctx_mut().captures.push(cap);
+        Some(var)
     }
 
     /// Lookup variable of the given name in the outer scopes.
-    /// Return a `LambdaCapture` (which variable is captured) and a
-    /// `HirExpression` (how it can be retrieved from `captures`).
+    /// Return a `LambdaCapture` (which variable is captured) and an
+    /// `OuterVar` (how it can be retrieved from `captures`).
     fn lookup_var_in_outer_scope(
         &self,
         arity: usize,
         cidx: usize,
         ctx: &HirMakerContext,
         name: &str,
-    ) -> Option<(LambdaCapture, HirExpression)> {
+    ) -> Option<(LambdaCapture, OuterVar)> {
         if let Some(lvar) = ctx.find_lvar(name) {
+            // Capture the cell instead of the value
+            let cap_ty = if lvar.shared {
+                shared_lvars::cell_ty()
+            } else {
+                lvar.ty.clone()
+            };
             let cap = LambdaCapture {
                 ctx_depth: ctx.depth,
-                ty: lvar.ty.clone(),
+                ty: cap_ty.clone(),
                 detail: LambdaCaptureDetail::CapLVar {
                     name: name.to_string(),
                 },
             };
-            return Some((cap, Hir::lambda_capture_ref(lvar.ty.clone(), arity, cidx)));
+            let var = OuterVar {
+                expr: Hir::lambda_capture_ref(cap_ty, arity, cidx),
+                ty: lvar.ty.clone(),
+                readonly: lvar.readonly,
+                shared: lvar.shared,
+            };
+            return Some((cap, var));
         }
         if let Some((idx, param)) = ctx.find_fn_arg(name) {
             let cap = LambdaCapture {
@@ -440,7 +1330,13 @@ impl HirMaker {
                 ty: param.ty.clone(),
                 detail: LambdaCaptureDetail::CapFnArg { idx },
             };
-            return Some((cap, Hir::lambda_capture_ref(param.ty.clone(), arity, cidx)));
+            let var = OuterVar {
+                expr: Hir::lambda_capture_ref(param.ty.clone(), arity, cidx),
+                ty: param.ty.clone(),
+                readonly: true,
+                shared: false,
+            };
+            return Some((cap, var));
         }
 
         // TODO: It may be a nullary method call
@@ -455,10 +1351,17 @@ impl HirMaker {
         let method_ctx = self.method_ctx().ok_or_else(|| {
             error::program_error(&format!("referring ivar `{}' out of a method", name))
         })?;
-        match self
-            .class_dict
-            .find_ivar(&method_ctx.self_ty.fullname, name)
-        {
+        // The ivars of the class are not defined until the initializer is
+        // processed
+        let declared = if method_ctx.is_initializer {
+            method_ctx.iivars.get(name)
+        } else {
+            None
+        };
+        match declared.or_else(|| {
+            self.class_dict
+                .find_ivar(&method_ctx.self_ty.fullname, name)
+        }) {
             Some(ivar) => Ok(Hir::ivar_ref(ivar.ty.clone(), name.to_string(), ivar.idx)),
             None => Err(error::program_error(&format!(
                 "ivar `{}' was not found",
@@ -468,9 +1371,16 @@ impl HirMaker {
     }
 
     /// Resolve constant name
-    fn convert_const_ref(&self, names: &[String]) -> Result<HirExpression, Error> {
-        // TODO: Resolve using ctx
-        let fullname = ConstFullname("::".to_string() + &names.join("::"));
+    fn convert_const_ref(&mut self, names: &[String]) -> Result<HirExpression, Error> {
+        if let Some(value) = self.config_value(names) {
+            return Ok(self.convert_config_value(value));
+        }
+        let fullname = match self.resolve_const_name(names) {
+            Some(fullname) => fullname,
+            None => ConstFullname("::".to_string() + &names.join("::")),
+        };
+        // The constant may be defined later in the program
+        self.convert_const_def(&fullname)?;
         match self.constants.get(&fullname) {
             Some(ty) => Ok(Hir::const_ref(ty.clone(), fullname)),
             None => {
@@ -487,6 +1397,55 @@ impl HirMaker {
         }
     }
 
+    /// `A<Int>` is the class `A` typed as `Meta:A<Int>` (so that `A<Int>.new`
+    /// returns an `A<Int>`)
+    fn convert_specialized_const_ref(
+        &mut self,
+        names: &[String],
+        typ_args: &[Typ],
+    ) -> Result<HirExpression, Error> {
+        let class_expr = self.convert_const_ref(names)?;
+        let (base_name, fullname) = match (&class_expr.ty.body, &class_expr.node) {
+            (TyBody::TyMeta { base_fullname }, HirExpressionBase::HirConstRef { fullname }) => {
+                (base_fullname.clone(), fullname.clone())
+            }
+            _ => {
+                return Err(error::type_error(&format!(
+                    "`{}' is not a class and cannot take type args",
+                    names.join("::")
+                )))
+            }
+        };
+        let n_typarams = self
+            .class_dict
+            .get_class(&class_fullname(&base_name), "convert_specialized_const_ref")
+            .typarams
+            .len();
+        if n_typarams != typ_args.len() {
+            return Err(error::type_error(&format!(
+                "class `{}' takes {} type arg(s) but got {}",
+                base_name,
+                n_typarams,
+                typ_args.len()
+            )));
+        }
+        let mut type_args = vec![];
+        for typ in typ_args {
+            let class_dict = &self.class_dict;
+            let typ = signature::resolve_typ(typ, &self.ctx().namespace, &[], &|name| {
+                class_dict.class_exists(&name.0)
+            });
+            if let Some(name) = unknown_class_in(&typ, class_dict) {
+                return Err(error::name_error(&format!("unknown class `{}'", name)));
+            }
+            type_args.push(signature::convert_typ(&typ, &[]));
+        }
+        Ok(Hir::const_ref(
+            ty::spe_meta(&base_name, type_args),
+            fullname,
+        ))
+    }
+
     fn convert_pseudo_variable(&self, token: &Token) -> Result<HirExpression, Error> {
         match token {
             Token::KwSelf => self.convert_self_expr(),
@@ -506,23 +1465,17 @@ impl HirMaker {
             .iter()
             .map(|expr| self.convert_expr(expr))
             .collect::<Result<Vec<_>, _>>()?;
+        for expr in &item_exprs {
+            type_checking::check_not_void(&expr.ty, "an element of an array literal")?;
+        }
         self.convert_array_literal_(item_exprs)
     }
 
-    fn convert_array_literal_(
+    pub(super) fn convert_array_literal_(
         &mut self,
         item_exprs: Vec<HirExpression>,
     ) -> Result<HirExpression, Error> {
-        // TODO #102: Support empty array literal
-        let mut item_ty = if item_exprs.len() == 0 {
-            ty::raw("Object")
-        } else {
-            item_exprs[0].ty.clone()
-        };
-
-        for expr in &item_exprs {
-            item_ty = self.nearest_common_ancestor_type(&item_ty, &expr.ty)
-        }
+        let item_ty = self.array_item_ty(&item_exprs)?;
         let ary_ty = ty::spe("Array", vec![item_ty]);
         let upper_bound_ty = ty::raw("Object");
 
@@ -553,31 +1506,254 @@ impl HirMaker {
         Ok(Hir::array_literal(exprs, ary_ty))
     }
 
-    fn convert_self_expr(&self) -> Result<HirExpression, Error> {
+    /// `(a, b)` is `Tuple2<A, B>.new(a, b)`
+    fn convert_tuple_literal(&mut self, exprs: &[AstExpression]) -> Result<HirExpression, Error> {
+        let item_hirs = exprs
+            .iter()
+            .map(|expr| self.convert_expr(expr))
+            .collect::<Result<Vec<_>, _>>()?;
+        for expr in &item_hirs {
+            type_checking::check_not_void(&expr.ty, "an element of a tuple")?;
+        }
+        let name = ast::tuple_class_name(item_hirs.len());
+        let item_tys = item_hirs.iter().map(|expr| expr.ty.clone()).collect();
+        self.make_method_call(
+            Hir::const_ref(
+                ty::spe_meta(&name, item_tys),
+                const_fullname(&format!("::{}", name)),
+            ),
+            &method_firstname("new"),
+            item_hirs,
+        )
+    }
+
+    pub(super) fn convert_self_expr(&self) -> Result<HirExpression, Error> {
         let ctx = self.ctx();
         Ok(Hir::self_expression(ctx.self_ty.clone()))
     }
 
+    /// `a..b` is `Range.new(a, b, false)`
+    fn convert_range_literal(
+        &mut self,
+        first: &AstExpression,
+        last: &AstExpression,
+        exclusive: bool,
+    ) -> Result<HirExpression, Error> {
+        let first_hir = self.convert_expr(first)?;
+        let last_hir = self.convert_expr(last)?;
+        check_range_ty(&first_hir.ty, &last_hir.ty)?;
+        self.make_method_call(
+            Hir::const_ref(ty::meta("Range"), const_fullname("::Range")),
+            &method_firstname("new"),
+            vec![first_hir, last_hir, Hir::boolean_literal(exclusive)],
+        )
+    }
+
     fn convert_string_literal(&mut self, content: &str) -> Result<HirExpression, Error> {
         let idx = self.register_string_literal(content);
         Ok(Hir::string_literal(idx))
     }
 
+    /// `'a'` is `Char.new(97)` (the code point)
+    fn convert_char_literal(&mut self, value: char) -> Result<HirExpression, Error> {
+        self.make_method_call(
+            Hir::const_ref(ty::meta("Char"), const_fullname("::Char")),
+            &method_firstname("new"),
+            vec![Hir::decimal_literal(value as i32)],
+        )
+    }
+
     pub(super) fn register_string_literal(&mut self, content: &str) -> usize {
         let idx = self.str_literals.len();
         self.str_literals.push(content.to_string());
         idx
     }
 
+    /// Return the type of the elements of an array literal, which is the
+    /// nearest common ancestor of the elements (eg. `Object` for
+    /// `[1, "a"]`.) The elements which never have a value (eg. `panic`) are
+    /// ignored
+    // TODO #102: Support empty array literal (`Object` for now)
+    fn array_item_ty(&self, item_exprs: &[HirExpression]) -> Result<TermTy, Error> {
+        let mut tys = item_exprs
+            .iter()
+            .map(|expr| &expr.ty)
+            .filter(|ty| !ty.is_never_type());
+        let mut item_ty = match tys.next() {
+            Some(ty) => ty.clone(),
+            None => return Ok(ty::raw("Object")),
+        };
+        for ty in tys {
+            item_ty = self
+                .nearest_common_ancestor_type(&item_ty, ty)
+                .ok_or_else(|| {
+                    error::type_error(&format!(
+                        "the elements of an array literal have no common type ({} and {})",
+                        item_ty, ty
+                    ))
+                })?;
+        }
+        Ok(item_ty)
+    }
+
     /// Return the nearest common ancestor of the classes
-    fn nearest_common_ancestor_type(&self, ty1: &TermTy, ty2: &TermTy) -> TermTy {
+    fn nearest_common_ancestor_type(&self, ty1: &TermTy, ty2: &TermTy) -> Option<TermTy> {
         let ancestors1 = self.class_dict.ancestor_types(ty1);
         let ancestors2 = self.class_dict.ancestor_types(ty2);
         for t2 in ancestors2 {
             if let Some(eq) = ancestors1.iter().find(|t1| t1.equals_to(&t2)) {
-                return eq.clone();
+                return Some(eq.clone());
             }
         }
-        panic!("[BUG] nearest_common_ancestor_type not found");
+        None
+    }
+}
+
+/// Return the name of the method used to convert an object into `ty`
+/// implicitly (for `~Type` params)
+fn implicit_conversion_method(ty: &TermTy) -> Option<&'static str> {
+    match base_class_name(ty) {
+        "String" => Some("to_str"),
+        "Array" => Some("to_ary"),
+        _ => None,
+    }
+}
+
+/// Return the name of the class of `ty` without the type arguments (eg.
+/// `Array` for `Array<Int>`)
+pub(super) fn base_class_name(ty: &TermTy) -> &str {
+    match &ty.body {
+        TyBody::TySpe { base_name, .. } => base_name.as_str(),
+        _ => ty.fullname.0.as_str(),
+    }
+}
+
+/// Cast the value of a subclass to `ty` (eg. when assigned to an ivar)
+fn cast_to(ty: TermTy, expr: HirExpression) -> HirExpression {
+    if expr.ty == ty {
+        expr
+    } else {
+        Hir::bit_cast(ty, expr)
+    }
+}
+
+/// Type of `if` with `else`
+pub(super) fn if_ty(then_ty: &TermTy, else_ty: &TermTy) -> TermTy {
+    if then_ty.is_never_type() {
+        else_ty.clone()
+    } else if else_ty.is_never_type() || then_ty == else_ty {
+        then_ty.clone()
+    } else if then_ty.is_void_type() || else_ty.is_void_type() {
+        ty::raw("Void")
+    } else {
+        // TODO: then and else must have conpatible type
+        then_ty.clone()
+    }
+}
+
+/// Check the both ends of a range are Int
+/// Return the type of an infinite loop from the `break`s in it
+fn loop_value_ty(lp: &CtxLoop) -> Result<TermTy, Error> {
+    let ty = match lp.break_tys.first() {
+        Some(ty) => ty,
+        None => return Ok(ty::raw("Void")),
+    };
+    if lp.has_plain_break {
+        return Err(error::type_error(
+            "`break' without a value in a loop which has a value",
+        ));
+    }
+    if let Some(t) = lp.break_tys.iter().find(|t| *t != ty) {
+        return Err(error::type_error(&format!(
+            "the values of `break' must have the same type (got {} and {})",
+            ty, t
+        )));
+    }
+    Ok(ty.clone())
+}
+
+fn check_range_ty(first_ty: &TermTy, last_ty: &TermTy) -> Result<(), Error> {
+    let int_ty = ty::raw("Int");
+    if *first_ty != int_ty || *last_ty != int_ty {
+        return Err(error::type_error(&format!(
+            "the ends of a range must be Int (got {} and {})",
+            first_ty, last_ty
+        )));
+    }
+    Ok(())
+}
+
+/// Return the name of a class in `typ` which does not exist
+fn unknown_class_in(typ: &Typ, class_dict: &ClassDict) -> Option<String> {
+    if !class_dict.class_exists(&typ.name) {
+        return Some(typ.name.clone());
+    }
+    typ.typ_args
+        .iter()
+        .find_map(|arg| unknown_class_in(arg, class_dict))
+}
+
+/// A variable found in the outer scopes
+struct OuterVar {
+    /// The captured value (or the cell, if `shared`)
+    expr: HirExpression,
+    /// Type of the variable
+    ty: TermTy,
+    readonly: bool,
+    shared: bool,
+}
+
+/// Declare a local variable stored in a cell. Returns the value
+fn declare_shared_lvar(name: &str, value: HirExpression) -> HirExpression {
+    let ty = value.ty.clone();
+    let cell = Hir::lvar_ref(shared_lvars::cell_ty(), name.to_string());
+    Hir::parenthesized_expression(Hir::expressions(vec![
+        Hir::assign_lvar(name, shared_lvars::new_cell(value)),
+        shared_lvars::cell_value(cell, ty),
+    ]))
+}
+
+/// A variable declared again after its block ended is stored in the same
+/// place as the old one, so it must be the same kind
+fn check_ended_lvars(
+    ctx: &HirMakerContext,
+    name: &str,
+    ty: &TermTy,
+    shared: bool,
+) -> Result<(), Error> {
+    let lvar = match ctx.ended_lvars.iter().find(|lvar| lvar.name == name) {
+        Some(lvar) => lvar,
+        None => return Ok(()),
+    };
+    if lvar.ty != *ty {
+        return Err(error::type_error(&format!(
+            "variable `{}' of another block is {} (use another name for {})",
+            name, lvar.ty, ty
+        )));
+    }
+    if lvar.shared != shared {
+        return Err(error::program_error(&format!(
+            "variable `{}' of another block is declared differently (use another name)",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the method is defined with `async def` (see
+/// parser/async_lowering.rs)
+fn is_async(sig: &MethodSignature) -> bool {
+    match sig.params.last() {
+        Some(param) => param.name == ASYNC_CONT_PARAM,
+        None => false,
+    }
+}
+
+/// Give `err` the span of `expr` (an error about a subexpression is
+/// reported at it rather than at the whole expression)
+fn at(expr: &AstExpression, err: Error) -> Error {
+    match (&err.span, &expr.span) {
+        (None, Some(span)) => err.with_span(span.clone()),
+        _ => err,
     }
 }
@@ -0,0 +1,51 @@
+/// Collects where the classes, methods and constants are defined
+use crate::ast;
+use crate::names::*;
+use crate::parser::lexer::Span;
+use std::collections::HashMap;
+
+/// Return the spans of the names of the definitions in `prog`,
+/// keyed by the full name (eg. `A::B`, `A#foo`, `Meta:A#bar`, `::A::FOO`)
+pub fn collect_def_spans(prog: &ast::Program) -> HashMap<String, Span> {
+    let mut spans = HashMap::new();
+    for item in &prog.toplevel_items {
+        if let ast::TopLevelItem::Def(def) = item {
+            collect(def, &class_fullname(""), &mut spans);
+        }
+    }
+    spans
+}
+
+fn collect(def: &ast::Definition, namespace: &ClassFullname, spans: &mut HashMap<String, Span>) {
+    match def {
+        ast::Definition::ClassDefinition {
+            name,
+            name_span,
+            defs,
+            ..
+        } => {
+            let fullname = name.add_namespace(&namespace.0);
+            spans.insert(fullname.0.clone(), name_span.clone());
+            for def in defs {
+                collect(def, &fullname, spans);
+            }
+        }
+        ast::Definition::InstanceMethodDefinition { sig, .. }
+        | ast::Definition::AbstractMethodDefinition { sig } => {
+            let fullname = method_fullname(namespace, &sig.name.0);
+            spans.insert(fullname.full_name, sig.name_span.clone());
+        }
+        ast::Definition::ClassMethodDefinition { sig, .. }
+        | ast::Definition::ExternMethodDefinition { sig } => {
+            let fullname = method_fullname(&namespace.meta_name(), &sig.name.0);
+            spans.insert(fullname.full_name, sig.name_span.clone());
+        }
+        ast::Definition::ConstDefinition {
+            name, name_span, ..
+        } => {
+            let fullname = const_fullname_in(&namespace.0, &name.0);
+            spans.insert(fullname.0, name_span.clone());
+        }
+        ast::Definition::Delegation { .. } | ast::Definition::Derive { .. } => (),
+    }
+}
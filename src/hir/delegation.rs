@@ -0,0 +1,80 @@
+use crate::error;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+
+impl HirMaker {
+    /// Define the methods for `@delegate(to: @foo)`, which call the
+    /// method of the same name of `@foo` with the same arguments. They are
+    /// defined for the public methods (whose name does not start with `_`)
+    /// of the class of `@foo` and its superclasses except `Object`, unless
+    /// the class already has a method of the same name (including the
+    /// accessors)
+    pub(super) fn define_delegation(
+        &mut self,
+        clsname: &ClassFullname,
+        ivar_name: &str,
+    ) -> Result<(), Error> {
+        let ivar = match self.class_dict.find_ivar(clsname, ivar_name) {
+            Some(ivar) => ivar.clone(),
+            None => {
+                return Err(error::program_error(&format!(
+                    "@delegate: class `{}' does not have `@{}'",
+                    clsname, ivar_name
+                )))
+            }
+        };
+        let delegate_name = match &ivar.ty.body {
+            TyBody::TySpe { base_name, .. } => class_fullname(base_name),
+            _ => ivar.ty.fullname.clone(),
+        };
+        let mut names = self
+            .class_dict
+            .ancestor_classes(&delegate_name)
+            .into_iter()
+            .filter(|cls| cls.fullname.0 != "Object")
+            .flat_map(|cls| cls.method_sigs.keys().cloned())
+            .filter(|name| {
+                !name.0.starts_with('_')
+                    && name.0 != "initialize"
+                    && self.class_dict.find_method(clsname, name).is_none()
+            })
+            .collect::<Vec<_>>();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        names.dedup();
+
+        for name in names {
+            let (delegate_sig, _) = self.class_dict.lookup_method(&ivar.ty, &name)?;
+            let sig = MethodSignature {
+                fullname: method_fullname(clsname, &name.0),
+                ret_ty: delegate_sig.ret_ty.clone(),
+                params: delegate_sig.params.clone(),
+                typarams: delegate_sig.typarams.clone(),
+            };
+            let arg_hirs = sig
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, param)| Hir::hir_arg_ref(param.ty.clone(), i))
+                .collect();
+            let call = Hir::method_call(
+                delegate_sig.ret_ty.clone(),
+                Hir::ivar_ref(ivar.ty.clone(), ivar.name.clone(), ivar.idx),
+                delegate_sig.fullname.clone(),
+                arg_hirs,
+            );
+            let body = SkMethodBody::ShiikaMethodBody {
+                exprs: HirExpressions::new(vec![call]),
+            };
+            self.class_dict.add_method(clsname, sig.clone());
+            self.method_dict.add_method(
+                clsname,
+                SkMethod {
+                    signature: sig,
+                    body,
+                },
+            );
+        }
+        Ok(())
+    }
+}
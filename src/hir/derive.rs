@@ -0,0 +1,190 @@
+use crate::ast;
+use crate::ast::AstExpression;
+use crate::error;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::hir_maker_context::HirMakerContext;
+use crate::hir::*;
+use crate::parser::token::Token;
+
+/// The traits of `@derive` and the methods defined by them
+const TRAITS: [(&str, &[&str]); 3] = [
+    ("Eq", &["=="]),
+    ("Hash", &["hash"]),
+    ("ToS", &["to_s", "inspect"]),
+];
+
+/// Return the names of the methods to define for `@derive(traits)`, except
+/// the ones which the class defines by itself
+pub(super) fn derived_method_names(
+    traits: &[String],
+    defs: &[ast::Definition],
+) -> Result<Vec<&'static str>, Error> {
+    let mut names = vec![];
+    for trait_name in traits {
+        match TRAITS.iter().find(|(name, _)| name == trait_name) {
+            Some((_, methods)) => names.extend(methods.iter()),
+            None => {
+                return Err(error::program_error(&format!(
+                    "@derive: unknown trait `{}' (expected one of Eq, Hash, ToS)",
+                    trait_name
+                )))
+            }
+        }
+    }
+    names.retain(|name| {
+        !defs.iter().any(|def| match def {
+            ast::Definition::InstanceMethodDefinition { sig, .. } => sig.name.0 == *name,
+            _ => false,
+        })
+    });
+    Ok(names)
+}
+
+/// Return the signature of a derived method of the class
+pub(super) fn derived_signature(
+    clsname: &ClassFullname,
+    typarams: &[String],
+    name: &str,
+) -> ast::AstMethodSignature {
+    let typ = |name: &str| ast::Typ {
+        name: name.to_string(),
+        typ_args: vec![],
+    };
+    let (params, ret_typ) = match name {
+        "==" => {
+            let self_typ = ast::Typ {
+                name: clsname.0.clone(),
+                typ_args: typarams.iter().map(|s| typ(s)).collect(),
+            };
+            let other = ast::Param {
+                name: "other".to_string(),
+                typ: self_typ,
+                implicit_conv: false,
+            };
+            (vec![other], typ("Bool"))
+        }
+        "hash" => (vec![], typ("Int")),
+        _ => (vec![], typ("String")),
+    };
+    ast::AstMethodSignature {
+        name: method_firstname(name),
+        name_span: Default::default(),
+        typarams: vec![],
+        params,
+        ret_typ,
+        doc: None,
+    }
+}
+
+impl HirMaker {
+    /// Define the methods for `@derive(...)`. The signatures are indexed
+    /// beforehand (see class_dict/indexing.rs) and the bodies are made here
+    /// because the ivars are known only after `#initialize` is processed.
+    ///
+    /// - `Eq`: `==(other)` is true if each ivar is `==` to that of `other`
+    /// - `Hash`: `hash` combines the `hash` of the ivars
+    /// - `ToS`: `to_s` returns eg. `"Point(x: 1, y: 2)"` with the `to_s`
+    ///   of the ivars, and `inspect` returns the same
+    ///
+    /// So the types of the ivars must have these methods.
+    pub(super) fn define_derived_methods(
+        &mut self,
+        ctx: &HirMakerContext,
+        clsname: &ClassFullname,
+        defs: &[ast::Definition],
+    ) -> Result<(), Error> {
+        let mut ivar_names = self
+            .class_dict
+            .get_class(clsname, "define_derived_methods")
+            .ivars
+            .values()
+            .map(|ivar| (ivar.idx, ivar.name.clone()))
+            .collect::<Vec<_>>();
+        ivar_names.sort();
+        let ivar_names = ivar_names
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+
+        for def in defs {
+            if let ast::Definition::Derive { traits, .. } = def {
+                for name in derived_method_names(traits, defs)? {
+                    let body_exprs = derived_method_body(clsname, &ivar_names, name);
+                    let method = self.convert_method_def(
+                        ctx,
+                        clsname,
+                        &method_firstname(name),
+                        &body_exprs,
+                    )?;
+                    self.method_dict.add_method(clsname, method);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create the body of a derived method
+fn derived_method_body(
+    clsname: &ClassFullname,
+    ivar_names: &[String],
+    name: &str,
+) -> Vec<AstExpression> {
+    let ivar = |name: &String| ast::ivar_ref(name.clone());
+    let call = |receiver: AstExpression, name: &str| {
+        ast::method_call(Some(receiver), name, vec![], true, false)
+    };
+    match name {
+        // `@a == other.a and @b == other.b`
+        "==" => {
+            let expr = ivar_names
+                .iter()
+                .map(|name| {
+                    let other = call(ast::bare_name("other"), name);
+                    ast::bin_op_expr(ivar(name), "==", other)
+                })
+                .fold(None, |acc, expr| match acc {
+                    Some(acc) => Some(ast::logical_and(acc, expr)),
+                    None => Some(expr),
+                });
+            vec![expr.unwrap_or_else(|| ast::pseudo_variable(Token::KwTrue))]
+        }
+        // `var h = 17; h = h * 31 + @a.hash; ...; h`
+        "hash" => {
+            let mut exprs = vec![ast::lvar_decl("h".to_string(), ast::decimal_literal(17))];
+            for name in ivar_names {
+                let h31 = ast::bin_op_expr(ast::bare_name("h"), "*", ast::decimal_literal(31));
+                let rhs = ast::bin_op_expr(h31, "+", call(ivar(name), "hash"));
+                exprs.push(ast::assignment(ast::bare_name("h"), rhs));
+            }
+            exprs.push(ast::bare_name("h"));
+            exprs
+        }
+        // `"Foo(a: " + @a.to_s + ", b: " + @b.to_s + ")"`
+        "to_s" => {
+            let concat = |left: Option<AstExpression>, right: AstExpression| match left {
+                Some(left) => ast::bin_op_expr(left, "+", right),
+                None => right,
+            };
+            let mut expr = None;
+            let mut text = format!("{}(", clsname.0);
+            for (i, name) in ivar_names.iter().enumerate() {
+                if i > 0 {
+                    text += ", ";
+                }
+                text += &format!("{}: ", name);
+                let lit = ast::string_literal(std::mem::take(&mut text));
+                expr = Some(ast::bin_op_expr(
+                    concat(expr, lit),
+                    "+",
+                    call(ivar(name), "to_s"),
+                ));
+            }
+            text += ")";
+            vec![concat(expr, ast::string_literal(text))]
+        }
+        // `self.to_s`
+        _ => vec![call(ast::pseudo_variable(Token::KwSelf), "to_s")],
+    }
+}
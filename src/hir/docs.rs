@@ -0,0 +1,84 @@
+/// Collects the doc comments of the classes and the methods
+///
+/// The `##` lines right above `class` or `def` are its doc comment (see
+/// `Parser::doc_comment`.) They are kept in the HIR with the same keys as
+/// `def_spans` so that tools (`--dump-docs`, a doc generator, the
+/// language server, etc.) can show them.
+use crate::ast;
+use crate::error::json_str;
+use crate::hir::Hir;
+use crate::names::*;
+use crate::parser::lexer::Cursor;
+use std::collections::HashMap;
+
+/// Return the doc comments of the definitions in `prog`, keyed by the
+/// full name (eg. `A::B`, `A#foo`, `Meta:A#bar`)
+pub fn collect_docs(prog: &ast::Program) -> HashMap<String, String> {
+    let mut docs = HashMap::new();
+    for item in &prog.toplevel_items {
+        if let ast::TopLevelItem::Def(def) = item {
+            collect(def, &class_fullname(""), &mut docs);
+        }
+    }
+    docs
+}
+
+fn collect(def: &ast::Definition, namespace: &ClassFullname, docs: &mut HashMap<String, String>) {
+    let (name, doc) = match def {
+        ast::Definition::ClassDefinition {
+            name, defs, doc, ..
+        } => {
+            let fullname = name.add_namespace(&namespace.0);
+            for def in defs {
+                collect(def, &fullname, docs);
+            }
+            (fullname.0, doc)
+        }
+        ast::Definition::InstanceMethodDefinition { sig, .. }
+        | ast::Definition::AbstractMethodDefinition { sig } => {
+            (method_fullname(namespace, &sig.name.0).full_name, &sig.doc)
+        }
+        ast::Definition::ClassMethodDefinition { sig, .. }
+        | ast::Definition::ExternMethodDefinition { sig } => (
+            method_fullname(&namespace.meta_name(), &sig.name.0).full_name,
+            &sig.doc,
+        ),
+        _ => return,
+    };
+    if let Some(doc) = doc {
+        docs.insert(name, doc.clone());
+    }
+}
+
+/// Return the doc comments of the names for which `include` returns true
+/// as JSON, in the order of the definitions
+/// (`[{"name":"A#foo","line":3,"doc":"..."}]`; `line` is 1-origin and
+/// counted from `base`, which is the end of the builtin library)
+pub fn to_json(hir: &Hir, include: impl Fn(&str) -> bool, base: &Cursor) -> String {
+    let mut entries = hir
+        .docs
+        .iter()
+        .filter(|(name, _)| include(name))
+        .map(|(name, doc)| {
+            let line = hir
+                .def_spans
+                .get(name)
+                .and_then(|span| span.begin.relative_to(base))
+                .map_or(0, |cur| cur.line() + 1);
+            (line, name, doc)
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    let items = entries
+        .iter()
+        .map(|(line, name, doc)| {
+            format!(
+                "{{\"name\":{},\"line\":{},\"doc\":{}}}",
+                json_str(name),
+                line,
+                json_str(doc)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]\n", items.join(","))
+}
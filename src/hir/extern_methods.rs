@@ -0,0 +1,55 @@
+use crate::code_gen::CodeGen;
+use crate::error;
+use crate::error::Error;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+
+/// Types which can be passed to C functions (see code_gen/ffi.rs)
+const C_TYPES: [&str; 5] = ["Int", "Float", "Bool", "String", "Shiika::Internal::Ptr"];
+
+impl HirMaker {
+    /// Create the class method for `extern def`, which calls the C
+    /// function of the same name
+    pub(super) fn create_extern_method(
+        &self,
+        meta_name: &ClassFullname,
+        name: &MethodFirstname,
+    ) -> Result<SkMethod, Error> {
+        let sig = self
+            .class_dict
+            .find_method(meta_name, name)
+            .unwrap_or_else(|| panic!("[BUG] signature of extern `{}' not found", name))
+            .clone();
+        for param in &sig.params {
+            check_c_type(&sig, &param.ty)?;
+        }
+        if !sig.ret_ty.is_void_type() {
+            check_c_type(&sig, &sig.ret_ty)?;
+        }
+
+        let c_name = name.0.clone();
+        let sig_ = sig.clone();
+        let extern_body = move |code_gen: &CodeGen, function: &inkwell::values::FunctionValue| {
+            code_gen.build_extern_call(&c_name, &sig_, function);
+            Ok(())
+        };
+        Ok(SkMethod {
+            signature: sig,
+            body: SkMethodBody::RustClosureMethodBody {
+                boxed_gen: Box::new(extern_body),
+            },
+        })
+    }
+}
+
+fn check_c_type(sig: &MethodSignature, ty: &TermTy) -> Result<(), Error> {
+    if C_TYPES.contains(&ty.fullname.0.as_str()) {
+        return Ok(());
+    }
+    Err(error::type_error(&format!(
+        "extern method `{}' cannot take or return {} (expected {})",
+        sig.fullname,
+        ty,
+        C_TYPES.join(", ")
+    )))
+}
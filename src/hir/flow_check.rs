@@ -0,0 +1,216 @@
+/// Control-flow checks over HIR.
+///
+/// - Expressions after `break`/`next` (or any expression of type `Never`)
+///   are never evaluated and rejected as unreachable code
+/// - Every path of a method body must end with a value when the method
+///   returns something other than `Void`
+///
+/// These would otherwise result in broken LLVM IR (eg. instructions after
+/// a terminator.)
+use crate::error;
+use crate::error::Error;
+use crate::hir::HirExpressionBase::*;
+use crate::hir::*;
+
+/// Check there is no unreachable code in the program
+pub fn check_unreachable(hir: &Hir) -> Result<(), Error> {
+    for methods in hir.sk_methods.values() {
+        for method in methods {
+            if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
+                check_exprs(exprs, &method.signature.fullname.full_name)?;
+            }
+        }
+    }
+    for expr in &hir.const_inits {
+        check_expr(expr, "the initializer of a constant")?;
+    }
+    check_exprs(&hir.main_exprs, "the toplevel")
+}
+
+/// Check every path of the method body ends with a value
+pub fn check_method_value(sig: &MethodSignature, exprs: &HirExpressions) -> Result<(), Error> {
+    if sig.ret_ty.is_void_type() {
+        return Ok(());
+    }
+    match missing_value(exprs) {
+        Some(reason) => Err(error::type_error(&format!(
+            "{} should return {} but {}",
+            sig.fullname, sig.ret_ty.fullname, reason
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Return the reason if some path of `exprs` ends without a value
+fn missing_value(exprs: &HirExpressions) -> Option<&'static str> {
+    if is_empty(exprs) {
+        return Some("the body is empty");
+    }
+    let last_expr = exprs.exprs.last().unwrap();
+    match &last_expr.node {
+        HirIfExpression {
+            then_exprs,
+            else_exprs,
+            ..
+        } => match else_exprs.as_ref() {
+            None => Some("`if' without `else' does not have a value"),
+            Some(else_exprs) => {
+                if !then_exprs.ty.is_never_type() && is_empty(then_exprs) {
+                    Some("the `then' clause is empty")
+                } else if !else_exprs.ty.is_never_type() && is_empty(else_exprs) {
+                    Some("the `else' clause is empty")
+                } else {
+                    missing_value_in_branch(then_exprs)
+                        .or_else(|| missing_value_in_branch(else_exprs))
+                }
+            }
+        },
+        HirWhileExpression { .. } if last_expr.ty.is_void_type() => {
+            Some("`while' without `break' does not have a value")
+        }
+        HirParenthesizedExpr { exprs } => missing_value(exprs),
+        _ => None,
+    }
+}
+
+fn missing_value_in_branch(exprs: &HirExpressions) -> Option<&'static str> {
+    if exprs.ty.is_never_type() {
+        None
+    } else {
+        missing_value(exprs)
+    }
+}
+
+/// Whether `exprs` is converted from an empty list of expressions
+/// (see `HirExpressions::new`)
+fn is_empty(exprs: &HirExpressions) -> bool {
+    if exprs.exprs.len() != 1 {
+        return false;
+    }
+    match &exprs.exprs[0].node {
+        HirConstRef { fullname } => fullname.0 == "::Void",
+        _ => false,
+    }
+}
+
+fn check_exprs(exprs: &HirExpressions, on: &str) -> Result<(), Error> {
+    let n = exprs.exprs.len();
+    for (i, expr) in exprs.exprs.iter().enumerate() {
+        check_expr(expr, on)?;
+        if i < n - 1 && expr.ty.is_never_type() {
+            return Err(unreachable_error(expr, on));
+        }
+    }
+    Ok(())
+}
+
+fn check_expr(expr: &HirExpression, on: &str) -> Result<(), Error> {
+    match &expr.node {
+        HirLogicalNot { expr } => check_operand(expr, on),
+        HirLogicalAnd { left, right } | HirLogicalOr { left, right } => {
+            check_operand(left, on)?;
+            check_operand(right, on)
+        }
+        HirIfExpression {
+            cond_expr,
+            then_exprs,
+            else_exprs,
+        } => {
+            check_operand(cond_expr, on)?;
+            check_exprs(then_exprs, on)?;
+            if let Some(exprs) = else_exprs.as_ref() {
+                check_exprs(exprs, on)?;
+            }
+            Ok(())
+        }
+        HirWhileExpression {
+            cond_expr,
+            body_exprs,
+        } => {
+            check_operand(cond_expr, on)?;
+            check_exprs(body_exprs, on)
+        }
+        HirBreakExpression { expr } => match expr {
+            Some(expr) => check_operand(expr, on),
+            None => Ok(()),
+        },
+        HirNextExpression => Ok(()),
+        HirReturnExpression { arg } => check_operand(arg, on),
+        HirLVarAssign { rhs, .. } => check_operand(rhs, on),
+        HirIVarAssign { rhs, .. } => check_operand(rhs, on),
+        HirConstAssign { rhs, .. } => check_operand(rhs, on),
+        HirMethodCall {
+            receiver_expr,
+            arg_exprs,
+            ..
+        } => {
+            check_operand(receiver_expr, on)?;
+            for expr in arg_exprs {
+                check_operand(expr, on)?;
+            }
+            Ok(())
+        }
+        HirArgRef { .. } => Ok(()),
+        HirLVarRef { .. } => Ok(()),
+        HirIVarRef { .. } => Ok(()),
+        HirConstRef { .. } => Ok(()),
+        HirLambdaExpr {
+            exprs,
+            captures_ary,
+            ..
+        } => {
+            check_exprs(exprs, on)?;
+            check_expr(captures_ary, on)
+        }
+        HirSelfExpression => Ok(()),
+        HirParenthesizedExpr { exprs } => check_exprs(exprs, on),
+        HirArrayLiteral { exprs } => {
+            for expr in &exprs.exprs {
+                check_operand(expr, on)?;
+            }
+            Ok(())
+        }
+        HirFloatLiteral { .. } => Ok(()),
+        HirDecimalLiteral { .. } => Ok(()),
+        HirStringLiteral { .. } => Ok(()),
+        HirBooleanLiteral { .. } => Ok(()),
+        HirBox { expr } => check_operand(expr, on),
+        HirUnbox { expr } => check_operand(expr, on),
+        HirBitCast { expr } => check_operand(expr, on),
+        HirClassLiteral { .. } => Ok(()),
+        HirBreakpoint { .. } => Ok(()),
+    }
+}
+
+/// Check an expression whose value is used by another expression
+/// (eg. `puts(break)`, where `puts` is never called)
+fn check_operand(expr: &HirExpression, on: &str) -> Result<(), Error> {
+    check_expr(expr, on)?;
+    if expr.ty.is_never_type() {
+        return Err(unreachable_error(expr, on));
+    }
+    Ok(())
+}
+
+fn unreachable_error(expr: &HirExpression, on: &str) -> Error {
+    error::program_error(&format!(
+        "unreachable code after {} (in {})",
+        describe_jump(expr),
+        on
+    ))
+}
+
+fn describe_jump(expr: &HirExpression) -> &'static str {
+    match &expr.node {
+        HirBreakExpression { .. } => "`break'",
+        HirNextExpression => "`next'",
+        HirReturnExpression { .. } => "`?'",
+        HirIfExpression { .. } => "`if' whose branches all jump",
+        HirWhileExpression { .. } => "`loop' without `break'",
+        HirParenthesizedExpr { exprs } => match exprs.exprs.last() {
+            Some(last_expr) => describe_jump(last_expr),
+            None => "an expression which never returns",
+        },
+        _ => "an expression which never returns",
+    }
+}
@@ -0,0 +1,195 @@
+/// `format` (and `sprintf`, `String#%`)
+///
+/// ```text
+///   format("%05d %.2f", n, f)
+///   "%s: %x" % (name, n)
+/// ```
+///
+/// is converted into
+///
+/// ```text
+///   Shiika::Internal::Format.format("%05d %.2f",
+///     [FormatArg.int(n), FormatArg.float(f)])
+/// ```
+///
+/// where each argument is wrapped according to its type (`to_s` is called
+/// on the ones which are neither `Int` nor `Float`) and the format string
+/// is parsed at runtime (see builtin/shiika_internal_format.sk.) If the
+/// format string is a literal, it is parsed here too and the number and
+/// the types of the arguments are checked.
+///
+/// A conversion is `%` followed by flags (`-`, `+`, ` `, `0`, `#`), a
+/// width, a precision (`.2`) and one of `d`, `i`, `x`, `X`, `o` (for
+/// `Int`), `f`, `e`, `E`, `g`, `G` (for `Float` or `Int`) or `s` (for any
+/// value.) `%%` is `%`.
+use crate::ast::*;
+use crate::error;
+use crate::error::Error;
+use crate::hir::convert_exprs::base_class_name;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+
+impl HirMaker {
+    /// Convert a call of `format` or `String#%` (`name`.) `literal` is
+    /// the content of the format string if it is a literal
+    pub(super) fn convert_format(
+        &mut self,
+        name: &str,
+        literal: Option<&str>,
+        fmt_hir: HirExpression,
+        arg_hirs: Vec<HirExpression>,
+    ) -> Result<HirExpression, Error> {
+        if fmt_hir.ty != ty::raw("String") {
+            return Err(error::type_error(&format!(
+                "the format string of `{}' must be a String (got {})",
+                name, fmt_hir.ty
+            )));
+        }
+        if let Some(fmt) = literal {
+            check_format_args(name, fmt, &arg_hirs)?;
+        }
+        let wrapped = arg_hirs
+            .into_iter()
+            .map(|arg| self.wrap_format_arg(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let args_ty = ty::spe("Array", vec![format_arg_ty()]);
+        let ary_hir = Hir::bit_cast(args_ty, self.convert_array_literal_(wrapped)?);
+        self.make_method_call(
+            Hir::const_ref(
+                ty::meta("Shiika::Internal::Format"),
+                const_fullname("::Shiika::Internal::Format"),
+            ),
+            &method_firstname("format"),
+            vec![fmt_hir, ary_hir],
+        )
+    }
+
+    /// Return the arguments for `fmt % arg`; the elements if `arg` is a
+    /// tuple (which is evaluated once), otherwise `arg` itself
+    pub(super) fn format_operand(
+        &mut self,
+        arg: HirExpression,
+    ) -> Result<(Option<HirExpression>, Vec<HirExpression>), Error> {
+        let n = match &arg.ty.body {
+            TyBody::TySpe {
+                base_name,
+                type_args,
+            } if base_name.starts_with("Tuple") => type_args.len(),
+            _ => return Ok((None, vec![arg])),
+        };
+        let tuple_ty = arg.ty.clone();
+        let tmp = self.gensym();
+        let assign_hir = Hir::assign_lvar(&tmp, arg);
+        let items = (0..n)
+            .map(|i| {
+                let tuple = Hir::lvar_ref(tuple_ty.clone(), tmp.clone());
+                self.make_method_call(tuple, &method_firstname(&format!("_{}", i)), vec![])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((Some(assign_hir), items))
+    }
+
+    /// `FormatArg.int(n)`, `FormatArg.float(f)` or `FormatArg.str(x.to_s)`
+    fn wrap_format_arg(&self, arg: HirExpression) -> Result<HirExpression, Error> {
+        let class_name = base_class_name(&arg.ty).to_string();
+        let (method_name, value) = match class_name.as_str() {
+            "Int" => ("int", arg),
+            "Float" => ("float", arg),
+            _ => (
+                "str",
+                self.make_method_call(arg, &method_firstname("to_s"), vec![])?,
+            ),
+        };
+        self.make_method_call(
+            Hir::const_ref(
+                ty::meta("Shiika::Internal::FormatArg"),
+                const_fullname("::Shiika::Internal::FormatArg"),
+            ),
+            &method_firstname(method_name),
+            vec![value],
+        )
+    }
+}
+
+fn format_arg_ty() -> TermTy {
+    ty::raw("Shiika::Internal::FormatArg")
+}
+
+/// Return the content of `expr` if it is a string literal
+pub(super) fn string_literal_content(expr: &AstExpression) -> Option<&str> {
+    match &expr.body {
+        AstExpressionBody::StringLiteral { content } => Some(content.as_str()),
+        _ => None,
+    }
+}
+
+/// Check the arguments match the conversions of the format string
+fn check_format_args(name: &str, fmt: &str, arg_hirs: &[HirExpression]) -> Result<(), Error> {
+    let specs = parse_format(fmt).map_err(|msg| {
+        error::program_error(&format!("invalid format string for `{}': {}", name, msg))
+    })?;
+    if specs.len() != arg_hirs.len() {
+        return Err(error::program_error(&format!(
+            "the format string of `{}' needs {} argument(s) but {} given",
+            name,
+            specs.len(),
+            arg_hirs.len()
+        )));
+    }
+    for (i, (spec, arg)) in specs.iter().zip(arg_hirs).enumerate() {
+        let class_name = base_class_name(&arg.ty);
+        let ok = match spec.chars().last().unwrap() {
+            'd' | 'i' | 'x' | 'X' | 'o' => class_name == "Int",
+            'f' | 'e' | 'E' | 'g' | 'G' => class_name == "Int" || class_name == "Float",
+            _ => true,
+        };
+        if !ok {
+            return Err(error::type_error(&format!(
+                "the argument #{} of `{}' is {}, which cannot be formatted with `{}'",
+                i + 1,
+                name,
+                arg.ty,
+                spec
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Return the conversions (like `%05d`) in `fmt` which take an argument
+fn parse_format(fmt: &str) -> Result<Vec<String>, String> {
+    let mut specs = vec![];
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        let mut spec = "%".to_string();
+        while let Some(c) = chars.peek().copied().filter(|c| "-+ 0#".contains(*c)) {
+            spec.push(c);
+            chars.next();
+        }
+        while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+            spec.push(c);
+            chars.next();
+        }
+        if chars.peek() == Some(&'.') {
+            spec.push('.');
+            chars.next();
+            while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                spec.push(c);
+                chars.next();
+            }
+        }
+        match chars.next() {
+            Some('%') if spec == "%" => (),
+            Some(c) if "dixXofeEgGs".contains(c) => {
+                spec.push(c);
+                specs.push(spec);
+            }
+            Some(c) => return Err(format!("unknown conversion `{}{}'", spec, c)),
+            None => return Err(format!("incomplete conversion `{}'", spec)),
+        }
+    }
+    Ok(specs)
+}
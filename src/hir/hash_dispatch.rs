@@ -0,0 +1,182 @@
+use crate::code_gen::CodeGen;
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+use std::collections::HashMap;
+
+/// The methods of `Object` which are dispatched by the class of the
+/// receiver. They depend on the classes of the whole program, so they are
+/// not compiled into the builtin module (see code_gen/part.rs)
+pub const DISPATCHED_METHODS: [&str; 3] = ["Object#hash", "Object#==", "Object#to_s"];
+
+impl HirMaker {
+    /// Replace `Object#hash`, `Object#==` and `Object#to_s` with the
+    /// methods which call the ones of the class of the receiver (see
+    /// `create_dispatcher`)
+    pub(super) fn define_hash_dispatchers(&mut self) {
+        let object = class_fullname("Object");
+        for name in &["hash", "==", "to_s"] {
+            let sig = match self
+                .class_dict
+                .find_method(&object, &method_firstname(name))
+            {
+                Some(sig) => sig.clone(),
+                None => continue, // `Object` is not defined (in some tests)
+            };
+            let method = create_dispatcher(&self.class_dict.sk_classes, sig);
+            self.method_dict.add_method(&object, method);
+        }
+    }
+}
+
+/// Create `Object#hash`, `Object#==` or `Object#to_s` (given its
+/// signature) which calls the method of the class of the receiver, so that
+/// `hash`, `==` and `to_s` of the elements of `Set`, `Array#join` etc.
+/// (whose type is a type parameter, ie. `Object`) work as expected. The
+/// methods of `Object` (the identity, and `inspect` for `to_s`) are called
+/// if the class does not define them.
+///
+/// `x == y` is false if `y` is not an instance of the class of `x`. The
+/// methods which have other signatures than `hash -> Int`,
+/// `==(other) -> Bool` and `to_s -> String` are not called
+pub fn create_dispatcher(
+    classes: &HashMap<ClassFullname, SkClass>,
+    sig: MethodSignature,
+) -> SkMethod {
+    let impls = implementations(classes, sig.first_name());
+    dispatcher(sig, impls)
+}
+
+/// Returns the list of (receiver class, class which implements the method)
+/// for the classes which have their own `hash` (or `==`)
+fn implementations(
+    classes: &HashMap<ClassFullname, SkClass>,
+    method_name: &MethodFirstname,
+) -> Vec<(ClassFullname, ClassFullname)> {
+    let (n_params, ret_ty) = match method_name.0.as_str() {
+        "hash" => (0, ty::raw("Int")),
+        "to_s" => (0, ty::raw("String")),
+        _ => (1, ty::raw("Bool")),
+    };
+    let mut impls = classes
+        .values()
+        .filter(|cls| !cls.is_abstract())
+        .filter_map(|cls| {
+            let mut owner = Some(cls);
+            while let Some(c) = owner {
+                if c.method_sigs.contains_key(method_name)
+                    && !c.abstract_methods.contains(method_name)
+                {
+                    break;
+                }
+                owner = c
+                    .superclass_fullname
+                    .as_ref()
+                    .and_then(|name| classes.get(name));
+            }
+            let owner = owner?;
+            let sig = &owner.method_sigs[method_name];
+            if owner.fullname.0 == "Object" || sig.params.len() != n_params || sig.ret_ty != ret_ty
+            {
+                return None;
+            }
+            Some((cls.fullname.clone(), owner.fullname.clone()))
+        })
+        .collect::<Vec<_>>();
+    impls.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+    impls
+}
+
+/// `impls`: list of (receiver class, class which implements the method)
+fn dispatcher(sig: MethodSignature, impls: Vec<(ClassFullname, ClassFullname)>) -> SkMethod {
+    let method_name = sig.first_name().0.clone();
+    let dispatcher_body = move |code_gen: &CodeGen, function: &inkwell::values::FunctionValue| {
+        let params = function.get_params();
+        let this = params[0];
+        let class_id = code_gen.build_class_id_load(this);
+        let default_block = code_gen.context.append_basic_block(*function, "Default");
+        let cases = impls
+            .iter()
+            .map(|(cls, _)| {
+                let block = code_gen
+                    .context
+                    .append_basic_block(*function, &format!("Call_{}", cls.0));
+                (code_gen.class_id(cls), block)
+            })
+            .collect::<Vec<_>>();
+        code_gen
+            .builder
+            .build_switch(class_id, default_block, &cases);
+
+        for ((_, owner), (cls_id, block)) in impls.iter().zip(cases.iter()) {
+            code_gen.builder.position_at_end(*block);
+            let func_name = method_fullname(owner, &method_name);
+            let func = code_gen
+                .module
+                .get_function(&func_name.full_name)
+                .unwrap_or_else(|| panic!("[BUG] function `{}' not found", &func_name));
+            let param_types = func.get_type().get_param_types();
+            let mut args = vec![code_gen
+                .builder
+                .build_bitcast(this, param_types[0], "receiver")];
+            if method_name == "==" {
+                // Not equal if `other` is an instance of another class
+                let other = params[1];
+                let same_class = code_gen.builder.build_int_compare(
+                    inkwell::IntPredicate::EQ,
+                    code_gen.build_class_id_load(other),
+                    *cls_id,
+                    "same_class",
+                );
+                let call_block = code_gen.context.append_basic_block(*function, "CallEq");
+                let false_block = code_gen.context.append_basic_block(*function, "NotEq");
+                code_gen
+                    .builder
+                    .build_conditional_branch(same_class, call_block, false_block);
+                code_gen.builder.position_at_end(false_block);
+                let sk_false = code_gen.box_bool(code_gen.i1_type.const_int(0, false));
+                code_gen.builder.build_return(Some(&sk_false));
+                code_gen.builder.position_at_end(call_block);
+                args.push(
+                    code_gen
+                        .builder
+                        .build_bitcast(other, param_types[1], "other"),
+                );
+            }
+            let result = code_gen
+                .builder
+                .build_call(func, &args, "result")
+                .try_as_basic_value()
+                .left()
+                .expect("[BUG] no return value");
+            code_gen.builder.build_return(Some(&result));
+        }
+
+        // The methods of `Object`
+        code_gen.builder.position_at_end(default_block);
+        let result = match method_name.as_str() {
+            "==" => code_gen.box_bool(code_gen.build_identical(this, params[1])),
+            "hash" => code_gen.box_int(&code_gen.build_identity_hash(this)),
+            _ => {
+                let func = code_gen
+                    .module
+                    .get_function("Object#inspect")
+                    .expect("[BUG] function `Object#inspect' not found");
+                code_gen
+                    .builder
+                    .build_call(func, &[this], "inspected")
+                    .try_as_basic_value()
+                    .left()
+                    .expect("[BUG] no return value")
+            }
+        };
+        code_gen.builder.build_return(Some(&result));
+        Ok(())
+    };
+
+    SkMethod {
+        signature: sig,
+        body: SkMethodBody::RustClosureMethodBody {
+            boxed_gen: Box::new(dispatcher_body),
+        },
+    }
+}
@@ -1,13 +1,18 @@
 use crate::ast::*;
 use crate::code_gen::CodeGen;
+use crate::error;
 use crate::error::Error;
 use crate::hir;
 use crate::hir::class_dict::ClassDict;
+use crate::hir::config::BuildConfig;
 use crate::hir::hir_maker_context::*;
 use crate::hir::method_dict::MethodDict;
+use crate::hir::shared_lvars;
 use crate::hir::*;
 use crate::names;
+use crate::parser::lexer::Span;
 use crate::type_checking;
+use std::collections::HashSet;
 
 #[derive(Debug)]
 pub struct HirMaker {
@@ -18,6 +23,11 @@ pub struct HirMaker {
     /// List of constants found so far
     pub(super) constants: HashMap<ConstFullname, TermTy>,
     pub(super) const_inits: Vec<HirExpression>,
+    /// Initializers of constants which are not converted yet
+    /// (namespace, expr)
+    pub(super) const_defs: HashMap<ConstFullname, (ClassFullname, AstExpression)>,
+    /// Constants whose initializers are being converted
+    pub(super) const_resolving: Vec<ConstFullname>,
     /// List of string literals found so far
     pub(super) str_literals: Vec<String>,
     /// Stack of ctx
@@ -26,11 +36,25 @@ pub struct HirMaker {
     gensym_ct: usize,
     /// Counter to give unique name for lambdas
     pub(super) lambda_ct: usize,
+    /// Current nesting level of expressions
+    pub(super) nesting: usize,
+    /// Warnings found so far
+    pub(super) warnings: Vec<warnings::Warning>,
+    /// Classes whose definitions are processed (the later ones reopen them)
+    defined_classes: HashSet<ClassFullname>,
+    /// `--release` (see assertions.rs) and the compile-time constants (see
+    /// config.rs)
+    pub(super) config: BuildConfig,
 }
 
-pub fn make_hir(ast: ast::Program, corelib: Corelib) -> Result<Hir, Error> {
+/// Default value of `BuildConfig::max_type_nesting`.
+/// (Deeply nested expressions like `1+1+...+1` can be made without
+/// nesting parentheses)
+pub const DEFAULT_MAX_NESTING: usize = 256;
+
+pub fn make_hir(ast: ast::Program, corelib: Corelib, config: &BuildConfig) -> Result<Hir, Error> {
     let class_dict = class_dict::create(&ast, corelib.sk_classes)?;
-    let mut hir = convert_program(class_dict, ast)?;
+    let mut hir = convert_program(class_dict, ast, config)?;
 
     // While corelib classes are included in `class_dict`,
     // corelib methods are not. Here we need to add them manually
@@ -39,24 +63,46 @@ pub fn make_hir(ast: ast::Program, corelib: Corelib) -> Result<Hir, Error> {
     Ok(hir)
 }
 
-fn convert_program(class_dict: ClassDict, prog: ast::Program) -> Result<Hir, Error> {
-    let mut hir_maker = HirMaker::new(class_dict);
+fn convert_program(
+    class_dict: ClassDict,
+    prog: ast::Program,
+    config: &BuildConfig,
+) -> Result<Hir, Error> {
+    let mut hir_maker = HirMaker::new(class_dict, config.clone());
     hir_maker.register_class_consts();
-    let main_exprs = hir_maker.convert_toplevel_items(&prog.toplevel_items)?;
-    Ok(hir_maker.extract_hir(main_exprs))
+    hir_maker.collect_const_defs(&prog.toplevel_items)?;
+    let mut main_exprs = hir_maker.convert_toplevel_items(&prog.toplevel_items)?;
+    let hook_calls = hir_maker.inherited_hook_calls(&prog.toplevel_items)?;
+    main_exprs.exprs.splice(0..0, hook_calls);
+    hir_maker.class_dict.check_abstract_methods()?;
+    hir_maker.class_dict.check_overloads()?;
+    hir_maker.define_abstract_method_dispatchers();
+    hir_maker.define_hash_dispatchers();
+    let mut hir = hir_maker.extract_hir(main_exprs);
+    hir.const_inits = hir::const_init_order::sort_const_inits(hir.const_inits, &hir.sk_methods)?;
+    hir::flow_check::check_unreachable(&hir)?;
+    hir.def_spans = hir::def_spans::collect_def_spans(&prog);
+    hir.docs = hir::docs::collect_docs(&prog);
+    Ok(hir)
 }
 
 impl HirMaker {
-    fn new(class_dict: ClassDict) -> HirMaker {
+    fn new(class_dict: ClassDict, config: BuildConfig) -> HirMaker {
         HirMaker {
             class_dict,
             method_dict: MethodDict::new(),
             constants: HashMap::new(),
             const_inits: vec![],
+            const_defs: HashMap::new(),
+            const_resolving: vec![],
             str_literals: vec![],
             ctx_stack: vec![],
             gensym_ct: 0,
             lambda_ct: 0,
+            nesting: 0,
+            warnings: vec![],
+            defined_classes: HashSet::new(),
+            config,
         }
     }
 
@@ -64,7 +110,7 @@ impl HirMaker {
     fn extract_hir(&mut self, main_exprs: HirExpressions) -> Hir {
         // Extract data from self
         let sk_classes = std::mem::replace(&mut self.class_dict.sk_classes, HashMap::new());
-        let sk_methods = std::mem::take(&mut self.method_dict.sk_methods);
+        let sk_methods = self.method_dict.take_methods();
         let mut constants = HashMap::new();
         std::mem::swap(&mut constants, &mut self.constants);
         let mut str_literals = vec![];
@@ -82,6 +128,10 @@ impl HirMaker {
             str_literals,
             const_inits,
             main_exprs,
+            def_spans: HashMap::new(),
+            docs: HashMap::new(),
+            warnings: std::mem::take(&mut self.warnings),
+            release: self.config.release,
         }
     }
 
@@ -111,6 +161,18 @@ impl HirMaker {
         self.const_inits.push(op);
     }
 
+    /// Convert the toplevel expressions into the main program.
+    ///
+    /// - A local variable assigned at toplevel is visible in the following
+    ///   toplevel expressions (and the lambdas in them), but not in the
+    ///   methods or the initializers of the constants.
+    /// - A constant assigned at toplevel (eg. `A = 1`) is not a local
+    ///   variable: its initializer is evaluated before the main program (see
+    ///   `collect_const_defs`) and here it is just a reference to the value.
+    /// - The value of the last expression is the exit status of the program
+    ///   if it is an `Int` (see `yields_exit_status`.) Otherwise the type of
+    ///   the main program (not the last expression) is made `Void` and the
+    ///   status is 0.
     fn convert_toplevel_items(
         &mut self,
         items: &[ast::TopLevelItem],
@@ -118,6 +180,11 @@ impl HirMaker {
         let mut main_exprs = vec![];
         // Contains local vars defined at toplevel
         self.push_ctx(HirMakerContext::toplevel());
+        let toplevel_exprs = items.iter().filter_map(|item| match item {
+            ast::TopLevelItem::Expr(expr) => Some(expr),
+            _ => None,
+        });
+        self.ctx_mut().shared_lvars = shared_lvars::collect_shared_lvars(toplevel_exprs);
         for item in items {
             match item {
                 ast::TopLevelItem::Def(def) => {
@@ -128,8 +195,14 @@ impl HirMaker {
                 }
             }
         }
-        self.pop_ctx();
-        Ok(HirExpressions::new(main_exprs))
+        let ctx = self.pop_ctx();
+        self.warn_unused_lvars(&ctx, call_graph::MAIN.to_string());
+        let is_status = yields_exit_status(items, &main_exprs);
+        let mut exprs = HirExpressions::new(main_exprs);
+        if !is_status {
+            exprs.ty = ty::raw("Void");
+        }
+        Ok(exprs)
     }
 
     fn process_toplevel_def(&mut self, def: &ast::Definition) -> Result<(), Error> {
@@ -139,8 +212,8 @@ impl HirMaker {
                 let full = name.add_namespace("");
                 self.collect_sk_methods(&full, defs)?;
             }
-            ast::Definition::ConstDefinition { name, expr } => {
-                self.register_const(name, expr)?;
+            ast::Definition::ConstDefinition { name, expr, .. } => {
+                self.register_const(&class_fullname(""), name, expr)?;
             }
             _ => panic!("should be checked in hir::class_dict"),
         }
@@ -153,8 +226,11 @@ impl HirMaker {
         fullname: &ClassFullname,
         defs: &[ast::Definition],
     ) -> Result<(), Error> {
-        self.register_meta_ivar(&fullname)?;
-        self.process_defs(defs, &fullname)?;
+        let reopened = !self.defined_classes.insert(fullname.clone());
+        if !reopened {
+            self.register_meta_ivar(&fullname)?;
+        }
+        self.process_defs(defs, &fullname, reopened)?;
         Ok(())
     }
 
@@ -173,30 +249,42 @@ impl HirMaker {
         Ok(())
     }
 
-    /// Process each method def and const def
+    /// Process each method def and const def. When the class is
+    /// `reopened`, the ivars and `.new` are left as they are (a reopened
+    /// class cannot have `#initialize`; see class_dict/indexing.rs) and the
+    /// methods of the same name are replaced
     fn process_defs(
         &mut self,
         defs: &[ast::Definition],
         fullname: &ClassFullname,
+        reopened: bool,
     ) -> Result<(), Error> {
         let meta_name = fullname.meta_name();
         let mut ctx = HirMakerContext::class_ctx(&fullname);
 
-        // Add `#initialize`
-        let mut own_ivars = HashMap::default();
-        if let Some(ast::Definition::InstanceMethodDefinition {
-            sig, body_exprs, ..
-        }) = defs.iter().find(|d| d.is_initializer())
-        {
-            let (sk_method, found_ivars) =
-                self.create_initialize(&mut ctx, &fullname, &sig.name, &body_exprs)?;
-            self.method_dict.add_method(&fullname, sk_method);
-            own_ivars = found_ivars;
+        if !reopened {
+            // Add `#initialize`
+            let mut own_ivars = HashMap::default();
+            if let Some(ast::Definition::InstanceMethodDefinition {
+                sig, body_exprs, ..
+            }) = defs.iter().find(|d| d.is_initializer())
+            {
+                let (sk_method, found_ivars) =
+                    self.create_initialize(&mut ctx, &fullname, &sig.name, &body_exprs)?;
+                self.method_dict.add_method(&fullname, sk_method);
+                own_ivars = found_ivars;
+            }
+            self.define_ivars(fullname, own_ivars, defs)?;
         }
-        self.define_ivars(fullname, own_ivars, defs)?;
+        for def in defs {
+            if let ast::Definition::Delegation { ivar_name, .. } = def {
+                self.define_delegation(&fullname, ivar_name)?;
+            }
+        }
+        self.define_derived_methods(&ctx, &fullname, defs)?;
 
         // Add `.new`
-        if has_new(&fullname) {
+        if !reopened && has_new(&fullname) {
             self.method_dict
                 .add_method(&meta_name, self.create_new(&fullname)?);
         }
@@ -217,13 +305,21 @@ impl HirMaker {
                         self.convert_method_def(&ctx, &meta_name, &sig.name, &body_exprs)?;
                     self.method_dict.add_method(&meta_name, method);
                 }
-                ast::Definition::ConstDefinition { name, expr } => {
-                    self.register_const(name, expr)?;
+                // Dispatchers are defined later (see define_abstract_method_dispatchers)
+                ast::Definition::AbstractMethodDefinition { .. } => (),
+                ast::Definition::ExternMethodDefinition { sig } => {
+                    let method = self.create_extern_method(&meta_name, &sig.name)?;
+                    self.method_dict.add_method(&meta_name, method);
+                }
+                ast::Definition::ConstDefinition { name, expr, .. } => {
+                    self.register_const(&fullname, name, expr)?;
                 }
                 ast::Definition::ClassDefinition { name, defs, .. } => {
                     let full = name.add_namespace(&fullname.0);
                     self.collect_sk_methods(&full, defs)?;
                 }
+                // Defined above
+                ast::Definition::Delegation { .. } | ast::Definition::Derive { .. } => (),
             }
         }
         Ok(())
@@ -329,20 +425,138 @@ impl HirMaker {
         ))
     }
 
-    /// Register a constant
+    /// Collect constant definitions in the program so that an initializer
+    /// can refer constants defined after it
+    fn collect_const_defs(&mut self, items: &[ast::TopLevelItem]) -> Result<(), Error> {
+        let toplevel = class_fullname("");
+        items.iter().try_for_each(|item| match item {
+            ast::TopLevelItem::Def(def) => self.collect_const_defs_(&toplevel, def),
+            ast::TopLevelItem::Expr(AstExpression {
+                body: AstExpressionBody::ConstAssign { names, rhs },
+                ..
+            }) => self.add_const_def(&toplevel, &const_firstname(&names.join("::")), None, rhs),
+            ast::TopLevelItem::Expr(_) => Ok(()),
+        })
+    }
+
+    fn collect_const_defs_(
+        &mut self,
+        namespace: &ClassFullname,
+        def: &ast::Definition,
+    ) -> Result<(), Error> {
+        match def {
+            ast::Definition::ConstDefinition {
+                name,
+                name_span,
+                expr,
+            } => self.add_const_def(namespace, name, Some(name_span), expr),
+            ast::Definition::ClassDefinition { name, defs, .. } => {
+                let full = name.add_namespace(&namespace.0);
+                defs.iter()
+                    .try_for_each(|def| self.collect_const_defs_(&full, def))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn add_const_def(
+        &mut self,
+        namespace: &ClassFullname,
+        name: &ConstFirstname,
+        name_span: Option<&Span>,
+        expr: &AstExpression,
+    ) -> Result<(), Error> {
+        self.check_config_const_def(namespace, name)?;
+        let fullname = const_fullname_in(&namespace.0, &name.0);
+        if self.constants.contains_key(&fullname) || self.const_defs.contains_key(&fullname) {
+            let err = error::name_error(&format!("constant `{}' is already defined", fullname));
+            return Err(match name_span {
+                Some(span) => err.with_span(span.clone()),
+                None => err,
+            });
+        }
+        self.const_defs
+            .insert(fullname, (namespace.clone(), expr.clone()));
+        Ok(())
+    }
+
+    /// Register a constant defined in `namespace`
     pub(super) fn register_const(
         &mut self,
+        namespace: &ClassFullname,
         name: &ConstFirstname,
         expr: &AstExpression,
     ) -> Result<ConstFullname, Error> {
-        let ctx = self.ctx();
-        // TODO: resolve name using ctx
-        let fullname = const_fullname(&format!("{}::{}", ctx.namespace.0, &name.0));
-        let hir_expr = self.convert_expr(expr)?;
+        self.check_config_const_def(namespace, name)?;
+        let fullname = const_fullname_in(&namespace.0, &name.0);
+        if !self.constants.contains_key(&fullname) {
+            if !self.const_defs.contains_key(&fullname) {
+                self.const_defs
+                    .insert(fullname.clone(), (namespace.clone(), expr.clone()));
+            }
+            self.convert_const_def(&fullname)?;
+        }
+        Ok(fullname)
+    }
+
+    /// Convert the initializer of a constant (if not yet)
+    pub(super) fn convert_const_def(&mut self, fullname: &ConstFullname) -> Result<(), Error> {
+        if let Some(i) = self.const_resolving.iter().position(|c| c == fullname) {
+            let cycle = self.const_resolving[i..]
+                .iter()
+                .chain(std::iter::once(fullname))
+                .map(|c| c.0.clone())
+                .collect::<Vec<_>>();
+            return Err(error::program_error(&format!(
+                "cyclic initialization of constants: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        let (namespace, expr) = match self.const_defs.remove(fullname) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        // Initializers are evaluated before the main program, so local
+        // variables etc. are not visible from them
+        let ctx = if namespace.0.is_empty() {
+            HirMakerContext::toplevel()
+        } else {
+            HirMakerContext::class_ctx(&namespace)
+        };
+        let ctx_stack = std::mem::replace(&mut self.ctx_stack, vec![ctx]);
+        self.ctx_mut().shared_lvars = shared_lvars::collect_shared_lvars(std::iter::once(&expr));
+        self.const_resolving.push(fullname.clone());
+        let result = self.convert_expr(&expr);
+        self.const_resolving.pop();
+        self.ctx_stack = ctx_stack;
+
+        let hir_expr = result?;
+        type_checking::check_not_void(
+            &hir_expr.ty,
+            &format!("the value of constant `{}'", fullname.0),
+        )?;
         self.constants.insert(fullname.clone(), hir_expr.ty.clone());
         let op = Hir::assign_const(fullname.clone(), hir_expr);
         self.const_inits.push(op);
-        Ok(fullname)
+        Ok(())
+    }
+
+    /// Resolve the constant `names` (eg. `["A", "B"]` for `A::B`) referred
+    /// in the current namespace, searching from the innermost scope outward.
+    /// Returns None if not found
+    pub(super) fn resolve_const_name(&self, names: &[String]) -> Option<ConstFullname> {
+        let name = names.join("::");
+        self.ctx()
+            .namespace
+            .lexical_scopes()
+            .iter()
+            .map(|scope| const_fullname_in(scope, &name))
+            .find(|fullname| {
+                self.constants.contains_key(fullname)
+                    || self.const_defs.contains_key(fullname)
+                    || self.const_resolving.contains(fullname)
+            })
     }
 
     fn convert_method_def(
@@ -380,13 +594,25 @@ impl HirMaker {
 
         self.push_ctx(HirMakerContext::method_ctx(
             ctx,
+            class_fullname,
             &signature,
             is_initializer,
             super_ivars.unwrap_or_else(|| HashMap::new()),
         ));
-        let body_exprs = self.convert_exprs(body_exprs)?;
-        let iivars = self.pop_ctx().iivars;
-        type_checking::check_return_value(&signature, &body_exprs.ty)?;
+        self.ctx_mut().shared_lvars = shared_lvars::collect_shared_lvars(body_exprs);
+        let mut body_exprs = self.convert_exprs(body_exprs)?;
+        let ctx = self.pop_ctx();
+        self.warn_unused_lvars(&ctx, signature.fullname.full_name.clone());
+        let iivars = ctx.iivars;
+        hir::flow_check::check_method_value(&signature, &body_exprs)?;
+        type_checking::check_return_value(&self.class_dict, &signature, &body_exprs.ty)?;
+        if !signature.ret_ty.is_void_type()
+            && body_exprs.ty != signature.ret_ty
+            && body_exprs.ty.is_raw()
+        {
+            // Upcast needed
+            body_exprs.bit_cast(signature.ret_ty.clone());
+        }
 
         let body = SkMethodBody::ShiikaMethodBody { exprs: body_exprs };
         Ok((SkMethod { signature, body }, iivars))
@@ -409,3 +635,24 @@ fn has_new(fullname: &ClassFullname) -> bool {
     }
     true
 }
+
+/// Returns true if the last toplevel expression gives the exit status (an
+/// `Int` or `Never`.) An assignment like `n = 1` does not count because it
+/// is not meant to be the result of the program
+fn yields_exit_status(items: &[ast::TopLevelItem], main_exprs: &[HirExpression]) -> bool {
+    let last_item = items.iter().rev().find_map(|item| match item {
+        ast::TopLevelItem::Expr(expr) => Some(expr),
+        _ => None,
+    });
+    let is_assign = match last_item.map(|expr| &expr.body) {
+        Some(AstExpressionBody::LVarAssign { .. })
+        | Some(AstExpressionBody::IVarAssign { .. })
+        | Some(AstExpressionBody::ConstAssign { .. })
+        | Some(AstExpressionBody::MultipleAssign { .. }) => true,
+        _ => false,
+    };
+    match main_exprs.last() {
+        Some(expr) => !is_assign && (expr.ty == ty::raw("Int") || expr.ty.is_never_type()),
+        None => false,
+    }
+}
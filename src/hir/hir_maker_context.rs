@@ -3,7 +3,7 @@ use crate::hir::*;
 use crate::names::*;
 use crate::ty;
 use crate::ty::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct HirMakerContext {
@@ -21,8 +21,19 @@ pub struct HirMakerContext {
     pub namespace: ClassFullname,
     /// Current local variables
     pub lvars: HashMap<String, CtxLVar>,
+    /// Names of the local variables declared in each block (the body of
+    /// `if`, `while`, etc.) being converted. The last one is the innermost
+    pub scopes: Vec<Vec<String>>,
+    /// Local variables whose block has ended. A variable declared again
+    /// with the same name shares the storage with them
+    pub ended_lvars: Vec<CtxLVar>,
+    /// Local variables (to be) declared in this ctx which are reassigned
+    /// in lambdas (see hir/shared_lvars.rs)
+    pub shared_lvars: HashSet<String>,
     /// List of free variables captured in this context
     pub captures: Vec<LambdaCapture>,
+    /// Loops (`while`) we are in. The last one is the innermost
+    pub loops: Vec<CtxLoop>,
 
     //
     // ivar-related stuffs
@@ -49,6 +60,19 @@ pub struct CtxLVar {
     pub name: String,
     pub ty: TermTy,
     pub readonly: bool,
+    /// Whether stored in a cell (see hir/shared_lvars.rs)
+    pub shared: bool,
+    /// Whether referred anywhere (see hir/warnings.rs)
+    pub used: bool,
+}
+
+/// A loop being converted
+#[derive(Debug, Default)]
+pub struct CtxLoop {
+    /// Types of the values given to `break`
+    pub break_tys: Vec<TermTy>,
+    /// Whether there is a `break` without a value
+    pub has_plain_break: bool,
 }
 
 #[derive(Debug)]
@@ -75,7 +99,11 @@ impl HirMakerContext {
             self_ty: ty::raw("Object"),
             namespace: ClassFullname("".to_string()),
             lvars: HashMap::new(),
+            scopes: vec![],
+            ended_lvars: vec![],
+            shared_lvars: HashSet::new(),
             captures: vec![],
+            loops: vec![],
             iivars: HashMap::new(),
             is_initializer: false,
             super_ivars: HashMap::new(),
@@ -91,7 +119,11 @@ impl HirMakerContext {
             self_ty: ty::raw("Object"),
             namespace: fullname.clone(),
             lvars: HashMap::new(),
+            scopes: vec![],
+            ended_lvars: vec![],
+            shared_lvars: HashSet::new(),
             captures: vec![],
+            loops: vec![],
             iivars: HashMap::new(),
             is_initializer: false,
             super_ivars: HashMap::new(),
@@ -99,8 +131,11 @@ impl HirMakerContext {
     }
 
     /// Create a method context
+    /// `class_fullname` is the metaclass for a class method (so that
+    /// `new(...)` in it is `Foo.new(...)`)
     pub fn method_ctx(
         class_ctx: &HirMakerContext,
+        class_fullname: &ClassFullname,
         method_sig: &MethodSignature,
         is_initializer: bool,
         super_ivars: SkIVars,
@@ -109,10 +144,14 @@ impl HirMakerContext {
             kind: CtxKind::Method,
             depth: 0,
             method_sig: Some(method_sig.clone()),
-            self_ty: ty::raw(&class_ctx.namespace.0),
+            self_ty: class_fullname.to_ty(),
             namespace: class_ctx.namespace.clone(),
             lvars: HashMap::new(),
+            scopes: vec![],
+            ended_lvars: vec![],
+            shared_lvars: HashSet::new(),
             captures: vec![],
+            loops: vec![],
             iivars: HashMap::new(),
             is_initializer,
             super_ivars,
@@ -125,6 +164,7 @@ impl HirMakerContext {
             fullname: method_fullname(&class_fullname("(anon)"), "(anon)"),
             ret_ty: ty::raw("(dummy)"),
             params,
+            typarams: vec![],
         };
         HirMakerContext {
             kind: CtxKind::Lambda,
@@ -133,7 +173,11 @@ impl HirMakerContext {
             self_ty: method_ctx.self_ty.clone(),
             namespace: method_ctx.namespace.clone(),
             lvars: HashMap::new(),
+            scopes: vec![],
+            ended_lvars: vec![],
+            shared_lvars: HashSet::new(),
             captures: vec![],
+            loops: vec![],
             iivars: HashMap::new(),
             is_initializer: false,
             super_ivars: HashMap::new(),
@@ -145,6 +189,14 @@ impl HirMakerContext {
         self.lvars.get(name)
     }
 
+    /// Add a local variable to the innermost block
+    pub fn declare_lvar(&mut self, lvar: CtxLVar) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(lvar.name.clone());
+        }
+        self.lvars.insert(lvar.name.clone(), lvar);
+    }
+
     /// Return method/lambda argument of given name, if any
     pub fn find_fn_arg(&self, name: &str) -> Option<(usize, &MethodParam)> {
         self.method_sig
@@ -163,7 +215,9 @@ impl HirMaker {
         self.ctx_stack.last_mut().unwrap()
     }
 
-    pub(super) fn push_ctx(&mut self, ctx: HirMakerContext) {
+    pub(super) fn push_ctx(&mut self, mut ctx: HirMakerContext) {
+        // Lambdas in a method look up the captured variables by this
+        ctx.depth = self.ctx_stack.len();
         self.ctx_stack.push(ctx);
     }
 
@@ -171,6 +225,20 @@ impl HirMaker {
         self.ctx_stack.pop().unwrap()
     }
 
+    /// Start a block which has its own local variables
+    pub(super) fn push_scope(&mut self) {
+        self.ctx_mut().scopes.push(vec![]);
+    }
+
+    /// End the innermost block; its variables cannot be referred after this
+    pub(super) fn pop_scope(&mut self) {
+        let ctx = self.ctx_mut();
+        for name in ctx.scopes.pop().unwrap() {
+            let lvar = ctx.lvars.remove(&name).unwrap();
+            ctx.ended_lvars.push(lvar);
+        }
+    }
+
     pub(super) fn method_ctx(&self) -> Option<&HirMakerContext> {
         let mut i = (self.ctx_stack.len() as isize) - 1;
         while i >= 0 {
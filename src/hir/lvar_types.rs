@@ -0,0 +1,41 @@
+/// Collects the types of local variables (for the hover of editors)
+use crate::hir::const_init_order::{collect_refs_in_exprs, Refs};
+use crate::hir::*;
+
+/// Return the types of the local variables (including the parameters)
+/// in each method, keyed by the full name of the method (`""` for the
+/// toplevel). If a variable is assigned more than once, the first one is
+/// used
+pub fn collect_lvar_types(hir: &Hir) -> HashMap<String, HashMap<String, TermTy>> {
+    let mut types = HashMap::new();
+    for methods in hir.sk_methods.values() {
+        for method in methods {
+            if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
+                let sig = &method.signature;
+                let params = sig
+                    .params
+                    .iter()
+                    .map(|param| (param.name.clone(), param.ty.clone()));
+                types.insert(sig.fullname.full_name.clone(), lvar_types(params, exprs));
+            }
+        }
+    }
+    types.insert(
+        "".to_string(),
+        lvar_types(std::iter::empty(), &hir.main_exprs),
+    );
+    types
+}
+
+fn lvar_types(
+    params: impl Iterator<Item = (String, TermTy)>,
+    exprs: &HirExpressions,
+) -> HashMap<String, TermTy> {
+    let mut refs = Refs::default();
+    collect_refs_in_exprs(exprs, &mut refs);
+    let mut types = HashMap::new();
+    for (name, ty) in params.chain(refs.lvars) {
+        types.entry(name).or_insert(ty);
+    }
+    types
+}
@@ -5,27 +5,43 @@ use std::collections::HashMap;
 /// Contains all the methods
 #[derive(Debug)]
 pub struct MethodDict {
-    pub sk_methods: HashMap<ClassFullname, Vec<SkMethod>>,
+    sk_methods: HashMap<ClassFullname, Vec<SkMethod>>,
+    /// Index of each method in `sk_methods` (so that adding a method does
+    /// not scan the methods of the class)
+    indices: HashMap<ClassFullname, HashMap<MethodFirstname, usize>>,
 }
 
 impl MethodDict {
     pub fn new() -> MethodDict {
         MethodDict {
             sk_methods: HashMap::new(),
+            indices: HashMap::new(),
         }
     }
 
-    /// Return the vec for the method for mutation
+    /// Add a method. A method of the same name is replaced (the class is
+    /// reopened)
     pub fn add_method(&mut self, classname: &ClassFullname, method: SkMethod) {
-        self.register_class(classname);
-        let vec = self.sk_methods.get_mut(classname).unwrap();
-        vec.push(method);
+        let vec = self
+            .sk_methods
+            .entry(classname.clone())
+            .or_insert_with(Vec::new);
+        let indices = self
+            .indices
+            .entry(classname.clone())
+            .or_insert_with(HashMap::new);
+        match indices.get(&method.signature.fullname.first_name) {
+            Some(&i) => vec[i] = method,
+            None => {
+                indices.insert(method.signature.fullname.first_name.clone(), vec.len());
+                vec.push(method);
+            }
+        }
     }
 
-    /// Add entry for the class if not exist.
-    fn register_class(&mut self, fullname: &ClassFullname) {
-        if !self.sk_methods.contains_key(fullname) {
-            self.sk_methods.insert(fullname.clone(), vec![]);
-        }
+    /// Take all the methods (self becomes empty)
+    pub fn take_methods(&mut self) -> HashMap<ClassFullname, Vec<SkMethod>> {
+        self.indices.clear();
+        std::mem::take(&mut self.sk_methods)
     }
 }
@@ -1,18 +1,38 @@
+mod abstract_methods;
 mod accessors;
+mod assertions;
+pub mod call_graph;
 pub mod class_dict;
+mod class_hooks;
+pub mod config;
+mod const_init_order;
 mod convert_exprs;
+mod def_spans;
+mod delegation;
+mod derive;
+pub mod docs;
+mod extern_methods;
+mod flow_check;
+mod format;
+pub mod hash_dispatch;
 mod hir_maker;
 mod hir_maker_context;
+pub mod lvar_types;
 mod method_dict;
+mod pattern_match;
+pub mod pretty_print;
+pub mod serialize;
 pub mod signature;
+mod shared_lvars;
 mod sk_class;
+pub mod warnings;
 use crate::ast;
 use crate::corelib::Corelib;
 use crate::names::*;
 use crate::ty;
 use crate::ty::*;
 pub use sk_class::SkClass;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct Hir {
@@ -22,17 +42,44 @@ pub struct Hir {
     pub str_literals: Vec<String>,
     pub const_inits: Vec<HirExpression>,
     pub main_exprs: HirExpressions,
+    /// Where the classes, methods and constants are defined
+    /// (see `def_spans::collect_def_spans`)
+    pub def_spans: HashMap<String, crate::parser::lexer::Span>,
+    /// Doc comments of the classes and methods (see `docs::collect_docs`)
+    pub docs: HashMap<String, String>,
+    /// Warnings found while making the HIR (see `warnings`)
+    pub warnings: Vec<warnings::Warning>,
+    /// Whether this is a release build (`--release`): the assertions are
+    /// removed and the methods do not record the backtrace
+    pub release: bool,
 }
 
 pub fn build(ast: ast::Program, corelib: Corelib) -> Result<Hir, crate::error::Error> {
-    hir_maker::make_hir(ast, corelib)
+    hir_maker::make_hir(ast, corelib, &config::BuildConfig::default())
+}
+
+/// Same as `build` but with the given configuration (`--release`,
+/// `--define`; see hir/config.rs)
+pub fn build_with_config(
+    ast: ast::Program,
+    corelib: Corelib,
+    config: &config::BuildConfig,
+) -> Result<Hir, crate::error::Error> {
+    hir_maker::make_hir(ast, corelib, config)
 }
 
 impl Hir {
+    /// Add the methods of the corelib. The ones redefined in the program
+    /// (by reopening the class) are skipped
     pub fn add_methods(&mut self, sk_methods: HashMap<ClassFullname, Vec<SkMethod>>) {
         for (classname, mut new_methods) in sk_methods {
             match self.sk_methods.get_mut(&classname) {
                 Some(methods) => {
+                    let defined = methods
+                        .iter()
+                        .map(|m| m.signature.fullname.full_name.clone())
+                        .collect::<HashSet<_>>();
+                    new_methods.retain(|new| !defined.contains(&new.signature.fullname.full_name));
                     methods.append(&mut new_methods);
                 }
                 None => {
@@ -75,10 +122,13 @@ pub type GenMethodBody = fn(
     code_gen: &crate::code_gen::CodeGen,
     function: &inkwell::values::FunctionValue,
 ) -> Result<(), crate::error::Error>;
+/// (`Send` and `Sync` to generate the methods in parallel)
 pub type ClosureMethodBody = dyn Fn(
-    &crate::code_gen::CodeGen,
-    &inkwell::values::FunctionValue,
-) -> Result<(), crate::error::Error>;
+        &crate::code_gen::CodeGen,
+        &inkwell::values::FunctionValue,
+    ) -> Result<(), crate::error::Error>
+    + Send
+    + Sync;
 
 #[derive(Debug)]
 pub struct HirExpressions {
@@ -98,6 +148,13 @@ impl HirExpressions {
 
         HirExpressions { ty, exprs }
     }
+
+    /// Change the type of the last expression (and therefore self) to `ty`
+    pub fn bit_cast(&mut self, ty: TermTy) {
+        let last_expr = self.exprs.pop().unwrap();
+        self.exprs.push(Hir::bit_cast(ty.clone(), last_expr));
+        self.ty = ty;
+    }
 }
 
 #[derive(Debug)]
@@ -106,6 +163,18 @@ pub struct HirExpression {
     pub node: HirExpressionBase,
 }
 
+/// Nodes of HIR.
+///
+/// HIR is fully typed and does not rely on implicit conversions in the
+/// backend (code_gen):
+/// - Shiika objects (eg. `Int`) and native values (eg. `ty::native("Int")`,
+///   which is i32 in LLVM) are distinguished by the type and converted only
+///   by `HirBox` and `HirUnbox`. Literals and the operands/results of
+///   `HirLogical*` and conditions of `if`/`while` are native values
+/// - The receiver of `HirMethodCall` is passed to the function as the
+///   first argument (`HirArgRef` does not count it)
+/// - Variables captured by a lambda are loaded from its `captures`
+///   argument with explicit `Array#nth` calls and `HirBitCast`
 #[derive(Debug)]
 pub enum HirExpressionBase {
     HirLogicalNot {
@@ -128,7 +197,14 @@ pub enum HirExpressionBase {
         cond_expr: Box<HirExpression>,
         body_exprs: Box<HirExpressions>,
     },
-    HirBreakExpression,
+    HirBreakExpression {
+        expr: Option<Box<HirExpression>>,
+    },
+    HirNextExpression,
+    /// Return from the method (made from `expr?`)
+    HirReturnExpression {
+        arg: Box<HirExpression>,
+    },
     HirLVarAssign {
         name: String,
         rhs: Box<HirExpression>,
@@ -168,6 +244,10 @@ pub enum HirExpressionBase {
         captures_ary: Box<HirExpression>,
     },
     HirSelfExpression,
+    /// Evaluate the expressions and return the last value
+    HirParenthesizedExpr {
+        exprs: HirExpressions,
+    },
     HirArrayLiteral {
         exprs: HirExpressions,
     },
@@ -188,9 +268,13 @@ pub enum HirExpressionBase {
     //
     // Special opecodes (does not appear in a source program directly)
     //
-    /// Refer a variable in `captures`
-    HirLambdaCaptureRef {
-        idx: usize,
+    /// Convert a native value into a Shiika object (eg. i32 into `Int`)
+    HirBox {
+        expr: Box<HirExpression>,
+    },
+    /// Convert a Shiika object into a native value (eg. `Int` into i32)
+    HirUnbox {
+        expr: Box<HirExpression>,
     },
     /// Represents bitcast of an object
     HirBitCast {
@@ -203,6 +287,15 @@ pub enum HirExpressionBase {
         fullname: ClassFullname,
         str_literal_idx: usize,
     },
+    /// `breakpoint` (see code_gen/debugger.rs.) `vars` are the variables
+    /// visible from there. If `step` is true, this is a point where the
+    /// `next` command of the debugger stops (inserted before the statements
+    /// after a `breakpoint`)
+    HirBreakpoint {
+        place: String,
+        vars: Vec<(String, HirExpression)>,
+        step: bool,
+    },
 }
 
 impl Hir {
@@ -211,32 +304,32 @@ impl Hir {
     }
 
     pub fn logical_not(expr_hir: HirExpression) -> HirExpression {
-        HirExpression {
-            ty: ty::raw("Bool"),
+        Hir::boxing(HirExpression {
+            ty: ty::native("Bool"),
             node: HirExpressionBase::HirLogicalNot {
-                expr: Box::new(expr_hir),
+                expr: Box::new(Hir::unboxing(expr_hir)),
             },
-        }
+        })
     }
 
     pub fn logical_and(left_hir: HirExpression, right_hir: HirExpression) -> HirExpression {
-        HirExpression {
-            ty: ty::raw("Bool"),
+        Hir::boxing(HirExpression {
+            ty: ty::native("Bool"),
             node: HirExpressionBase::HirLogicalAnd {
-                left: Box::new(left_hir),
-                right: Box::new(right_hir),
+                left: Box::new(Hir::unboxing(left_hir)),
+                right: Box::new(Hir::unboxing(right_hir)),
             },
-        }
+        })
     }
 
     pub fn logical_or(left_hir: HirExpression, right_hir: HirExpression) -> HirExpression {
-        HirExpression {
-            ty: ty::raw("Bool"),
+        Hir::boxing(HirExpression {
+            ty: ty::native("Bool"),
             node: HirExpressionBase::HirLogicalOr {
-                left: Box::new(left_hir),
-                right: Box::new(right_hir),
+                left: Box::new(Hir::unboxing(left_hir)),
+                right: Box::new(Hir::unboxing(right_hir)),
             },
-        }
+        })
     }
 
     pub fn if_expression(
@@ -248,27 +341,50 @@ impl Hir {
         HirExpression {
             ty,
             node: HirExpressionBase::HirIfExpression {
-                cond_expr: Box::new(cond_hir),
+                cond_expr: Box::new(Hir::unboxing(cond_hir)),
                 then_exprs: Box::new(then_hir),
                 else_exprs: Box::new(else_hir),
             },
         }
     }
 
-    pub fn while_expression(cond_hir: HirExpression, body_hirs: HirExpressions) -> HirExpression {
+    /// `ty` is the type of the values given to `break` (or Void)
+    pub fn while_expression(
+        ty: TermTy,
+        cond_hir: HirExpression,
+        body_hirs: HirExpressions,
+    ) -> HirExpression {
         HirExpression {
-            ty: ty::raw("Void"),
+            ty,
             node: HirExpressionBase::HirWhileExpression {
-                cond_expr: Box::new(cond_hir),
+                cond_expr: Box::new(Hir::unboxing(cond_hir)),
                 body_exprs: Box::new(body_hirs),
             },
         }
     }
 
-    pub fn break_expression() -> HirExpression {
+    pub fn break_expression(expr: Option<HirExpression>) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Never"),
+            node: HirExpressionBase::HirBreakExpression {
+                expr: expr.map(Box::new),
+            },
+        }
+    }
+
+    pub fn next_expression() -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Never"),
+            node: HirExpressionBase::HirNextExpression {},
+        }
+    }
+
+    pub fn return_expression(arg: HirExpression) -> HirExpression {
         HirExpression {
             ty: ty::raw("Never"),
-            node: HirExpressionBase::HirBreakExpression {},
+            node: HirExpressionBase::HirReturnExpression {
+                arg: Box::new(arg),
+            },
         }
     }
 
@@ -365,6 +481,7 @@ impl Hir {
         params.push(MethodParam {
             name: "(captures)".to_string(),
             ty: ty::ary(ty::raw("Object")),
+            implicit_conv: false,
         });
         HirExpression {
             ty,
@@ -384,6 +501,13 @@ impl Hir {
         }
     }
 
+    pub fn parenthesized_expression(exprs: HirExpressions) -> HirExpression {
+        HirExpression {
+            ty: exprs.ty.clone(),
+            node: HirExpressionBase::HirParenthesizedExpr { exprs },
+        }
+    }
+
     pub fn array_literal(exprs: Vec<HirExpression>, ty: TermTy) -> HirExpression {
         HirExpression {
             ty,
@@ -394,17 +518,17 @@ impl Hir {
     }
 
     pub fn float_literal(value: f64) -> HirExpression {
-        HirExpression {
-            ty: ty::raw("Float"),
+        Hir::boxing(HirExpression {
+            ty: ty::native("Float"),
             node: HirExpressionBase::HirFloatLiteral { value },
-        }
+        })
     }
 
     pub fn decimal_literal(value: i32) -> HirExpression {
-        HirExpression {
-            ty: ty::raw("Int"),
+        Hir::boxing(HirExpression {
+            ty: ty::native("Int"),
             node: HirExpressionBase::HirDecimalLiteral { value },
-        }
+        })
     }
 
     pub fn string_literal(idx: usize) -> HirExpression {
@@ -415,9 +539,37 @@ impl Hir {
     }
 
     pub fn boolean_literal(value: bool) -> HirExpression {
-        HirExpression {
-            ty: ty::raw("Bool"),
+        Hir::boxing(HirExpression {
+            ty: ty::native("Bool"),
             node: HirExpressionBase::HirBooleanLiteral { value },
+        })
+    }
+
+    /// Convert a native value into the corresponding Shiika object
+    pub fn boxing(expr: HirExpression) -> HirExpression {
+        let ty = expr
+            .ty
+            .boxed_ty()
+            .unwrap_or_else(|| panic!("[BUG] cannot box {:?}", expr.ty));
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirBox {
+                expr: Box::new(expr),
+            },
+        }
+    }
+
+    /// Convert a Shiika object into the corresponding native value
+    pub fn unboxing(expr: HirExpression) -> HirExpression {
+        let ty = expr
+            .ty
+            .native_ty()
+            .unwrap_or_else(|| panic!("[BUG] cannot unbox {:?}", expr.ty));
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirUnbox {
+                expr: Box::new(expr),
+            },
         }
     }
 
@@ -440,12 +592,30 @@ impl Hir {
         }
     }
 
-    pub fn lambda_capture_ref(ty: TermTy, _arity: usize, idx: usize) -> HirExpression {
+    pub fn breakpoint(
+        place: String,
+        vars: Vec<(String, HirExpression)>,
+        step: bool,
+    ) -> HirExpression {
         HirExpression {
-            ty,
-            node: HirExpressionBase::HirLambdaCaptureRef { idx },
+            ty: ty::raw("Void"),
+            node: HirExpressionBase::HirBreakpoint { place, vars, step },
         }
     }
+
+    /// Load a captured variable from `captures` (the last argument of the
+    /// lambda function)
+    /// `arity`: number of the params of the lambda (excluding `captures`)
+    pub fn lambda_capture_ref(ty: TermTy, arity: usize, idx: usize) -> HirExpression {
+        let captures = Hir::hir_arg_ref(ty::ary(ty::raw("Object")), arity);
+        let item = Hir::method_call(
+            ty::raw("Object"),
+            captures,
+            method_fullname(&class_fullname("Array"), "nth"),
+            vec![Hir::decimal_literal(idx as i32)],
+        );
+        Hir::bit_cast(ty, item)
+    }
 }
 
 fn lambda_ty(params: &Vec<MethodParam>, ret_ty: &TermTy) -> TermTy {
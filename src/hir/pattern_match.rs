@@ -0,0 +1,180 @@
+/// Pattern matching with `case`
+///
+/// ```text
+///   case expr
+///   when 0, 1 then ...                    # Compared with `==`
+///   when [x, 0, *rest] if x > 0 then ...  # Array pattern with a guard
+///   when n then ...                       # Matches anything
+///   else ...
+///   end
+/// ```
+///
+/// is converted into nested `if`s. The value is assigned to a temporary
+/// variable and each `when` becomes a condition like `tmp.length >= 2 &&
+/// (x = tmp[0]; true) && tmp[1] == 0 && (rest = tmp.drop(2); true) && x > 0`
+/// so that the variables of the pattern are assigned only when the
+/// preceding parts match. They are local to the `when` clause like the
+/// variable of `for` is to the loop.
+///
+/// There are no enums and a value cannot be downcasted, so neither enum
+/// variant patterns nor type patterns (`Int => n`) are supported yet and
+/// `case` is not checked for exhaustiveness (it has no value without
+/// `else`.)
+use crate::ast::*;
+use crate::error;
+use crate::error::Error;
+use crate::hir::convert_exprs::{base_class_name, if_ty};
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::*;
+use crate::type_checking;
+
+impl HirMaker {
+    pub(super) fn convert_case_expr(
+        &mut self,
+        expr: &AstExpression,
+        clauses: &[CaseClause],
+        else_exprs: &Option<Vec<AstExpression>>,
+    ) -> Result<HirExpression, Error> {
+        let value_hir = self.convert_expr(expr)?;
+        type_checking::check_not_void(&value_hir.ty, "the value of `case'")?;
+        let value_ty = value_hir.ty.clone();
+        let tmp = self.gensym();
+        let assign_hir = Hir::assign_lvar(&tmp, value_hir);
+
+        let mut branches = vec![];
+        for clause in clauses {
+            self.push_scope();
+            let mut cond_hir = None;
+            for pattern in &clause.patterns {
+                let value_ref = Hir::lvar_ref(value_ty.clone(), tmp.clone());
+                let hir = and_all(self.convert_pattern(pattern, value_ref)?);
+                cond_hir = Some(match cond_hir {
+                    Some(left) => Hir::logical_or(left, hir),
+                    None => hir,
+                });
+            }
+            let mut cond_hir = cond_hir.unwrap();
+            if let Some(guard) = &clause.guard {
+                let guard_hir = self.convert_expr(guard)?;
+                type_checking::check_condition_ty(&guard_hir.ty, "when")?;
+                cond_hir = Hir::logical_and(cond_hir, guard_hir);
+            }
+            let body_hirs = self.convert_exprs(&clause.body_exprs)?;
+            self.pop_scope();
+            branches.push((cond_hir, body_hirs));
+        }
+        let else_hirs = match else_exprs {
+            Some(exprs) => Some(self.convert_block(exprs)?),
+            None => None,
+        };
+        // `case` without `else` has no value
+        let ty = match &else_hirs {
+            Some(else_hirs) => branches
+                .iter()
+                .rev()
+                .fold(else_hirs.ty.clone(), |ty, (_, body)| if_ty(&body.ty, &ty)),
+            None => ty::raw("Void"),
+        };
+
+        let mut rest_hirs = else_hirs;
+        for (cond_hir, body_hirs) in branches.into_iter().rev() {
+            let if_hir = Hir::if_expression(ty.clone(), cond_hir, body_hirs, rest_hirs);
+            rest_hirs = Some(Hir::expressions(vec![if_hir]));
+        }
+        let mut exprs = vec![assign_hir];
+        exprs.extend(rest_hirs.unwrap().exprs);
+        Ok(Hir::parenthesized_expression(Hir::expressions(exprs)))
+    }
+
+    /// Return the conditions which are all true if `value` matches
+    /// `pattern` (`value` is evaluated at most once)
+    fn convert_pattern(
+        &mut self,
+        pattern: &Pattern,
+        value: HirExpression,
+    ) -> Result<Vec<HirExpression>, Error> {
+        match pattern {
+            Pattern::Wildcard => Ok(vec![]),
+            Pattern::Binding(name) => {
+                let assign_hir = self.assign_loop_var(name, value)?;
+                Ok(vec![and_true(assign_hir)])
+            }
+            Pattern::Value(expr) => {
+                let expr_hir = self.convert_expr(expr)?;
+                let cmp_hir =
+                    self.make_method_call(value, &method_firstname("=="), vec![expr_hir])?;
+                type_checking::check_condition_ty(&cmp_hir.ty, "when")?;
+                Ok(vec![cmp_hir])
+            }
+            Pattern::Array { items, rest } => self.convert_array_pattern(items, rest, value),
+        }
+    }
+
+    /// `[x, 0, *rest]` checks the length first and then the items
+    fn convert_array_pattern(
+        &mut self,
+        items: &[Pattern],
+        rest: &Option<String>,
+        value: HirExpression,
+    ) -> Result<Vec<HirExpression>, Error> {
+        if base_class_name(&value.ty) != "Array" {
+            return Err(error::type_error(&format!(
+                "cannot match {} with an array pattern (Array expected)",
+                value.ty
+            )));
+        }
+        let ary_ty = value.ty.clone();
+        let ary = self.gensym();
+        let ary_ref = || Hir::lvar_ref(ary_ty.clone(), ary.clone());
+        let n = items.len() as i32;
+        let mut conds = vec![and_true(Hir::assign_lvar(&ary, value))];
+
+        let length_hir = self.make_method_call(ary_ref(), &method_firstname("length"), vec![])?;
+        let op = if rest.is_some() { ">=" } else { "==" };
+        conds.push(self.make_method_call(
+            length_hir,
+            &method_firstname(op),
+            vec![Hir::decimal_literal(n)],
+        )?);
+        for (i, item) in items.iter().enumerate() {
+            if let Pattern::Wildcard = item {
+                continue;
+            }
+            let item_hir = self.make_method_call(
+                ary_ref(),
+                &method_firstname("[]"),
+                vec![Hir::decimal_literal(i as i32)],
+            )?;
+            conds.extend(self.convert_pattern(item, item_hir)?);
+        }
+        match rest {
+            Some(name) if name != "_" => {
+                let rest_hir = self.make_method_call(
+                    ary_ref(),
+                    &method_firstname("drop"),
+                    vec![Hir::decimal_literal(n)],
+                )?;
+                let assign_hir = self.assign_loop_var(name, rest_hir)?;
+                conds.push(and_true(assign_hir));
+            }
+            _ => (),
+        }
+        Ok(conds)
+    }
+}
+
+/// `cond1 && cond2 && ...` (`true` if empty)
+fn and_all(conds: Vec<HirExpression>) -> HirExpression {
+    conds
+        .into_iter()
+        .fold(None, |acc, cond| match acc {
+            Some(left) => Some(Hir::logical_and(left, cond)),
+            None => Some(cond),
+        })
+        .unwrap_or_else(|| Hir::boolean_literal(true))
+}
+
+/// `(expr; true)`
+fn and_true(expr: HirExpression) -> HirExpression {
+    Hir::parenthesized_expression(Hir::expressions(vec![expr, Hir::boolean_literal(true)]))
+}
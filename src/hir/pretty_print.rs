@@ -0,0 +1,271 @@
+/// Readable dump of HIR (`shiika compile --dump-hir`)
+///
+/// Expressions are printed in a Ruby-like syntax with the conversions
+/// which are implicit in the source (`box`, `unbox` and `as`). Method calls
+/// are printed with the full name of the method (eg. `x.Int#+(1)`) and
+/// each statement is followed by its type.
+use crate::hir::*;
+
+/// Return the dump of the classes, constants, methods and the main
+/// program. Classes and methods are printed only if `include` returns
+/// true for the name (eg. `A`, `A#foo`, `Meta:A#new`), and constants if it
+/// returns true for the full name (eg. `::A::B`)
+pub fn hir_to_string(hir: &Hir, include: &dyn Fn(&str) -> bool) -> String {
+    let mut printer = Printer {
+        hir,
+        out: String::new(),
+        params: vec![],
+    };
+
+    let mut classes = hir
+        .sk_classes
+        .values()
+        .filter(|c| include(&c.fullname.0))
+        .collect::<Vec<_>>();
+    classes.sort_by_key(|c| &c.fullname.0);
+    for class in classes {
+        printer.print_class(class);
+    }
+
+    for expr in &hir.const_inits {
+        if let HirExpressionBase::HirConstAssign { fullname, .. } = &expr.node {
+            if !include(&fullname.0) {
+                continue;
+            }
+        }
+        printer.print_stmt(expr, 0);
+    }
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+
+    let mut methods = hir
+        .sk_methods
+        .values()
+        .flatten()
+        .filter(|m| include(&m.signature.fullname.full_name))
+        .collect::<Vec<_>>();
+    methods.sort_by_key(|m| &m.signature.fullname.full_name);
+    for method in methods {
+        printer.print_method(method);
+    }
+
+    printer.out += "main\n";
+    printer.print_exprs(&hir.main_exprs, 1);
+    printer.out += "end\n";
+    printer.out
+}
+
+struct Printer<'a> {
+    hir: &'a Hir,
+    out: String,
+    /// Names of the params of the methods and the lambdas being printed
+    params: Vec<Vec<String>>,
+}
+
+impl<'a> Printer<'a> {
+    fn print_class(&mut self, class: &SkClass) {
+        self.out += &format!("class {}", class.fullname);
+        if let Some(sup) = &class.superclass_fullname {
+            self.out += &format!(" : {}", sup);
+        }
+        self.out.push('\n');
+        let mut ivars = class.ivars.values().collect::<Vec<_>>();
+        ivars.sort_by_key(|ivar| ivar.idx);
+        for ivar in ivars {
+            let var = if ivar.readonly { "" } else { "var " };
+            self.out += &format!("  {}@{}: {}\n", var, ivar.name, ivar.ty);
+        }
+        self.out += "end\n\n";
+    }
+
+    fn print_method(&mut self, method: &SkMethod) {
+        self.out += &format!("def {}\n", method.signature);
+        match &method.body {
+            SkMethodBody::ShiikaMethodBody { exprs } => {
+                self.params.push(
+                    method
+                        .signature
+                        .params
+                        .iter()
+                        .map(|p| p.name.clone())
+                        .collect(),
+                );
+                self.print_exprs(exprs, 1);
+                self.params.pop();
+            }
+            _ => self.out += "  (builtin)\n",
+        }
+        self.out += "end\n\n";
+    }
+
+    fn print_exprs(&mut self, exprs: &HirExpressions, level: usize) {
+        for expr in &exprs.exprs {
+            self.print_stmt(expr, level);
+        }
+    }
+
+    /// Print an expression as a line (or lines)
+    fn print_stmt(&mut self, expr: &HirExpression, level: usize) {
+        let s = self.expr(expr, level);
+        self.out += &format!("{}{}  #: {}\n", indent(level), s, expr.ty);
+    }
+
+    /// Return the lines of `exprs` instead of printing them
+    fn block(&mut self, exprs: &HirExpressions, level: usize) -> String {
+        let saved = std::mem::take(&mut self.out);
+        self.print_exprs(exprs, level);
+        std::mem::replace(&mut self.out, saved)
+    }
+
+    fn expr(&mut self, expr: &HirExpression, level: usize) -> String {
+        match &expr.node {
+            HirExpressionBase::HirLogicalNot { expr } => format!("!{}", self.expr(expr, level)),
+            HirExpressionBase::HirLogicalAnd { left, right } => format!(
+                "({} && {})",
+                self.expr(left, level),
+                self.expr(right, level)
+            ),
+            HirExpressionBase::HirLogicalOr { left, right } => format!(
+                "({} || {})",
+                self.expr(left, level),
+                self.expr(right, level)
+            ),
+            HirExpressionBase::HirIfExpression {
+                cond_expr,
+                then_exprs,
+                else_exprs,
+            } => {
+                let mut s = format!("if {}\n", self.expr(cond_expr, level));
+                s += &self.block(then_exprs, level + 1);
+                if let Some(else_exprs) = &**else_exprs {
+                    s += &format!("{}else\n", indent(level));
+                    s += &self.block(else_exprs, level + 1);
+                }
+                s + &indent(level) + "end"
+            }
+            HirExpressionBase::HirWhileExpression {
+                cond_expr,
+                body_exprs,
+            } => {
+                let mut s = format!("while {}\n", self.expr(cond_expr, level));
+                s += &self.block(body_exprs, level + 1);
+                s + &indent(level) + "end"
+            }
+            HirExpressionBase::HirBreakExpression { expr } => match expr {
+                Some(e) => format!("break {}", self.expr(e, level)),
+                None => "break".to_string(),
+            },
+            HirExpressionBase::HirNextExpression => "next".to_string(),
+            HirExpressionBase::HirReturnExpression { arg } => {
+                format!("return {}", self.expr(arg, level))
+            }
+            HirExpressionBase::HirLVarAssign { name, rhs } => {
+                format!("{} = {}", name, self.expr(rhs, level))
+            }
+            HirExpressionBase::HirIVarAssign {
+                name,
+                rhs,
+                writable,
+                ..
+            } => {
+                let var = if *writable { "var " } else { "" };
+                format!("{}@{} = {}", var, name, self.expr(rhs, level))
+            }
+            HirExpressionBase::HirConstAssign { fullname, rhs } => {
+                format!("{} = {}", fullname, self.expr(rhs, level))
+            }
+            HirExpressionBase::HirMethodCall {
+                receiver_expr,
+                method_fullname,
+                arg_exprs,
+            } => {
+                let args = arg_exprs
+                    .iter()
+                    .map(|arg| self.expr(arg, level))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}.{}({})",
+                    self.expr(receiver_expr, level),
+                    method_fullname,
+                    args.join(", ")
+                )
+            }
+            HirExpressionBase::HirArgRef { idx } => {
+                match self.params.last().and_then(|names| names.get(*idx)) {
+                    Some(name) => name.clone(),
+                    None => format!("(arg {})", idx),
+                }
+            }
+            HirExpressionBase::HirLVarRef { name } => name.clone(),
+            HirExpressionBase::HirIVarRef { name, .. } => format!("@{}", name),
+            HirExpressionBase::HirConstRef { fullname } => fullname.0.clone(),
+            HirExpressionBase::HirLambdaExpr {
+                name,
+                params,
+                exprs,
+                captures_ary,
+            } => {
+                let captures = self.expr(captures_ary, level);
+                let sig = params
+                    .iter()
+                    .map(|p| format!("{}: {}", p.name, p.ty))
+                    .collect::<Vec<_>>();
+                self.params
+                    .push(params.iter().map(|p| p.name.clone()).collect());
+                let body = self.block(exprs, level + 1);
+                self.params.pop();
+                format!(
+                    "fn {}({}) captures {} {{\n{}{}}}",
+                    name,
+                    sig.join(", "),
+                    captures,
+                    body,
+                    indent(level)
+                )
+            }
+            HirExpressionBase::HirSelfExpression => "self".to_string(),
+            HirExpressionBase::HirParenthesizedExpr { exprs } => {
+                let items = exprs
+                    .exprs
+                    .iter()
+                    .map(|e| self.expr(e, level))
+                    .collect::<Vec<_>>();
+                format!("({})", items.join("; "))
+            }
+            HirExpressionBase::HirArrayLiteral { exprs } => {
+                let items = exprs
+                    .exprs
+                    .iter()
+                    .map(|e| self.expr(e, level))
+                    .collect::<Vec<_>>();
+                // The elements are pushed to a temporary array
+                format!("[{}]", items.join(";"))
+            }
+            HirExpressionBase::HirFloatLiteral { value } => format!("{:?}", value),
+            HirExpressionBase::HirDecimalLiteral { value } => format!("{}", value),
+            HirExpressionBase::HirStringLiteral { idx } => {
+                format!("{:?}", self.hir.str_literals[*idx])
+            }
+            HirExpressionBase::HirBooleanLiteral { value } => format!("{}", value),
+            HirExpressionBase::HirBox { expr } => format!("box({})", self.expr(expr, level)),
+            HirExpressionBase::HirUnbox { expr } => format!("unbox({})", self.expr(expr, level)),
+            HirExpressionBase::HirBitCast { expr: e } => {
+                format!("({} as {})", self.expr(e, level), expr.ty)
+            }
+            HirExpressionBase::HirClassLiteral { fullname, .. } => format!("class({})", fullname),
+            HirExpressionBase::HirBreakpoint { vars, step, .. } => {
+                let names = vars
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>();
+                let kind = if *step { "step_point" } else { "breakpoint" };
+                format!("{}({})", kind, names.join(", "))
+            }
+        }
+    }
+}
+
+fn indent(level: usize) -> String {
+    "  ".repeat(level)
+}
@@ -0,0 +1,894 @@
+/// Write the HIR to a file and read it back (`shiika compile --emit hir`)
+///
+/// The HIR is written in JSON (see ide/json.rs) so that it can be read by
+/// other tools, and the code can be generated from it by another process
+/// (`shiika compile --from-hir`.) The items are sorted by name so that the
+/// same program always gives the same output.
+///
+/// The methods implemented in Rust (see corelib/) are written without the
+/// body and attached again from the corelib (or made again, see
+/// hash_dispatch.rs) when loaded, so the file can only be loaded by the
+/// same version of the compiler (`FORMAT_VERSION` is checked.) Floats are
+/// written as strings to keep `inf` and `NaN`
+use crate::corelib::Corelib;
+use crate::hir::hash_dispatch::{create_dispatcher, DISPATCHED_METHODS};
+use crate::hir::warnings::{Warning, WarningKind};
+use crate::hir::*;
+use crate::ide::json::Json;
+use crate::parser::lexer::{Cursor, Span};
+use std::collections::HashMap;
+
+/// Incremented when the format is changed
+pub const FORMAT_VERSION: usize = 2;
+
+/// Serialize `hir` into a JSON string
+pub fn serialize(hir: &Hir) -> String {
+    to_json(hir).to_string()
+}
+
+/// Load the HIR serialized by `serialize`
+pub fn deserialize(s: &str) -> Result<Hir, String> {
+    from_json(&Json::parse(s)?)
+}
+
+pub fn to_json(hir: &Hir) -> Json {
+    let mut classes = hir.sk_classes.values().collect::<Vec<_>>();
+    classes.sort_by(|a, b| a.fullname.0.cmp(&b.fullname.0));
+    let mut methods = hir.sk_methods.iter().collect::<Vec<_>>();
+    methods.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+    let mut constants = hir.constants.iter().collect::<Vec<_>>();
+    constants.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+    Json::object(vec![
+        ("version", number(FORMAT_VERSION)),
+        ("fn_arity", number(fn_arity(hir))),
+        ("release", Json::Bool(hir.release)),
+        (
+            "classes",
+            Json::Array(classes.into_iter().map(class_json).collect()),
+        ),
+        (
+            "methods",
+            Json::Array(
+                methods
+                    .into_iter()
+                    .map(|(classname, methods)| {
+                        Json::object(vec![
+                            ("class", Json::str(&classname.0)),
+                            (
+                                "methods",
+                                Json::Array(methods.iter().map(method_json).collect()),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "constants",
+            Json::Array(
+                constants
+                    .into_iter()
+                    .map(|(name, ty)| {
+                        Json::object(vec![("name", Json::str(&name.0)), ("ty", ty_json(ty))])
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "str_literals",
+            Json::Array(hir.str_literals.iter().map(|s| Json::str(s)).collect()),
+        ),
+        (
+            "const_inits",
+            Json::Array(hir.const_inits.iter().map(expr_json).collect()),
+        ),
+        ("main_exprs", exprs_json(&hir.main_exprs)),
+        ("def_spans", sorted_pairs(&hir.def_spans, span_json)),
+        ("docs", sorted_pairs(&hir.docs, |doc| Json::str(doc))),
+        (
+            "warnings",
+            Json::Array(hir.warnings.iter().map(warning_json).collect()),
+        ),
+    ])
+}
+
+/// Load the HIR from the JSON made by `to_json`
+pub fn from_json(json: &Json) -> Result<Hir, String> {
+    let version = get_usize(json, "version")?;
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "unsupported version of HIR: {} (expected {})",
+            version, FORMAT_VERSION
+        ));
+    }
+    // The bodies of the methods implemented in Rust
+    let corelib = Corelib::with_fn_arity(get_usize(json, "fn_arity")?);
+    let mut rust_bodies = corelib
+        .sk_methods
+        .into_iter()
+        .flat_map(|(_, methods)| methods)
+        .map(|m| (m.signature.fullname.full_name, m.body))
+        .collect::<HashMap<_, _>>();
+
+    let mut sk_classes = HashMap::new();
+    for item in get_array(json, "classes")? {
+        let class = class_from(item)?;
+        sk_classes.insert(class.fullname.clone(), class);
+    }
+    let mut sk_methods = HashMap::new();
+    for item in get_array(json, "methods")? {
+        let methods = get_array(item, "methods")?
+            .iter()
+            .map(|m| method_from(m, &mut rust_bodies))
+            .collect::<Result<Vec<_>, _>>()?;
+        sk_methods.insert(class_fullname(get_str(item, "class")?), methods);
+    }
+    // These depend on the classes (see hash_dispatch.rs)
+    for method in sk_methods.values_mut().flatten() {
+        if DISPATCHED_METHODS.contains(&method.signature.fullname.full_name.as_str()) {
+            *method = create_dispatcher(&sk_classes, method.signature.clone());
+        }
+    }
+    let mut constants = HashMap::new();
+    for item in get_array(json, "constants")? {
+        constants.insert(
+            const_fullname(&get_str(item, "name")?),
+            ty_from(get(item, "ty")?)?,
+        );
+    }
+    let str_literals = get_array(json, "str_literals")?
+        .iter()
+        .map(as_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    let const_inits = get_array(json, "const_inits")?
+        .iter()
+        .map(expr_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut def_spans = HashMap::new();
+    for item in get_array(json, "def_spans")? {
+        def_spans.insert(get_str(item, "name")?, span_from(get(item, "value")?)?);
+    }
+    let mut docs = HashMap::new();
+    for item in get_array(json, "docs")? {
+        docs.insert(get_str(item, "name")?, get_str(item, "value")?);
+    }
+    let warnings = get_array(json, "warnings")?
+        .iter()
+        .map(warning_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Hir {
+        sk_classes,
+        sk_methods,
+        constants,
+        str_literals,
+        const_inits,
+        main_exprs: exprs_from(get(json, "main_exprs")?)?,
+        def_spans,
+        docs,
+        warnings,
+        release: get_bool(json, "release")?,
+    })
+}
+
+/// The largest arity of the `Fn` classes of the corelib
+fn fn_arity(hir: &Hir) -> usize {
+    hir.sk_classes
+        .keys()
+        .filter_map(|name| name.0.strip_prefix("Fn")?.parse::<usize>().ok())
+        .max()
+        .unwrap_or(0)
+}
+
+//
+// Writing
+//
+
+fn number(n: usize) -> Json {
+    Json::Number(n as f64)
+}
+
+/// Make an array of `{"name": key, "value": value}` sorted by the key
+fn sorted_pairs<T>(map: &HashMap<String, T>, f: impl Fn(&T) -> Json) -> Json {
+    let mut pairs = map.iter().collect::<Vec<_>>();
+    pairs.sort_by(|a, b| a.0.cmp(b.0));
+    Json::Array(
+        pairs
+            .into_iter()
+            .map(|(k, v)| Json::object(vec![("name", Json::str(k)), ("value", f(v))]))
+            .collect(),
+    )
+}
+
+fn span_json(span: &Span) -> Json {
+    Json::Array(
+        [&span.begin, &span.end]
+            .iter()
+            .flat_map(|c| vec![c.line(), c.col(), c.pos()])
+            .map(number)
+            .collect(),
+    )
+}
+
+fn warning_json(warning: &Warning) -> Json {
+    Json::object(vec![
+        ("kind", Json::str(warning.kind.name())),
+        ("msg", Json::str(&warning.msg)),
+        ("place", Json::str(&warning.place)),
+    ])
+}
+
+fn class_json(class: &SkClass) -> Json {
+    let mut ivars = class.ivars.values().collect::<Vec<_>>();
+    ivars.sort_by_key(|ivar| ivar.idx);
+    let mut sigs = class.method_sigs.values().collect::<Vec<_>>();
+    sigs.sort_by(|a, b| a.fullname.full_name.cmp(&b.fullname.full_name));
+    Json::object(vec![
+        ("fullname", Json::str(&class.fullname.0)),
+        (
+            "typarams",
+            Json::Array(class.typarams.iter().map(|t| Json::str(&t.name)).collect()),
+        ),
+        (
+            "superclass",
+            match &class.superclass_fullname {
+                Some(name) => Json::str(&name.0),
+                None => Json::Null,
+            },
+        ),
+        ("instance_ty", ty_json(&class.instance_ty)),
+        (
+            "ivars",
+            Json::Array(
+                ivars
+                    .into_iter()
+                    .map(|ivar| {
+                        Json::object(vec![
+                            ("idx", number(ivar.idx)),
+                            ("name", Json::str(&ivar.name)),
+                            ("ty", ty_json(&ivar.ty)),
+                            ("readonly", Json::Bool(ivar.readonly)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "method_sigs",
+            Json::Array(sigs.into_iter().map(signature_json).collect()),
+        ),
+        ("const_is_obj", Json::Bool(class.const_is_obj)),
+        (
+            "abstract_methods",
+            Json::Array(
+                class
+                    .abstract_methods
+                    .iter()
+                    .map(|name| Json::str(&name.0))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+/// The body is `null` if the method is implemented in Rust
+fn method_json(method: &SkMethod) -> Json {
+    let body = match &method.body {
+        SkMethodBody::ShiikaMethodBody { exprs } => exprs_json(exprs),
+        _ => Json::Null,
+    };
+    Json::object(vec![
+        ("signature", signature_json(&method.signature)),
+        ("body", body),
+    ])
+}
+
+fn signature_json(sig: &MethodSignature) -> Json {
+    Json::object(vec![
+        ("fullname", method_fullname_json(&sig.fullname)),
+        ("ret_ty", ty_json(&sig.ret_ty)),
+        ("params", params_json(&sig.params)),
+        (
+            "typarams",
+            Json::Array(sig.typarams.iter().map(ty_json).collect()),
+        ),
+    ])
+}
+
+fn method_fullname_json(name: &MethodFullname) -> Json {
+    Json::object(vec![
+        ("full_name", Json::str(&name.full_name)),
+        ("first_name", Json::str(&name.first_name.0)),
+    ])
+}
+
+fn params_json(params: &[MethodParam]) -> Json {
+    Json::Array(
+        params
+            .iter()
+            .map(|param| {
+                Json::object(vec![
+                    ("name", Json::str(&param.name)),
+                    ("ty", ty_json(&param.ty)),
+                    ("implicit_conv", Json::Bool(param.implicit_conv)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn ty_json(ty: &TermTy) -> Json {
+    let tys = |tys: &[TermTy]| Json::Array(tys.iter().map(ty_json).collect());
+    let mut pairs = vec![("fullname", Json::str(&ty.fullname.0))];
+    match &ty.body {
+        TyBody::TyRaw => pairs.push(("body", Json::str("raw"))),
+        TyBody::TyMeta { base_fullname } => {
+            pairs.push(("body", Json::str("meta")));
+            pairs.push(("base_fullname", Json::str(base_fullname)));
+        }
+        TyBody::TyClass => pairs.push(("body", Json::str("class"))),
+        TyBody::TyGenMeta {
+            base_name,
+            typaram_names,
+        } => {
+            pairs.push(("body", Json::str("gen_meta")));
+            pairs.push(("base_name", Json::str(base_name)));
+            pairs.push((
+                "typaram_names",
+                Json::Array(typaram_names.iter().map(|s| Json::str(s)).collect()),
+            ));
+        }
+        TyBody::TySpe {
+            base_name,
+            type_args,
+        } => {
+            pairs.push(("body", Json::str("spe")));
+            pairs.push(("base_name", Json::str(base_name)));
+            pairs.push(("type_args", tys(type_args)));
+        }
+        TyBody::TySpeMeta {
+            base_name,
+            type_args,
+        } => {
+            pairs.push(("body", Json::str("spe_meta")));
+            pairs.push(("base_name", Json::str(base_name)));
+            pairs.push(("type_args", tys(type_args)));
+        }
+        TyBody::TyParamRef { name, idx } => {
+            pairs.push(("body", Json::str("typaram")));
+            pairs.push(("name", Json::str(name)));
+            pairs.push(("idx", number(*idx)));
+        }
+    }
+    Json::object(pairs)
+}
+
+fn exprs_json(exprs: &HirExpressions) -> Json {
+    Json::object(vec![
+        ("ty", ty_json(&exprs.ty)),
+        (
+            "exprs",
+            Json::Array(exprs.exprs.iter().map(expr_json).collect()),
+        ),
+    ])
+}
+
+/// `{"ty": ..., "node": "HirXxx", <fields of the node>}`
+fn expr_json(expr: &HirExpression) -> Json {
+    let e = |expr: &HirExpression| expr_json(expr);
+    let (node, mut fields) = match &expr.node {
+        HirExpressionBase::HirLogicalNot { expr } => ("HirLogicalNot", vec![("expr", e(expr))]),
+        HirExpressionBase::HirLogicalAnd { left, right } => (
+            "HirLogicalAnd",
+            vec![("left", e(left)), ("right", e(right))],
+        ),
+        HirExpressionBase::HirLogicalOr { left, right } => {
+            ("HirLogicalOr", vec![("left", e(left)), ("right", e(right))])
+        }
+        HirExpressionBase::HirIfExpression {
+            cond_expr,
+            then_exprs,
+            else_exprs,
+        } => (
+            "HirIfExpression",
+            vec![
+                ("cond_expr", e(cond_expr)),
+                ("then_exprs", exprs_json(then_exprs)),
+                (
+                    "else_exprs",
+                    match &**else_exprs {
+                        Some(exprs) => exprs_json(exprs),
+                        None => Json::Null,
+                    },
+                ),
+            ],
+        ),
+        HirExpressionBase::HirWhileExpression {
+            cond_expr,
+            body_exprs,
+        } => (
+            "HirWhileExpression",
+            vec![
+                ("cond_expr", e(cond_expr)),
+                ("body_exprs", exprs_json(body_exprs)),
+            ],
+        ),
+        HirExpressionBase::HirBreakExpression { expr } => (
+            "HirBreakExpression",
+            vec![(
+                "expr",
+                match expr {
+                    Some(expr) => e(expr),
+                    None => Json::Null,
+                },
+            )],
+        ),
+        HirExpressionBase::HirNextExpression => ("HirNextExpression", vec![]),
+        HirExpressionBase::HirReturnExpression { arg } => {
+            ("HirReturnExpression", vec![("arg", e(arg))])
+        }
+        HirExpressionBase::HirLVarAssign { name, rhs } => (
+            "HirLVarAssign",
+            vec![("name", Json::str(name)), ("rhs", e(rhs))],
+        ),
+        HirExpressionBase::HirIVarAssign {
+            name,
+            idx,
+            rhs,
+            writable,
+        } => (
+            "HirIVarAssign",
+            vec![
+                ("name", Json::str(name)),
+                ("idx", number(*idx)),
+                ("rhs", e(rhs)),
+                ("writable", Json::Bool(*writable)),
+            ],
+        ),
+        HirExpressionBase::HirConstAssign { fullname, rhs } => (
+            "HirConstAssign",
+            vec![("fullname", Json::str(&fullname.0)), ("rhs", e(rhs))],
+        ),
+        HirExpressionBase::HirMethodCall {
+            receiver_expr,
+            method_fullname,
+            arg_exprs,
+        } => (
+            "HirMethodCall",
+            vec![
+                ("receiver_expr", e(receiver_expr)),
+                ("method_fullname", method_fullname_json(method_fullname)),
+                ("arg_exprs", Json::Array(arg_exprs.iter().map(e).collect())),
+            ],
+        ),
+        HirExpressionBase::HirArgRef { idx } => ("HirArgRef", vec![("idx", number(*idx))]),
+        HirExpressionBase::HirLVarRef { name } => ("HirLVarRef", vec![("name", Json::str(name))]),
+        HirExpressionBase::HirIVarRef { name, idx } => (
+            "HirIVarRef",
+            vec![("name", Json::str(name)), ("idx", number(*idx))],
+        ),
+        HirExpressionBase::HirConstRef { fullname } => {
+            ("HirConstRef", vec![("fullname", Json::str(&fullname.0))])
+        }
+        HirExpressionBase::HirLambdaExpr {
+            name,
+            params,
+            exprs,
+            captures_ary,
+        } => (
+            "HirLambdaExpr",
+            vec![
+                ("name", Json::str(name)),
+                ("params", params_json(params)),
+                ("exprs", exprs_json(exprs)),
+                ("captures_ary", e(captures_ary)),
+            ],
+        ),
+        HirExpressionBase::HirSelfExpression => ("HirSelfExpression", vec![]),
+        HirExpressionBase::HirParenthesizedExpr { exprs } => {
+            ("HirParenthesizedExpr", vec![("exprs", exprs_json(exprs))])
+        }
+        HirExpressionBase::HirArrayLiteral { exprs } => {
+            ("HirArrayLiteral", vec![("exprs", exprs_json(exprs))])
+        }
+        HirExpressionBase::HirFloatLiteral { value } => (
+            "HirFloatLiteral",
+            vec![("value", Json::Str(value.to_string()))],
+        ),
+        HirExpressionBase::HirDecimalLiteral { value } => (
+            "HirDecimalLiteral",
+            vec![("value", Json::Number(*value as f64))],
+        ),
+        HirExpressionBase::HirStringLiteral { idx } => {
+            ("HirStringLiteral", vec![("idx", number(*idx))])
+        }
+        HirExpressionBase::HirBooleanLiteral { value } => {
+            ("HirBooleanLiteral", vec![("value", Json::Bool(*value))])
+        }
+        HirExpressionBase::HirBox { expr } => ("HirBox", vec![("expr", e(expr))]),
+        HirExpressionBase::HirUnbox { expr } => ("HirUnbox", vec![("expr", e(expr))]),
+        HirExpressionBase::HirBitCast { expr } => ("HirBitCast", vec![("expr", e(expr))]),
+        HirExpressionBase::HirClassLiteral {
+            fullname,
+            str_literal_idx,
+        } => (
+            "HirClassLiteral",
+            vec![
+                ("fullname", Json::str(&fullname.0)),
+                ("str_literal_idx", number(*str_literal_idx)),
+            ],
+        ),
+        HirExpressionBase::HirBreakpoint { place, vars, step } => (
+            "HirBreakpoint",
+            vec![
+                ("place", Json::str(place)),
+                (
+                    "vars",
+                    Json::Array(
+                        vars.iter()
+                            .map(|(name, expr)| {
+                                Json::object(vec![("name", Json::str(name)), ("expr", e(expr))])
+                            })
+                            .collect(),
+                    ),
+                ),
+                ("step", Json::Bool(*step)),
+            ],
+        ),
+    };
+    let mut pairs = vec![("ty", ty_json(&expr.ty)), ("node", Json::str(node))];
+    pairs.append(&mut fields);
+    Json::object(pairs)
+}
+
+//
+// Reading
+//
+
+fn get<'a>(json: &'a Json, key: &str) -> Result<&'a Json, String> {
+    json.get(key).ok_or_else(|| format!("`{}' not found", key))
+}
+
+fn as_str(json: &Json) -> Result<String, String> {
+    json.as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("not a string: {}", json))
+}
+
+fn get_str(json: &Json, key: &str) -> Result<String, String> {
+    as_str(get(json, key)?)
+}
+
+fn get_usize(json: &Json, key: &str) -> Result<usize, String> {
+    let value = get(json, key)?;
+    value
+        .as_usize()
+        .ok_or_else(|| format!("not a number: {}", value))
+}
+
+fn get_bool(json: &Json, key: &str) -> Result<bool, String> {
+    match get(json, key)? {
+        Json::Bool(b) => Ok(*b),
+        value => Err(format!("not a bool: {}", value)),
+    }
+}
+
+fn get_array<'a>(json: &'a Json, key: &str) -> Result<&'a Vec<Json>, String> {
+    let value = get(json, key)?;
+    value
+        .as_array()
+        .ok_or_else(|| format!("not an array: {}", value))
+}
+
+/// `None` if the value is `null`
+fn get_opt<'a>(json: &'a Json, key: &str) -> Result<Option<&'a Json>, String> {
+    match get(json, key)? {
+        Json::Null => Ok(None),
+        value => Ok(Some(value)),
+    }
+}
+
+fn span_from(json: &Json) -> Result<Span, String> {
+    let ns = json
+        .as_array()
+        .filter(|items| items.len() == 6)
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|n| n.as_usize())
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| format!("not a span: {}", json))?;
+    Ok(Span {
+        begin: Cursor::at(ns[0], ns[1], ns[2]),
+        end: Cursor::at(ns[3], ns[4], ns[5]),
+    })
+}
+
+fn warning_from(json: &Json) -> Result<Warning, String> {
+    let name = get_str(json, "kind")?;
+    let kind = [
+        WarningKind::UnusedVariable,
+        WarningKind::ShadowedVariable,
+        WarningKind::UnusedMethod,
+        WarningKind::ConstantCondition,
+        WarningKind::LargeItem,
+        WarningKind::EqWithoutHash,
+    ]
+    .iter()
+    .find(|kind| kind.name() == name)
+    .ok_or_else(|| format!("unknown kind of warning: {}", name))?;
+    Ok(Warning {
+        kind: *kind,
+        msg: get_str(json, "msg")?,
+        place: get_str(json, "place")?,
+    })
+}
+
+fn class_from(json: &Json) -> Result<SkClass, String> {
+    let typarams = get_array(json, "typarams")?
+        .iter()
+        .map(|t| Ok(TyParam { name: as_str(t)? }))
+        .collect::<Result<Vec<_>, String>>()?;
+    let superclass_fullname = match get_opt(json, "superclass")? {
+        Some(name) => Some(class_fullname(as_str(name)?)),
+        None => None,
+    };
+    let mut ivars = HashMap::new();
+    for item in get_array(json, "ivars")? {
+        let ivar = SkIVar {
+            idx: get_usize(item, "idx")?,
+            name: get_str(item, "name")?,
+            ty: ty_from(get(item, "ty")?)?,
+            readonly: get_bool(item, "readonly")?,
+        };
+        ivars.insert(ivar.name.clone(), ivar);
+    }
+    let mut method_sigs = HashMap::new();
+    for item in get_array(json, "method_sigs")? {
+        let sig = signature_from(item)?;
+        method_sigs.insert(sig.fullname.first_name.clone(), sig);
+    }
+    let abstract_methods = get_array(json, "abstract_methods")?
+        .iter()
+        .map(|name| Ok(method_firstname(&as_str(name)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(SkClass {
+        fullname: class_fullname(get_str(json, "fullname")?),
+        typarams,
+        superclass_fullname,
+        instance_ty: ty_from(get(json, "instance_ty")?)?,
+        ivars,
+        method_sigs,
+        const_is_obj: get_bool(json, "const_is_obj")?,
+        abstract_methods,
+    })
+}
+
+fn method_from(
+    json: &Json,
+    rust_bodies: &mut HashMap<String, SkMethodBody>,
+) -> Result<SkMethod, String> {
+    let signature = signature_from(get(json, "signature")?)?;
+    let body = match get_opt(json, "body")? {
+        Some(exprs) => SkMethodBody::ShiikaMethodBody {
+            exprs: exprs_from(exprs)?,
+        },
+        None => rust_bodies
+            .remove(&signature.fullname.full_name)
+            .ok_or_else(|| {
+                format!(
+                    "method not found in the corelib: {}",
+                    signature.fullname.full_name
+                )
+            })?,
+    };
+    Ok(SkMethod { signature, body })
+}
+
+fn signature_from(json: &Json) -> Result<MethodSignature, String> {
+    let typarams = get_array(json, "typarams")?
+        .iter()
+        .map(ty_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(MethodSignature {
+        fullname: method_fullname_from(get(json, "fullname")?)?,
+        ret_ty: ty_from(get(json, "ret_ty")?)?,
+        params: params_from(get(json, "params")?)?,
+        typarams,
+    })
+}
+
+fn method_fullname_from(json: &Json) -> Result<MethodFullname, String> {
+    Ok(MethodFullname {
+        full_name: get_str(json, "full_name")?,
+        first_name: method_firstname(&get_str(json, "first_name")?),
+    })
+}
+
+fn params_from(json: &Json) -> Result<Vec<MethodParam>, String> {
+    json.as_array()
+        .ok_or_else(|| format!("not an array: {}", json))?
+        .iter()
+        .map(|param| {
+            Ok(MethodParam {
+                name: get_str(param, "name")?,
+                ty: ty_from(get(param, "ty")?)?,
+                implicit_conv: get_bool(param, "implicit_conv")?,
+            })
+        })
+        .collect()
+}
+
+fn ty_from(json: &Json) -> Result<TermTy, String> {
+    let tys = |key: &str| {
+        get_array(json, key)?
+            .iter()
+            .map(ty_from)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let body = match get_str(json, "body")?.as_str() {
+        "raw" => TyBody::TyRaw,
+        "meta" => TyBody::TyMeta {
+            base_fullname: get_str(json, "base_fullname")?,
+        },
+        "class" => TyBody::TyClass,
+        "gen_meta" => TyBody::TyGenMeta {
+            base_name: get_str(json, "base_name")?,
+            typaram_names: get_array(json, "typaram_names")?
+                .iter()
+                .map(as_str)
+                .collect::<Result<Vec<_>, _>>()?,
+        },
+        "spe" => TyBody::TySpe {
+            base_name: get_str(json, "base_name")?,
+            type_args: tys("type_args")?,
+        },
+        "spe_meta" => TyBody::TySpeMeta {
+            base_name: get_str(json, "base_name")?,
+            type_args: tys("type_args")?,
+        },
+        "typaram" => TyBody::TyParamRef {
+            name: get_str(json, "name")?,
+            idx: get_usize(json, "idx")?,
+        },
+        body => return Err(format!("unknown kind of type: {}", body)),
+    };
+    Ok(TermTy {
+        fullname: class_fullname(get_str(json, "fullname")?),
+        body,
+    })
+}
+
+fn exprs_from(json: &Json) -> Result<HirExpressions, String> {
+    let exprs = get_array(json, "exprs")?
+        .iter()
+        .map(expr_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(HirExpressions {
+        ty: ty_from(get(json, "ty")?)?,
+        exprs,
+    })
+}
+
+fn expr_from(json: &Json) -> Result<HirExpression, String> {
+    let e = |key: &str| -> Result<Box<HirExpression>, String> {
+        Ok(Box::new(expr_from(get(json, key)?)?))
+    };
+    let node = match get_str(json, "node")?.as_str() {
+        "HirLogicalNot" => HirExpressionBase::HirLogicalNot { expr: e("expr")? },
+        "HirLogicalAnd" => HirExpressionBase::HirLogicalAnd {
+            left: e("left")?,
+            right: e("right")?,
+        },
+        "HirLogicalOr" => HirExpressionBase::HirLogicalOr {
+            left: e("left")?,
+            right: e("right")?,
+        },
+        "HirIfExpression" => HirExpressionBase::HirIfExpression {
+            cond_expr: e("cond_expr")?,
+            then_exprs: Box::new(exprs_from(get(json, "then_exprs")?)?),
+            else_exprs: Box::new(match get_opt(json, "else_exprs")? {
+                Some(exprs) => Some(exprs_from(exprs)?),
+                None => None,
+            }),
+        },
+        "HirWhileExpression" => HirExpressionBase::HirWhileExpression {
+            cond_expr: e("cond_expr")?,
+            body_exprs: Box::new(exprs_from(get(json, "body_exprs")?)?),
+        },
+        "HirBreakExpression" => HirExpressionBase::HirBreakExpression {
+            expr: match get_opt(json, "expr")? {
+                Some(expr) => Some(Box::new(expr_from(expr)?)),
+                None => None,
+            },
+        },
+        "HirNextExpression" => HirExpressionBase::HirNextExpression,
+        "HirReturnExpression" => HirExpressionBase::HirReturnExpression { arg: e("arg")? },
+        "HirLVarAssign" => HirExpressionBase::HirLVarAssign {
+            name: get_str(json, "name")?,
+            rhs: e("rhs")?,
+        },
+        "HirIVarAssign" => HirExpressionBase::HirIVarAssign {
+            name: get_str(json, "name")?,
+            idx: get_usize(json, "idx")?,
+            rhs: e("rhs")?,
+            writable: get_bool(json, "writable")?,
+        },
+        "HirConstAssign" => HirExpressionBase::HirConstAssign {
+            fullname: const_fullname(&get_str(json, "fullname")?),
+            rhs: e("rhs")?,
+        },
+        "HirMethodCall" => HirExpressionBase::HirMethodCall {
+            receiver_expr: e("receiver_expr")?,
+            method_fullname: method_fullname_from(get(json, "method_fullname")?)?,
+            arg_exprs: get_array(json, "arg_exprs")?
+                .iter()
+                .map(expr_from)
+                .collect::<Result<Vec<_>, _>>()?,
+        },
+        "HirArgRef" => HirExpressionBase::HirArgRef {
+            idx: get_usize(json, "idx")?,
+        },
+        "HirLVarRef" => HirExpressionBase::HirLVarRef {
+            name: get_str(json, "name")?,
+        },
+        "HirIVarRef" => HirExpressionBase::HirIVarRef {
+            name: get_str(json, "name")?,
+            idx: get_usize(json, "idx")?,
+        },
+        "HirConstRef" => HirExpressionBase::HirConstRef {
+            fullname: const_fullname(&get_str(json, "fullname")?),
+        },
+        "HirLambdaExpr" => HirExpressionBase::HirLambdaExpr {
+            name: get_str(json, "name")?,
+            params: params_from(get(json, "params")?)?,
+            exprs: exprs_from(get(json, "exprs")?)?,
+            captures_ary: e("captures_ary")?,
+        },
+        "HirSelfExpression" => HirExpressionBase::HirSelfExpression,
+        "HirParenthesizedExpr" => HirExpressionBase::HirParenthesizedExpr {
+            exprs: exprs_from(get(json, "exprs")?)?,
+        },
+        "HirArrayLiteral" => HirExpressionBase::HirArrayLiteral {
+            exprs: exprs_from(get(json, "exprs")?)?,
+        },
+        "HirFloatLiteral" => {
+            let s = get_str(json, "value")?;
+            HirExpressionBase::HirFloatLiteral {
+                value: s.parse().map_err(|_| format!("invalid float: {}", s))?,
+            }
+        }
+        "HirDecimalLiteral" => HirExpressionBase::HirDecimalLiteral {
+            value: match get(json, "value")? {
+                Json::Number(n) => *n as i32,
+                value => return Err(format!("not a number: {}", value)),
+            },
+        },
+        "HirStringLiteral" => HirExpressionBase::HirStringLiteral {
+            idx: get_usize(json, "idx")?,
+        },
+        "HirBooleanLiteral" => HirExpressionBase::HirBooleanLiteral {
+            value: get_bool(json, "value")?,
+        },
+        "HirBox" => HirExpressionBase::HirBox { expr: e("expr")? },
+        "HirUnbox" => HirExpressionBase::HirUnbox { expr: e("expr")? },
+        "HirBitCast" => HirExpressionBase::HirBitCast { expr: e("expr")? },
+        "HirClassLiteral" => HirExpressionBase::HirClassLiteral {
+            fullname: class_fullname(get_str(json, "fullname")?),
+            str_literal_idx: get_usize(json, "str_literal_idx")?,
+        },
+        "HirBreakpoint" => HirExpressionBase::HirBreakpoint {
+            place: get_str(json, "place")?,
+            vars: get_array(json, "vars")?
+                .iter()
+                .map(|var| Ok((get_str(var, "name")?, expr_from(get(var, "expr")?)?)))
+                .collect::<Result<Vec<_>, String>>()?,
+            step: get_bool(json, "step")?,
+        },
+        node => return Err(format!("unknown node of HIR: {}", node)),
+    };
+    Ok(HirExpression {
+        ty: ty_from(get(json, "ty")?)?,
+        node,
+    })
+}
@@ -0,0 +1,159 @@
+/// Local variables shared with lambdas
+///
+/// Lambdas capture the values of the free variables. When a local variable
+/// is reassigned in a lambda, it is stored in a `Shiika::Internal::Cell` and
+/// the lambda captures the cell instead, so that the outer scope (and other
+/// lambdas) can see the change.
+use crate::ast::*;
+use crate::ast_visitor::*;
+use crate::hir::*;
+use std::collections::HashSet;
+
+/// Return the names of the local variables declared in `exprs` which are
+/// reassigned in the lambdas in `exprs`
+pub(super) fn collect_shared_lvars<'a>(
+    exprs: impl IntoIterator<Item = &'a AstExpression>,
+) -> HashSet<String> {
+    let mut collector = Collector::default();
+    for expr in exprs {
+        collector.visit_expr(expr);
+    }
+    collector.shared
+}
+
+#[derive(Debug, Default)]
+struct Collector {
+    /// Local variables declared so far
+    declared: HashSet<String>,
+    /// Local variables assigned in the current lambda
+    assigned: HashSet<String>,
+    /// Current nesting level of lambdas
+    lambda_depth: usize,
+    /// Result
+    shared: HashSet<String>,
+}
+
+impl Collector {
+    fn walk_lambda(&mut self, params: Vec<&str>, exprs: &[AstExpression]) {
+        let outer_assigned = std::mem::take(&mut self.assigned);
+        self.lambda_depth += 1;
+        self.visit_exprs(exprs);
+        self.lambda_depth -= 1;
+        let mut assigned = std::mem::replace(&mut self.assigned, outer_assigned);
+        for param in params {
+            assigned.remove(param);
+        }
+        if self.lambda_depth == 0 {
+            let declared = &self.declared;
+            self.shared
+                .extend(assigned.into_iter().filter(|name| declared.contains(name)));
+        } else {
+            self.assigned.extend(assigned);
+        }
+    }
+
+    fn assign(&mut self, name: &str, is_var: bool) {
+        if self.lambda_depth == 0 {
+            self.declared.insert(name.to_string());
+        } else if !is_var {
+            // `var x = ...` in a lambda declares a new variable
+            self.assigned.insert(name.to_string());
+        }
+    }
+}
+
+impl AstVisitor for Collector {
+    fn visit_expr(&mut self, expr: &AstExpression) {
+        match &expr.body {
+            AstExpressionBody::For {
+                var_name,
+                iterable,
+                body_exprs,
+            } => {
+                self.visit_expr(iterable);
+                self.assign(var_name, false);
+                self.visit_exprs(body_exprs);
+            }
+            AstExpressionBody::LVarAssign { name, rhs, is_var } => {
+                self.visit_expr(rhs);
+                self.assign(name, *is_var);
+            }
+            AstExpressionBody::MultipleAssign { names, rhs } => {
+                self.visit_expr(rhs);
+                for name in names {
+                    self.assign(name, false);
+                }
+            }
+            AstExpressionBody::LambdaExpr { params, exprs } => {
+                let names = params.iter().map(|param| param.name.as_str());
+                self.walk_lambda(names.collect(), exprs);
+            }
+            AstExpressionBody::AwaitCall {
+                call,
+                var_name,
+                rest,
+            } => {
+                self.visit_expr(call);
+                // The continuation is a lambda
+                self.walk_lambda(var_name.iter().map(|s| s.as_str()).collect(), rest);
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => self.assign(name, false),
+            Pattern::Array { items, rest } => {
+                for item in items {
+                    self.visit_pattern(item);
+                }
+                if let Some(name) = rest {
+                    self.assign(name, false);
+                }
+            }
+            _ => walk_pattern(self, pattern),
+        }
+    }
+}
+
+/// Type of the cells
+pub(super) fn cell_ty() -> TermTy {
+    ty::raw("Shiika::Internal::Cell")
+}
+
+/// `Shiika::Internal::Cell.new(value)`
+pub(super) fn new_cell(value: HirExpression) -> HirExpression {
+    Hir::method_call(
+        cell_ty(),
+        Hir::const_ref(
+            ty::meta("Shiika::Internal::Cell"),
+            const_fullname("::Shiika::Internal::Cell"),
+        ),
+        method_fullname(&class_fullname("Meta:Shiika::Internal::Cell"), "new"),
+        vec![Hir::bit_cast(ty::raw("Object"), value)],
+    )
+}
+
+/// `cell.value`
+pub(super) fn cell_value(cell: HirExpression, ty: TermTy) -> HirExpression {
+    let value = Hir::method_call(
+        ty::raw("Object"),
+        cell,
+        method_fullname(&cell_ty().fullname, "value"),
+        vec![],
+    );
+    Hir::bit_cast(ty, value)
+}
+
+/// `cell.value = value`
+pub(super) fn set_cell_value(cell: HirExpression, value: HirExpression) -> HirExpression {
+    let ty = value.ty.clone();
+    let result = Hir::method_call(
+        ty::raw("Object"),
+        cell,
+        method_fullname(&cell_ty().fullname, "value="),
+        vec![Hir::bit_cast(ty::raw("Object"), value)],
+    );
+    Hir::bit_cast(ty, result)
+}
@@ -10,17 +10,95 @@ pub fn create_signature(
     typarams: &[String],
 ) -> MethodSignature {
     let fullname = method_fullname(class_fullname, &sig.name.0);
-    let ret_ty = convert_typ(&sig.ret_typ, typarams);
-    let params = convert_params(&sig.params, typarams);
+    let typarams = all_typarams(typarams, sig);
+    let ret_ty = convert_typ(&sig.ret_typ, &typarams);
+    let params = convert_params(&sig.params, &typarams);
+    let n_class_typarams = typarams.len() - sig.typarams.len();
+    let method_typarams = sig
+        .typarams
+        .iter()
+        .enumerate()
+        .map(|(i, name)| ty::typaram(name, n_class_typarams + i))
+        .collect();
     MethodSignature {
         fullname,
         ret_ty,
         params,
+        typarams: method_typarams,
+    }
+}
+
+/// Return the type parameters of the class followed by those of the method.
+/// So the index of `U` of `Array<T>#map<U>` is 1
+fn all_typarams(class_typarams: &[String], sig: &ast::AstMethodSignature) -> Vec<String> {
+    class_typarams
+        .iter()
+        .chain(sig.typarams.iter())
+        .cloned()
+        .collect()
+}
+
+/// Resolve class names in `sig` which is written in `namespace`
+/// (eg. `B` in class `A` may be `A::B`)
+pub fn resolve_signature(
+    sig: &ast::AstMethodSignature,
+    namespace: &ClassFullname,
+    typarams: &[String],
+    class_exists: &dyn Fn(&ClassFullname) -> bool,
+) -> ast::AstMethodSignature {
+    let typarams = all_typarams(typarams, sig);
+    ast::AstMethodSignature {
+        name: sig.name.clone(),
+        name_span: sig.name_span.clone(),
+        typarams: sig.typarams.clone(),
+        params: resolve_params(&sig.params, namespace, &typarams, class_exists),
+        ret_typ: resolve_typ(&sig.ret_typ, namespace, &typarams, class_exists),
+        doc: sig.doc.clone(),
     }
 }
 
-// TODO: pass the list of visible classes
-fn convert_typ(typ: &ast::Typ, typarams: &[String]) -> TermTy {
+/// Resolve class names in `params` which are written in `namespace`
+pub fn resolve_params(
+    params: &[ast::Param],
+    namespace: &ClassFullname,
+    typarams: &[String],
+    class_exists: &dyn Fn(&ClassFullname) -> bool,
+) -> Vec<ast::Param> {
+    params
+        .iter()
+        .map(|param| ast::Param {
+            name: param.name.clone(),
+            typ: resolve_typ(&param.typ, namespace, typarams, class_exists),
+            implicit_conv: param.implicit_conv,
+        })
+        .collect()
+}
+
+/// Resolve class names in `typ` which is written in `namespace`.
+/// Names which are not found are left as is
+pub fn resolve_typ(
+    typ: &ast::Typ,
+    namespace: &ClassFullname,
+    typarams: &[String],
+    class_exists: &dyn Fn(&ClassFullname) -> bool,
+) -> ast::Typ {
+    let name = if typarams.contains(&typ.name) {
+        typ.name.clone()
+    } else {
+        match resolve_class_name(&typ.name, namespace, class_exists) {
+            Some(fullname) => fullname.0,
+            None => typ.name.clone(),
+        }
+    };
+    let typ_args = typ
+        .typ_args
+        .iter()
+        .map(|t| resolve_typ(t, namespace, typarams, class_exists))
+        .collect();
+    ast::Typ { name, typ_args }
+}
+
+pub fn convert_typ(typ: &ast::Typ, typarams: &[String]) -> TermTy {
     let found = typarams.iter().enumerate().find(|(_, s)| **s == typ.name);
     if let Some((idx, _)) = found {
         ty::typaram(&typ.name, idx)
@@ -44,6 +122,7 @@ pub fn convert_params(params: &[ast::Param], typarams: &[String]) -> Vec<MethodP
         .map(|param| MethodParam {
             name: param.name.to_string(),
             ty: convert_typ(&param.typ, typarams),
+            implicit_conv: param.implicit_conv,
         })
         .collect()
 }
@@ -57,5 +136,6 @@ pub fn signature_of_new(
         fullname: method_fullname(metaclass_fullname, "new"),
         ret_ty: instance_ty.clone(),
         params: initialize_params,
+        typarams: vec![],
     }
 }
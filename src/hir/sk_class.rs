@@ -13,10 +13,18 @@ pub struct SkClass {
     pub method_sigs: HashMap<MethodFirstname, MethodSignature>,
     /// eg. `Void` is an instance, not the class
     pub const_is_obj: bool,
+    /// Names of abstract methods declared in this class
+    pub abstract_methods: Vec<MethodFirstname>,
 }
 
 impl SkClass {
     pub fn class_ty(&self) -> TermTy {
         self.instance_ty.meta_ty()
     }
+
+    /// Whether this class declares any abstract method.
+    /// Abstract classes cannot be instantiated
+    pub fn is_abstract(&self) -> bool {
+        !self.abstract_methods.is_empty()
+    }
 }
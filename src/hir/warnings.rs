@@ -0,0 +1,424 @@
+/// Compile-time warnings
+///
+/// Unlike errors, warnings do not stop the compilation (unless
+/// `--deny-warnings` is given.) They are collected while making the HIR
+/// and stored in `Hir::warnings`, except the unused methods which are
+/// found in the call graph afterwards (`unused_methods`) and the items too
+/// large to compile quickly (`large_items`.)
+///
+/// Unreachable code is not a warning but an error (see flow_check.rs)
+use crate::ast::*;
+use crate::hir::call_graph::{self, CallGraph};
+use crate::hir::hir_maker::HirMaker;
+use crate::hir::hir_maker_context::*;
+use crate::hir::*;
+use crate::parser::token::Token;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Array literals with more elements than this are warned about by
+/// `large_items`
+pub const MAX_ARRAY_LITERAL_LEN: usize = 10_000;
+/// Methods (or constants, or the toplevel) with more expressions than this
+/// are warned about by `large_items`
+pub const MAX_ITEM_SIZE: usize = 100_000;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WarningKind {
+    /// A local variable is assigned but never referred
+    UnusedVariable,
+    /// A local variable of a lambda has the same name as an outer one
+    ShadowedVariable,
+    /// A method is never called
+    UnusedMethod,
+    /// The condition of `if`, `unless` or `while` is `true` or `false`
+    ConstantCondition,
+    /// A method (or a constant, or the toplevel) generates a huge function
+    LargeItem,
+    /// A class defines `==` but not `hash`
+    EqWithoutHash,
+}
+
+impl WarningKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedVariable => "unused_variable",
+            WarningKind::ShadowedVariable => "shadowed_variable",
+            WarningKind::UnusedMethod => "unused_method",
+            WarningKind::ConstantCondition => "constant_condition",
+            WarningKind::LargeItem => "large_item",
+            WarningKind::EqWithoutHash => "eq_without_hash",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub msg: String,
+    /// Where the warning is found: the full name of the method (eg.
+    /// `A#foo`), the constant (eg. `::A::B`) or `call_graph::MAIN`
+    pub place: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "warning[{}]: {} (in {})",
+            self.kind.name(),
+            self.msg,
+            self.place
+        )
+    }
+}
+
+impl HirMaker {
+    pub(super) fn warn(&mut self, kind: WarningKind, msg: String) {
+        let place = self.warning_place();
+        self.warnings.push(Warning { kind, msg, place });
+    }
+
+    /// Return the name of the method (or the constant) being converted
+    pub(super) fn warning_place(&self) -> String {
+        if let Some(sig) = self.method_ctx().and_then(|ctx| ctx.method_sig.as_ref()) {
+            return sig.fullname.full_name.clone();
+        }
+        match self.const_resolving.last() {
+            Some(fullname) => fullname.0.clone(),
+            None => call_graph::MAIN.to_string(),
+        }
+    }
+
+    /// Warn about the local variables of `ctx` (which is just popped)
+    /// never referred, including the ones of the ended blocks. Variables
+    /// whose name starts with `_` are ignored
+    pub(super) fn warn_unused_lvars(&mut self, ctx: &HirMakerContext, place: String) {
+        let mut names = ctx
+            .lvars
+            .values()
+            .chain(&ctx.ended_lvars)
+            .filter(|lvar| !lvar.used && !is_ignored(&lvar.name))
+            .map(|lvar| lvar.name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            self.warnings.push(Warning {
+                kind: WarningKind::UnusedVariable,
+                msg: format!("variable `{}' is never used", name),
+                place: place.clone(),
+            });
+        }
+    }
+
+    /// Warn if a new local variable (or a param) of the lambda being
+    /// converted hides the one of the outer scope
+    pub(super) fn warn_shadowing(&mut self, name: &str) {
+        if is_ignored(name) {
+            return;
+        }
+        let found = match self.outer_lvar_scope_of(self.ctx()) {
+            Some(outer_ctx) => self.is_visible_in(outer_ctx, name),
+            None => false,
+        };
+        if found {
+            self.warn(
+                WarningKind::ShadowedVariable,
+                format!("variable `{}' shadows the outer one", name),
+            );
+        }
+    }
+
+    fn is_visible_in(&self, ctx: &HirMakerContext, name: &str) -> bool {
+        if ctx.find_lvar(name).is_some() || ctx.find_fn_arg(name).is_some() {
+            return true;
+        }
+        match self.outer_lvar_scope_of(ctx) {
+            Some(outer_ctx) => self.is_visible_in(outer_ctx, name),
+            None => false,
+        }
+    }
+
+    /// Warn if `cond_expr` is a literal `true` or `false` (`while true` is
+    /// allowed because it is the way to write an infinite loop)
+    pub(super) fn warn_constant_condition(&mut self, cond_expr: &AstExpression, is_while: bool) {
+        match constant_value(cond_expr) {
+            Some(true) if is_while => (),
+            Some(value) => self.warn(
+                WarningKind::ConstantCondition,
+                format!("the condition is always {}", value),
+            ),
+            None => (),
+        }
+    }
+}
+
+/// Whether the variable is exempt from the warnings (names starting with
+/// `_` and the ones made by the compiler)
+fn is_ignored(name: &str) -> bool {
+    name.starts_with(&['_', ' '][..])
+}
+
+/// Return the value of the condition if it is a constant
+fn constant_value(expr: &AstExpression) -> Option<bool> {
+    match &expr.body {
+        AstExpressionBody::PseudoVariable(Token::KwTrue) => Some(true),
+        AstExpressionBody::PseudoVariable(Token::KwFalse) => Some(false),
+        AstExpressionBody::LogicalNot { expr } => constant_value(expr).map(|v| !v),
+        _ => None,
+    }
+}
+
+/// Return warnings for the methods written in Shiika which are never
+/// called. These are exempt:
+///
+/// - `initialize` (called by `new`) and `inherited` (a hook)
+/// - methods which override the ones of the superclasses (they may be
+///   called through the superclass)
+/// - methods which are not written in the program (eg. the ones defined by
+///   `@delegate`)
+pub fn unused_methods(hir: &Hir) -> Vec<Warning> {
+    let graph = CallGraph::new(hir);
+    let called = graph
+        .calls
+        .iter()
+        .map(|(_, callee)| callee.as_str())
+        .collect::<HashSet<_>>();
+    let mut warnings = vec![];
+    for (clsname, methods) in &hir.sk_methods {
+        for method in methods {
+            let name = &method.signature.fullname;
+            let is_shiika = match method.body {
+                SkMethodBody::ShiikaMethodBody { .. } => true,
+                _ => false,
+            };
+            if !is_shiika
+                || name.first_name.0 == "initialize"
+                || name.first_name.0 == "inherited"
+                || called.contains(name.full_name.as_str())
+                || overrides(hir, clsname, &name.first_name)
+                || !hir.def_spans.contains_key(&name.full_name)
+            {
+                continue;
+            }
+            warnings.push(Warning {
+                kind: WarningKind::UnusedMethod,
+                msg: format!("method `{}' is never called", name),
+                place: name.full_name.clone(),
+            });
+        }
+    }
+    warnings.sort_by(|a, b| a.place.cmp(&b.place));
+    warnings
+}
+
+/// Whether a superclass of the class has the method (including the
+/// abstract ones)
+fn overrides(hir: &Hir, clsname: &ClassFullname, name: &MethodFirstname) -> bool {
+    let mut sup = hir
+        .sk_classes
+        .get(clsname)
+        .and_then(|cls| cls.superclass_fullname.clone());
+    while let Some(sup_name) = sup {
+        let cls = match hir.sk_classes.get(&sup_name) {
+            Some(c) => c,
+            None => return false,
+        };
+        if cls.method_sigs.contains_key(name) {
+            return true;
+        }
+        sup = cls.superclass_fullname.clone();
+    }
+    false
+}
+
+/// Return warnings for the classes which define `==` but not `hash`.
+/// Objects which are `==` must have the same hash value to be the same
+/// element of `Set` (or the same key of `Hash`), but the `hash` of
+/// `Object` is different for each object. A `hash` of a superclass (other
+/// than `Object`) is assumed to be consistent with the `==`
+pub fn eq_without_hash(hir: &Hir) -> Vec<Warning> {
+    let eq = method_firstname("==");
+    let hash = method_firstname("hash");
+    let mut warnings = vec![];
+    for (clsname, cls) in &hir.sk_classes {
+        if clsname.0 == "Object"
+            || clsname.0.starts_with("Meta:")
+            || !cls.method_sigs.contains_key(&eq)
+        {
+            continue;
+        }
+        let has_hash = cls.method_sigs.contains_key(&hash)
+            || (overrides(hir, clsname, &hash) && !defined_in_object(hir, clsname, &hash));
+        if !has_hash {
+            warnings.push(Warning {
+                kind: WarningKind::EqWithoutHash,
+                msg: format!(
+                    "class `{}' defines `==' but not `hash' (objects which are `==' must have the same `hash')",
+                    clsname
+                ),
+                place: clsname.0.clone(),
+            });
+        }
+    }
+    warnings.sort_by(|a, b| a.place.cmp(&b.place));
+    warnings
+}
+
+/// Whether the nearest superclass which has the method is `Object`
+fn defined_in_object(hir: &Hir, clsname: &ClassFullname, name: &MethodFirstname) -> bool {
+    let mut sup = hir
+        .sk_classes
+        .get(clsname)
+        .and_then(|cls| cls.superclass_fullname.clone());
+    while let Some(sup_name) = sup {
+        let cls = match hir.sk_classes.get(&sup_name) {
+            Some(c) => c,
+            None => return false,
+        };
+        if cls.method_sigs.contains_key(name) {
+            return sup_name.0 == "Object";
+        }
+        sup = cls.superclass_fullname.clone();
+    }
+    false
+}
+
+/// Return warnings for the methods, the constants and the toplevel which
+/// make a function so large that LLVM takes minutes to compile it. The
+/// size is the number of the expressions in the HIR (an element of an
+/// array literal is compiled into a call of `Array#push`, so it is counted
+/// as an expression.)
+///
+/// Note that generic classes are not specialized for each type argument,
+/// so their methods are compiled (and counted) only once.
+pub fn large_items(hir: &Hir) -> Vec<Warning> {
+    let mut items = vec![];
+    for methods in hir.sk_methods.values() {
+        for method in methods {
+            if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
+                let mut size = ItemSize::default();
+                size.add_exprs(exprs);
+                items.push((method.signature.fullname.full_name.clone(), size));
+            }
+        }
+    }
+    for expr in &hir.const_inits {
+        if let HirExpressionBase::HirConstAssign { fullname, rhs } = &expr.node {
+            let mut size = ItemSize::default();
+            size.add_expr(rhs);
+            items.push((fullname.0.clone(), size));
+        }
+    }
+    let mut size = ItemSize::default();
+    size.add_exprs(&hir.main_exprs);
+    items.push((call_graph::MAIN.to_string(), size));
+
+    let mut warnings = vec![];
+    for (place, size) in items {
+        if size.max_array_len > MAX_ARRAY_LITERAL_LEN {
+            warnings.push(Warning {
+                kind: WarningKind::LargeItem,
+                msg: format!(
+                    "array literal with {} elements (consider making the array with a loop)",
+                    size.max_array_len
+                ),
+                place: place.clone(),
+            });
+        }
+        if size.n_exprs > MAX_ITEM_SIZE {
+            warnings.push(Warning {
+                kind: WarningKind::LargeItem,
+                msg: format!(
+                    "{} expressions in one function (consider splitting it into methods)",
+                    size.n_exprs
+                ),
+                place,
+            });
+        }
+    }
+    warnings.sort_by(|a, b| a.place.cmp(&b.place));
+    warnings
+}
+
+/// Size of a method (or a constant, or the toplevel) in the HIR
+#[derive(Debug, Default)]
+struct ItemSize {
+    /// Number of the expressions (including the bodies of the lambdas,
+    /// which are compiled into the same module)
+    n_exprs: usize,
+    /// Number of the elements of the largest array literal
+    max_array_len: usize,
+}
+
+impl ItemSize {
+    fn add_exprs(&mut self, exprs: &HirExpressions) {
+        for expr in &exprs.exprs {
+            self.add_expr(expr);
+        }
+    }
+
+    fn add_expr(&mut self, expr: &HirExpression) {
+        self.n_exprs += 1;
+        match &expr.node {
+            HirExpressionBase::HirLogicalNot { expr }
+            | HirExpressionBase::HirBox { expr }
+            | HirExpressionBase::HirUnbox { expr }
+            | HirExpressionBase::HirBitCast { expr } => self.add_expr(expr),
+            HirExpressionBase::HirLogicalAnd { left, right }
+            | HirExpressionBase::HirLogicalOr { left, right } => {
+                self.add_expr(left);
+                self.add_expr(right);
+            }
+            HirExpressionBase::HirIfExpression {
+                cond_expr,
+                then_exprs,
+                else_exprs,
+            } => {
+                self.add_expr(cond_expr);
+                self.add_exprs(then_exprs);
+                if let Some(exprs) = else_exprs {
+                    self.add_exprs(exprs);
+                }
+            }
+            HirExpressionBase::HirWhileExpression {
+                cond_expr,
+                body_exprs,
+            } => {
+                self.add_expr(cond_expr);
+                self.add_exprs(body_exprs);
+            }
+            HirExpressionBase::HirBreakExpression { expr: Some(expr) } => self.add_expr(expr),
+            HirExpressionBase::HirReturnExpression { arg } => self.add_expr(arg),
+            HirExpressionBase::HirLVarAssign { rhs, .. }
+            | HirExpressionBase::HirIVarAssign { rhs, .. }
+            | HirExpressionBase::HirConstAssign { rhs, .. } => self.add_expr(rhs),
+            HirExpressionBase::HirMethodCall {
+                receiver_expr,
+                arg_exprs,
+                ..
+            } => {
+                self.add_expr(receiver_expr);
+                for expr in arg_exprs {
+                    self.add_expr(expr);
+                }
+            }
+            HirExpressionBase::HirLambdaExpr {
+                exprs,
+                captures_ary,
+                ..
+            } => {
+                self.add_exprs(exprs);
+                self.add_expr(captures_ary);
+            }
+            HirExpressionBase::HirParenthesizedExpr { exprs } => self.add_exprs(exprs),
+            HirExpressionBase::HirArrayLiteral { exprs } => {
+                // `tmp = Array.new`, `tmp.push(x)`, ..., `tmp`
+                let len = exprs.exprs.len().saturating_sub(2);
+                self.max_array_len = self.max_array_len.max(len);
+                self.add_exprs(exprs);
+            }
+            _ => (),
+        }
+    }
+}
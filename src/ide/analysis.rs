@@ -0,0 +1,153 @@
+use crate::error::Error;
+use crate::hir::call_graph::class_of;
+use crate::ide::*;
+use crate::parser::lexer::{Cursor, Span};
+use std::collections::HashMap;
+
+/// Symbols and semantic tokens of a source file
+#[derive(Debug)]
+pub struct Analysis {
+    pub src: String,
+    /// Symbols of the program (with the builtin library). Definition
+    /// spans are the ones in the source file
+    pub index: SymbolIndex,
+    pub tokens: Vec<SemanticToken>,
+    /// Types of the local variables in each method
+    /// (see `hir::lvar_types::collect_lvar_types`)
+    pub lvar_types: HashMap<String, HashMap<String, String>>,
+}
+
+/// Return all the parse errors in `src` (see `Parser::parse_recovering`).
+/// The builtin library is prepended and the spans are the ones in `src` as
+/// `Analysis::with_builtin` does
+pub fn parse_errors(builtin: &str, src: &str) -> Vec<Error> {
+    let base = Cursor::end_of(builtin);
+    let (_, errors) = crate::parser::Parser::parse_recovering(&(builtin.to_string() + src));
+    errors
+        .into_iter()
+        .map(|mut err| {
+            err.span = err.span.and_then(|span| span.relative_to(&base));
+            err
+        })
+        .collect()
+}
+
+impl Analysis {
+    /// Analyze `src` as a program (the builtin library is prepended as
+    /// `runner::compile` does)
+    pub fn new(src: &str) -> Result<Analysis, Box<dyn std::error::Error>> {
+        let builtin = crate::runner::load_builtin()?;
+        Ok(Analysis::with_builtin(&builtin, src)?)
+    }
+
+    /// Same as `new` but does not load the builtin library from the files.
+    /// The span of the error (if any) is the one in `src`
+    pub fn with_builtin(builtin: &str, src: &str) -> Result<Analysis, Error> {
+        let base = Cursor::end_of(builtin);
+        let in_src = |mut err: Error| {
+            err.span = err.span.and_then(|span| span.relative_to(&base));
+            err
+        };
+        let ast = crate::parser::Parser::parse(&(builtin.to_string() + src)).map_err(in_src)?;
+        let corelib = crate::corelib::Corelib::create(&ast);
+        let hir = crate::hir::build(ast, corelib).map_err(in_src)?;
+
+        let mut index = SymbolIndex::new(&hir);
+        for sym in &mut index.symbols {
+            // Definitions in the builtin library are not in `src`
+            sym.def_span = sym.def_span.as_ref().and_then(|s| s.relative_to(&base));
+        }
+        let tokens = semantic_tokens(src, &index);
+        // Constants defined with an assignment at the toplevel are not
+        // recorded in the HIR
+        for token in &tokens {
+            if let (SemanticTokenKind::Const, Some(span)) = (token.kind, &token.def_span) {
+                let name = format!("::{}", span.src(src));
+                if let Some(sym) = index.symbols.iter_mut().find(|sym| sym.name == name) {
+                    sym.def_span.get_or_insert_with(|| span.clone());
+                }
+            }
+        }
+        let lvar_types = crate::hir::lvar_types::collect_lvar_types(&hir)
+            .into_iter()
+            .map(|(method, types)| {
+                let types = types.into_iter().map(|(k, ty)| (k, ty.to_string()));
+                (method, types.collect())
+            })
+            .collect();
+        Ok(Analysis {
+            src: src.to_string(),
+            index,
+            tokens,
+            lvar_types,
+        })
+    }
+
+    /// Return the token at `pos` (a byte offset of the source)
+    pub fn token_at(&self, pos: usize) -> Option<&SemanticToken> {
+        self.tokens.iter().find(|t| t.span.contains(pos))
+    }
+
+    /// Return the spans where the name at `pos` is defined. May return
+    /// more than one for a method because the type of the receiver is not
+    /// considered
+    pub fn definitions_at(&self, pos: usize) -> Vec<Span> {
+        let token = match self.token_at(pos) {
+            Some(t) => t,
+            None => return vec![],
+        };
+        let kind = match token.kind {
+            SemanticTokenKind::LVar => return token.def_span.iter().cloned().collect(),
+            SemanticTokenKind::IVar => return vec![],
+            SemanticTokenKind::Class => SymbolKind::Class,
+            SemanticTokenKind::Method => SymbolKind::Method,
+            SemanticTokenKind::Const => SymbolKind::Const,
+        };
+        let name = token.span.src(&self.src);
+        self.index
+            .find_by_short_name(kind, name)
+            .into_iter()
+            .filter_map(|sym| sym.def_span.clone())
+            .collect()
+    }
+
+    /// Return the description of the name at `pos` (eg. `x: Int` for a
+    /// local variable, `A#foo(x: Int) -> Int` for a method)
+    pub fn hover_at(&self, pos: usize) -> Option<String> {
+        let token = self.token_at(pos)?;
+        let name = token.span.src(&self.src);
+        let kind = match token.kind {
+            SemanticTokenKind::LVar => {
+                let ty = self.lvar_types.get(&token.scope)?.get(name)?;
+                return Some(format!("{}: {}", name, ty));
+            }
+            SemanticTokenKind::IVar => {
+                let class = class_of(&token.scope)?;
+                let sym = self.index.symbols.iter().find(|sym| {
+                    sym.kind == SymbolKind::IVar
+                        && sym.name == name
+                        && sym.container.as_deref() == Some(class)
+                })?;
+                return Some(format!("{}: {}", name, sym.detail));
+            }
+            SemanticTokenKind::Class => SymbolKind::Class,
+            SemanticTokenKind::Method => SymbolKind::Method,
+            SemanticTokenKind::Const => SymbolKind::Const,
+        };
+        let found = self
+            .index
+            .find_by_short_name(kind, name)
+            .into_iter()
+            .map(|sym| match sym.kind {
+                SymbolKind::Method => format!("{}{}", sym.name, sym.detail),
+                SymbolKind::Const => format!("{}: {}", sym.name, sym.detail),
+                _ => sym.detail.clone(),
+            })
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            None
+        } else {
+            Some(found.join("\n"))
+        }
+    }
+}
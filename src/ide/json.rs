@@ -0,0 +1,234 @@
+/// Minimal JSON reader/writer for the messages of the language server
+use crate::error::json_str;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    /// Keys are kept in the order of appearance
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut reader = Reader {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let value = reader.read_value()?;
+        reader.skip_ws();
+        if reader.pos < reader.chars.len() {
+            return Err(format!("unexpected char at {}", reader.pos));
+        }
+        Ok(value)
+    }
+
+    /// Make an object from the pairs of a key and a value
+    pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+        Json::Object(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn str(s: &str) -> Json {
+        Json::Str(s.to_string())
+    }
+
+    /// Return the value of `key` (`None` if `self` is not an object)
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follow the keys (eg. `["textDocument", "uri"]`)
+    pub fn dig(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |json, key| json.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::Str(s) => write!(f, "{}", json_str(s)),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", json_str(k), v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next_char(&mut self) -> Result<char, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| "unexpected end of input".to_string())?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        for c in word.chars() {
+            if self.next_char()? != c {
+                return Err(format!("expected `{}' at {}", word, self.pos));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        match self.peek() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.read_string().map(Json::Str),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_ws();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.read_value()?);
+                    self.skip_ws();
+                    match self.next_char()? {
+                        ',' => (),
+                        ']' => return Ok(Json::Array(items)),
+                        c => return Err(format!("unexpected `{}' in an array", c)),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut pairs = vec![];
+                self.skip_ws();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(pairs));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.read_string()?;
+                    self.skip_ws();
+                    self.expect(":")?;
+                    pairs.push((key, self.read_value()?));
+                    self.skip_ws();
+                    match self.next_char()? {
+                        ',' => (),
+                        '}' => return Ok(Json::Object(pairs)),
+                        c => return Err(format!("unexpected `{}' in an object", c)),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let begin = self.pos;
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_digit() || "+-.eE".contains(c) {
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let s = self.chars[begin..self.pos].iter().collect::<String>();
+                s.parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("invalid number: {}", s))
+            }
+            Some(c) => Err(format!("unexpected `{}' at {}", c, self.pos)),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            match self.next_char()? {
+                '"' => return Ok(s),
+                '\\' => match self.next_char()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let hex = (0..4)
+                            .map(|_| self.next_char())
+                            .collect::<Result<String, _>>()?;
+                        let code = u32::from_str_radix(&hex, 16)
+                            .map_err(|_| format!("invalid escape: \\u{}", hex))?;
+                        // Surrogate pairs are not supported
+                        s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+}
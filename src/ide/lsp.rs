@@ -0,0 +1,329 @@
+/// Language server (`shiika lsp`)
+///
+/// Talks the Language Server Protocol over stdin/stdout. Supported
+/// features are:
+/// - diagnostics (when a file is opened or saved)
+/// - go to definition
+/// - hover (shows the type of the name)
+///
+/// Positions are counted in chars, not in UTF-16 code units
+use crate::error::{Error, ErrorDetails};
+use crate::ide::json::Json;
+use crate::ide::Analysis;
+use crate::parser::lexer::Span;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Run the server until `exit` is received
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let builtin = crate::runner::load_builtin()?;
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    serve(&builtin, stdin.lock(), stdout.lock())
+}
+
+/// Read messages from `input` and write the responses to `output`
+pub fn serve<R: BufRead, W: Write>(
+    builtin: &str,
+    mut input: R,
+    mut output: W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut server = Server::new(builtin);
+    while let Some(msg) = read_message(&mut input)? {
+        for res in server.handle(&msg) {
+            let body = res.to_string();
+            write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+            output.flush()?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read a message (`Content-Length: N\r\n\r\n` followed by N bytes of
+/// JSON). Returns `None` at the end of the input
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Json>, Box<dyn std::error::Error>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = Some(n.trim().parse::<usize>()?);
+        }
+    }
+    let len = len.ok_or("lsp: Content-Length not given")?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    let json = Json::parse(&String::from_utf8(body)?)?;
+    Ok(Some(json))
+}
+
+struct Document {
+    text: String,
+    /// Result of the last successful analysis
+    analysis: Option<Analysis>,
+    /// Whether `text` is changed after `analysis`
+    stale: bool,
+}
+
+pub struct Server {
+    builtin: String,
+    documents: HashMap<String, Document>,
+    /// Set to true when `exit` is received
+    pub exited: bool,
+}
+
+impl Server {
+    pub fn new(builtin: &str) -> Server {
+        Server {
+            builtin: builtin.to_string(),
+            documents: HashMap::new(),
+            exited: false,
+        }
+    }
+
+    /// Handle a request or a notification and return the messages to send
+    pub fn handle(&mut self, msg: &Json) -> Vec<Json> {
+        let method = msg.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = msg.get("params").unwrap_or(&Json::Null);
+        let uri = params
+            .dig(&["textDocument", "uri"])
+            .and_then(|u| u.as_str())
+            .unwrap_or("")
+            .to_string();
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => Json::Null,
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let text = params.dig(&["textDocument", "text"]);
+                let text = text.and_then(|t| t.as_str()).unwrap_or("");
+                self.documents.insert(
+                    uri.clone(),
+                    Document {
+                        text: text.to_string(),
+                        analysis: None,
+                        stale: true,
+                    },
+                );
+                return vec![self.analyze(&uri)];
+            }
+            "textDocument/didChange" => {
+                // The whole text is sent (`TextDocumentSyncKind.Full`)
+                let changes = params.get("contentChanges").and_then(|c| c.as_array());
+                let text = changes.and_then(|c| c.last()).and_then(|c| c.get("text"));
+                if let (Some(doc), Some(text)) =
+                    (self.documents.get_mut(&uri), text.and_then(|t| t.as_str()))
+                {
+                    doc.text = text.to_string();
+                    doc.stale = true;
+                }
+                return vec![];
+            }
+            "textDocument/didSave" => return vec![self.analyze(&uri)],
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![diagnostics(&uri, vec![])];
+            }
+            "textDocument/definition" => {
+                let spans = match self.pos_in(&uri, params) {
+                    Some((analysis, pos)) => analysis.definitions_at(pos),
+                    None => vec![],
+                };
+                let locations = spans
+                    .iter()
+                    .map(|span| {
+                        Json::object(vec![("uri", Json::str(&uri)), ("range", range(span))])
+                    })
+                    .collect();
+                Json::Array(locations)
+            }
+            "textDocument/hover" => match self.pos_in(&uri, params) {
+                Some((analysis, pos)) => match analysis.hover_at(pos) {
+                    Some(s) => Json::object(vec![(
+                        "contents",
+                        Json::object(vec![
+                            ("kind", Json::str("markdown")),
+                            ("value", Json::str(&format!("```\n{}\n```", s))),
+                        ]),
+                    )]),
+                    None => Json::Null,
+                },
+                None => Json::Null,
+            },
+            _ => match msg.get("id") {
+                // Unknown request
+                Some(id) => {
+                    return vec![Json::object(vec![
+                        ("jsonrpc", Json::str("2.0")),
+                        ("id", id.clone()),
+                        (
+                            "error",
+                            Json::object(vec![
+                                ("code", Json::Number(-32601.0)),
+                                ("message", Json::str(&format!("unknown method: {}", method))),
+                            ]),
+                        ),
+                    ])]
+                }
+                // Unknown notification (eg. `initialized`)
+                None => return vec![],
+            },
+        };
+        match msg.get("id") {
+            Some(id) => vec![Json::object(vec![
+                ("jsonrpc", Json::str("2.0")),
+                ("id", id.clone()),
+                ("result", result),
+            ])],
+            None => vec![],
+        }
+    }
+
+    /// Analyze the document and return the diagnostics
+    fn analyze(&mut self, uri: &str) -> Json {
+        let doc = match self.documents.get_mut(uri) {
+            Some(doc) => doc,
+            None => return diagnostics(uri, vec![]),
+        };
+        let errors = match analyze_(&self.builtin, &doc.text) {
+            Ok(analysis) => {
+                doc.analysis = Some(analysis);
+                doc.stale = false;
+                vec![]
+            }
+            Err(e) => match e.details {
+                // Report the other parse errors too
+                ErrorDetails::ParseError { .. } => all_parse_errors(&self.builtin, &doc.text, e),
+                _ => vec![e],
+            },
+        };
+        diagnostics(uri, errors)
+    }
+
+    /// Return the analysis of the document and the position of the request
+    /// (as a byte offset)
+    fn pos_in(&mut self, uri: &str, params: &Json) -> Option<(&Analysis, usize)> {
+        if self.documents.get(uri)?.stale {
+            // Use the last result if the current text has an error
+            let _ = self.analyze(uri);
+        }
+        let line = params.dig(&["position", "line"])?.as_usize()?;
+        let col = params.dig(&["position", "character"])?.as_usize()?;
+        let analysis = self.documents.get(uri)?.analysis.as_ref()?;
+        Some((analysis, offset_of(&analysis.src, line, col)?))
+    }
+}
+
+/// Analyze `src`. Panics in the compiler are reported as errors
+fn analyze_(builtin: &str, src: &str) -> Result<Analysis, Error> {
+    let result = std::panic::catch_unwind(|| Analysis::with_builtin(builtin, src));
+    match result {
+        Ok(r) => r,
+        Err(_) => Err(crate::error::bug("[BUG] the compiler panicked")),
+    }
+}
+
+/// Return all the parse errors of `src`. `first` is the one which stopped
+/// the analysis (returned in case the parser does not find it again)
+fn all_parse_errors(builtin: &str, src: &str, first: Error) -> Vec<Error> {
+    let errors = crate::ide::parse_errors(builtin, src);
+    if errors.is_empty() {
+        vec![first]
+    } else {
+        errors
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                (
+                    "textDocumentSync",
+                    Json::object(vec![
+                        ("openClose", Json::Bool(true)),
+                        ("change", Json::Number(1.0)),
+                        ("save", Json::Bool(true)),
+                    ]),
+                ),
+                ("definitionProvider", Json::Bool(true)),
+                ("hoverProvider", Json::Bool(true)),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![("name", Json::str("shiika"))]),
+        ),
+    ])
+}
+
+/// Make a `textDocument/publishDiagnostics` notification
+fn diagnostics(uri: &str, errors: Vec<Error>) -> Json {
+    let items = errors
+        .iter()
+        .map(|err| {
+            let span = err.span.clone().unwrap_or_default();
+            Json::object(vec![
+                ("range", range(&span)),
+                ("severity", Json::Number(1.0)),
+                ("code", Json::str(err.code())),
+                ("source", Json::str("shiika")),
+                ("message", Json::str(&err.msg.replace("**", ""))),
+            ])
+        })
+        .collect();
+    Json::object(vec![
+        ("jsonrpc", Json::str("2.0")),
+        ("method", Json::str("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", Json::str(uri)),
+                ("diagnostics", Json::Array(items)),
+            ]),
+        ),
+    ])
+}
+
+fn range(span: &Span) -> Json {
+    let pos = |line: usize, col: usize| {
+        Json::object(vec![
+            ("line", Json::Number(line as f64)),
+            ("character", Json::Number(col as f64)),
+        ])
+    };
+    Json::object(vec![
+        ("start", pos(span.begin.line(), span.begin.col())),
+        ("end", pos(span.end.line(), span.end.col())),
+    ])
+}
+
+/// Convert a position (0-origin line and column) into a byte offset of `src`
+fn offset_of(src: &str, line: usize, col: usize) -> Option<usize> {
+    let mut offset = 0;
+    for (i, l) in src.split('\n').enumerate() {
+        if i == line {
+            let n = l
+                .char_indices()
+                .nth(col)
+                .map(|(j, _)| j)
+                .unwrap_or_else(|| l.len());
+            return Some(offset + n);
+        }
+        offset += l.len() + 1;
+    }
+    None
+}
@@ -0,0 +1,19 @@
+/// APIs for editors and IDEs
+///
+/// - `SymbolIndex`: classes, methods, constants and ivars of a program
+///   (for workspace symbol search)
+/// - `semantic_tokens`: kind and span of the names in a source
+///   (for syntax highlighting)
+/// - `Analysis`: both of the above for a source file, with lookup of
+///   definitions and types (for go-to-definition and hover)
+/// - `parse_errors`: all the parse errors of a source (not only the first
+///   one)
+/// - `lsp`: language server which provides the above to editors
+mod analysis;
+pub mod json;
+pub mod lsp;
+mod semantic_tokens;
+mod symbol_index;
+pub use analysis::{parse_errors, Analysis};
+pub use semantic_tokens::{semantic_tokens, SemanticToken, SemanticTokenKind};
+pub use symbol_index::{Symbol, SymbolIndex, SymbolKind};
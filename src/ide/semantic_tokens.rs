@@ -0,0 +1,170 @@
+use crate::formatter;
+use crate::ide::SymbolIndex;
+use crate::parser::lexer::{Lexer, Span};
+use crate::parser::token::Token;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SemanticTokenKind {
+    Class,
+    Method,
+    Const,
+    LVar,
+    IVar,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SemanticToken {
+    pub kind: SemanticTokenKind,
+    pub span: Span,
+    /// Where the variable is declared (for `LVar`). For `Const`, this is
+    /// set to `span` if it is an assignment at the toplevel (`X = ...`)
+    pub def_span: Option<Span>,
+    /// Full name of the method which contains this token (eg. `A#foo`,
+    /// `Meta:A#bar`. Empty for the toplevel)
+    pub scope: String,
+}
+
+/// Local variables visible in a class, a method or the toplevel
+struct Scope {
+    /// Nesting level of `end` where this scope began
+    level: usize,
+    /// Full name of the class or the method (`None` until the name appears)
+    name: Option<String>,
+    lvars: HashMap<String, Span>,
+}
+
+/// Return the names in `src` with their kinds, in the order of appearance.
+/// `index` is used to tell classes from other constants.
+///
+/// `src` is expected to be a program which can be parsed (for now the
+/// lexer panics on some invalid inputs)
+pub fn semantic_tokens(src: &str, index: &SymbolIndex) -> Vec<SemanticToken> {
+    // A comment at the end of the file must be terminated by a newline
+    let src = format!("{}\n", src);
+    let words = read_words(&src);
+    let mut level = 0;
+    let mut scopes = vec![Scope {
+        level,
+        name: Some("".to_string()),
+        lvars: HashMap::new(),
+    }];
+    let mut tokens = vec![];
+    for (i, (token, span)) in words.iter().enumerate() {
+        let prev = if i == 0 { None } else { Some(&words[i - 1].0) };
+        let next = words.get(i + 1).map(|(t, _)| t);
+        let mut def_span = None;
+        let kind = match token {
+            Token::KwClass | Token::KwDef => {
+                // `abstract def` and `extern def` have no body
+                if prev != Some(&Token::KwAbstract) && prev != Some(&Token::KwExtern) {
+                    level += 1;
+                    scopes.push(Scope {
+                        level,
+                        name: None,
+                        lvars: HashMap::new(),
+                    });
+                }
+                continue;
+            }
+            Token::KwCase | Token::KwLoop => {
+                level += 1;
+                continue;
+            }
+            Token::KwIf | Token::KwUnless | Token::KwWhile | Token::KwFor => {
+                // A modifier (eg. `foo if bar`) has no `end`
+                let is_modifier = i > 0
+                    && words[i - 1].1.end.line() == span.begin.line()
+                    && formatter::ends_value(&words[i - 1].0);
+                if !is_modifier {
+                    level += 1;
+                }
+                continue;
+            }
+            Token::KwEnd => {
+                if scopes.len() > 1 && scopes.last().unwrap().level == level {
+                    scopes.pop();
+                }
+                level = level.saturating_sub(1);
+                continue;
+            }
+            Token::UpperWord(name) => {
+                if prev == Some(&Token::KwClass) {
+                    let outer = outer_name(&scopes);
+                    let fullname = if outer.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{}::{}", outer, name)
+                    };
+                    scopes.last_mut().unwrap().name.get_or_insert(fullname);
+                    SemanticTokenKind::Class
+                } else if index.has_class(name) {
+                    SemanticTokenKind::Class
+                } else {
+                    if level == 0 && next == Some(&Token::Equal) {
+                        def_span = Some(span.clone());
+                    }
+                    SemanticTokenKind::Const
+                }
+            }
+            Token::IVar(_) => SemanticTokenKind::IVar,
+            Token::LowerWord(name) => {
+                if prev == Some(&Token::KwDef) || prev == Some(&Token::Dot) {
+                    let is_class_method =
+                        i >= 3 && words[i - 2].0 == Token::KwSelf && words[i - 3].0 == Token::KwDef;
+                    if prev == Some(&Token::KwDef) || is_class_method {
+                        let class = outer_name(&scopes);
+                        let fullname = if is_class_method {
+                            format!("Meta:{}#{}", class, name)
+                        } else {
+                            format!("{}#{}", class, name)
+                        };
+                        scopes.last_mut().unwrap().name.get_or_insert(fullname);
+                    }
+                    SemanticTokenKind::Method
+                } else if prev == Some(&Token::KwVar)
+                    || prev == Some(&Token::KwFor)
+                    || next == Some(&Token::Equal)
+                    || next == Some(&Token::Colon)
+                {
+                    // Declaration of a variable or a parameter
+                    let lvars = &mut scopes.last_mut().unwrap().lvars;
+                    let decl = lvars.entry(name.clone()).or_insert_with(|| span.clone());
+                    def_span = Some(decl.clone());
+                    SemanticTokenKind::LVar
+                } else if scopes.last().unwrap().lvars.contains_key(name)
+                    && next != Some(&Token::LParen)
+                {
+                    def_span = scopes.last().unwrap().lvars.get(name).cloned();
+                    SemanticTokenKind::LVar
+                } else {
+                    SemanticTokenKind::Method
+                }
+            }
+            _ => continue,
+        };
+        let scope = scopes.last().unwrap().name.clone().unwrap_or_default();
+        tokens.push(SemanticToken {
+            kind,
+            span: span.clone(),
+            def_span,
+            scope,
+        });
+    }
+    tokens
+}
+
+/// Return the name of the scope which contains the innermost one
+fn outer_name(scopes: &[Scope]) -> String {
+    if scopes.len() < 2 {
+        return "".to_string();
+    }
+    scopes[scopes.len() - 2].name.clone().unwrap_or_default()
+}
+
+/// Return the tokens in `src` except spaces and separators
+fn read_words(src: &str) -> Vec<(Token, Span)> {
+    Lexer::tokens(src)
+        .filter(|(token, _)| token != &Token::Space && token != &Token::Separator)
+        .collect()
+}
@@ -0,0 +1,119 @@
+use crate::hir::Hir;
+use crate::names::*;
+use crate::parser::lexer::Span;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolKind {
+    Class,
+    Method,
+    Const,
+    IVar,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// Full name (eg. `A::B`, `A#foo`, `::A::FOO`, `@bar`)
+    pub name: String,
+    /// Full name of the class this symbol belongs to (for methods and ivars)
+    pub container: Option<String>,
+    /// Type of the symbol (eg. `Int` for a constant)
+    pub detail: String,
+    /// Where the name is written in the definition (`None` for the
+    /// corelib, ivars, etc.)
+    pub def_span: Option<Span>,
+}
+
+/// Symbols defined in a program (including the corelib)
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    /// Sorted by the name
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolIndex {
+    pub fn new(hir: &Hir) -> SymbolIndex {
+        let mut symbols = vec![];
+        for (fullname, sk_class) in &hir.sk_classes {
+            if !fullname.is_meta() {
+                let detail = match &sk_class.superclass_fullname {
+                    Some(s) => format!("class {} < {}", fullname, s),
+                    None => format!("class {}", fullname),
+                };
+                symbols.push(Symbol {
+                    kind: SymbolKind::Class,
+                    name: fullname.0.clone(),
+                    container: None,
+                    detail,
+                    def_span: hir.def_spans.get(&fullname.0).cloned(),
+                });
+            }
+            for sig in sk_class.method_sigs.values() {
+                let params = sig
+                    .params
+                    .iter()
+                    .map(|param| format!("{}: {}", param.name, param.ty))
+                    .collect::<Vec<_>>();
+                symbols.push(Symbol {
+                    kind: SymbolKind::Method,
+                    name: sig.fullname.full_name.clone(),
+                    container: Some(fullname.0.clone()),
+                    detail: format!("({}) -> {}", params.join(", "), sig.ret_ty),
+                    def_span: hir.def_spans.get(&sig.fullname.full_name).cloned(),
+                });
+            }
+            for ivar in sk_class.ivars.values() {
+                symbols.push(Symbol {
+                    kind: SymbolKind::IVar,
+                    name: format!("@{}", ivar.name.trim_start_matches('@')),
+                    container: Some(fullname.0.clone()),
+                    detail: ivar.ty.to_string(),
+                    def_span: None,
+                });
+            }
+        }
+        for (fullname, ty) in &hir.constants {
+            // Constants for classes are listed as classes
+            let name = fullname.0.trim_start_matches("::");
+            if hir.sk_classes.contains_key(&class_fullname(name)) {
+                continue;
+            }
+            symbols.push(Symbol {
+                kind: SymbolKind::Const,
+                name: fullname.0.clone(),
+                container: None,
+                detail: ty.to_string(),
+                def_span: hir.def_spans.get(&fullname.0).cloned(),
+            });
+        }
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        SymbolIndex { symbols }
+    }
+
+    /// Return the symbols whose name contains `query` (case insensitive)
+    pub fn search(&self, query: &str) -> Vec<&Symbol> {
+        let query = query.to_lowercase();
+        self.symbols
+            .iter()
+            .filter(|sym| sym.name.to_lowercase().contains(&query))
+            .collect()
+    }
+
+    /// Return the symbols of `kind` whose last part of the name is `name`
+    /// (eg. `B` for `A::B`, `foo` for `A#foo`)
+    pub fn find_by_short_name(&self, kind: SymbolKind, name: &str) -> Vec<&Symbol> {
+        let suffix = match kind {
+            SymbolKind::Method => format!("#{}", name),
+            _ => format!("::{}", name),
+        };
+        self.symbols
+            .iter()
+            .filter(|sym| sym.kind == kind && (sym.name == name || sym.name.ends_with(&suffix)))
+            .collect()
+    }
+
+    /// Whether there is a class whose name is (or ends with `::`) `name`
+    pub fn has_class(&self, name: &str) -> bool {
+        !self.find_by_short_name(SymbolKind::Class, name).is_empty()
+    }
+}
@@ -1,11 +1,23 @@
 // #![feature(nll)]  // QUESTION: Do we still need this?
 pub mod ast;
+pub mod ast_visitor;
+pub mod build_cache;
+pub mod build_plan;
+pub mod builtin_cache;
 pub mod code_gen;
+pub mod conformance;
 pub mod corelib;
 pub mod error;
+pub mod error_codes;
+pub mod formatter;
 pub mod hir;
+pub mod ide;
 pub mod names;
 pub mod parser;
+pub mod platform;
 pub mod runner;
+pub mod test_runner;
 pub mod ty;
 pub mod type_checking;
+
+pub use runner::{compile_str, CompileOptions, CompiledModule};
@@ -1,21 +1,252 @@
+use shiika::conformance;
+use shiika::error::{Error, ErrorDetails};
+use shiika::error_codes;
+use shiika::formatter;
+use shiika::hir::config::BuildConfig;
 use shiika::runner;
+use shiika::test_runner;
 #[macro_use]
 extern crate clap;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = clap::App::from(yaml).get_matches();
+    if let Err(e) = main_(&matches) {
+        if matches.value_of("error-format") == Some("json") {
+            report_error_json(e);
+        } else {
+            report_error(e);
+        }
+        std::process::exit(1);
+    }
+}
 
+fn main_(matches: &clap::ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
     if let Some(ref matches) = matches.subcommand_matches("compile") {
         let filepath = matches.value_of("INPUT").unwrap();
-        runner::compile(filepath)?;
+        match matches.value_of("emit") {
+            Some(kind) => print!("{}", runner::emit(filepath, kind)?),
+            None if matches.is_present("from-hir") => {
+                runner::compile_hir(filepath, matches.is_present("verify"), jobs(matches)?)?
+            }
+            None => runner::compile_with_dumps(
+                filepath,
+                matches.is_present("verify"),
+                &dumps(matches),
+                matches.is_present("deny-warnings"),
+                &build_config(matches)?,
+                jobs(matches)?,
+            )?,
+        }
     }
 
     if let Some(ref matches) = matches.subcommand_matches("run") {
         let filepath = matches.value_of("INPUT").unwrap();
-        runner::compile(filepath)?;
-        runner::run(filepath)?;
+        runner::compile_with_dumps(
+            filepath,
+            matches.is_present("verify"),
+            &dumps(matches),
+            matches.is_present("deny-warnings"),
+            &build_config(matches)?,
+            jobs(matches)?,
+        )?;
+        if let Some(seed) = seed(matches)? {
+            std::env::set_var(runner::SEED_ENV, seed.to_string());
+        }
+        let status = runner::run(filepath, matches.is_present("static"))?;
+        if status != 0 {
+            std::process::exit(status);
+        }
+    }
+
+    if let Some(ref matches) = matches.subcommand_matches("fmt") {
+        let check = matches.is_present("check");
+        let mut unformatted = vec![];
+        for filepath in matches.values_of("INPUT").unwrap() {
+            if formatter::format_file(filepath, check)? {
+                unformatted.push(filepath);
+            }
+        }
+        if check && !unformatted.is_empty() {
+            return Err(Box::new(shiika::error::plain_runner_error(format!(
+                "not formatted: {}",
+                unformatted.join(", ")
+            ))));
+        }
+    }
+
+    if let Some(ref matches) = matches.subcommand_matches("conformance") {
+        let dir = matches.value_of("dir").unwrap();
+        let features = matches
+            .values_of("FEATURE")
+            .map(|v| v.collect::<Vec<_>>())
+            .unwrap_or_default();
+        let cases = conformance::collect_cases(std::path::Path::new(dir), &features)?;
+        let n_failed = conformance::run_suite(&cases, matches.value_of("runner"));
+        if n_failed > 0 {
+            return Err(Box::new(shiika::error::plain_runner_error(format!(
+                "{} conformance case(s) failed",
+                n_failed
+            ))));
+        }
+    }
+
+    if let Some(ref matches) = matches.subcommand_matches("test") {
+        let paths = matches.values_of("PATH").unwrap().collect::<Vec<_>>();
+        let files = test_runner::collect_test_files(&paths)?;
+        let seed = seed(matches)?.unwrap_or_else(test_runner::random_seed);
+        let (_, n_failed) = test_runner::run_tests(&files, seed);
+        if n_failed > 0 {
+            return Err(Box::new(shiika::error::plain_runner_error(format!(
+                "{} test(s) failed",
+                n_failed
+            ))));
+        }
+    }
+
+    if matches.subcommand_matches("lsp").is_some() {
+        shiika::ide::lsp::run()?;
+    }
+
+    if let Some(ref matches) = matches.subcommand_matches("explain") {
+        let code = matches.value_of("CODE").unwrap();
+        match error_codes::find(code) {
+            Some(entry) => println!("{}: {}\n\n{}", entry.code, entry.title, entry.explanation),
+            None => {
+                return Err(Box::new(shiika::error::plain_runner_error(format!(
+                    "unknown error code: {}",
+                    code
+                ))))
+            }
+        }
     }
 
     Ok(())
 }
+
+fn dumps(matches: &clap::ArgMatches) -> runner::Dumps {
+    runner::Dumps {
+        ast: matches.is_present("dump-ast"),
+        hir: matches.is_present("dump-hir"),
+        llvm: matches.is_present("dump-llvm"),
+        docs: matches.is_present("dump-docs"),
+    }
+}
+
+/// The configuration given by `--release`, `--define` and
+/// `--max-{parse,type}-nesting`
+fn build_config(matches: &clap::ArgMatches) -> Result<BuildConfig, Box<dyn std::error::Error>> {
+    let defines = matches
+        .values_of("define")
+        .into_iter()
+        .flatten()
+        .map(BuildConfig::parse_define)
+        .collect::<Result<Vec<_>, _>>()?;
+    let default = BuildConfig::default();
+    Ok(BuildConfig {
+        release: matches.is_present("release"),
+        defines,
+        max_parse_nesting: max_nesting(matches, "max-parse-nesting", default.max_parse_nesting)?,
+        max_type_nesting: max_nesting(matches, "max-type-nesting", default.max_type_nesting)?,
+        ..default
+    })
+}
+
+/// The nesting limit given by `--max-parse-nesting` or `--max-type-nesting`
+fn max_nesting(
+    matches: &clap::ArgMatches,
+    name: &str,
+    default: usize,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    match matches.value_of(name) {
+        Some(value) => match value.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(Box::new(shiika::error::plain_runner_error(format!(
+                "--{} must be a positive integer (given: {})",
+                name, value
+            )))),
+        },
+        None => Ok(default),
+    }
+}
+
+/// Number of the threads given by `--jobs`
+fn jobs(matches: &clap::ArgMatches) -> Result<usize, Box<dyn std::error::Error>> {
+    let value = matches.value_of("jobs").unwrap();
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(Box::new(shiika::error::plain_runner_error(format!(
+            "--jobs must be a positive integer (given: {})",
+            value
+        )))),
+    }
+}
+
+/// The seed given by `--seed`
+fn seed(matches: &clap::ArgMatches) -> Result<Option<i32>, Box<dyn std::error::Error>> {
+    match matches.value_of("seed") {
+        Some(value) => match value.parse::<i32>() {
+            Ok(n) => Ok(Some(n)),
+            _ => Err(Box::new(shiika::error::plain_runner_error(format!(
+                "--seed must be an integer (given: {})",
+                value
+            )))),
+        },
+        None => Ok(None),
+    }
+}
+
+fn report_error(e: Box<dyn std::error::Error>) {
+    match e.downcast_ref::<Error>() {
+        Some(err) => {
+            let msg = if use_color() {
+                shiika::error::colorize_message(&err.msg)
+            } else {
+                err.msg.replace("**", "")
+            };
+            eprintln!("error[{}]: {}", err.code(), msg);
+            if let Some(span) = &err.span {
+                eprintln!(
+                    "  --> line {}:{}",
+                    span.begin.line() + 1,
+                    span.begin.col() + 1
+                );
+            }
+            let is_bug = if let ErrorDetails::Bug = err.details {
+                true
+            } else {
+                false
+            };
+            if is_bug || std::env::var("RUST_BACKTRACE").is_ok() {
+                eprintln!("{:?}", err.backtrace);
+            }
+            eprintln!(
+                "(For more information, try `shiika explain {}`)",
+                err.code()
+            );
+        }
+        None => eprintln!("error: {}", e),
+    }
+}
+
+/// Print the error as a JSON object (`--error-format=json`)
+fn report_error_json(e: Box<dyn std::error::Error>) {
+    match e.downcast_ref::<Error>() {
+        Some(err) => eprintln!("{}", err.to_json()),
+        None => eprintln!(
+            "{}",
+            shiika::error::plain_runner_error(e.to_string()).to_json()
+        ),
+    }
+}
+
+/// Whether to print colored messages (only to a terminal)
+fn use_color() -> bool {
+    if std::env::var("NO_COLOR").is_ok() || !atty::is(atty::Stream::Stderr) {
+        return false;
+    }
+    match std::env::var("TERM") {
+        Ok(term) => term != "dumb",
+        Err(_) => false,
+    }
+}
@@ -61,6 +61,47 @@ impl ClassFullname {
     pub fn meta_name(&self) -> ClassFullname {
         ClassFullname("Meta:".to_string() + &self.0)
     }
+
+    /// Return the namespaces to look up names in this namespace,
+    /// innermost first
+    /// eg. `A::B` -> `["A::B", "A", ""]`
+    pub fn lexical_scopes(&self) -> Vec<String> {
+        let mut scopes = vec![];
+        let mut s = self.0.as_str();
+        while !s.is_empty() {
+            scopes.push(s.to_string());
+            s = match s.rfind("::") {
+                Some(i) => &s[..i],
+                None => "",
+            };
+        }
+        scopes.push("".to_string());
+        scopes
+    }
+
+    /// Return the namespace this class is defined in
+    /// eg. `A::B` -> `A`
+    pub fn namespace(&self) -> ClassFullname {
+        match self.0.rfind("::") {
+            Some(i) => class_fullname(&self.0[..i]),
+            None => class_fullname(""),
+        }
+    }
+}
+
+/// Resolve the class `name` (may be qualified like `B::C`) written in
+/// `namespace`, searching from the innermost scope outward.
+/// Returns None if not found
+pub fn resolve_class_name(
+    name: &str,
+    namespace: &ClassFullname,
+    class_exists: &dyn Fn(&ClassFullname) -> bool,
+) -> Option<ClassFullname> {
+    namespace
+        .lexical_scopes()
+        .iter()
+        .map(|scope| class_firstname(name).add_namespace(scope))
+        .find(|fullname| class_exists(fullname))
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -80,6 +121,12 @@ impl MethodFirstname {
     pub fn append(&self, suffix: &str) -> MethodFirstname {
         MethodFirstname(self.0.clone() + suffix)
     }
+
+    /// The name of the overload which takes `arity` args (eg. `log/2`; see
+    /// parser/overloading.rs)
+    pub fn overloaded(&self, arity: usize) -> MethodFirstname {
+        self.append(&format!("/{}", arity))
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -126,3 +173,13 @@ impl std::fmt::Display for ConstFullname {
 pub fn const_fullname(s: &str) -> ConstFullname {
     ConstFullname(s.to_string())
 }
+
+/// Return the fullname of the constant `name` defined in `namespace`
+/// eg. `::A::B::C` for `C` in `A::B`
+pub fn const_fullname_in(namespace: &str, name: &str) -> ConstFullname {
+    if namespace.is_empty() {
+        ConstFullname(format!("::{}", name))
+    } else {
+        ConstFullname(format!("::{}::{}", namespace, name))
+    }
+}
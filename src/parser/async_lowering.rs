@@ -0,0 +1,149 @@
+/// Lowering of `async def` and `await`
+///
+/// An async method does not return a value but takes the continuation
+/// (what the caller does with the value) as the last param:
+///
+/// ```text
+///   async def foo(x: Int) -> Int        def foo(x: Int, ` k': Fn1<Int, Void>)
+///     y = await bar(x)          =>        bar(x, fn(y: Int){
+///     y + 1                                 ` k'.call(y + 1)
+///   end                                   })
+///                                       end
+/// ```
+///
+/// `await` takes a call of a method whose last param is a callback
+/// (`Fn0<Void>` or `Fn1<T, Void>`) and passes the statements after it as
+/// the callback. This is `AwaitCall` in the AST because the type of the
+/// param (`T`) is not known until the method is looked up in hir_maker.
+///
+/// `await` must be a statement (`await x`, `y = await x` or
+/// `var y = await x`) of an async method or the toplevel. The toplevel
+/// ends with `Async.run` if it has `await`.
+use crate::ast::*;
+
+/// Name of the param for the value of `await` in the generated code
+const TMP_VAR: &str = " r";
+
+/// Make `sig` take the continuation instead of returning the value.
+/// Returns true if the continuation takes a value
+pub(super) fn async_signature(sig: &mut AstMethodSignature) -> bool {
+    let void = Typ {
+        name: "Void".to_string(),
+        typ_args: vec![],
+    };
+    let has_value = sig.ret_typ != void;
+    let typ = if has_value {
+        Typ {
+            name: "Fn1".to_string(),
+            typ_args: vec![sig.ret_typ.clone(), void.clone()],
+        }
+    } else {
+        Typ {
+            name: "Fn0".to_string(),
+            typ_args: vec![void.clone()],
+        }
+    };
+    sig.params.push(Param {
+        name: ASYNC_CONT_PARAM.to_string(),
+        typ,
+        implicit_conv: false,
+    });
+    sig.ret_typ = void;
+    has_value
+}
+
+/// Convert the body of an async method
+pub(super) fn lower_async_body(exprs: Vec<AstExpression>, has_value: bool) -> Vec<AstExpression> {
+    lower(exprs, Some(has_value))
+}
+
+/// Convert the toplevel statements if they have `await`
+pub(super) fn lower_toplevel(items: Vec<TopLevelItem>) -> Vec<TopLevelItem> {
+    let has_await = items.iter().any(|item| match item {
+        TopLevelItem::Expr(expr) => split_await(expr).is_some(),
+        _ => false,
+    });
+    if !has_await {
+        return items;
+    }
+    // Definitions (including the constants) are not a part of the
+    // continuations
+    let mut new_items = vec![];
+    let mut exprs = vec![];
+    for item in items {
+        match item {
+            TopLevelItem::Expr(AstExpression {
+                body: AstExpressionBody::ConstAssign { .. },
+                ..
+            }) => new_items.push(item),
+            TopLevelItem::Expr(expr) => exprs.push(expr),
+            TopLevelItem::Def(_) => new_items.push(item),
+        }
+    }
+    let mut exprs = lower(exprs, None);
+    exprs.push(method_call(
+        Some(const_ref(vec!["Async".to_string()])),
+        "run",
+        vec![],
+        false,
+        false,
+    ));
+    new_items.extend(exprs.into_iter().map(TopLevelItem::Expr));
+    new_items
+}
+
+/// Replace the first `await` in `exprs` with `AwaitCall` (and then the
+/// ones in its continuation.) `cont` is `Some(has_value)` in async methods
+fn lower(mut exprs: Vec<AstExpression>, cont: Option<bool>) -> Vec<AstExpression> {
+    let idx = match exprs.iter().position(|expr| split_await(expr).is_some()) {
+        Some(i) => i,
+        None => return finish(exprs, cont),
+    };
+    let mut rest = exprs.split_off(idx + 1);
+    let stmt = exprs.pop().unwrap();
+    let (call, var_name, is_var) = split_await(&stmt).unwrap();
+    let param = match var_name {
+        // `var x = await ...` (params are readonly)
+        Some(name) if is_var => {
+            rest.insert(0, lvar_decl(name.to_string(), bare_name(name)));
+            Some(name.to_string())
+        }
+        Some(name) => Some(name.to_string()),
+        // `await ...` as the last statement gives the value of the method
+        None if rest.is_empty() && cont == Some(true) => {
+            rest.push(bare_name(TMP_VAR));
+            Some(TMP_VAR.to_string())
+        }
+        None => None,
+    };
+    exprs.push(await_call(call.clone(), param, lower(rest, cont)));
+    exprs
+}
+
+/// Pass the value to the continuation of the async method
+fn finish(mut exprs: Vec<AstExpression>, cont: Option<bool>) -> Vec<AstExpression> {
+    let k = bare_name(ASYNC_CONT_PARAM);
+    match cont {
+        Some(true) => {
+            // Missing value is reported as the wrong number of the args
+            let args = exprs.pop().into_iter().collect();
+            exprs.push(method_call(Some(k), "call", args, false, false));
+        }
+        Some(false) => exprs.push(method_call(Some(k), "call", vec![], false, false)),
+        None => (),
+    }
+    exprs
+}
+
+/// Return the call, the name of the variable and whether it is declared
+/// with `var` if `expr` is an `await` statement
+fn split_await(expr: &AstExpression) -> Option<(&AstExpression, Option<&str>, bool)> {
+    match &expr.body {
+        AstExpressionBody::Await { expr } => Some((expr, None, false)),
+        AstExpressionBody::LVarAssign { name, rhs, is_var } => match &rhs.body {
+            AstExpressionBody::Await { expr } => Some((expr, Some(name), *is_var)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
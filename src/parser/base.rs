@@ -101,6 +101,47 @@ impl<'a> Parser<'a> {
         self.lexer.cur.clone()
     }
 
+    /// Return the span from `begin` to the current position
+    pub(super) fn span_from(&self, begin: Cursor) -> Span {
+        Span {
+            begin,
+            end: self.current_position(),
+        }
+    }
+
+    /// Set the span from `begin` to the current position to `expr`, unless
+    /// it already has one (ie. it is an inner expression as a whole)
+    pub(super) fn set_span(&self, expr: AstExpression, begin: Cursor) -> AstExpression {
+        if expr.span.is_some() {
+            expr
+        } else {
+            expr.with_span(self.span_from(begin))
+        }
+    }
+
+    /// Return the doc comment of the definition at the current position,
+    /// which is the `##` lines right above the line (without `##` and a
+    /// space after it.) Lines starting with `###` are not doc comments
+    pub(super) fn doc_comment(&self) -> Option<String> {
+        let src = self.lexer.src;
+        let line_begin = src[..self.lexer.cur.pos()].rfind('\n').map_or(0, |i| i + 1);
+        let mut lines = src[..line_begin]
+            .lines()
+            .rev()
+            .map(|line| line.trim_start())
+            .take_while(|line| line.starts_with("##") && !line.starts_with("###"))
+            .map(|line| {
+                let text = &line[2..];
+                text.strip_prefix(' ').unwrap_or(text).trim_end()
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
     /// Rewind lexer position (backtrack)
     pub(super) fn rewind_to(&mut self, cur: Cursor) {
         self.lexer.set_position(cur);
@@ -110,14 +151,61 @@ impl<'a> Parser<'a> {
         self.lexer.set_state(state);
     }
 
+    /// Call `f` one nesting level deeper. Returns error if it is too deep.
+    /// The level is restored even if `f` fails
+    pub(super) fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.nesting >= self.max_nesting {
+            return Err(parse_error!(
+                self,
+                "program too deeply nested (max: {})",
+                self.max_nesting
+            ));
+        }
+        self.nesting += 1;
+        let result = f(self);
+        self.nesting -= 1;
+        result
+    }
+
     pub(super) fn parseerror(&self, msg: &str) -> Error {
+        let span = Span {
+            begin: self.lexer.cur.clone(),
+            end: self.lexer.cur.clone(),
+        };
+        self.parseerror_at(msg, span)
+    }
+
+    /// Parse error at `span` (instead of the current position)
+    pub(super) fn parseerror_at(&self, msg: &str, span: Span) -> Error {
+        // The lexer has stopped at an error and the parser found the `Eof`
+        // it returns instead of the rest of the program
+        if let Some(e) = &self.lexer.error {
+            return self.lex_error(e);
+        }
         Error {
             msg: msg.to_string(),
             backtrace: backtrace::Backtrace::new(),
             details: ErrorDetails::ParseError {
-                location: self.lexer.cur.clone(),
+                location: span.begin.clone(),
+            },
+            source: None,
+            span: Some(span),
+        }
+    }
+
+    /// Convert the error of the lexer (see `LexError`)
+    pub(super) fn lex_error(&self, e: &LexError) -> Error {
+        Error {
+            msg: e.msg.clone(),
+            backtrace: backtrace::Backtrace::new(),
+            details: ErrorDetails::ParseError {
+                location: e.span.begin.clone(),
             },
             source: None,
+            span: Some(e.span.clone()),
         }
     }
 
@@ -0,0 +1,97 @@
+/// Data classes
+///
+/// `class Point(x: Int, y: Int)` declares a class with these definitions:
+///
+/// ```text
+///   @derive(Eq, Hash, ToS)
+///   def initialize(x: Int, y: Int)
+///     @x = x
+///     @y = y
+///   end
+///   def with_x(x: Int) -> Point    # Returns a copy with another `x`
+///     Point.new(x, @y)
+///   end
+///   def with_y(y: Int) -> Point
+///     Point.new(@x, y)
+///   end
+/// ```
+///
+/// The ivars have readers like any other class, and the types of the
+/// params must have `==` and `to_s` (see hir/derive.rs; all types have
+/// `hash`.) A data class has no body; write the definitions above to add
+/// methods.
+use crate::ast::*;
+use crate::names::*;
+
+/// Return the definitions of the data class
+pub(super) fn data_class_definitions(
+    name: &ClassFirstname,
+    typarams: &[String],
+    params: &[Param],
+) -> Vec<Definition> {
+    let mut defs = vec![
+        Definition::Derive {
+            traits: vec!["Eq".to_string(), "Hash".to_string(), "ToS".to_string()],
+            span: Default::default(),
+        },
+        Definition::InstanceMethodDefinition {
+            sig: AstMethodSignature {
+                name: method_firstname("initialize"),
+                name_span: Default::default(),
+                typarams: vec![],
+                params: params.to_vec(),
+                ret_typ: Typ {
+                    name: "Void".to_string(),
+                    typ_args: vec![],
+                },
+                doc: None,
+            },
+            body_exprs: params
+                .iter()
+                .map(|param| assignment(ivar_ref(param.name.clone()), bare_name(&param.name)))
+                .collect(),
+        },
+    ];
+
+    let self_typ = Typ {
+        name: name.0.clone(),
+        typ_args: typarams
+            .iter()
+            .map(|s| Typ {
+                name: s.clone(),
+                typ_args: vec![],
+            })
+            .collect(),
+    };
+    for param in params {
+        let args = params
+            .iter()
+            .map(|p| {
+                if p.name == param.name {
+                    bare_name(&p.name)
+                } else {
+                    ivar_ref(p.name.clone())
+                }
+            })
+            .collect();
+        let new = method_call(
+            Some(const_ref(vec![name.0.clone()])),
+            "new",
+            args,
+            true,
+            false,
+        );
+        defs.push(Definition::InstanceMethodDefinition {
+            sig: AstMethodSignature {
+                name: method_firstname(&format!("with_{}", param.name)),
+                name_span: Default::default(),
+                typarams: vec![],
+                params: vec![param.clone()],
+                ret_typ: self_typ.clone(),
+                doc: None,
+            },
+            body_exprs: vec![new],
+        });
+    }
+    defs
+}
@@ -1,5 +1,8 @@
 use super::super::ast;
+use super::async_lowering;
 use super::base::*;
+use super::data_class;
+use super::overloading;
 use super::Parser; // REFACTOR: use crate:: instead of super
 use crate::names::*;
 
@@ -16,8 +19,12 @@ impl<'a> Parser<'a> {
     fn parse_definition(&mut self) -> Result<Option<ast::Definition>, Error> {
         match self.current_token() {
             Token::KwClass => Ok(Some(self.parse_class_definition()?)),
-            Token::KwDef => Ok(Some(self.parse_method_definition()?)),
+            Token::KwDef | Token::KwAsync => Ok(Some(self.parse_method_definition()?)),
+            Token::KwAbstract => Ok(Some(self.parse_abstract_method_definition()?)),
+            Token::KwExtern => Ok(Some(self.parse_extern_method_definition()?)),
             Token::UpperWord(_) => Ok(Some(self.parse_const_definition()?)),
+            Token::IVar(s) if s == "delegate" => Ok(Some(self.parse_delegation()?)),
+            Token::IVar(s) if s == "derive" => Ok(Some(self.parse_derive()?)),
             _ => Ok(None),
         }
     }
@@ -25,9 +32,16 @@ impl<'a> Parser<'a> {
     pub fn parse_class_definition(&mut self) -> Result<ast::Definition, Error> {
         self.debug_log("parse_class_definition");
         self.lv += 1;
+        let def = self.nested(Self::parse_class_definition_)?;
+        self.lv -= 1;
+        Ok(def)
+    }
+
+    fn parse_class_definition_(&mut self) -> Result<ast::Definition, Error> {
+        let doc = self.doc_comment();
         let name;
+        let name_span;
         let mut typarams = vec![];
-        let defs;
 
         // `class'
         assert!(self.consume(Token::KwClass));
@@ -37,7 +51,9 @@ impl<'a> Parser<'a> {
         match self.current_token() {
             Token::UpperWord(s) => {
                 name = class_firstname(s);
+                let begin = self.current_position();
                 self.consume_token();
+                name_span = self.span_from(begin);
             }
             token => {
                 return Err(parse_error!(
@@ -50,32 +66,15 @@ impl<'a> Parser<'a> {
 
         // Type parameters (optional)
         if self.current_token_is(Token::LessThan) {
+            typarams = self.parse_typarams()?;
+        }
+
+        // Params of a data class (optional)
+        let mut params = None;
+        if self.current_token_is(Token::LParen) {
             self.consume_token();
             self.skip_wsn();
-            loop {
-                match self.current_token() {
-                    Token::GreaterThan => {
-                        self.consume_token();
-                        break;
-                    }
-                    Token::UpperWord(s) => {
-                        typarams.push(s.to_string());
-                        self.consume_token();
-                        self.skip_wsn();
-                    }
-                    Token::Comma => {
-                        self.consume_token();
-                        self.skip_wsn();
-                    }
-                    token => {
-                        return Err(parse_error!(
-                            self,
-                            "unexpected token `{:?}' in type parameter definition",
-                            token
-                        ))
-                    }
-                }
-            }
+            params = Some(self.parse_params()?);
         }
 
         // Superclass name (optional)
@@ -86,8 +85,27 @@ impl<'a> Parser<'a> {
             self.skip_wsn();
             match self.current_token() {
                 Token::UpperWord(s) => {
-                    super_name = class_fullname(s);
+                    // May be qualified (eg. `A::B`)
+                    let mut name = s.to_string();
                     self.consume_token();
+                    while self.current_token_is(Token::ColonColon) {
+                        self.consume_token();
+                        match self.current_token() {
+                            Token::UpperWord(s) => {
+                                name += "::";
+                                name += s;
+                                self.consume_token();
+                            }
+                            token => {
+                                return Err(parse_error!(
+                                    self,
+                                    "superclass name must start with A-Z but got {:?}",
+                                    token
+                                ))
+                            }
+                        }
+                    }
+                    super_name = class_fullname(name);
                 }
                 token => {
                     return Err(parse_error!(
@@ -101,8 +119,22 @@ impl<'a> Parser<'a> {
 
         self.expect_sep()?;
 
+        // A data class has no body
+        if let Some(params) = params {
+            return Ok(ast::Definition::ClassDefinition {
+                defs: data_class::data_class_definitions(&name, &typarams, &params),
+                name,
+                name_span,
+                typarams,
+                super_name,
+                doc,
+            });
+        }
+
         // Internal definitions
-        defs = self.parse_definitions()?;
+        let mut defs = self.parse_definitions()?;
+        overloading::rename_overloads(&mut defs)
+            .map_err(|(msg, span)| self.parseerror_at(&msg, span))?;
 
         // `end'
         match self.current_token() {
@@ -119,29 +151,50 @@ impl<'a> Parser<'a> {
             }
         }
 
-        self.lv -= 1;
         Ok(ast::Definition::ClassDefinition {
             name,
+            name_span,
             typarams,
             super_name,
             defs,
+            doc,
         })
     }
 
     pub fn parse_method_definition(&mut self) -> Result<ast::Definition, Error> {
         self.debug_log("parse_method_definition");
         self.lv += 1;
+        // `async' (optional)
+        let is_async = self.consume(Token::KwAsync);
+        if is_async {
+            self.skip_ws();
+        }
+
         // `def'
         self.set_lexer_state(LexerState::MethodName);
-        assert!(self.consume(Token::KwDef));
+        if !self.consume(Token::KwDef) {
+            return Err(parse_error!(
+                self,
+                "expected `def' after `async' but got {:?}",
+                self.current_token()
+            ));
+        }
         self.skip_ws();
 
         // `foo(bar) -> Baz`
-        let (sig, is_class_method) = self.parse_method_signature()?;
+        let (mut sig, is_class_method) = self.parse_method_signature()?;
         self.expect_sep()?;
+        let has_value = if is_async {
+            Some(async_lowering::async_signature(&mut sig))
+        } else {
+            None
+        };
 
         // Body (optional)
-        let body_exprs = self.parse_exprs(vec![Token::KwEnd])?;
+        let mut body_exprs = self.parse_exprs(vec![Token::KwEnd])?;
+        if let Some(has_value) = has_value {
+            body_exprs = async_lowering::lower_async_body(body_exprs, has_value);
+        }
 
         // `end'
         self.skip_wsn();
@@ -167,11 +220,176 @@ impl<'a> Parser<'a> {
         }
     }
 
+    pub fn parse_abstract_method_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_abstract_method_definition");
+        self.lv += 1;
+        // `abstract'
+        assert!(self.consume(Token::KwAbstract));
+        self.skip_ws();
+
+        // `def'
+        self.set_lexer_state(LexerState::MethodName);
+        if !self.consume(Token::KwDef) {
+            return Err(parse_error!(
+                self,
+                "expected `def' after `abstract' but got {:?}",
+                self.current_token()
+            ));
+        }
+        self.skip_ws();
+
+        // `foo(bar) -> Baz`
+        let (sig, is_class_method) = self.parse_method_signature()?;
+        if is_class_method {
+            return Err(parse_error!(
+                self,
+                "class method cannot be abstract ({:?})",
+                sig.name
+            ));
+        }
+        self.expect_sep()?;
+
+        self.lv -= 1;
+        Ok(ast::Definition::AbstractMethodDefinition { sig })
+    }
+
+    pub fn parse_extern_method_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_extern_method_definition");
+        self.lv += 1;
+        // `extern'
+        assert!(self.consume(Token::KwExtern));
+        self.skip_ws();
+
+        // `def'
+        self.set_lexer_state(LexerState::MethodName);
+        if !self.consume(Token::KwDef) {
+            return Err(parse_error!(
+                self,
+                "expected `def' after `extern' but got {:?}",
+                self.current_token()
+            ));
+        }
+        self.skip_ws();
+
+        // `foo(bar) -> Baz`
+        let (sig, is_class_method) = self.parse_method_signature()?;
+        if is_class_method {
+            return Err(parse_error!(
+                self,
+                "extern method is always a class method; remove `self.' ({:?})",
+                sig.name
+            ));
+        }
+        self.expect_sep()?;
+
+        self.lv -= 1;
+        Ok(ast::Definition::ExternMethodDefinition { sig })
+    }
+
+    /// `@delegate(to: @foo)`
+    pub fn parse_delegation(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_delegation");
+        self.lv += 1;
+        // `@delegate(`
+        self.consume_token();
+        self.expect(Token::LParen)?;
+        self.skip_wsn();
+
+        // `to:`
+        match self.current_token() {
+            Token::LowerWord(s) if s == "to" => {
+                self.consume_token();
+            }
+            token => return Err(parse_error!(self, "expected `to:' but got {:?}", token)),
+        }
+        self.expect(Token::Colon)?;
+        self.skip_wsn();
+
+        // `@foo`
+        let begin = self.current_position();
+        let ivar_name = match self.current_token() {
+            Token::IVar(s) => s.to_string(),
+            token => {
+                return Err(parse_error!(
+                    self,
+                    "expected an instance variable but got {:?}",
+                    token
+                ))
+            }
+        };
+        self.consume_token();
+        let ivar_span = self.span_from(begin);
+        self.skip_wsn();
+
+        // `)`
+        self.expect(Token::RParen)?;
+        self.expect_sep()?;
+
+        self.lv -= 1;
+        Ok(ast::Definition::Delegation {
+            ivar_name,
+            ivar_span,
+        })
+    }
+
+    pub fn parse_derive(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_derive");
+        self.lv += 1;
+        let begin = self.current_position();
+        // `@derive(`
+        self.consume_token();
+        self.expect(Token::LParen)?;
+        self.skip_wsn();
+
+        // `Eq, Hash`
+        let mut traits = vec![];
+        loop {
+            match self.current_token() {
+                Token::UpperWord(s) => {
+                    traits.push(s.to_string());
+                    self.consume_token();
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "expected a trait name but got {:?}",
+                        token
+                    ))
+                }
+            }
+            self.skip_wsn();
+            match self.current_token() {
+                Token::Comma => {
+                    self.consume_token();
+                    self.skip_wsn();
+                }
+                Token::RParen => break,
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "expected `,' or `)' but got {:?}",
+                        token
+                    ))
+                }
+            }
+        }
+
+        // `)`
+        self.expect(Token::RParen)?;
+        let span = self.span_from(begin);
+        self.expect_sep()?;
+
+        self.lv -= 1;
+        Ok(ast::Definition::Derive { traits, span })
+    }
+
     pub fn parse_method_signature(&mut self) -> Result<(ast::AstMethodSignature, bool), Error> {
         let mut name = None;
         let params;
         let ret_typ;
         let mut is_class_method = false;
+        let mut begin = self.current_position();
+        let doc = self.doc_comment();
 
         // `self.` (Optional)
         if self.consume(Token::KwSelf) {
@@ -179,6 +397,7 @@ impl<'a> Parser<'a> {
                 is_class_method = true;
                 self.set_lexer_state(LexerState::MethodName);
                 self.consume_token();
+                begin = self.current_position();
             } else {
                 // Defining a method named `self` :thinking_face:
                 name = Some(method_firstname("self"));
@@ -190,6 +409,13 @@ impl<'a> Parser<'a> {
             name = Some(method_firstname(self.get_method_name()?));
             self.consume_token();
         }
+        let name_span = self.span_from(begin);
+
+        // Type parameters (optional)
+        let mut typarams = vec![];
+        if self.current_token_is(Token::LessThan) {
+            typarams = self.parse_typarams()?;
+        }
         self.skip_ws();
 
         // Params (optional)
@@ -224,12 +450,47 @@ impl<'a> Parser<'a> {
 
         let sig = ast::AstMethodSignature {
             name: name.unwrap(),
+            name_span,
+            typarams,
             params,
             ret_typ,
+            doc,
         };
         Ok((sig, is_class_method))
     }
 
+    /// Parse type parameters (eg. `<S, T>`)
+    fn parse_typarams(&mut self) -> Result<Vec<String>, Error> {
+        let mut typarams = vec![];
+        assert!(self.consume(Token::LessThan));
+        self.skip_wsn();
+        loop {
+            match self.current_token() {
+                Token::GreaterThan => {
+                    self.consume_token();
+                    break;
+                }
+                Token::UpperWord(s) => {
+                    typarams.push(s.to_string());
+                    self.consume_token();
+                    self.skip_wsn();
+                }
+                Token::Comma => {
+                    self.consume_token();
+                    self.skip_wsn();
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "unexpected token `{:?}' in type parameter definition",
+                        token
+                    ))
+                }
+            }
+        }
+        Ok(typarams)
+    }
+
     fn get_method_name(&mut self) -> Result<&str, Error> {
         let name = match self.current_token() {
             Token::LowerWord(s) => s,
@@ -250,7 +511,11 @@ impl<'a> Parser<'a> {
             Token::GreaterThan => ">",
             Token::GreaterEq => ">=",
             Token::EqEq => "==",
+            Token::EqTilde => "=~",
             Token::NotEq => "!=",
+            Token::Spaceship => "<=>",
+            Token::GetMethod => "[]",
+            Token::SetMethod => "[]=",
             token => return Err(parse_error!(self, "invalid method name {:?}", token)),
         };
         Ok(name)
@@ -321,13 +586,23 @@ impl<'a> Parser<'a> {
         self.expect(Token::Colon)?;
         self.skip_ws();
 
+        // `~' (Optional)
+        let implicit_conv = self.consume(Token::Tilde);
+
         // Type
         let typ = self.parse_typ()?;
 
-        Ok(ast::Param { name, typ })
+        Ok(ast::Param {
+            name,
+            typ,
+            implicit_conv,
+        })
     }
 
     fn parse_typ(&mut self) -> Result<ast::Typ, Error> {
+        if self.current_token_is(Token::LParen) {
+            return self.parse_tuple_typ();
+        }
         let mut name = String::new();
         loop {
             match self.current_token() {
@@ -358,7 +633,45 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_typ_args(&mut self) -> Result<Vec<ast::Typ>, Error> {
+    /// Parse `(A, B)` (the type of tuples)
+    fn parse_tuple_typ(&mut self) -> Result<ast::Typ, Error> {
+        assert!(self.consume(Token::LParen));
+        let mut typ_args = vec![];
+        loop {
+            self.skip_wsn();
+            typ_args.push(self.parse_typ()?);
+            self.skip_wsn();
+            match self.current_token() {
+                Token::Comma => {
+                    self.consume_token();
+                }
+                Token::RParen => {
+                    self.consume_token();
+                    break;
+                }
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "expected `,' or `)' but got {:?}",
+                        token
+                    ))
+                }
+            }
+        }
+        if typ_args.len() < 2 || typ_args.len() > ast::MAX_TUPLE_LEN {
+            return Err(parse_error!(
+                self,
+                "a tuple type must have 2 to {} types",
+                ast::MAX_TUPLE_LEN
+            ));
+        }
+        Ok(ast::Typ {
+            name: ast::tuple_class_name(typ_args.len()),
+            typ_args,
+        })
+    }
+
+    pub(super) fn parse_typ_args(&mut self) -> Result<Vec<ast::Typ>, Error> {
         let mut typ_args = vec![];
         loop {
             self.skip_wsn();
@@ -393,7 +706,9 @@ impl<'a> Parser<'a> {
             }
             _ => panic!("must be called on an UpperWord"),
         }
+        let begin = self.current_position();
         self.consume_token();
+        let name_span = self.span_from(begin);
 
         self.skip_wsn();
         self.expect(Token::Equal)?;
@@ -402,6 +717,10 @@ impl<'a> Parser<'a> {
         let expr = self.parse_expr()?;
 
         self.lv -= 1;
-        Ok(ast::Definition::ConstDefinition { name, expr })
+        Ok(ast::Definition::ConstDefinition {
+            name,
+            name_span,
+            expr,
+        })
     }
 }
@@ -0,0 +1,98 @@
+/// An invalid escape sequence found by `unescape`
+#[derive(Debug, PartialEq)]
+pub(super) struct InvalidEscape {
+    pub msg: String,
+    /// Byte offsets of the escape sequence in the given string
+    pub begin: usize,
+    pub end: usize,
+}
+
+/// Replace the escape sequences in `s` with the characters:
+///
+/// ```text
+///   \n  newline           \t  tab           \r  carriage return
+///   \0  null              \"  "             \\  \
+///   \`  ` (for command literals)
+///   \u{3042}  the character of the code point (1 to 6 hex digits)
+/// ```
+///
+/// The lexer keeps the backslashes (it only skips the character after a
+/// backslash so that `\"` does not end the string)
+pub(super) fn unescape(s: &str) -> Result<String, InvalidEscape> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.char_indices().peekable();
+    while let Some((begin, c)) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let (i, c) = match chars.next() {
+            Some(x) => x,
+            None => {
+                return Err(InvalidEscape {
+                    msg: "string ends with `\\'".to_string(),
+                    begin,
+                    end: s.len(),
+                })
+            }
+        };
+        let end = i + c.len_utf8();
+        match c {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '0' => out.push('\0'),
+            '"' => out.push('"'),
+            '`' => out.push('`'),
+            '\\' => out.push('\\'),
+            'u' => {
+                let (value, end) = unicode_escape(s, begin, end)?;
+                out.push(value);
+                while chars.peek().map_or(false, |(i, _)| *i < end) {
+                    chars.next();
+                }
+            }
+            _ => {
+                return Err(InvalidEscape {
+                    msg: format!("unknown escape sequence `\\{}'", c),
+                    begin,
+                    end,
+                })
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Read `{3042}` at `i` (after `\u` which begins at `begin`.) Returns the
+/// character and the end of the escape sequence
+fn unicode_escape(s: &str, begin: usize, i: usize) -> Result<(char, usize), InvalidEscape> {
+    let rest = &s[i..];
+    let close = match rest.find('}') {
+        Some(close) if rest.starts_with('{') => close,
+        _ => {
+            return Err(InvalidEscape {
+                msg: "`\\u' must be followed by `{code point}' (eg. `\\u{3042}')".to_string(),
+                begin,
+                end: i,
+            })
+        }
+    };
+    let end = i + close + 1;
+    let digits = &rest[1..close];
+    let invalid = |msg: String| InvalidEscape { msg, begin, end };
+    if digits.is_empty() || digits.len() > 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid(format!(
+            "invalid code point `{}' (1 to 6 hex digits expected)",
+            digits
+        )));
+    }
+    let code = u32::from_str_radix(digits, 16).unwrap();
+    match std::char::from_u32(code) {
+        Some(c) => Ok((c, end)),
+        None => Err(invalid(format!(
+            "`\\u{{{}}}' is not a Unicode scalar value",
+            digits
+        ))),
+    }
+}
@@ -1,4 +1,5 @@
 use crate::parser::base::*;
+use crate::parser::escape;
 use std::collections::HashMap;
 
 impl<'a> Parser<'a> {
@@ -18,12 +19,102 @@ impl<'a> Parser<'a> {
                 if expr_seen {
                     self.expect_sep()?; // Missing separator between exprs
                 }
-                ret.push(self.parse_expr()?);
+                ret.push(self.parse_stmt()?);
                 expr_seen = true;
             }
         }
     }
 
+    /// Parse an expression or a multiple assignment (`a, b = tuple`,
+    /// which is only allowed as a statement) followed by modifiers
+    /// (`foo if bar`, `foo unless bar`; same as `if bar then foo end`)
+    pub fn parse_stmt(&mut self) -> Result<AstExpression, Error> {
+        let begin = self.current_position();
+        let is_multiple_assignment = match self.current_token() {
+            Token::LowerWord(_) => self.peek_next_token() == Token::Comma,
+            _ => false,
+        };
+        let mut expr = if is_multiple_assignment {
+            self.parse_multiple_assignment()?
+        } else {
+            self.parse_expr()?
+        };
+        loop {
+            self.skip_ws();
+            let negated = match self.current_token() {
+                Token::KwIf => false,
+                Token::KwUnless => true,
+                _ => break,
+            };
+            self.lv += 1;
+            self.debug_log("parse_modifier");
+            self.consume_token();
+            self.skip_ws();
+            let cond_expr = self.parse_expr()?;
+            let cond_expr = if negated {
+                ast::logical_not(cond_expr)
+            } else {
+                cond_expr
+            };
+            expr = self.set_span(ast::if_expr(cond_expr, vec![expr], None), begin.clone());
+            self.lv -= 1;
+        }
+        Ok(expr)
+    }
+
+    fn parse_multiple_assignment(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_multiple_assignment");
+        let mut names = vec![];
+        loop {
+            match self.current_token() {
+                Token::LowerWord(s) => {
+                    names.push(s.to_string());
+                    self.consume_token();
+                }
+                token => return Err(parse_error!(self, "invalid var name: {:?}", token)),
+            }
+            self.skip_ws();
+            match self.current_token() {
+                Token::Comma => {
+                    self.consume_token();
+                    self.skip_ws();
+                }
+                Token::Equal => break,
+                token => {
+                    return Err(parse_error!(
+                        self,
+                        "expected `,' or `=' but got {:?}",
+                        token
+                    ))
+                }
+            }
+        }
+        self.expect(Token::Equal)?;
+        self.skip_wsn();
+        let mut rhs_exprs = vec![self.parse_operator_expr()?];
+        self.skip_ws();
+        // `a, b = b, a` (same as `a, b = (b, a)`)
+        while self.consume(Token::Comma) {
+            self.skip_wsn();
+            rhs_exprs.push(self.parse_operator_expr()?);
+            self.skip_ws();
+        }
+        let rhs = if rhs_exprs.len() == 1 {
+            rhs_exprs.pop().unwrap()
+        } else if rhs_exprs.len() <= ast::MAX_TUPLE_LEN {
+            ast::tuple_literal(rhs_exprs)
+        } else {
+            return Err(parse_error!(
+                self,
+                "a tuple can have at most {} elements",
+                ast::MAX_TUPLE_LEN
+            ));
+        };
+        self.lv -= 1;
+        Ok(ast::multiple_assignment(names, rhs))
+    }
+
     pub fn parse_expr(&mut self) -> Result<AstExpression, Error> {
         self.parse_var_decl()
     }
@@ -31,6 +122,7 @@ impl<'a> Parser<'a> {
     pub fn parse_var_decl(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_var_decl");
+        let begin = self.current_position();
         let expr;
         if self.current_token_is(Token::KwVar) {
             self.consume_token();
@@ -43,7 +135,7 @@ impl<'a> Parser<'a> {
                     self.expect(Token::Equal)?; // TODO: `+=` etc.
                     self.skip_wsn();
                     let rhs = self.parse_operator_expr()?;
-                    expr = ast::lvar_decl(name, rhs);
+                    expr = self.set_span(ast::lvar_decl(name, rhs), begin);
                 }
                 Token::IVar(s) => {
                     let name = s.to_string();
@@ -52,7 +144,7 @@ impl<'a> Parser<'a> {
                     self.expect(Token::Equal)?; // TODO: `+=` etc.
                     self.skip_wsn();
                     let rhs = self.parse_operator_expr()?;
-                    expr = ast::ivar_decl(name, rhs);
+                    expr = self.set_span(ast::ivar_decl(name, rhs), begin);
                 }
                 token => return Err(parse_error!(self, "invalid var name: {:?}", token)),
             }
@@ -66,6 +158,7 @@ impl<'a> Parser<'a> {
     pub fn parse_and_or_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_and_or_expr");
+        let begin = self.current_position();
         let mut expr = self.parse_not_expr()?;
         self.skip_ws();
         loop {
@@ -73,12 +166,14 @@ impl<'a> Parser<'a> {
                 Token::KwAnd => {
                     self.consume_token();
                     self.skip_wsn();
-                    expr = ast::logical_and(expr, self.parse_not_expr()?);
+                    let right = self.parse_not_expr()?;
+                    expr = self.set_span(ast::logical_and(expr, right), begin.clone());
                 }
                 Token::KwOr => {
                     self.consume_token();
                     self.skip_wsn();
-                    expr = ast::logical_or(expr, self.parse_not_expr()?);
+                    let right = self.parse_not_expr()?;
+                    expr = self.set_span(ast::logical_or(expr, right), begin.clone());
                 }
                 _ => break,
             }
@@ -91,18 +186,21 @@ impl<'a> Parser<'a> {
     fn parse_not_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_not_expr");
+        let begin = self.current_position();
+        // The operand is nested by `parse_operator_expr`, except `not`
+        // which is parsed here recursively
         let expr = match self.current_token() {
             Token::KwNot => {
                 self.consume_token();
                 self.skip_ws();
-                let inner = self.parse_not_expr()?;
-                ast::logical_not(inner)
+                let inner = self.nested(Self::parse_not_expr)?;
+                self.set_span(ast::logical_not(inner), begin)
             }
             Token::Bang => {
                 self.consume_token();
                 self.skip_ws();
                 let inner = self.parse_call_wo_paren()?;
-                ast::logical_not(inner)
+                self.set_span(ast::logical_not(inner), begin)
             }
             _ => self.parse_call_wo_paren()?,
         };
@@ -117,6 +215,7 @@ impl<'a> Parser<'a> {
     fn parse_call_wo_paren(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_call_wo_paren");
+        let begin = self.current_position();
 
         let token = self.current_token();
         if let Token::LowerWord(s) = token.clone() {
@@ -126,11 +225,12 @@ impl<'a> Parser<'a> {
                 self.consume_token();
                 self.set_lexer_state(LexerState::ExprArg);
                 assert!(self.consume(Token::Space));
-                let args = self.parse_operator_exprs()?;
+                let args = self.parse_command_args()?;
                 self.debug_log(&format!("tried/args: {:?}", args));
                 if !args.is_empty() {
                     self.lv -= 1;
-                    return Ok(ast::method_call(None, &s, args, false, false));
+                    let call = ast::method_call(None, &s, args, false, false);
+                    return Ok(self.set_span(call, begin));
                 }
                 self.rewind_to(cur)
             }
@@ -138,15 +238,24 @@ impl<'a> Parser<'a> {
         let mut expr = self.parse_operator_expr()?;
         if expr.may_have_paren_wo_args() {
             // foo bar, baz
-            let args = self.parse_operator_exprs()?;
+            let args = self.parse_command_args()?;
             if !args.is_empty() {
-                expr = ast::set_method_call_args(expr, args);
+                expr = self.set_span(ast::set_method_call_args(expr, args), begin);
             }
         }
         self.lv -= 1;
         Ok(expr)
     }
 
+    /// Parse the args of a method call without parens (`foo bar, baz`.)
+    /// `if` and `unless` here are a modifier (`foo if bar`), not an arg
+    fn parse_command_args(&mut self) -> Result<Vec<AstExpression>, Error> {
+        if self.next_nonspace_token().is_modifier() {
+            return Ok(vec![]);
+        }
+        self.parse_operator_exprs()
+    }
+
     fn parse_args(&mut self) -> Result<Vec<AstExpression>, Error> {
         self.lv += 1;
         self.debug_log("parse_args");
@@ -184,13 +293,31 @@ impl<'a> Parser<'a> {
     fn parse_operator_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_operator_expr");
-        let expr = self.parse_conditional_expr()?;
-        if expr.is_lhs() && self.next_nonspace_token() == Token::Equal {
-            self.parse_assignment_expr(expr)
-        } else {
-            self.lv -= 1;
-            Ok(expr)
-        }
+        let expr = self.nested(|p| {
+            if p.current_token_is(Token::KwAwait) {
+                p.parse_await_expr()
+            } else {
+                let expr = p.parse_conditional_expr()?;
+                if expr.is_lhs() && p.next_nonspace_token() == Token::Equal {
+                    p.parse_assignment_expr(expr)
+                } else {
+                    Ok(expr)
+                }
+            }
+        })?;
+        self.lv -= 1;
+        Ok(expr)
+    }
+
+    /// `await foo(bar)`, `await foo bar`
+    fn parse_await_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_await_expr");
+        assert!(self.consume(Token::KwAwait));
+        self.skip_ws();
+        let expr = self.parse_call_wo_paren()?;
+        self.lv -= 1;
+        Ok(ast::await_expr(expr))
     }
 
     // assignmentExpression:
@@ -200,6 +327,7 @@ impl<'a> Parser<'a> {
     fn parse_assignment_expr(&mut self, lhs: AstExpression) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_assignment_expr");
+        let begin = lhs.span.as_ref().map(|span| span.begin.clone());
 
         self.skip_ws();
         assert!(self.consume(Token::Equal)); // TODO: `+=` etc.
@@ -207,7 +335,11 @@ impl<'a> Parser<'a> {
         let rhs = self.parse_operator_expr()?;
 
         self.lv -= 1;
-        Ok(ast::assignment(lhs, rhs))
+        let expr = ast::assignment(lhs, rhs);
+        Ok(match begin {
+            Some(begin) => self.set_span(expr, begin),
+            None => expr,
+        })
     }
 
     /// `a ? b : c`
@@ -236,17 +368,19 @@ impl<'a> Parser<'a> {
     fn parse_range_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_range_expr");
-        let expr = self.parse_operator_or()?;
-        //        self.skip_ws();
-        //        match self.current_token() {
-        //            Token::symbol(s @ "..") | Token::symbol(s @ "...") => {
-        //                let inclusive = (s == "..");
-        //                self.skip_wsn();
-        //                let end_expr = self.parse_operator_or()?;
-        //                Ok(ast::range_expr(Some(expr), Some(end_expr), inclusive))
-        //            },
-        //            _ => Ok(expr)
-        //        }
+        let mut expr = self.parse_operator_or()?;
+        let exclusive = match self.next_nonspace_token() {
+            Token::DotDot => Some(false),
+            Token::DotDotDot => Some(true),
+            _ => None,
+        };
+        if let Some(exclusive) = exclusive {
+            self.skip_ws();
+            self.consume_token();
+            self.skip_wsn();
+            let last = self.parse_operator_or()?;
+            expr = ast::range_literal(expr, last, exclusive);
+        }
         self.lv -= 1;
         Ok(expr)
     }
@@ -295,15 +429,17 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    /// `==`, etc.
+    /// `==`, `<=>`, etc.
     fn parse_equality_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_equality_expr");
         let left = self.parse_relational_expr()?;
         let op = match self.next_nonspace_token() {
-            // TODO: <=> === =~ !~
+            // TODO: === !~
             Token::EqEq => "==",
+            Token::EqTilde => "=~",
             Token::NotEq => "!=",
+            Token::Spaceship => "<=>",
             _ => {
                 self.lv -= 1;
                 return Ok(left);
@@ -314,11 +450,11 @@ impl<'a> Parser<'a> {
         self.consume_token();
         self.skip_wsn();
         let right = self.parse_relational_expr()?;
-        let call_eq = ast::method_call(Some(left), "==", vec![right], false, false);
         let expr = if op == "!=" {
+            let call_eq = ast::method_call(Some(left), "==", vec![right], false, false);
             ast::logical_not(call_eq)
         } else {
-            call_eq
+            ast::method_call(Some(left), op, vec![right], false, false)
         };
         self.lv -= 1;
         Ok(expr)
@@ -429,9 +565,13 @@ impl<'a> Parser<'a> {
         self.debug_log("parse_secondary_expr");
         let expr = match self.current_token() {
             Token::KwBreak => self.parse_break_expr(),
+            Token::KwNext => self.parse_next_expr(),
             Token::KwIf => self.parse_if_expr(),
             Token::KwUnless => self.parse_unless_expr(),
             Token::KwWhile => self.parse_while_expr(),
+            Token::KwFor => self.parse_for_expr(),
+            Token::KwLoop => self.parse_loop_expr(),
+            Token::KwCase => self.parse_case_expr(),
             _ => self.parse_primary_expr(),
         }?;
         self.lv -= 1;
@@ -442,7 +582,23 @@ impl<'a> Parser<'a> {
         self.lv += 1;
         self.debug_log("parse_break_expr");
         assert!(self.consume(Token::KwBreak));
-        Ok(ast::break_expr())
+        self.skip_ws();
+        let token = self.current_token();
+        let expr = if token.value_starts() && !token.is_modifier() {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        self.lv -= 1;
+        Ok(ast::break_expr(expr))
+    }
+
+    fn parse_next_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_next_expr");
+        assert!(self.consume(Token::KwNext));
+        self.lv -= 1;
+        Ok(ast::next_expr())
     }
 
     fn parse_if_expr(&mut self) -> Result<AstExpression, Error> {
@@ -511,20 +667,227 @@ impl<'a> Parser<'a> {
         Ok(ast::while_expr(cond_expr, body_exprs))
     }
 
+    fn parse_for_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_for_expr");
+        assert!(self.consume(Token::KwFor));
+        self.skip_ws();
+        let var_name = match self.current_token() {
+            Token::LowerWord(s) => s.to_string(),
+            token => return Err(parse_error!(self, "invalid loop variable: {:?}", token)),
+        };
+        self.consume_token();
+        self.skip_ws();
+        self.expect(Token::KwIn)?;
+        self.skip_ws();
+        let iterable = self.parse_expr()?;
+        self.skip_ws();
+        self.expect(Token::Separator)?;
+        let body_exprs = self.parse_exprs(vec![Token::KwEnd])?;
+        self.skip_wsn();
+        self.expect(Token::KwEnd)?;
+        self.lv -= 1;
+        Ok(ast::for_expr(var_name, iterable, body_exprs))
+    }
+
+    /// `loop do ... end`
+    fn parse_loop_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_loop_expr");
+        assert!(self.consume(Token::KwLoop));
+        self.skip_ws();
+        self.expect(Token::KwDo)?;
+        self.skip_ws();
+        self.expect(Token::Separator)?;
+        let body_exprs = self.parse_exprs(vec![Token::KwEnd])?;
+        self.skip_wsn();
+        self.expect(Token::KwEnd)?;
+        self.lv -= 1;
+        Ok(ast::loop_expr(body_exprs))
+    }
+
+    /// `case expr when pattern, ... if guard then ... else ... end`
+    fn parse_case_expr(&mut self) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_case_expr");
+        assert!(self.consume(Token::KwCase));
+        self.skip_ws();
+        let expr = self.parse_expr()?;
+        self.skip_ws();
+        self.expect(Token::Separator)?;
+        self.skip_wsn();
+        if !self.current_token_is(Token::KwWhen) {
+            return Err(parse_error!(
+                self,
+                "`case' must have `when' (got {:?})",
+                self.current_token()
+            ));
+        }
+        let mut clauses = vec![];
+        while self.consume(Token::KwWhen) {
+            clauses.push(self.parse_case_clause()?);
+        }
+        let else_exprs = if self.consume(Token::KwElse) {
+            self.skip_wsn();
+            let exprs = self.parse_exprs(vec![Token::KwEnd])?;
+            self.skip_wsn();
+            Some(exprs)
+        } else {
+            None
+        };
+        self.expect(Token::KwEnd)?;
+        self.lv -= 1;
+        Ok(ast::case_expr(expr, clauses, else_exprs))
+    }
+
+    /// The rest of `when pattern, ... if guard then ...`
+    fn parse_case_clause(&mut self) -> Result<CaseClause, Error> {
+        self.lv += 1;
+        self.debug_log("parse_case_clause");
+        self.skip_ws();
+        let mut patterns = vec![self.parse_pattern()?];
+        while self.next_nonspace_token() == Token::Comma {
+            self.skip_ws();
+            self.consume_token();
+            self.skip_wsn();
+            patterns.push(self.parse_pattern()?);
+        }
+        if patterns.len() > 1 && patterns.iter().any(|p| !p.binding_names().is_empty()) {
+            return Err(parse_error!(
+                self,
+                "patterns separated by `,' cannot have variables"
+            ));
+        }
+        self.skip_ws();
+        let guard = if self.consume(Token::KwIf) {
+            self.skip_ws();
+            let expr = self.parse_expr()?;
+            self.skip_ws();
+            Some(expr)
+        } else {
+            None
+        };
+        if self.consume(Token::KwThen) {
+            self.skip_wsn();
+        } else {
+            self.expect(Token::Separator)?;
+        }
+        let body_exprs = self.parse_exprs(vec![Token::KwWhen, Token::KwElse, Token::KwEnd])?;
+        self.skip_wsn();
+        self.lv -= 1;
+        Ok(CaseClause {
+            patterns,
+            guard,
+            body_exprs,
+        })
+    }
+
+    /// Parse a pattern of `when`. A name is a variable (`_` matches
+    /// anything) and other expressions are compared with `==` (write
+    /// `(x)` to compare with the variable `x`)
+    fn parse_pattern(&mut self) -> Result<Pattern, Error> {
+        if self.current_token_is(Token::LSqBracket) {
+            return self.parse_array_pattern();
+        }
+        let parenthesized = self.current_token_is(Token::LParen);
+        let expr = self.parse_operator_or()?;
+        let pattern = match &expr.body {
+            AstExpressionBody::BareName(name) if !parenthesized => {
+                if name == "_" {
+                    Pattern::Wildcard
+                } else {
+                    Pattern::Binding(name.clone())
+                }
+            }
+            _ => Pattern::Value(expr),
+        };
+        Ok(pattern)
+    }
+
+    /// `[x, 0, *rest]`
+    fn parse_array_pattern(&mut self) -> Result<Pattern, Error> {
+        self.lv += 1;
+        self.debug_log("parse_array_pattern");
+        assert!(self.consume(Token::LSqBracket));
+        let mut items = vec![];
+        let mut rest = None;
+        self.skip_wsn();
+        loop {
+            match self.current_token() {
+                Token::RSqBracket => {
+                    self.consume_token();
+                    break;
+                }
+                Token::Mul => {
+                    self.consume_token();
+                    let name = match self.current_token() {
+                        Token::LowerWord(s) => s.to_string(),
+                        token => {
+                            return Err(parse_error!(
+                                self,
+                                "variable name expected after `*' (got {:?})",
+                                token
+                            ))
+                        }
+                    };
+                    self.consume_token();
+                    self.skip_wsn();
+                    if !self.current_token_is(Token::RSqBracket) {
+                        return Err(parse_error!(
+                            self,
+                            "`*{}' must be the last of an array pattern",
+                            name
+                        ));
+                    }
+                    rest = Some(name);
+                }
+                _ => {
+                    items.push(self.parse_pattern()?);
+                    self.skip_wsn();
+                    match self.current_token() {
+                        Token::Comma => {
+                            self.consume_token();
+                            self.skip_wsn();
+                        }
+                        Token::RSqBracket => (),
+                        token => {
+                            return Err(parse_error!(
+                                self,
+                                "unexpected token `{:?}' in an array pattern",
+                                token
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        self.lv -= 1;
+        Ok(Pattern::Array { items, rest })
+    }
+
     // prim . methodName argumentWithParentheses? block?
     // prim [ indexingArgumentList? ] not(EQUAL)
     fn parse_primary_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_primary_expr");
+        let begin = self.current_position();
         let mut expr = self.parse_atomic()?;
         loop {
             if self.next_nonspace_token() == Token::Dot {
                 // TODO: Newline should also be allowed here (but Semicolon is not)
                 self.skip_ws();
                 expr = self.parse_method_chain(expr)?;
+            } else if self.current_token_is(Token::LSqBracket) {
+                // `a[1]` (`a [1]` is not an indexing)
+                expr = self.parse_indexing(expr)?;
+            } else if self.current_token_is(Token::Question) {
+                // `a?` (`a ? b : c` is a conditional expression)
+                self.consume_token();
+                expr = ast::try_expr(expr);
             } else {
                 break;
             }
+            expr = self.set_span(expr, begin.clone());
         }
         self.lv -= 1;
         Ok(expr)
@@ -541,6 +904,18 @@ impl<'a> Parser<'a> {
         // Method name
         let method_name = match self.current_token() {
             Token::LowerWord(s) => s.clone(),
+            // `tuple.0` (`tuple.0.1` is lexed as `tuple` `.` `0.1`)
+            Token::Number(s) => {
+                let mut fields = s.split('.').map(|i| format!("_{}", i)).collect::<Vec<_>>();
+                self.consume_token();
+                let last = fields.pop().unwrap();
+                let mut expr = expr;
+                for field in fields {
+                    expr = ast::method_call(Some(expr), &field, vec![], true, false);
+                }
+                self.lv -= 1;
+                return Ok(ast::method_call(Some(expr), &last, vec![], true, false));
+            }
             token => return Err(parse_error!(self, "invalid method name: {:?}", token)),
         };
         self.consume_token();
@@ -563,6 +938,19 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    /// Parse `[args]` (as a call of `[]`)
+    fn parse_indexing(&mut self, expr: AstExpression) -> Result<AstExpression, Error> {
+        self.lv += 1;
+        self.debug_log("parse_indexing");
+        assert!(self.consume(Token::LSqBracket));
+        self.skip_wsn();
+        let args = self.parse_args()?;
+        self.skip_wsn();
+        self.expect(Token::RSqBracket)?;
+        self.lv -= 1;
+        Ok(ast::method_call(Some(expr), "[]", args, true, false))
+    }
+
     fn parse_paren_and_args(&mut self) -> Result<Vec<AstExpression>, Error> {
         self.lv += 1;
         self.debug_log("parse_paren_and_args");
@@ -583,6 +971,7 @@ impl<'a> Parser<'a> {
     fn parse_atomic(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_atomic");
+        let begin = self.current_position();
         let token = self.current_token();
         let expr = match token {
             Token::LowerWord(s) => {
@@ -608,11 +997,18 @@ impl<'a> Parser<'a> {
             Token::LSqBracket => self.parse_array_literal(),
             Token::Number(_) => self.parse_decimal_literal(),
             Token::Str(_) => self.parse_string(),
+            Token::Regexp(_) => self.parse_regexp(),
+            Token::Command(_) => self.parse_command(),
+            Token::Char(c) => {
+                let expr = ast::char_literal(*c);
+                self.consume_token();
+                Ok(expr)
+            }
             Token::LParen => self.parse_parenthesized_expr(),
             token => Err(parse_error!(self, "unexpected token: {:?}", token)),
         }?;
         self.lv -= 1;
-        Ok(expr)
+        Ok(self.set_span(expr, begin))
     }
 
     // Method call with explicit parenthesis (eg. `foo(bar)`)
@@ -651,6 +1047,17 @@ impl<'a> Parser<'a> {
                 token => return Err(parse_error!(self, "unexpected token: {:?}", token)),
             }
         }
+        // Parse `A<Int>` (not `A < b`; there must be no space before `<`)
+        if self.current_token_is(Token::LessThan) {
+            if let Token::UpperWord(_) = self.peek_next_token() {
+                let cur = self.current_position();
+                self.consume_token();
+                if let Ok(typ_args) = self.parse_typ_args() {
+                    return Ok(ast::specialized_const_ref(names, typ_args));
+                }
+                self.rewind_to(cur);
+            }
+        }
         Ok(ast::const_ref(names))
     }
 
@@ -660,6 +1067,13 @@ impl<'a> Parser<'a> {
         assert!(self.consume(Token::KwFn));
         self.expect(Token::LParen)?;
         let params = self.parse_params()?;
+        if let Some(param) = params.iter().find(|p| p.implicit_conv) {
+            return Err(parse_error!(
+                self,
+                "lambda param cannot be `~' ({})",
+                param.name
+            ));
+        }
         self.skip_ws();
         self.expect(Token::LBrace)?;
         self.consume_token();
@@ -676,9 +1090,29 @@ impl<'a> Parser<'a> {
         self.skip_wsn();
         let expr = self.parse_expr()?; // Should be parse_exprs() ?
         self.skip_wsn();
+        if !self.current_token_is(Token::Comma) {
+            self.expect(Token::RParen)?;
+            self.lv -= 1;
+            return Ok(expr);
+        }
+
+        // `(a, b)`
+        let mut exprs = vec![expr];
+        while self.consume(Token::Comma) {
+            self.skip_wsn();
+            exprs.push(self.parse_expr()?);
+            self.skip_wsn();
+        }
         self.expect(Token::RParen)?;
+        if exprs.len() > ast::MAX_TUPLE_LEN {
+            return Err(parse_error!(
+                self,
+                "a tuple can have at most {} elements",
+                ast::MAX_TUPLE_LEN
+            ));
+        }
         self.lv -= 1;
-        Ok(expr)
+        Ok(ast::tuple_literal(exprs))
     }
 
     fn parse_array_literal(&mut self) -> Result<AstExpression, Error> {
@@ -724,34 +1158,84 @@ impl<'a> Parser<'a> {
     fn parse_decimal_literal(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_decimal_literal");
-        let expr = match self.consume_token() {
+        let expr = match self.current_token() {
             Token::Number(s) => {
-                if s.contains('.') {
-                    let value = s.parse().unwrap();
-                    ast::float_literal(value)
-                } else {
-                    let value = s.parse().unwrap();
-                    ast::decimal_literal(value)
-                }
-            }
-            _ => {
-                self.lv -= 1;
-                return Err(self.parseerror("expected decimal literal"));
+                number_literal(s).map_err(|msg| parse_error!(self, "{}: `{}'", msg, s))
             }
+            _ => Err(self.parseerror("expected decimal literal")),
         };
         self.lv -= 1;
+        let expr = expr?;
+        self.consume_token();
         Ok(expr)
     }
 
     fn parse_string(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log("parse_string");
-        let expr = match self.consume_token() {
-            Token::Str(content) => ast::string_literal(content),
+        let content = match self.current_token() {
+            Token::Str(content) => escape::unescape(content),
             _ => panic!("parse_string called on non-string token"),
         };
         self.lv -= 1;
-        Ok(expr)
+        match content {
+            Ok(content) => {
+                self.consume_token();
+                Ok(ast::string_literal(content))
+            }
+            Err(_) => Err(self.invalid_escape_error()),
+        }
+    }
+
+    /// `/a+b/` is `Regexp.new("a+b")` (with the content as is)
+    fn parse_regexp(&mut self) -> Result<AstExpression, Error> {
+        let content = match self.current_token() {
+            Token::Regexp(content) => content.to_string(),
+            _ => panic!("parse_regexp called on non-regexp token"),
+        };
+        self.consume_token();
+        Ok(ast::method_call(
+            Some(ast::const_ref(vec!["Regexp".to_string()])),
+            "new",
+            vec![ast::string_literal(content)],
+            true,
+            false,
+        ))
+    }
+
+    /// `` `ls -1` `` is `Process.capture("ls -1")`
+    fn parse_command(&mut self) -> Result<AstExpression, Error> {
+        let content = match self.current_token() {
+            Token::Command(content) => escape::unescape(content),
+            _ => panic!("parse_command called on non-command token"),
+        };
+        let content = content.map_err(|_| self.invalid_escape_error())?;
+        self.consume_token();
+        Ok(ast::method_call(
+            Some(ast::const_ref(vec!["Process".to_string()])),
+            "capture",
+            vec![ast::string_literal(content)],
+            true,
+            false,
+        ))
+    }
+
+    /// Return the error for the invalid escape sequence in the current
+    /// token. It is searched in the source of the token (not the content,
+    /// which is not the same for heredocs) to know its position
+    fn invalid_escape_error(&self) -> Error {
+        let e = escape::unescape(self.lexer.current_token_src())
+            .expect_err("[BUG] no invalid escape in the token");
+        let base = self.current_position().pos();
+        let mut begin = self.current_position();
+        while begin.pos() < base + e.begin {
+            begin.proceed(self.lexer.src);
+        }
+        let mut end = begin.clone();
+        while end.pos() < base + e.end {
+            end.proceed(self.lexer.src);
+        }
+        self.parseerror_at(&e.msg, Span { begin, end })
     }
 
     // func: parse_xx
@@ -764,6 +1248,7 @@ impl<'a> Parser<'a> {
     ) -> Result<AstExpression, Error> {
         self.lv += 1;
         self.debug_log(name);
+        let begin = self.current_position();
         let mut left = func(self)?;
         loop {
             let t = self.next_nonspace_token();
@@ -778,7 +1263,62 @@ impl<'a> Parser<'a> {
             self.consume_token(); // Consume t
             self.skip_wsn(); // TODO: should ban ';' here
             let right = func(self)?;
-            left = ast::bin_op_expr(left, op, right)
+            left = self.set_span(ast::bin_op_expr(left, op, right), begin.clone());
+        }
+    }
+}
+
+/// Convert a number literal into `DecimalLiteral` or `FloatLiteral`.
+///
+/// - `_` may be put between digits (`1_000_000`)
+/// - `0x`, `0b` and `0o` are for hexadecimal, binary and octal integers.
+///   They may be up to `0xffff_ffff`, which is `-1` (the bits are the same)
+/// - A float has a fraction (`1.5`), an exponent (`1e3`) or both (`1.5e-3`)
+fn number_literal(s: &str) -> Result<AstExpression, &'static str> {
+    let radix = match s.get(..2) {
+        Some("0x") => 16,
+        Some("0b") => 2,
+        Some("0o") => 8,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = remove_separators(&s[2..], radix).ok_or("invalid number literal")?;
+        if !digits.chars().all(|c| c.is_digit(radix)) {
+            return Err("invalid number literal");
         }
+        let value = u32::from_str_radix(&digits, radix).map_err(|_| "integer literal too large")?;
+        return Ok(ast::decimal_literal(value as i32));
+    }
+
+    let digits = remove_separators(s, 10).ok_or("invalid number literal")?;
+    if digits.contains(|c| c == '.' || c == 'e' || c == 'E') {
+        let value = digits
+            .parse::<f64>()
+            .map_err(|_| "invalid number literal")?;
+        if !value.is_finite() {
+            return Err("float literal too large");
+        }
+        Ok(ast::float_literal(value))
+    } else {
+        let value = digits
+            .parse::<i32>()
+            .map_err(|_| "integer literal too large")?;
+        Ok(ast::decimal_literal(value))
+    }
+}
+
+/// Remove `_` between digits. Returns `None` if `s` is empty or a `_` is
+/// not between digits (eg. `1__0`, `1_`, `1_.5`)
+fn remove_separators(s: &str, radix: u32) -> Option<String> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let is_digit = |i: usize| chars.get(i).map_or(false, |c| c.is_digit(radix));
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '_' && (i == 0 || !is_digit(i - 1) || !is_digit(i + 1)) {
+            return None;
+        }
+    }
+    if chars.is_empty() {
+        return None;
     }
+    Some(chars.into_iter().filter(|c| *c != '_').collect())
 }
@@ -1,5 +1,8 @@
 use super::token::Token;
 
+/// Beginning and end of a multi-line string
+const TRIPLE_QUOTE: &str = "\"\"\"";
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
     pub src: &'a str,
@@ -9,6 +12,29 @@ pub struct Lexer<'a> {
     space_seen: bool,
     pub current_token: Token,
     next_cur: Option<Cursor>,
+    /// The error which stopped the lexer, if any (see `LexError`)
+    pub error: Option<LexError>,
+}
+
+/// An error in the source found by the lexer (eg. an unterminated string.)
+/// The lexer stops there: `current_token` is `Eof` from then on and the
+/// parser reports this error instead of the one it finds at the `Eof`
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub msg: String,
+    pub span: Span,
+}
+
+impl LexError {
+    fn new(msg: impl Into<String>, begin: &Cursor, end: &Cursor) -> LexError {
+        LexError {
+            msg: msg.into(),
+            span: Span {
+                begin: begin.clone(),
+                end: end.clone(),
+            },
+        }
+    }
 }
 
 /// Flags to decide a `-`, `+`, etc. is unary or binary.
@@ -21,7 +47,7 @@ pub struct Lexer<'a> {
 /// - `p - x`  # binary minus            ExprArg
 /// - `p -x`   # unary minus             ExprArg
 /// - `1 -2`   # binary minus (unusual)  ExprArg  
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum LexerState {
     /// A new expression begins here
     /// `+`/`-` is always unary
@@ -53,6 +79,54 @@ impl Cursor {
         }
     }
 
+    /// Make a cursor at `line` and `col` (0-origin) which is `pos` bytes
+    /// from the beginning of the source
+    pub fn at(line: usize, col: usize, pos: usize) -> Cursor {
+        Cursor { line, col, pos }
+    }
+
+    /// Line number (0-origin)
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column number (0-origin, counted in chars)
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    /// Byte offset from the beginning of the source
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Return the cursor at the end of `src`
+    pub fn end_of(src: &str) -> Cursor {
+        let mut cur = Cursor::new();
+        while cur.peek(src).is_some() {
+            cur.proceed(src);
+        }
+        cur
+    }
+
+    /// Convert a cursor on `prefix + src` into the one on `src`, where
+    /// `base` is the end of `prefix`. Returns `None` if it is on `prefix`
+    pub fn relative_to(&self, base: &Cursor) -> Option<Cursor> {
+        if self.pos < base.pos {
+            return None;
+        }
+        let col = if self.line == base.line {
+            self.col - base.col
+        } else {
+            self.col
+        };
+        Some(Cursor {
+            line: self.line - base.line,
+            col,
+            pos: self.pos - base.pos,
+        })
+    }
+
     /// Return the current char (None if eof)
     pub fn peek(&self, src: &str) -> Option<char> {
         src[self.pos..].chars().next()
@@ -83,17 +157,46 @@ impl Cursor {
     }
 }
 
+/// A range of the source (`end` is exclusive)
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Span {
+    pub begin: Cursor,
+    pub end: Cursor,
+}
+
+impl Span {
+    /// Whether `pos` (a byte offset) is in this span
+    pub fn contains(&self, pos: usize) -> bool {
+        self.begin.pos <= pos && pos < self.end.pos
+    }
+
+    /// Return the part of `src` in this span
+    pub fn src<'s>(&self, src: &'s str) -> &'s str {
+        &src[self.begin.pos..self.end.pos]
+    }
+
+    /// Same as `Cursor::relative_to`
+    pub fn relative_to(&self, base: &Cursor) -> Option<Span> {
+        Some(Span {
+            begin: self.begin.relative_to(base)?,
+            end: self.end.relative_to(base)?,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum CharType {
     Space,
     Separator, // Newline or ';'
-    Comment,   // From '#' to the next newline
+    Comment,   // From '#' to the next newline (or `#|` to `|#`)
     UpperWord, // identifier which starts with upper-case letter
     LowerWord, // Keyword or identifier which starts with lower-case letter
     IVar,      // Instance variable (eg. "foo" for @foo)
     Symbol,    // '+', '(', etc.
     Number,    // '0'~'9'
     Str,       // '"'
+    Char,      // '\''
+    Command,   // '`'
     Eof,
 }
 
@@ -111,11 +214,41 @@ impl<'a> Lexer<'a> {
             space_seen: false,
             next_cur: None,
             current_token: Token::Bof,
+            error: None,
         };
         lexer.read_token();
         lexer
     }
 
+    /// Return all the tokens in `src` with their spans, including spaces
+    /// and separators. Comments are returned as `Token::Separator` and
+    /// can be read from the source with the span (used by the formatter)
+    pub fn tokenize(src: &str) -> Vec<(Token, Span)> {
+        Lexer::tokens(src).collect()
+    }
+
+    /// Same as `tokenize` but reads the tokens one by one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use shiika::parser::lexer::Lexer;
+    /// use shiika::parser::token::Token;
+    ///
+    /// let src = "a = 1";
+    /// let mut tokens = Lexer::tokens(src);
+    /// let (token, span) = tokens.next().unwrap();
+    /// assert_eq!(token, Token::LowerWord("a".to_string()));
+    /// assert_eq!(span.src(src), "a");
+    /// assert_eq!(tokens.count(), 4);
+    /// ```
+    pub fn tokens(src: &str) -> Tokens {
+        Tokens {
+            lexer: Lexer::new(src),
+            last_words: (None, None),
+        }
+    }
+
     pub fn set_state(&mut self, state: LexerState) {
         self.state = state;
     }
@@ -125,6 +258,22 @@ impl<'a> Lexer<'a> {
         self.current_token = token;
     }
 
+    /// Return the source of the current token
+    pub fn current_token_src(&self) -> &str {
+        match &self.next_cur {
+            Some(next_cur) => &self.src[self.cur.pos..next_cur.pos],
+            None => "",
+        }
+    }
+
+    /// Return the span of the current token
+    pub fn current_span(&self) -> Span {
+        Span {
+            begin: self.cur.clone(),
+            end: self.next_cur.clone().unwrap_or_else(|| self.cur.clone()),
+        }
+    }
+
     pub fn debug_info(&self) -> String {
         format!("{:?} {:?}", self.current_token, self.state)
     }
@@ -197,51 +346,64 @@ impl<'a> Lexer<'a> {
         let next_cur = self.next_cur.as_ref().unwrap().clone();
         let c = next_cur.peek(self.src);
         let mut next_next_cur = next_cur.clone();
-        let (token, _) = match self.char_type(c) {
-            CharType::Space => (self.read_space(&mut next_next_cur), None),
-            CharType::Separator => (self.read_separator(&mut next_next_cur), None),
-            CharType::Comment => (self.read_comment(&mut next_next_cur), None),
-            CharType::UpperWord => (
-                self.read_upper_word(&mut next_next_cur, Some(&next_cur)),
-                None,
-            ),
-            CharType::LowerWord => self.read_lower_word(&mut next_next_cur, Some(&next_cur)),
-            CharType::IVar => (self.read_ivar(&mut next_next_cur, Some(&next_cur)), None),
-            CharType::Symbol => self.read_symbol(&mut next_next_cur),
-            CharType::Number => (self.read_number(&mut next_next_cur, Some(&next_cur)), None),
-            CharType::Str => (self.read_str(&mut next_next_cur, Some(&next_cur)), None),
-            CharType::Eof => (self.read_eof(), None),
+        let result = match self.char_type(c) {
+            CharType::Space => Ok(self.read_space(&mut next_next_cur)),
+            CharType::Separator => Ok(self.read_separator(&mut next_next_cur)),
+            CharType::Comment => self.read_comment(&mut next_next_cur),
+            CharType::UpperWord => Ok(self.read_upper_word(&mut next_next_cur, Some(&next_cur))),
+            CharType::LowerWord => Ok(self.read_lower_word(&mut next_next_cur, Some(&next_cur)).0),
+            CharType::IVar => Ok(self.read_ivar(&mut next_next_cur, Some(&next_cur))),
+            CharType::Symbol => self.read_symbol(&mut next_next_cur).map(|(t, _)| t),
+            CharType::Number => self.read_number(&mut next_next_cur, Some(&next_cur)),
+            CharType::Str => self.read_str(&mut next_next_cur, Some(&next_cur)),
+            CharType::Char => self.read_char(&mut next_next_cur),
+            CharType::Command => self.read_command(&mut next_next_cur),
+            CharType::Eof => Ok(self.read_eof()),
         };
-        token
+        // The error is reported when the token is read by `consume_token`
+        result.unwrap_or(Token::Eof)
     }
 
     /// Read a token and set it to `current_token`
     fn read_token(&mut self) {
         let c = self.cur.peek(self.src);
         let mut next_cur = self.cur.clone();
-        let (token, new_state) = match self.char_type(c) {
-            CharType::Space => (self.read_space(&mut next_cur), None),
-            CharType::Separator => (self.read_separator(&mut next_cur), None),
-            CharType::Comment => (self.read_comment(&mut next_cur), None),
-            CharType::UpperWord => (
+        let result = match self.char_type(c) {
+            CharType::Space => Ok((self.read_space(&mut next_cur), None)),
+            CharType::Separator => Ok((self.read_separator(&mut next_cur), None)),
+            CharType::Comment => self.read_comment(&mut next_cur).map(|t| (t, None)),
+            CharType::UpperWord => Ok((
                 self.read_upper_word(&mut next_cur, None),
                 Some(LexerState::ExprEnd),
-            ),
-            CharType::LowerWord => self.read_lower_word(&mut next_cur, None),
-            CharType::IVar => (
+            )),
+            CharType::LowerWord => Ok(self.read_lower_word(&mut next_cur, None)),
+            CharType::IVar => Ok((
                 self.read_ivar(&mut next_cur, None),
                 Some(LexerState::ExprEnd),
-            ),
+            )),
             CharType::Symbol => self.read_symbol(&mut next_cur),
-            CharType::Number => (
-                self.read_number(&mut next_cur, None),
-                Some(LexerState::ExprEnd),
-            ),
-            CharType::Str => (
-                self.read_str(&mut next_cur, None),
-                Some(LexerState::ExprEnd),
-            ),
-            CharType::Eof => (self.read_eof(), None),
+            CharType::Number => self
+                .read_number(&mut next_cur, None)
+                .map(|t| (t, Some(LexerState::ExprEnd))),
+            CharType::Str => self
+                .read_str(&mut next_cur, None)
+                .map(|t| (t, Some(LexerState::ExprEnd))),
+            CharType::Char => self
+                .read_char(&mut next_cur)
+                .map(|t| (t, Some(LexerState::ExprEnd))),
+            CharType::Command => self
+                .read_command(&mut next_cur)
+                .map(|t| (t, Some(LexerState::ExprEnd))),
+            CharType::Eof => Ok((self.read_eof(), None)),
+        };
+        let (token, new_state) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                // Stop here; reading the token again gives the same error
+                self.error = Some(e);
+                next_cur = self.cur.clone();
+                (Token::Eof, None)
+            }
         };
         self.set_current_token(token);
         if let Some(state) = new_state {
@@ -264,15 +426,53 @@ impl<'a> Lexer<'a> {
         Token::Separator
     }
 
-    fn read_comment(&mut self, next_cur: &mut Cursor) -> Token {
+    fn read_comment(&mut self, next_cur: &mut Cursor) -> Result<Token, LexError> {
+        let begin = next_cur.clone();
         next_cur.proceed(self.src); // Skip the `#'
-        loop {
-            let c = next_cur.proceed(self.src);
+        if next_cur.peek(self.src) == Some('|') {
+            return self.read_block_comment(next_cur, &begin);
+        }
+        // The last line may not end with a newline
+        while let Some(c) = next_cur.peek(self.src) {
+            next_cur.proceed(self.src);
             if c == '\n' {
                 break;
             }
         }
-        Token::Separator
+        Ok(Token::Separator)
+    }
+
+    /// Read `#| ... |#` (which may contain newlines but cannot be nested.)
+    /// Like the other comments, it works as a separator. `open` is the `#`
+    fn read_block_comment(
+        &mut self,
+        next_cur: &mut Cursor,
+        open: &Cursor,
+    ) -> Result<Token, LexError> {
+        next_cur.proceed(self.src); // Skip the `|'
+        let open_end = next_cur.clone();
+        loop {
+            match next_cur.peek(self.src) {
+                Some('|') if next_cur.peek2(self.src) == Some('#') => {
+                    next_cur.proceed(self.src);
+                    next_cur.proceed(self.src);
+                    break;
+                }
+                Some(_) => {
+                    next_cur.proceed(self.src);
+                }
+                // Point at the `#|` since the end of the file does not tell
+                // which comment is not closed
+                None => {
+                    return Err(LexError::new(
+                        "found unterminated block comment (`|#' expected)",
+                        open,
+                        &open_end,
+                    ))
+                }
+            }
+        }
+        Ok(Token::Separator)
     }
 
     fn read_upper_word(&mut self, next_cur: &mut Cursor, cur: Option<&Cursor>) -> Token {
@@ -317,6 +517,10 @@ impl<'a> Lexer<'a> {
             "class" => (Token::KwClass, LexerState::ExprBegin),
             "end" => (Token::KwEnd, LexerState::ExprEnd),
             "def" => (Token::KwDef, LexerState::ExprBegin),
+            "abstract" => (Token::KwAbstract, LexerState::ExprBegin),
+            "extern" => (Token::KwExtern, LexerState::ExprBegin),
+            "async" => (Token::KwAsync, LexerState::ExprBegin),
+            "await" => (Token::KwAwait, LexerState::ExprBegin),
             "var" => (Token::KwVar, LexerState::ExprBegin),
             "and" => (Token::KwAnd, LexerState::ExprBegin),
             "or" => (Token::KwOr, LexerState::ExprBegin),
@@ -324,7 +528,14 @@ impl<'a> Lexer<'a> {
             "if" => (Token::KwIf, LexerState::ExprBegin),
             "unless" => (Token::KwUnless, LexerState::ExprBegin),
             "while" => (Token::KwWhile, LexerState::ExprBegin),
-            "break" => (Token::KwBreak, LexerState::ExprEnd),
+            "for" => (Token::KwFor, LexerState::ExprBegin),
+            "loop" => (Token::KwLoop, LexerState::ExprBegin),
+            "do" => (Token::KwDo, LexerState::ExprBegin),
+            "case" => (Token::KwCase, LexerState::ExprBegin),
+            "when" => (Token::KwWhen, LexerState::ExprBegin),
+            "in" => (Token::KwIn, LexerState::ExprBegin),
+            "break" => (Token::KwBreak, LexerState::ExprBegin),
+            "next" => (Token::KwNext, LexerState::ExprBegin),
             "then" => (Token::KwThen, LexerState::ExprBegin),
             "else" => (Token::KwElse, LexerState::ExprBegin),
             "fn" => (Token::KwFn, LexerState::ExprBegin),
@@ -353,13 +564,29 @@ impl<'a> Lexer<'a> {
         Token::IVar(s.to_string())
     }
 
-    fn read_symbol(&mut self, next_cur: &mut Cursor) -> (Token, Option<LexerState>) {
+    fn read_symbol(
+        &mut self,
+        next_cur: &mut Cursor,
+    ) -> Result<(Token, Option<LexerState>), LexError> {
+        let begin = next_cur.clone();
         let c1 = next_cur.proceed(self.src);
         let c2 = next_cur.peek(self.src);
         let (token, state) = match c1 {
             '(' => (Token::LParen, LexerState::ExprBegin),
             ')' => (Token::RParen, LexerState::ExprEnd),
-            '[' => (Token::LSqBracket, LexerState::ExprBegin),
+            '[' => {
+                if self.state == LexerState::MethodName && c2 == Some(']') {
+                    next_cur.proceed(self.src);
+                    if next_cur.peek(self.src) == Some('=') {
+                        next_cur.proceed(self.src);
+                        (Token::SetMethod, LexerState::ExprBegin)
+                    } else {
+                        (Token::GetMethod, LexerState::ExprBegin)
+                    }
+                } else {
+                    (Token::LSqBracket, LexerState::ExprBegin)
+                }
+            }
             ']' => (Token::RSqBracket, LexerState::ExprEnd),
             '{' => (Token::LBrace, LexerState::ExprBegin),
             '}' => (Token::RBrace, LexerState::ExprEnd),
@@ -387,12 +614,21 @@ impl<'a> Lexer<'a> {
                 }
             }
             '*' => (Token::Mul, LexerState::ExprBegin),
-            '/' => (Token::Div, LexerState::ExprBegin),
+            '/' => {
+                if self.is_unary(c2) {
+                    (self.read_regexp(next_cur, &begin)?, LexerState::ExprEnd)
+                } else {
+                    (Token::Div, LexerState::ExprBegin)
+                }
+            }
             '%' => (Token::Mod, LexerState::ExprBegin),
             '=' => {
                 if c2 == Some('=') {
                     next_cur.proceed(self.src);
                     (Token::EqEq, LexerState::ExprBegin)
+                } else if c2 == Some('~') {
+                    next_cur.proceed(self.src);
+                    (Token::EqTilde, LexerState::ExprBegin)
                 } else {
                     (Token::Equal, LexerState::ExprBegin)
                 }
@@ -408,7 +644,14 @@ impl<'a> Lexer<'a> {
             '<' => {
                 if c2 == Some('=') {
                     next_cur.proceed(self.src);
-                    (Token::LessEq, LexerState::ExprBegin)
+                    if next_cur.peek(self.src) == Some('>') {
+                        next_cur.proceed(self.src);
+                        (Token::Spaceship, LexerState::ExprBegin)
+                    } else {
+                        (Token::LessEq, LexerState::ExprBegin)
+                    }
+                } else if c2 == Some('<') && self.heredoc_follows(next_cur) {
+                    (self.read_heredoc(next_cur, &begin)?, LexerState::ExprEnd)
                 } else if c2 == Some('<') {
                     next_cur.proceed(self.src);
                     (Token::LShift, LexerState::ExprBegin)
@@ -427,10 +670,29 @@ impl<'a> Lexer<'a> {
                     (Token::GreaterThan, LexerState::ExprBegin)
                 }
             }
-            '.' => (Token::Dot, LexerState::ExprBegin),
+            '.' => {
+                if c2 == Some('.') {
+                    next_cur.proceed(self.src);
+                    if next_cur.peek(self.src) == Some('.') {
+                        next_cur.proceed(self.src);
+                        (Token::DotDotDot, LexerState::ExprBegin)
+                    } else {
+                        (Token::DotDot, LexerState::ExprBegin)
+                    }
+                } else {
+                    (Token::Dot, LexerState::ExprBegin)
+                }
+            }
             '@' => (Token::At, LexerState::ExprBegin),
             '~' => (Token::Tilde, LexerState::ExprBegin),
-            '?' => (Token::Question, LexerState::ExprBegin),
+            '?' => {
+                // `a?` (postfix) ends an expression; `a ? b : c` does not
+                if self.state != LexerState::ExprBegin && self.current_token != Token::Space {
+                    (Token::Question, LexerState::ExprEnd)
+                } else {
+                    (Token::Question, LexerState::ExprBegin)
+                }
+            }
             ',' => (Token::Comma, LexerState::ExprBegin),
             ':' => {
                 if c2 == Some(':') {
@@ -458,11 +720,14 @@ impl<'a> Lexer<'a> {
             }
             '^' => (Token::Xor, LexerState::ExprBegin),
             c => {
-                // TODO: this should be lexing error
-                panic!("unknown symbol: {}", c)
+                return Err(LexError::new(
+                    format!("unknown symbol: {}", c),
+                    &begin,
+                    next_cur,
+                ))
             }
         };
-        (token, Some(state))
+        Ok((token, Some(state)))
     }
 
     fn is_unary(&self, next_char: Option<char>) -> bool {
@@ -474,16 +739,55 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn read_number(&mut self, next_cur: &mut Cursor, cur: Option<&Cursor>) -> Token {
+    /// Read a number literal (eg. `123`, `1_000`, `1.5`, `1.5e-3`, `0xff`.)
+    /// The digits are checked by the parser
+    fn read_number(
+        &mut self,
+        next_cur: &mut Cursor,
+        cur: Option<&Cursor>,
+    ) -> Result<Token, LexError> {
+        let begin = match cur {
+            Some(c) => c.pos,
+            None => self.cur.pos,
+        };
+        // `0x`, `0b` or `0o`
+        if next_cur.peek(self.src) == Some('0') {
+            if let Some('x') | Some('b') | Some('o') = next_cur.peek2(self.src) {
+                next_cur.proceed(self.src);
+                next_cur.proceed(self.src);
+                while let Some(c) = next_cur.peek(self.src) {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        next_cur.proceed(self.src);
+                    } else {
+                        break;
+                    }
+                }
+                return Ok(Token::Number(self.src[begin..next_cur.pos].to_string()));
+            }
+        }
         loop {
             match self.char_type(next_cur.peek(self.src)) {
                 CharType::Number => {
                     next_cur.proceed(self.src);
                 }
-                CharType::UpperWord | CharType::LowerWord => {
-                    // TODO: this should be lexing error
-                    panic!("need space after a number")
-                }
+                CharType::UpperWord | CharType::LowerWord => match next_cur.peek(self.src) {
+                    // Digit separator (eg. `1_000`)
+                    Some('_') => {
+                        next_cur.proceed(self.src);
+                    }
+                    // Exponent (eg. `1e3`, `1.5e-3`)
+                    Some('e') | Some('E') if self.exponent_follows(next_cur) => {
+                        next_cur.proceed(self.src);
+                        if let Some('+') | Some('-') = next_cur.peek(self.src) {
+                            next_cur.proceed(self.src);
+                        }
+                    }
+                    _ => {
+                        let mut end = next_cur.clone();
+                        end.proceed(self.src);
+                        return Err(LexError::new("need space after a number", next_cur, &end));
+                    }
+                },
                 CharType::Symbol => {
                     if next_cur.peek(self.src) == Some('.') {
                         if self.char_type(next_cur.peek2(self.src)) == CharType::Number {
@@ -499,35 +803,288 @@ impl<'a> Lexer<'a> {
                 _ => break,
             }
         }
+        Ok(Token::Number(self.src[begin..next_cur.pos].to_string()))
+    }
+
+    /// Whether `e` at `cur` is followed by the digits of an exponent (with
+    /// an optional sign)
+    fn exponent_follows(&self, cur: &Cursor) -> bool {
+        let mut c = cur.clone();
+        c.proceed(self.src);
+        if let Some('+') | Some('-') = c.peek(self.src) {
+            c.proceed(self.src);
+        }
+        self.char_type(c.peek(self.src)) == CharType::Number
+    }
+
+    fn read_str(&mut self, next_cur: &mut Cursor, cur: Option<&Cursor>) -> Result<Token, LexError> {
+        if self.src[next_cur.pos..].starts_with(TRIPLE_QUOTE) {
+            return self.read_triple_quoted_str(next_cur);
+        }
+        let open = next_cur.clone();
+        next_cur.proceed(self.src);
+        loop {
+            match next_cur.peek(self.src) {
+                None => return Err(LexError::new("found unterminated string", &open, next_cur)),
+                Some('"') => {
+                    next_cur.proceed(self.src);
+                    break;
+                }
+                // The escapes are processed by the parser
+                Some('\\') => {
+                    next_cur.proceed(self.src);
+                    if next_cur.peek(self.src).is_some() {
+                        next_cur.proceed(self.src);
+                    }
+                }
+                _ => {
+                    next_cur.proceed(self.src);
+                }
+            }
+        }
         let begin = match cur {
             Some(c) => c.pos,
             None => self.cur.pos,
         };
-        Token::Number(self.src[begin..next_cur.pos].to_string())
+        Ok(Token::Str(
+            self.src[(begin + 1)..(next_cur.pos - 1)].to_string(),
+        ))
+    }
+
+    /// Read a regexp literal (eg. `/a+b/`) after the first `/`. The content
+    /// is kept as is except `\/`, which is `/`. `open` is the first `/`
+    fn read_regexp(&mut self, next_cur: &mut Cursor, open: &Cursor) -> Result<Token, LexError> {
+        let mut content = String::new();
+        loop {
+            match next_cur.peek(self.src) {
+                None | Some('\n') => {
+                    return Err(LexError::new("found unterminated regexp", open, next_cur))
+                }
+                Some('/') => {
+                    next_cur.proceed(self.src);
+                    break;
+                }
+                Some('\\') => {
+                    next_cur.proceed(self.src);
+                    match next_cur.peek(self.src) {
+                        Some('/') => content.push('/'),
+                        Some('\n') | None => continue,
+                        Some(c) => {
+                            content.push('\\');
+                            content.push(c);
+                        }
+                    }
+                    next_cur.proceed(self.src);
+                }
+                Some(c) => {
+                    content.push(c);
+                    next_cur.proceed(self.src);
+                }
+            }
+        }
+        Ok(Token::Regexp(content))
     }
 
-    fn read_str(&mut self, next_cur: &mut Cursor, cur: Option<&Cursor>) -> Token {
+    /// Read a command literal (eg. `` `ls -1` ``). The escapes are processed
+    /// by the parser like strings
+    fn read_command(&mut self, next_cur: &mut Cursor) -> Result<Token, LexError> {
+        let open = next_cur.clone();
         next_cur.proceed(self.src);
+        let begin = next_cur.pos;
         loop {
+            match next_cur.peek(self.src) {
+                None | Some('\n') => {
+                    return Err(LexError::new("found unterminated command", &open, next_cur))
+                }
+                Some('`') => break,
+                Some('\\') => {
+                    next_cur.proceed(self.src);
+                    if next_cur.peek(self.src).is_some() {
+                        next_cur.proceed(self.src);
+                    }
+                }
+                _ => {
+                    next_cur.proceed(self.src);
+                }
+            }
+        }
+        let content = self.src[begin..next_cur.pos].to_string();
+        next_cur.proceed(self.src);
+        Ok(Token::Command(content))
+    }
+
+    /// Read a string which may have newlines and `\"` (eg. `\"\"\"a "b" c\"\"\"`)
+    fn read_triple_quoted_str(&mut self, next_cur: &mut Cursor) -> Result<Token, LexError> {
+        let open = next_cur.clone();
+        for _ in 0..TRIPLE_QUOTE.len() {
+            next_cur.proceed(self.src);
+        }
+        let begin = next_cur.pos;
+        while !self.src[next_cur.pos..].starts_with(TRIPLE_QUOTE) {
             match next_cur.peek(self.src) {
                 None => {
-                    // TODO: should be a LexError
-                    panic!("found unterminated string");
+                    return Err(LexError::new(
+                        "found unterminated string (`\"\"\"' expected)",
+                        &open,
+                        next_cur,
+                    ))
                 }
-                Some('"') => {
+                Some('\\') => {
                     next_cur.proceed(self.src);
-                    break;
+                    if next_cur.peek(self.src).is_some() {
+                        next_cur.proceed(self.src);
+                    }
                 }
                 _ => {
                     next_cur.proceed(self.src);
                 }
             }
         }
-        let begin = match cur {
-            Some(c) => c.pos,
-            None => self.cur.pos,
+        let content = self.src[begin..next_cur.pos].to_string();
+        for _ in 0..TRIPLE_QUOTE.len() {
+            next_cur.proceed(self.src);
+        }
+        Ok(Token::Str(content))
+    }
+
+    /// Whether `<` at `cur` is the second one of a heredoc (eg. `<<~EOS`)
+    fn heredoc_follows(&self, cur: &Cursor) -> bool {
+        let rest = &self.src[cur.pos..];
+        rest.starts_with("<~") && rest[2..].starts_with(|c: char| c.is_ascii_uppercase())
+    }
+
+    /// Read a heredoc. It must be the last token of the line and its
+    /// content is the lines until the one which has only the terminator
+    /// (`EOS` for `<<~EOS`.) The common indentation of the lines is
+    /// removed:
+    ///
+    /// ```text
+    ///   s = <<~EOS
+    ///     foo
+    ///       bar
+    ///     EOS       # s is "foo\n  bar\n"
+    /// ```
+    ///
+    /// `open` is where the heredoc begins (the first `<`)
+    fn read_heredoc(&mut self, next_cur: &mut Cursor, open: &Cursor) -> Result<Token, LexError> {
+        next_cur.proceed(self.src); // <
+        next_cur.proceed(self.src); // ~
+        let begin = next_cur.pos;
+        while let Some(c) = next_cur.peek(self.src) {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                next_cur.proceed(self.src);
+            } else {
+                break;
+            }
+        }
+        let terminator = self.src[begin..next_cur.pos].to_string();
+        while let Some(' ') | Some('\t') = next_cur.peek(self.src) {
+            next_cur.proceed(self.src);
+        }
+        match next_cur.peek(self.src) {
+            Some('\n') => {
+                next_cur.proceed(self.src);
+            }
+            None => {
+                return Err(LexError::new(
+                    format!("found unterminated heredoc (`{}' expected)", terminator),
+                    open,
+                    next_cur,
+                ))
+            }
+            Some(_) => {
+                let mut end = next_cur.clone();
+                while let Some(c) = end.peek(self.src) {
+                    if c == '\n' {
+                        break;
+                    }
+                    end.proceed(self.src);
+                }
+                return Err(LexError::new(
+                    "heredoc must be the last token of the line",
+                    next_cur,
+                    &end,
+                ));
+            }
+        }
+
+        let mut lines = vec![];
+        loop {
+            let line_begin = next_cur.pos;
+            while let Some(c) = next_cur.peek(self.src) {
+                if c == '\n' {
+                    break;
+                }
+                next_cur.proceed(self.src);
+            }
+            let line = &self.src[line_begin..next_cur.pos];
+            if line.trim() == terminator {
+                break;
+            }
+            if next_cur.peek(self.src).is_none() {
+                return Err(LexError::new(
+                    format!("found unterminated heredoc (`{}' expected)", terminator),
+                    open,
+                    next_cur,
+                ));
+            }
+            next_cur.proceed(self.src);
+            lines.push(line);
+        }
+        Ok(Token::Str(remove_indent(&lines)))
+    }
+
+    /// Read a character literal (eg. `'a'`, `'\n'`.) The escapes are `\n`,
+    /// `\t`, `\0`, `\\` and `\'`
+    fn read_char(&mut self, next_cur: &mut Cursor) -> Result<Token, LexError> {
+        let begin = next_cur.clone();
+        next_cur.proceed(self.src);
+        let c = match next_cur.peek(self.src) {
+            None | Some('\n') => {
+                return Err(LexError::new(
+                    "found unterminated character literal",
+                    &begin,
+                    next_cur,
+                ))
+            }
+            Some('\\') => {
+                let escape = next_cur.clone();
+                next_cur.proceed(self.src);
+                match next_cur.peek(self.src) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    Some('\'') => '\'',
+                    None | Some('\n') => {
+                        return Err(LexError::new(
+                            "found unterminated character literal",
+                            &begin,
+                            next_cur,
+                        ))
+                    }
+                    Some(c) => {
+                        next_cur.proceed(self.src);
+                        return Err(LexError::new(
+                            format!("unknown escape sequence `\\{}' in character literal", c),
+                            &escape,
+                            next_cur,
+                        ));
+                    }
+                }
+            }
+            Some(c) => c,
         };
-        Token::Str(self.src[(begin + 1)..(next_cur.pos - 1)].to_string())
+        next_cur.proceed(self.src);
+        if next_cur.peek(self.src) != Some('\'') {
+            return Err(LexError::new(
+                "character literal must have exactly one character",
+                &begin,
+                next_cur,
+            ));
+        }
+        next_cur.proceed(self.src);
+        Ok(Token::Char(c))
     }
 
     fn read_eof(&mut self) -> Token {
@@ -543,6 +1100,8 @@ impl<'a> Lexer<'a> {
             '\n' | ';' => CharType::Separator,
             '#' => CharType::Comment,
             '"' => CharType::Str,
+            '\'' => CharType::Char,
+            '`' => CharType::Command,
             '0'..='9' => CharType::Number,
             '@' => CharType::IVar,
             '(' | ')' | '[' | ']' | '<' | '>' | '{' | '}' | '+' | '-' | '*' | '/' | '%' | '='
@@ -552,3 +1111,60 @@ impl<'a> Lexer<'a> {
         }
     }
 }
+
+/// Iterator of the tokens of a source (see `Lexer::tokens`)
+pub struct Tokens<'a> {
+    lexer: Lexer<'a>,
+    /// Last two tokens except spaces
+    last_words: (Option<Token>, Option<Token>),
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (Token, Span);
+
+    fn next(&mut self) -> Option<(Token, Span)> {
+        loop {
+            let lexer = &mut self.lexer;
+            // Method name follows (see `parse_method_definition`)
+            let is_class_method = self.last_words == (Some(Token::KwDef), Some(Token::KwSelf));
+            if lexer.current_token == Token::KwDef
+                || (lexer.current_token == Token::Dot && is_class_method)
+            {
+                lexer.set_state(LexerState::MethodName);
+            }
+            let begin = lexer.cur.clone();
+            match lexer.current_token {
+                Token::Eof => return None,
+                Token::Bof => {
+                    lexer.consume_token();
+                }
+                _ => {
+                    let token = lexer.consume_token();
+                    if token != Token::Space {
+                        self.last_words = (self.last_words.1.take(), Some(token.clone()));
+                    }
+                    let end = lexer.cur.clone();
+                    return Some((token, Span { begin, end }));
+                }
+            }
+        }
+    }
+}
+
+/// Join the lines of a heredoc removing the indentation which is common to
+/// them (blank lines are not taken into account)
+fn remove_indent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            let n = indent.min(line.len() - line.trim_start().len());
+            format!("{}\n", &line[n..])
+        })
+        .collect()
+}
@@ -10,21 +10,40 @@ macro_rules! parse_error {
     })
 }
 
+mod async_lowering;
 mod base;
+mod data_class;
 mod definition_parser;
+mod escape;
 mod expression_parser;
 pub mod lexer;
+mod overloading;
 pub mod token;
 use crate::ast;
 use crate::error::Error;
+use crate::parser::lexer::Cursor;
 use crate::parser::lexer::Lexer;
 use crate::parser::lexer::LexerState;
 pub use crate::parser::token::Token;
 
+/// Default value of `Parser::max_nesting` (and `BuildConfig::max_parse_nesting`.)
+/// A pair of parentheses, an `if`, a block, etc. is one level
+pub const DEFAULT_MAX_NESTING: usize = 48;
+
 pub struct Parser<'a> {
     pub lexer: Lexer<'a>,
     /// For debug print
     pub lv: usize,
+    /// Current nesting level of expressions
+    nesting: usize,
+    /// Parse error is raised when `nesting` exceeds this
+    /// (so that the compiler does not overflow its stack on adversarial inputs)
+    pub max_nesting: usize,
+    /// Whether to continue parsing after a parse error (see
+    /// `parse_recovering`)
+    recovering: bool,
+    /// Parse errors found so far (when `recovering`)
+    errors: Vec<Error>,
 }
 
 impl<'a> Parser<'a> {
@@ -32,6 +51,10 @@ impl<'a> Parser<'a> {
         Parser {
             lexer: Lexer::new(src),
             lv: 0,
+            nesting: 0,
+            max_nesting: DEFAULT_MAX_NESTING,
+            recovering: false,
+            errors: vec![],
         }
     }
 
@@ -39,6 +62,10 @@ impl<'a> Parser<'a> {
         Parser {
             lexer: Lexer::new_with_state(src, state),
             lv: 0,
+            nesting: 0,
+            max_nesting: DEFAULT_MAX_NESTING,
+            recovering: false,
+            errors: vec![],
         }
     }
 
@@ -47,14 +74,61 @@ impl<'a> Parser<'a> {
         parser.parse_program()
     }
 
+    /// Same as `parse` but with custom nesting limit
+    pub fn parse_with_max_nesting(src: &str, max_nesting: usize) -> Result<ast::Program, Error> {
+        let mut parser = Parser::new(src);
+        parser.max_nesting = max_nesting;
+        parser.parse_program()
+    }
+
+    /// Same as `parse` but does not stop at a parse error. Returns the
+    /// toplevel items parsed without errors (with which the IDE can still
+    /// analyze the program) and all the parse errors.
+    ///
+    /// After an error, the tokens are skipped to the next line which starts
+    /// at column 0 (see `skip_to_toplevel`), so the errors in the same
+    /// toplevel definition or statement are reported only once. A lexical
+    /// error stops the parser (see `LexError`); it is the last error
+    pub fn parse_recovering(src: &str) -> (ast::Program, Vec<Error>) {
+        let mut parser = Parser::new(src);
+        parser.recovering = true;
+        parser.skip_wsn();
+        let toplevel_items = match parser.parse_toplevel_items() {
+            Ok(items) => items,
+            Err(e) => {
+                // Not expected since the errors are collected into
+                // `errors` while recovering
+                parser.errors.push(e);
+                vec![]
+            }
+        };
+        // A lexical error is found here unless it was reported by the item
+        // it stopped
+        if let Err(e) = parser.expect_eof() {
+            let reported = parser
+                .errors
+                .last()
+                .map_or(false, |last| last.msg == e.msg && last.span == e.span);
+            if !reported {
+                parser.errors.push(e);
+            }
+        }
+        let toplevel_items = async_lowering::lower_toplevel(toplevel_items);
+        (ast::Program { toplevel_items }, parser.errors)
+    }
+
     fn parse_program(&mut self) -> Result<ast::Program, Error> {
         self.skip_wsn();
         let toplevel_items = self.parse_toplevel_items()?;
         self.expect_eof()?;
+        let toplevel_items = async_lowering::lower_toplevel(toplevel_items);
         Ok(ast::Program { toplevel_items })
     }
 
     pub fn expect_eof(&self) -> Result<(), Error> {
+        if let Some(e) = &self.lexer.error {
+            return Err(self.lex_error(e));
+        }
         if *self.current_token() != Token::Eof {
             return Err(parse_error!(
                 self,
@@ -68,20 +142,62 @@ impl<'a> Parser<'a> {
     fn parse_toplevel_items(&mut self) -> Result<Vec<ast::TopLevelItem>, Error> {
         let mut items = vec![];
         loop {
-            match self.current_token() {
-                Token::KwClass => {
-                    items.push(ast::TopLevelItem::Def(self.parse_class_definition()?));
+            let begin = self.current_position();
+            let item = match self.current_token() {
+                Token::KwClass => self.parse_class_definition().map(ast::TopLevelItem::Def),
+                Token::KwDef | Token::KwAsync => {
+                    self.parse_method_definition().map(ast::TopLevelItem::Def)
                 }
-                Token::KwDef => {
-                    items.push(ast::TopLevelItem::Def(self.parse_method_definition()?));
+                Token::KwExtern => Err(parse_error!(
+                    self,
+                    "extern method must be defined in a class"
+                )),
+                Token::KwEnd if self.recovering => {
+                    Err(parse_error!(self, "unexpected token: {:?}", Token::KwEnd))
                 }
                 Token::Eof | Token::KwEnd => break,
-                _ => {
-                    items.push(ast::TopLevelItem::Expr(self.parse_expr()?));
+                _ => self.parse_stmt().map(ast::TopLevelItem::Expr),
+            };
+            match item {
+                Ok(item) => items.push(item),
+                Err(e) if self.recovering => {
+                    self.errors.push(e);
+                    self.skip_to_toplevel(begin);
                 }
+                Err(e) => return Err(e),
             }
             self.skip_wsn();
         }
         Ok(items)
     }
+
+    /// Skip the tokens to the next line which starts at column 0, where a
+    /// toplevel definition or statement should begin. `begin` is where the
+    /// item which had the error begins (it is not the next line even if the
+    /// error is there.) `end`s at column 0 are skipped too since they are
+    /// the end of the definition which had the error
+    fn skip_to_toplevel(&mut self, begin: Cursor) {
+        self.lv = 0;
+        let mut line_head = self.lexer.cur.col() == 0 && self.lexer.cur.pos() > begin.pos();
+        loop {
+            loop {
+                match self.current_token() {
+                    Token::Eof => return,
+                    Token::Separator => line_head = true,
+                    Token::Space => (),
+                    _ if line_head && self.lexer.cur.col() == 0 => break,
+                    _ => line_head = false,
+                }
+                self.consume_token();
+            }
+            if *self.current_token() != Token::KwEnd {
+                break;
+            }
+            self.consume_token();
+            line_head = false;
+        }
+        // Read the token again as the beginning of an expression
+        self.set_lexer_state(LexerState::ExprBegin);
+        self.rewind_to(self.current_position());
+    }
 }
@@ -0,0 +1,93 @@
+/// Method overloading by arity
+///
+/// A class may define methods of the same name which take different
+/// numbers of params:
+///
+/// ```text
+///   def log(msg: String)                  def log/1(msg: String)
+///   def log(msg: String, level: Int)  =>  def log/2(msg: String, level: Int)
+/// ```
+///
+/// Their names are mangled with the arity and a call `log(x)` is resolved
+/// to `log/1` (see `ClassDict::overload_name`.) Since only the methods in
+/// one class body are renamed, overloads cannot be added by reopening the
+/// class and a subclass must define all of them to override one (see
+/// `ClassDict::check_overloads`.) `initialize` and extern methods cannot be
+/// overloaded.
+use crate::ast::*;
+use crate::parser::lexer::Span;
+use std::collections::HashMap;
+
+/// Rename the overloaded methods in `defs` (the definitions of a class).
+/// Returns an error message (and where the method name is) if a method is
+/// defined twice with the same arity
+pub(super) fn rename_overloads(defs: &mut [Definition]) -> Result<(), (String, Span)> {
+    // Instance methods and class methods are counted separately
+    let mut arities: HashMap<(bool, String), Vec<usize>> = HashMap::new();
+    for def in defs.iter() {
+        // An extern method is a class method
+        let found = match def {
+            Definition::ExternMethodDefinition { sig } => Some((true, sig)),
+            _ => method_sig(def),
+        };
+        if let Some((is_class_method, sig)) = found {
+            let entry = arities
+                .entry((is_class_method, sig.name.0.clone()))
+                .or_default();
+            let arity = sig.params.len();
+            if entry.contains(&arity) {
+                let msg = format!(
+                    "method `{}' is defined twice with {} params",
+                    sig.name, arity
+                );
+                return Err((msg, sig.name_span.clone()));
+            }
+            entry.push(arity);
+        }
+    }
+
+    for def in defs.iter() {
+        if let Definition::ExternMethodDefinition { sig } = def {
+            if arities[&(true, sig.name.0.clone())].len() >= 2 {
+                let msg = format!("extern method `{}' cannot be overloaded", sig.name);
+                return Err((msg, sig.name_span.clone()));
+            }
+        }
+    }
+
+    for def in defs.iter_mut() {
+        let (is_class_method, sig) = match method_sig_mut(def) {
+            Some(x) => x,
+            None => continue,
+        };
+        if arities[&(is_class_method, sig.name.0.clone())].len() < 2 {
+            continue;
+        }
+        if !is_class_method && sig.name.0 == "initialize" {
+            let msg = "`initialize' cannot be overloaded".to_string();
+            return Err((msg, sig.name_span.clone()));
+        }
+        sig.name = sig.name.overloaded(sig.params.len());
+    }
+    Ok(())
+}
+
+/// Return the signature of a method which may be overloaded (and whether
+/// it is a class method)
+fn method_sig(def: &Definition) -> Option<(bool, &AstMethodSignature)> {
+    match def {
+        Definition::InstanceMethodDefinition { sig, .. }
+        | Definition::AbstractMethodDefinition { sig } => Some((false, sig)),
+        Definition::ClassMethodDefinition { sig, .. } => Some((true, sig)),
+        _ => None,
+    }
+}
+
+fn method_sig_mut(def: &mut Definition) -> Option<(bool, &mut AstMethodSignature)> {
+    match def {
+        Definition::InstanceMethodDefinition { sig, .. }
+        | Definition::AbstractMethodDefinition { sig } => Some((false, sig)),
+        Definition::ClassMethodDefinition { sig, .. } => Some((true, sig)),
+        _ => None,
+    }
+}
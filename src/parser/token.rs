@@ -9,6 +9,9 @@ pub enum Token {
     IVar(String),
     Number(String),
     Str(String),
+    Char(char),
+    Regexp(String),
+    Command(String),
     // Symbols
     LParen,       //  (
     RParen,       //  )
@@ -25,6 +28,7 @@ pub enum Token {
     Div,          //  /
     Mod,          //  %
     EqEq,         //  ==
+    EqTilde,      //  =~
     NotEq,        //  !=
     LessThan,     //  <
     GreaterThan,  //  >
@@ -33,6 +37,8 @@ pub enum Token {
     Equal,        //  =
     Bang,         //  !
     Dot,          //  .
+    DotDot,       //  ..
+    DotDotDot,    //  ...
     At,           //  @
     Tilde,        //  ~
     Question,     //  ?
@@ -48,10 +54,17 @@ pub enum Token {
     RShift,       //  >>
     UPlusMethod,  //  +@
     UMinusMethod, //  -@
+    GetMethod,    //  []
+    SetMethod,    //  []=
+    Spaceship,    //  <=>
     // Keywords
     KwClass,
     KwEnd,
     KwDef,
+    KwAbstract,
+    KwExtern,
+    KwAsync,
+    KwAwait,
     KwVar,
     KwAnd,
     KwOr,
@@ -59,7 +72,14 @@ pub enum Token {
     KwIf,
     KwUnless,
     KwWhile,
+    KwFor,
+    KwLoop,
+    KwDo,
+    KwCase,
+    KwWhen,
+    KwIn,
     KwBreak,
+    KwNext,
     KwThen,
     KwElse,
     KwFn,
@@ -79,6 +99,15 @@ impl Token {
         Token::Number(s.to_string())
     }
 
+    /// Return true if this token is a modifier of a statement when it
+    /// follows one (`foo if bar`, `foo unless bar`)
+    pub fn is_modifier(&self) -> bool {
+        match self {
+            Token::KwIf | Token::KwUnless => true,
+            _ => false,
+        }
+    }
+
     /// Return true if a value may start with this token
     ///
     /// Must not be called on `Token::Space`
@@ -93,6 +122,9 @@ impl Token {
             Token::IVar(_) => true,
             Token::Number(_) => true,
             Token::Str(_) => true,
+            Token::Char(_) => true,
+            Token::Regexp(_) => true,
+            Token::Command(_) => true,
             // Symbols
             Token::LParen => true,        //  (
             Token::RParen => false,       //  )
@@ -109,6 +141,7 @@ impl Token {
             Token::Div => false,          //  /
             Token::Mod => false,          //  %
             Token::EqEq => false,         //  ==
+            Token::EqTilde => false,      //  =~
             Token::NotEq => false,        //  !=
             Token::LessThan => false,     //  <
             Token::GreaterThan => false,  //  >
@@ -117,6 +150,8 @@ impl Token {
             Token::Equal => false,        //  =
             Token::Bang => true,          //  !
             Token::Dot => false,          //  .
+            Token::DotDot => false,       //  ..
+            Token::DotDotDot => false,    //  ...
             Token::At => true,            //  @
             Token::Tilde => true,         //  ~
             Token::Question => false,     //  ?
@@ -132,10 +167,17 @@ impl Token {
             Token::RShift => false,       //  >>
             Token::UPlusMethod => false,  //  +@
             Token::UMinusMethod => false, //  -@
+            Token::GetMethod => false,    //  []
+            Token::SetMethod => false,    //  []=
+            Token::Spaceship => false,    //  <=>
             // Keywords
             Token::KwClass => false,
             Token::KwEnd => false,
             Token::KwDef => false,
+            Token::KwAbstract => false,
+            Token::KwExtern => false,
+            Token::KwAsync => false,
+            Token::KwAwait => true,
             Token::KwVar => false,
             Token::KwAnd => false,
             Token::KwOr => false,
@@ -143,7 +185,14 @@ impl Token {
             Token::KwIf => true,
             Token::KwUnless => true,
             Token::KwWhile => true,
+            Token::KwFor => true,
+            Token::KwLoop => true,
+            Token::KwDo => false,
+            Token::KwCase => true,
+            Token::KwWhen => false,
+            Token::KwIn => false,
             Token::KwBreak => false,
+            Token::KwNext => false,
             Token::KwThen => false,
             Token::KwElse => false,
             Token::KwFn => true,
@@ -0,0 +1,303 @@
+/// OS-dependent parts of the compiler driver
+///
+/// Only the driver settings (how the executable is linked and named) are
+/// here. The runtime (I/O, time, threads and processes in code_gen/*.rs)
+/// still calls the POSIX functions directly, and Windows builds are not
+/// tested end to end.
+use crate::error::{plain_runner_error, Error};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Major versions of LLVM which Shiika can be built with and whose tools
+/// (llc, clang) can compile the generated IR
+pub const SUPPORTED_LLVM_VERSIONS: &[u32] = &[7];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Platform {
+    Linux,
+    MacOS,
+    /// Windows with the MSVC toolchain
+    Windows,
+}
+
+impl Platform {
+    /// The platform the compiler is running on
+    pub fn host() -> Platform {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOS
+        } else {
+            Platform::Linux
+        }
+    }
+
+    /// The value of `PLATFORM` in the programs (see hir/config.rs)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Linux => "linux",
+            Platform::MacOS => "macos",
+            Platform::Windows => "windows",
+        }
+    }
+
+    /// Path of the executable compiled from `sk_path`
+    pub fn exe_path(&self, sk_path: &str) -> PathBuf {
+        let suffix = match self {
+            Platform::Windows => ".exe",
+            _ => ".out",
+        };
+        PathBuf::from(format!("{}{}", sk_path, suffix))
+    }
+
+    /// Arguments given to clang to link the executable
+    pub fn link_args(&self) -> Vec<&'static str> {
+        match self {
+            Platform::Linux => vec!["-no-pie", "-lm", "-lgc"],
+            // libm is a part of libSystem
+            Platform::MacOS => vec!["-lgc"],
+            // `printf` is defined inline in the headers of the Universal CRT,
+            // so the library is needed to link it from LLVM IR
+            Platform::Windows => vec!["-lgc", "-llegacy_stdio_definitions"],
+        }
+    }
+}
+
+/// The programs used to make an executable from .ll
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Toolchain {
+    /// llc and clang (with the MSVC libraries on Windows)
+    Clang,
+    /// llc and gcc of MinGW-w64
+    MinGW,
+    /// llc and lld-link (with the MSVC libraries)
+    LldLink,
+    /// llc and musl-gcc (makes fully static executables on Linux)
+    Musl,
+}
+
+impl Toolchain {
+    /// Select the toolchain for `platform`. `static_link` is true when
+    /// `--static` is given
+    ///
+    /// Can be specified by `SHIIKA_TOOLCHAIN` (`clang`, `mingw`, `lld` or
+    /// `musl`). On Windows, the first one found in `PATH` is used
+    pub fn detect(platform: Platform, static_link: bool) -> Result<Toolchain, Error> {
+        if static_link {
+            return if platform == Platform::Linux {
+                Ok(Toolchain::Musl)
+            } else {
+                Err(plain_runner_error("--static is only supported on Linux"))
+            };
+        }
+        if let Ok(name) = env::var("SHIIKA_TOOLCHAIN") {
+            return match name.as_str() {
+                "clang" => Ok(Toolchain::Clang),
+                "mingw" => Ok(Toolchain::MinGW),
+                "lld" => Ok(Toolchain::LldLink),
+                "musl" => Ok(Toolchain::Musl),
+                _ => Err(plain_runner_error(format!(
+                    "unknown SHIIKA_TOOLCHAIN: {} (expected clang, mingw, lld or musl)",
+                    name
+                ))),
+            };
+        }
+        if platform != Platform::Windows || find_llvm_tool("clang").is_ok() {
+            Ok(Toolchain::Clang)
+        } else if find_in_path("gcc").is_some() {
+            Ok(Toolchain::MinGW)
+        } else if find_llvm_tool("lld-link").is_ok() {
+            Ok(Toolchain::LldLink)
+        } else {
+            Err(plain_runner_error(
+                "clang, gcc or lld-link not found (install LLVM or MinGW-w64)",
+            ))
+        }
+    }
+
+    /// Arguments given to llc
+    pub fn llc_args(&self) -> Vec<&'static str> {
+        match self {
+            Toolchain::Clang => vec![],
+            Toolchain::MinGW => vec!["-mtriple=x86_64-w64-windows-gnu"],
+            // lld-link does not take assembly
+            Toolchain::LldLink => vec!["-filetype=obj"],
+            Toolchain::Musl => vec![
+                "-mtriple=x86_64-unknown-linux-musl",
+                "-relocation-model=static",
+            ],
+        }
+    }
+
+    /// Extension of the file made by llc
+    pub fn llc_output_ext(&self) -> &'static str {
+        match self {
+            Toolchain::LldLink => ".obj",
+            _ => ".s",
+        }
+    }
+
+    /// Arguments given to llc to make an object file (instead of an
+    /// assembly)
+    pub fn llc_object_args(&self) -> Vec<&'static str> {
+        let mut args = self.llc_args();
+        if !args.contains(&"-filetype=obj") {
+            args.push("-filetype=obj");
+        }
+        args
+    }
+
+    /// Extension of the object files
+    pub fn object_ext(&self) -> &'static str {
+        match self {
+            Toolchain::LldLink => ".obj",
+            _ => ".o",
+        }
+    }
+
+    /// The linker. Can be overridden by `CLANG`, `CC` or `MUSL_CC`
+    pub fn linker(&self) -> Result<PathBuf, Error> {
+        match self {
+            Toolchain::Clang => llvm_tool("CLANG", "clang"),
+            Toolchain::MinGW => Ok(PathBuf::from(
+                env::var("CC").unwrap_or_else(|_| "gcc".to_string()),
+            )),
+            Toolchain::LldLink => find_llvm_tool("lld-link"),
+            Toolchain::Musl => Ok(PathBuf::from(
+                env::var("MUSL_CC").unwrap_or_else(|_| "musl-gcc".to_string()),
+            )),
+        }
+    }
+
+    /// Arguments given to the linker to make `out_path` from `input_paths`
+    pub fn link_args(
+        &self,
+        platform: Platform,
+        input_paths: &[&str],
+        out_path: &Path,
+    ) -> Vec<String> {
+        let out = out_path.to_string_lossy().to_string();
+        let inputs = input_paths
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        let (before, after) = match self {
+            Toolchain::Clang => {
+                let mut args = platform
+                    .link_args()
+                    .into_iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>();
+                args.extend(vec!["-o".to_string(), out]);
+                (args, vec![])
+            }
+            // msvcrt provides libm
+            Toolchain::MinGW => (vec!["-o".to_string(), out], vec!["-lgc"]),
+            // The directories of the libraries are given by `LIB`
+            // (set by vcvars.bat)
+            Toolchain::LldLink => (
+                vec![format!("/out:{}", out), "/subsystem:console".to_string()],
+                vec!["gc.lib", "libcmt.lib", "legacy_stdio_definitions.lib"],
+            ),
+            // libgc.a (built with musl) needs libpthread
+            Toolchain::Musl => (
+                vec!["-static".to_string(), "-o".to_string(), out],
+                vec!["-lgc", "-lpthread", "-lm"],
+            ),
+        };
+        before
+            .into_iter()
+            .chain(inputs)
+            .chain(after.into_iter().map(|s| s.to_string()))
+            .collect()
+    }
+}
+
+/// Return the path of an LLVM tool, or the value of `env_name` if set
+pub fn llvm_tool(env_name: &str, name: &str) -> Result<PathBuf, Error> {
+    match env::var(env_name) {
+        Ok(path) => Ok(PathBuf::from(path)),
+        Err(_) => find_llvm_tool(name),
+    }
+}
+
+/// Find an LLVM tool (eg. `llc`)
+///
+/// If `SHIIKA_LLVM_DIR` is set (eg. to a prebuilt LLVM), it is searched in
+/// `$SHIIKA_LLVM_DIR/bin`. Otherwise it is searched in the LLVM Shiika is
+/// built with (`LLVM_SYS_70_PREFIX`) and then the names in `llvm_tool_names`
+/// are searched in `PATH`
+pub fn find_llvm_tool(name: &str) -> Result<PathBuf, Error> {
+    if let Some(dir) = env::var_os("SHIIKA_LLVM_DIR") {
+        let path = Path::new(&dir).join("bin").join(exe_name(name));
+        return if path.is_file() {
+            Ok(path)
+        } else {
+            Err(plain_runner_error(format!(
+                "{} not found in SHIIKA_LLVM_DIR ({})",
+                name,
+                path.display()
+            )))
+        };
+    }
+    if let Some(dir) = option_env!("LLVM_SYS_70_PREFIX") {
+        let path = Path::new(dir).join("bin").join(exe_name(name));
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+    llvm_tool_names(name)
+        .iter()
+        .find_map(|cand| find_in_path(cand))
+        .ok_or_else(|| {
+            plain_runner_error(format!(
+                "{} not found (supported LLVM versions: {}). Install LLVM or set SHIIKA_LLVM_DIR",
+                name,
+                SUPPORTED_LLVM_VERSIONS
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })
+}
+
+/// Names of an LLVM tool to search for, in the order of preference
+/// (eg. `llc-7`, `llc`)
+pub fn llvm_tool_names(name: &str) -> Vec<String> {
+    let mut names = SUPPORTED_LLVM_VERSIONS
+        .iter()
+        .rev()
+        .map(|v| format!("{}-{}", name, v))
+        .collect::<Vec<_>>();
+    names.push(name.to_string());
+    names
+}
+
+/// Return the path of the command if found in `PATH`
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file_name = exe_name(name);
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+fn exe_name(name: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Return the path to execute the file at `path`
+///
+/// A bare file name is looked up in `PATH` (and on Windows, in the current
+/// directory too) so it is explicitly made relative to the current directory
+pub fn command_path(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new(".").join(path),
+        _ => path.to_path_buf(),
+    }
+}
@@ -1,66 +1,552 @@
+use crate::ast;
+use crate::build_cache;
+use crate::build_plan::{self, BuildPlan};
+use crate::builtin_cache;
+use crate::code_gen::{BuiltinIndex, Part};
 use crate::error::*;
-use std::env;
+use crate::hir::call_graph::{self, CallGraph};
+use crate::hir::config::BuildConfig;
+use crate::hir::warnings::{self, Warning};
+use crate::hir::Hir;
+use crate::parser::lexer::Cursor;
+use crate::platform::{self, Platform, Toolchain};
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// Environment variable which gives the seed of the default `Random` to
+/// the programs (`--seed`; see builtin/random.sk)
+pub const SEED_ENV: &str = "SHIIKA_SEED";
+
 /// Generate .ll from .sk
-pub fn compile<P: AsRef<Path>>(filepath: P) -> Result<(), Box<dyn std::error::Error>> {
+///
+/// If `verify` is true, the generated IR is checked with the LLVM verifier
+pub fn compile<P: AsRef<Path>>(
+    filepath: P,
+    verify: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    compile_with_dumps(
+        filepath,
+        verify,
+        &Dumps::default(),
+        false,
+        &BuildConfig::default(),
+        1,
+    )
+}
+
+/// What to print to stdout while compiling (`--dump-ast`, `--dump-hir`,
+/// `--dump-llvm` and `--dump-docs`)
+#[derive(Debug, Default)]
+pub struct Dumps {
+    pub ast: bool,
+    pub hir: bool,
+    pub llvm: bool,
+    /// Doc comments in JSON (see hir/docs.rs)
+    pub docs: bool,
+}
+
+/// Same as `compile` but also prints the intermediate representations.
+/// If `deny_warnings` is true, warnings are reported as an error. `config`
+/// is given by `--release` (which removes the assertions and the recording
+/// of the backtrace) and `--define` (see hir/config.rs.) The methods are
+/// generated in `jobs` threads (`--jobs`).
+///
+/// Nothing is done if the .ll is up to date (see build_cache.rs)
+pub fn compile_with_dumps<P: AsRef<Path>>(
+    filepath: P,
+    verify: bool,
+    dumps: &Dumps,
+    deny_warnings: bool,
+    config: &BuildConfig,
+    jobs: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
     let path = filepath
         .as_ref()
         .to_str()
         .expect("failed to unwrap filepath")
         .to_string();
     let builtin = load_builtin()?;
-    let str = builtin
-        + &fs::read_to_string(filepath)
-            .map_err(|e| runner_error(format!("{} is not utf8", path), e))?;
-    let ast = crate::parser::Parser::parse(&str)?;
-    let corelib = crate::corelib::Corelib::create();
-    let hir = crate::hir::build(ast, corelib)?;
-    crate::code_gen::run(&hir, &(path + ".ll"))?;
+    let src = read_source(&path)?;
+    let ll_path = path + ".ll";
+    let cache_key = build_cache::key(&src, &builtin, verify, config);
+    let use_cache =
+        builtin_cache::enabled() && !(dumps.ast || dumps.hir || dumps.llvm || dumps.docs);
+    if use_cache && build_cache::is_fresh(&ll_path, &cache_key) {
+        return Ok(());
+    }
+
+    let ast = parse_program(&src, &builtin, config)?;
+    if dumps.ast {
+        // Omit the builtin library
+        let n_builtin_items = crate::parser::Parser::parse(&builtin)?.toplevel_items.len();
+        println!("{:#?}", &ast.toplevel_items[n_builtin_items..]);
+    }
+    let hir = build_hir(ast, &builtin, config)?;
+    let warnings = user_warnings(&hir, &builtin)?;
+    report_warnings(&hir, &builtin, &warnings);
+    if deny_warnings && !warnings.is_empty() {
+        return Err(Box::new(plain_runner_error(format!(
+            "{} warning(s) (denied by --deny-warnings)",
+            warnings.len()
+        ))));
+    }
+    if dumps.hir {
+        let include = user_defined(&hir, &builtin)?;
+        print!(
+            "{}",
+            crate::hir::pretty_print::hir_to_string(&hir, &include)
+        );
+    }
+    if dumps.docs {
+        let include = user_defined(&hir, &builtin)?;
+        let base = Cursor::end_of(&builtin);
+        print!("{}", crate::hir::docs::to_json(&hir, include, &base));
+    }
+    build_cache::invalidate(&ll_path);
+    gen_ll(&hir, &builtin, &ll_path, verify, jobs)?;
+    if use_cache && warnings.is_empty() {
+        build_cache::save(&ll_path, &cache_key)?;
+    }
+    if dumps.llvm {
+        print!(
+            "{}",
+            fs::read_to_string(&ll_path)
+                .map_err(|e| runner_error(format!("failed to read {}", ll_path), e))?
+        );
+    }
+    Ok(())
+}
+
+/// Generate .ll from the HIR written by `--emit hir`
+/// (`shiika compile --from-hir`)
+pub fn compile_hir<P: AsRef<Path>>(
+    filepath: P,
+    verify: bool,
+    jobs: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = filepath
+        .as_ref()
+        .to_str()
+        .expect("failed to unwrap filepath")
+        .to_string();
+    let json = fs::read_to_string(&path)
+        .map_err(|e| runner_error(format!("failed to read {}", path), e))?;
+    let hir = crate::hir::serialize::deserialize(&json)
+        .map_err(|e| plain_runner_error(format!("failed to load {}: {}", path, e)))?;
+    let ll_path = path + ".ll";
+    build_cache::invalidate(&ll_path);
+    crate::code_gen::run(&hir, &ll_path, verify, jobs)?;
     Ok(())
 }
 
-fn load_builtin() -> Result<String, Box<dyn std::error::Error>> {
+/// Generate the .ll of the program. The builtin library is linked from
+/// the cache if possible (see builtin_cache.rs)
+fn gen_ll(
+    hir: &Hir,
+    builtin: &str,
+    ll_path: &str,
+    verify: bool,
+    jobs: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if builtin_cache::enabled() {
+        let cache = builtin_cache::load(builtin)?;
+        if links_builtin_cache(hir, builtin, &cache.index) {
+            let part = Part::User(&cache.index);
+            return crate::code_gen::run_part(hir, ll_path, verify, part, &cache.header(), jobs);
+        }
+    }
+    crate::code_gen::run(hir, ll_path, verify, jobs)?;
+    Ok(())
+}
+
+/// Returns true if the compiled builtin library of `index` can be linked
+/// with the program. The whole program is compiled if the builtin classes
+/// are reopened
+fn links_builtin_cache(hir: &Hir, builtin: &str, index: &BuiltinIndex) -> bool {
+    let base = Cursor::end_of(builtin);
+    let redefined = |name: &str| {
+        hir.def_spans
+            .get(name)
+            .map_or(false, |span| span.begin.pos() >= base.pos())
+    };
+    index.covers(hir, redefined)
+}
+
+/// Options for `compile_str`
+#[derive(Debug)]
+pub struct CompileOptions {
+    /// Check the generated IR with the LLVM verifier
+    pub verify: bool,
+    /// Report warnings as an error
+    pub deny_warnings: bool,
+    /// Directory of the builtin library (default: `builtin`)
+    pub builtin_dir: PathBuf,
+    /// Number of the threads to generate the methods (default: 1)
+    pub jobs: usize,
+    /// Remove the assertions and the recording of the backtrace (see
+    /// hir/assertions.rs and code_gen/backtrace.rs)
+    pub release: bool,
+    /// The compile-time constants given with `--define` (see
+    /// hir/config.rs)
+    pub defines: Vec<(String, String)>,
+    /// The maximum nesting level of the program in the parser (see
+    /// `BuildConfig`)
+    pub max_parse_nesting: usize,
+    /// The maximum nesting level of the expressions in the type checker
+    pub max_type_nesting: usize,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        let config = BuildConfig::default();
+        CompileOptions {
+            verify: false,
+            deny_warnings: false,
+            builtin_dir: PathBuf::from("builtin"),
+            jobs: 1,
+            release: false,
+            defines: vec![],
+            max_parse_nesting: config.max_parse_nesting,
+            max_type_nesting: config.max_type_nesting,
+        }
+    }
+}
+
+/// A program compiled by `compile_str`
+#[derive(Debug)]
+pub struct CompiledModule {
+    /// The program in LLVM IR
+    pub llvm_ir: String,
+    /// Warnings for the program
+    pub warnings: Vec<Warning>,
+}
+
+impl CompiledModule {
+    /// Return the program in LLVM bitcode
+    pub fn bitcode(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        crate::code_gen::llvm_ir_to_bitcode(&self.llvm_ir)
+    }
+
+    /// Execute the program in the current process with the JIT compiler
+    /// of LLVM and return the exit status. `args` are the command line
+    /// arguments (excluding the program name.)
+    ///
+    /// The functions the program calls (bdwgc and the libc) are looked up
+    /// from the current process, so it must be linked with them. Note that
+    /// `exit` in the program terminates the current process
+    pub fn run_jit(&self, args: &[&str]) -> Result<i32, Box<dyn std::error::Error>> {
+        crate::code_gen::run_llvm_ir_jit(&self.llvm_ir, args)
+    }
+}
+
+/// Compile the source of a program without touching the files (except
+/// the builtin library.) Errors and warnings are not printed, and the
+/// spans in them are the ones in `src`
+pub fn compile_str(
+    src: &str,
+    options: &CompileOptions,
+) -> Result<CompiledModule, Box<dyn std::error::Error>> {
+    let builtin = load_builtin_from(&options.builtin_dir)?;
+    let config = BuildConfig {
+        release: options.release,
+        defines: options.defines.clone(),
+        max_parse_nesting: options.max_parse_nesting,
+        max_type_nesting: options.max_type_nesting,
+        ..BuildConfig::default()
+    };
+    let ast = parse_program(src, &builtin, &config)?;
+    let hir = build_hir(ast, &builtin, &config)?;
+    let warnings = user_warnings(&hir, &builtin)?;
+    if options.deny_warnings && !warnings.is_empty() {
+        return Err(Box::new(plain_runner_error(format!(
+            "{} warning(s) (denied by deny_warnings)",
+            warnings.len()
+        ))));
+    }
+    let llvm_ir = crate::code_gen::gen_llvm_ir(&hir, options.verify, options.jobs)?;
+    Ok(CompiledModule { llvm_ir, warnings })
+}
+
+/// Return information about the program instead of compiling it
+/// (`shiika compile --emit KIND`)
+///
+/// - `callgraph`: call graph and class dependencies in the DOT language
+/// - `callgraph-json`: same as above in JSON
+/// - `layout`: memory layout of the objects of the classes
+/// - `plan`: the steps to make the executable in JSON (see build_plan.rs)
+/// - `hir`: the HIR in JSON, which can be compiled with `compile_hir`
+///   (see hir/serialize.rs)
+/// - `llvm-ir`: the LLVM IR of the whole program with the lines of the
+///   methods in comments (see code_gen/source_map.rs)
+pub fn emit<P: AsRef<Path>>(filepath: P, kind: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path = filepath
+        .as_ref()
+        .to_str()
+        .expect("failed to unwrap filepath")
+        .to_string();
+    let (hir, builtin) = load_hir(&path)?;
+    match kind {
+        "callgraph" | "callgraph-json" => {
+            let mut graph = CallGraph::new(&hir);
+            // Omit the edges of the builtin library
+            let include = user_defined(&hir, &builtin)?;
+            graph.retain(|name| name == call_graph::MAIN || include(name));
+            if kind == "callgraph" {
+                Ok(graph.to_dot())
+            } else {
+                Ok(graph.to_json())
+            }
+        }
+        "layout" => {
+            // Omit the classes of the builtin library
+            let include = user_defined(&hir, &builtin)?;
+            Ok(crate::code_gen::class_layouts(&hir)?
+                .iter()
+                .filter(|layout| include(&layout.name.0))
+                .map(|layout| layout.to_string())
+                .collect())
+        }
+        "plan" => {
+            let builtin_bitcode = if builtin_cache::enabled()
+                && links_builtin_cache(&hir, &builtin, &builtin_cache::index(&builtin)?)
+            {
+                Some(builtin_cache::bitcode_path(&builtin))
+            } else {
+                None
+            };
+            let platform = Platform::host();
+            let plan = BuildPlan::new(
+                &path,
+                builtin_files("builtin")?,
+                builtin_bitcode,
+                platform,
+                Toolchain::detect(platform, false)?,
+            )?;
+            Ok(plan.to_json())
+        }
+        "hir" => Ok(crate::hir::serialize::serialize(&hir)),
+        "llvm-ir" => {
+            let ir = crate::code_gen::gen_llvm_ir(&hir, false, 1)?;
+            let src = read_source(&path)?;
+            let base = Cursor::end_of(&builtin);
+            Ok(crate::code_gen::annotate_llvm_ir(
+                &ir,
+                &hir.def_spans,
+                &path,
+                &src,
+                &base,
+            ))
+        }
+        _ => Err(Box::new(plain_runner_error(format!(
+            "unknown kind for --emit: {}",
+            kind
+        )))),
+    }
+}
+
+/// Parse and type check the program. Also returns the source of the
+/// builtin library
+fn load_hir(path: &str) -> Result<(Hir, String), Box<dyn std::error::Error>> {
+    let builtin = load_builtin()?;
+    let ast = load_ast(path, &builtin)?;
+    let hir = build_hir(ast, &builtin, &BuildConfig::default())?;
+    Ok((hir, builtin))
+}
+
+/// Parse the program (with the builtin library prepended)
+fn load_ast(path: &str, builtin: &str) -> Result<ast::Program, Box<dyn std::error::Error>> {
+    let src = read_source(path)?;
+    parse_program(&src, builtin, &BuildConfig::default())
+}
+
+/// Read the source of the program
+fn read_source(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let src =
+        fs::read_to_string(path).map_err(|e| runner_error(format!("{} is not utf8", path), e))?;
+    Ok(src)
+}
+
+/// Parse the source of a program (with the builtin library prepended)
+pub(crate) fn parse_program(
+    src: &str,
+    builtin: &str,
+    config: &BuildConfig,
+) -> Result<ast::Program, Box<dyn std::error::Error>> {
+    let base = Cursor::end_of(builtin);
+    let str = builtin.to_string() + src;
+    let ast = crate::parser::Parser::parse_with_max_nesting(&str, config.max_parse_nesting)
+        .map_err(|e| in_user_file(e, &base))?;
+    Ok(ast)
+}
+
+pub(crate) fn build_hir(
+    ast: ast::Program,
+    builtin: &str,
+    config: &BuildConfig,
+) -> Result<Hir, Box<dyn std::error::Error>> {
+    let base = Cursor::end_of(builtin);
+    let corelib = crate::corelib::Corelib::create(&ast);
+    let hir =
+        crate::hir::build_with_config(ast, corelib, config).map_err(|e| in_user_file(e, &base))?;
+    Ok(hir)
+}
+
+/// Return a function which tells if the class, the method (eg. `A#foo`)
+/// or the constant (eg. `::A`) is defined in the user's program, not in
+/// the builtin library
+fn user_defined<'a>(
+    hir: &'a Hir,
+    builtin: &str,
+) -> Result<impl Fn(&str) -> bool + 'a, Box<dyn std::error::Error>> {
+    let base = Cursor::end_of(builtin);
+    let builtin_consts = toplevel_consts(&crate::parser::Parser::parse(builtin)?);
+    Ok(move |name: &str| {
+        match hir
+            .def_spans
+            .get(call_graph::class_of(name).unwrap_or(name))
+        {
+            Some(span) => span.begin.pos() >= base.pos(),
+            None => name.starts_with("::") && !builtin_consts.contains(name),
+        }
+    })
+}
+
+/// Return the warnings for the user's program (the ones in the builtin
+/// library are omitted)
+fn user_warnings(hir: &Hir, builtin: &str) -> Result<Vec<Warning>, Box<dyn std::error::Error>> {
+    let include = user_defined(hir, builtin)?;
+    Ok(hir
+        .warnings
+        .iter()
+        .cloned()
+        .chain(warnings::unused_methods(hir))
+        .chain(warnings::large_items(hir))
+        .chain(warnings::eq_without_hash(hir))
+        .filter(|w| w.place == call_graph::MAIN || include(&w.place))
+        .collect())
+}
+
+/// Print the warnings to stderr with the line of the method (or the
+/// constant) where they are found
+fn report_warnings(hir: &Hir, builtin: &str, warnings: &[Warning]) {
+    let base = Cursor::end_of(builtin);
+    for warning in warnings {
+        eprintln!("{}", warning);
+        let span = hir
+            .def_spans
+            .get(&warning.place)
+            .and_then(|span| span.relative_to(&base));
+        if let Some(span) = span {
+            eprintln!(
+                "  --> line {}:{}",
+                span.begin.line() + 1,
+                span.begin.col() + 1
+            );
+        }
+    }
+}
+
+/// Return the full names of the constants assigned at the toplevel
+/// (eg. `::X` for `X = 1`)
+fn toplevel_consts(ast: &ast::Program) -> HashSet<String> {
+    ast.toplevel_items
+        .iter()
+        .filter_map(|item| match item {
+            ast::TopLevelItem::Expr(ast::AstExpression {
+                body: ast::AstExpressionBody::ConstAssign { names, .. },
+                ..
+            }) => Some(format!("::{}", names.join("::"))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Convert the span of `err` into the one in the user's file (the span is
+/// removed if it is in the builtin library)
+fn in_user_file(mut err: Error, base: &Cursor) -> Error {
+    err.span = err.span.and_then(|span| span.relative_to(base));
+    err
+}
+
+/// Return the source of the builtin library (which is prepended to programs)
+pub fn load_builtin() -> Result<String, Box<dyn std::error::Error>> {
+    load_builtin_from("builtin")
+}
+
+/// Same as `load_builtin` but reads the .sk files in `dir_path`
+pub fn load_builtin_from<P: AsRef<Path>>(
+    dir_path: P,
+) -> Result<String, Box<dyn std::error::Error>> {
     let mut s = String::new();
-    let dir = fs::read_dir("builtin").map_err(|e| runner_error("./builtin not found", e))?;
-    for item in dir {
-        let pathbuf = item?.path();
+    for pathbuf in builtin_files(dir_path)? {
         let path = pathbuf
             .to_str()
             .ok_or_else(|| plain_runner_error("Filename not utf8"))?;
-        if path.ends_with(".sk") {
-            s += &fs::read_to_string(path)
-                .map_err(|e| runner_error(format!("failed to load {}", path), e))?;
-        }
+        s += &fs::read_to_string(path)
+            .map_err(|e| runner_error(format!("failed to load {}", path), e))?;
     }
     Ok(s)
 }
 
-/// Execute compiled .ll
-pub fn run<P: AsRef<Path>>(sk_path: P) -> Result<(), Box<dyn std::error::Error>> {
-    run_(sk_path, false)?;
-    Ok(())
+/// Return the .sk files in `dir_path` in the order they are loaded
+fn builtin_files<P: AsRef<Path>>(dir_path: P) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let dir = fs::read_dir(&dir_path)
+        .map_err(|e| runner_error(format!("{} not found", dir_path.as_ref().display()), e))?;
+    // The order of `read_dir` differs between platforms
+    let mut pathbufs = dir
+        .map(|item| item.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    pathbufs.retain(|path| path.extension().map_or(false, |ext| ext == "sk"));
+    pathbufs.sort();
+    Ok(pathbufs)
+}
+
+/// Execute compiled .ll and return the exit status of the program
+///
+/// If `static_link` is true, the executable is linked statically with musl
+pub fn run<P: AsRef<Path>>(
+    sk_path: P,
+    static_link: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let out_path = build_exe(sk_path, static_link)?;
+    let mut cmd = Command::new(platform::command_path(&out_path));
+    // Enable `breakpoint` (see code_gen/debugger.rs)
+    cmd.env(crate::code_gen::DEBUGGER_ENV, "1");
+    let status = cmd
+        .status()
+        .map_err(|e| runner_error("failed to execute process", e))?;
+    // No code if killed by a signal
+    Ok(status.code().unwrap_or(1))
 }
 
 /// Execute compiled .ll and return the outputs
 pub fn run_and_capture<P: AsRef<Path>>(
     sk_path: P,
 ) -> Result<(String, String), Box<dyn std::error::Error>> {
-    run_(sk_path, true)
+    let out_path = build_exe(sk_path, false)?;
+    let output = Command::new(platform::command_path(&out_path))
+        .output()
+        .map_err(|e| runner_error("failed to execute process", e))?;
+    let stdout = String::from_utf8(output.stdout).expect("invalid utf8 in stdout");
+    let stderr = String::from_utf8(output.stderr).expect("invalid utf8 in stderr");
+    Ok((stdout, stderr))
 }
 
-fn run_<P: AsRef<Path>>(
+/// Make the executable from the .ll and return its path
+fn build_exe<P: AsRef<Path>>(
     sk_path: P,
-    capture_out: bool,
-) -> Result<(String, String), Box<dyn std::error::Error>> {
+    static_link: bool,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let s = sk_path.as_ref().to_str().expect("failed to unwrap sk_path");
     let ll_path = s.to_string() + ".ll";
     //let opt_ll_path = s.to_string() + ".opt.ll";
     //let bc_path = s.to_string() + ".bc";
-    let asm_path = s.to_string() + ".s";
-    let out_path = s.to_string() + ".out";
+    let platform = Platform::host();
+    let toolchain = Toolchain::detect(platform, static_link)?;
+    let obj_path = s.to_string() + toolchain.llc_output_ext();
+    let out_path = platform.exe_path(s);
 
     //    let mut cmd = Command::new("opt");
     //    cmd.arg("-O3");
@@ -78,60 +564,46 @@ fn run_<P: AsRef<Path>>(
     //    cmd.arg(opt_ll_path);
     //    cmd.output()?;
 
-    let mut cmd = Command::new(env::var("LLC").unwrap_or_else(|_| "llc".to_string()));
-    cmd.arg(ll_path);
-    cmd.output()
+    let llc = build_plan::llc_step(toolchain, Path::new(&ll_path), Path::new(&obj_path))?;
+    let status = llc
+        .command()
+        .status()
         .map_err(|e| runner_error("failed to run llc", e))?;
-    if !cmd.status()?.success() {
+    if !status.success() {
         return Err(Box::new(plain_runner_error("llc failed")));
     }
 
-    let mut cmd = Command::new(env::var("CLANG").unwrap_or_else(|_| "clang".to_string()));
-    add_args_from_env(&mut cmd, "CFLAGS");
-    add_args_from_env(&mut cmd, "LDFLAGS");
-    add_args_from_env(&mut cmd, "LDLIBS");
-    cmd.arg("-no-pie");
-    cmd.arg("-lm");
-    cmd.arg("-lgc");
-    cmd.arg("-o");
-    cmd.arg(out_path.clone());
-    cmd.arg(asm_path.clone());
-    if !cmd.status()?.success() {
-        return Err(Box::new(plain_runner_error("clang failed")));
+    let mut inputs = vec![PathBuf::from(&obj_path)];
+    if let Some(bitcode_path) = builtin_cache::linked_bitcode(&ll_path)? {
+        inputs.push(builtin_cache::object_path(&bitcode_path, toolchain)?);
+    }
+
+    let link = build_plan::link_step(platform, toolchain, inputs, &out_path)?;
+    let status = link
+        .command()
+        .status()
+        .map_err(|e| runner_error(format!("failed to run {}", link.program.display()), e))?;
+    if !status.success() {
+        return Err(Box::new(plain_runner_error(format!(
+            "{} failed",
+            link.program.display()
+        ))));
     }
 
     //fs::remove_file(bc_path)?;
-    fs::remove_file(asm_path).map_err(|e| runner_error("failed to remove .s", e))?;
+    fs::remove_file(&obj_path)
+        .map_err(|e| runner_error(format!("failed to remove {}", obj_path), e))?;
 
-    let mut cmd = Command::new(format!("./{}", out_path.to_string()));
-    if capture_out {
-        let output = cmd
-            .output()
-            .map_err(|e| runner_error("failed to execute process", e))?;
-        let stdout = String::from_utf8(output.stdout).expect("invalid utf8 in stdout");
-        let stderr = String::from_utf8(output.stderr).expect("invalid utf8 in stderr");
-        Ok((stdout, stderr))
-    } else {
-        cmd.status()?;
-        Ok(("".to_string(), "".to_string()))
-    }
+    Ok(out_path)
 }
 
-/// Remove .ll and .out
+/// Remove .ll (and its stamp, if any) and the executable
 pub fn cleanup<P: AsRef<Path>>(sk_path: P) -> Result<(), Box<dyn std::error::Error>> {
     let s = sk_path.as_ref().to_str().expect("failed to unwrap sk_path");
     let ll_path = s.to_string() + ".ll";
-    let out_path = s.to_string() + ".out";
+    let out_path = Platform::host().exe_path(s);
+    build_cache::invalidate(&ll_path);
     fs::remove_file(ll_path)?;
     fs::remove_file(out_path)?;
     Ok(())
 }
-
-fn add_args_from_env(cmd: &mut Command, key: &str) {
-    for arg in env::var(key)
-        .unwrap_or_else(|_| "".to_string())
-        .split_ascii_whitespace()
-    {
-        cmd.arg(arg);
-    }
-}
@@ -0,0 +1,257 @@
+/// Test runner (`shiika test`)
+///
+/// Compiles and runs `*_test.sk` and counts the lines printed by `test`
+/// (`PASS <name>` and `FAIL <name>`; see builtin/test.sk). Before compiling,
+/// `assert(...)`, `assert_eq(...)` and `panic(...)` are rewritten into
+/// `assert_at`, `assert_eq_at` and `panic_at` with the location of the call
+/// so that failures are reported with it.
+///
+/// The files are run in an order shuffled with a seed, which is also the
+/// seed of `Random` in the tests. It is printed when a test failed so that
+/// the run can be reproduced with `--seed`.
+use crate::error;
+use crate::parser::lexer::Lexer;
+use crate::parser::token::Token;
+use crate::runner;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The methods which are given the location of the call
+const ASSERTIONS: [&str; 3] = ["assert", "assert_eq", "panic"];
+
+#[derive(Debug, PartialEq)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub passed: Vec<String>,
+    pub failed: Vec<String>,
+    /// Stdout of the test program
+    pub output: String,
+    /// Set if the file could not be compiled or run
+    pub error: Option<String>,
+}
+
+/// Return the `*_test.sk` files in `paths` (files or directories, searched
+/// recursively), sorted in each directory
+pub fn collect_test_files(paths: &[&str]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = vec![];
+    for path in paths {
+        collect_test_files_(Path::new(path), &mut files)?;
+    }
+    Ok(files)
+}
+
+fn collect_test_files_(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !path.is_dir() {
+        if !path.exists() {
+            return Err(Box::new(error::plain_runner_error(format!(
+                "{} not found",
+                path.display()
+            ))));
+        }
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let entries = fs::read_dir(path)
+        .map_err(|e| error::runner_error(format!("failed to read {}", path.display()), e))?;
+    let mut paths = entries
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    for p in paths {
+        let name = p
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if p.is_dir() {
+            collect_test_files_(&p, files)?;
+        } else if name.ends_with("_test.sk") {
+            files.push(p);
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite the calls of `assert`, `assert_eq` and `panic` in `src` to pass
+/// the location (`path:line`). Lines are not changed
+pub fn instrument(src: &str, path: &str) -> String {
+    // A comment at the end of the file must be terminated by a newline
+    let src = format!("{}\n", src);
+    let tokens = Lexer::tokenize(&src);
+    let mut out = String::new();
+    let mut last = 0;
+    let mut prev_word = None;
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        if let Token::LowerWord(name) = token {
+            let is_call = match prev_word {
+                Some(Token::Dot) | Some(Token::KwDef) => false,
+                _ => ASSERTIONS.contains(&name.as_str()),
+            };
+            let loc = format!("\"{}:{}\"", path.replace('"', "'"), span.begin.line() + 1);
+            match (is_call, tokens.get(i + 1), tokens.get(i + 2)) {
+                // `assert(x)` => `assert_at("a.sk:1", x)`
+                (true, Some((Token::LParen, paren)), _) => {
+                    out += &src[last..span.end.pos()];
+                    out += &format!("_at({}, ", loc);
+                    last = paren.end.pos();
+                }
+                // `assert x` => `assert_at "a.sk:1", x`
+                (true, Some((Token::Space, _)), Some((next, _))) if begins_value(next) => {
+                    out += &src[last..span.end.pos()];
+                    out += &format!("_at {},", loc);
+                    last = span.end.pos();
+                }
+                _ => (),
+            }
+        }
+        if *token != Token::Space {
+            prev_word = Some(token.clone());
+        }
+    }
+    out += &src[last..src.len() - 1];
+    out
+}
+
+/// Whether an argument may begin with the token (without parentheses)
+fn begins_value(token: &Token) -> bool {
+    match token {
+        Token::UpperWord(_)
+        | Token::LowerWord(_)
+        | Token::IVar(_)
+        | Token::Number(_)
+        | Token::Str(_)
+        | Token::Char(_)
+        | Token::Regexp(_)
+        | Token::Command(_)
+        | Token::LParen
+        | Token::LSqBracket
+        | Token::UnaryMinus
+        | Token::Bang
+        | Token::KwSelf
+        | Token::KwTrue
+        | Token::KwFalse
+        | Token::KwFn => true,
+        _ => false,
+    }
+}
+
+/// Return the names of the passed tests and the failed tests in the output
+pub fn parse_output(output: &str) -> (Vec<String>, Vec<String>) {
+    let mut passed = vec![];
+    let mut failed = vec![];
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix("PASS ") {
+            passed.push(name.to_string());
+        } else if let Some(name) = line.strip_prefix("FAIL ") {
+            failed.push(name.to_string());
+        }
+    }
+    (passed, failed)
+}
+
+/// Compile and run a test file
+pub fn run_file(path: &Path) -> FileResult {
+    match run_file_(path) {
+        Ok(output) => {
+            let (passed, failed) = parse_output(&output);
+            FileResult {
+                path: path.to_path_buf(),
+                passed,
+                failed,
+                output,
+                error: None,
+            }
+        }
+        Err(e) => FileResult {
+            path: path.to_path_buf(),
+            passed: vec![],
+            failed: vec![],
+            output: "".to_string(),
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+fn run_file_(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let src = fs::read_to_string(path)
+        .map_err(|e| error::runner_error(format!("failed to read {}", path.display()), e))?;
+    let src = instrument(&src, &path.to_string_lossy());
+
+    // Compile the instrumented copy in the temporary directory
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = std::env::temp_dir().join(format!("{}_{}.sk", stem, std::process::id()));
+    fs::write(&tmp_path, src)
+        .map_err(|e| error::runner_error(format!("failed to write {}", tmp_path.display()), e))?;
+    let result = runner::compile(&tmp_path, false)
+        .and_then(|_| runner::run_and_capture(&tmp_path))
+        .and_then(|(stdout, _)| {
+            runner::cleanup(&tmp_path)?;
+            Ok(stdout)
+        });
+    let _ = fs::remove_file(&tmp_path);
+    result
+}
+
+/// Return a seed made from the current time (used if `--seed` is not
+/// given)
+pub fn random_seed() -> i32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    (nanos & 0x7fff_ffff) as i32
+}
+
+/// Shuffle the files. The order is the same for the same seed
+pub fn shuffle(files: &mut [PathBuf], seed: i32) {
+    // Same as builtin/random.sk
+    let mut state = if seed == 0 { 88172645 } else { seed as u32 };
+    for i in (1..files.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let j = (state & 0x7fff_ffff) as usize % (i + 1);
+        files.swap(i, j);
+    }
+}
+
+/// Run the test files in the order shuffled with `seed` and print the
+/// results. Returns the number of the passed tests and the failed ones
+/// (a file which cannot be compiled counts as a failure)
+pub fn run_tests(files: &[PathBuf], seed: i32) -> (usize, usize) {
+    let mut files = files.to_vec();
+    shuffle(&mut files, seed);
+    // The seed of `Random` in the tests
+    env::set_var(runner::SEED_ENV, seed.to_string());
+
+    let mut n_passed = 0;
+    let mut n_failed = 0;
+    for file in &files {
+        println!("# {}", file.display());
+        let result = run_file(file);
+        match &result.error {
+            Some(msg) => {
+                println!("ERROR {}", msg);
+                n_failed += 1;
+            }
+            None => print!("{}", result.output),
+        }
+        n_passed += result.passed.len();
+        n_failed += result.failed.len();
+    }
+    println!("\n{} passed, {} failed", n_passed, n_failed);
+    if n_failed > 0 {
+        println!("Randomized with seed {} (rerun with --seed {})", seed, seed);
+    }
+    (n_passed, n_failed)
+}
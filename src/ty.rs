@@ -81,6 +81,45 @@ impl TermTy {
         }
     }
 
+    /// Returns true for the type of `break`, etc. (which has no value)
+    pub fn is_never_type(&self) -> bool {
+        match self.body {
+            TyRaw => (self.fullname.0 == "Never"),
+            _ => false,
+        }
+    }
+
+    // Returns true when this is a type of non-generic class
+    pub fn is_raw(&self) -> bool {
+        match self.body {
+            TyRaw => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true when this is a type of native values (see `ty::native`)
+    pub fn is_native(&self) -> bool {
+        self.is_raw() && self.fullname.0.starts_with(NATIVE_PREFIX)
+    }
+
+    /// Returns the native type of the unboxed value of this type
+    /// (`Bool`, `Int` and `Float` only)
+    pub fn native_ty(&self) -> Option<TermTy> {
+        match self.fullname.0.as_str() {
+            "Bool" | "Int" | "Float" if self.is_raw() => Some(native(&self.fullname.0)),
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the boxed value of this native type
+    pub fn boxed_ty(&self) -> Option<TermTy> {
+        if self.is_native() {
+            Some(raw(&self.fullname.0[NATIVE_PREFIX.len()..]))
+        } else {
+            None
+        }
+    }
+
     pub fn meta_ty(&self) -> TermTy {
         match self.body {
             TyRaw => ty::meta(&self.fullname.0),
@@ -92,7 +131,7 @@ impl TermTy {
 
     pub fn conforms_to(&self, other: &TermTy) -> bool {
         if let TyParamRef { .. } = other.body {
-            return self == &ty::raw("Object"); // The upper bound
+            return self == other || self == &ty::raw("Object"); // The upper bound
         }
         // TODO: Should respect class hierarchy
         self.equals_to(other)
@@ -116,14 +155,42 @@ impl TermTy {
                 }
             }
             TyClass => Some(ty::raw("Object")),
+            // eg. `Object` for `Fn0<Void>`
+            TySpe { base_name, .. } => class_dict
+                .get_superclass(&class_fullname(base_name))
+                .map(|scls| ty::raw(&scls.fullname.0)),
+            // eg. `Meta:Object` for `Meta:Array<Int>`
+            TySpeMeta { base_name, .. } => {
+                match class_dict.get_superclass(&class_fullname(base_name)) {
+                    Some(scls) => Some(ty::meta(&scls.fullname.0)),
+                    None => Some(ty::class()),
+                }
+            }
+            // The upper bound of type parameters
+            TyParamRef { .. } => Some(ty::raw("Object")),
             _ => panic!("TODO"),
         }
     }
 
-    /// Apply type argments into type parameters
+    /// Apply type argments into type parameters (including the ones in
+    /// the type args, eg. `T` of `Fn0<T>`.) Type parameters without type
+    /// args (eg. the ones of a generic method) are left as is
     pub fn substitute(&self, type_args: &[TermTy]) -> TermTy {
+        self.replace_typarams(&|idx| type_args.get(idx).cloned())
+    }
+
+    /// Replace the type parameters for which `f` returns a type (`f` takes
+    /// the index of the type parameter)
+    fn replace_typarams(&self, f: &dyn Fn(usize) -> Option<TermTy>) -> TermTy {
         match &self.body {
-            TyParamRef { idx, .. } => type_args[*idx].clone(),
+            TyParamRef { idx, .. } => f(*idx).unwrap_or_else(|| self.clone()),
+            TySpe {
+                base_name,
+                type_args: args,
+            } => ty::spe(
+                base_name,
+                args.iter().map(|t| t.replace_typarams(f)).collect(),
+            ),
             _ => self.clone(),
         }
     }
@@ -135,6 +202,21 @@ impl TermTy {
         }
     }
 
+    /// Returns the number of the params if this is a type of lambdas
+    /// (eg. 2 for `Fn2<Int, Int, Bool>`)
+    pub fn fn_arity(&self) -> Option<usize> {
+        let name = match &self.body {
+            TyRaw => &self.fullname.0,
+            TySpe { base_name, .. } => base_name,
+            _ => return None,
+        };
+        if name.starts_with("Fn") {
+            name[2..].parse().ok()
+        } else {
+            None
+        }
+    }
+
     pub fn upper_bound(&self) -> TermTy {
         match &self.body {
             TyParamRef { .. } => ty::raw("Object"),
@@ -201,13 +283,22 @@ pub fn spe_meta(base_name: &str, type_args: Vec<TermTy>) -> TermTy {
         .collect::<Vec<_>>();
     TermTy {
         fullname: class_fullname(&format!("Meta:{}<{}>", &base_name, &tyarg_names.join(","))),
-        body: TySpe {
+        body: TySpeMeta {
             base_name: base_name.to_string(),
             type_args,
         },
     }
 }
 
+const NATIVE_PREFIX: &str = "Shiika::Internal::Native";
+
+/// Type of unboxed values (eg. LLVM's i1 for `native("Bool")`).
+/// These do not appear in Shiika programs but only in HIR
+/// (see `HirBox` and `HirUnbox`)
+pub fn native(boxed_name: &str) -> TermTy {
+    raw(&format!("{}{}", NATIVE_PREFIX, boxed_name))
+}
+
 /// Shortcut for Array<T>
 pub fn ary(type_arg: TermTy) -> TermTy {
     spe("Array", vec![type_arg])
@@ -234,6 +325,9 @@ pub struct MethodSignature {
     pub fullname: MethodFullname,
     pub ret_ty: TermTy,
     pub params: Vec<MethodParam>,
+    /// Type parameters of the method itself (eg. `U` of
+    /// `Array<T>#map<U>`.) They are numbered after the ones of the class
+    pub typarams: Vec<TermTy>,
 }
 
 impl MethodSignature {
@@ -259,14 +353,66 @@ impl MethodSignature {
                 .iter()
                 .map(|param| param.substitute(&type_args))
                 .collect(),
+            typarams: self.typarams.clone(),
         }
     }
+
+    /// Substitute the type parameters of the method with type arguments
+    /// (eg. `String` for `U` of `Array<Int>#map<U>`)
+    pub fn instantiate(&self, method_tyargs: &[TermTy]) -> MethodSignature {
+        let f = |idx: usize| {
+            self.typarams
+                .iter()
+                .position(|t| match &t.body {
+                    TyParamRef { idx: i, .. } => *i == idx,
+                    _ => false,
+                })
+                .map(|i| method_tyargs[i].clone())
+        };
+        MethodSignature {
+            fullname: self.fullname.clone(),
+            ret_ty: self.ret_ty.replace_typarams(&f),
+            params: self
+                .params
+                .iter()
+                .map(|param| MethodParam {
+                    ty: param.ty.replace_typarams(&f),
+                    ..param.clone()
+                })
+                .collect(),
+            typarams: vec![],
+        }
+    }
+}
+
+/// eg. `A#foo(x: Int, s: ~String) -> Bool`
+impl std::fmt::Display for MethodSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(|param| {
+                let conv = if param.implicit_conv { "~" } else { "" };
+                format!("{}: {}{}", param.name, conv, param.ty)
+            })
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "{}({}) -> {}",
+            self.fullname,
+            params.join(", "),
+            self.ret_ty
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct MethodParam {
     pub name: String,
     pub ty: TermTy,
+    /// Whether the param accepts objects which can be implicitly converted
+    /// into `ty` (eg. `x: ~String`)
+    pub implicit_conv: bool,
 }
 
 impl MethodParam {
@@ -274,6 +420,7 @@ impl MethodParam {
         MethodParam {
             name: self.name.clone(),
             ty: self.ty.substitute(&type_args),
+            implicit_conv: self.implicit_conv,
         }
     }
 }
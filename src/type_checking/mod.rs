@@ -1,7 +1,9 @@
 use crate::error::Error;
 use crate::hir;
+use crate::hir::class_dict::ClassDict;
 use crate::ty;
 use crate::ty::*;
+pub mod ty_diff;
 
 macro_rules! type_error {
     ( $( $arg:expr ),* ) => ({
@@ -9,11 +11,38 @@ macro_rules! type_error {
     })
 }
 
-pub fn check_return_value(sig: &MethodSignature, ty: &TermTy) -> Result<(), Error> {
-    if ty.conforms_to(&sig.ret_ty) || sig.ret_ty.is_void_type() {
+/// Type error which has the expected type and the actual type
+macro_rules! type_mismatch {
+    ( $expected:expr, $actual:expr, $( $arg:expr ),* ) => ({
+        crate::error::type_mismatch(&format!( $( $arg ),* ), $expected, $actual)
+    })
+}
+
+macro_rules! arity_error {
+    ( $expected:expr, $actual:expr, $( $arg:expr ),* ) => ({
+        crate::error::arity_error(&format!( $( $arg ),* ), $expected, $actual)
+    })
+}
+
+pub fn check_return_value(
+    class_dict: &ClassDict,
+    sig: &MethodSignature,
+    ty: &TermTy,
+) -> Result<(), Error> {
+    if class_dict.conforms(ty, &sig.ret_ty) || sig.ret_ty.is_void_type() {
         Ok(())
+    } else if let Some(details) = ty_diff::mismatch_details(&sig.ret_ty, ty) {
+        Err(type_mismatch!(
+            &sig.ret_ty,
+            ty,
+            "{} returns a value of wrong type{}",
+            sig.fullname,
+            details
+        ))
     } else {
-        Err(type_error!(
+        Err(type_mismatch!(
+            &sig.ret_ty,
+            ty,
             "{} should return {} but returns {}",
             sig.fullname,
             sig.ret_ty.fullname,
@@ -22,11 +51,26 @@ pub fn check_return_value(sig: &MethodSignature, ty: &TermTy) -> Result<(), Erro
     }
 }
 
+/// Check the expression has a value (eg. `x = puts("")` is an error)
+pub fn check_not_void(ty: &TermTy, on: &str) -> Result<(), Error> {
+    if ty.is_void_type() {
+        Err(type_error!("{} must not be Void", on))
+    } else {
+        Ok(())
+    }
+}
+
 pub fn check_logical_operator_ty(ty: &TermTy, on: &str) -> Result<(), Error> {
     if *ty == ty::raw("Bool") {
         Ok(())
     } else {
-        Err(type_error!("{} must be bool but got {:?}", on, ty.fullname))
+        Err(type_mismatch!(
+            &ty::raw("Bool"),
+            ty,
+            "{} must be bool but got {:?}",
+            on,
+            ty.fullname
+        ))
     }
 }
 
@@ -34,7 +78,9 @@ pub fn check_condition_ty(ty: &TermTy, on: &str) -> Result<(), Error> {
     if *ty == ty::raw("Bool") {
         Ok(())
     } else {
-        Err(type_error!(
+        Err(type_mismatch!(
+            &ty::raw("Bool"),
+            ty,
             "{} condition must be bool but got {:?}",
             on,
             ty.fullname
@@ -45,8 +91,18 @@ pub fn check_condition_ty(ty: &TermTy, on: &str) -> Result<(), Error> {
 pub fn check_reassign_var(orig_ty: &TermTy, new_ty: &TermTy, name: &str) -> Result<(), Error> {
     if orig_ty.equals_to(new_ty) {
         Ok(())
+    } else if let Some(details) = ty_diff::mismatch_details(orig_ty, new_ty) {
+        Err(type_mismatch!(
+            orig_ty,
+            new_ty,
+            "variable {} is reassigned with a value of wrong type{}",
+            name,
+            details
+        ))
     } else {
-        Err(type_error!(
+        Err(type_mismatch!(
+            orig_ty,
+            new_ty,
             "variable {} is {:?} but tried to assign a {:?}",
             name,
             orig_ty,
@@ -55,14 +111,88 @@ pub fn check_reassign_var(orig_ty: &TermTy, new_ty: &TermTy, name: &str) -> Resu
     }
 }
 
+/// Check the value assigned to an ivar (`ivar_ty` is the type given in
+/// the initializer)
+pub fn check_ivar_assign(
+    class_dict: &ClassDict,
+    ivar_ty: &TermTy,
+    ty: &TermTy,
+    name: &str,
+) -> Result<(), Error> {
+    if class_dict.conforms(ty, ivar_ty) {
+        Ok(())
+    } else if let Some(details) = ty_diff::mismatch_details(ivar_ty, ty) {
+        Err(type_mismatch!(
+            ivar_ty,
+            ty,
+            "instance variable `{}' is assigned a value of wrong type{}",
+            name,
+            details
+        ))
+    } else {
+        Err(type_mismatch!(
+            ivar_ty,
+            ty,
+            "instance variable `{}' has type {:?} but tried to assign a {:?}",
+            name,
+            ivar_ty,
+            ty
+        ))
+    }
+}
+
+/// Check the arguments of `Fn#call` against the type of the lambda
+pub fn check_fn_call_args(
+    class_dict: &ClassDict,
+    fn_ty: &TermTy,
+    sig: &MethodSignature,
+    arg_tys: &[&TermTy],
+) -> Result<(), Error> {
+    if sig.params.len() != arg_tys.len() {
+        return Err(arity_error!(
+            sig.params.len(),
+            arg_tys.len(),
+            "lambda of type {} takes {} args but got {}",
+            fn_ty,
+            sig.params.len(),
+            arg_tys.len()
+        ));
+    }
+
+    sig.params
+        .iter()
+        .zip(arg_tys.iter())
+        .enumerate()
+        .try_for_each(|(i, (param, arg_ty))| {
+            let a = arg_ty.upper_bound();
+            let p = param.ty.upper_bound();
+            if class_dict.conforms(&a, &p) {
+                Ok(())
+            } else {
+                Err(type_mismatch!(
+                    &param.ty,
+                    arg_ty,
+                    "lambda of type {} takes {} as the arg #{} but got {}",
+                    fn_ty,
+                    param.ty,
+                    i + 1,
+                    arg_ty
+                ))
+            }
+        })
+}
+
 pub fn check_method_args(
+    class_dict: &ClassDict,
     sig: &MethodSignature,
     arg_tys: &[&TermTy],
     receiver_hir: &hir::HirExpression,
     arg_hirs: &[hir::HirExpression],
 ) -> Result<(), Error> {
     if sig.params.len() != arg_tys.len() {
-        return Err(type_error!(
+        return Err(arity_error!(
+            sig.params.len(),
+            arg_tys.len(),
             "{} takes {} args but got {} (receiver: {:?}, args: {:?})",
             sig.fullname,
             sig.params.len(),
@@ -78,10 +208,21 @@ pub fn check_method_args(
         .try_for_each(|(param, arg_ty)| {
             let a = arg_ty.upper_bound();
             let p = param.ty.upper_bound();
-            if a.conforms_to(&p) {
+            if class_dict.conforms(&a, &p) {
                 Ok(())
+            } else if let Some(details) = ty_diff::mismatch_details(&p, &a) {
+                Err(type_mismatch!(
+                    &param.ty,
+                    arg_ty,
+                    "{} got a value of wrong type for `{}'{}",
+                    sig.fullname,
+                    param.name,
+                    details
+                ))
             } else {
-                Err(type_error!(
+                Err(type_mismatch!(
+                    &param.ty,
+                    arg_ty,
                     "{} takes {} but got {} (receiver: {:?}, args: {:?})",
                     sig.fullname,
                     param.ty.fullname,
@@ -94,3 +235,72 @@ pub fn check_method_args(
 
     Ok(())
 }
+
+/// Substitute the type parameters of a generic method with the types
+/// inferred from the args (eg. `U` of `Array<Int>#map<U>(f: Fn1<Int, U>)`
+/// is `String` when called with a `Fn1<Int, String>`.) The number of the
+/// args is checked later by `check_method_args`
+pub fn instantiate_method(
+    sig: MethodSignature,
+    arg_tys: &[&TermTy],
+) -> Result<MethodSignature, Error> {
+    if sig.typarams.is_empty() || sig.params.len() != arg_tys.len() {
+        return Ok(sig);
+    }
+    let mut inferred = vec![None; sig.typarams.len()];
+    for (param, arg_ty) in sig.params.iter().zip(arg_tys.iter()) {
+        infer_tyargs(&sig.typarams, &param.ty, arg_ty, &mut inferred);
+    }
+    let mut method_tyargs = vec![];
+    for (typaram, tyarg) in sig.typarams.iter().zip(inferred) {
+        match tyarg {
+            Some(t) => method_tyargs.push(t),
+            None => {
+                let name = match &typaram.body {
+                    TyBody::TyParamRef { name, .. } => name.clone(),
+                    _ => typaram.to_string(),
+                };
+                return Err(type_error!(
+                    "{}: cannot infer the type of `{}' from the args",
+                    sig.fullname,
+                    name
+                ));
+            }
+        }
+    }
+    Ok(sig.instantiate(&method_tyargs))
+}
+
+/// Find the types of `typarams` in `param_ty` by matching it with `arg_ty`
+/// (eg. `String` for `U` of `Fn1<Int, U>` and `Fn1<Int, String>`.) The
+/// first one found is used when a type parameter appears more than once
+fn infer_tyargs(
+    typarams: &[TermTy],
+    param_ty: &TermTy,
+    arg_ty: &TermTy,
+    inferred: &mut [Option<TermTy>],
+) {
+    if let Some(i) = typarams.iter().position(|t| t == param_ty) {
+        if inferred[i].is_none() {
+            inferred[i] = Some(arg_ty.clone());
+        }
+        return;
+    }
+    if let (
+        TyBody::TySpe {
+            base_name,
+            type_args,
+        },
+        TyBody::TySpe {
+            base_name: arg_base_name,
+            type_args: arg_type_args,
+        },
+    ) = (&param_ty.body, &arg_ty.body)
+    {
+        if base_name == arg_base_name && type_args.len() == arg_type_args.len() {
+            for (p, a) in type_args.iter().zip(arg_type_args.iter()) {
+                infer_tyargs(typarams, p, a, inferred);
+            }
+        }
+    }
+}
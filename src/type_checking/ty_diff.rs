@@ -0,0 +1,48 @@
+use crate::ty::*;
+
+/// Render `expected` and `actual` with the differing components
+/// surrounded by `**`.
+///
+/// eg. `Array<Fn1<Int,**Bool**>>` and `Array<Fn1<Int,**Int**>>`
+pub fn diff(expected: &TermTy, actual: &TermTy) -> (String, String) {
+    if expected == actual {
+        return (expected.to_string(), actual.to_string());
+    }
+    match (&expected.body, &actual.body) {
+        (
+            TyBody::TySpe {
+                base_name: base1,
+                type_args: args1,
+            },
+            TyBody::TySpe {
+                base_name: base2,
+                type_args: args2,
+            },
+        ) if base1 == base2 && args1.len() == args2.len() => {
+            let (diffs1, diffs2): (Vec<_>, Vec<_>) = args1
+                .iter()
+                .zip(args2.iter())
+                .map(|(a, b)| diff(a, b))
+                .unzip();
+            (
+                format!("{}<{}>", base1, diffs1.join(",")),
+                format!("{}<{}>", base2, diffs2.join(",")),
+            )
+        }
+        _ => (highlight(expected), highlight(actual)),
+    }
+}
+
+/// Return aligned diff of the two types, if they are long enough to
+/// need it (i.e. one of them is a generic type)
+pub fn mismatch_details(expected: &TermTy, actual: &TermTy) -> Option<String> {
+    if !expected.is_specialized() && !actual.is_specialized() {
+        return None;
+    }
+    let (e, a) = diff(expected, actual);
+    Some(format!("\n  expected: {}\n     found: {}", e, a))
+}
+
+fn highlight(ty: &TermTy) -> String {
+    format!("**{}**", ty)
+}
@@ -0,0 +1,61 @@
+use shiika::build_cache;
+use shiika::hir::config::BuildConfig;
+use shiika::platform::Platform;
+use std::fs;
+
+#[test]
+fn test_key() {
+    let (a, b) = ("class A; end", "class B; end");
+    let config = BuildConfig::default();
+    let release = BuildConfig {
+        release: true,
+        ..BuildConfig::default()
+    };
+    let defined = BuildConfig {
+        defines: vec![("A".to_string(), "1".to_string())],
+        ..BuildConfig::default()
+    };
+    let key = build_cache::key("puts 1", a, false, &config);
+    assert_eq!(build_cache::key("puts 1", a, false, &config), key);
+    assert_ne!(build_cache::key("puts 2", a, false, &config), key);
+    assert_ne!(build_cache::key("puts 1", b, false, &config), key);
+    assert_ne!(build_cache::key("puts 1", a, true, &config), key);
+    assert_ne!(build_cache::key("puts 1", a, false, &release), key);
+    assert_ne!(build_cache::key("puts 1", a, false, &defined), key);
+}
+
+#[test]
+fn test_stamp() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join("shiika_build_cache_test.ll");
+    let ll_path = path.to_str().unwrap();
+    fs::write(&path, "; ModuleID = 'main'\n")?;
+    build_cache::invalidate(ll_path);
+    assert!(!build_cache::is_fresh(ll_path, "a"));
+    build_cache::save(ll_path, "a")?;
+    assert!(build_cache::is_fresh(ll_path, "a"));
+    assert!(!build_cache::is_fresh(ll_path, "b"));
+
+    // The bitcode of the builtin library is removed
+    fs::write(&path, "; shiika-builtin: /nonexistent/a.bc\n")?;
+    assert!(!build_cache::is_fresh(ll_path, "a"));
+
+    build_cache::invalidate(ll_path);
+    assert!(!build_cache::is_fresh(ll_path, "a"));
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_cleanup() -> Result<(), Box<dyn std::error::Error>> {
+    let sk_path = std::env::temp_dir().join("shiika_cleanup_test.sk");
+    let s = sk_path.to_str().unwrap();
+    let ll_path = format!("{}.ll", s);
+    fs::write(&ll_path, "; ModuleID = 'main'\n")?;
+    build_cache::save(&ll_path, "a")?;
+    fs::write(Platform::host().exe_path(s), "")?;
+    shiika::runner::cleanup(&sk_path)?;
+    // The stamp is removed too
+    assert!(!build_cache::is_fresh(&ll_path, "a"));
+    assert!(!std::path::Path::new(&format!("{}.stamp", ll_path)).exists());
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use shiika::build_plan::BuildPlan;
+use shiika::platform::{Platform, Toolchain};
+use std::path::PathBuf;
+
+fn paths(names: &[&str]) -> Vec<PathBuf> {
+    names.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn test_steps() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = BuildPlan::new(
+        "a.sk",
+        paths(&["builtin/int.sk"]),
+        Some(PathBuf::from("/cache/builtin.bc")),
+        Platform::Linux,
+        Toolchain::Musl,
+    )?;
+    assert_eq!(plan.sources, paths(&["a.sk", "builtin/int.sk"]));
+    assert_eq!(plan.product, PathBuf::from("a.sk.out"));
+    let names = plan.steps.iter().map(|step| step.name).collect::<Vec<_>>();
+    assert_eq!(names, vec!["compile", "llc", "llc-builtin", "link"]);
+
+    let compile = plan.step("compile").unwrap();
+    assert_eq!(compile.inputs, plan.sources);
+    assert_eq!(compile.outputs, paths(&["a.sk.ll", "/cache/builtin.bc"]));
+    assert_eq!(compile.args, vec!["compile", "a.sk"]);
+
+    let llc = plan.step("llc").unwrap();
+    assert_eq!(llc.inputs, paths(&["a.sk.ll"]));
+    assert_eq!(llc.outputs, paths(&["a.sk.s"]));
+    assert!(llc.args.ends_with(&[
+        "a.sk.ll".to_string(),
+        "-o".to_string(),
+        "a.sk.s".to_string()
+    ]));
+
+    let builtin_obj = plan.step("llc-builtin").unwrap().outputs[0].clone();
+    let link = plan.step("link").unwrap();
+    assert_eq!(link.inputs, vec![PathBuf::from("a.sk.s"), builtin_obj]);
+    assert_eq!(link.outputs, paths(&["a.sk.out"]));
+    assert!(link.args.contains(&"-static".to_string()));
+    Ok(())
+}
+
+#[test]
+fn test_without_builtin_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = BuildPlan::new("a.sk", vec![], None, Platform::Linux, Toolchain::Musl)?;
+    let names = plan.steps.iter().map(|step| step.name).collect::<Vec<_>>();
+    assert_eq!(names, vec!["compile", "llc", "link"]);
+    assert_eq!(plan.step("link").unwrap().inputs, paths(&["a.sk.s"]));
+    Ok(())
+}
+
+#[test]
+fn test_to_json() -> Result<(), Box<dyn std::error::Error>> {
+    let plan = BuildPlan::new("a.sk", vec![], None, Platform::Linux, Toolchain::Musl)?;
+    let json = plan.to_json();
+    assert!(json.starts_with("{\"sources\":[\"a.sk\"],\"products\":[\"a.sk.out\"],\"steps\":["));
+    assert!(json.contains(
+        "{\"name\":\"compile\",\"inputs\":[\"a.sk\"],\"outputs\":[\"a.sk.ll\"],\"command\":["
+    ));
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use shiika::builtin_cache;
+use shiika::code_gen::BuiltinIndex;
+use shiika::names::class_fullname;
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn test_builtin_index_text() {
+    let index = BuiltinIndex {
+        classes: vec![(class_fullname("Int"), 1), (class_fullname("Meta:Int"), 0)],
+        methods: vec!["Int#+".to_string(), "Meta:Int#new".to_string()]
+            .into_iter()
+            .collect(),
+    };
+    assert_eq!(BuiltinIndex::from_text(&index.to_text()), Some(index));
+    assert_eq!(BuiltinIndex::from_text("class Int"), None);
+}
+
+#[test]
+fn test_linked_bitcode() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join("shiika_builtin_cache_test.ll");
+    let path_str = path.to_str().unwrap();
+    fs::write(&path, "; shiika-builtin: /tmp/a.bc\n; ModuleID = 'main'\n")?;
+    assert_eq!(
+        builtin_cache::linked_bitcode(path_str)?,
+        Some(PathBuf::from("/tmp/a.bc"))
+    );
+    fs::write(&path, "; ModuleID = 'main'\n")?;
+    assert_eq!(builtin_cache::linked_bitcode(path_str)?, None);
+    fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+mod common;
+use shiika::hir::call_graph::*;
+
+fn build_graph(src: &str) -> CallGraph {
+    let hir = common::build(src).unwrap();
+    CallGraph::new(&hir)
+}
+
+const SRC: &str = "class A
+  def foo -> Int
+    self.bar + B.new.baz
+  end
+
+  def bar -> Int
+    1
+  end
+end
+class B
+  def baz -> Int
+    2
+  end
+end
+class C : A
+end
+X = A.new.foo
+";
+
+#[test]
+fn test_calls() {
+    let graph = build_graph(SRC);
+    let has_call = |from: &str, to: &str| graph.calls.contains(&(from.to_string(), to.to_string()));
+    assert!(has_call("A#foo", "A#bar"));
+    assert!(has_call("A#foo", "B#baz"));
+    assert!(has_call("A#foo", "Meta:B#new"));
+    assert!(has_call("::X", "A#foo"));
+}
+
+#[test]
+fn test_class_deps() {
+    let graph = build_graph(SRC);
+    let has_dep = |from: &str, to: &str, kind: ClassDepKind| {
+        graph
+            .class_deps
+            .contains(&(from.to_string(), to.to_string(), kind))
+    };
+    assert!(has_dep("C", "A", ClassDepKind::Superclass));
+    assert!(has_dep("A", "B", ClassDepKind::Call));
+    assert!(!has_dep("A", "A", ClassDepKind::Call));
+}
+
+#[test]
+fn test_to_dot() {
+    let mut graph = build_graph(SRC);
+    graph.retain(|name| class_of(name) == Some("B") || name == "B");
+    assert_eq!(
+        graph.to_dot(),
+        "digraph callgraph {\n  \"B\" -> \"Object\" [style=dashed, label=superclass];\n}\n"
+    );
+}
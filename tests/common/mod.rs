@@ -0,0 +1,18 @@
+//! Helpers shared by the tests (not all of them are used by each test)
+#![allow(dead_code)]
+use shiika::error::Error;
+use shiika::hir::config::BuildConfig;
+use shiika::hir::Hir;
+
+/// Make the HIR of `src` (with the builtin library)
+pub fn build(src: &str) -> Result<Hir, Error> {
+    build_with_config(src, &BuildConfig::default())
+}
+
+/// Same as `build` but with the given configuration
+pub fn build_with_config(src: &str, config: &BuildConfig) -> Result<Hir, Error> {
+    let builtin = shiika::runner::load_builtin().unwrap();
+    let ast = shiika::parser::Parser::parse(&(builtin + src)).unwrap();
+    let corelib = shiika::corelib::Corelib::create(&ast);
+    shiika::hir::build_with_config(ast, corelib, config)
+}
@@ -0,0 +1,120 @@
+use shiika::{compile_str, CompileOptions};
+
+#[test]
+fn test_compile_str_error() {
+    let err = compile_str("puts 1\nputs(", &CompileOptions::default()).unwrap_err();
+    let err = err.downcast_ref::<shiika::error::Error>().unwrap();
+    // The span is the one in the given source
+    assert_eq!(err.span.as_ref().unwrap().begin.line(), 1);
+}
+
+#[test]
+fn test_compile_str_type_error() {
+    let err = compile_str(
+        "x = 1\nputs \"a\"\nif x then 2 end",
+        &CompileOptions::default(),
+    )
+    .unwrap_err();
+    let err = err.downcast_ref::<shiika::error::Error>().unwrap();
+    // The span of the condition
+    let span = err.span.as_ref().unwrap();
+    assert_eq!(span.begin.line(), 2);
+    assert_eq!(span.begin.col(), 3);
+    let json = err.to_json();
+    assert!(json.contains("\"expected\":\"Bool\""), "{}", json);
+    assert!(json.contains("\"actual\":\"Int\""), "{}", json);
+}
+
+#[test]
+fn test_compile_str_deny_warnings() {
+    let src = "a = 1";
+    let options = CompileOptions {
+        deny_warnings: true,
+        ..CompileOptions::default()
+    };
+    assert!(compile_str(src, &options).is_err());
+}
+
+#[test]
+fn test_compile_str_builtin_dir() {
+    let options = CompileOptions {
+        builtin_dir: "no_such_dir".into(),
+        ..CompileOptions::default()
+    };
+    assert!(compile_str("1", &options).is_err());
+}
+
+#[test]
+fn test_compile_str_jobs() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+      class A
+        def foo -> Int
+          1
+        end
+        def bar -> Int
+          foo + 1
+        end
+      end
+      A.new.bar
+    ";
+    let options = CompileOptions {
+        verify: true,
+        jobs: 3,
+        ..CompileOptions::default()
+    };
+    let compiled = compile_str(src, &options)?;
+    // The methods generated in the other threads are linked
+    let defined = |name: &str| {
+        compiled
+            .llvm_ir
+            .lines()
+            .any(|line| line.starts_with("define") && line.contains(name))
+    };
+    assert!(defined("@\"A#foo\"("));
+    assert!(defined("@\"A#bar\"("));
+    Ok(())
+}
+
+#[test]
+fn test_compile_str_release() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "x = 1\nassert(x == 1, \"x is 1\")\nputs x.to_s";
+    let calls_assert = |release: bool| -> Result<bool, Box<dyn std::error::Error>> {
+        let options = CompileOptions {
+            release,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_str(src, &options)?;
+        Ok(compiled
+            .llvm_ir
+            .lines()
+            .any(|line| line.contains("call") && line.contains("@\"Object#assert_at/3\"(")))
+    };
+    // The place is given to the assertion, which is removed in a release build
+    assert!(calls_assert(false)?);
+    assert!(!calls_assert(true)?);
+    Ok(())
+}
+
+#[test]
+fn test_compile_str_backtrace() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "class A\n  def self.foo -> Int\n    1\n  end\nend\nA.foo";
+    let records = |release: bool| -> Result<bool, Box<dyn std::error::Error>> {
+        let options = CompileOptions {
+            release,
+            ..CompileOptions::default()
+        };
+        let ir = compile_str(src, &options)?.llvm_ir;
+        let body = ir
+            .split("\ndefine ")
+            .find(|f| f.contains("@\"Meta:A#foo\"("))
+            .unwrap();
+        Ok(body
+            .split("\n}")
+            .next()
+            .unwrap()
+            .contains("@shiika_backtrace_depth"))
+    };
+    assert!(records(false)?);
+    assert!(!records(true)?);
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use shiika::conformance;
+use std::path::Path;
+
+#[test]
+fn test_collect_cases() -> Result<(), Box<dyn std::error::Error>> {
+    let cases = conformance::collect_cases(Path::new("conformance"), &[])?;
+    assert!(cases.iter().any(|c| c.id() == "basics/hello"));
+    assert_eq!(
+        cases
+            .iter()
+            .find(|c| c.id() == "basics/hello")
+            .unwrap()
+            .expected,
+        "hello\nworld\n"
+    );
+    for case in &cases {
+        assert!(
+            !case.expected.is_empty(),
+            "{} has no expected output",
+            case.id()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_collect_cases_of_features() -> Result<(), Box<dyn std::error::Error>> {
+    let cases = conformance::collect_cases(Path::new("conformance"), &["strings"])?;
+    assert!(!cases.is_empty());
+    assert!(cases.iter().all(|c| c.feature == "strings"));
+    Ok(())
+}
+
+#[test]
+fn test_external_runner() -> Result<(), Box<dyn std::error::Error>> {
+    let cases = conformance::collect_cases(Path::new("conformance"), &["basics"])?;
+    let hello = cases.iter().find(|c| c.name == "hello").unwrap();
+    // `cat` prints the program itself, which is not the expected output
+    match conformance::run_case(hello, Some("cat")) {
+        conformance::Outcome::Fail { actual } => assert!(actual.starts_with("puts")),
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+    Ok(())
+}
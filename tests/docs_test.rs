@@ -0,0 +1,62 @@
+mod common;
+use shiika::hir::Hir;
+use shiika::parser::lexer::Cursor;
+
+fn build(src: &str) -> (Hir, Cursor) {
+    let base = Cursor::end_of(&shiika::runner::load_builtin().unwrap());
+    (common::build(src).unwrap(), base)
+}
+
+const SRC: &str = "## A point
+##
+##   p = Point.new(1)
+class Point
+  def initialize(x: Int)
+    @x = x
+  end
+
+  ## Returns the origin
+  def self.origin -> Point
+    Point.new(0)
+  end
+
+  ### Not a doc comment
+  def x2 -> Int
+    @x * 2
+  end
+
+  #| A block comment
+  ## is not a doc comment either |#
+  #|
+  |#
+  def x3 -> Int
+    @x * 3
+  end
+end
+";
+
+#[test]
+fn test_collect_docs() {
+    let (hir, _) = build(SRC);
+    assert_eq!(
+        hir.docs.get("Point").unwrap(),
+        "A point\n\n  p = Point.new(1)"
+    );
+    assert_eq!(
+        hir.docs.get("Meta:Point#origin").unwrap(),
+        "Returns the origin"
+    );
+    assert!(!hir.docs.contains_key("Point#x2"));
+    assert!(!hir.docs.contains_key("Point#x3"));
+}
+
+#[test]
+fn test_docs_to_json() {
+    let (hir, base) = build(SRC);
+    let json = shiika::hir::docs::to_json(&hir, |name| name.contains("Point"), &base);
+    assert_eq!(
+        json,
+        "[{\"name\":\"Point\",\"line\":4,\"doc\":\"A point\\n\\n  p = Point.new(1)\"},\
+         {\"name\":\"Meta:Point#origin\",\"line\":10,\"doc\":\"Returns the origin\"}]\n"
+    );
+}
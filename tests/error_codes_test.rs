@@ -0,0 +1,34 @@
+use shiika::error;
+use shiika::error_codes;
+
+#[test]
+fn test_find_error_code() {
+    assert_eq!(error_codes::find("E0301").unwrap().code, "E0301");
+    assert_eq!(error_codes::find("e0301").unwrap().code, "E0301");
+    assert_eq!(error_codes::find("0301").unwrap().code, "E0301");
+    assert!(error_codes::find("E9999").is_none());
+}
+
+#[test]
+fn test_every_error_has_explanation() {
+    let errors = vec![
+        error::syntax_error(""),
+        error::type_error(""),
+        error::arity_error("", 1, 2),
+        error::name_error(""),
+        error::program_error(""),
+        error::plain_runner_error(""),
+    ];
+    for err in errors {
+        assert!(error_codes::find(err.code()).is_some());
+    }
+}
+
+#[test]
+fn test_error_to_json() {
+    let err = error::arity_error("A#foo takes 1 args but got \"2\"", 1, 2);
+    assert_eq!(
+        err.to_json(),
+        r#"{"code":"E0302","kind":"ArityError","message":"A#foo takes 1 args but got \"2\"","span":null,"expected":"1","actual":"2"}"#
+    );
+}
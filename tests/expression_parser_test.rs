@@ -32,6 +32,164 @@ fn test_if_expr_with_sep() {
     )
 }
 
+#[test]
+fn test_modifiers() {
+    let mut parser = Parser::new("puts 1 if a");
+    assert_eq!(
+        parser.parse_stmt().unwrap(),
+        ast::if_expr(
+            ast::bare_name("a"),
+            vec![ast::method_call(
+                None,
+                "puts",
+                vec![ast::decimal_literal(1)],
+                false,
+                false
+            )],
+            None
+        )
+    );
+    let mut parser = Parser::new("x = 1 unless a");
+    assert_eq!(
+        parser.parse_stmt().unwrap(),
+        ast::if_expr(
+            ast::logical_not(ast::bare_name("a")),
+            vec![ast::assignment(
+                ast::bare_name("x"),
+                ast::decimal_literal(1)
+            )],
+            None
+        )
+    );
+    // `break` takes no value here
+    let result = parse_expr("while true; break if a; end");
+    assert_eq!(
+        result.unwrap(),
+        ast::while_expr(
+            ast::pseudo_variable(shiika::parser::token::Token::KwTrue),
+            vec![ast::if_expr(
+                ast::bare_name("a"),
+                vec![ast::break_expr(None)],
+                None
+            )]
+        )
+    );
+}
+
+#[test]
+fn test_case_expr() {
+    let result =
+        parse_expr("case a\nwhen 0, 1 then 2\nwhen [x, _, *rest] if x > 0\n  3\nelse 4\nend");
+    assert_eq!(
+        result.unwrap(),
+        ast::case_expr(
+            ast::bare_name("a"),
+            vec![
+                ast::CaseClause {
+                    patterns: vec![
+                        ast::Pattern::Value(ast::decimal_literal(0)),
+                        ast::Pattern::Value(ast::decimal_literal(1)),
+                    ],
+                    guard: None,
+                    body_exprs: vec![ast::decimal_literal(2)],
+                },
+                ast::CaseClause {
+                    patterns: vec![ast::Pattern::Array {
+                        items: vec![
+                            ast::Pattern::Binding("x".to_string()),
+                            ast::Pattern::Wildcard,
+                        ],
+                        rest: Some("rest".to_string()),
+                    }],
+                    guard: Some(ast::bin_op_expr(
+                        ast::bare_name("x"),
+                        ">",
+                        ast::decimal_literal(0)
+                    )),
+                    body_exprs: vec![ast::decimal_literal(3)],
+                },
+            ],
+            Some(vec![ast::decimal_literal(4)])
+        )
+    );
+    // `(x)` is compared with `x`
+    let result = parse_expr("case a\nwhen (x) then 1\nend");
+    assert_eq!(
+        result.unwrap(),
+        ast::case_expr(
+            ast::bare_name("a"),
+            vec![ast::CaseClause {
+                patterns: vec![ast::Pattern::Value(ast::bare_name("x"))],
+                guard: None,
+                body_exprs: vec![ast::decimal_literal(1)],
+            }],
+            None
+        )
+    );
+}
+
+#[test]
+fn test_invalid_case_expr() {
+    let err = parse_expr("case a\nelse 1\nend").unwrap_err();
+    assert!(err.msg.contains("`when'"), "{}", err.msg);
+    let err = parse_expr("case a\nwhen x, 1 then 2\nend").unwrap_err();
+    assert!(err.msg.contains("cannot have variables"), "{}", err.msg);
+    let err = parse_expr("case a\nwhen [*rest, x] then 2\nend").unwrap_err();
+    assert!(err.msg.contains("must be the last"), "{}", err.msg);
+}
+
+#[test]
+fn test_try_expr() {
+    let result = parse_expr("a.b? + 1");
+    assert_eq!(
+        result.unwrap(),
+        ast::bin_op_expr(
+            ast::try_expr(ast::method_call(
+                Some(ast::bare_name("a")),
+                "b",
+                vec![],
+                true,
+                true
+            )),
+            "+",
+            ast::decimal_literal(1)
+        )
+    );
+    // `?` with a space before it is a conditional expression
+    assert!(parse_expr("a ? b : c").is_ok());
+}
+
+#[test]
+fn test_break_with_value() {
+    let result = parse_expr("while true; break -1; end");
+    assert_eq!(
+        result.unwrap(),
+        ast::while_expr(
+            ast::pseudo_variable(shiika::parser::token::Token::KwTrue),
+            vec![ast::break_expr(Some(ast::unary_expr(
+                ast::decimal_literal(1),
+                "-@"
+            )))]
+        )
+    )
+}
+
+#[test]
+fn test_loop_expr() {
+    let result = parse_expr("loop do\n  next\nend");
+    assert_eq!(result.unwrap(), ast::loop_expr(vec![ast::next_expr()]));
+    assert!(parse_expr("loop\n  next\nend").is_err());
+}
+
+#[test]
+fn test_range_literal() {
+    let result = parse_expr("1...n");
+    assert_eq!(
+        result.unwrap(),
+        ast::range_literal(ast::decimal_literal(1), ast::bare_name("n"), true)
+    )
+}
+
 #[test]
 fn test_const_assign() {
     let result = parse_expr("X = 1");
@@ -168,6 +326,120 @@ fn test_decimal_literal() {
     assert_eq!(result.unwrap(), ast::decimal_literal(123),)
 }
 
+#[test]
+fn test_number_literals() {
+    assert_eq!(parse_expr("0xFF").unwrap(), ast::decimal_literal(255));
+    assert_eq!(parse_expr("0b1010").unwrap(), ast::decimal_literal(10));
+    assert_eq!(parse_expr("0o755").unwrap(), ast::decimal_literal(493));
+    assert_eq!(parse_expr("0xffff_ffff").unwrap(), ast::decimal_literal(-1));
+    assert_eq!(
+        parse_expr("1_000_000").unwrap(),
+        ast::decimal_literal(1000000)
+    );
+    assert_eq!(parse_expr("1.5e3").unwrap(), ast::float_literal(1500.0));
+    assert_eq!(parse_expr("2E-2").unwrap(), ast::float_literal(0.02));
+    assert_eq!(parse_expr("1_0.2_5").unwrap(), ast::float_literal(10.25));
+}
+
+#[test]
+fn test_heredoc() {
+    let result = parse_expr("<<~EOS\n    a\n\n      b\n    EOS\n");
+    assert_eq!(
+        result.unwrap(),
+        ast::string_literal("a\n\n  b\n".to_string())
+    );
+}
+
+#[test]
+fn test_triple_quoted_string() {
+    let result = parse_expr("\"\"\"a \"b\"\nc\"\"\"");
+    assert_eq!(
+        result.unwrap(),
+        ast::string_literal("a \"b\"\nc".to_string())
+    );
+}
+
+#[test]
+fn test_unterminated_heredocs() {
+    let e = parse_expr("s = <<~EOS\n  a\n").unwrap_err();
+    assert_eq!(e.msg, "found unterminated heredoc (`EOS' expected)");
+    assert_eq!(e.span.unwrap().begin.col(), 4);
+
+    let e = parse_expr("s = <<~EOS + 1\n  a\nEOS\n").unwrap_err();
+    assert_eq!(e.msg, "heredoc must be the last token of the line");
+
+    let e = parse_expr("s = \"\"\"a\nb\n").unwrap_err();
+    assert_eq!(e.msg, "found unterminated string (`\"\"\"' expected)");
+}
+
+#[test]
+fn test_string_escapes() {
+    assert_eq!(
+        parse_expr("\"a\\nb\\t\\\"c\\\"\\\\\"").unwrap(),
+        ast::string_literal("a\nb\t\"c\"\\".to_string())
+    );
+    assert_eq!(
+        parse_expr("\"\\u{3042}\\u{1F600}\"").unwrap(),
+        ast::string_literal("\u{3042}\u{1F600}".to_string())
+    );
+    assert_eq!(
+        parse_expr("<<~EOS\n  a\\tb\n  EOS\n").unwrap(),
+        ast::string_literal("a\tb\n".to_string())
+    );
+}
+
+#[test]
+fn test_invalid_string_escapes() {
+    for src in &["\"\\q\"", "\"\\u3042\"", "\"\\u{}\"", "\"\\u{D800}\""] {
+        assert!(parse_expr(src).is_err(), "{}", src);
+    }
+    let err = parse_expr("\"ab\\qc\"").unwrap_err();
+    assert_eq!(err.msg, "unknown escape sequence `\\q'");
+    let span = err.span.unwrap();
+    assert_eq!((span.begin.col(), span.end.col()), (3, 5));
+    let err = parse_expr("<<~EOS\n  a\n  \\u{110000}\n  EOS\n").unwrap_err();
+    let span = err.span.unwrap();
+    assert_eq!((span.begin.line(), span.begin.col()), (2, 2));
+}
+
+#[test]
+fn test_char_literal() {
+    assert_eq!(parse_expr("'a'").unwrap(), ast::char_literal('a'));
+    assert_eq!(parse_expr("'\\n'").unwrap(), ast::char_literal('\n'));
+    assert_eq!(parse_expr("'あ'").unwrap(), ast::char_literal('あ'));
+}
+
+#[test]
+fn test_invalid_char_literals() {
+    let msg = |src: &str| parse_expr(src).unwrap_err().msg;
+    assert_eq!(msg("'a"), "found unterminated character literal");
+    assert_eq!(
+        msg("'ab'"),
+        "character literal must have exactly one character"
+    );
+    assert_eq!(
+        msg("'\\q'"),
+        "unknown escape sequence `\\q' in character literal"
+    );
+    let span = parse_expr("x + '\\q'").unwrap_err().span.unwrap();
+    assert_eq!((span.begin.col(), span.end.col()), (5, 7));
+}
+
+#[test]
+fn test_invalid_number_literals() {
+    for src in &[
+        "2147483648",
+        "0x1_0000_0000",
+        "0b102",
+        "0x",
+        "1__0",
+        "1_",
+        "1e400",
+    ] {
+        assert!(parse_expr(src).is_err(), "{}", src);
+    }
+}
+
 //
 // Method call (0 args)
 //
@@ -251,3 +523,306 @@ fn test_call_with_space_2() {
         )
     )
 }
+
+#[test]
+fn test_too_deeply_nested() {
+    let src = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+    let result = parse_expr(&src);
+    assert!(result.is_err());
+
+    let src = format!("{}1{}", "(".repeat(10), ")".repeat(10));
+    let result = parse_expr(&src);
+    assert_eq!(result.unwrap(), ast::decimal_literal(1));
+
+    // The level is restored after the error
+    let src = format!(
+        "x = {}1{}\ny = {}1{}\n",
+        "(".repeat(100),
+        ")".repeat(100),
+        "(".repeat(20),
+        ")".repeat(20)
+    );
+    let (program, errors) = Parser::parse_recovering(&src);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(program.toplevel_items.len(), 1);
+
+    // One level per pair of parentheses (and one for the statement)
+    assert!(Parser::parse_with_max_nesting("((1))", 3).is_ok());
+    assert!(Parser::parse_with_max_nesting("(((1)))", 3).is_err());
+    assert!(Parser::parse_with_max_nesting("not not 1", 3).is_ok());
+    assert!(Parser::parse_with_max_nesting("not not not 1", 3).is_err());
+}
+
+#[test]
+fn test_lex_errors() {
+    let e = Parser::parse("a = 1\nb = \"abc\n").unwrap_err();
+    assert_eq!(e.msg, "found unterminated string");
+    let span = e.span.unwrap();
+    assert_eq!((span.begin.line(), span.begin.col()), (1, 4));
+
+    let e = Parser::parse("x = 1abc").unwrap_err();
+    assert_eq!(e.msg, "need space after a number");
+
+    // A comment at the end without a newline
+    assert!(Parser::parse("a = 1 # comment").is_ok());
+}
+
+#[test]
+fn test_unterminated_block_comment() {
+    let e = Parser::parse("a = 1\n#| note\nb = 2\n").unwrap_err();
+    assert_eq!(e.msg, "found unterminated block comment (`|#' expected)");
+    let span = e.span.unwrap();
+    assert_eq!((span.begin.line(), span.begin.col()), (1, 0));
+    assert_eq!((span.end.line(), span.end.col()), (1, 2));
+}
+
+#[test]
+fn test_spaceship_operator() {
+    let result = parse_expr("1 <=> 2");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::decimal_literal(1)),
+            "<=>",
+            vec![ast::decimal_literal(2)],
+            false,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_indexing() {
+    let result = parse_expr("a[1]");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::bare_name("a")),
+            "[]",
+            vec![ast::decimal_literal(1)],
+            true,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_index_assign() {
+    let result = parse_expr("a[1] = 2");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::bare_name("a")),
+            "[]=",
+            vec![ast::decimal_literal(1), ast::decimal_literal(2)],
+            false,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_await() {
+    let result = parse_expr("x = await foo(1)");
+    assert_eq!(
+        result.unwrap(),
+        ast::assignment(
+            ast::bare_name("x"),
+            ast::await_expr(ast::method_call(
+                None,
+                "foo",
+                vec![ast::decimal_literal(1)],
+                true,
+                false
+            ))
+        )
+    )
+}
+
+#[test]
+fn test_await_lowering() {
+    // The statements after `await` are the continuation
+    let program = Parser::parse("x = await foo\nbar(x)").unwrap();
+    let exprs = program
+        .toplevel_items
+        .into_iter()
+        .map(|item| match item {
+            ast::TopLevelItem::Expr(expr) => expr,
+            ast::TopLevelItem::Def(def) => panic!("unexpected def: {:?}", def),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        exprs,
+        vec![
+            ast::await_call(
+                ast::bare_name("foo"),
+                Some("x".to_string()),
+                vec![ast::method_call(
+                    None,
+                    "bar",
+                    vec![ast::bare_name("x")],
+                    true,
+                    false
+                )]
+            ),
+            ast::method_call(
+                Some(ast::const_ref(vec!["Async".to_string()])),
+                "run",
+                vec![],
+                false,
+                false
+            ),
+        ]
+    )
+}
+
+#[test]
+fn test_specialized_const_ref() {
+    let result = parse_expr("Fiber<Int>.new");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::specialized_const_ref(
+                vec!["Fiber".to_string()],
+                vec![ast::Typ {
+                    name: "Int".to_string(),
+                    typ_args: vec![],
+                }]
+            )),
+            "new",
+            vec![],
+            true,
+            true
+        )
+    )
+}
+
+#[test]
+fn test_const_less_than() {
+    let result = parse_expr("A<B");
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(
+            Some(ast::const_ref(vec!["A".to_string()])),
+            "<",
+            vec![ast::const_ref(vec!["B".to_string()])],
+            false,
+            false
+        )
+    )
+}
+
+#[test]
+fn test_tuple() {
+    let result = parse_expr("(1, (2, 3)).1.0");
+    let inner = ast::tuple_literal(vec![ast::decimal_literal(2), ast::decimal_literal(3)]);
+    let tuple = ast::tuple_literal(vec![ast::decimal_literal(1), inner]);
+    let field1 = ast::method_call(Some(tuple), "_1", vec![], true, false);
+    assert_eq!(
+        result.unwrap(),
+        ast::method_call(Some(field1), "_0", vec![], true, false)
+    )
+}
+
+#[test]
+fn test_multiple_assignment() {
+    let mut parser = Parser::new("a, b = f");
+    assert_eq!(
+        parser.parse_stmt().unwrap(),
+        ast::multiple_assignment(vec!["a".to_string(), "b".to_string()], ast::bare_name("f"))
+    );
+    let mut parser = Parser::new("a, b = b, a");
+    assert_eq!(
+        parser.parse_stmt().unwrap(),
+        ast::multiple_assignment(
+            vec!["a".to_string(), "b".to_string()],
+            ast::tuple_literal(vec![ast::bare_name("b"), ast::bare_name("a")])
+        )
+    );
+    // Only parsed as a statement
+    assert_eq!(parse_expr("a, b = f").unwrap(), ast::bare_name("a"));
+    assert!(parse_expr("(1, 2, 3, 4, 5)").is_err());
+}
+
+#[test]
+fn test_regexp_literal() {
+    let regexp = |src: &str| {
+        ast::method_call(
+            Some(ast::const_ref(vec!["Regexp".to_string()])),
+            "new",
+            vec![ast::string_literal(src.to_string())],
+            true,
+            false,
+        )
+    };
+    assert_eq!(parse_expr(r"/a\d+\/b/").unwrap(), regexp(r"a\d+/b"));
+    assert_eq!(
+        parse_expr("s =~ /a/").unwrap(),
+        ast::method_call(
+            Some(ast::bare_name("s")),
+            "=~",
+            vec![regexp("a")],
+            false,
+            false
+        )
+    );
+    // Division
+    let div = |a: &str, b: &str| ast::bin_op_expr(ast::bare_name(a), "/", ast::bare_name(b));
+    assert_eq!(parse_expr("a / b").unwrap(), div("a", "b"));
+    assert_eq!(parse_expr("a/b").unwrap(), div("a", "b"));
+
+    let e = parse_expr("s =~ /a+\nb").unwrap_err();
+    assert_eq!(e.msg, "found unterminated regexp");
+    assert_eq!(e.span.unwrap().begin.col(), 5);
+}
+
+#[test]
+fn test_command_literal() {
+    let command = |src: &str| {
+        ast::method_call(
+            Some(ast::const_ref(vec!["Process".to_string()])),
+            "capture",
+            vec![ast::string_literal(src.to_string())],
+            true,
+            false,
+        )
+    };
+    assert_eq!(parse_expr("`ls -1`").unwrap(), command("ls -1"));
+    assert_eq!(parse_expr(r"`echo \`a\``").unwrap(), command("echo `a`"));
+    assert_eq!(
+        parse_expr("`pwd`.bytesize").unwrap(),
+        ast::method_call(Some(command("pwd")), "bytesize", vec![], false, false)
+    );
+
+    let e = parse_expr("`ls -1\n").unwrap_err();
+    assert_eq!(e.msg, "found unterminated command");
+}
+
+#[test]
+fn test_parse_recovering() {
+    let (program, errors) = Parser::parse_recovering("x = (1\ndef foo\n  1\nend\ny = )\nz = 2\n");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(program.toplevel_items.len(), 2);
+
+    // Skips to the `end` of the class
+    let src = "class A\n  def foo\n    1 +\n  end\nend\nA.new\n";
+    let (program, errors) = Parser::parse_recovering(src);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(program.toplevel_items.len(), 1);
+
+    // Stops at a lexical error
+    let (program, errors) = Parser::parse_recovering("a = 1\nb = )\nc = \"x\n");
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[1].msg, "found unterminated string");
+    assert_eq!(program.toplevel_items.len(), 1);
+
+    // Reported once even if the item before it has no error
+    let (program, errors) = Parser::parse_recovering("a = 1\nfoo 'ab'\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(program.toplevel_items.len(), 2);
+
+    // Same as `parse` if there is no error
+    let (program, errors) = Parser::parse_recovering("a = 1\nb = 2\n");
+    assert!(errors.is_empty());
+    assert_eq!(program, Parser::parse("a = 1\nb = 2\n").unwrap());
+}
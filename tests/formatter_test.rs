@@ -0,0 +1,124 @@
+use shiika::formatter::format;
+
+#[test]
+fn test_indentation() {
+    let src = "class A
+def foo(x: Int) -> Int
+    if x>=0
+  x*2
+      else
+-x
+end
+end
+  end
+";
+    let expected = "class A
+  def foo(x: Int) -> Int
+    if x >= 0
+      x * 2
+    else
+      -x
+    end
+  end
+end
+";
+    assert_eq!(format(src).unwrap(), expected);
+}
+
+#[test]
+fn test_comments_and_blank_lines() {
+    let src = "# head
+
+
+X = 1    # one
+class A  ;  end
+fn(){
+    puts \"a  b\"
+}
+";
+    let expected = "# head
+
+X = 1    # one
+class A; end
+fn(){
+  puts \"a  b\"
+}
+";
+    assert_eq!(format(src).unwrap(), expected);
+}
+
+#[test]
+fn test_block_comments() {
+    let src = "class A
+#| a
+   b |#
+    ## doc
+    def foo
+    end
+end
+";
+    let expected = "class A
+  #| a
+   b |#
+  ## doc
+  def foo
+  end
+end
+";
+    assert_eq!(format(src).unwrap(), expected);
+}
+
+#[test]
+fn test_case_expr() {
+    let src = "case x
+    when 1 then foo
+  when [a, *b] if a > 0
+bar
+else
+baz
+end
+";
+    let expected = "case x
+when 1 then foo
+when [a, *b] if a > 0
+  bar
+else
+  baz
+end
+";
+    assert_eq!(format(src).unwrap(), expected);
+}
+
+#[test]
+fn test_operator_methods() {
+    let src = "class A
+  def +(other: A) -> A
+    self
+  end
+  def []=(i: Int, v: Int)
+    i
+  end
+end
+";
+    assert_eq!(format(src).unwrap(), src);
+}
+
+#[test]
+fn test_syntax_error() {
+    assert!(format("class A\n").is_err());
+}
+
+#[test]
+fn test_extern_def_has_no_body() {
+    let src = "class LibC
+extern def abs(n: Int) -> Int
+  extern def atoi(s: String) -> Int
+end
+";
+    let expected = "class LibC
+  extern def abs(n: Int) -> Int
+  extern def atoi(s: String) -> Int
+end
+";
+    assert_eq!(format(src).unwrap(), expected);
+}
@@ -0,0 +1,51 @@
+use shiika::{compile_str, CompileOptions};
+
+fn error_of(src: &str) -> String {
+    let err = compile_str(src, &CompileOptions::default()).unwrap_err();
+    err.downcast_ref::<shiika::error::Error>()
+        .unwrap()
+        .msg
+        .clone()
+}
+
+#[test]
+fn test_map_returns_array_of_lambda_result() {
+    // `strs` is `Array<String>` so `first` is a `String`
+    let src = "strs = [1, 2].map(fn(x: Int){ x.to_s })\nstrs.first + 1\n";
+    let msg = error_of(src);
+    assert!(msg.contains("String#+"), "{}", msg);
+}
+
+#[test]
+fn test_push_wrong_type() {
+    let msg = error_of("a = [1, 2]\na.push(\"3\")\n");
+    assert!(msg.contains("Array#push"), "{}", msg);
+}
+
+#[test]
+fn test_generic_class_method() {
+    let src = "
+class A
+  def self.apply<U>(x: Int, f: Fn1<Int, U>) -> U
+    f.call(x)
+  end
+end
+s = A.apply(1, fn(x: Int){ x.to_s })
+s.bytesize
+";
+    assert!(compile_str(src, &CompileOptions::default()).is_ok());
+}
+
+#[test]
+fn test_cannot_infer() {
+    let src = "
+class A
+  def self.empty<U>(n: Int) -> Array<U>
+    Array.new(n)
+  end
+end
+A.empty(1)
+";
+    let msg = error_of(src);
+    assert!(msg.contains("cannot infer the type of `U'"), "{}", msg);
+}
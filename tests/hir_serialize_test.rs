@@ -0,0 +1,55 @@
+mod common;
+use shiika::hir::serialize::{deserialize, serialize, FORMAT_VERSION};
+use shiika::hir::*;
+use shiika::names::class_fullname;
+
+fn build(src: &str) -> Hir {
+    common::build(src).unwrap()
+}
+
+#[test]
+fn test_roundtrip() {
+    let hir = build(
+        "class A
+  def initialize(x: Int)
+    var @x = x
+  end
+
+  def foo(f: Fn1<Int, Int>) -> Array<Int>
+    [f.call(@x), 1.5.to_i]
+  end
+end
+a = A.new(1)
+puts a.foo(fn(n: Int){ n + 1 }).to_s
+",
+    );
+    let s = serialize(&hir);
+    let loaded = deserialize(&s).unwrap();
+    assert_eq!(serialize(&loaded), s);
+    assert_eq!(loaded.str_literals, hir.str_literals);
+    assert_eq!(
+        loaded.sk_classes.get(&class_fullname("A")),
+        hir.sk_classes.get(&class_fullname("A"))
+    );
+}
+
+#[test]
+fn test_rust_bodies_are_attached() {
+    let loaded = deserialize(&serialize(&build(""))).unwrap();
+    let methods = &loaded.sk_methods[&class_fullname("Int")];
+    let plus = methods
+        .iter()
+        .find(|m| m.signature.fullname.full_name == "Int#+")
+        .unwrap();
+    match plus.body {
+        SkMethodBody::ShiikaMethodBody { .. } => panic!("body of Int#+ is not attached"),
+        _ => (),
+    }
+}
+
+#[test]
+fn test_unknown_version() {
+    let version = format!("\"version\":{}", FORMAT_VERSION);
+    let s = serialize(&build("")).replacen(&version, "\"version\":0", 1);
+    assert!(deserialize(&s).unwrap_err().contains("version"));
+}
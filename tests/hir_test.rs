@@ -0,0 +1,401 @@
+mod common;
+use common::build;
+use shiika::hir::HirExpressionBase::*;
+use shiika::hir::*;
+use shiika::ty;
+
+#[test]
+fn test_abstract_methods() {
+    let shape = "class Shape
+  abstract def area -> Int
+  abstract def scale(n: Int) -> Int
+end
+";
+    let rect = "class Rect : Shape
+  def area -> Int
+    1
+  end
+end
+";
+    let err = build(&format!("{}{}", shape, rect)).unwrap_err();
+    assert!(err.msg.contains("`Rect' must implement"), "{}", err.msg);
+    assert!(err.msg.contains("scale"), "{}", err.msg);
+    assert!(!err.msg.contains("area"), "{}", err.msg);
+
+    let err = build(&format!("{}Shape.new", shape)).unwrap_err();
+    assert!(err.msg.contains("cannot instantiate"), "{}", err.msg);
+}
+
+#[test]
+fn test_cyclic_constants() {
+    let err = build("A = B\nB = A\n").unwrap_err();
+    assert!(
+        err.msg.contains("cyclic initialization of constants"),
+        "{}",
+        err.msg
+    );
+    assert!(err.msg.contains("::A -> ::B -> ::A"), "{}", err.msg);
+
+    // Not a cycle
+    assert!(build("A = B + 1\nB = 1\n").is_ok());
+}
+
+#[test]
+fn test_literals_are_boxed() {
+    let expr = Hir::decimal_literal(1);
+    assert_eq!(expr.ty, ty::raw("Int"));
+    match expr.node {
+        HirBox { expr: inner } => {
+            assert_eq!(inner.ty, ty::native("Int"));
+            match inner.node {
+                HirDecimalLiteral { value } => assert_eq!(value, 1),
+                _ => panic!("not a decimal literal: {:?}", inner),
+            }
+        }
+        _ => panic!("not boxed: {:?}", expr),
+    }
+}
+
+#[test]
+fn test_conditions_are_unboxed() {
+    let cond = Hir::boolean_literal(true);
+    let body = Hir::expressions(vec![Hir::decimal_literal(1)]);
+    let expr = Hir::while_expression(ty::raw("Void"), cond, body);
+    match expr.node {
+        HirWhileExpression { cond_expr, .. } => {
+            assert_eq!(cond_expr.ty, ty::native("Bool"));
+            match cond_expr.node {
+                HirUnbox { expr } => assert_eq!(expr.ty, ty::raw("Bool")),
+                _ => panic!("not unboxed: {:?}", cond_expr),
+            }
+        }
+        _ => panic!("not a while: {:?}", expr),
+    }
+}
+
+#[test]
+fn test_lambda_capture_ref() {
+    let expr = Hir::lambda_capture_ref(ty::raw("Int"), 2, 0);
+    assert_eq!(expr.ty, ty::raw("Int"));
+    match expr.node {
+        HirBitCast { expr: call } => match call.node {
+            HirMethodCall {
+                receiver_expr,
+                method_fullname,
+                ..
+            } => {
+                assert_eq!(method_fullname.full_name, "Array#nth");
+                match receiver_expr.node {
+                    HirArgRef { idx } => assert_eq!(idx, 2),
+                    _ => panic!("not an arg ref: {:?}", receiver_expr),
+                }
+            }
+            _ => panic!("not a method call: {:?}", call),
+        },
+        _ => panic!("not a bitcast: {:?}", expr),
+    }
+}
+
+#[test]
+fn test_fn_arity() {
+    let fn_ty = ty::spe("Fn2", vec![ty::raw("Int"), ty::raw("Int"), ty::raw("Bool")]);
+    assert_eq!(fn_ty.fn_arity(), Some(2));
+    assert_eq!(ty::raw("Fn0").fn_arity(), Some(0));
+    assert_eq!(ty::raw("Fnord").fn_arity(), None);
+    assert_eq!(ty::ary(ty::raw("Int")).fn_arity(), None);
+}
+
+fn main_expr_ty(src: &str) -> ty::TermTy {
+    let hir = build(src).unwrap();
+    hir.main_exprs.exprs.last().unwrap().ty.clone()
+}
+
+#[test]
+fn test_array_literal_ty() {
+    assert_eq!(
+        main_expr_ty("[1, 2]"),
+        ty::spe("Array", vec![ty::raw("Int")])
+    );
+    assert_eq!(
+        main_expr_ty("[1, \"a\"]"),
+        ty::spe("Array", vec![ty::raw("Object")])
+    );
+    // The type is propagated to the methods
+    assert_eq!(main_expr_ty("[1, \"a\"].first"), ty::raw("Object"));
+    assert_eq!(
+        main_expr_ty("[[1], [2]].first"),
+        ty::spe("Array", vec![ty::raw("Int")])
+    );
+}
+
+#[test]
+fn test_case_expr_ty() {
+    assert_eq!(
+        main_expr_ty("case 1\nwhen 0 then \"a\"\nelse \"b\"\nend"),
+        ty::raw("String")
+    );
+    // No value without `else`
+    assert_eq!(
+        main_expr_ty("case 1\nwhen 0 then \"a\"\nend"),
+        ty::raw("Void")
+    );
+    assert_eq!(
+        main_expr_ty("case [1, 2]\nwhen [x, *rest] then rest\nelse [0]\nend"),
+        ty::spe("Array", vec![ty::raw("Int")])
+    );
+
+    let err = build("case 1\nwhen [x] then x\nend").unwrap_err();
+    assert!(err.msg.contains("array pattern"), "{}", err.msg);
+}
+
+#[test]
+fn test_if_with_assignment() {
+    // Assigning a Bool is a condition
+    assert!(build("if b = true then b end").is_ok());
+    let err = build("if x = 1 then x end").unwrap_err();
+    assert!(err.msg.contains("must be bool"), "{}", err.msg);
+}
+
+#[test]
+fn test_try_expr() {
+    let def = "class A
+      def self.f(x: Int) -> Result<Int, String>
+        Result<Int, String>.ok(x)
+      end
+      def self.g -> Result<Int, String>
+        Result<Int, String>.ok(A.f(1)? + 1)
+      end
+    end
+    ";
+    assert!(build(def).is_ok());
+    let err = build(&format!("{}A.f(1)?", def)).unwrap_err();
+    assert!(err.msg.contains("only in a method"), "{}", err.msg);
+    let other = "class B
+      def self.h -> Result<Int, Int>
+        Result<Int, Int>.ok(A.f(1)?)
+      end
+    end
+    ";
+    let err = build(&format!("{}{}", def, other)).unwrap_err();
+    assert!(err.msg.contains("Result<_, String>"), "{}", err.msg);
+}
+
+#[test]
+fn test_overloading_by_arity() {
+    let def = "class A
+      def self.f(x: Int) -> Int
+        x
+      end
+      def self.f(x: Int, y: Int) -> String
+        \"two\"
+      end
+    end
+    ";
+    assert_eq!(main_expr_ty(&format!("{}A.f(1)", def)), ty::raw("Int"));
+    assert_eq!(
+        main_expr_ty(&format!("{}A.f(1, 2)", def)),
+        ty::raw("String")
+    );
+
+    let src = "class B
+      def g(x: Int) -> Int
+        x
+      end
+      def g(y: Int) -> Int
+        y
+      end
+    end
+    ";
+    let err = shiika::parser::Parser::parse(src).unwrap_err();
+    assert!(err.msg.contains("defined twice"), "{}", err.msg);
+
+    // An extern method is checked only if its name is overloaded
+    let src = "class C
+      extern def abs(n: Int) -> Int
+      def self.f -> Int
+        1
+      end
+    end
+    ";
+    assert!(shiika::parser::Parser::parse(src).is_ok());
+    let src = "class C
+      extern def abs(n: Int) -> Int
+      def self.abs(a: Int, b: Int) -> Int
+        a
+      end
+    end
+    ";
+    let err = shiika::parser::Parser::parse(src).unwrap_err();
+    assert!(err.msg.contains("cannot be overloaded"), "{}", err.msg);
+
+    // A subclass must override all the overloads or none of them
+    let base = "class D
+      def g(x: Int) -> Int
+        x
+      end
+      def g(x: Int, y: Int) -> Int
+        y
+      end
+    end
+    ";
+    let src = format!(
+        "{}class E : D\n  def g(x: Int) -> Int\n    0\n  end\nend\n",
+        base
+    );
+    let err = build(&src).unwrap_err();
+    assert!(err.msg.contains("all the overloads of `g'"), "{}", err.msg);
+    let src = format!("{}class E : D\n  def h -> Int\n    0\n  end\nend\n", base);
+    assert!(build(&src).is_ok());
+}
+
+#[test]
+fn test_new_in_class_method() {
+    let src = "class P
+      def initialize(x: Int, y: Int)
+        @x = x
+        @y = y
+      end
+      def self.origin -> P
+        new(0, 0)
+      end
+      def self.on_x(x: Int) -> P
+        self.new(x, 0)
+      end
+    end
+    P.origin
+    ";
+    assert_eq!(main_expr_ty(src), ty::raw("P"));
+}
+
+#[test]
+fn test_block_scope() {
+    let err = build("if true\n  x = 1\nend\nx").unwrap_err();
+    assert!(err.msg.contains("`x' was not found"), "{}", err.msg);
+    let err = build("for i in 0..1\n  y = i\nend\ni").unwrap_err();
+    assert!(err.msg.contains("`i' was not found"), "{}", err.msg);
+    // Assigned before the block
+    assert!(build("var x = 1\nif true\n  x = 2\nend\nx").is_ok());
+    // The blocks have their own variables
+    assert!(build("if true\n  x = 1\nelse\n  x = 2\nend\nwhile false\n  x = 3\nend").is_ok());
+    let err = build("if true\n  x = 1\nend\nx = \"a\"").unwrap_err();
+    assert!(err.msg.contains("another block"), "{}", err.msg);
+}
+
+#[test]
+fn test_loop_expr_ty() {
+    let src = "var i = 0
+    loop do
+      i = i + 1
+      if i == 3 then break i * 2 end
+    end
+    ";
+    assert_eq!(main_expr_ty(src), ty::raw("Int"));
+    assert_eq!(main_expr_ty("loop do\n  break\nend"), ty::raw("Void"));
+    // `break` of the inner loop does not end the outer one
+    let src = "loop do
+      while true
+        break
+      end
+    end
+    ";
+    assert_eq!(main_expr_ty(src), ty::raw("Never"));
+
+    let err = build("loop do\n  puts \"a\"\nend\nputs \"b\"").unwrap_err();
+    assert!(err.msg.contains("`loop' without `break'"), "{}", err.msg);
+    let err = build("loop do\n  if true then break 1 end\n  break\nend").unwrap_err();
+    assert!(err.msg.contains("without a value"), "{}", err.msg);
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(
+        main_expr_ty("format(\"%05d %.2f\", 1, 2.0)"),
+        ty::raw("String")
+    );
+    assert_eq!(main_expr_ty("\"%s: %x\" % (\"a\", 255)"), ty::raw("String"));
+    assert_eq!(
+        main_expr_ty("fmt = \"%d\"\nsprintf(fmt, 1)"),
+        ty::raw("String")
+    );
+
+    let err = build("format(\"%d %d\", 1)").unwrap_err();
+    assert!(
+        err.msg.contains("needs 2 argument(s) but 1 given"),
+        "{}",
+        err.msg
+    );
+    let err = build("\"%x\" % 1.5").unwrap_err();
+    assert!(err.msg.contains("cannot be formatted"), "{}", err.msg);
+    let err = build("format(\"%q\", 1)").unwrap_err();
+    assert!(err.msg.contains("invalid format string"), "{}", err.msg);
+}
+
+#[test]
+fn test_toplevel_lvars() {
+    // Not visible in the methods
+    let err = build("x = 1\nclass A\n  def foo -> Int\n    x\n  end\nend").unwrap_err();
+    assert!(err.msg.contains("not visible in methods"), "{}", err.msg);
+    // .. or the initializers of the constants
+    let err = build("x = 1\nA = x + 1").unwrap_err();
+    assert!(err.msg.contains("initializer of a constant"), "{}", err.msg);
+    // The type of the main program is Int only if it gives the exit status
+    assert_eq!(build("1").unwrap().main_exprs.ty, ty::raw("Int"));
+    assert_eq!(build("x = 1").unwrap().main_exprs.ty, ty::raw("Void"));
+    assert_eq!(build("A = 1\nA").unwrap().main_exprs.ty, ty::raw("Int"));
+}
+
+#[test]
+fn test_compile_time_constants() {
+    let build = |src: &str, defines: &[(&str, &str)]| {
+        let config = config::BuildConfig {
+            release: true,
+            defines: defines
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..config::BuildConfig::default()
+        };
+        common::build_with_config(src, &config)
+    };
+    let defines = [("N", "2"), ("FLAG", "true"), ("NAME", "a")];
+    let ty = |src: &str| build(src, &defines).unwrap().main_exprs.ty;
+    assert_eq!(ty("N"), ty::raw("Int"));
+    assert_eq!(ty("if FLAG then 1 else \"a\" end"), ty::raw("Int"));
+    assert_eq!(ty("if DEBUG then \"a\" else 2 end"), ty::raw("Int"));
+    // Only the taken branch is converted
+    let src = "if NAME == \"b\" && N == 2 then no_such_method end";
+    assert!(build(src, &defines).is_ok());
+    assert!(build("unless FLAG then no_such_method end", &defines).is_ok());
+    assert!(build("if N == 3 || FLAG then no_such_method end", &defines).is_err());
+    // Not folded if the condition depends on runtime values
+    assert!(build("x = 1\nif FLAG && x == 1 then no_such_method end", &defines).is_err());
+    // Cannot be redefined at toplevel
+    let err = build("PLATFORM = 1", &defines).unwrap_err();
+    assert!(err.msg.contains("defined by the compiler"), "{}", err.msg);
+    let err = build("FLAG = 1", &defines).unwrap_err();
+    assert!(err.msg.contains("defined by the compiler"), "{}", err.msg);
+}
+
+#[test]
+fn test_max_type_nesting() {
+    let src = format!("1{}", "+1".repeat(100));
+    assert!(build(&src).is_ok());
+    let config = config::BuildConfig {
+        max_type_nesting: 64,
+        ..config::BuildConfig::default()
+    };
+    let err = common::build_with_config(&src, &config).unwrap_err();
+    assert_eq!(err.msg, "program too deeply nested (max: 64)");
+}
+
+#[test]
+fn test_parse_define() {
+    let parse = config::BuildConfig::parse_define;
+    assert_eq!(
+        parse("KEY=a=b").unwrap(),
+        ("KEY".to_string(), "a=b".to_string())
+    );
+    assert!(parse("KEY").is_err());
+    assert!(parse("key=1").is_err());
+    assert!(parse("DEBUG=false").is_err());
+}
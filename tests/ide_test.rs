@@ -0,0 +1,131 @@
+use shiika::ide::*;
+
+fn build_index(src: &str) -> SymbolIndex {
+    Analysis::new(src).unwrap().index
+}
+
+const SRC: &str = "class Foo
+  def initialize(a: Int)
+    @a = a
+  end
+
+  def bar(b: Int) -> Int
+    var c = b + 1
+    c + @a
+  end
+end
+MAX = 10
+x = Foo.new(1).bar(MAX).abs
+";
+
+#[test]
+fn test_symbol_index() {
+    let index = build_index(SRC);
+    let find = |name: &str| index.symbols.iter().find(|sym| sym.name == name);
+    assert_eq!(find("Foo").unwrap().kind, SymbolKind::Class);
+    assert_eq!(find("Foo#bar").unwrap().kind, SymbolKind::Method);
+    assert_eq!(find("Foo#bar").unwrap().detail, "(b: Int) -> Int");
+    assert_eq!(find("::MAX").unwrap().kind, SymbolKind::Const);
+    assert_eq!(find("@a").unwrap().container, Some("Foo".to_string()));
+    // Classes are not listed as constants
+    assert!(find("::Foo").is_none());
+
+    let found = index.search("foo#b");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name, "Foo#bar");
+}
+
+#[test]
+fn test_semantic_tokens() {
+    let index = build_index(SRC);
+    let tokens = semantic_tokens(SRC, &index)
+        .into_iter()
+        .map(|t| (&SRC[t.span.begin.pos()..t.span.end.pos()], t.kind))
+        .collect::<Vec<_>>();
+    use SemanticTokenKind::*;
+    assert_eq!(
+        tokens,
+        vec![
+            ("Foo", Class),
+            ("initialize", Method),
+            ("a", LVar),
+            ("Int", Class),
+            ("@a", IVar),
+            ("a", LVar),
+            ("bar", Method),
+            ("b", LVar),
+            ("Int", Class),
+            ("Int", Class),
+            ("c", LVar),
+            ("b", LVar),
+            ("c", LVar),
+            ("@a", IVar),
+            ("MAX", Const),
+            ("x", LVar),
+            ("Foo", Class),
+            ("new", Method),
+            ("bar", Method),
+            ("MAX", Const),
+            ("abs", Method),
+        ]
+    );
+    let max = semantic_tokens(SRC, &index)[14].span.clone();
+    assert_eq!((max.begin.line(), max.begin.col()), (10, 0));
+}
+
+#[test]
+fn test_semantic_tokens_after_modifier() {
+    let src = "class A
+  def foo
+    x = 1 if true
+  end
+  def bar
+    y = 2
+  end
+end
+";
+    let index = build_index(src);
+    let y = semantic_tokens(src, &index)
+        .into_iter()
+        .find(|t| &src[t.span.begin.pos()..t.span.end.pos()] == "y")
+        .unwrap();
+    assert_eq!(y.scope, "A#bar");
+}
+
+#[test]
+fn test_definitions() {
+    let analysis = Analysis::new(SRC).unwrap();
+    let def_of = |name: &str, nth: usize| {
+        let pos = SRC.match_indices(name).nth(nth).unwrap().0;
+        analysis
+            .definitions_at(pos)
+            .iter()
+            .map(|span| (span.begin.line(), span.begin.col()))
+            .collect::<Vec<_>>()
+    };
+    // class
+    assert_eq!(def_of("Foo", 1), vec![(0, 6)]);
+    // method
+    assert_eq!(def_of("bar", 1), vec![(5, 6)]);
+    // constant
+    assert_eq!(def_of("MAX", 1), vec![(10, 0)]);
+    // lvar (`a` in `@a = a`)
+    assert_eq!(def_of("a\n", 0), vec![(1, 17)]);
+    // defined in the builtin library
+    assert_eq!(def_of("abs", 0), vec![]);
+}
+
+#[test]
+fn test_hover() {
+    let analysis = Analysis::new(SRC).unwrap();
+    let hover = |name: &str, nth: usize| {
+        let pos = SRC.match_indices(name).nth(nth).unwrap().0;
+        analysis.hover_at(pos)
+    };
+    assert_eq!(hover("c", 2), Some("c: Int".to_string()));
+    assert_eq!(hover("b + 1", 0), Some("b: Int".to_string()));
+    assert_eq!(hover("@a", 1), Some("@a: Int".to_string()));
+    assert_eq!(hover("x =", 0), Some("x: Int".to_string()));
+    assert_eq!(hover("bar", 1), Some("Foo#bar(b: Int) -> Int".to_string()));
+    assert_eq!(hover("MAX", 1), Some("::MAX: Int".to_string()));
+}
@@ -16,11 +16,69 @@ fn test_compile_and_run() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The programs in tests/sk_panic/ must print the message in their first
+/// line (after `# `) and stop there
+#[test]
+fn test_panics() -> Result<(), Box<dyn std::error::Error>> {
+    for item in fs::read_dir("tests/sk_panic/")? {
+        let pathbuf = item?.path();
+        let path = pathbuf
+            .to_str()
+            .ok_or(plain_runner_error("Filename not utf8"))?;
+        if path.ends_with(".sk") {
+            dbg!(&path);
+            let src = fs::read_to_string(path)?;
+            let expected = src.lines().next().unwrap().trim_start_matches("# ");
+            shiika::runner::compile(path, true)?;
+            let (stdout, _) = shiika::runner::run_and_capture(path)?;
+            assert!(stdout.starts_with(expected), "{}", stdout);
+            assert!(!stdout.contains("not reached"));
+            shiika::runner::cleanup(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compile_str() -> Result<(), Box<dyn std::error::Error>> {
+    let compiled = shiika::compile_str("Math.sqrt(4.0)", &shiika::CompileOptions::default())?;
+    assert!(compiled.warnings.is_empty());
+    assert!(compiled.llvm_ir.contains("define i32 @main"));
+    assert!(!compiled.bitcode()?.is_empty());
+    assert_eq!(compiled.run_jit(&[])?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_exit_status() -> Result<(), Box<dyn std::error::Error>> {
+    let status = |src: &str| -> Result<i32, Box<dyn std::error::Error>> {
+        let compiled = shiika::compile_str(src, &shiika::CompileOptions::default())?;
+        compiled.run_jit(&[])
+    };
+    // The last expression gives the exit status if it is an Int
+    assert_eq!(status("puts \"a\"\n40 + 2")?, 42);
+    // .. but an assignment does not
+    assert_eq!(status("x = 3")?, 0);
+    assert_eq!(status("\"3\"")?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_defines() -> Result<(), Box<dyn std::error::Error>> {
+    let options = shiika::CompileOptions {
+        defines: vec![("N".to_string(), "7".to_string())],
+        ..shiika::CompileOptions::default()
+    };
+    let compiled = shiika::compile_str("N * 6", &options)?;
+    assert_eq!(compiled.run_jit(&[])?, 42);
+    Ok(())
+}
+
 /// Execute tests/sk/x.sk
 /// Fail if it prints something
 fn run_sk_test(path: &str) -> Result<(), Box<dyn std::error::Error>> {
     dbg!(&path);
-    shiika::runner::compile(path)?;
+    shiika::runner::compile(path, true)?;
     let (stdout, stderr) = shiika::runner::run_and_capture(path)?;
     assert_eq!(stderr, "");
     assert_eq!(stdout, "ok\n");
@@ -0,0 +1,68 @@
+mod common;
+use shiika::code_gen::{class_layouts, ClassLayout};
+
+fn build_layouts(src: &str) -> Vec<ClassLayout> {
+    let hir = common::build(src).unwrap();
+    class_layouts(&hir).unwrap()
+}
+
+fn find<'a>(layouts: &'a [ClassLayout], name: &str) -> &'a ClassLayout {
+    layouts.iter().find(|l| l.name.0 == name).unwrap()
+}
+
+#[test]
+fn test_ivars() {
+    let layouts = build_layouts(
+        "class A
+  def initialize(a: Int, b: String)
+    @a = a
+    @b = b
+  end
+end
+",
+    );
+    let a = find(&layouts, "A");
+    let names = a
+        .fields
+        .iter()
+        .map(|f| f.name.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["a", "b"]);
+    // The ivars are pointers
+    assert_eq!(a.fields[0].offset, 0);
+    assert_eq!(a.fields[1].offset, a.fields[0].size);
+    assert_eq!(a.size, a.fields[0].size * 2);
+    assert_eq!(a.padding(), 0);
+    assert!(a.to_string().starts_with("A (size: "));
+}
+
+#[test]
+fn test_values() {
+    let layouts = build_layouts("");
+    let int = find(&layouts, "Int");
+    assert_eq!(int.size, 4);
+    assert_eq!(int.fields.len(), 1);
+    assert_eq!(int.fields[0].name, None);
+    let float = find(&layouts, "Float");
+    assert_eq!(float.size, 8);
+}
+
+#[test]
+fn test_header() {
+    let layouts = build_layouts(
+        "class A
+  def initialize(a: Float)
+    @a = a
+  end
+end
+",
+    );
+    let a = find(&layouts, "A");
+    // The class id is stored before the object, so the ivars start at 0
+    assert_eq!(a.fields[0].offset, 0);
+    assert_eq!(a.allocated_size(), a.size + 8);
+    assert!(a.to_string().contains("header: 8)"));
+    // Every object (including the values) has the header
+    let int = find(&layouts, "Int");
+    assert_eq!(int.allocated_size(), 12);
+}
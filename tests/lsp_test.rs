@@ -0,0 +1,90 @@
+use shiika::ide::json::Json;
+use shiika::ide::lsp;
+
+fn message(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+/// Send the messages to the server and return the responses
+fn talk(bodies: &[&str]) -> Vec<Json> {
+    let builtin = shiika::runner::load_builtin().unwrap();
+    let input = bodies.iter().map(|b| message(b)).collect::<String>();
+    let mut output = vec![];
+    lsp::serve(&builtin, input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output)
+        .unwrap()
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|s| Json::parse(s.splitn(2, "\r\n\r\n").nth(1).unwrap()).unwrap())
+        .collect()
+}
+
+const OPEN: &str = r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.sk","languageId":"shiika","version":1,"text":"class A\n  def foo -> Int\n    1\n  end\nend\nA.new.foo\n"}}}"#;
+
+#[test]
+fn test_initialize() {
+    let res = talk(&[
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ]);
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].get("id"), Some(&Json::Number(1.0)));
+    let hover = res[0].dig(&["result", "capabilities", "hoverProvider"]);
+    assert_eq!(hover, Some(&Json::Bool(true)));
+}
+
+#[test]
+fn test_definition_and_hover() {
+    let res = talk(&[
+        OPEN,
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///a.sk"},"position":{"line":5,"character":7}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///a.sk"},"position":{"line":5,"character":7}}}"#,
+    ]);
+    // No errors
+    let diags = res[0].dig(&["params", "diagnostics"]);
+    assert_eq!(diags, Some(&Json::Array(vec![])));
+    let def = &res[1].get("result").unwrap().as_array().unwrap()[0];
+    assert_eq!(
+        def.dig(&["range", "start"]).unwrap().to_string(),
+        r#"{"line":1,"character":6}"#
+    );
+    let hover = res[2].dig(&["result", "contents", "value"]).unwrap();
+    assert_eq!(hover.as_str(), Some("```\nA#foo() -> Int\n```"));
+}
+
+#[test]
+fn test_diagnostics() {
+    let res = talk(&[
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///b.sk","text":"1\nx = 1 + true\n"}}}"#,
+    ]);
+    let diags = res[0].dig(&["params", "diagnostics"]).unwrap();
+    let diag = &diags.as_array().unwrap()[0];
+    assert_eq!(diag.get("code"), Some(&Json::str("E0301")));
+}
+
+#[test]
+fn test_diagnostics_of_parse_errors() {
+    let res = talk(&[
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///c.sk","text":"x = )\ny = 1\nz = ]\n"}}}"#,
+    ]);
+    let diags = res[0].dig(&["params", "diagnostics"]).unwrap();
+    let diags = diags.as_array().unwrap();
+    assert_eq!(diags.len(), 2);
+    assert_eq!(diags[0].get("code"), Some(&Json::str("E0101")));
+    assert_eq!(
+        diags[1].dig(&["range", "start", "line"]),
+        Some(&Json::Number(2.0))
+    );
+
+    // A lexical error is the last one
+    let res = talk(&[
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///d.sk","text":"x = )\ny = \"abc\n"}}}"#,
+    ]);
+    let diags = res[0].dig(&["params", "diagnostics"]).unwrap();
+    let diags = diags.as_array().unwrap();
+    assert_eq!(diags.len(), 2);
+    assert_eq!(
+        diags[1].dig(&["range", "start", "line"]),
+        Some(&Json::Number(1.0))
+    );
+}
@@ -0,0 +1,71 @@
+use shiika::platform::{self, Platform, Toolchain};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn test_exe_path() {
+    assert_eq!(Platform::Linux.exe_path("a.sk"), PathBuf::from("a.sk.out"));
+    assert_eq!(
+        Platform::Windows.exe_path("a.sk"),
+        PathBuf::from("a.sk.exe")
+    );
+}
+
+#[test]
+fn test_command_path() {
+    assert_eq!(
+        platform::command_path(Path::new("a.sk.out")),
+        Path::new(".").join("a.sk.out")
+    );
+    assert_eq!(
+        platform::command_path(Path::new("tests/sk/a.sk.out")),
+        PathBuf::from("tests/sk/a.sk.out")
+    );
+    assert_eq!(
+        platform::command_path(Path::new("/tmp/a.sk.out")),
+        PathBuf::from("/tmp/a.sk.out")
+    );
+}
+
+#[test]
+fn test_toolchain_llc() {
+    assert!(Toolchain::Clang.llc_args().is_empty());
+    assert_eq!(Toolchain::Clang.llc_output_ext(), ".s");
+    assert_eq!(Toolchain::LldLink.llc_args(), vec!["-filetype=obj"]);
+    assert_eq!(Toolchain::LldLink.llc_output_ext(), ".obj");
+    assert_eq!(Toolchain::Clang.llc_object_args(), vec!["-filetype=obj"]);
+    assert_eq!(Toolchain::LldLink.llc_object_args(), vec!["-filetype=obj"]);
+    assert_eq!(Toolchain::Clang.object_ext(), ".o");
+}
+
+#[test]
+fn test_toolchain_link_args() {
+    let out = Platform::Windows.exe_path("a.sk");
+    assert_eq!(
+        Toolchain::MinGW.link_args(Platform::Windows, &["a.sk.s"], &out),
+        vec!["-o", "a.sk.exe", "a.sk.s", "-lgc"]
+    );
+    assert_eq!(
+        Toolchain::MinGW.link_args(Platform::Windows, &["a.sk.s", "builtin.o"], &out),
+        vec!["-o", "a.sk.exe", "a.sk.s", "builtin.o", "-lgc"]
+    );
+    let args = Toolchain::LldLink.link_args(Platform::Windows, &["a.sk.obj"], &out);
+    assert_eq!(args[0], "/out:a.sk.exe");
+    assert!(args.contains(&"gc.lib".to_string()));
+}
+
+#[test]
+fn test_toolchain_static() {
+    assert_eq!(
+        Toolchain::detect(Platform::Linux, true).unwrap(),
+        Toolchain::Musl
+    );
+    assert!(Toolchain::detect(Platform::MacOS, true).is_err());
+    let out = Platform::Linux.exe_path("a.sk");
+    let args = Toolchain::Musl.link_args(Platform::Linux, &["a.sk.s"], &out);
+    assert_eq!(args[0], "-static");
+}
+
+#[test]
+fn test_llvm_tool_names() {
+    assert_eq!(platform::llvm_tool_names("llc"), vec!["llc-7", "llc"]);
+}
@@ -0,0 +1,49 @@
+mod common;
+use shiika::hir::pretty_print::hir_to_string;
+
+fn dump(src: &str) -> String {
+    let hir = common::build(src).unwrap();
+    hir_to_string(&hir, &|name| name == "A" || name.starts_with("A#"))
+}
+
+#[test]
+fn test_method() {
+    let s = dump(
+        "class A
+  def foo(x: Int) -> Int
+    if x > 0 then x + 1 else 0 end
+  end
+end
+",
+    );
+    assert!(s.contains("class A : Object\nend\n"));
+    assert!(s.contains("def A#foo(x: Int) -> Int\n"));
+    assert!(s.contains("  if unbox(x.Int#>(box(0)))\n"));
+    assert!(s.contains("    x.Int#+(box(1))  #: Int\n"));
+    assert!(s.contains("  end  #: Int\n"));
+}
+
+#[test]
+fn test_main() {
+    let s = dump("a = 1\nputs \"hi\"\n");
+    assert!(s.starts_with("main\n"));
+    assert!(s.contains("  a = box(1)  #: Int\n"));
+    assert!(s.contains("  self.Object#puts(\"hi\")  #: Void\n"));
+    assert!(s.ends_with("end\n"));
+}
+
+#[test]
+fn test_breakpoint() {
+    let s = dump(
+        "class A
+  def foo(x: Int) -> Int
+    y = x
+    breakpoint
+    y
+  end
+end
+",
+    );
+    assert!(s.contains("  breakpoint(x, y)  #: Void\n"));
+    assert!(s.contains("  step_point(x, y)  #: Void\n"));
+}
@@ -0,0 +1,56 @@
+use shiika::{compile_str, CompileOptions};
+
+fn error_of(src: &str) -> String {
+    let err = compile_str(src, &CompileOptions::default()).unwrap_err();
+    err.downcast_ref::<shiika::error::Error>()
+        .unwrap()
+        .msg
+        .clone()
+}
+
+const CLASS_A: &str = "
+class A
+  def initialize(n: Int)
+    @n = n
+  end
+
+  def foo(x: Int) -> Int
+    x
+  end
+end
+";
+
+#[test]
+fn test_reopen() {
+    let src = format!(
+        "{}class A\n  def foo(y: Int) -> Int\n    y + 1\n  end\nend\n",
+        CLASS_A
+    );
+    assert!(compile_str(&src, &CompileOptions::default()).is_ok());
+}
+
+#[test]
+fn test_change_signature() {
+    let src = format!(
+        "{}class A\n  def foo(x: String) -> Int\n    1\n  end\nend\n",
+        CLASS_A
+    );
+    assert!(error_of(&src).contains("cannot change the signature of A#foo"));
+    let src = format!(
+        "{}class A\n  def foo(x: Int) -> Bool\n    true\n  end\nend\n",
+        CLASS_A
+    );
+    assert!(error_of(&src).contains("cannot change the signature of A#foo"));
+}
+
+#[test]
+fn test_redefine_initialize() {
+    let src = format!("{}class A\n  def initialize\n  end\nend\n", CLASS_A);
+    assert!(error_of(&src).contains("cannot define #initialize"));
+}
+
+#[test]
+fn test_change_superclass() {
+    let src = format!("{}class B; end\nclass A : B\nend\n", CLASS_A);
+    assert!(error_of(&src).contains("cannot change the superclass of A"));
+}
@@ -0,0 +1,18 @@
+use std::fs;
+
+#[test]
+fn test_emit_llvm_ir() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join("shiika_source_map_test.sk");
+    let path_str = path.to_str().unwrap();
+    let src = "class A\n  def self.foo -> Int\n    1\n  end\nend\nA.foo\n";
+    fs::write(&path, src)?;
+    let ir = shiika::runner::emit(path_str, "llvm-ir")?;
+    fs::remove_file(&path)?;
+
+    // The line of the method precedes its function
+    let comment = format!("; {}:2: def self.foo -> Int\ndefine ", path_str);
+    let i = ir.find(&comment).expect("no comment for Meta:A#foo");
+    let define = ir[i..].lines().nth(1).unwrap();
+    assert!(define.contains("@\"Meta:A#foo\"("), "{}", define);
+    Ok(())
+}
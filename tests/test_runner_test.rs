@@ -0,0 +1,49 @@
+use shiika::test_runner;
+use std::path::PathBuf;
+
+#[test]
+fn test_instrument() {
+    let src = "test \"a\", fn(){\n  assert(1 == 1)\n  assert_eq 2, 1 + 1\n}\n";
+    assert_eq!(
+        test_runner::instrument(src, "a_test.sk"),
+        "test \"a\", fn(){\n  assert_at(\"a_test.sk:2\", 1 == 1)\n  assert_eq_at \"a_test.sk:3\", 2, 1 + 1\n}\n"
+    );
+}
+
+#[test]
+fn test_instrument_panic() {
+    let src = "assert(x, \"msg\")\npanic(\"no\")\n";
+    assert_eq!(
+        test_runner::instrument(src, "a_test.sk"),
+        "assert_at(\"a_test.sk:1\", x, \"msg\")\npanic_at(\"a_test.sk:2\", \"no\")\n"
+    );
+}
+
+#[test]
+fn test_instrument_skips_others() {
+    // Method definitions, method calls with a receiver and other names
+    let src = "def assert(x: Bool)\nend\nfoo.assert(true)\nasserts(1)\nassert = 1 # assert(x)";
+    assert_eq!(test_runner::instrument(src, "a_test.sk"), src);
+}
+
+#[test]
+fn test_parse_output() {
+    let output = "PASS a\nFAIL b\n  a_test.sk:3: expected 1, got 2\nhello\nPASS c\n";
+    let (passed, failed) = test_runner::parse_output(output);
+    assert_eq!(passed, vec!["a", "c"]);
+    assert_eq!(failed, vec!["b"]);
+}
+
+#[test]
+fn test_shuffle() {
+    let files = (0..10)
+        .map(|i| PathBuf::from(format!("{}_test.sk", i)))
+        .collect::<Vec<_>>();
+    let mut a = files.clone();
+    let mut b = files.clone();
+    test_runner::shuffle(&mut a, 1234);
+    test_runner::shuffle(&mut b, 1234);
+    assert_eq!(a, b);
+    a.sort();
+    assert_eq!(a, files);
+}
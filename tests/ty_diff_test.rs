@@ -0,0 +1,25 @@
+use shiika::ty;
+use shiika::type_checking::ty_diff;
+
+#[test]
+fn test_diff_nested_type_args() {
+    let expected = ty::ary(ty::spe("Fn1", vec![ty::raw("Int"), ty::raw("Bool")]));
+    let actual = ty::ary(ty::spe("Fn1", vec![ty::raw("Int"), ty::raw("Int")]));
+    let (e, a) = ty_diff::diff(&expected, &actual);
+    assert_eq!(e, "Array<Fn1<Int,**Bool**>>");
+    assert_eq!(a, "Array<Fn1<Int,**Int**>>");
+}
+
+#[test]
+fn test_diff_different_base() {
+    let expected = ty::ary(ty::raw("Int"));
+    let actual = ty::spe("Pair", vec![ty::raw("Int")]);
+    let (e, a) = ty_diff::diff(&expected, &actual);
+    assert_eq!(e, "**Array<Int>**");
+    assert_eq!(a, "**Pair<Int>**");
+}
+
+#[test]
+fn test_mismatch_details_of_simple_types() {
+    assert!(ty_diff::mismatch_details(&ty::raw("Int"), &ty::raw("Bool")).is_none());
+}
@@ -0,0 +1,189 @@
+mod common;
+use shiika::hir::warnings::*;
+
+/// Return the warnings for `src` (except the ones about the builtin
+/// library) as strings
+fn warnings(src: &str) -> Vec<String> {
+    let n = shiika::runner::load_builtin().unwrap().len();
+    let hir = common::build(src).unwrap();
+    let in_src = |w: &Warning| match hir.def_spans.get(&w.place) {
+        Some(span) => span.begin.pos() >= n,
+        None => w.place == "main",
+    };
+    hir.warnings
+        .iter()
+        .cloned()
+        .chain(unused_methods(&hir))
+        .filter(in_src)
+        .map(|w| w.to_string())
+        .collect()
+}
+
+#[test]
+fn test_unused_variable() {
+    let src = "class A
+  def self.foo(x: Int) -> Int
+    a = 1
+    _b = 2
+    c = 3
+    f = fn(y: Int){ d = y }
+    f.call(c)
+    x
+  end
+end
+A.foo(1)
+";
+    assert_eq!(
+        warnings(src),
+        vec![
+            "warning[unused_variable]: variable `d' is never used (in Meta:A#foo)",
+            "warning[unused_variable]: variable `a' is never used (in Meta:A#foo)",
+        ]
+    );
+}
+
+#[test]
+fn test_unused_variable_in_block() {
+    let src = "var a = 1
+if a > 0
+  b = 2
+end
+for i in 0..2
+  a = i
+end
+";
+    assert_eq!(
+        warnings(src),
+        vec!["warning[unused_variable]: variable `b' is never used (in main)"]
+    );
+}
+
+#[test]
+fn test_captured_variable_is_used() {
+    let src = "a = 1
+f = fn(){ a + 1 }
+f.call
+";
+    assert_eq!(warnings(src), Vec::<String>::new());
+}
+
+#[test]
+fn test_shadowed_variable() {
+    let src = "a = 1
+b = 2
+f = fn(a: Int){
+  var b = a
+  b + 1
+}
+f.call(a + b)
+";
+    assert_eq!(
+        warnings(src),
+        vec![
+            "warning[shadowed_variable]: variable `a' shadows the outer one (in main)",
+            "warning[shadowed_variable]: variable `b' shadows the outer one (in main)",
+        ]
+    );
+}
+
+#[test]
+fn test_unused_method() {
+    let src = "class A
+  def initialize
+  end
+  def foo -> Int
+    1
+  end
+  def bar -> Int
+    2
+  end
+end
+class AA : A
+  def bar -> Int
+    3
+  end
+end
+A.new.foo
+";
+    assert_eq!(
+        warnings(src),
+        vec!["warning[unused_method]: method `A#bar' is never called (in A#bar)"]
+    );
+}
+
+#[test]
+fn test_constant_condition() {
+    let src = "if true
+  puts \"a\"
+end
+unless false
+  puts \"b\"
+end
+while false
+  puts \"c\"
+end
+while true
+  break
+end
+";
+    assert_eq!(
+        warnings(src),
+        vec![
+            "warning[constant_condition]: the condition is always true (in main)",
+            "warning[constant_condition]: the condition is always true (in main)",
+            "warning[constant_condition]: the condition is always false (in main)",
+        ]
+    );
+}
+
+#[test]
+fn test_large_items() {
+    let items = vec!["1"; MAX_ARRAY_LITERAL_LEN + 1].join(", ");
+    let src = format!("X = [{}]\n[1, 2, 3]\n", items);
+    let hir = common::build(&src).unwrap();
+    let warnings = large_items(&hir)
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![format!(
+            "warning[large_item]: array literal with {} elements (consider making the array with a loop) (in ::X)",
+            MAX_ARRAY_LITERAL_LEN + 1
+        )]
+    );
+}
+
+#[test]
+fn test_eq_without_hash() {
+    let src = "class A
+  def ==(other: A) -> Bool
+    true
+  end
+end
+class B
+  def ==(other: B) -> Bool
+    true
+  end
+
+  def hash -> Int
+    0
+  end
+end
+class C : B
+  def ==(other: B) -> Bool
+    false
+  end
+end
+class D(x: Int)
+";
+    let hir = common::build(src).unwrap();
+    let warnings = eq_without_hash(&hir)
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec!["warning[eq_without_hash]: class `A' defines `==' but not `hash' (objects which are `==' must have the same `hash') (in A)"]
+    );
+}